@@ -1,41 +1,316 @@
-use anyhow::Result;
-use crate::llm::{LlmClient, Message, Role};
+use anyhow::{anyhow, Result};
+use std::time::Instant;
+use crate::config::Config;
+use crate::dashboard::Transcript;
+use crate::context::{ContextBudget, SummaryPolicy, TrimStrategy, estimate_request};
+use crate::journal::{millis, Journal, JournalEvent};
+use crate::llm::{Completion, GenerationConfig, ImageContent, LlmClient, Message, Role};
+use crate::parser::{parse_json_actions, render_markers, ActionProtocol, JSON_PROTOCOL_PROMPT};
+use crate::response_cache::ResponseCache;
+use crate::usage::{PriceTable, UsageReport, UsageTracker};
+use tracing::{info, info_span, Instrument};
+
+/// Times a malformed JSON action is sent back for another try
+pub const JSON_RETRIES: usize = 2;
+
+/// A conversation set aside with `Agent::take_conversation`, to carry on later
+#[derive(Debug, Clone, Default)]
+pub struct Conversation {
+    history: Vec<Message>,
+    summary: Option<String>,
+}
+
+impl Conversation {
+    pub fn history(&self) -> &[Message] {
+        &self.history
+    }
+}
 
 pub struct Agent {
     llm: LlmClient,
     history: Vec<Message>,
     system_prompt: String,
+    generation: GenerationConfig,
+    budget: ContextBudget,
+    summary_policy: Option<SummaryPolicy>,
+    summary: Option<String>,
+    usage: UsageTracker,
+    journal: Option<Journal>,
+    transcript: Option<Transcript>,
+    protocol: ActionProtocol,
 }
 
 impl Agent {
     pub async fn new(system_prompt: &str) -> Result<Self> {
-        Ok(Self {
-            llm: LlmClient::new().await?,
+        Ok(Self::with_client(LlmClient::new().await?, system_prompt))
+    }
+
+    /// Build an agent on the model `config` names, with the action protocol it sets for that model
+    pub async fn from_config(config: &Config, system_prompt: &str) -> Result<Self> {
+        let llm = LlmClient::from_config(&config.llm).await?;
+        let mut agent = Self::with_client(llm, system_prompt);
+        agent.set_protocol(config.llm.protocol_for(&agent.llm.label()));
+        Ok(agent)
+    }
+
+    /// Build an agent around an already configured client
+    pub fn with_client(llm: LlmClient, system_prompt: &str) -> Self {
+        let budget = ContextBudget::from_env(llm.provider());
+        Self {
+            llm,
             history: Vec::new(),
             system_prompt: system_prompt.to_string(),
-        })
+            generation: GenerationConfig::default(),
+            budget,
+            summary_policy: SummaryPolicy::from_env(),
+            summary: None,
+            usage: UsageTracker::new(PriceTable::from_env()),
+            journal: None,
+            transcript: None,
+            protocol: ActionProtocol::default(),
+        }
+    }
+
+    /// Override generation parameters (on top of the client defaults) for subsequent turns
+    pub fn set_generation(&mut self, generation: GenerationConfig) {
+        self.generation = generation;
+    }
+
+    pub fn set_system_prompt(&mut self, system_prompt: &str) {
+        self.system_prompt = system_prompt.to_string();
+    }
+
+    pub fn set_context_budget(&mut self, budget: ContextBudget) {
+        self.budget = budget;
+    }
+
+    /// Enable (or with `None`, disable) rolling summarization of older turns
+    pub fn set_summary_policy(&mut self, policy: Option<SummaryPolicy>) {
+        self.summary_policy = policy;
+    }
+
+    /// Record each turn and model request in `journal`
+    pub fn set_journal(&mut self, journal: Option<Journal>) {
+        self.journal = journal;
+    }
+
+    pub fn journal(&self) -> Option<&Journal> {
+        self.journal.as_ref()
+    }
+
+    /// Copy every turn to `transcript`, e.g. for the dashboard
+    pub fn set_transcript(&mut self, transcript: Option<Transcript>) {
+        self.transcript = transcript;
+    }
+
+    /// How the model is asked to write its actions. Under `ActionProtocol::Json`, replies are
+    /// checked and re-prompted until valid, and `chat` returns them written out as markers.
+    pub fn set_protocol(&mut self, protocol: ActionProtocol) {
+        self.protocol = protocol;
+    }
+
+    pub fn protocol(&self) -> ActionProtocol {
+        self.protocol
+    }
+
+    /// Forget the conversation and its summary, to start on something unrelated
+    pub fn clear_history(&mut self) {
+        self.history.clear();
+        self.summary = None;
+    }
+
+    pub fn history(&self) -> &[Message] {
+        &self.history
+    }
+
+    /// Set the conversation aside, leaving the agent to start a new one
+    pub fn take_conversation(&mut self) -> Conversation {
+        Conversation { history: std::mem::take(&mut self.history), summary: self.summary.take() }
+    }
+
+    /// Carry on `conversation` in place of the current one
+    pub fn resume_conversation(&mut self, conversation: Conversation) {
+        self.history = conversation.history;
+        self.summary = conversation.summary;
+    }
+
+    /// Rolling summary of turns no longer kept verbatim in the history, if any
+    pub fn summary(&self) -> Option<&str> {
+        self.summary.as_deref()
+    }
+
+    /// Tokens and estimated cost spent so far
+    pub fn usage(&self) -> &UsageReport {
+        self.usage.report()
+    }
+
+    /// Estimated tokens the next request will use
+    pub fn context_tokens(&self) -> usize {
+        estimate_request(self.llm.provider(), &self.history, &self.effective_system_prompt())
+    }
+
+    fn effective_system_prompt(&self) -> String {
+        let mut prompt = self.system_prompt.clone();
+        if self.protocol == ActionProtocol::Json {
+            prompt.push_str("\n\n");
+            prompt.push_str(JSON_PROTOCOL_PROMPT);
+        }
+        if let Some(summary) = &self.summary {
+            prompt.push_str(&format!("\n\nSummary of the earlier conversation:\n{}", summary));
+        }
+        prompt
+    }
+
+    /// Fold older turns into the rolling summary once the history passes the policy threshold
+    async fn roll_summary(&mut self) -> Result<()> {
+        let Some(policy) = &self.summary_policy else {
+            return Ok(());
+        };
+        let end = policy.summarize_point(self.llm.provider(), &self.history);
+        if end == 0 {
+            return Ok(());
+        }
+
+        let older: Vec<Message> = self.history.drain(..end).collect();
+        info!("Summarizing {} older messages", older.len());
+        self.summary = Some(self.summarize(&older).await?);
+        Ok(())
+    }
+
+    /// Trim the oldest turns until the request fits the context budget
+    async fn fit_context(&mut self) -> Result<()> {
+        let start = self.budget.trim_point(self.llm.provider(), &self.history, &self.effective_system_prompt());
+        if start == 0 {
+            return Ok(());
+        }
+
+        let dropped: Vec<Message> = self.history.drain(..start).collect();
+        info!("Context budget exceeded, trimming {} older messages", dropped.len());
+
+        if self.budget.strategy == TrimStrategy::Summarize {
+            self.summary = Some(self.summarize(&dropped).await?);
+            // The summary itself takes space; drop more turns if it pushed us over
+            let start = self.budget.trim_point(self.llm.provider(), &self.history, &self.effective_system_prompt());
+            self.history.drain(..start);
+        }
+        Ok(())
+    }
+
+    async fn summarize(&mut self, dropped: &[Message]) -> Result<String> {
+        let mut transcript = String::new();
+        if let Some(previous) = &self.summary {
+            transcript.push_str(&format!("Earlier summary: {}\n\n", previous));
+        }
+        for message in dropped {
+            let speaker = match message.role {
+                Role::User => "User",
+                Role::Assistant => "Assistant",
+            };
+            transcript.push_str(&format!("{}: {}\n", speaker, message.content));
+        }
+
+        let request = vec![Message::user(format!(
+            "Summarize this conversation concisely, keeping facts, decisions and open tasks:\n\n{}", transcript
+        ))];
+        let completion = self.complete(request, None, "summary").await?;
+        Ok(completion.text)
+    }
+
+    /// Ask the model, counting usage and journaling the request and its outcome
+    async fn complete(&mut self, messages: Vec<Message>, system_prompt: Option<String>, purpose: &str) -> Result<Completion> {
+        let Some(journal) = self.journal.clone() else {
+            let completion = self.llm.complete(messages, system_prompt, &self.generation).await?;
+            self.usage.record(&completion);
+            return Ok(completion);
+        };
+        let model = self.llm.label();
+        let hash = ResponseCache::key(&model, &messages, system_prompt.as_deref(), &self.generation);
+        journal.record_or_warn(JournalEvent::LlmRequest { hash: hash.clone(), model, messages: messages.len(), purpose: purpose.to_string() });
+        let started = Instant::now();
+        match self.llm.complete(messages, system_prompt, &self.generation).await {
+            Ok(completion) => {
+                self.usage.record(&completion);
+                journal.record_or_warn(JournalEvent::LlmResponse {
+                    hash,
+                    model: completion.model.clone(),
+                    text: completion.text.clone(),
+                    input_tokens: completion.usage.input_tokens,
+                    output_tokens: completion.usage.output_tokens,
+                    cached: completion.cached,
+                    duration_ms: millis(started.elapsed()),
+                });
+                Ok(completion)
+            }
+            Err(e) => {
+                journal.record_or_warn(JournalEvent::LlmError { hash, error: e.to_string(), duration_ms: millis(started.elapsed()) });
+                Err(e)
+            }
+        }
     }
 
     pub async fn chat(&mut self, user_input: &str) -> Result<String> {
+        self.chat_with_images(user_input, Vec::new()).await
+    }
+
+    /// Send a user turn with attached images (for multimodal models)
+    pub async fn chat_with_images(&mut self, user_input: &str, images: Vec<ImageContent>) -> Result<String> {
+        let span = info_span!("agent_turn", model = %self.llm.label(), input_chars = user_input.len(), images = images.len());
+        Box::pin(self.turn(user_input, images)).instrument(span).await
+    }
+
+    async fn turn(&mut self, user_input: &str, images: Vec<ImageContent>) -> Result<String> {
         // Add user message to history
-        let user_msg = Message {
-            role: Role::User,
-            content: user_input.to_string(),
-        };
-        
+        let mut user_msg = Message::user(user_input);
+        user_msg.images = images;
+
         self.history.push(user_msg);
+        if let Some(transcript) = &self.transcript {
+            transcript.push(Message::user(user_input));
+        }
+        self.usage.start_turn();
+        if let Some(journal) = &self.journal {
+            journal.start_turn();
+            journal.record_or_warn(JournalEvent::Input { text: user_input.to_string() });
+        }
+        self.roll_summary().await?;
+        self.fit_context().await?;
 
         // Get response from LLM
-        let response_text = self.llm.chat(self.history.clone(), Some(self.system_prompt.clone())).await?;
-
-        // Add assistant response to history
-        let assistant_msg = Message {
-            role: Role::Assistant,
-            content: response_text.clone(),
+        let completion = self.complete(self.history.clone(), Some(self.effective_system_prompt()), "chat").await?;
+        let response_text = match self.protocol {
+            ActionProtocol::Markers => {
+                self.history.push(Message::assistant(completion.text.clone()));
+                completion.text
+            }
+            ActionProtocol::Json => self.json_actions(completion.text).await?,
         };
-        
-        self.history.push(assistant_msg);
+
+        if let Some(transcript) = &self.transcript {
+            transcript.push(Message::assistant(response_text.clone()));
+        }
 
         Ok(response_text)
     }
+
+    /// Check a JSON protocol reply, asking again while it is malformed (up to `JSON_RETRIES`
+    /// times), and write its actions out as markers. Every reply and correction stays in the history.
+    async fn json_actions(&mut self, mut text: String) -> Result<String> {
+        let mut retries = 0;
+        loop {
+            let parsed = parse_json_actions(&text);
+            self.history.push(Message::assistant(text));
+            match parsed {
+                Ok(actions) => return Ok(render_markers(&actions)),
+                Err(e) if retries < JSON_RETRIES => {
+                    retries += 1;
+                    info!("Malformed JSON action, asking again: {}", e);
+                    self.history.push(Message::user(format!(
+                        "That reply was not a valid JSON action: {}. Reply with only the JSON action.", e
+                    )));
+                    text = self.complete(self.history.clone(), Some(self.effective_system_prompt()), "action retry").await?.text;
+                }
+                Err(e) => return Err(anyhow!("No valid JSON action after {} retries: {}", JSON_RETRIES, e)),
+            }
+        }
+    }
 }
@@ -0,0 +1,123 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::warn;
+use rhai::Engine;
+use crate::tools::{NativeContext, AUDIT_SHOW_LIMIT};
+
+/// One privileged action performed by the agent
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditEntry {
+    /// Seconds since the Unix epoch
+    pub timestamp: u64,
+    pub action: String,
+    pub detail: String,
+    pub outcome: String,
+}
+
+impl AuditEntry {
+    pub fn render(&self) -> String {
+        format!("[{}] {}: {} -> {}", self.timestamp, self.action, self.detail, self.outcome)
+    }
+
+    /// Whether `filter` appears in the action, detail or outcome, ignoring case
+    pub fn matches(&self, filter: &str) -> bool {
+        let filter = filter.to_lowercase();
+        [&self.action, &self.detail, &self.outcome].iter().any(|field| field.to_lowercase().contains(&filter))
+    }
+}
+
+/// Append-only JSON-lines audit trail
+#[derive(Debug, Clone)]
+pub struct AuditLog {
+    path: PathBuf,
+}
+
+impl AuditLog {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        if let Some(parent) = path.parent() {
+            if !parent.as_os_str().is_empty() {
+                fs::create_dir_all(parent)?;
+            }
+        }
+        Ok(Self { path })
+    }
+
+    /// Open the log at `AGENT_AUDIT_LOG`, defaulting to `state/audit.jsonl`
+    pub fn from_env() -> Result<Self> {
+        let path = std::env::var("AGENT_AUDIT_LOG").unwrap_or_else(|_| "state/audit.jsonl".to_string());
+        Self::open(path)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn record(&self, action: &str, detail: &str, outcome: &str) -> Result<()> {
+        let entry = AuditEntry {
+            timestamp: SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0),
+            action: action.to_string(),
+            detail: detail.to_string(),
+            outcome: outcome.to_string(),
+        };
+        let mut line = serde_json::to_string(&entry)?;
+        line.push('\n');
+
+        let mut file = fs::OpenOptions::new().create(true).append(true).open(&self.path)?;
+        file.write_all(line.as_bytes())?;
+        Ok(())
+    }
+
+    /// `record` for callers that carry on regardless: failures are only reported on stderr
+    pub fn record_or_warn(&self, action: &str, detail: &str, outcome: &str) {
+        if let Err(e) = self.record(action, detail, outcome) {
+            warn!("Failed to write audit log: {}", e);
+        }
+    }
+
+    /// The last `limit` entries matching `filter` (all of them for an empty filter), oldest first
+    pub fn search(&self, filter: &str, limit: usize) -> Result<Vec<AuditEntry>> {
+        let filter = filter.trim();
+        let mut entries: Vec<AuditEntry> = self.entries()?.into_iter().filter(|e| filter.is_empty() || e.matches(filter)).collect();
+        let skip = entries.len().saturating_sub(limit);
+        entries.drain(..skip);
+        Ok(entries)
+    }
+
+    /// `search` as one line per entry
+    pub fn render_search(&self, filter: &str, limit: usize) -> Result<String> {
+        let entries = self.search(filter, limit)?;
+        if entries.is_empty() {
+            return Ok(match filter.trim() {
+                "" => "The audit log is empty".to_string(),
+                filter => format!("No audit entries match '{}'", filter),
+            });
+        }
+        Ok(entries.iter().map(AuditEntry::render).collect::<Vec<_>>().join("\n"))
+    }
+
+    pub fn entries(&self) -> Result<Vec<AuditEntry>> {
+        if !self.path.exists() {
+            return Ok(Vec::new());
+        }
+        let content = fs::read_to_string(&self.path)?;
+        Ok(content
+            .lines()
+            .filter_map(|line| serde_json::from_str(line).ok())
+            .collect())
+    }
+}
+
+/// `show_audit`
+pub(crate) fn register_audit_natives(engine: &mut Engine, ctx: &NativeContext) {
+    let NativeContext { audit, .. } = ctx;
+    // show_audit: the most recent audit entries mentioning `filter` ("" for all)
+    let audit_clone = audit.clone();
+    engine.register_fn("show_audit", move |filter: &str| -> String {
+        audit_clone.render_search(filter, AUDIT_SHOW_LIMIT).unwrap_or_else(|e| format!("Error reading audit log: {}", e))
+    });
+}
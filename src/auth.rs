@@ -0,0 +1,376 @@
+use anyhow::{Result, anyhow};
+use base64::Engine as _;
+use ed25519_dalek::{Signer, SigningKey, Verifier, VerifyingKey};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::net::IpAddr;
+use std::sync::Arc;
+use crate::encryption::AgentKeys;
+use crate::ipc::Message;
+use crate::registry::unix_now;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Proof that a `Message` came from an agent holding the swarm secret
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Signature {
+    /// Identity the sender signed as
+    pub agent: String,
+    /// Unix time (seconds) the message was signed
+    pub timestamp: u64,
+    /// Base64 HMAC-SHA256 over agent, timestamp, `from`, content, `id` and `reply_to`
+    pub hmac: String,
+    /// Fingerprint of the key the sender signed with, so a mismatched secret is named as such
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key: Option<String>,
+    /// Base64 Ed25519 key of the sending agent itself (its `AgentKeys::signing_key`). Every
+    /// member holds the swarm secret, so only this tells one member from another.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub agent_key: Option<String>,
+    /// Base64 Ed25519 signature with `agent_key` over what the HMAC covers and the key itself
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub agent_signature: Option<String>,
+}
+
+impl Signature {
+    /// Fingerprint of `agent_key`, which `IpcAuth::verify` has checked signed the message
+    pub fn agent_fingerprint(&self) -> Option<String> {
+        let key = b64().decode(self.agent_key.as_deref()?).ok()?;
+        Some(key_fingerprint(&key))
+    }
+}
+
+fn b64() -> base64::engine::GeneralPurpose {
+    base64::engine::general_purpose::STANDARD
+}
+
+/// Short, public fingerprint of a signing secret: the first 8 bytes of its SHA-256, in hex
+pub fn key_fingerprint(secret: &[u8]) -> String {
+    Sha256::digest(secret)[..8].iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Who sent a message, as far as the receiver can tell
+#[derive(Debug, Clone, PartialEq)]
+pub enum Sender {
+    /// Signature checked against the shared secret
+    Verified(String),
+    /// No usable signature, accepted because unsigned traffic is allowed
+    Unsigned,
+}
+
+/// Shared-secret signing and verification of IPC messages
+#[derive(Clone)]
+pub struct IpcAuth {
+    secret: Option<Arc<Vec<u8>>>,
+    /// The agent's own key, signing beside the swarm secret
+    signing: Option<SigningKey>,
+    identity: String,
+    allow_unsigned: bool,
+    /// Oldest signature accepted, in seconds, to limit replays
+    max_age: u64,
+}
+
+impl IpcAuth {
+    /// `secret` of `None` disables signing; unsigned traffic is then the only kind there is
+    /// and is allowed
+    pub fn new(secret: Option<&str>, identity: impl Into<String>) -> Self {
+        let secret = secret.filter(|s| !s.is_empty()).map(|s| Arc::new(s.as_bytes().to_vec()));
+        Self {
+            allow_unsigned: secret.is_none(),
+            secret,
+            signing: None,
+            identity: identity.into(),
+            max_age: 300,
+        }
+    }
+
+    /// `IPC_SECRET`, identity `AGENT_NAME`, `IPC_ALLOW_UNSIGNED` (default false when a secret
+    /// is set) and `IPC_SIGNATURE_MAX_AGE_SECS` (default 300). With a secret, messages are also
+    /// signed with the agent's key from `AGENT_KEY_FILE`.
+    pub fn from_env() -> Self {
+        let secret = std::env::var("IPC_SECRET").ok();
+        let identity = std::env::var("AGENT_NAME").unwrap_or_else(|_| "Swarm Thing".to_string());
+        let mut auth = Self::new(secret.as_deref(), identity);
+        if auth.is_enabled() {
+            if let Ok(keys) = AgentKeys::from_env() {
+                auth = auth.with_keys(&keys);
+            }
+        }
+        if let Ok(allow) = std::env::var("IPC_ALLOW_UNSIGNED") {
+            auth.allow_unsigned = allow.eq_ignore_ascii_case("true") || allow == "1";
+        }
+        if let Some(age) = std::env::var("IPC_SIGNATURE_MAX_AGE_SECS").ok().and_then(|v| v.parse().ok()) {
+            auth.max_age = age;
+        }
+        auth
+    }
+
+    /// Also sign with `keys`, so receivers can tell this agent from others holding the secret
+    pub fn with_keys(mut self, keys: &AgentKeys) -> Self {
+        self.signing = Some(keys.signing_key());
+        self
+    }
+
+    pub fn with_allow_unsigned(mut self, allow: bool) -> Self {
+        self.allow_unsigned = allow;
+        self
+    }
+
+    pub fn with_max_age(mut self, secs: u64) -> Self {
+        self.max_age = secs;
+        self
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.secret.is_some()
+    }
+
+    pub fn allows_unsigned(&self) -> bool {
+        self.allow_unsigned
+    }
+
+    pub fn identity(&self) -> &str {
+        &self.identity
+    }
+
+    /// Fingerprint of the swarm secret, None without one
+    pub fn fingerprint(&self) -> Option<String> {
+        self.secret.as_ref().map(|secret| key_fingerprint(secret))
+    }
+
+    /// Attach a signature to `message`; a no-op without a secret
+    pub fn sign(&self, message: &mut Message) {
+        if let Some(secret) = &self.secret {
+            let timestamp = unix_now();
+            let input = signing_input(&self.identity, timestamp, message);
+            let (agent_key, agent_signature) = match &self.signing {
+                Some(signing) => {
+                    let key = signing.verifying_key().to_bytes();
+                    let signature = signing.sign(&agent_input(&input, &key));
+                    (Some(b64().encode(key)), Some(b64().encode(signature.to_bytes())))
+                }
+                None => (None, None),
+            };
+            message.signature = Some(Signature {
+                agent: self.identity.clone(),
+                timestamp,
+                hmac: mac(secret, &input),
+                key: Some(key_fingerprint(secret)),
+                agent_key,
+                agent_signature,
+            });
+        }
+    }
+
+    /// Check `message`'s signature. Errors when it is forged, stale, or missing while
+    /// unsigned traffic isn't allowed.
+    pub fn verify(&self, message: &Message) -> Result<Sender> {
+        let (secret, signature) = match (&self.secret, &message.signature) {
+            (Some(secret), Some(signature)) => (secret, signature),
+            _ if self.allow_unsigned => return Ok(Sender::Unsigned),
+            (Some(_), None) => return Err(anyhow!("unsigned message")),
+            (None, Some(_)) => return Err(anyhow!("signed message but no IPC_SECRET to verify it")),
+            (None, None) => return Err(anyhow!("unsigned message")),
+        };
+        // The fingerprint isn't covered by the HMAC; it only explains why a signature can't verify
+        if let Some(key) = signature.key.as_deref().filter(|key| *key != key_fingerprint(secret)) {
+            return Err(anyhow!("{} signed with key {}, not this swarm's key {}", signature.agent, key, key_fingerprint(secret)));
+        }
+        if unix_now().abs_diff(signature.timestamp) > self.max_age {
+            return Err(anyhow!("signature from {} is too old", signature.agent));
+        }
+        let expected = base64::engine::general_purpose::STANDARD.decode(&signature.hmac)
+            .map_err(|_| anyhow!("malformed signature"))?;
+        let input = signing_input(&signature.agent, signature.timestamp, message);
+        let mut hmac = keyed(secret);
+        hmac.update(&input);
+        hmac.verify_slice(&expected).map_err(|_| anyhow!("bad signature claiming to be from {}", signature.agent))?;
+        if let Some(key) = &signature.agent_key {
+            verify_agent_key(key, signature.agent_signature.as_deref(), &input)
+                .map_err(|e| anyhow!("bad agent key signature claiming to be from {}: {}", signature.agent, e))?;
+        }
+        Ok(Sender::Verified(signature.agent.clone()))
+    }
+
+    /// Sign `data` that is kept rather than sent, such as a tool bundle; None without a secret
+    pub fn sign_bytes(&self, data: &[u8]) -> Option<Signature> {
+        let secret = self.secret.as_ref()?;
+        let timestamp = unix_now();
+        let mut hmac = keyed(secret);
+        hmac.update(&bytes_input(&self.identity, timestamp, data));
+        Some(Signature {
+            agent: self.identity.clone(),
+            timestamp,
+            hmac: base64::engine::general_purpose::STANDARD.encode(hmac.finalize().into_bytes()),
+            key: Some(key_fingerprint(secret)),
+            agent_key: None,
+            agent_signature: None,
+        })
+    }
+
+    /// Check a `sign_bytes` signature, under the rules of `verify` but at any age
+    pub fn verify_bytes(&self, data: &[u8], signature: Option<&Signature>) -> Result<Sender> {
+        let (secret, signature) = match (&self.secret, signature) {
+            (Some(secret), Some(signature)) => (secret, signature),
+            _ if self.allow_unsigned => return Ok(Sender::Unsigned),
+            (None, Some(signature)) => return Err(anyhow!("signed by {} but no IPC_SECRET to verify it", signature.agent)),
+            (_, None) => return Err(anyhow!("unsigned")),
+        };
+        if let Some(key) = signature.key.as_deref().filter(|key| *key != key_fingerprint(secret)) {
+            return Err(anyhow!("{} signed with key {}, not this swarm's key {}", signature.agent, key, key_fingerprint(secret)));
+        }
+        let expected = base64::engine::general_purpose::STANDARD.decode(&signature.hmac)
+            .map_err(|_| anyhow!("malformed signature"))?;
+        let mut hmac = keyed(secret);
+        hmac.update(&bytes_input(&signature.agent, signature.timestamp, data));
+        hmac.verify_slice(&expected).map_err(|_| anyhow!("bad signature claiming to be from {}", signature.agent))?;
+        Ok(Sender::Verified(signature.agent.clone()))
+    }
+}
+
+fn keyed(secret: &[u8]) -> HmacSha256 {
+    HmacSha256::new_from_slice(secret).expect("HMAC accepts keys of any length")
+}
+
+/// Length-prefixed fields so no two messages share an input. The context comes last, and only
+/// when there is one, so messages without it sign as they always have.
+fn signing_input(agent: &str, timestamp: u64, message: &Message) -> Vec<u8> {
+    let from = message.from.as_deref().unwrap_or("");
+    let id = message.id.as_deref().unwrap_or("");
+    let reply_to = message.reply_to.as_deref().unwrap_or("");
+    let mut input = Vec::new();
+    for field in [agent, &timestamp.to_string(), from, &message.content, id, reply_to].into_iter().chain(message.context.as_deref()) {
+        input.extend_from_slice(&(field.len() as u64).to_be_bytes());
+        input.extend_from_slice(field.as_bytes());
+    }
+    input
+}
+
+fn bytes_input(agent: &str, timestamp: u64, data: &[u8]) -> Vec<u8> {
+    let mut input = Vec::new();
+    for field in [agent.as_bytes(), timestamp.to_string().as_bytes(), data] {
+        input.extend_from_slice(&(field.len() as u64).to_be_bytes());
+        input.extend_from_slice(field);
+    }
+    input
+}
+
+/// What the agent key signs: the HMAC's input, then the key
+fn agent_input(input: &[u8], key: &[u8]) -> Vec<u8> {
+    let mut signed = input.to_vec();
+    signed.extend_from_slice(key);
+    signed
+}
+
+fn verify_agent_key(key: &str, signature: Option<&str>, input: &[u8]) -> Result<()> {
+    let key: [u8; 32] = b64().decode(key)?.try_into().map_err(|_| anyhow!("an Ed25519 key is 32 bytes"))?;
+    let signature: [u8; 64] = b64().decode(signature.ok_or_else(|| anyhow!("no signature for the key"))?)?
+        .try_into()
+        .map_err(|_| anyhow!("an Ed25519 signature is 64 bytes"))?;
+    VerifyingKey::from_bytes(&key)?
+        .verify(&agent_input(input, &key), &ed25519_dalek::Signature::from_bytes(&signature))
+        .map_err(|_| anyhow!("does not match the message"))
+}
+
+fn mac(secret: &[u8], input: &[u8]) -> String {
+    let mut hmac = keyed(secret);
+    hmac.update(input);
+    base64::engine::general_purpose::STANDARD.encode(hmac.finalize().into_bytes())
+}
+
+/// One allow/deny entry: an IP, a CIDR block, or a verified agent identity
+#[derive(Debug, Clone, PartialEq)]
+pub enum AccessRule {
+    Ip(IpAddr),
+    Network(IpAddr, u8),
+    Agent(String),
+}
+
+impl AccessRule {
+    /// `10.0.0.5`, `10.0.0.0/8`, or anything else as an agent name
+    pub fn parse(entry: &str) -> Self {
+        let entry = entry.trim();
+        if let Ok(ip) = entry.parse() {
+            return AccessRule::Ip(ip);
+        }
+        if let Some((ip, bits)) = entry.split_once('/') {
+            if let (Ok(ip), Ok(bits)) = (ip.parse::<IpAddr>(), bits.parse::<u8>()) {
+                let max = if ip.is_ipv4() { 32 } else { 128 };
+                return AccessRule::Network(ip, bits.min(max));
+            }
+        }
+        AccessRule::Agent(entry.to_string())
+    }
+
+    pub fn matches(&self, ip: Option<IpAddr>, sender: &Sender) -> bool {
+        match (self, ip, sender) {
+            (AccessRule::Ip(rule), Some(ip), _) => *rule == canonical(ip),
+            (AccessRule::Network(network, bits), Some(ip), _) => in_network(canonical(ip), *network, *bits),
+            (AccessRule::Agent(name), _, Sender::Verified(agent)) => name == agent,
+            _ => false,
+        }
+    }
+}
+
+/// IPv4 clients on a dual-stack listener show up as `::ffff:a.b.c.d`
+fn canonical(ip: IpAddr) -> IpAddr {
+    match ip {
+        IpAddr::V6(v6) => v6.to_ipv4_mapped().map(IpAddr::V4).unwrap_or(ip),
+        v4 => v4,
+    }
+}
+
+fn in_network(ip: IpAddr, network: IpAddr, bits: u8) -> bool {
+    match (ip, network) {
+        (IpAddr::V4(ip), IpAddr::V4(network)) => {
+            let mask = u32::MAX.checked_shl(32 - bits as u32).unwrap_or(0);
+            u32::from(ip) & mask == u32::from(network) & mask
+        }
+        (IpAddr::V6(ip), IpAddr::V6(network)) => {
+            let mask = u128::MAX.checked_shl(128 - bits as u32).unwrap_or(0);
+            u128::from(ip) & mask == u128::from(network) & mask
+        }
+        _ => false,
+    }
+}
+
+/// Which peers may talk to the IPC server. Deny entries win; a non-empty allowlist
+/// admits only the peers it matches. Agent entries only match verified signatures.
+#[derive(Debug, Clone, Default)]
+pub struct AccessControl {
+    pub allow: Vec<AccessRule>,
+    pub deny: Vec<AccessRule>,
+}
+
+impl AccessControl {
+    /// `IPC_ALLOW` and `IPC_DENY`: comma-separated IPs, CIDR blocks or agent names
+    pub fn from_env() -> Self {
+        let rules = |key: &str| -> Vec<AccessRule> {
+            std::env::var(key).unwrap_or_default()
+                .split(',')
+                .filter(|e| !e.trim().is_empty())
+                .map(AccessRule::parse)
+                .collect()
+        };
+        Self { allow: rules("IPC_ALLOW"), deny: rules("IPC_DENY") }
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.allow.is_empty() && self.deny.is_empty()
+    }
+
+    pub fn check(&self, ip: Option<IpAddr>, sender: &Sender) -> Result<()> {
+        let who = match sender {
+            Sender::Verified(agent) => format!("{} ({})", agent, ip.map(|i| i.to_string()).unwrap_or_default()),
+            Sender::Unsigned => ip.map(|i| i.to_string()).unwrap_or_else(|| "unknown peer".to_string()),
+        };
+        if self.deny.iter().any(|rule| rule.matches(ip, sender)) {
+            return Err(anyhow!("{} is denied", who));
+        }
+        if !self.allow.is_empty() && !self.allow.iter().any(|rule| rule.matches(ip, sender)) {
+            return Err(anyhow!("{} is not on the allowlist", who));
+        }
+        Ok(())
+    }
+}
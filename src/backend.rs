@@ -0,0 +1,270 @@
+use anyhow::{Result, anyhow};
+use rhai::{Dynamic, Engine, Scope, AST};
+use std::path::{Path, PathBuf};
+use std::sync::{OnceLock, RwLock};
+use crate::message::ToolSafetyLevel;
+use crate::safety::SafetyPolicy;
+use crate::tool_cache::ToolCache;
+use crate::tool_index::tool_description;
+use crate::tools::{list_tool_names, validate_tool_code};
+
+/// A function defined in a tool's source
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FunctionSignature {
+    pub name: String,
+    pub params: Vec<String>,
+    /// Its doc comment (`///` or `/** */`), if it has one
+    pub doc: Option<String>,
+}
+
+impl FunctionSignature {
+    /// How to call it, e.g. `clamp(n, lo, hi)`
+    pub fn call(&self) -> String {
+        format!("{}({})", self.name, self.params.join(", "))
+    }
+}
+
+/// What a backend reads from a tool's source
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ToolSignature {
+    /// Parameter names of the tool's function, in order
+    pub params: Vec<String>,
+    /// The function's doc comment, else the leading comment, if there is one
+    pub description: Option<String>,
+    /// The other functions the source defines, by name
+    pub helpers: Vec<FunctionSignature>,
+}
+
+impl ToolSignature {
+    /// `name(params)`, then the description if there is one, for the system prompt
+    pub fn summary(&self, name: &str) -> String {
+        let call = format!("{}({})", name, self.params.join(", "));
+        match &self.description {
+            Some(description) => format!("{} - {}", call, description),
+            None => call,
+        }
+    }
+}
+
+/// Runs the tool files with one extension. Rhai tools are built in; Python tools are enabled
+/// with `PYTHON_TOOLS`, and an application can add its own with `ToolManager::register_backend`.
+pub trait ToolBackend: Send + Sync {
+    /// Short name, e.g. `rhai` or `python`
+    fn name(&self) -> &str;
+
+    /// Extension of the tool files it runs, without the dot
+    fn extension(&self) -> &str;
+
+    /// Check that `code` would load as tool `name`, with the error if not. Run when a tool is
+    /// saved or imported.
+    fn compile(&self, name: &str, code: &str) -> Result<()>;
+
+    /// Run tool `name`, saved at `path`, with `args`
+    fn execute(&self, name: &str, path: &Path, args: &[String]) -> Result<String>;
+
+    /// The parameters and description of tool `name` in `code`
+    fn inspect(&self, name: &str, code: &str) -> ToolSignature;
+
+    /// The risk of running `code` under `safety`. By default, that of the natives it mentions.
+    fn validate(&self, code: &str, safety: &SafetyPolicy) -> ToolSafetyLevel {
+        validate_tool_code(code, safety)
+    }
+}
+
+/// Parameters of the first `{keyword} {name}(...)` line in `code`, without type annotations or
+/// defaults (and `self`)
+pub fn function_params(code: &str, keyword: &str, name: &str) -> Vec<String> {
+    let header = format!("{} {}(", keyword, name);
+    code.lines()
+        .find_map(|line| line.trim().strip_prefix(header.as_str()))
+        .and_then(|rest| rest.split_once(')'))
+        .map(|(params, _)| params.split(',')
+            .map(|p| p.split([':', '=']).next().unwrap_or_default().trim().to_string())
+            .filter(|p| !p.is_empty() && p != "self")
+            .collect())
+        .unwrap_or_default()
+}
+
+/// `function_params` and `tool_description` together
+pub fn signature_of(code: &str, keyword: &str, name: &str) -> ToolSignature {
+    let description = tool_description(code);
+    ToolSignature {
+        params: function_params(code, keyword, name),
+        description: (!description.is_empty()).then_some(description),
+        helpers: Vec::new(),
+    }
+}
+
+/// The text of doc comment lines, joined with spaces
+fn doc_text(comments: &[&str]) -> Option<String> {
+    let text = comments.iter()
+        .flat_map(|c| c.lines())
+        .map(|l| l.trim().trim_start_matches("/**").trim_end_matches("*/").trim_start_matches('/').trim_start_matches('*').trim())
+        .filter(|l| !l.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ");
+    (!text.is_empty()).then_some(text)
+}
+
+/// The signature of tool `name` from its compiled `ast`: the parameters of its function (the
+/// overload with the most, if there are several), its doc comment or else the leading comment of
+/// `code`, and the other functions
+pub fn ast_signature(ast: &AST, code: &str, name: &str) -> ToolSignature {
+    let mut functions: Vec<FunctionSignature> = ast.iter_functions()
+        .map(|f| FunctionSignature {
+            name: f.name.to_string(),
+            params: f.params.iter().map(|p| p.to_string()).collect(),
+            doc: doc_text(&f.comments),
+        })
+        .collect();
+    functions.sort_by(|a, b| a.name.cmp(&b.name).then(b.params.len().cmp(&a.params.len())));
+    let main = functions.iter().position(|f| f.name == name).map(|i| functions.remove(i));
+    functions.retain(|f| f.name != name);
+    let description = main.as_ref().and_then(|f| f.doc.clone())
+        .or_else(|| Some(tool_description(code)).filter(|d| !d.is_empty()));
+    ToolSignature { params: main.map(|f| f.params).unwrap_or_default(), description, helpers: functions }
+}
+
+/// The signature of Rhai tool `name`, parsed without running anything; falls back to reading
+/// the lines of `code` if it doesn't compile
+pub fn rhai_signature(engine: &Engine, code: &str, name: &str) -> ToolSignature {
+    match engine.compile(code) {
+        Ok(ast) => ast_signature(&ast, code, name),
+        Err(_) => signature_of(code, "fn", name),
+    }
+}
+
+fn extensions() -> &'static RwLock<Vec<String>> {
+    static EXTENSIONS: OnceLock<RwLock<Vec<String>>> = OnceLock::new();
+    EXTENSIONS.get_or_init(|| RwLock::new(vec!["rhai".to_string(), "py".to_string()]))
+}
+
+/// File extensions of tools: Rhai scripts, Python tools and those of every backend registered
+/// since, in that order
+pub fn tool_extensions() -> Vec<String> {
+    extensions().read().unwrap().clone()
+}
+
+/// Treat `*.{extension}` files as tools from now on, in every tools directory
+pub(crate) fn add_tool_extension(extension: &str) {
+    let mut known = extensions().write().unwrap();
+    if !known.iter().any(|e| e == extension) {
+        known.push(extension.to_string());
+    }
+}
+
+/// The built-in backend: Rhai scripts, run in the engine the natives are registered on. Each tool
+/// is compiled into its own AST (see `ToolCache`), merged with the tools it calls.
+pub struct RhaiBackend {
+    engine: Engine,
+    cache: ToolCache,
+    tools_dir: PathBuf,
+}
+
+impl RhaiBackend {
+    pub fn new(engine: Engine, cache: ToolCache, tools_dir: impl AsRef<Path>) -> Self {
+        Self { engine, cache, tools_dir: tools_dir.as_ref().to_path_buf() }
+    }
+
+    pub fn engine(&self) -> &Engine {
+        &self.engine
+    }
+
+    /// For registering natives after the fact
+    pub fn engine_mut(&mut self) -> &mut Engine {
+        &mut self.engine
+    }
+
+    pub fn cache(&self) -> &ToolCache {
+        &self.cache
+    }
+
+    /// Whether the engine has a native `name`
+    pub fn has_native(&self, name: &str) -> bool {
+        let prefix = format!("{}(", name);
+        self.engine.gen_fn_signatures(false).iter().any(|s| s.trim_start_matches("fn ").starts_with(&prefix))
+    }
+
+    /// The names of the engine's natives, each once
+    pub fn native_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.engine.gen_fn_signatures(false).iter()
+            .filter_map(|s| s.trim_start_matches("fn ").split_once('(').map(|(name, _)| name.to_string()))
+            .collect();
+        names.sort();
+        names.dedup();
+        names
+    }
+
+    /// `arg1`, `arg2`... for the longest overload of native `name`
+    pub fn native_params(&self, name: &str) -> Vec<String> {
+        let prefix = format!("{}(", name);
+        self.engine.gen_fn_signatures(false).iter()
+            .filter_map(|s| s.trim_start_matches("fn ").strip_prefix(&prefix)?.split_once(')').map(|(params, _)| params.to_string()))
+            .max_by_key(|params| params.len())
+            .map(|params| (1..=params.split(',').filter(|p| !p.trim().is_empty()).count()).map(|i| format!("arg{}", i)).collect())
+            .unwrap_or_default()
+    }
+
+    /// Run the script tool or native `name`
+    pub fn run(&self, name: &str, args: &[String]) -> Result<String> {
+        let mut scope = Scope::new();
+
+        // Script tools get their own AST (plus the tools they call), compiled lazily
+        let script_path = self.tools_dir.join(format!("{}.rhai", name));
+        let ast = if script_path.exists() {
+            let rhai_tools: Vec<String> = list_tool_names(&self.tools_dir).into_iter()
+                .filter(|t| self.tools_dir.join(format!("{}.rhai", t)).exists())
+                .collect();
+            self.cache.resolve(&self.engine, &self.tools_dir, name, &rhai_tools)
+                .map_err(|e| anyhow!("Error executing tool '{}': {}", name, e))?
+        } else {
+            AST::empty()
+        };
+
+        // A script tool takes its first argument, or none
+        let result: Result<Dynamic, _> = if args.is_empty() {
+            self.engine.call_fn(&mut scope, &ast, name, ())
+        } else {
+            self.engine.call_fn(&mut scope, &ast, name, (args[0].clone(),))
+        };
+
+        match result {
+            Ok(v) => Ok(v.to_string()),
+            // Not a script function: try the natives, which may take several arguments
+            Err(e) if e.to_string().contains("Function not found") => {
+                let mut params = Vec::new();
+                for (i, arg) in args.iter().enumerate() {
+                    scope.push(format!("arg{}", i), arg.clone());
+                    params.push(format!("arg{}", i));
+                }
+                let script = format!("{}({})", name, params.join(", "));
+                self.engine.eval_with_scope::<Dynamic>(&mut scope, &script)
+                    .map(|v| v.to_string())
+                    .map_err(|e2| anyhow!("Error executing tool '{}': {}", name, e2))
+            }
+            Err(e) => Err(anyhow!("Error executing tool '{}': {}", name, e)),
+        }
+    }
+}
+
+impl ToolBackend for RhaiBackend {
+    fn name(&self) -> &str {
+        "rhai"
+    }
+
+    fn extension(&self) -> &str {
+        "rhai"
+    }
+
+    fn compile(&self, _name: &str, code: &str) -> Result<()> {
+        self.engine.compile(code).map(|_| ()).map_err(|e| anyhow!("{}", e))
+    }
+
+    fn execute(&self, name: &str, _path: &Path, args: &[String]) -> Result<String> {
+        self.run(name, args)
+    }
+
+    fn inspect(&self, name: &str, code: &str) -> ToolSignature {
+        rhai_signature(&self.engine, code, name)
+    }
+}
@@ -0,0 +1,265 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::time::{SystemTime, UNIX_EPOCH};
+use crate::message::IpcMessage;
+use crate::registry::{address_key, topic_matches};
+use crate::state::StateStore;
+use rhai::Engine;
+use crate::tools::NativeContext;
+
+const STATE_KEY: &str = "blackboard";
+
+/// Writes seen per agent: `writer -> count`
+pub type VectorClock = BTreeMap<String, u64>;
+
+/// One value on the blackboard, with the history needed to merge it with peers' copies
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct BlackboardEntry {
+    pub key: String,
+    pub value: String,
+    pub clock: VectorClock,
+    /// The agent that wrote this value
+    pub writer: String,
+    /// Unix time (milliseconds) of the write; breaks ties between concurrent writes
+    pub timestamp: u64,
+}
+
+/// How two vector clocks relate: `Less` if `a` happened before `b`, `Greater` if after, None if
+/// the writes were concurrent
+pub fn compare_clocks(a: &VectorClock, b: &VectorClock) -> Option<Ordering> {
+    let (mut less, mut greater) = (false, false);
+    for writer in a.keys().chain(b.keys()) {
+        let (x, y) = (a.get(writer).copied().unwrap_or(0), b.get(writer).copied().unwrap_or(0));
+        less |= x < y;
+        greater |= x > y;
+    }
+    match (less, greater) {
+        (false, false) => Some(Ordering::Equal),
+        (true, false) => Some(Ordering::Less),
+        (false, true) => Some(Ordering::Greater),
+        (true, true) => None,
+    }
+}
+
+impl BlackboardEntry {
+    /// Whether this copy replaces `current`: it does if it happened after it, or, for concurrent
+    /// writes, if it was written later (last writer wins, then the higher writer name)
+    pub fn supersedes(&self, current: &BlackboardEntry) -> bool {
+        match compare_clocks(&self.clock, &current.clock) {
+            Some(ordering) => ordering == Ordering::Greater,
+            None => (self.timestamp, &self.writer) > (current.timestamp, &current.writer),
+        }
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct BlackboardData {
+    #[serde(default)]
+    entries: BTreeMap<String, BlackboardEntry>,
+    /// Key patterns whose changes by peers go to the inbox; a trailing `*` matches any suffix
+    #[serde(default)]
+    watches: Vec<String>,
+}
+
+fn now_millis() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis() as u64).unwrap_or(0)
+}
+
+/// Key-value store shared by the swarm, persisted in the agent state store. Local writes go out
+/// to peers as `BlackboardUpdate`s; copies are reconciled with vector clocks, so every agent ends
+/// up with the same value whatever order updates arrive in.
+#[derive(Debug, Clone)]
+pub struct Blackboard {
+    store: StateStore,
+}
+
+impl Blackboard {
+    pub fn new(store: StateStore) -> Self {
+        Self { store }
+    }
+
+    /// Write `value` under `key` as agent `writer`, returning the entry to send to peers
+    pub fn set(&self, key: &str, value: &str, writer: &str) -> Result<BlackboardEntry> {
+        self.store.update(STATE_KEY, |data: &mut BlackboardData| {
+            let mut clock = data.entries.get(key).map(|e| e.clock.clone()).unwrap_or_default();
+            *clock.entry(writer.to_string()).or_default() += 1;
+            let entry = BlackboardEntry {
+                key: key.to_string(),
+                value: value.to_string(),
+                clock,
+                writer: writer.to_string(),
+                timestamp: now_millis(),
+            };
+            data.entries.insert(key.to_string(), entry.clone());
+            entry
+        })
+    }
+
+    pub fn get(&self, key: &str) -> Result<Option<BlackboardEntry>> {
+        let data = self.store.get::<BlackboardData>(STATE_KEY)?.unwrap_or_default();
+        Ok(data.entries.get(key).cloned())
+    }
+
+    /// Every entry, by key
+    pub fn entries(&self) -> Result<Vec<BlackboardEntry>> {
+        let data = self.store.get::<BlackboardData>(STATE_KEY)?.unwrap_or_default();
+        Ok(data.entries.into_values().collect())
+    }
+
+    /// Take a peer's copy of an entry if it supersedes ours; true if it did. A concurrent write
+    /// that wins keeps both clocks, so it supersedes either copy from then on.
+    pub fn merge(&self, mut entry: BlackboardEntry) -> Result<bool> {
+        self.store.update(STATE_KEY, |data: &mut BlackboardData| {
+            let Some(current) = data.entries.get(&entry.key) else {
+                data.entries.insert(entry.key.clone(), entry);
+                return true;
+            };
+            if !entry.supersedes(current) {
+                return false;
+            }
+            for (writer, count) in &current.clock {
+                let seen = entry.clock.entry(writer.clone()).or_default();
+                *seen = (*seen).max(*count);
+            }
+            data.entries.insert(entry.key.clone(), entry);
+            true
+        })
+    }
+
+    /// Send peers' changes to keys matching `pattern` to the inbox; false if already watched
+    pub fn watch(&self, pattern: &str) -> Result<bool> {
+        self.store.update(STATE_KEY, |data: &mut BlackboardData| {
+            if data.watches.iter().any(|w| w == pattern) {
+                return false;
+            }
+            data.watches.push(pattern.to_string());
+            true
+        })
+    }
+
+    /// False if `pattern` wasn't watched
+    pub fn unwatch(&self, pattern: &str) -> Result<bool> {
+        self.store.update(STATE_KEY, |data: &mut BlackboardData| {
+            let before = data.watches.len();
+            data.watches.retain(|w| w != pattern);
+            data.watches.len() != before
+        })
+    }
+
+    pub fn is_watched(&self, key: &str) -> Result<bool> {
+        let data = self.store.get::<BlackboardData>(STATE_KEY)?.unwrap_or_default();
+        Ok(data.watches.iter().any(|pattern| topic_matches(pattern, key)))
+    }
+
+    /// One `key = value (writer)` line per entry
+    pub fn render(&self) -> Result<String> {
+        Ok(self.entries()?.iter()
+            .map(|e| format!("{} = {} ({})", e.key, e.value, e.writer))
+            .collect::<Vec<_>>()
+            .join("\n"))
+    }
+}
+
+/// `blackboard_set`, `blackboard_get` and the other blackboard natives
+pub(crate) fn register_blackboard_natives(engine: &mut Engine, ctx: &NativeContext) {
+    let NativeContext { threads, peers, local_address, .. } = ctx;
+    // Blackboard: a key-value store shared with the peers in BLACKBOARD_GROUP (default all)
+    let blackboard = Blackboard::new(threads.store().clone());
+    let blackboard_group = std::env::var("BLACKBOARD_GROUP").unwrap_or_else(|_| "all".to_string());
+    let blackboard_clone = blackboard.clone();
+    let peers_clone = peers.clone();
+    let address_clone = local_address.clone();
+    engine.register_fn("blackboard_set", move |key: &str, value: &str| -> String {
+        let from = address_clone.lock().unwrap().clone();
+        // Writes are counted per agent, by address once the server is up
+        let writer = from.clone().unwrap_or_else(|| std::env::var("AGENT_NAME").unwrap_or_else(|_| "Swarm Thing".to_string()));
+        let entry = match blackboard_clone.set(key, value, &writer) {
+            Ok(entry) => entry,
+            Err(e) => return format!("Error writing blackboard: {}", e),
+        };
+        let targets = peers_clone.resolve_group(&blackboard_group);
+        if targets.is_empty() {
+            return format!("Set '{}'", key);
+        }
+        let message = IpcMessage::BlackboardUpdate(entry);
+        let report = std::thread::spawn(move || {
+            let rt = tokio::runtime::Runtime::new().unwrap();
+            rt.block_on(crate::registry::broadcast(&targets, &message, from, std::time::Duration::from_secs(10)))
+        }).join();
+        match report {
+            Ok(report) => format!("Set '{}'; shared with {} of {} peer(s)", key, report.delivered(), report.deliveries.len()),
+            Err(_) => format!("Set '{}'; Thread panic sharing it", key),
+        }
+    });
+
+    let blackboard_clone = blackboard.clone();
+    engine.register_fn("blackboard_get", move |key: &str| -> String {
+        match blackboard_clone.get(key) {
+            Ok(Some(entry)) => entry.value,
+            Ok(None) => format!("Error: nothing on the blackboard under '{}'", key),
+            Err(e) => format!("Error reading blackboard: {}", e),
+        }
+    });
+
+    let blackboard_clone = blackboard.clone();
+    engine.register_fn("blackboard_get", move || -> String {
+        match blackboard_clone.render() {
+            Ok(rendered) if rendered.is_empty() => "The blackboard is empty".to_string(),
+            Ok(rendered) => rendered,
+            Err(e) => format!("Error reading blackboard: {}", e),
+        }
+    });
+
+    let blackboard_clone = blackboard.clone();
+    engine.register_fn("blackboard_watch", move |pattern: &str| -> String {
+        match blackboard_clone.watch(pattern) {
+            Ok(true) => format!("Watching '{}': peers' changes go to the inbox", pattern),
+            Ok(false) => format!("Already watching '{}'", pattern),
+            Err(e) => format!("Error watching blackboard: {}", e),
+        }
+    });
+
+    let blackboard_clone = blackboard.clone();
+    engine.register_fn("blackboard_unwatch", move |pattern: &str| -> String {
+        match blackboard_clone.unwatch(pattern) {
+            Ok(true) => format!("No longer watching '{}'", pattern),
+            Ok(false) => format!("Error: not watching '{}'", pattern),
+            Err(e) => format!("Error watching blackboard: {}", e),
+        }
+    });
+
+    // Catch up with a peer (after joining late, say): both sides end up with the newer entries
+    let blackboard_clone = blackboard.clone();
+    let address_clone = local_address.clone();
+    engine.register_fn("blackboard_sync", move |url: &str| -> String {
+        let entries = match blackboard_clone.entries() {
+            Ok(entries) => entries,
+            Err(e) => return format!("Error reading blackboard: {}", e),
+        };
+        let from = address_clone.lock().unwrap().clone();
+        let target = url.to_string();
+        let reply = std::thread::spawn(move || {
+            let rt = tokio::runtime::Runtime::new().unwrap();
+            rt.block_on(crate::ipc::send_ipc_message(&target, &IpcMessage::BlackboardSync { entries }, from))
+        }).join();
+        let entries = match reply {
+            Ok(Ok(reply)) => match IpcMessage::from_json_or_text(&reply) {
+                IpcMessage::BlackboardSync { entries } => entries,
+                _ => return format!("Error: {} answered: {}", url, reply),
+            },
+            Ok(Err(e)) => return format!("Error contacting {}: {}", url, e),
+            Err(_) => return "Thread panic".to_string(),
+        };
+        let mut updated = 0;
+        for entry in entries {
+            match blackboard_clone.merge(entry) {
+                Ok(true) => updated += 1,
+                Ok(false) => {}
+                Err(e) => return format!("Error writing blackboard: {}", e),
+            }
+        }
+        format!("Synced blackboard with {}: updated {} key(s) here", address_key(url), updated)
+    });
+}
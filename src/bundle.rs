@@ -0,0 +1,225 @@
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::Read;
+use std::path::Path;
+use crate::auth::{IpcAuth, Sender, Signature};
+use crate::message::ToolSafetyLevel;
+use crate::registry::unix_now;
+use crate::safety::SafetyPolicy;
+use crate::tool_index::tool_description;
+
+/// The manifest's name inside a bundle
+pub const MANIFEST: &str = "manifest.json";
+/// Version of the bundle layout this build writes and reads
+pub const BUNDLE_FORMAT: u32 = 1;
+/// Largest file a bundle may hold
+const MAX_ENTRY_BYTES: u64 = 1 << 20;
+
+/// A tool in a bundle's manifest
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BundledTool {
+    pub name: String,
+    /// `rhai` or `python`
+    pub language: String,
+    /// From a `// version: x` (or `# version: x`) line at the top of the tool
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    /// Hex SHA-256 of the source
+    pub sha256: String,
+    /// As classified by the exporting agent; the importer classifies the code again
+    pub safety_level: ToolSafetyLevel,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+}
+
+impl BundledTool {
+    pub fn is_python(&self) -> bool {
+        self.language == "python"
+    }
+
+    /// Where the source is kept in the bundle
+    pub fn file(&self) -> String {
+        format!("tools/{}.{}", self.name, if self.is_python() { "py" } else { "rhai" })
+    }
+}
+
+/// What a bundle holds and who exported it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BundleManifest {
+    pub format: u32,
+    pub exported_by: String,
+    /// Unix time (seconds) of the export
+    pub created: u64,
+    pub tools: Vec<BundledTool>,
+    /// Over the rest of the manifest, which holds each tool's hash
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signature: Option<Signature>,
+}
+
+impl BundleManifest {
+    /// The bytes the signature covers: the manifest without it
+    fn signed_bytes(&self) -> Vec<u8> {
+        let unsigned = Self { signature: None, ..self.clone() };
+        serde_json::to_vec(&unsigned).expect("a manifest serializes")
+    }
+}
+
+/// A bundle read back, every source matching its hash
+#[derive(Debug, Clone)]
+pub struct Bundle {
+    pub manifest: BundleManifest,
+    /// Source by tool name
+    pub sources: BTreeMap<String, String>,
+}
+
+impl Bundle {
+    /// Who signed the bundle, checked like an IPC message against the swarm secret, so an
+    /// unsigned bundle is only accepted where unsigned traffic is
+    pub fn verify(&self, auth: &IpcAuth) -> Result<Sender> {
+        auth.verify_bytes(&self.manifest.signed_bytes(), self.manifest.signature.as_ref())
+    }
+}
+
+/// Hex SHA-256 of `text`
+pub fn sha256_hex(text: &str) -> String {
+    Sha256::digest(text.as_bytes()).iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// The `version:` a tool declares in its leading comments
+pub fn tool_version(source: &str) -> Option<String> {
+    source.lines()
+        .map(str::trim)
+        .take_while(|l| l.is_empty() || l.starts_with("//") || l.starts_with('#'))
+        .filter_map(|l| l.trim_start_matches(['/', '#']).trim().strip_prefix("version:"))
+        .map(|v| v.trim().to_string())
+        .find(|v| !v.is_empty())
+}
+
+fn valid_name(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(|c| c.is_alphanumeric() || c == '_')
+}
+
+/// Write `tools` (name, source, whether Python) to a tar bundle at `path` with a manifest rating
+/// them under `safety`, signed when `auth` has a secret
+pub fn write_bundle(path: &Path, tools: &[(String, String, bool)], auth: &IpcAuth, safety: &SafetyPolicy) -> Result<BundleManifest> {
+    let mut manifest = BundleManifest {
+        format: BUNDLE_FORMAT,
+        exported_by: auth.identity().to_string(),
+        created: unix_now(),
+        tools: tools.iter().map(|(name, code, python)| BundledTool {
+            name: name.clone(),
+            language: if *python { "python" } else { "rhai" }.to_string(),
+            version: tool_version(code),
+            sha256: sha256_hex(code),
+            safety_level: if *python { ToolSafetyLevel::HighRisk } else { crate::tools::validate_tool_code(code, safety) },
+            description: Some(tool_description(code)).filter(|d| !d.is_empty()),
+        }).collect(),
+        signature: None,
+    };
+    manifest.signature = auth.sign_bytes(&manifest.signed_bytes());
+
+    let mut archive = tar::Builder::new(fs::File::create(path).map_err(|e| anyhow!("Cannot create {:?}: {}", path, e))?);
+    let mut append = |name: &str, data: &[u8]| -> Result<()> {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(data.len() as u64);
+        header.set_mode(0o644);
+        header.set_mtime(manifest.created);
+        archive.append_data(&mut header, name, data)?;
+        Ok(())
+    };
+    append(MANIFEST, &serde_json::to_vec_pretty(&manifest)?)?;
+    for (tool, (_, code, _)) in manifest.tools.iter().zip(tools) {
+        append(&tool.file(), code.as_bytes())?;
+    }
+    archive.into_inner()?.sync_all()?;
+    Ok(manifest)
+}
+
+/// Read the bundle at `path`, checking its layout and every tool's hash (not its signature;
+/// see `Bundle::verify`)
+pub fn read_bundle(path: &Path) -> Result<Bundle> {
+    let file = fs::File::open(path).map_err(|e| anyhow!("Cannot open {:?}: {}", path, e))?;
+    let mut files = BTreeMap::new();
+    for entry in tar::Archive::new(file).entries()? {
+        let entry = entry?;
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+        if entry.size() > MAX_ENTRY_BYTES {
+            return Err(anyhow!("{:?} is over {} bytes", entry.path()?, MAX_ENTRY_BYTES));
+        }
+        let name = entry.path()?.to_string_lossy().to_string();
+        let mut data = String::new();
+        entry.take(MAX_ENTRY_BYTES).read_to_string(&mut data).map_err(|e| anyhow!("Cannot read {}: {}", name, e))?;
+        files.insert(name, data);
+    }
+    let manifest: BundleManifest = serde_json::from_str(files.get(MANIFEST).ok_or_else(|| anyhow!("Not a tool bundle: no {}", MANIFEST))?)
+        .map_err(|e| anyhow!("Bad bundle manifest: {}", e))?;
+    if manifest.format != BUNDLE_FORMAT {
+        return Err(anyhow!("Bundle format {} is not supported (expected {})", manifest.format, BUNDLE_FORMAT));
+    }
+    let mut sources = BTreeMap::new();
+    for tool in &manifest.tools {
+        if !valid_name(&tool.name) || !["rhai", "python"].contains(&tool.language.as_str()) {
+            return Err(anyhow!("Bundle lists an invalid tool: '{}' ({})", tool.name, tool.language));
+        }
+        let code = files.remove(&tool.file()).ok_or_else(|| anyhow!("Bundle is missing {}", tool.file()))?;
+        if sha256_hex(&code) != tool.sha256 {
+            return Err(anyhow!("Tool '{}' does not match its hash in the manifest", tool.name));
+        }
+        if sources.insert(tool.name.clone(), code).is_some() {
+            return Err(anyhow!("Bundle lists tool '{}' twice", tool.name));
+        }
+    }
+    Ok(Bundle { manifest, sources })
+}
+
+/// What importing a tool came to
+#[derive(Debug, Clone, PartialEq)]
+pub enum ImportOutcome {
+    Installed,
+    /// The same code is already installed
+    Unchanged,
+    /// Waiting in the pending queue for `approve_tool`
+    Queued,
+    /// The operator said no on review
+    Declined,
+    /// Refused by the safety policy, or broken
+    Refused(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImportedTool {
+    pub name: String,
+    pub version: Option<String>,
+    pub outcome: ImportOutcome,
+}
+
+/// The outcome of `ToolManager::import_tools`
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImportReport {
+    /// Who signed the bundle (`alpha [key 1a2b…]`), or `unsigned bundle from alpha`
+    pub origin: String,
+    pub tools: Vec<ImportedTool>,
+}
+
+impl ImportReport {
+    pub fn render(&self) -> String {
+        let mut out = format!("Imported {} tools from {}", self.tools.len(), self.origin);
+        for tool in &self.tools {
+            let version = tool.version.as_ref().map(|v| format!(" {}", v)).unwrap_or_default();
+            let outcome = match &tool.outcome {
+                ImportOutcome::Installed => "installed".to_string(),
+                ImportOutcome::Unchanged => "unchanged".to_string(),
+                ImportOutcome::Queued => "queued for approval".to_string(),
+                ImportOutcome::Declined => "declined".to_string(),
+                ImportOutcome::Refused(reason) => format!("refused: {}", reason),
+            };
+            out.push_str(&format!("\n  {}{}: {}", tool.name, version, outcome));
+        }
+        out
+    }
+}
@@ -0,0 +1,156 @@
+use anyhow::{anyhow, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use crate::citations::format_time;
+use crate::message::session_id;
+use crate::registry::unix_now;
+
+/// Directory under the workspace root that holds the backups; the file tools can't touch it
+pub const BACKUP_DIR: &str = ".changes";
+
+/// What a file tool did to a file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChangeKind {
+    Write,
+    Append,
+    Delete,
+}
+
+impl ChangeKind {
+    pub fn name(self) -> &'static str {
+        match self {
+            ChangeKind::Write => "write",
+            ChangeKind::Append => "append",
+            ChangeKind::Delete => "delete",
+        }
+    }
+}
+
+/// One change to a file, and whether there was a file before it
+#[derive(Debug, Clone, PartialEq)]
+pub struct Change {
+    pub seq: u64,
+    /// Relative to the workspace root, with `/` separators
+    pub path: String,
+    pub kind: ChangeKind,
+    /// False if the change created the file
+    pub existed: bool,
+    /// Unix time (seconds)
+    pub time: u64,
+}
+
+impl Change {
+    pub fn render(&self) -> String {
+        let new = if self.existed { "" } else { " (new file)" };
+        format!("{} {}{} at {}", self.kind.name(), self.path, new, format_time(self.time))
+    }
+}
+
+#[derive(Debug)]
+struct Inner {
+    root: PathBuf,
+    /// This log's backups, `<root>/.changes/<id>/<seq>`
+    dir: PathBuf,
+    changes: Vec<Change>,
+    next: u64,
+}
+
+impl Drop for Inner {
+    // The backups only serve undo in this session
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.dir);
+        if let Some(parent) = self.dir.parent() {
+            let _ = fs::remove_dir(parent);
+        }
+    }
+}
+
+/// The file changes the file tools made this session, each with a copy of the file as it was
+/// before, so they can be undone newest first. Clones share the log.
+#[derive(Debug, Clone)]
+pub struct ChangeLog {
+    inner: Arc<Mutex<Inner>>,
+}
+
+impl ChangeLog {
+    /// A log for the workspace at `root` (an absolute, canonical path, like `FsJail::root`)
+    pub fn new(root: &Path) -> Self {
+        let dir = root.join(BACKUP_DIR).join(session_id());
+        Self { inner: Arc::new(Mutex::new(Inner { root: root.to_path_buf(), dir, changes: Vec::new(), next: 1 })) }
+    }
+
+    /// Whether `path` is among the backups, which only the log may change
+    pub fn is_reserved(&self, path: &Path) -> bool {
+        path.starts_with(self.inner.lock().unwrap().root.join(BACKUP_DIR))
+    }
+
+    /// Back `path` up, then let `change` do `kind` to it. The change is only logged if it succeeds.
+    pub fn track(&self, path: &Path, kind: ChangeKind, change: impl FnOnce() -> std::io::Result<()>) -> Result<()> {
+        if self.is_reserved(path) {
+            return Err(anyhow!("{} is kept for undo_changes and can't be changed", BACKUP_DIR));
+        }
+        let mut inner = self.inner.lock().unwrap();
+        let seq = inner.next;
+        let backup = inner.dir.join(seq.to_string());
+        let existed = path.is_file();
+        if existed {
+            fs::create_dir_all(&inner.dir)?;
+            fs::copy(path, &backup)?;
+        }
+        if let Err(e) = change() {
+            let _ = fs::remove_file(&backup);
+            return Err(e.into());
+        }
+        let relative = path.strip_prefix(&inner.root).unwrap_or(path);
+        let relative = relative.components().map(|c| c.as_os_str().to_string_lossy()).collect::<Vec<_>>().join("/");
+        inner.changes.push(Change { seq, path: relative, kind, existed, time: unix_now() });
+        inner.next += 1;
+        Ok(())
+    }
+
+    /// The changes, oldest first
+    pub fn list(&self) -> Vec<Change> {
+        self.inner.lock().unwrap().changes.clone()
+    }
+
+    /// A numbered list for `list_changes`
+    pub fn render(&self) -> String {
+        let changes = self.list();
+        if changes.is_empty() {
+            return "No file changes in this session".to_string();
+        }
+        let mut lines: Vec<String> = changes.iter().enumerate().map(|(i, c)| format!("{}. {}", i + 1, c.render())).collect();
+        lines.push("undo_changes(n) reverts the last n".to_string());
+        lines.join("\n")
+    }
+
+    /// Revert the last `n` changes, newest first: files are put back as they were, and files a
+    /// change created are removed. Says what was done to each.
+    pub fn undo(&self, n: usize) -> Result<Vec<String>> {
+        let mut inner = self.inner.lock().unwrap();
+        let mut undone = Vec::new();
+        for _ in 0..n {
+            let Some(change) = inner.changes.last().cloned() else { break };
+            let path = inner.root.join(&change.path);
+            let backup = inner.dir.join(change.seq.to_string());
+            if change.existed {
+                if let Some(parent) = path.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+                fs::copy(&backup, &path).map_err(|e| anyhow!("Cannot restore {}: {}", change.path, e))?;
+                let _ = fs::remove_file(&backup);
+                undone.push(format!("Restored {} (undid {})", change.path, change.kind.name()));
+            } else {
+                match fs::remove_file(&path) {
+                    Ok(()) => {}
+                    Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                    Err(e) => return Err(anyhow!("Cannot remove {}: {}", change.path, e)),
+                }
+                undone.push(format!("Removed {} (undid {})", change.path, change.kind.name()));
+            }
+            inner.changes.pop();
+        }
+        Ok(undone)
+    }
+}
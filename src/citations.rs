@@ -0,0 +1,131 @@
+use std::sync::{Arc, Mutex};
+use crate::registry::unix_now;
+use crate::scheduler::civil_date;
+use rhai::Engine;
+use crate::tools::NativeContext;
+
+/// Characters of a source's text kept as its excerpt
+const EXCERPT_CHARS: usize = 240;
+
+/// Shortest finding matched against the text of recorded sources; shorter ones match too easily
+const MIN_QUOTE_CHARS: usize = 20;
+
+/// A source a tool read during the session: where, when, by which tool, and the start of what it said
+#[derive(Debug, Clone, PartialEq)]
+pub struct Citation {
+    /// The URL, or what stands in for one (`search: <query>`)
+    pub source: String,
+    pub tool: String,
+    /// Unix time (seconds) it was last read
+    pub accessed: u64,
+    pub excerpt: String,
+}
+
+/// `2026-10-17 09:30 UTC`
+pub fn format_time(secs: u64) -> String {
+    let (year, month, day) = civil_date((secs / 86_400) as i64);
+    let minutes = secs % 86_400 / 60;
+    format!("{:04}-{:02}-{:02} {:02}:{:02} UTC", year, month, day, minutes / 60, minutes % 60)
+}
+
+fn normalize(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ").to_lowercase()
+}
+
+fn excerpt(text: &str) -> String {
+    let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    if text.chars().count() <= EXCERPT_CHARS {
+        return text;
+    }
+    let cut: String = text.chars().take(EXCERPT_CHARS).collect();
+    let cut = cut.rsplit_once(' ').map(|(head, _)| head).unwrap_or(&cut);
+    format!("{}…", cut.trim_end_matches(|c: char| c.is_ascii_punctuation()))
+}
+
+impl Citation {
+    /// What a report's source list adds after the source
+    pub fn footnote(&self) -> String {
+        format!("accessed {} via {}: \"{}\"", format_time(self.accessed), self.tool, self.excerpt)
+    }
+}
+
+#[derive(Debug)]
+struct Recorded {
+    citation: Citation,
+    /// Everything the tool returned, normalized, to recognise findings quoting it
+    text: String,
+}
+
+/// The sources read in this session, in the order they were first read. Clones share the list
+#[derive(Debug, Clone, Default)]
+pub struct Citations {
+    recorded: Arc<Mutex<Vec<Recorded>>>,
+}
+
+impl Citations {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Note that `tool` read `text` from `source`. Reading a source again refreshes its entry
+    pub fn record(&self, source: &str, tool: &str, text: &str) {
+        let source = source.trim();
+        if source.is_empty() || text.trim().is_empty() {
+            return;
+        }
+        let recorded = Recorded {
+            citation: Citation {
+                source: source.to_string(),
+                tool: tool.to_string(),
+                accessed: unix_now(),
+                excerpt: excerpt(text),
+            },
+            text: normalize(text),
+        };
+        let mut all = self.recorded.lock().unwrap();
+        match all.iter_mut().find(|r| r.citation.source == source) {
+            Some(existing) => *existing = recorded,
+            None => all.push(recorded),
+        }
+    }
+
+    pub fn list(&self) -> Vec<Citation> {
+        self.recorded.lock().unwrap().iter().map(|r| r.citation.clone()).collect()
+    }
+
+    pub fn get(&self, source: &str) -> Option<Citation> {
+        self.recorded.lock().unwrap().iter().find(|r| r.citation.source == source.trim()).map(|r| r.citation.clone())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.recorded.lock().unwrap().is_empty()
+    }
+
+    /// The source `content` came from: the last one whose URL it mentions or whose text it quotes
+    pub fn attribute(&self, content: &str) -> Option<String> {
+        let quote = normalize(content);
+        let quote = quote.trim_end_matches(['.', '!', '?']);
+        self.recorded.lock().unwrap().iter().rev()
+            .find(|r| content.contains(&r.citation.source) || (quote.chars().count() >= MIN_QUOTE_CHARS && r.text.contains(quote)))
+            .map(|r| r.citation.source.clone())
+    }
+
+    /// A numbered list for `list_sources`
+    pub fn render(&self) -> String {
+        let citations = self.list();
+        if citations.is_empty() {
+            return "No sources recorded in this session".to_string();
+        }
+        citations.iter().enumerate()
+            .map(|(i, c)| format!("{}. {} ({}, {})\n   \"{}\"", i + 1, c.source, c.tool, format_time(c.accessed), c.excerpt))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+/// `list_sources`: what `ctx.citations` has recorded
+pub(crate) fn register_citation_natives(engine: &mut Engine, ctx: &NativeContext) {
+    let NativeContext { citations, .. } = ctx;
+    let citations_clone = citations.clone();
+    engine.register_fn("list_sources", move || -> String { citations_clone.render() });
+}
@@ -0,0 +1,146 @@
+use clap::{Parser, Subcommand};
+use std::path::PathBuf;
+use crate::lineage::CloneOptions;
+
+/// A self-extending agent: chat with it, serve it to peers, or manage its tools and clones
+#[derive(Debug, Clone, Parser)]
+#[command(name = "swarm-thing", version)]
+pub struct Cli {
+    /// Run as this persona (default AGENT_PERSONA)
+    #[arg(long, global = true)]
+    pub persona: Option<String>,
+    /// Run risky tools without confirmation
+    #[arg(long, global = true)]
+    pub auto_approve: bool,
+    /// What to do; the interactive chat when absent
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Debug, Clone, PartialEq, Subcommand)]
+pub enum Command {
+    /// Chat in the REPL (the default)
+    Chat,
+    /// Serve peers and delegated tasks over IPC, without a REPL, until halted
+    Serve {
+        /// Port to serve on (default ipc.port from the config)
+        #[arg(long)]
+        port: Option<u16>,
+    },
+    /// Work on one prompt and print the answer
+    Run {
+        /// The prompt, or `-` to read it from stdin
+        prompt: String,
+        /// Print the outcome as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Work through a file of prompts, one per line
+    Batch {
+        file: PathBuf,
+        /// Print each outcome as a JSON line
+        #[arg(long)]
+        json: bool,
+    },
+    /// Re-run a journaled session on its recorded responses and report divergences
+    Replay {
+        /// Session id, `latest` or a journal file
+        target: String,
+        /// Save and run the tools again rather than only parsing the calls
+        #[arg(long)]
+        execute: bool,
+    },
+    /// Manage tools
+    #[command(subcommand)]
+    Tool(ToolCommand),
+    /// Copy this agent into a directory
+    Clone {
+        dir: String,
+        /// Only these tools or capabilities, comma separated
+        #[arg(long, value_delimiter = ',')]
+        tools: Option<Vec<String>>,
+        /// Copy the .env too, less its secrets
+        #[arg(long)]
+        env: bool,
+        /// Env file to give the clone instead of the .env
+        #[arg(long)]
+        config: Option<PathBuf>,
+        /// Persona the clone runs as
+        #[arg(long = "as", value_name = "PERSONA")]
+        clone_persona: Option<String>,
+    },
+    /// Serve the tools to MCP clients over stdio, or SSE with --sse
+    Mcp {
+        /// Serve MCP over SSE on 127.0.0.1:<PORT> instead of stdio
+        #[arg(long, value_name = "PORT")]
+        sse: Option<u16>,
+    },
+    /// Show or check the configuration
+    #[command(subcommand)]
+    Config(ConfigCommand),
+}
+
+#[derive(Debug, Clone, PartialEq, Subcommand)]
+pub enum ToolCommand {
+    /// List the installed tools
+    List,
+    /// Install a tool from a file, or stdin without one; `.py` files are Python tools
+    Create { name: String, file: Option<PathBuf> },
+    /// Run a tool
+    Run { name: String, args: Vec<String> },
+    /// Show a tool's source and risk
+    Inspect { name: String },
+    /// Remove a tool
+    Delete { name: String },
+    /// Write tools to a bundle another agent can import, signed with IPC_SECRET when set
+    Export {
+        file: PathBuf,
+        /// Only these tools, comma separated
+        #[arg(long, value_delimiter = ',')]
+        tools: Option<Vec<String>>,
+    },
+    /// Install the tools of a bundle, reviewing each as the confirm policy says
+    Import { file: PathBuf },
+    /// The commits that changed a tool (with TOOLS_GIT)
+    Log { name: String },
+    /// Put a tool back as it was at a commit
+    Revert { name: String, commit: String },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Subcommand)]
+pub enum ConfigCommand {
+    /// Print the effective config
+    Show,
+    /// Check the config, exiting with 1 on problems
+    Validate,
+}
+
+impl Command {
+    /// Whether an operator is at the terminal to confirm risky runs
+    pub fn is_interactive(&self) -> bool {
+        matches!(self, Command::Chat | Command::Tool(_))
+    }
+
+    /// Whether stdout is left to the command's output, startup messages going to stderr
+    pub fn is_scripted(&self) -> bool {
+        !matches!(self, Command::Chat | Command::Serve { .. })
+    }
+
+    /// Whether the command is an agent session, with a journal and a workspace of its own
+    pub fn is_session(&self) -> bool {
+        matches!(self, Command::Chat | Command::Serve { .. } | Command::Run { .. } | Command::Batch { .. })
+    }
+
+    /// The clone options of a `clone` command
+    pub fn clone_options(&self) -> Option<CloneOptions> {
+        match self {
+            Command::Clone { tools, env, config, clone_persona, .. } => Some(CloneOptions {
+                tools: tools.clone(),
+                env: *env && config.is_none(),
+                config: config.clone(),
+                persona: clone_persona.clone(),
+            }),
+            _ => None,
+        }
+    }
+}
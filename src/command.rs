@@ -0,0 +1,281 @@
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::sync::{Arc, Mutex, RwLock};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+use crate::audit::AuditLog;
+use crate::limits::Limits;
+use tracing::warn;
+use rhai::Engine;
+use crate::tools::NativeContext;
+
+/// Decides whether a command line may run; receives the full command line
+pub type CommandApprover = Arc<dyn Fn(&str) -> bool + Send + Sync>;
+
+/// Result of a `run_command` invocation
+#[derive(Debug, Clone, PartialEq)]
+pub struct CommandOutcome {
+    /// "ok", "denied", "timeout" or "error"
+    pub status: String,
+    pub exit_code: i64,
+    pub stdout: String,
+    pub stderr: String,
+    /// Output past the limit was dropped from `stdout` or `stderr`
+    pub truncated: bool,
+}
+
+impl CommandOutcome {
+    fn failed(status: &str, message: String) -> Self {
+        Self {
+            status: status.to_string(),
+            exit_code: -1,
+            stdout: String::new(),
+            stderr: message,
+            truncated: false,
+        }
+    }
+
+    pub fn to_map(&self) -> rhai::Map {
+        let mut map = rhai::Map::new();
+        map.insert("status".into(), self.status.clone().into());
+        map.insert("exit_code".into(), self.exit_code.into());
+        map.insert("stdout".into(), self.stdout.clone().into());
+        map.insert("stderr".into(), self.stderr.clone().into());
+        map.insert("truncated".into(), self.truncated.into());
+        map
+    }
+}
+
+/// Runs allowlisted shell commands after approval, with a timeout
+#[derive(Clone)]
+pub struct CommandRunner {
+    allowlist: Vec<String>,
+    timeout: Duration,
+    working_dir: PathBuf,
+    /// How much of each of stdout and stderr is kept
+    max_output_bytes: usize,
+    approver: Arc<RwLock<CommandApprover>>,
+    audit: AuditLog,
+}
+
+impl CommandRunner {
+    pub fn new(allowlist: Vec<String>, timeout: Duration, working_dir: impl AsRef<Path>, audit: AuditLog) -> Self {
+        // Deny by default; the REPL (or embedding host) installs an interactive approver
+        let approver: CommandApprover = Arc::new(|_| false);
+        Self {
+            allowlist,
+            timeout,
+            working_dir: working_dir.as_ref().to_path_buf(),
+            max_output_bytes: Limits::default().max_body_bytes,
+            approver: Arc::new(RwLock::new(approver)),
+            audit,
+        }
+    }
+
+    /// Configure from `COMMAND_ALLOWLIST` (comma separated) and `COMMAND_TIMEOUT_SECS` (default 30),
+    /// keeping as much output as a peer message may carry (`IPC_MAX_BODY_BYTES`)
+    pub fn from_env(working_dir: impl AsRef<Path>, audit: AuditLog) -> Self {
+        let allowlist = std::env::var("COMMAND_ALLOWLIST")
+            .map(|v| v.split(',').map(|c| c.trim().to_string()).filter(|c| !c.is_empty()).collect())
+            .unwrap_or_default();
+        let timeout = std::env::var("COMMAND_TIMEOUT_SECS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(30);
+        Self::new(allowlist, Duration::from_secs(timeout), working_dir, audit)
+            .with_max_output_bytes(Limits::from_env().max_body_bytes)
+    }
+
+    pub fn with_max_output_bytes(mut self, max_output_bytes: usize) -> Self {
+        self.max_output_bytes = max_output_bytes;
+        self
+    }
+
+    pub fn set_approver(&self, approver: CommandApprover) {
+        *self.approver.write().unwrap() = approver;
+    }
+
+    /// Whether the approver allows `command_line`
+    pub fn approves(&self, command_line: &str) -> bool {
+        (self.approver.read().unwrap())(command_line)
+    }
+
+    pub fn is_allowed(&self, cmd: &str) -> bool {
+        self.allowlist.iter().any(|allowed| allowed == cmd)
+    }
+
+    pub fn run(&self, cmd: &str, args: &[String]) -> CommandOutcome {
+        let command_line = if args.is_empty() {
+            cmd.to_string()
+        } else {
+            format!("{} {}", cmd, args.join(" "))
+        };
+
+        let outcome = if !self.is_allowed(cmd) {
+            CommandOutcome::failed("denied", format!("Command '{}' is not in the allowlist", cmd))
+        } else if !self.approves(&command_line) {
+            CommandOutcome::failed("denied", format!("Command '{}' was not approved", command_line))
+        } else {
+            self.spawn(cmd, args)
+        };
+
+        let result = format!("{} (exit code {})", outcome.status, outcome.exit_code);
+        if let Err(e) = self.audit.record("run_command", &command_line, &result) {
+            warn!("Failed to write audit log: {}", e);
+        }
+        outcome
+    }
+
+    fn spawn(&self, cmd: &str, args: &[String]) -> CommandOutcome {
+        let mut command = Command::new(cmd);
+        command.args(args)
+            .current_dir(&self.working_dir)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+        own_process_group(&mut command);
+        let mut child = match command.spawn() {
+            Ok(child) => child,
+            Err(e) => return CommandOutcome::failed("error", format!("Error starting command: {}", e)),
+        };
+
+        // Drain both pipes on their own threads so a chatty process can't block on a full pipe.
+        // Output is collected incrementally so a timed-out command still reports what it printed,
+        // even if a grandchild keeps the pipe open.
+        let (stdout, stdout_reader) = drain(child.stdout.take().unwrap(), self.max_output_bytes);
+        let (stderr, stderr_reader) = drain(child.stderr.take().unwrap(), self.max_output_bytes);
+
+        let started = Instant::now();
+        let status = loop {
+            match child.try_wait() {
+                Ok(Some(status)) => break Some(status),
+                Ok(None) if started.elapsed() >= self.timeout => {
+                    kill_group(&mut child);
+                    let _ = child.wait();
+                    break None;
+                }
+                Ok(None) => std::thread::sleep(Duration::from_millis(20)),
+                Err(e) => {
+                    kill_group(&mut child);
+                    return CommandOutcome::failed("error", format!("Error waiting for command: {}", e));
+                }
+            }
+        };
+
+        // Whatever the command left running in the background (`sleep 999 &`) goes with it, so
+        // the pipes close; one that left the group is waited for only briefly
+        kill_group(&mut child);
+        let deadline = Instant::now() + PIPE_GRACE;
+        join_by(stdout_reader, deadline);
+        join_by(stderr_reader, deadline);
+        let (stdout, stderr) = (stdout.lock().unwrap(), stderr.lock().unwrap());
+        let truncated = stdout.truncated || stderr.truncated;
+        let (stdout, stderr) = (stdout.text(), stderr.text());
+        match status {
+            Some(status) => CommandOutcome {
+                status: "ok".to_string(),
+                exit_code: status.code().map(i64::from).unwrap_or(-1),
+                stdout,
+                stderr,
+                truncated,
+            },
+            None => CommandOutcome {
+                status: "timeout".to_string(),
+                exit_code: -1,
+                stdout,
+                stderr: format!("{}Command timed out after {:?}", stderr, self.timeout),
+                truncated,
+            },
+        }
+    }
+}
+
+/// How long output is still read after a command ends, from processes it left behind
+pub(crate) const PIPE_GRACE: Duration = Duration::from_secs(1);
+
+/// Start the child in a process group of its own, so `kill_group` reaches what it spawns
+#[cfg(unix)]
+pub(crate) fn own_process_group(command: &mut Command) {
+    use std::os::unix::process::CommandExt;
+    command.process_group(0);
+}
+
+#[cfg(not(unix))]
+pub(crate) fn own_process_group(_command: &mut Command) {}
+
+/// Kill `child` and everything left in its process group
+#[cfg(unix)]
+pub(crate) fn kill_group(child: &mut Child) {
+    // Safety: killpg only sends a signal; the group id is the child's pid (`process_group(0)`)
+    unsafe {
+        libc::killpg(child.id() as libc::pid_t, libc::SIGKILL);
+    }
+    let _ = child.kill();
+}
+
+#[cfg(not(unix))]
+pub(crate) fn kill_group(child: &mut Child) {
+    let _ = child.kill();
+}
+
+/// Join a `drain` reader if it finishes by `deadline`; one still blocked then is left to end
+/// on its own, with what it read so far already in its buffer
+pub(crate) fn join_by(reader: JoinHandle<()>, deadline: Instant) {
+    while !reader.is_finished() && Instant::now() < deadline {
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    if reader.is_finished() {
+        let _ = reader.join();
+    }
+}
+
+/// What `drain` read from a pipe, up to its limit
+#[derive(Debug, Default)]
+pub(crate) struct Captured {
+    pub(crate) bytes: Vec<u8>,
+    /// More was read than the limit kept
+    pub(crate) truncated: bool,
+}
+
+impl Captured {
+    pub(crate) fn text(&self) -> String {
+        String::from_utf8_lossy(&self.bytes).to_string()
+    }
+}
+
+/// Read a child pipe to completion on a background thread, collecting output incrementally.
+/// Only the first `limit` bytes are kept; the rest is read and dropped so the child never blocks.
+pub(crate) fn drain(mut pipe: impl Read + Send + 'static, limit: usize) -> (Arc<Mutex<Captured>>, JoinHandle<()>) {
+    let buffer = Arc::new(Mutex::new(Captured::default()));
+    let sink = buffer.clone();
+    let reader = std::thread::spawn(move || {
+        let mut chunk = [0u8; 4096];
+        while let Ok(n) = pipe.read(&mut chunk) {
+            if n == 0 {
+                break;
+            }
+            let mut captured = sink.lock().unwrap();
+            let kept = n.min(limit.saturating_sub(captured.bytes.len()));
+            captured.bytes.extend_from_slice(&chunk[..kept]);
+            captured.truncated |= kept < n;
+        }
+    });
+    (buffer, reader)
+}
+
+/// `run_command`, through `ctx.commands`
+pub(crate) fn register_command_natives(engine: &mut Engine, ctx: &NativeContext) {
+    let NativeContext { commands, .. } = ctx;
+    let commands_clone = commands.clone();
+    engine.register_fn("run_command", move |cmd: &str, args: &str| -> rhai::Map {
+        let args: Vec<String> = args.split_whitespace().map(String::from).collect();
+        commands_clone.run(cmd, &args).to_map()
+    });
+
+    let commands_clone = commands.clone();
+    engine.register_fn("run_command", move |cmd: &str, args: rhai::Array| -> rhai::Map {
+        let args: Vec<String> = args.iter().map(|a| a.to_string()).collect();
+        commands_clone.run(cmd, &args).to_map()
+    });
+}
@@ -0,0 +1,128 @@
+use anyhow::{Result, anyhow};
+use tokio::task::JoinSet;
+use crate::llm::{LlmClient, Message};
+
+/// One model's answer to a comparison prompt
+#[derive(Debug)]
+pub struct ModelAnswer {
+    pub model: String,
+    pub answer: Result<String>,
+}
+
+/// The outcome of asking several models the same question
+#[derive(Debug)]
+pub struct Comparison {
+    pub prompt: String,
+    pub answers: Vec<ModelAnswer>,
+    /// Reconciled answer from the judge model, if one is configured
+    pub verdict: Option<String>,
+}
+
+impl Comparison {
+    /// Render all answers (and the verdict) as labelled sections
+    pub fn render(&self) -> String {
+        let mut output = String::new();
+        for answer in &self.answers {
+            output.push_str(&format!("=== {} ===\n", answer.model));
+            match &answer.answer {
+                Ok(text) => output.push_str(text.trim()),
+                Err(e) => output.push_str(&format!("Error: {}", e)),
+            }
+            output.push_str("\n\n");
+        }
+        if let Some(verdict) = &self.verdict {
+            output.push_str("=== Judge verdict ===\n");
+            output.push_str(verdict.trim());
+            output.push('\n');
+        }
+        output
+    }
+}
+
+/// Sends one prompt to several models in parallel and optionally reconciles them
+pub struct Comparer {
+    models: Vec<LlmClient>,
+    judge: Option<LlmClient>,
+}
+
+impl Comparer {
+    pub fn new(models: Vec<LlmClient>, judge: Option<LlmClient>) -> Self {
+        Self { models, judge }
+    }
+
+    /// Build from `COMPARE_MODELS` (comma separated `provider:model` specs)
+    /// and the optional `COMPARE_JUDGE` spec
+    pub async fn from_env() -> Result<Self> {
+        let specs = std::env::var("COMPARE_MODELS")
+            .map_err(|_| anyhow!("COMPARE_MODELS is not set (e.g. bedrock:anthropic.claude-3-haiku-20240307-v1:0,ollama:llama3.1)"))?;
+
+        let mut models = Vec::new();
+        for spec in specs.split(',').filter(|s| !s.trim().is_empty()) {
+            models.push(LlmClient::from_spec(spec).await?);
+        }
+        if models.len() < 2 {
+            return Err(anyhow!("COMPARE_MODELS must list at least two models"));
+        }
+
+        let judge = match std::env::var("COMPARE_JUDGE") {
+            Ok(spec) if !spec.trim().is_empty() => Some(LlmClient::from_spec(&spec).await?),
+            _ => None,
+        };
+
+        Ok(Self::new(models, judge))
+    }
+
+    pub fn models(&self) -> Vec<String> {
+        self.models.iter().map(|m| m.label()).collect()
+    }
+
+    pub async fn compare(&self, prompt: &str, system_prompt: Option<String>) -> Result<Comparison> {
+        let messages = vec![Message::user(prompt)];
+
+        let mut tasks = JoinSet::new();
+        for (index, model) in self.models.iter().enumerate() {
+            let model = model.clone();
+            let messages = messages.clone();
+            let system_prompt = system_prompt.clone();
+            tasks.spawn(async move {
+                (index, model.label(), model.chat(messages, system_prompt).await)
+            });
+        }
+
+        let mut answers = Vec::new();
+        while let Some(joined) = tasks.join_next().await {
+            let (index, model, answer) = joined?;
+            answers.push((index, ModelAnswer { model, answer }));
+        }
+        answers.sort_by_key(|(index, _)| *index);
+        let answers: Vec<ModelAnswer> = answers.into_iter().map(|(_, a)| a).collect();
+
+        let verdict = match &self.judge {
+            Some(judge) => Some(Self::reconcile(judge, prompt, &answers).await?),
+            None => None,
+        };
+
+        Ok(Comparison {
+            prompt: prompt.to_string(),
+            answers,
+            verdict,
+        })
+    }
+
+    async fn reconcile(judge: &LlmClient, prompt: &str, answers: &[ModelAnswer]) -> Result<String> {
+        let mut request = format!("Question:\n{}\n\n", prompt);
+        for answer in answers {
+            if let Ok(text) = &answer.answer {
+                request.push_str(&format!("Answer from {}:\n{}\n\n", answer.model, text.trim()));
+            }
+        }
+        request.push_str(
+            "Reconcile these answers into a single final answer. \
+             Afterwards, list every point where the answers disagree under a 'Disagreements:' heading \
+             (write 'Disagreements: none' if they agree).",
+        );
+
+        let system = "You are a careful judge comparing answers from several AI models.".to_string();
+        judge.chat(vec![Message::user(request)], Some(system)).await
+    }
+}
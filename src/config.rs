@@ -0,0 +1,501 @@
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use crate::logging::LogFormat;
+use crate::parser::ActionProtocol;
+use crate::persona::Persona;
+use crate::provider::providers;
+use crate::safety::SafetyPolicy;
+use crate::workspace::Cleanup;
+use tracing_subscriber::EnvFilter;
+
+/// Config file read when `SWARM_CONFIG` doesn't name another
+pub const DEFAULT_CONFIG_FILE: &str = "swarm.toml";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AgentConfig {
+    /// How the agent introduces itself to the model and to peers
+    pub name: String,
+    pub persona: Option<String>,
+    /// Tool calls one `run`/`batch` task may chain
+    pub max_steps: usize,
+    /// Times the model is shown a broken tool's error and asked for a fix (0 to never ask)
+    pub fix_attempts: usize,
+    /// Workers running `execute_tool_background` jobs at the same time
+    pub job_workers: usize,
+    /// Independent tool calls of one response run at the same time (1 runs them in turn)
+    pub parallel_tools: usize,
+}
+
+impl Default for AgentConfig {
+    fn default() -> Self {
+        Self { name: "Swarm Thing".to_string(), persona: None, max_steps: 5, fix_attempts: 2, job_workers: 2, parallel_tools: 4 }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LlmConfig {
+    /// `bedrock`, `ollama` or `openai`
+    pub provider: String,
+    /// The provider's default model when absent
+    pub model: Option<String>,
+    /// `provider:model` spec to fail over to
+    pub fallback: Option<String>,
+    pub ollama_url: Option<String>,
+    /// How the model writes its actions: `markers` (code blocks and `[TOOL: ...]`) or `json`
+    pub protocol: String,
+    /// Per-model `protocol` overrides, keyed by `provider` or `provider:model`
+    pub protocols: BTreeMap<String, String>,
+    /// Seconds one request to the model may take before it is retried (0 for no limit)
+    pub timeout_secs: u64,
+}
+
+impl Default for LlmConfig {
+    fn default() -> Self {
+        Self {
+            provider: "bedrock".to_string(),
+            model: None,
+            fallback: None,
+            ollama_url: None,
+            protocol: "markers".to_string(),
+            protocols: BTreeMap::new(),
+            timeout_secs: 120,
+        }
+    }
+}
+
+impl LlmConfig {
+    /// The action protocol for the model labelled `label` (`provider:model`, see `LlmClient::label`)
+    pub fn protocol_for(&self, label: &str) -> ActionProtocol {
+        let provider = label.split(':').next().unwrap_or(label);
+        self.protocols.get(label)
+            .or_else(|| self.protocols.get(provider))
+            .unwrap_or(&self.protocol)
+            .parse()
+            .unwrap_or_default()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct IpcConfig {
+    /// The REPL starts its IPC server on this port when set
+    pub port: Option<u16>,
+    pub bind: String,
+    /// Host peers should use to reach this agent
+    pub public_host: Option<String>,
+    pub grpc_port: Option<u16>,
+    pub discovery_port: u16,
+    /// The web dashboard is served on this port, on 127.0.0.1, when set
+    pub dashboard_port: Option<u16>,
+}
+
+impl Default for IpcConfig {
+    fn default() -> Self {
+        Self { port: None, bind: "127.0.0.1".to_string(), public_host: None, grpc_port: None, discovery_port: 9898, dashboard_port: None }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PathsConfig {
+    pub tools_dir: PathBuf,
+    /// Root file-system tools are confined to
+    pub workspace: PathBuf,
+    pub prompts_dir: PathBuf,
+    pub personas_dir: PathBuf,
+    pub journal_dir: PathBuf,
+}
+
+impl Default for PathsConfig {
+    fn default() -> Self {
+        Self {
+            tools_dir: PathBuf::from("tools"),
+            workspace: PathBuf::from("."),
+            prompts_dir: PathBuf::from("prompts"),
+            personas_dir: PathBuf::from("personas"),
+            journal_dir: PathBuf::from("state/journal"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WorkspaceConfig {
+    /// Give each chat, run, batch or serve session a directory of its own under `dir`, where the
+    /// file tools work instead of `paths.workspace`
+    pub per_session: bool,
+    pub dir: PathBuf,
+    /// When a session ends: `keep` its workspace, remove it if `empty`, or remove it `always`
+    pub cleanup: String,
+    /// Session workspaces kept, the newest first, including the current one (0 for no limit)
+    pub keep_last: usize,
+    /// Days after their last change older session workspaces are removed (0 for never)
+    pub max_age_days: u64,
+}
+
+impl Default for WorkspaceConfig {
+    fn default() -> Self {
+        Self { per_session: true, dir: PathBuf::from("state/workspaces"), cleanup: "empty".to_string(), keep_last: 20, max_age_days: 30 }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PolicyConfig {
+    /// The safety policy file
+    pub safety: PathBuf,
+    /// Least risky tool level the operator confirms: `low_risk`, `medium_risk`, `high_risk` or `off`
+    pub confirm_risk: String,
+    /// Programs `run_command` may start
+    pub command_allowlist: Vec<String>,
+    /// Extra rules rendered into the system prompt
+    pub prompt: Vec<String>,
+    /// Keep the tools directory in git, committing every tool change
+    pub tools_git: bool,
+    /// Seconds one tool run may take (0 for no limit)
+    pub tool_timeout_secs: u64,
+}
+
+impl Default for PolicyConfig {
+    fn default() -> Self {
+        Self {
+            safety: PathBuf::from("policy.toml"),
+            confirm_risk: "medium_risk".to_string(),
+            command_allowlist: Vec::new(),
+            prompt: Vec::new(),
+            tools_git: false,
+            tool_timeout_secs: 60,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PeersConfig {
+    /// A peer's `host:port` to announce to on startup
+    pub registry: Option<String>,
+    /// `udp` to broadcast on the discovery port
+    pub discovery: Option<String>,
+    /// Named groups of peer addresses
+    pub groups: BTreeMap<String, Vec<String>>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct McpConfig {
+    /// External MCP servers whose tools the agent calls as `mcp::<name>::<tool>`
+    pub servers: BTreeMap<String, McpServerConfig>,
+}
+
+/// An MCP server launched as a child process, speaking MCP on its stdin and stdout
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct McpServerConfig {
+    pub command: String,
+    pub args: Vec<String>,
+    /// Extra environment for the server
+    pub env: BTreeMap<String, String>,
+    /// Seconds to wait for each answer
+    pub timeout_secs: u64,
+}
+
+impl Default for McpServerConfig {
+    fn default() -> Self {
+        Self { command: String::new(), args: Vec::new(), env: BTreeMap::new(), timeout_secs: 30 }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LogConfig {
+    /// Least severe level logged (`error`, `warn`, `info`, `debug`, `trace`), or an `EnvFilter` directive list
+    pub level: String,
+    /// `text` or `json` (one object per line)
+    pub format: String,
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        Self { level: "info".to_string(), format: "text".to_string() }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TelemetryConfig {
+    /// OTLP/gRPC collector spans are exported to, e.g. `http://localhost:4317`; none are without one
+    pub otlp_endpoint: Option<String>,
+    /// `service.name` of the exported spans, the agent's name when absent
+    pub service_name: Option<String>,
+}
+
+/// Settings from `swarm.toml`, each overridden by its environment variable when that is set
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub agent: AgentConfig,
+    pub llm: LlmConfig,
+    pub ipc: IpcConfig,
+    pub paths: PathsConfig,
+    pub workspace: WorkspaceConfig,
+    pub policies: PolicyConfig,
+    pub peers: PeersConfig,
+    pub mcp: McpConfig,
+    pub log: LogConfig,
+    pub telemetry: TelemetryConfig,
+    /// The file read, if any
+    #[serde(skip)]
+    pub path: Option<PathBuf>,
+    /// Environment variables that overrode the file
+    #[serde(skip)]
+    pub overridden: Vec<&'static str>,
+}
+
+/// A setting and the environment variable that overrides it
+struct Setting {
+    env: &'static str,
+    get: fn(&Config) -> Option<String>,
+    set: fn(&mut Config, &str) -> Result<()>,
+}
+
+const SETTINGS: &[Setting] = &[
+    Setting { env: "AGENT_NAME", get: |c| Some(c.agent.name.clone()), set: |c, v| { c.agent.name = v.to_string(); Ok(()) } },
+    Setting { env: "AGENT_PERSONA", get: |c| c.agent.persona.clone(), set: |c, v| { c.agent.persona = Some(v.to_string()); Ok(()) } },
+    Setting { env: "AGENT_MAX_STEPS", get: |c| Some(c.agent.max_steps.to_string()), set: |c, v| { c.agent.max_steps = number("AGENT_MAX_STEPS", v)?; Ok(()) } },
+    Setting { env: "AGENT_FIX_ATTEMPTS", get: |c| Some(c.agent.fix_attempts.to_string()), set: |c, v| { c.agent.fix_attempts = number("AGENT_FIX_ATTEMPTS", v)?; Ok(()) } },
+    Setting { env: "AGENT_PARALLEL_TOOLS", get: |c| Some(c.agent.parallel_tools.to_string()), set: |c, v| { c.agent.parallel_tools = number("AGENT_PARALLEL_TOOLS", v)?; Ok(()) } },
+    Setting { env: "AGENT_JOB_WORKERS", get: |c| Some(c.agent.job_workers.to_string()), set: |c, v| { c.agent.job_workers = number("AGENT_JOB_WORKERS", v)?; Ok(()) } },
+    Setting { env: "LLM_PROVIDER", get: |c| Some(c.llm.provider.clone()), set: |c, v| { c.llm.provider = v.to_string(); Ok(()) } },
+    Setting { env: "MODEL_ID", get: |c| c.llm.model.clone(), set: |c, v| { c.llm.model = Some(v.to_string()); Ok(()) } },
+    Setting { env: "LLM_FALLBACK", get: |c| c.llm.fallback.clone(), set: |c, v| { c.llm.fallback = Some(v.to_string()); Ok(()) } },
+    Setting { env: "OLLAMA_URL", get: |c| c.llm.ollama_url.clone(), set: |c, v| { c.llm.ollama_url = Some(v.to_string()); Ok(()) } },
+    Setting { env: "LLM_TIMEOUT_SECS", get: |c| Some(c.llm.timeout_secs.to_string()), set: |c, v| { c.llm.timeout_secs = number("LLM_TIMEOUT_SECS", v)?; Ok(()) } },
+    Setting { env: "ACTION_PROTOCOL", get: |c| Some(c.llm.protocol.clone()), set: |c, v| { c.llm.protocol = v.to_string(); Ok(()) } },
+    Setting { env: "IPC_PORT", get: |c| c.ipc.port.map(|p| p.to_string()), set: |c, v| { c.ipc.port = Some(number("IPC_PORT", v)?); Ok(()) } },
+    Setting { env: "IPC_BIND", get: |c| Some(c.ipc.bind.clone()), set: |c, v| { c.ipc.bind = v.to_string(); Ok(()) } },
+    Setting { env: "IPC_PUBLIC_HOST", get: |c| c.ipc.public_host.clone(), set: |c, v| { c.ipc.public_host = Some(v.to_string()); Ok(()) } },
+    Setting { env: "GRPC_PORT", get: |c| c.ipc.grpc_port.map(|p| p.to_string()), set: |c, v| { c.ipc.grpc_port = Some(number("GRPC_PORT", v)?); Ok(()) } },
+    Setting { env: "DISCOVERY_PORT", get: |c| Some(c.ipc.discovery_port.to_string()), set: |c, v| { c.ipc.discovery_port = number("DISCOVERY_PORT", v)?; Ok(()) } },
+    Setting { env: "DASHBOARD_PORT", get: |c| c.ipc.dashboard_port.map(|p| p.to_string()), set: |c, v| { c.ipc.dashboard_port = Some(number("DASHBOARD_PORT", v)?); Ok(()) } },
+    Setting { env: "TOOLS_DIR", get: |c| Some(display(&c.paths.tools_dir)), set: |c, v| { c.paths.tools_dir = PathBuf::from(v); Ok(()) } },
+    Setting { env: "AGENT_WORKSPACE", get: |c| Some(display(&c.paths.workspace)), set: |c, v| { c.paths.workspace = PathBuf::from(v); Ok(()) } },
+    Setting { env: "PROMPTS_DIR", get: |c| Some(display(&c.paths.prompts_dir)), set: |c, v| { c.paths.prompts_dir = PathBuf::from(v); Ok(()) } },
+    Setting { env: "PERSONAS_DIR", get: |c| Some(display(&c.paths.personas_dir)), set: |c, v| { c.paths.personas_dir = PathBuf::from(v); Ok(()) } },
+    Setting { env: "AGENT_JOURNAL_DIR", get: |c| Some(display(&c.paths.journal_dir)), set: |c, v| { c.paths.journal_dir = PathBuf::from(v); Ok(()) } },
+    Setting { env: "SESSION_WORKSPACE", get: |c| Some(c.workspace.per_session.to_string()), set: |c, v| { c.workspace.per_session = flag("SESSION_WORKSPACE", v)?; Ok(()) } },
+    Setting { env: "SESSION_WORKSPACE_DIR", get: |c| Some(display(&c.workspace.dir)), set: |c, v| { c.workspace.dir = PathBuf::from(v); Ok(()) } },
+    Setting { env: "WORKSPACE_CLEANUP", get: |c| Some(c.workspace.cleanup.clone()), set: |c, v| { c.workspace.cleanup = v.to_string(); Ok(()) } },
+    Setting { env: "WORKSPACE_KEEP_LAST", get: |c| Some(c.workspace.keep_last.to_string()), set: |c, v| { c.workspace.keep_last = number("WORKSPACE_KEEP_LAST", v)?; Ok(()) } },
+    Setting { env: "WORKSPACE_MAX_AGE_DAYS", get: |c| Some(c.workspace.max_age_days.to_string()), set: |c, v| { c.workspace.max_age_days = number("WORKSPACE_MAX_AGE_DAYS", v)?; Ok(()) } },
+    Setting { env: "SAFETY_POLICY", get: |c| Some(display(&c.policies.safety)), set: |c, v| { c.policies.safety = PathBuf::from(v); Ok(()) } },
+    Setting { env: "CONFIRM_RISK", get: |c| Some(c.policies.confirm_risk.clone()), set: |c, v| { c.policies.confirm_risk = v.to_string(); Ok(()) } },
+    Setting { env: "COMMAND_ALLOWLIST", get: |c| Some(c.policies.command_allowlist.join(",")), set: |c, v| { c.policies.command_allowlist = list(v, ','); Ok(()) } },
+    Setting { env: "TOOL_TIMEOUT_SECS", get: |c| Some(c.policies.tool_timeout_secs.to_string()), set: |c, v| { c.policies.tool_timeout_secs = number("TOOL_TIMEOUT_SECS", v)?; Ok(()) } },
+    Setting { env: "LOG_LEVEL", get: |c| Some(c.log.level.clone()), set: |c, v| { c.log.level = v.to_string(); Ok(()) } },
+    Setting { env: "LOG_FORMAT", get: |c| Some(c.log.format.clone()), set: |c, v| { c.log.format = v.to_string(); Ok(()) } },
+    Setting { env: "OTEL_EXPORTER_OTLP_ENDPOINT", get: |c| c.telemetry.otlp_endpoint.clone(), set: |c, v| { c.telemetry.otlp_endpoint = Some(v.to_string()); Ok(()) } },
+    Setting { env: "OTEL_SERVICE_NAME", get: |c| c.telemetry.service_name.clone(), set: |c, v| { c.telemetry.service_name = Some(v.to_string()); Ok(()) } },
+    Setting { env: "TOOLS_GIT", get: |c| Some(c.policies.tools_git.to_string()), set: |c, v| { c.policies.tools_git = flag("TOOLS_GIT", v)?; Ok(()) } },
+    Setting { env: "AGENT_POLICIES", get: |c| Some(c.policies.prompt.join(";")), set: |c, v| { c.policies.prompt = list(v, ';'); Ok(()) } },
+    Setting { env: "SWARM_REGISTRY", get: |c| c.peers.registry.clone(), set: |c, v| { c.peers.registry = Some(v.to_string()); Ok(()) } },
+    Setting { env: "SWARM_DISCOVERY", get: |c| c.peers.discovery.clone(), set: |c, v| { c.peers.discovery = Some(v.to_string()); Ok(()) } },
+    Setting {
+        env: "PEER_GROUPS",
+        get: |c| Some(c.peers.groups.iter().map(|(name, members)| format!("{}={}", name, members.join(","))).collect::<Vec<_>>().join(";")),
+        set: |c, v| {
+            c.peers.groups = list(v, ';').iter()
+                .filter_map(|group| group.split_once('='))
+                .map(|(name, members)| (name.trim().to_string(), list(members, ',')))
+                .collect();
+            Ok(())
+        },
+    },
+];
+
+fn number<T: FromStr>(env: &str, value: &str) -> Result<T> {
+    value.trim().parse().map_err(|_| anyhow!("{}={} is not a valid number", env, value))
+}
+
+fn flag(env: &str, value: &str) -> Result<bool> {
+    match value.trim().to_ascii_lowercase().as_str() {
+        "true" | "1" => Ok(true),
+        "false" | "0" => Ok(false),
+        _ => Err(anyhow!("{}={} is not true or false", env, value)),
+    }
+}
+
+fn list(value: &str, separator: char) -> Vec<String> {
+    value.split(separator).map(|v| v.trim().to_string()).filter(|v| !v.is_empty()).collect()
+}
+
+fn display(path: &Path) -> String {
+    path.display().to_string()
+}
+
+impl Config {
+    /// The config in the TOML file at `path`, without environment overrides
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let content = fs::read_to_string(path).map_err(|e| anyhow!("Cannot read config {:?}: {}", path, e))?;
+        let mut config: Self = toml::from_str(&content).map_err(|e| anyhow!("Invalid config {:?}: {}", path, e))?;
+        config.path = Some(path.to_path_buf());
+        Ok(config)
+    }
+
+    /// `SWARM_CONFIG` (default `swarm.toml`, the defaults if that doesn't exist) with
+    /// environment overrides
+    pub fn from_env() -> Result<Self> {
+        let mut config = match std::env::var("SWARM_CONFIG").ok().filter(|p| !p.trim().is_empty()) {
+            Some(path) => Self::load(path)?,
+            None if Path::new(DEFAULT_CONFIG_FILE).exists() => Self::load(DEFAULT_CONFIG_FILE)?,
+            None => Self::default(),
+        };
+        config.apply_env()?;
+        Ok(config)
+    }
+
+    /// Override settings with the environment variables that are set
+    pub fn apply_env(&mut self) -> Result<()> {
+        for setting in SETTINGS {
+            if let Some(value) = std::env::var(setting.env).ok().filter(|v| !v.trim().is_empty()) {
+                (setting.set)(self, &value)?;
+                if !self.overridden.contains(&setting.env) {
+                    self.overridden.push(setting.env);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Set the environment variables of settings that differ from the defaults and aren't set
+    /// yet, for the modules that read their setting from the environment
+    pub fn export_env(&self) {
+        let defaults = Self::default();
+        for setting in SETTINGS {
+            let value = (setting.get)(self);
+            if value.is_some() && value != (setting.get)(&defaults) && std::env::var(setting.env).is_err() {
+                std::env::set_var(setting.env, value.unwrap_or_default());
+            }
+        }
+    }
+
+    /// Problems that would stop the agent or make a setting be ignored
+    pub fn validate(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        if self.agent.job_workers == 0 {
+            problems.push("agent.job_workers: 0 would never run background jobs".to_string());
+        }
+        if self.agent.parallel_tools == 0 {
+            problems.push("agent.parallel_tools: 0 (use 1 to run tool calls in turn)".to_string());
+        }
+        if !providers().contains(&self.llm.provider) {
+            problems.push(format!("llm.provider: unknown provider '{}'", self.llm.provider));
+        }
+        if let Some(fallback) = &self.llm.fallback {
+            if fallback.split(':').next().unwrap_or_default().trim().is_empty() {
+                problems.push(format!("llm.fallback: '{}' is not a provider:model spec", fallback));
+            }
+        }
+        if let Err(e) = self.llm.protocol.parse::<ActionProtocol>() {
+            problems.push(format!("llm.protocol: {}", e));
+        }
+        for (model, protocol) in &self.llm.protocols {
+            if let Err(e) = protocol.parse::<ActionProtocol>() {
+                problems.push(format!("llm.protocols.{}: {}", model, e));
+            }
+        }
+        let ports = [
+            ("ipc.port", self.ipc.port),
+            ("ipc.grpc_port", self.ipc.grpc_port),
+            ("ipc.discovery_port", Some(self.ipc.discovery_port)),
+            ("ipc.dashboard_port", self.ipc.dashboard_port),
+        ];
+        for (i, (name, port)) in ports.iter().enumerate() {
+            match port {
+                Some(0) => problems.push(format!("{}: port 0", name)),
+                Some(port) => {
+                    if let Some((other, _)) = ports[..i].iter().find(|(_, p)| *p == Some(*port)) {
+                        problems.push(format!("{}: port {} is also {}", name, port, other));
+                    }
+                }
+                None => {}
+            }
+        }
+        if let Err(e) = self.log.format.parse::<LogFormat>() {
+            problems.push(format!("log.format: {}", e));
+        }
+        if let Err(e) = EnvFilter::try_new(&self.log.level) {
+            problems.push(format!("log.level: {}", e));
+        }
+        if let Some(endpoint) = self.telemetry.otlp_endpoint.as_ref().filter(|e| !e.starts_with("http://") && !e.starts_with("https://")) {
+            problems.push(format!("telemetry.otlp_endpoint: '{}' is not an http(s) URL", endpoint));
+        }
+        if self.ipc.bind.trim().is_empty() {
+            problems.push("ipc.bind: empty".to_string());
+        }
+        if !self.paths.workspace.is_dir() {
+            problems.push(format!("paths.workspace: {:?} is not a directory", self.paths.workspace));
+        }
+        if let Err(e) = self.workspace.cleanup.parse::<Cleanup>() {
+            problems.push(format!("workspace.cleanup: {}", e));
+        }
+        if self.workspace.dir.exists() && !self.workspace.dir.is_dir() {
+            problems.push(format!("workspace.dir: {:?} is not a directory", self.workspace.dir));
+        }
+        if self.paths.tools_dir.exists() && !self.paths.tools_dir.is_dir() {
+            problems.push(format!("paths.tools_dir: {:?} is not a directory", self.paths.tools_dir));
+        }
+        if let Err(e) = SafetyPolicy::load(&self.policies.safety) {
+            problems.push(format!("policies.safety: {}", e));
+        }
+        let risk = self.policies.confirm_risk.trim().to_lowercase().replace(['_', '-'], "");
+        if !["lowrisk", "mediumrisk", "highrisk", "off", "none"].contains(&risk.as_str()) {
+            problems.push(format!("policies.confirm_risk: expected low_risk, medium_risk, high_risk or off, not '{}'", self.policies.confirm_risk));
+        }
+        if let Some(persona) = &self.agent.persona {
+            if let Err(e) = Persona::load(&self.paths.personas_dir, persona) {
+                problems.push(format!("agent.persona: {}", e));
+            }
+        }
+        if let Some(discovery) = self.peers.discovery.as_ref().filter(|d| !d.eq_ignore_ascii_case("udp")) {
+            problems.push(format!("peers.discovery: expected udp, not '{}'", discovery));
+        }
+        for (name, members) in &self.peers.groups {
+            if members.is_empty() {
+                problems.push(format!("peers.groups.{}: no members", name));
+            }
+        }
+        for (name, server) in &self.mcp.servers {
+            if name.is_empty() || !name.chars().all(|c| c.is_alphanumeric() || c == '_' || c == '-') {
+                problems.push(format!("mcp.servers.{}: names may only use letters, digits, _ and -", name));
+            }
+            if server.command.trim().is_empty() {
+                problems.push(format!("mcp.servers.{}: no command", name));
+            }
+            if server.timeout_secs == 0 {
+                problems.push(format!("mcp.servers.{}: timeout_secs 0", name));
+            }
+        }
+        problems
+    }
+
+    /// The effective config as TOML, noting where it came from
+    pub fn render(&self) -> Result<String> {
+        let source = match &self.path {
+            Some(path) => format!("# From {}", path.display()),
+            None => "# No config file, defaults".to_string(),
+        };
+        let overrides = if self.overridden.is_empty() {
+            String::new()
+        } else {
+            format!("\n# Overridden by the environment: {}", self.overridden.join(", "))
+        };
+        Ok(format!("{}{}\n\n{}", source, overrides, toml::to_string_pretty(self)?))
+    }
+}
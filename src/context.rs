@@ -0,0 +1,124 @@
+use crate::llm::{Message, Role};
+use crate::provider::ChatProvider;
+
+/// Per-message framing overhead (role markers etc.)
+const MESSAGE_OVERHEAD: usize = 4;
+
+/// Estimate the number of tokens `text` uses with `provider`'s tokenizer
+pub fn estimate_tokens(provider: &dyn ChatProvider, text: &str) -> usize {
+    provider.count_tokens(text)
+}
+
+/// Estimate the tokens a request with these messages and system prompt will use
+pub fn estimate_request(provider: &dyn ChatProvider, messages: &[Message], system_prompt: &str) -> usize {
+    estimate_tokens(provider, system_prompt)
+        + messages.iter()
+            .map(|m| estimate_tokens(provider, &m.content) + MESSAGE_OVERHEAD)
+            .sum::<usize>()
+}
+
+/// What to do with turns that no longer fit the context budget
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TrimStrategy {
+    /// Forget the oldest turns
+    DropOldest,
+    /// Condense the oldest turns into a summary via the LLM
+    Summarize,
+}
+
+/// Token budget applied to every request
+#[derive(Debug, Clone, PartialEq)]
+pub struct ContextBudget {
+    /// Size of the model's context window
+    pub max_tokens: usize,
+    /// Tokens kept free for the response
+    pub reserve_tokens: usize,
+    pub strategy: TrimStrategy,
+}
+
+impl ContextBudget {
+    /// The provider's context window, less room for the response
+    pub fn default_for(provider: &dyn ChatProvider) -> Self {
+        let max_tokens = provider.context_window();
+        Self {
+            max_tokens,
+            reserve_tokens: 4_096.min(max_tokens / 4),
+            strategy: TrimStrategy::DropOldest,
+        }
+    }
+
+    /// Read `CONTEXT_MAX_TOKENS`, `CONTEXT_RESERVE_TOKENS` and `CONTEXT_STRATEGY`
+    /// (`drop` or `summarize`), falling back to the provider defaults
+    pub fn from_env(provider: &dyn ChatProvider) -> Self {
+        let defaults = Self::default_for(provider);
+        let parse = |key: &str| std::env::var(key).ok().and_then(|v| v.parse().ok());
+        Self {
+            max_tokens: parse("CONTEXT_MAX_TOKENS").unwrap_or(defaults.max_tokens),
+            reserve_tokens: parse("CONTEXT_RESERVE_TOKENS").unwrap_or(defaults.reserve_tokens),
+            strategy: match std::env::var("CONTEXT_STRATEGY").unwrap_or_default().to_lowercase().as_str() {
+                "summarize" => TrimStrategy::Summarize,
+                _ => defaults.strategy,
+            },
+        }
+    }
+
+    /// Tokens available for system prompt + messages
+    pub fn available(&self) -> usize {
+        self.max_tokens.saturating_sub(self.reserve_tokens)
+    }
+
+    /// Index of the first message to keep so the request fits the budget.
+    /// The kept slice always starts with a user turn (providers require it) and
+    /// always includes the latest message, even if that alone is over budget.
+    pub fn trim_point(&self, provider: &dyn ChatProvider, messages: &[Message], system_prompt: &str) -> usize {
+        let budget = self.available().saturating_sub(estimate_tokens(provider, system_prompt));
+        keep_within(provider, messages, budget)
+    }
+}
+
+/// Index of the first message of the longest suffix of `messages` that fits in `budget`
+/// tokens, moved forward to a user turn. The latest message is always kept.
+pub fn keep_within(provider: &dyn ChatProvider, messages: &[Message], budget: usize) -> usize {
+    let mut used = 0;
+    let mut start = messages.len();
+    for (i, message) in messages.iter().enumerate().rev() {
+        let cost = estimate_tokens(provider, &message.content) + MESSAGE_OVERHEAD;
+        if used + cost > budget && start < messages.len() {
+            break;
+        }
+        used += cost;
+        start = i;
+    }
+    // Advance to the first user turn in the kept range
+    while start < messages.len().saturating_sub(1) && !matches!(messages[start].role, Role::User) {
+        start += 1;
+    }
+    start
+}
+
+/// Rolling summarization: once the history grows past `threshold_tokens`, everything
+/// but the most recent `keep_recent_tokens` is folded into a running summary
+#[derive(Debug, Clone, PartialEq)]
+pub struct SummaryPolicy {
+    pub threshold_tokens: usize,
+    pub keep_recent_tokens: usize,
+}
+
+impl SummaryPolicy {
+    /// Enabled by `SUMMARY_THRESHOLD_TOKENS`; `SUMMARY_KEEP_TOKENS` defaults to half the threshold
+    pub fn from_env() -> Option<Self> {
+        let threshold_tokens: usize = std::env::var("SUMMARY_THRESHOLD_TOKENS").ok()?.parse().ok()?;
+        let keep_recent_tokens = std::env::var("SUMMARY_KEEP_TOKENS").ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(threshold_tokens / 2);
+        Some(Self { threshold_tokens, keep_recent_tokens })
+    }
+
+    /// Index up to which `messages` should be summarized, or 0 if under the threshold
+    pub fn summarize_point(&self, provider: &dyn ChatProvider, messages: &[Message]) -> usize {
+        if estimate_request(provider, messages, "") <= self.threshold_tokens {
+            return 0;
+        }
+        keep_within(provider, messages, self.keep_recent_tokens)
+    }
+}
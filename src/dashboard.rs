@@ -0,0 +1,263 @@
+use anyhow::Result;
+use axum::{
+    extract::{Path as UrlPath, Query, Request, State},
+    http::{header, StatusCode},
+    middleware::{self, Next},
+    response::{Html, IntoResponse, Response},
+    routing::{get, post},
+    Json,
+    Router,
+};
+use rand_core::{OsRng, RngCore};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::UNIX_EPOCH;
+use crate::audit::{AuditEntry, AuditLog};
+use crate::halt::HaltSwitch;
+use crate::llm::Message;
+use crate::message::ToolSafetyLevel;
+use crate::registry::{PeerInfo, PeerRegistry};
+use crate::safety::SafetyPolicy;
+use crate::tools::{approve_pending_tool, find_tool_file, list_tool_names, reject_pending_tool, tool_file_risk, PendingTool, ToolManager, AUDIT_SHOW_LIMIT};
+use tracing::warn;
+
+const INDEX: &str = include_str!("dashboard.html");
+
+/// Header the page sends the run's token in
+pub const TOKEN_HEADER: &str = "x-dashboard-token";
+
+/// Every turn of a conversation, shared with the dashboard. Unlike the agent's history it is
+/// never trimmed or summarized.
+#[derive(Clone, Default)]
+pub struct Transcript(Arc<Mutex<Vec<Message>>>);
+
+impl Transcript {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&self, message: Message) {
+        self.0.lock().unwrap().push(message);
+    }
+
+    pub fn messages(&self) -> Vec<Message> {
+        self.0.lock().unwrap().clone()
+    }
+}
+
+/// An installed tool and how risky it is
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ToolView {
+    pub name: String,
+    pub safety_level: ToolSafetyLevel,
+}
+
+/// A tool waiting for the operator's approval
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PendingView {
+    pub name: String,
+    pub safety_level: ToolSafetyLevel,
+    /// Where it came from, as `PendingTool::origin` puts it
+    pub origin: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    pub code: String,
+    /// Unix time (seconds) it arrived
+    pub received_at: u64,
+}
+
+impl From<&PendingTool> for PendingView {
+    fn from(tool: &PendingTool) -> Self {
+        Self {
+            name: tool.name.clone(),
+            safety_level: tool.safety_level.clone(),
+            origin: tool.origin(),
+            description: tool.description.clone(),
+            code: tool.code.clone(),
+            received_at: tool.received_at.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0),
+        }
+    }
+}
+
+/// What an approve or reject button did
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Decision {
+    pub ok: bool,
+    pub message: String,
+}
+
+#[derive(Deserialize)]
+struct PageQuery {
+    #[serde(default)]
+    token: String,
+}
+
+#[derive(Deserialize)]
+struct AuditQuery {
+    #[serde(default)]
+    filter: String,
+    limit: Option<usize>,
+}
+
+/// What the dashboard shows, shared with the agent it watches
+#[derive(Clone)]
+pub struct DashboardState {
+    transcript: Transcript,
+    tools_dir: PathBuf,
+    safety: Arc<Mutex<SafetyPolicy>>,
+    pending: Arc<Mutex<Vec<PendingTool>>>,
+    peers: PeerRegistry,
+    audit: AuditLog,
+    halt: HaltSwitch,
+    /// Random per run: the page gets it from the printed URL and sends it with every API call,
+    /// so other pages the operator visits can't approve tools through the browser
+    token: String,
+    /// The port served on, once bound, for the Host and Origin checks
+    port: Option<u16>,
+}
+
+impl DashboardState {
+    /// Watch `tools` and the conversation copied to `transcript` (see `Agent::set_transcript`)
+    pub fn new(tools: &ToolManager, transcript: Transcript) -> Self {
+        Self {
+            transcript,
+            tools_dir: tools.tools_dir().to_path_buf(),
+            safety: tools.shared_safety_policy().clone(),
+            pending: tools.pending_tools.clone(),
+            peers: tools.peers().clone(),
+            audit: tools.audit_log().clone(),
+            halt: tools.halt_switch().clone(),
+            token: new_token(),
+            port: None,
+        }
+    }
+
+    pub fn token(&self) -> &str {
+        &self.token
+    }
+
+    /// Whether `host` (a Host header, or an Origin without its scheme) names this dashboard
+    fn is_own_host(&self, host: &str) -> bool {
+        let (name, port) = match host.rsplit_once(':') {
+            Some((name, port)) if !port.contains(']') => (name, port.parse::<u16>().ok()),
+            _ => (host, None),
+        };
+        ["127.0.0.1", "localhost", "[::1]"].contains(&name) && (self.port.is_none() || port == self.port)
+    }
+}
+
+fn new_token() -> String {
+    let mut bytes = [0u8; 16];
+    OsRng.fill_bytes(&mut bytes);
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Refuse requests from other origins (including DNS rebinding, by Host), the page without the
+/// run's token in its query, and API calls without it in `TOKEN_HEADER`
+async fn guard(State(state): State<DashboardState>, request: Request, next: Next) -> Response {
+    let headers = request.headers();
+    let text = |name: header::HeaderName| headers.get(name).and_then(|v| v.to_str().ok()).map(str::to_string);
+    if !text(header::HOST).is_some_and(|host| state.is_own_host(&host)) {
+        return (StatusCode::FORBIDDEN, "Unexpected Host").into_response();
+    }
+    if let Some(origin) = text(header::ORIGIN) {
+        if !origin.strip_prefix("http://").is_some_and(|host| state.is_own_host(host)) {
+            return (StatusCode::FORBIDDEN, "Cross-origin requests are refused").into_response();
+        }
+    }
+    let token = if request.uri().path().starts_with("/api/") {
+        text(header::HeaderName::from_static(TOKEN_HEADER))
+    } else {
+        Query::<PageQuery>::try_from_uri(request.uri()).ok().map(|query| query.0.token)
+    };
+    if token.as_deref() != Some(state.token.as_str()) {
+        return (StatusCode::UNAUTHORIZED, "Open the dashboard at the URL the agent printed").into_response();
+    }
+    next.run(request).await
+}
+
+/// The dashboard page and the JSON APIs behind it
+pub fn router(state: DashboardState) -> Router {
+    Router::new()
+        .route("/", get(handle_index))
+        .route("/api/history", get(handle_history))
+        .route("/api/tools", get(handle_tools))
+        .route("/api/pending", get(handle_pending))
+        .route("/api/pending/:name/approve", post(handle_approve))
+        .route("/api/pending/:name/reject", post(handle_reject))
+        .route("/api/peers", get(handle_peers))
+        .route("/api/audit", get(handle_audit))
+        .route_layer(middleware::from_fn_with_state(state.clone(), guard))
+        .with_state(state)
+}
+
+/// Serve the dashboard on 127.0.0.1:`port` in the background, returning its URL with the run's
+/// token. It can approve tools, so it is never bound to other interfaces.
+pub async fn start(mut state: DashboardState, port: u16) -> Result<String> {
+    let listener = tokio::net::TcpListener::bind(("127.0.0.1", port)).await?;
+    let address = listener.local_addr()?;
+    state.port = Some(address.port());
+    let url = format!("http://{}/?token={}", address, state.token);
+    tokio::spawn(async move {
+        if let Err(e) = axum::serve(listener, router(state)).await {
+            warn!("Dashboard stopped: {}", e);
+        }
+    });
+    Ok(url)
+}
+
+async fn handle_index(State(state): State<DashboardState>) -> Html<String> {
+    Html(INDEX.replace("{{token}}", &state.token))
+}
+
+async fn handle_history(State(state): State<DashboardState>) -> Json<Vec<Message>> {
+    Json(state.transcript.messages())
+}
+
+async fn handle_tools(State(state): State<DashboardState>) -> Json<Vec<ToolView>> {
+    let mut names = list_tool_names(&state.tools_dir);
+    names.sort();
+    names.dedup();
+    Json(names.into_iter()
+        .filter_map(|name| {
+            let path = find_tool_file(&state.tools_dir, &name)?;
+            let code = std::fs::read_to_string(&path).ok()?;
+            let safety_level = tool_file_risk(&path, &code, &state.safety.lock().unwrap());
+            Some(ToolView { safety_level, name })
+        })
+        .collect())
+}
+
+async fn handle_pending(State(state): State<DashboardState>) -> Json<Vec<PendingView>> {
+    Json(state.pending.lock().unwrap().iter().map(PendingView::from).collect())
+}
+
+async fn handle_approve(State(state): State<DashboardState>, UrlPath(name): UrlPath<String>) -> Response {
+    let safety = state.safety.lock().unwrap().clone();
+    let approved = state.halt.check()
+        .and_then(|_| approve_pending_tool(&state.pending, &state.tools_dir, &safety, &state.audit, &name));
+    decision(approved)
+}
+
+async fn handle_reject(State(state): State<DashboardState>, UrlPath(name): UrlPath<String>) -> Response {
+    decision(reject_pending_tool(&state.pending, &state.tools_dir, &state.audit, &name))
+}
+
+fn decision(result: Result<String>) -> Response {
+    match result {
+        Ok(message) => Json(Decision { ok: true, message }).into_response(),
+        Err(e) => (StatusCode::CONFLICT, Json(Decision { ok: false, message: e.to_string() })).into_response(),
+    }
+}
+
+async fn handle_peers(State(state): State<DashboardState>) -> Json<Vec<PeerInfo>> {
+    Json(state.peers.list())
+}
+
+async fn handle_audit(State(state): State<DashboardState>, Query(query): Query<AuditQuery>) -> Response {
+    match state.audit.search(&query.filter, query.limit.unwrap_or(AUDIT_SHOW_LIMIT)) {
+        Ok(entries) => Json::<Vec<AuditEntry>>(entries).into_response(),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+}
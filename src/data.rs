@@ -0,0 +1,354 @@
+use anyhow::{anyhow, Result};
+use rusqlite::types::Value;
+use rusqlite::{params_from_iter, Connection};
+use serde_json::Value as Json;
+use std::fs;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use crate::database::confine;
+use rhai::Engine;
+use crate::tools::NativeContext;
+
+/// Rows of a result `query_data` shows unless told otherwise
+const MAX_ROWS: usize = 50;
+
+/// How many of a text column's most common values `describe_data` lists
+const TOP_VALUES: usize = 3;
+
+/// Columns, type and row count of a loaded table
+#[derive(Debug, Clone, PartialEq)]
+pub struct TableInfo {
+    pub name: String,
+    pub columns: Vec<(String, String)>,
+    pub rows: usize,
+}
+
+impl TableInfo {
+    pub fn render(&self) -> String {
+        let columns: Vec<String> = self.columns.iter().map(|(name, kind)| format!("{} {}", name, kind)).collect();
+        format!("Loaded {} rows into table '{}' ({})", self.rows, self.name, columns.join(", "))
+    }
+}
+
+/// The result of a query: column names, and every row's values
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rows {
+    pub columns: Vec<String>,
+    pub rows: Vec<Vec<Value>>,
+}
+
+impl Rows {
+    /// A `|`-separated table of at most `max` rows, saying how many there were in all
+    pub fn render(&self, max: usize) -> String {
+        let mut lines = vec![self.columns.join(" | ")];
+        lines.extend(self.rows.iter().take(max).map(|row| row.iter().map(show).collect::<Vec<_>>().join(" | ")));
+        lines.push(match self.rows.len() {
+            n if n > max => format!("({} of {} rows shown; aggregate or add LIMIT to see the rest)", max, n),
+            1 => "(1 row)".to_string(),
+            n => format!("({} rows)", n),
+        });
+        lines.join("\n")
+    }
+}
+
+fn show(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::Integer(i) => i.to_string(),
+        Value::Real(f) if f.fract() == 0.0 && f.abs() < 1e15 => format!("{:.0}", f),
+        Value::Real(f) => format!("{:.4}", f).trim_end_matches('0').to_string(),
+        Value::Text(t) => t.clone(),
+        Value::Blob(b) => format!("<{} bytes>", b.len()),
+    }
+}
+
+/// A table handle for the file at `path`: its stem, lowercased, with anything but letters,
+/// digits and `_` replaced by `_`
+pub fn table_name(path: &str) -> String {
+    let stem = Path::new(path).file_stem().and_then(|s| s.to_str()).unwrap_or("data");
+    let name: String = stem.to_lowercase().chars().map(|c| if c.is_ascii_alphanumeric() { c } else { '_' }).collect();
+    match name.chars().next() {
+        None => "data".to_string(),
+        Some(c) if c.is_ascii_digit() => format!("t_{}", name),
+        Some(_) => name,
+    }
+}
+
+fn quote(identifier: &str) -> String {
+    format!("\"{}\"", identifier.replace('"', "\"\""))
+}
+
+/// The SQLite type every value in `values` fits: INTEGER, REAL or else TEXT
+fn column_type<'a>(values: impl Iterator<Item = &'a str>) -> &'static str {
+    let mut kind = "INTEGER";
+    for value in values {
+        if kind == "INTEGER" && value.parse::<i64>().is_err() {
+            kind = "REAL";
+        }
+        if kind == "REAL" && value.parse::<f64>().is_err() {
+            return "TEXT";
+        }
+    }
+    kind
+}
+
+fn typed(value: Option<&str>, kind: &str) -> Value {
+    match (value, kind) {
+        (None, _) => Value::Null,
+        (Some(v), "INTEGER") => v.parse().map(Value::Integer).unwrap_or(Value::Null),
+        (Some(v), "REAL") => v.parse().map(Value::Real).unwrap_or(Value::Null),
+        (Some(v), _) => Value::Text(v.to_string()),
+    }
+}
+
+/// Unique, non-empty column names: blanks become `column_n`, repeats get `_2`, `_3`...
+fn column_names(header: Vec<String>) -> Vec<String> {
+    let mut names: Vec<String> = Vec::new();
+    for (i, name) in header.into_iter().enumerate() {
+        let base = match name.trim() {
+            "" => format!("column_{}", i + 1),
+            name => name.to_string(),
+        };
+        let mut name = base.clone();
+        let mut n = 1;
+        while names.iter().any(|existing| existing.eq_ignore_ascii_case(&name)) {
+            n += 1;
+            name = format!("{}_{}", base, n);
+        }
+        names.push(name);
+    }
+    names
+}
+
+/// The records of a JSON document: an array of objects, an object holding one such array
+/// (e.g. `{"data": [...]}`), or JSON Lines
+fn json_records(text: &str) -> Result<Vec<serde_json::Map<String, Json>>> {
+    let values = match serde_json::from_str::<Json>(text) {
+        Ok(Json::Array(items)) => items,
+        Ok(Json::Object(object)) => match object.values().find(|v| v.is_array()) {
+            Some(Json::Array(items)) => items.clone(),
+            _ => vec![Json::Object(object)],
+        },
+        Ok(_) => return Err(anyhow!("expected an array of objects")),
+        Err(e) => text.lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(serde_json::from_str)
+            .collect::<Result<Vec<Json>, _>>()
+            .map_err(|_| anyhow!("not JSON or JSON Lines: {}", e))?,
+    };
+    values.into_iter()
+        .map(|value| match value {
+            Json::Object(object) => Ok(object),
+            other => Err(anyhow!("expected objects, found {}", other)),
+        })
+        .collect()
+}
+
+/// In-memory tables for analysing datasets: `load_csv` and `load_json` turn files into
+/// SQLite tables, `query_data` runs read-only SQL over them and `describe_data` summarises one
+#[derive(Clone)]
+pub struct DataTables {
+    conn: Arc<Mutex<Connection>>,
+    max_rows: usize,
+}
+
+impl DataTables {
+    pub fn new() -> Result<Self> {
+        let conn = Connection::open_in_memory()?;
+        // ATTACH counts as read-only, so the readonly check in `query` doesn't stop it
+        confine(&conn);
+        Ok(Self { conn: Arc::new(Mutex::new(conn)), max_rows: MAX_ROWS })
+    }
+
+    /// Show at most `max` rows of a query result
+    pub fn with_max_rows(mut self, max: usize) -> Self {
+        self.max_rows = max.max(1);
+        self
+    }
+
+    /// `DATA_MAX_ROWS` (default 50) rows of a query result are shown
+    pub fn from_env() -> Result<Self> {
+        let tables = Self::new()?;
+        Ok(match std::env::var("DATA_MAX_ROWS").ok().and_then(|v| v.trim().parse().ok()) {
+            Some(max) => tables.with_max_rows(max),
+            None => tables,
+        })
+    }
+
+    pub fn max_rows(&self) -> usize {
+        self.max_rows
+    }
+
+    /// Load CSV `text` (its first row the header) as table `name`, replacing any earlier one.
+    /// The delimiter is whichever of `,`, `;` and tab the header uses most.
+    pub fn load_csv(&self, name: &str, text: &str) -> Result<TableInfo> {
+        let header_line = text.lines().next().unwrap_or_default();
+        let delimiter = [b',', b';', b'\t'].into_iter()
+            .max_by_key(|d| header_line.bytes().filter(|b| b == d).count())
+            .unwrap_or(b',');
+        let mut reader = csv::ReaderBuilder::new().delimiter(delimiter).flexible(true).from_reader(text.as_bytes());
+        let columns = column_names(reader.headers()?.iter().map(String::from).collect());
+        let mut rows = Vec::new();
+        for record in reader.records() {
+            let record = record?;
+            rows.push((0..columns.len())
+                .map(|i| record.get(i).map(str::trim).filter(|v| !v.is_empty()).map(String::from))
+                .collect());
+        }
+        self.store(name, columns, rows)
+    }
+
+    /// Load JSON `text` (see `json_records`) as table `name`, one column per key: the first
+    /// record's keys in alphabetical order, then keys that only later records have. Nested
+    /// arrays and objects are kept as JSON text.
+    pub fn load_json(&self, name: &str, text: &str) -> Result<TableInfo> {
+        let records = json_records(text)?;
+        let mut keys: Vec<String> = Vec::new();
+        for record in &records {
+            for key in record.keys() {
+                if !keys.contains(key) {
+                    keys.push(key.clone());
+                }
+            }
+        }
+        let rows = records.iter()
+            .map(|record| keys.iter().map(|key| match record.get(key) {
+                None | Some(Json::Null) => None,
+                Some(Json::String(s)) => Some(s.clone()),
+                Some(other) => Some(other.to_string()),
+            }).collect())
+            .collect();
+        self.store(name, column_names(keys), rows)
+    }
+
+    fn store(&self, name: &str, columns: Vec<String>, rows: Vec<Vec<Option<String>>>) -> Result<TableInfo> {
+        if columns.is_empty() {
+            return Err(anyhow!("no columns found"));
+        }
+        let kinds: Vec<&str> = (0..columns.len())
+            .map(|i| column_type(rows.iter().filter_map(|row| row[i].as_deref())))
+            .collect();
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        tx.execute_batch(&format!("DROP TABLE IF EXISTS {}", quote(name)))?;
+        let definitions: Vec<String> = columns.iter().zip(&kinds).map(|(c, k)| format!("{} {}", quote(c), k)).collect();
+        tx.execute_batch(&format!("CREATE TABLE {} ({})", quote(name), definitions.join(", ")))?;
+        {
+            let placeholders = vec!["?"; columns.len()].join(", ");
+            let mut insert = tx.prepare(&format!("INSERT INTO {} VALUES ({})", quote(name), placeholders))?;
+            for row in &rows {
+                insert.execute(params_from_iter(row.iter().zip(&kinds).map(|(v, k)| typed(v.as_deref(), k))))?;
+            }
+        }
+        tx.commit()?;
+        Ok(TableInfo {
+            name: name.to_string(),
+            columns: columns.into_iter().zip(kinds.into_iter().map(String::from)).collect(),
+            rows: rows.len(),
+        })
+    }
+
+    /// The loaded tables, by name
+    pub fn tables(&self) -> Result<Vec<String>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare("SELECT name FROM sqlite_master WHERE type = 'table' ORDER BY name")?;
+        let names = stmt.query_map([], |row| row.get(0))?.collect::<Result<_, _>>()?;
+        Ok(names)
+    }
+
+    /// Run a read-only SQL query over the loaded tables
+    pub fn query(&self, sql: &str) -> Result<Rows> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(sql)?;
+        if !stmt.readonly() {
+            return Err(anyhow!("Only read-only queries (SELECT ...) can be run on loaded data"));
+        }
+        let columns: Vec<String> = stmt.column_names().iter().map(|c| c.to_string()).collect();
+        let mut result = stmt.query([])?;
+        let mut rows = Vec::new();
+        while let Some(row) = result.next()? {
+            rows.push((0..columns.len()).map(|i| row.get_ref(i).map(Value::from)).collect::<Result<_, _>>()?);
+        }
+        Ok(Rows { columns, rows })
+    }
+
+    /// Row count, then for each column its type, how many values are present, missing and
+    /// distinct, min/max/mean of numbers and the most common texts; then the first rows
+    pub fn describe(&self, name: &str) -> Result<String> {
+        let tables = self.tables()?;
+        if !tables.iter().any(|t| t == name) {
+            return Err(anyhow!("No table '{}'; loaded: {}", name, if tables.is_empty() { "none".to_string() } else { tables.join(", ") }));
+        }
+        let table = quote(name);
+        let columns: Vec<(String, String)> = {
+            let conn = self.conn.lock().unwrap();
+            let mut stmt = conn.prepare(&format!("PRAGMA table_info({})", table))?;
+            let columns = stmt.query_map([], |row| Ok((row.get(1)?, row.get(2)?)))?.collect::<Result<_, _>>()?;
+            columns
+        };
+        let count = |rows: &Rows| match rows.rows.first().and_then(|r| r.first()) {
+            Some(Value::Integer(n)) => *n,
+            _ => 0,
+        };
+        let total = count(&self.query(&format!("SELECT COUNT(*) FROM {}", table))?);
+        let mut lines = vec![format!("Table '{}': {} rows, {} columns", name, total, columns.len())];
+        for (column, kind) in &columns {
+            let c = quote(column);
+            let stats = self.query(&format!("SELECT COUNT({c}), COUNT(DISTINCT {c}), MIN({c}), MAX({c}), AVG({c}) FROM {table}"))?;
+            let row = &stats.rows[0];
+            let present = match row[0] { Value::Integer(n) => n, _ => 0 };
+            let mut line = format!("- {} {}: {} values, {} missing, {} distinct", column, kind, present, total - present, show(&row[1]));
+            if kind != "TEXT" && present > 0 {
+                line.push_str(&format!("; min {}, max {}, mean {}", show(&row[2]), show(&row[3]), show(&row[4])));
+            } else if present > 0 {
+                let top = self.query(&format!(
+                    "SELECT {c}, COUNT(*) AS n FROM {table} WHERE {c} IS NOT NULL GROUP BY {c} ORDER BY n DESC, {c} LIMIT {TOP_VALUES}"
+                ))?;
+                let top: Vec<String> = top.rows.iter().map(|r| format!("{} ({})", show(&r[0]), show(&r[1]))).collect();
+                line.push_str(&format!("; top: {}", top.join(", ")));
+            }
+            lines.push(line);
+        }
+        let sample = self.query(&format!("SELECT * FROM {} LIMIT 5", table))?;
+        lines.push(format!("First rows:\n{}", sample.render(5)));
+        Ok(lines.join("\n"))
+    }
+}
+
+/// `load_csv`, `load_json`, `query_data` and `describe_data`
+pub(crate) fn register_data_natives(engine: &mut Engine, ctx: &NativeContext) -> Result<()> {
+    let NativeContext { jail, .. } = ctx;
+    // Datasets: CSV and JSON files loaded into in-memory tables for SQL analysis.
+    // load_csv(path) names the table after the file; load_csv(path, name) picks the name
+    let data = DataTables::from_env()?;
+    let load = {
+        let data = data.clone();
+        let jail = jail.clone();
+        move |path: &str, name: &str, json: bool| -> String {
+            let text = match jail.resolve(path).and_then(|p| fs::read_to_string(p).map_err(|e| anyhow!("Error reading {}: {}", path, e))) {
+                Ok(text) => text,
+                Err(e) => return format!("Error: {}", e),
+            };
+            let name = if name.trim().is_empty() { table_name(path) } else { table_name(name.trim()) };
+            let loaded = if json { data.load_json(&name, &text) } else { data.load_csv(&name, &text) };
+            loaded.map(|info| info.render()).unwrap_or_else(|e| format!("Error loading {}: {}", path, e))
+        }
+    };
+    let load_clone = load.clone();
+    engine.register_fn("load_csv", move |path: &str| -> String { load_clone(path, "", false) });
+    let load_clone = load.clone();
+    engine.register_fn("load_csv", move |path: &str, name: &str| -> String { load_clone(path, name, false) });
+    let load_clone = load.clone();
+    engine.register_fn("load_json", move |path: &str| -> String { load_clone(path, "", true) });
+    engine.register_fn("load_json", move |path: &str, name: &str| -> String { load(path, name, true) });
+
+    let data_clone = data.clone();
+    engine.register_fn("query_data", move |sql: &str| -> String {
+        data_clone.query(sql).map(|rows| rows.render(data_clone.max_rows()))
+            .unwrap_or_else(|e| format!("Error running query: {}", e))
+    });
+    engine.register_fn("describe_data", move |name: &str| -> String {
+        data.describe(name.trim()).unwrap_or_else(|e| format!("Error: {}", e))
+    });
+    Ok(())
+}
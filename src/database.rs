@@ -0,0 +1,135 @@
+use anyhow::{anyhow, Result};
+use rhai::{Array, Dynamic, Map};
+use rusqlite::limits::Limit;
+use rusqlite::types::{Value, ValueRef};
+use rusqlite::{params_from_iter, Connection};
+use std::fs;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use rhai::Engine;
+
+/// Agent-local SQLite database exposed to tools via `db_query` / `db_execute`
+#[derive(Clone)]
+pub struct AgentDb {
+    conn: Arc<Mutex<Connection>>,
+}
+
+impl AgentDb {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        if let Some(parent) = path.parent() {
+            if !parent.as_os_str().is_empty() {
+                fs::create_dir_all(parent)?;
+            }
+        }
+        let conn = Connection::open(path)?;
+        // Several agents in one process may share the file
+        conn.busy_timeout(std::time::Duration::from_secs(5))?;
+        confine(&conn);
+        Ok(Self {
+            conn: Arc::new(Mutex::new(conn)),
+        })
+    }
+
+    /// Open the database at `AGENT_DB`, defaulting to `state/agent.db`
+    pub fn from_env() -> Result<Self> {
+        let path = std::env::var("AGENT_DB").unwrap_or_else(|_| "state/agent.db".to_string());
+        Self::open(path)
+    }
+
+    /// Run a SELECT and return each row as a column -> value map
+    pub fn query(&self, sql: &str, params: &[Dynamic]) -> Result<Array> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(sql)?;
+        if !stmt.readonly() {
+            return Err(anyhow!("db_query only reads; use db_execute to change the database"));
+        }
+        let columns: Vec<String> = stmt.column_names().iter().map(|c| c.to_string()).collect();
+        let mut rows = stmt.query(params_from_iter(params.iter().map(to_sql_value)))?;
+
+        let mut result = Array::new();
+        while let Some(row) = rows.next()? {
+            let mut map = Map::new();
+            for (i, column) in columns.iter().enumerate() {
+                map.insert(column.as_str().into(), from_sql_value(row.get_ref(i)?));
+            }
+            result.push(map.into());
+        }
+        Ok(result)
+    }
+
+    /// Run a statement and return the number of affected rows
+    pub fn execute(&self, sql: &str, params: &[Dynamic]) -> Result<usize> {
+        let conn = self.conn.lock().unwrap();
+        if params.is_empty() {
+            // Allow several `;`-separated statements (e.g. schema setup) when there are no params
+            let before = conn.total_changes();
+            conn.execute_batch(sql)?;
+            return Ok((conn.total_changes() - before) as usize);
+        }
+        Ok(conn.execute(sql, params_from_iter(params.iter().map(to_sql_value)))?)
+    }
+}
+
+/// Keep `conn` to its own file: no other database can be attached, so SQL can't create, write
+/// or read SQLite files elsewhere on the host
+pub(crate) fn confine(conn: &Connection) {
+    conn.set_limit(Limit::SQLITE_LIMIT_ATTACHED, 0);
+}
+
+fn to_sql_value(value: &Dynamic) -> Value {
+    if value.is_unit() {
+        Value::Null
+    } else if let Some(i) = value.clone().try_cast::<i64>() {
+        Value::Integer(i)
+    } else if let Some(f) = value.clone().try_cast::<f64>() {
+        Value::Real(f)
+    } else if let Some(b) = value.clone().try_cast::<bool>() {
+        Value::Integer(b as i64)
+    } else if let Some(blob) = value.clone().try_cast::<rhai::Blob>() {
+        Value::Blob(blob)
+    } else {
+        Value::Text(value.to_string())
+    }
+}
+
+fn from_sql_value(value: ValueRef) -> Dynamic {
+    match value {
+        ValueRef::Null => Dynamic::UNIT,
+        ValueRef::Integer(i) => i.into(),
+        ValueRef::Real(f) => f.into(),
+        ValueRef::Text(t) => String::from_utf8_lossy(t).to_string().into(),
+        ValueRef::Blob(b) => Dynamic::from_blob(b.to_vec()),
+    }
+}
+
+/// `db_query` and `db_execute` on the agent database
+pub(crate) fn register_db_natives(engine: &mut Engine) -> Result<()> {
+    // Agent-local SQLite database
+    let db = AgentDb::from_env()?;
+
+    let db_clone = db.clone();
+    engine.register_fn("db_query", move |sql: &str| -> Dynamic {
+        db_clone.query(sql, &[]).map(Dynamic::from_array)
+            .unwrap_or_else(|e| format!("Error running query: {}", e).into())
+    });
+
+    let db_clone = db.clone();
+    engine.register_fn("db_query", move |sql: &str, params: rhai::Array| -> Dynamic {
+        db_clone.query(sql, &params).map(Dynamic::from_array)
+            .unwrap_or_else(|e| format!("Error running query: {}", e).into())
+    });
+
+    let db_clone = db.clone();
+    engine.register_fn("db_execute", move |sql: &str| -> Dynamic {
+        db_clone.execute(sql, &[]).map(|n| Dynamic::from(n as i64))
+            .unwrap_or_else(|e| format!("Error executing statement: {}", e).into())
+    });
+
+    let db_clone = db.clone();
+    engine.register_fn("db_execute", move |sql: &str, params: rhai::Array| -> Dynamic {
+        db_clone.execute(sql, &params).map(|n| Dynamic::from(n as i64))
+            .unwrap_or_else(|e| format!("Error executing statement: {}", e).into())
+    });
+    Ok(())
+}
@@ -0,0 +1,258 @@
+use anyhow::{anyhow, Result};
+use std::fs;
+use tracing::info;
+use crate::egress;
+use crate::http_cache::fetch_bytes;
+use crate::pdf::pdf_pages;
+use rhai::Engine;
+use crate::tools::NativeContext;
+
+/// Most characters `read_pdf` and friends return; the rest is for `ingest_document`
+pub const READ_MAX_CHARS: usize = 20_000;
+
+/// Formats whose text the agent can read besides plain text and HTML. Word and EPUB
+/// support are the `docx` and `epub` features.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DocumentKind {
+    Pdf,
+    #[cfg(feature = "docx")]
+    Docx,
+    #[cfg(feature = "epub")]
+    Epub,
+}
+
+impl DocumentKind {
+    /// The kind of a document named `name` (a path or URL), served as `content_type` or
+    /// starting with `bytes`; None for anything else
+    pub fn detect(name: &str, content_type: Option<&str>, bytes: &[u8]) -> Option<Self> {
+        let name = name.split(['?', '#']).next().unwrap_or_default().to_lowercase();
+        let content_type = content_type.unwrap_or_default().to_lowercase();
+        if name.ends_with(".pdf") || content_type.contains("application/pdf") || bytes.starts_with(b"%PDF-") {
+            return Some(DocumentKind::Pdf);
+        }
+        #[cfg(feature = "docx")]
+        if name.ends_with(".docx") || content_type.contains("wordprocessingml") {
+            return Some(DocumentKind::Docx);
+        }
+        #[cfg(feature = "epub")]
+        if name.ends_with(".epub") || content_type.contains("epub") {
+            return Some(DocumentKind::Epub);
+        }
+        None
+    }
+
+    /// The text of `bytes`, with a `--- Page n ---` (PDF) or `--- Section n ---` (EPUB)
+    /// line before each part
+    pub fn extract(self, bytes: &[u8]) -> Result<String> {
+        match self {
+            DocumentKind::Pdf => {
+                let pages = pdf_pages(bytes).map_err(|e| anyhow!("Cannot read PDF: {}", e))?;
+                Ok(with_markers("Page", &pages))
+            }
+            #[cfg(feature = "docx")]
+            DocumentKind::Docx => docx_text(bytes).map_err(|e| anyhow!("Cannot read DOCX: {}", e)),
+            #[cfg(feature = "epub")]
+            DocumentKind::Epub => {
+                let sections = epub_sections(bytes).map_err(|e| anyhow!("Cannot read EPUB: {}", e))?;
+                Ok(with_markers("Section", &sections))
+            }
+        }
+    }
+}
+
+fn with_markers(label: &str, parts: &[String]) -> String {
+    parts.iter().enumerate()
+        .map(|(i, text)| format!("--- {} {} ---\n{}", label, i + 1, text))
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
+
+/// `text` cut to `READ_MAX_CHARS`, saying so when it was
+pub fn truncate_for_reading(text: &str) -> String {
+    match text.char_indices().nth(READ_MAX_CHARS) {
+        Some((at, _)) => format!("{}\n[... truncated at {} characters; ingest_document the source to search all of it]", &text[..at], READ_MAX_CHARS),
+        None => text.to_string(),
+    }
+}
+
+/// One file of a zip archive, found through the central directory
+#[cfg(any(feature = "docx", feature = "epub"))]
+fn zip_entry(archive: &[u8], wanted: &str) -> Result<Vec<u8>> {
+    let u16_at = |at: usize| archive.get(at..at + 2).map(|b| u16::from_le_bytes([b[0], b[1]]) as usize).ok_or_else(|| anyhow!("truncated zip"));
+    let u32_at = |at: usize| archive.get(at..at + 4).map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as usize).ok_or_else(|| anyhow!("truncated zip"));
+    let end = (0..archive.len().saturating_sub(21)).rev()
+        .find(|&i| archive[i..].starts_with(b"PK\x05\x06"))
+        .ok_or_else(|| anyhow!("not a zip archive"))?;
+    let (count, mut at) = (u16_at(end + 10)?, u32_at(end + 16)?);
+    for _ in 0..count {
+        if !archive.get(at..).is_some_and(|rest| rest.starts_with(b"PK\x01\x02")) {
+            return Err(anyhow!("damaged zip directory"));
+        }
+        let (method, size, name_len) = (u16_at(at + 10)?, u32_at(at + 20)?, u16_at(at + 28)?);
+        let (extra_len, comment_len, local) = (u16_at(at + 30)?, u16_at(at + 32)?, u32_at(at + 42)?);
+        let name = archive.get(at + 46..at + 46 + name_len).ok_or_else(|| anyhow!("truncated zip"))?;
+        at += 46 + name_len + extra_len + comment_len;
+        if name != wanted.as_bytes() {
+            continue;
+        }
+        let start = local + 30 + u16_at(local + 26)? + u16_at(local + 28)?;
+        let data = archive.get(start..start + size).ok_or_else(|| anyhow!("truncated zip"))?;
+        return match method {
+            0 => Ok(data.to_vec()),
+            8 => crate::inflate::inflate(data),
+            other => Err(anyhow!("{} uses unsupported compression method {}", wanted, other)),
+        };
+    }
+    Err(anyhow!("{} not found in archive", wanted))
+}
+
+/// `&amp;` and friends, and numeric character references, decoded
+#[cfg(any(feature = "docx", feature = "epub"))]
+fn xml_unescape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(at) = rest.find('&') {
+        out.push_str(&rest[..at]);
+        rest = &rest[at..];
+        let Some(end) = rest.find(';') else { break };
+        let entity = &rest[1..end];
+        let decoded = match entity {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            _ => entity.strip_prefix("#x").map(|h| u32::from_str_radix(h, 16))
+                .or_else(|| entity.strip_prefix('#').map(|d| d.parse::<u32>()))
+                .and_then(|n| n.ok())
+                .and_then(char::from_u32),
+        };
+        match decoded {
+            Some(c) => {
+                out.push(c);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                out.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// The value of attribute `name` in the start tag `tag`
+#[cfg(feature = "epub")]
+fn attribute(tag: &str, name: &str) -> Option<String> {
+    let at = tag.find(&format!(" {}=", name))? + name.len() + 2;
+    let quote = tag[at..].chars().next()?;
+    let value = &tag[at + 1..];
+    Some(xml_unescape(&value[..value.find(quote)?]))
+}
+
+/// The paragraphs of a Word document, one per line
+#[cfg(feature = "docx")]
+fn docx_text(bytes: &[u8]) -> Result<String> {
+    let xml = String::from_utf8(zip_entry(bytes, "word/document.xml")?)?;
+    let mut text = String::new();
+    let mut rest = xml.as_str();
+    while let Some(open) = rest.find('<') {
+        let Some(close) = rest[open..].find('>') else { break };
+        let tag = &rest[open + 1..open + close];
+        rest = &rest[open + close + 1..];
+        let name = tag.split([' ', '/']).next().unwrap_or_default();
+        match name {
+            "w:t" if !tag.ends_with('/') => {
+                let end = rest.find("</w:t>").unwrap_or(rest.len());
+                text.push_str(&xml_unescape(&rest[..end]));
+                rest = &rest[end..];
+            }
+            "w:tab" => text.push('\t'),
+            "w:br" | "w:cr" => text.push('\n'),
+            "" if tag == "/w:p" => text.push('\n'),
+            _ => {}
+        }
+    }
+    Ok(text.lines().map(str::trim_end).filter(|l| !l.is_empty()).collect::<Vec<_>>().join("\n"))
+}
+
+/// The text of each document of an EPUB, in reading (spine) order
+#[cfg(feature = "epub")]
+fn epub_sections(bytes: &[u8]) -> Result<Vec<String>> {
+    let container = String::from_utf8(zip_entry(bytes, "META-INF/container.xml")?)?;
+    let rootfile = container.split('<').find(|tag| tag.starts_with("rootfile "))
+        .and_then(|tag| attribute(tag, "full-path"))
+        .ok_or_else(|| anyhow!("no rootfile in container.xml"))?;
+    let base = rootfile.rsplit_once('/').map(|(dir, _)| format!("{}/", dir)).unwrap_or_default();
+    let package = String::from_utf8(zip_entry(bytes, &rootfile)?)?;
+    let tags: Vec<&str> = package.split('<').collect();
+    let manifest: std::collections::HashMap<String, String> = tags.iter()
+        .filter(|tag| tag.starts_with("item ") || tag.starts_with("opf:item "))
+        .filter_map(|tag| Some((attribute(tag, "id")?, attribute(tag, "href")?)))
+        .collect();
+    let mut sections = Vec::new();
+    for tag in tags.iter().filter(|tag| tag.starts_with("itemref ") || tag.starts_with("opf:itemref ")) {
+        let Some(href) = attribute(tag, "idref").and_then(|id| manifest.get(&id)) else { continue };
+        let html = String::from_utf8_lossy(&zip_entry(bytes, &format!("{}{}", base, href))?).into_owned();
+        let text = crate::knowledge::html_to_text(&html);
+        if !text.is_empty() {
+            sections.push(text);
+        }
+    }
+    if sections.is_empty() {
+        return Err(anyhow!("no readable sections"));
+    }
+    Ok(sections)
+}
+
+/// `read_pdf`, and `read_docx`/`read_epub` with their features
+pub(crate) fn register_document_natives(engine: &mut Engine, ctx: &NativeContext) {
+    let NativeContext { jail, audit, safety, politeness, .. } = ctx;
+    // read_pdf (and read_docx/read_epub with the docx/epub features): the text of a
+    // document in the workspace or on the web, page by page
+    let read_document = {
+        let jail = jail.clone();
+        let safety = safety.clone();
+        let audit = audit.clone();
+        let politeness = politeness.clone();
+        move |native: &str, source: &str, kind: DocumentKind| -> String {
+            let bytes = if source.starts_with("http://") || source.starts_with("https://") {
+                if let Err(e) = egress::guard(&safety.lock().unwrap(), source, native, Some(&audit)) {
+                    return format!("Error: {}", e);
+                }
+                info!("Reading: {}", source);
+                let (politeness, url) = (politeness.clone(), source.to_string());
+                let fetched = std::thread::spawn(move || {
+                    tokio::runtime::Runtime::new().unwrap().block_on(fetch_bytes(&politeness, &url))
+                }).join();
+                match fetched {
+                    Ok(Ok((bytes, _))) => bytes,
+                    Ok(Err(e)) => return format!("Error fetching {}: {}", source, e),
+                    Err(_) => return "Thread panic".to_string(),
+                }
+            } else {
+                match jail.resolve(source).and_then(|path| fs::read(path).map_err(|e| anyhow!("Error reading {}: {}", source, e))) {
+                    Ok(bytes) => bytes,
+                    Err(e) => return format!("Error: {}", e),
+                }
+            };
+            match kind.extract(&bytes) {
+                Ok(text) => truncate_for_reading(&text),
+                Err(e) => format!("Error: {}", e),
+            }
+        }
+    };
+    let read_clone = read_document.clone();
+    engine.register_fn("read_pdf", move |source: &str| -> String { read_clone("read_pdf", source, DocumentKind::Pdf) });
+    #[cfg(feature = "docx")]
+    {
+        let read_clone = read_document.clone();
+        engine.register_fn("read_docx", move |source: &str| -> String { read_clone("read_docx", source, DocumentKind::Docx) });
+    }
+    #[cfg(feature = "epub")]
+    {
+        let read_clone = read_document.clone();
+        engine.register_fn("read_epub", move |source: &str| -> String { read_clone("read_epub", source, DocumentKind::Epub) });
+    }
+}
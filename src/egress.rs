@@ -0,0 +1,199 @@
+use std::fmt;
+use std::net::{IpAddr, SocketAddr, ToSocketAddrs};
+use std::sync::Arc;
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use crate::audit::AuditLog;
+use crate::safety::SafetyPolicy;
+
+/// An outbound request the safety policy's host lists refuse
+#[derive(Debug, Clone, PartialEq)]
+pub struct EgressDenied {
+    pub host: String,
+    pub reason: String,
+}
+
+impl fmt::Display for EgressDenied {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Egress to {} denied: {}", self.host, self.reason)
+    }
+}
+
+impl std::error::Error for EgressDenied {}
+
+/// Whether `error` is, or was caused by, an egress refusal (a denied redirect comes wrapped
+/// in the HTTP client's error)
+pub fn is_egress_denied(error: &anyhow::Error) -> bool {
+    error.chain().any(|e| e.downcast_ref::<EgressDenied>().is_some())
+}
+
+/// Lower-case host of `url`, which may lack a scheme. A fully qualified name's trailing dot
+/// is dropped, as `example.com.` is the same host as `example.com`.
+pub fn host_of(url: &str) -> Option<String> {
+    let url = if url.contains("://") { url.to_string() } else { format!("http://{}", url) };
+    reqwest::Url::parse(&url).ok()?.host_str().map(normalize)
+}
+
+fn normalize(host: &str) -> String {
+    host.trim_matches(['[', ']']).trim_end_matches('.').to_lowercase()
+}
+
+/// A host rule: `example.com`, `*.example.com` (the domain and its subdomains), an IP
+/// or a CIDR range such as `10.0.0.0/8`
+#[derive(Debug, Clone, PartialEq)]
+enum HostRule {
+    Domain(String),
+    Subdomains(String),
+    Network(IpAddr, u8),
+}
+
+impl HostRule {
+    fn parse(rule: &str) -> Self {
+        let rule = rule.trim().trim_end_matches('.').to_lowercase();
+        if let Some((ip, bits)) = rule.split_once('/') {
+            if let (Ok(ip), Ok(bits)) = (ip.parse::<IpAddr>(), bits.parse::<u8>()) {
+                return HostRule::Network(ip, bits);
+            }
+        }
+        if let Ok(ip) = rule.parse::<IpAddr>() {
+            return HostRule::Network(ip, if ip.is_ipv4() { 32 } else { 128 });
+        }
+        match rule.strip_prefix("*.") {
+            Some(domain) => HostRule::Subdomains(domain.to_string()),
+            None => HostRule::Domain(rule),
+        }
+    }
+
+    fn matches(&self, host: &str, addresses: &[IpAddr]) -> bool {
+        match self {
+            HostRule::Domain(domain) => host == domain,
+            HostRule::Subdomains(domain) => host == domain || host.ends_with(&format!(".{}", domain)),
+            HostRule::Network(network, bits) => addresses.iter().any(|ip| in_network(ip, network, *bits)),
+        }
+    }
+}
+
+fn in_network(ip: &IpAddr, network: &IpAddr, bits: u8) -> bool {
+    match (ip, network) {
+        (IpAddr::V4(ip), IpAddr::V4(network)) => {
+            let bits = bits.min(32) as u32;
+            let mask = u32::MAX.checked_shl(32 - bits).unwrap_or(0);
+            u32::from(*ip) & mask == u32::from(*network) & mask
+        }
+        (IpAddr::V6(ip), IpAddr::V6(network)) => {
+            let bits = bits.min(128) as u32;
+            let mask = u128::MAX.checked_shl(128 - bits).unwrap_or(0);
+            u128::from(*ip) & mask == u128::from(*network) & mask
+        }
+        _ => false,
+    }
+}
+
+/// The host's own IP, or the addresses it resolves to. Only looked up when a CIDR rule
+/// needs it, so a name that resolves into a denied range is caught too.
+fn addresses_of(host: &str, rules: &[HostRule]) -> Vec<IpAddr> {
+    if let Ok(ip) = host.parse::<IpAddr>() {
+        return vec![ip];
+    }
+    if !rules.iter().any(|r| matches!(r, HostRule::Network(..))) {
+        return Vec::new();
+    }
+    (host, 0).to_socket_addrs()
+        .map(|addrs| addrs.map(|a| a.ip()).collect())
+        .unwrap_or_default()
+}
+
+/// Check `url` against the allowlist and denylist: denied hosts are refused, and when there
+/// is an allowlist, so is anything not on it
+pub fn check(policy: &SafetyPolicy, url: &str) -> Result<(), EgressDenied> {
+    if policy.allowed_hosts.is_empty() && policy.denied_hosts.is_empty() {
+        return Ok(());
+    }
+    let Some(host) = host_of(url) else {
+        return Err(EgressDenied { host: url.to_string(), reason: "no host in the address".to_string() });
+    };
+    let (allow, deny) = rules(policy);
+    let addresses = addresses_of(&host, &[allow.as_slice(), deny.as_slice()].concat());
+    check_host(policy, host, &addresses)
+}
+
+fn rules(policy: &SafetyPolicy) -> (Vec<HostRule>, Vec<HostRule>) {
+    let parse = |rules: &[String]| rules.iter().map(|r| HostRule::parse(r)).collect();
+    (parse(&policy.allowed_hosts), parse(&policy.denied_hosts))
+}
+
+/// The host lists' verdict on `host` reached at `addresses`
+fn check_host(policy: &SafetyPolicy, host: String, addresses: &[IpAddr]) -> Result<(), EgressDenied> {
+    let (allow, deny) = rules(policy);
+    if let Some(rule) = policy.denied_hosts.iter().zip(&deny).find(|(_, r)| r.matches(&host, addresses)) {
+        return Err(EgressDenied { host, reason: format!("matches denied_hosts entry '{}'", rule.0) });
+    }
+    if !allow.is_empty() && !allow.iter().any(|r| r.matches(&host, addresses)) {
+        return Err(EgressDenied { host, reason: "not in allowed_hosts".to_string() });
+    }
+    Ok(())
+}
+
+/// Redirects reqwest follows at most, as its default policy does
+const MAX_REDIRECTS: usize = 10;
+
+/// A redirect policy that runs `check` on every hop, so an allowed host can't bounce a
+/// request on to a denied one
+pub fn redirect_policy(policy: SafetyPolicy) -> reqwest::redirect::Policy {
+    reqwest::redirect::Policy::custom(move |attempt| {
+        if attempt.previous().len() >= MAX_REDIRECTS {
+            return attempt.error("too many redirects");
+        }
+        match check(&policy, attempt.url().as_str()) {
+            Ok(()) => attempt.follow(),
+            Err(denied) => attempt.error(denied),
+        }
+    })
+}
+
+/// Resolves names for an HTTP client, keeping only the addresses the host lists allow. `check`
+/// resolves a name on its own, and the name may resolve elsewhere by the time the client
+/// connects; with this resolver the connection only goes to an address that passed.
+pub struct CheckedResolver {
+    policy: Box<dyn Fn() -> anyhow::Result<SafetyPolicy> + Send + Sync>,
+}
+
+/// A resolver for `ClientBuilder::dns_resolver` that checks against the policy `policy`
+/// returns at each lookup
+pub fn resolver(policy: impl Fn() -> anyhow::Result<SafetyPolicy> + Send + Sync + 'static) -> Arc<CheckedResolver> {
+    Arc::new(CheckedResolver { policy: Box::new(policy) })
+}
+
+impl Resolve for CheckedResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let policy = (self.policy)();
+        let host = normalize(name.as_str());
+        Box::pin(async move {
+            let policy = policy?;
+            let resolved: Vec<SocketAddr> = tokio::net::lookup_host((host.as_str(), 0)).await?.collect();
+            if policy.allowed_hosts.is_empty() && policy.denied_hosts.is_empty() {
+                return Ok(Box::new(resolved.into_iter()) as Addrs);
+            }
+            let mut refused = None;
+            let mut allowed = Vec::new();
+            for address in resolved {
+                match check_host(&policy, host.clone(), &[address.ip()]) {
+                    Ok(()) => allowed.push(address),
+                    Err(denied) => refused = refused.or(Some(denied)),
+                }
+            }
+            match (allowed.is_empty(), refused) {
+                (true, Some(denied)) => Err(denied.into()),
+                _ => Ok(Box::new(allowed.into_iter()) as Addrs),
+            }
+        })
+    }
+}
+
+/// `check`, recording refusals in `audit` as `egress_denied` for `tool`
+pub fn guard(policy: &SafetyPolicy, url: &str, tool: &str, audit: Option<&AuditLog>) -> Result<(), EgressDenied> {
+    let checked = check(policy, url);
+    if let (Err(denied), Some(audit)) = (&checked, audit) {
+        audit.record_or_warn("egress_denied", &format!("{} to {}", tool, denied.host), &denied.reason);
+    }
+    checked
+}
@@ -0,0 +1,181 @@
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::Notify;
+use tracing::{info, warn};
+use crate::message::IpcMessage;
+use crate::registry::{address_key, broadcast, HeartbeatConfig, PeerRegistry, PeerStatus};
+use rhai::Engine;
+use crate::tools::NativeContext;
+
+/// What a higher agent answers an `Election` message with, before running its own election
+pub const TAKING_OVER: &str = "Taking over the election";
+
+/// Rounds of waiting for a higher agent to claim leadership before claiming it anyway
+const MAX_ROUNDS: usize = 3;
+
+#[derive(Debug, Default)]
+struct ElectionState {
+    leader: Option<String>,
+    running: bool,
+}
+
+/// Bully election of a swarm leader (for hosting the registry or dispatching tasks, say).
+/// The agent with the highest address among those answering wins: a candidate asks every
+/// higher peer, becomes leader if none answers, and otherwise waits for the winner's `Leader`
+/// message. A leader that stops answering heartbeats is replaced by the next election.
+#[derive(Debug, Clone)]
+pub struct Election {
+    state: Arc<Mutex<ElectionState>>,
+    announced: Arc<Notify>,
+    timeout: Duration,
+}
+
+impl Default for Election {
+    fn default() -> Self {
+        Self::new(Duration::from_secs(2))
+    }
+}
+
+impl Election {
+    /// `timeout` bounds each message, and the wait for a higher agent's `Leader` message
+    pub fn new(timeout: Duration) -> Self {
+        Self { state: Arc::default(), announced: Arc::new(Notify::new()), timeout }
+    }
+
+    /// Elections run on their own when `LEADER_ELECTION` is `true`/`1` (and heartbeats are on);
+    /// `ELECTION_TIMEOUT_SECS` (default 2) bounds each step
+    pub fn from_env() -> (Self, bool) {
+        let enabled = std::env::var("LEADER_ELECTION")
+            .map(|v| matches!(v.to_lowercase().as_str(), "1" | "true" | "yes"))
+            .unwrap_or(false);
+        let timeout = std::env::var("ELECTION_TIMEOUT_SECS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(2);
+        (Self::new(Duration::from_secs(timeout)), enabled)
+    }
+
+    /// The current leader's address, if one was elected
+    pub fn leader(&self) -> Option<String> {
+        self.state.lock().unwrap().leader.clone()
+    }
+
+    /// Accept `address` as leader, as its `Leader` message says
+    pub fn set_leader(&self, address: &str) {
+        self.state.lock().unwrap().leader = Some(address_key(address));
+        self.announced.notify_waiters();
+    }
+
+    /// Forget the leader, e.g. when it stopped answering
+    pub fn clear(&self) {
+        self.state.lock().unwrap().leader = None;
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.state.lock().unwrap().running
+    }
+
+    /// Run an election as the agent at `me` among the peers in `peers`, returning the leader.
+    /// If one is already running, wait for its outcome instead.
+    pub async fn run(&self, me: &str, peers: &PeerRegistry) -> Option<String> {
+        let me = address_key(me);
+        let announced = self.announced.notified();
+        let already_running = std::mem::replace(&mut self.state.lock().unwrap().running, true);
+        if already_running {
+            let _ = tokio::time::timeout(self.timeout * MAX_ROUNDS as u32, announced).await;
+            return self.leader();
+        }
+        let leader = self.elect(&me, peers).await;
+        self.state.lock().unwrap().running = false;
+        leader
+    }
+
+    async fn elect(&self, me: &str, peers: &PeerRegistry) -> Option<String> {
+        let others: Vec<String> = peers.list().into_iter()
+            .map(|p| address_key(&p.address))
+            .filter(|a| a != me)
+            .collect();
+        let higher: Vec<String> = others.iter().filter(|a| a.as_str() > me).cloned().collect();
+        self.clear();
+        for round in 1..=MAX_ROUNDS {
+            // Listen before asking, so an announcement racing the replies isn't missed
+            let announced = self.announced.notified();
+            let report = broadcast(&higher, &IpcMessage::Election { address: me.to_string() }, Some(me.to_string()), self.timeout).await;
+            let challenged = report.deliveries.iter()
+                .any(|d| matches!(&d.outcome, Ok(reply) if reply.starts_with(TAKING_OVER)));
+            if !challenged {
+                break;
+            }
+            // A higher agent is alive: it announces itself once its own election is over
+            if self.leader().is_none() {
+                let _ = tokio::time::timeout(self.timeout, announced).await;
+            }
+            if let Some(leader) = self.leader() {
+                return Some(leader);
+            }
+            warn!("No leader announced after election round {}", round);
+        }
+        info!("{} is the leader", me);
+        self.set_leader(me);
+        let report = broadcast(&others, &IpcMessage::Leader { address: me.to_string() }, Some(me.to_string()), self.timeout).await;
+        info!("Leadership announced to {}/{} peer(s)", report.delivered(), report.deliveries.len());
+        Some(me.to_string())
+    }
+}
+
+/// Elect a leader now and again each heartbeat `interval` in which there is none, or the leader
+/// has stopped answering heartbeats. Runs until the task is dropped.
+pub async fn leader_loop(election: Election, me: String, peers: PeerRegistry, config: HeartbeatConfig) {
+    let mut ticker = tokio::time::interval(config.interval);
+    ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    loop {
+        ticker.tick().await;
+        let gone = match election.leader() {
+            None => true,
+            Some(leader) if leader == address_key(&me) => false,
+            Some(leader) => peers.status(&leader, config.timeout) == Some(PeerStatus::Dead),
+        };
+        if gone && !election.is_running() {
+            if let Some(leader) = election.leader() {
+                warn!("Leader {} stopped answering; electing a new one", leader);
+                election.clear();
+            }
+            election.run(&me, &peers).await;
+        }
+    }
+}
+
+/// `current_leader` and `elect_leader`
+pub(crate) fn register_election_natives(engine: &mut Engine, ctx: &NativeContext) {
+    let NativeContext { peers, election, local_address, .. } = ctx;
+    // Leader election (bully): the highest address among the agents answering leads
+    let election_clone = election.clone();
+    let address_clone = local_address.clone();
+    engine.register_fn("current_leader", move || -> String {
+        let me = address_clone.lock().unwrap().clone().map(|a| address_key(&a));
+        match election_clone.leader() {
+            Some(leader) if Some(&leader) == me.as_ref() => format!("{} (this agent)", leader),
+            Some(leader) => leader,
+            None if election_clone.is_running() => "No leader yet: an election is running".to_string(),
+            None => "No leader elected yet".to_string(),
+        }
+    });
+
+    let election_clone = election.clone();
+    let peers_clone = peers.clone();
+    let address_clone = local_address.clone();
+    engine.register_fn("elect_leader", move || -> String {
+        let Some(me) = address_clone.lock().unwrap().clone() else {
+            return "Error: start_server first so peers can reach this agent".to_string();
+        };
+        let election = election_clone.clone();
+        let peers = peers_clone.clone();
+        std::thread::spawn(move || {
+            let rt = tokio::runtime::Runtime::new().unwrap();
+            match rt.block_on(election.run(&me, &peers)) {
+                Some(leader) => format!("Leader: {}", leader),
+                None => "Error: no leader came out of the election".to_string(),
+            }
+        }).join().unwrap_or_else(|_| "Thread panic".to_string())
+    });
+}
@@ -0,0 +1,257 @@
+use anyhow::{Result, anyhow};
+use aws_config::BehaviorVersion;
+use aws_sdk_bedrockruntime::primitives::Blob;
+use aws_sdk_bedrockruntime::Client;
+use crate::llm::OpenAiConfig;
+use crate::retry::{is_transient_status, transient};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EmbeddingProvider {
+    /// Local feature hashing of words and character trigrams: no model needed,
+    /// matches on shared vocabulary rather than meaning
+    Hashing,
+    Ollama,
+    OpenAi,
+    Bedrock,
+}
+
+impl EmbeddingProvider {
+    /// Parse a provider name, falling back to local hashing for unknown values
+    pub fn parse(name: &str) -> Self {
+        match name.to_lowercase().as_str() {
+            "ollama" => EmbeddingProvider::Ollama,
+            "openai" | "azure" | "azure_openai" => EmbeddingProvider::OpenAi,
+            "bedrock" => EmbeddingProvider::Bedrock,
+            _ => EmbeddingProvider::Hashing,
+        }
+    }
+
+    pub fn default_model(&self) -> &'static str {
+        match self {
+            EmbeddingProvider::Hashing => "hashing-256",
+            EmbeddingProvider::Ollama => "nomic-embed-text",
+            EmbeddingProvider::OpenAi => "text-embedding-3-small",
+            EmbeddingProvider::Bedrock => "amazon.titan-embed-text-v2:0",
+        }
+    }
+}
+
+/// Dimensions of the local hashing embedder
+const HASHING_DIMS: usize = 256;
+
+/// Turns text into vectors for similarity search
+#[derive(Clone)]
+pub struct Embedder {
+    provider: EmbeddingProvider,
+    model: String,
+    ollama_url: String,
+    openai: OpenAiConfig,
+    bedrock: Option<Client>,
+}
+
+impl Embedder {
+    /// The local hashing embedder
+    pub fn hashing() -> Self {
+        Self {
+            provider: EmbeddingProvider::Hashing,
+            model: EmbeddingProvider::Hashing.default_model().to_string(),
+            ollama_url: String::new(),
+            openai: OpenAiConfig::from_env(),
+            bedrock: None,
+        }
+    }
+
+    /// Read `EMBEDDING_PROVIDER` (`hash` by default, or `ollama`/`openai`/`bedrock`) and
+    /// `EMBEDDING_MODEL`. Ollama uses `OLLAMA_EMBED_URL`, defaulting to `/api/embed` next
+    /// to `OLLAMA_URL`; OpenAI uses the same `OPENAI_*` settings as chat.
+    pub async fn from_env() -> Self {
+        let provider = EmbeddingProvider::parse(&std::env::var("EMBEDDING_PROVIDER").unwrap_or_default());
+        let model = std::env::var("EMBEDDING_MODEL").ok().filter(|m| !m.trim().is_empty());
+        Self::with_model(provider, model).await
+    }
+
+    pub async fn with_model(provider: EmbeddingProvider, model: Option<String>) -> Self {
+        let bedrock = match provider {
+            EmbeddingProvider::Bedrock => {
+                let config = aws_config::load_defaults(BehaviorVersion::latest()).await;
+                Some(Client::new(&config))
+            }
+            _ => None,
+        };
+        let ollama_url = std::env::var("OLLAMA_EMBED_URL").unwrap_or_else(|_| {
+            let chat_url = std::env::var("OLLAMA_URL").unwrap_or_else(|_| "http://localhost:11434/api/chat".to_string());
+            format!("{}/api/embed", chat_url.trim_end_matches("/api/chat").trim_end_matches('/'))
+        });
+        Self {
+            provider,
+            model: model.unwrap_or_else(|| provider.default_model().to_string()),
+            ollama_url,
+            openai: OpenAiConfig::from_env(),
+            bedrock,
+        }
+    }
+
+    /// Override the Ollama embeddings endpoint
+    pub fn with_ollama_url(mut self, url: impl Into<String>) -> Self {
+        self.ollama_url = url.into();
+        self
+    }
+
+    pub fn with_openai_config(mut self, config: OpenAiConfig) -> Self {
+        self.openai = config;
+        self
+    }
+
+    pub fn provider(&self) -> EmbeddingProvider {
+        self.provider
+    }
+
+    /// `provider:model`, stored with vectors so an index built by another model is detected
+    pub fn label(&self) -> String {
+        let provider = match self.provider {
+            EmbeddingProvider::Hashing => "hash",
+            EmbeddingProvider::Ollama => "ollama",
+            EmbeddingProvider::OpenAi => "openai",
+            EmbeddingProvider::Bedrock => "bedrock",
+        };
+        format!("{}:{}", provider, self.model)
+    }
+
+    pub async fn embed(&self, text: &str) -> Result<Vec<f32>> {
+        let mut vectors = self.embed_batch(&[text.to_string()]).await?;
+        vectors.pop().ok_or_else(|| anyhow!("Embedding provider returned no vector"))
+    }
+
+    pub async fn embed_batch(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+        if texts.is_empty() {
+            return Ok(Vec::new());
+        }
+        match self.provider {
+            EmbeddingProvider::Hashing => Ok(texts.iter().map(|t| hash_embedding(t)).collect()),
+            EmbeddingProvider::Ollama => self.embed_ollama(texts).await,
+            EmbeddingProvider::OpenAi => self.embed_openai(texts).await,
+            EmbeddingProvider::Bedrock => self.embed_bedrock(texts).await,
+        }
+    }
+
+    async fn embed_ollama(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+        let payload = serde_json::json!({ "model": self.model, "input": texts });
+        let resp = reqwest::Client::new().post(&self.ollama_url)
+            .json(&payload)
+            .send()
+            .await
+            .map_err(|e| transient(format!("Ollama embedding request error: {}", e)))?;
+        if !resp.status().is_success() {
+            let message = format!("Ollama embedding API error: {}", resp.status());
+            return Err(if is_transient_status(resp.status()) { transient(message) } else { anyhow!(message) });
+        }
+
+        // Response format: { "embeddings": [[...], ...] }
+        let resp_json: serde_json::Value = resp.json().await?;
+        resp_json.get("embeddings")
+            .and_then(|e| e.as_array())
+            .map(|vectors| vectors.iter().map(json_vector).collect())
+            .ok_or_else(|| anyhow!("Invalid response format from Ollama embeddings"))
+    }
+
+    async fn embed_openai(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+        let url = format!("{}/embeddings", self.openai.base_url.trim_end_matches('/'));
+        let mut request = reqwest::Client::new().post(&url)
+            .json(&serde_json::json!({ "model": self.model, "input": texts }));
+        match (&self.openai.api_version, &self.openai.api_key) {
+            (Some(version), key) => {
+                request = request.query(&[("api-version", version)]);
+                if let Some(key) = key {
+                    request = request.header("api-key", key);
+                }
+            }
+            (None, Some(key)) => request = request.bearer_auth(key),
+            (None, None) => {}
+        }
+
+        let resp = request.send().await
+            .map_err(|e| transient(format!("OpenAI embedding request error: {}", e)))?;
+        if !resp.status().is_success() {
+            let status = resp.status();
+            let message = format!("OpenAI embedding API error: {} {}", status, resp.text().await.unwrap_or_default());
+            return Err(if is_transient_status(status) { transient(message) } else { anyhow!(message) });
+        }
+
+        // Response format: { "data": [ { "index": 0, "embedding": [...] }, ... ] }
+        let resp_json: serde_json::Value = resp.json().await?;
+        let mut data = resp_json.get("data")
+            .and_then(|d| d.as_array())
+            .cloned()
+            .ok_or_else(|| anyhow!("Invalid response format from OpenAI embeddings"))?;
+        data.sort_by_key(|d| d.get("index").and_then(|i| i.as_u64()).unwrap_or(0));
+        Ok(data.iter().map(|d| json_vector(&d["embedding"])).collect())
+    }
+
+    async fn embed_bedrock(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+        let client = self.bedrock.as_ref().ok_or_else(|| anyhow!("Bedrock client not initialized"))?;
+        // Titan embeds one text per request
+        let mut vectors = Vec::with_capacity(texts.len());
+        for text in texts {
+            let body = serde_json::json!({ "inputText": text });
+            let output = client.invoke_model()
+                .model_id(&self.model)
+                .content_type("application/json")
+                .body(Blob::new(body.to_string()))
+                .send()
+                .await
+                .map_err(|e| anyhow!("Bedrock embedding error: {}", e))?;
+            let resp_json: serde_json::Value = serde_json::from_slice(output.body.as_ref())?;
+            vectors.push(json_vector(&resp_json["embedding"]));
+        }
+        Ok(vectors)
+    }
+}
+
+fn json_vector(value: &serde_json::Value) -> Vec<f32> {
+    value.as_array()
+        .map(|v| v.iter().filter_map(|x| x.as_f64()).map(|x| x as f32).collect())
+        .unwrap_or_default()
+}
+
+/// FNV-1a, stable across runs and platforms so stored vectors stay valid
+pub(crate) fn fnv1a(text: &str) -> u64 {
+    text.bytes().fold(0xcbf29ce484222325, |hash, b| (hash ^ b as u64).wrapping_mul(0x100000001b3))
+}
+
+/// Normalized feature-hashing vector over words and their character trigrams
+pub fn hash_embedding(text: &str) -> Vec<f32> {
+    let mut vector = vec![0.0f32; HASHING_DIMS];
+    let lower = text.to_lowercase();
+    for word in lower.split(|c: char| !c.is_alphanumeric()).filter(|w| !w.is_empty()) {
+        vector[(fnv1a(word) % HASHING_DIMS as u64) as usize] += 1.0;
+        let padded: Vec<char> = format!("#{}#", word).chars().collect();
+        for trigram in padded.windows(3) {
+            let trigram: String = trigram.iter().collect();
+            vector[(fnv1a(&trigram) % HASHING_DIMS as u64) as usize] += 0.5;
+        }
+    }
+    normalize(&mut vector);
+    vector
+}
+
+fn normalize(vector: &mut [f32]) {
+    let norm = vector.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm > 0.0 {
+        vector.iter_mut().for_each(|x| *x /= norm);
+    }
+}
+
+/// Cosine similarity; 0 for empty or mismatched vectors
+pub fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    if a.len() != b.len() || a.is_empty() {
+        return 0.0;
+    }
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norm_a = a.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let norm_b = b.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm_a == 0.0 || norm_b == 0.0 {
+        0.0
+    } else {
+        dot / (norm_a * norm_b)
+    }
+}
@@ -0,0 +1,156 @@
+use anyhow::{Result, anyhow};
+use base64::Engine as _;
+use chacha20poly1305::aead::{Aead, KeyInit};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use ed25519_dalek::SigningKey;
+use hkdf::Hkdf;
+use rand_core::{OsRng, RngCore};
+use sha2::Sha256;
+use std::path::{Path, PathBuf};
+use x25519_dalek::{EphemeralSecret, PublicKey, StaticSecret};
+
+/// Start of tool code sealed with `seal`
+pub const SEALED_PREFIX: &str = "sealed:x25519:";
+
+const KEY_INFO: &[u8] = b"swarm-thing tool code";
+const SIGNING_INFO: &[u8] = b"swarm-thing tool signing";
+
+fn b64() -> base64::engine::GeneralPurpose {
+    base64::engine::general_purpose::STANDARD
+}
+
+/// Parse a base64 X25519 public key, as agents advertise it in `PeerInfo::public_key`
+pub fn parse_public_key(key: &str) -> Result<PublicKey> {
+    let bytes: [u8; 32] = b64().decode(key.trim())?
+        .try_into()
+        .map_err(|_| anyhow!("an X25519 public key is 32 bytes"))?;
+    Ok(PublicKey::from(bytes))
+}
+
+fn cipher(shared: &[u8], ephemeral: &PublicKey, recipient: &PublicKey) -> ChaCha20Poly1305 {
+    let salt = [ephemeral.as_bytes().as_slice(), recipient.as_bytes().as_slice()].concat();
+    let mut key = [0u8; 32];
+    // 32 bytes is always a valid HKDF-SHA256 output length
+    Hkdf::<Sha256>::new(Some(&salt), shared).expand(KEY_INFO, &mut key).expect("valid key length");
+    ChaCha20Poly1305::new(Key::from_slice(&key))
+}
+
+/// Encrypt `plaintext` so only the holder of `recipient`'s secret key can read it: a fresh
+/// X25519 key agreement, HKDF-SHA256 and ChaCha20-Poly1305. The result starts with
+/// `SEALED_PREFIX`.
+pub fn seal(plaintext: &str, recipient: &PublicKey) -> Result<String> {
+    let ephemeral = EphemeralSecret::random_from_rng(OsRng);
+    let ephemeral_public = PublicKey::from(&ephemeral);
+    let shared = ephemeral.diffie_hellman(recipient);
+    let mut nonce = [0u8; 12];
+    OsRng.fill_bytes(&mut nonce);
+    let ciphertext = cipher(shared.as_bytes(), &ephemeral_public, recipient)
+        .encrypt(Nonce::from_slice(&nonce), plaintext.as_bytes())
+        .map_err(|_| anyhow!("encryption failed"))?;
+    let sealed = [ephemeral_public.as_bytes().as_slice(), &nonce, &ciphertext].concat();
+    Ok(format!("{}{}", SEALED_PREFIX, b64().encode(sealed)))
+}
+
+pub fn is_sealed(text: &str) -> bool {
+    text.starts_with(SEALED_PREFIX)
+}
+
+/// `plaintext` sealed to the base64 `public_key` if the recipient advertised one, as is otherwise
+pub fn seal_to(public_key: Option<&str>, plaintext: &str) -> Result<String> {
+    match public_key {
+        Some(key) => seal(plaintext, &parse_public_key(key)?),
+        None => Ok(plaintext.to_string()),
+    }
+}
+
+/// `text` decrypted with `keys` if it is sealed, as is otherwise
+pub fn unseal(keys: Option<&AgentKeys>, text: &str) -> Result<String> {
+    match (is_sealed(text), keys) {
+        (false, _) => Ok(text.to_string()),
+        (true, Some(keys)) => keys.open(text),
+        (true, None) => Err(anyhow!("sealed, but this agent has no key to open it")),
+    }
+}
+
+/// This agent's X25519 key pair. Peers encrypt the tool code they share with it to the public
+/// key it advertises when it announces itself; its tool signing key derives from it too.
+#[derive(Clone)]
+pub struct AgentKeys {
+    secret: StaticSecret,
+}
+
+impl AgentKeys {
+    pub fn generate() -> Self {
+        Self { secret: StaticSecret::random_from_rng(OsRng) }
+    }
+
+    /// The key pair in `path` (the base64 secret key), created there if missing
+    pub fn load_or_create(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        if path.exists() {
+            let bytes: [u8; 32] = b64().decode(std::fs::read_to_string(path)?.trim())?
+                .try_into()
+                .map_err(|_| anyhow!("Corrupt key file {:?}", path))?;
+            return Ok(Self { secret: StaticSecret::from(bytes) });
+        }
+        let keys = Self::generate();
+        if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            std::fs::create_dir_all(parent)?;
+        }
+        // Write aside and link into place, so agents starting together end up sharing one key
+        let tmp = path.with_extension(format!("{}.tmp", keys.public_key_base64().replace(['/', '+', '='], "")));
+        std::fs::write(&tmp, b64().encode(keys.secret.to_bytes()))?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(&tmp, std::fs::Permissions::from_mode(0o600))?;
+        }
+        let linked = std::fs::hard_link(&tmp, path);
+        let _ = std::fs::remove_file(&tmp);
+        match linked {
+            Ok(()) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => return Self::load_or_create(path),
+            Err(e) => return Err(e.into()),
+        }
+        Ok(keys)
+    }
+
+    /// The key pair in `AGENT_KEY_FILE`, default `state/agent.key`
+    pub fn from_env() -> Result<Self> {
+        let path = std::env::var("AGENT_KEY_FILE").map(PathBuf::from).unwrap_or_else(|_| PathBuf::from("state/agent.key"));
+        Self::load_or_create(path)
+    }
+
+    pub fn public_key(&self) -> PublicKey {
+        PublicKey::from(&self.secret)
+    }
+
+    /// Ed25519 key the agent signs the tools it authors with, derived from the same secret
+    pub fn signing_key(&self) -> SigningKey {
+        let mut seed = [0u8; 32];
+        Hkdf::<Sha256>::new(None, self.secret.as_bytes()).expand(SIGNING_INFO, &mut seed).expect("valid key length");
+        SigningKey::from_bytes(&seed)
+    }
+
+    /// The public key in base64, as advertised to peers
+    pub fn public_key_base64(&self) -> String {
+        b64().encode(self.public_key().as_bytes())
+    }
+
+    /// Decrypt what `seal` encrypted to this agent's public key
+    pub fn open(&self, sealed: &str) -> Result<String> {
+        let encoded = sealed.strip_prefix(SEALED_PREFIX).ok_or_else(|| anyhow!("not sealed"))?;
+        let bytes = b64().decode(encoded.trim())?;
+        if bytes.len() < 32 + 12 {
+            return Err(anyhow!("sealed text is too short"));
+        }
+        let (ephemeral, rest) = bytes.split_at(32);
+        let (nonce, ciphertext) = rest.split_at(12);
+        let ephemeral = PublicKey::from(<[u8; 32]>::try_from(ephemeral)?);
+        let shared = self.secret.diffie_hellman(&ephemeral);
+        let plaintext = cipher(shared.as_bytes(), &ephemeral, &self.public_key())
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| anyhow!("sealed for a different key, or tampered with"))?;
+        Ok(String::from_utf8(plaintext)?)
+    }
+}
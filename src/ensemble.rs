@@ -0,0 +1,286 @@
+use anyhow::{Result, anyhow};
+use std::collections::HashMap;
+use std::time::Duration;
+use tokio::task::JoinSet;
+use crate::ipc::{call_peer, PendingCalls};
+use crate::llm::{LlmClient, Message};
+use crate::message::IpcMessage;
+use tracing::info;
+use rhai::Engine;
+use crate::tools::NativeContext;
+
+/// Who answers in an ensemble: a model configuration, or a peer agent (by address)
+#[derive(Clone)]
+pub enum Voter {
+    Model(LlmClient),
+    Peer(String),
+}
+
+impl Voter {
+    pub fn label(&self) -> String {
+        match self {
+            Voter::Model(client) => client.label(),
+            Voter::Peer(address) => address.clone(),
+        }
+    }
+}
+
+/// How the answers become one
+#[derive(Clone)]
+pub enum Aggregator {
+    /// The most common answer (after `normalize_vote`); ties go to the earliest voter
+    Majority,
+    /// A judge model writes one answer from all of them
+    Synthesis(LlmClient),
+}
+
+impl Aggregator {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Aggregator::Majority => "majority vote",
+            Aggregator::Synthesis(_) => "synthesis",
+        }
+    }
+}
+
+/// One voter's answer
+#[derive(Debug, Clone)]
+pub struct Vote {
+    pub voter: String,
+    pub answer: std::result::Result<String, String>,
+}
+
+/// The final answer to an ensemble question, with the answers it came from
+#[derive(Debug, Clone)]
+pub struct Consensus {
+    pub question: String,
+    pub votes: Vec<Vote>,
+    pub answer: String,
+    /// How many answers agree with `answer`, for a majority vote
+    pub agreeing: Option<usize>,
+    pub aggregator: &'static str,
+}
+
+impl Consensus {
+    /// The answer, then how it was reached and one line per voter
+    pub fn render(&self) -> String {
+        let answered = self.votes.iter().filter(|v| v.answer.is_ok()).count();
+        let mut lines = vec![self.answer.trim().to_string(), String::new()];
+        lines.push(match self.agreeing {
+            Some(agreeing) => format!("{} of {} answer(s) agree ({})", agreeing, answered, self.aggregator),
+            None => format!("From {} of {} answer(s) ({})", answered, self.votes.len(), self.aggregator),
+        });
+        for vote in &self.votes {
+            lines.push(match &vote.answer {
+                Ok(answer) => format!("- {}: {}", vote.voter, first_line(answer)),
+                Err(e) => format!("- {}: Error: {}", vote.voter, e),
+            });
+        }
+        lines.join("\n")
+    }
+}
+
+fn first_line(text: &str) -> &str {
+    text.trim().lines().next().unwrap_or_default()
+}
+
+/// The form answers are compared in for a majority vote: lowercase, single spaces, without
+/// surrounding quotes or a trailing full stop
+pub fn normalize_vote(answer: &str) -> String {
+    answer.split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase()
+        .trim_matches(|c: char| c == '"' || c == '\'' || c == '`')
+        .trim_end_matches(['.', '!'])
+        .to_string()
+}
+
+/// Asks several voters the same question at once and aggregates their answers
+pub struct Ensemble {
+    voters: Vec<Voter>,
+    aggregator: Aggregator,
+    calls: PendingCalls,
+    from: Option<String>,
+    timeout: Duration,
+}
+
+impl Ensemble {
+    pub fn new(voters: Vec<Voter>, aggregator: Aggregator) -> Self {
+        Self { voters, aggregator, calls: PendingCalls::new(), from: None, timeout: Duration::from_secs(60) }
+    }
+
+    /// Peers answer through the server at `from`, which completes `calls`
+    pub fn with_calls(mut self, calls: PendingCalls, from: Option<String>) -> Self {
+        self.calls = calls;
+        self.from = from;
+        self
+    }
+
+    /// How long to wait for each voter
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// `n` voters: the models in `ENSEMBLE_MODELS` (comma separated `provider:model` specs, taken
+    /// in turn until there are `n`), or else the first `n` of `peers`. `ENSEMBLE_AGGREGATOR` is
+    /// `majority` (default) or `synthesis`, judged by `ENSEMBLE_JUDGE` (default the main model);
+    /// `ENSEMBLE_TIMEOUT_SECS` (default 60) bounds each answer.
+    pub async fn from_env(n: usize, peers: Vec<String>) -> Result<Self> {
+        if n == 0 {
+            return Err(anyhow!("an ensemble needs at least one voter"));
+        }
+        let specs: Vec<String> = std::env::var("ENSEMBLE_MODELS").unwrap_or_default()
+            .split(',')
+            .map(|s| s.trim().to_string())
+            .filter(|s| !s.is_empty())
+            .collect();
+        let voters = if specs.is_empty() {
+            if peers.is_empty() {
+                return Err(anyhow!("no peers to ask; set ENSEMBLE_MODELS or discover peers first"));
+            }
+            peers.into_iter().take(n).map(Voter::Peer).collect()
+        } else {
+            let mut models = Vec::new();
+            for spec in &specs {
+                models.push(LlmClient::from_spec(spec).await?);
+            }
+            models.into_iter().cycle().take(n).map(Voter::Model).collect()
+        };
+        let aggregator = match std::env::var("ENSEMBLE_AGGREGATOR").unwrap_or_default().to_lowercase().as_str() {
+            "" | "majority" => Aggregator::Majority,
+            "synthesis" => Aggregator::Synthesis(match std::env::var("ENSEMBLE_JUDGE") {
+                Ok(spec) if !spec.trim().is_empty() => LlmClient::from_spec(&spec).await?,
+                _ => LlmClient::new().await?,
+            }),
+            other => return Err(anyhow!("unknown ENSEMBLE_AGGREGATOR '{}' (majority or synthesis)", other)),
+        };
+        let timeout = std::env::var("ENSEMBLE_TIMEOUT_SECS").ok().and_then(|v| v.parse().ok()).unwrap_or(60);
+        Ok(Self::new(voters, aggregator).with_timeout(Duration::from_secs(timeout)))
+    }
+
+    pub fn voters(&self) -> Vec<String> {
+        self.voters.iter().map(Voter::label).collect()
+    }
+
+    /// Ask every voter `question` and aggregate the answers; fails if none answered
+    pub async fn ask(&self, question: &str) -> Result<Consensus> {
+        let mut asks = JoinSet::new();
+        for (index, voter) in self.voters.iter().enumerate() {
+            let voter = voter.clone();
+            let question = question.to_string();
+            let calls = self.calls.clone();
+            let from = self.from.clone();
+            let timeout = self.timeout;
+            asks.spawn(async move {
+                let answer = match tokio::time::timeout(timeout, Self::ask_one(&voter, &question, &calls, from, timeout)).await {
+                    Ok(answer) => answer.map_err(|e| e.to_string()),
+                    Err(_) => Err(format!("no answer within {:?}", timeout)),
+                };
+                (index, Vote { voter: voter.label(), answer })
+            });
+        }
+        let mut votes = Vec::new();
+        while let Some(joined) = asks.join_next().await {
+            votes.push(joined?);
+        }
+        votes.sort_by_key(|(index, _)| *index);
+        let votes: Vec<Vote> = votes.into_iter().map(|(_, vote)| vote).collect();
+
+        let answers: Vec<&str> = votes.iter().filter_map(|v| v.answer.as_deref().ok()).collect();
+        if answers.is_empty() {
+            return Err(anyhow!("none of the {} voter(s) answered", votes.len()));
+        }
+        let (answer, agreeing) = match &self.aggregator {
+            Aggregator::Majority => {
+                let (answer, agreeing) = majority(&answers);
+                (answer, Some(agreeing))
+            }
+            Aggregator::Synthesis(judge) => (synthesize(judge, question, &votes).await?, None),
+        };
+        Ok(Consensus { question: question.to_string(), votes, answer, agreeing, aggregator: self.aggregator.name() })
+    }
+
+    async fn ask_one(voter: &Voter, question: &str, calls: &PendingCalls, from: Option<String>, timeout: Duration) -> Result<String> {
+        match voter {
+            Voter::Model(client) => client.chat(vec![Message::user(question)], None).await,
+            Voter::Peer(address) => match call_peer(calls, address, &IpcMessage::text(question), from, timeout).await? {
+                IpcMessage::Text { content, .. } => Ok(content),
+                other => Err(anyhow!("answered with a {} message", other.kind())),
+            },
+        }
+    }
+}
+
+/// The most common answer and how many gave it; ties go to the one given first
+fn majority(answers: &[&str]) -> (String, usize) {
+    let mut counts: HashMap<String, usize> = HashMap::new();
+    for answer in answers {
+        *counts.entry(normalize_vote(answer)).or_default() += 1;
+    }
+    let mut best: Option<(&str, usize)> = None;
+    for answer in answers {
+        let count = counts[&normalize_vote(answer)];
+        if best.is_none_or(|(_, most)| count > most) {
+            best = Some((answer, count));
+        }
+    }
+    best.map(|(answer, count)| (answer.trim().to_string(), count)).unwrap_or_default()
+}
+
+async fn synthesize(judge: &LlmClient, question: &str, votes: &[Vote]) -> Result<String> {
+    let mut request = format!("Question:\n{}\n\n", question);
+    for (i, vote) in votes.iter().enumerate() {
+        if let Ok(answer) = &vote.answer {
+            request.push_str(&format!("Answer {}:\n{}\n\n", i + 1, answer.trim()));
+        }
+    }
+    request.push_str(
+        "Combine these independent answers into the single best answer. \
+         Prefer what most answers agree on, and keep any correct detail only one of them has.",
+    );
+    let system = "You aggregate answers from several agents into one final answer.".to_string();
+    judge.chat(vec![Message::user(request)], Some(system)).await
+}
+
+/// `ensemble_ask`
+pub(crate) fn register_ensemble_natives(engine: &mut Engine, ctx: &NativeContext) {
+    let NativeContext { meter, peers, calls, local_address, .. } = ctx;
+    // Ensembles: the same question to n voters (ENSEMBLE_MODELS, or else the peers in
+    // ENSEMBLE_GROUP, default all), aggregated into one answer
+    let ensemble_ask = {
+        let calls = calls.clone();
+        let peers = peers.clone();
+        let local_address = local_address.clone();
+        let meter = meter.clone();
+        move |question: &str, n: &str| -> String {
+            let Ok(n) = n.trim().parse::<usize>() else {
+                return format!("Error: '{}' is not a number of voters", n);
+            };
+            if let Err(e) = meter("ensemble_ask") {
+                return format!("Error: {}", e);
+            }
+            let group = std::env::var("ENSEMBLE_GROUP").unwrap_or_else(|_| "all".to_string());
+            let voters = peers.resolve_group(&group);
+            let calls = calls.clone();
+            let from = local_address.lock().unwrap().clone();
+            let question = question.to_string();
+            std::thread::spawn(move || {
+                let rt = tokio::runtime::Runtime::new().unwrap();
+                rt.block_on(async {
+                    let ensemble = match Ensemble::from_env(n, voters).await {
+                        Ok(ensemble) => ensemble.with_calls(calls, from),
+                        Err(e) => return format!("Error: {}", e),
+                    };
+                    info!("Asking an ensemble of {}: {}", ensemble.voters().len(), question);
+                    match ensemble.ask(&question).await {
+                        Ok(consensus) => consensus.render(),
+                        Err(e) => format!("Error: {}", e),
+                    }
+                })
+            }).join().unwrap_or_else(|_| "Thread panic".to_string())
+        }
+    };
+    engine.register_fn("ensemble_ask", ensemble_ask);
+}
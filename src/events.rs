@@ -0,0 +1,75 @@
+use std::future::Future;
+use std::sync::Arc;
+use crate::inbox::{Inbox, InboxMessage};
+use crate::message::ToolSafetyLevel;
+use crate::review::ToolReview;
+use crate::tools::ToolManager;
+
+/// Something only the operator may allow
+#[derive(Debug, Clone, Copy)]
+pub enum ApprovalRequest<'a> {
+    /// A `run_command` invocation
+    Command { command_line: &'a str },
+    /// A tool run the confirm policy covers, as the call the model made
+    Execution { call: &'a str, level: &'a ToolSafetyLevel },
+    /// A tool the model wrote, before it's installed
+    Install { review: &'a ToolReview },
+}
+
+/// Hooks for an application embedding the agent, to show what it does and answer its
+/// questions. Every method has a default that does nothing, so implement only the ones needed.
+/// They're called from the agent loop and tool runs, so keep them quick.
+pub trait AgentEvents: Send + Sync {
+    /// The model answered `input`, or failed to
+    fn on_llm_call(&self, _input: &str, _response: Result<&str, &str>) {}
+
+    /// A tool the model wrote was saved, or why it wasn't
+    fn on_tool_created(&self, _name: &str, _outcome: Result<&str, &str>) {}
+
+    /// A tool the model called ran, with its output or error
+    fn on_tool_executed(&self, _name: &str, _args: &[String], _result: Result<&str, &str>, _duration_ms: u64) {}
+
+    /// A peer's message landed in the inbox
+    fn on_message_received(&self, _message: &InboxMessage) {}
+
+    /// Allow `request`? None declines it: a host has to answer to let anything through.
+    fn on_approval_needed(&self, _request: &ApprovalRequest) -> Option<bool> {
+        None
+    }
+}
+
+/// Hooks that ignore everything
+pub struct NoEvents;
+
+impl AgentEvents for NoEvents {}
+
+/// Send `tools`' approvals for commands, tool runs and installs to `events`
+pub fn ask_for_approvals(tools: &ToolManager, events: Arc<dyn AgentEvents>) {
+    let commands = events.clone();
+    tools.set_command_approver(Arc::new(move |command_line: &str| {
+        commands.on_approval_needed(&ApprovalRequest::Command { command_line }).unwrap_or(false)
+    }));
+    let runs = events.clone();
+    tools.set_execution_approver(Arc::new(move |call: &str, level: &ToolSafetyLevel| {
+        runs.on_approval_needed(&ApprovalRequest::Execution { call, level }).unwrap_or(false)
+    }));
+    tools.set_install_approver(Arc::new(move |review: &ToolReview| {
+        events.on_approval_needed(&ApprovalRequest::Install { review }).unwrap_or(false)
+    }));
+}
+
+/// Pass each message that arrives in `inbox` from now on to `events`, until the process exits
+pub fn watch_inbox(inbox: Inbox, events: Arc<dyn AgentEvents>) -> impl Future<Output = ()> {
+    let mut seen = inbox.list().iter().map(|m| m.id).max().unwrap_or(0);
+    async move {
+        loop {
+            // Listed rather than unread: whoever answers messages may have read it already
+            let last = seen;
+            for message in inbox.list().into_iter().filter(|m| m.id > last) {
+                seen = message.id;
+                events.on_message_received(&message);
+            }
+            inbox.changed().await;
+        }
+    }
+}
@@ -0,0 +1,149 @@
+use anyhow::{anyhow, Result};
+use axum::http::StatusCode;
+use std::net::SocketAddr;
+use tonic::{Request, Response, Status};
+use crate::auth::{IpcAuth, Signature};
+use crate::ipc::{admit, bind_host, dispatch, host_port, IpcState, Message};
+use crate::message::{IpcMessage, ToolSafetyLevel};
+
+/// Types and client/server stubs generated from `proto/swarm.proto`
+pub mod proto {
+    tonic::include_proto!("swarm");
+}
+
+use proto::request_tool_reply::Outcome;
+use proto::swarm_server::{Swarm, SwarmServer};
+use tracing::info;
+
+/// Port `start_server` also serves gRPC on: `GRPC_PORT`, unset (the default) for none
+pub fn grpc_port() -> Option<u16> {
+    std::env::var("GRPC_PORT").ok().and_then(|p| p.trim().parse().ok())
+}
+
+impl From<ToolSafetyLevel> for proto::SafetyLevel {
+    fn from(level: ToolSafetyLevel) -> Self {
+        match level {
+            ToolSafetyLevel::Safe => proto::SafetyLevel::Safe,
+            ToolSafetyLevel::LowRisk => proto::SafetyLevel::LowRisk,
+            ToolSafetyLevel::MediumRisk => proto::SafetyLevel::MediumRisk,
+            ToolSafetyLevel::HighRisk => proto::SafetyLevel::HighRisk,
+        }
+    }
+}
+
+impl From<proto::SafetyLevel> for ToolSafetyLevel {
+    fn from(level: proto::SafetyLevel) -> Self {
+        match level {
+            proto::SafetyLevel::Safe => ToolSafetyLevel::Safe,
+            proto::SafetyLevel::LowRisk => ToolSafetyLevel::LowRisk,
+            proto::SafetyLevel::MediumRisk => ToolSafetyLevel::MediumRisk,
+            proto::SafetyLevel::HighRisk => ToolSafetyLevel::HighRisk,
+        }
+    }
+}
+
+/// The envelope for a request standing for `message`, signed when `IPC_SECRET` is set.
+/// The signature covers the message's JSON, so the server checks it like an HTTP post.
+pub fn envelope(message: &IpcMessage, from: Option<String>) -> Result<proto::Envelope> {
+    let mut payload = Message::new(message, from)?;
+    IpcAuth::from_env().sign(&mut payload);
+    Ok(proto::Envelope {
+        from: payload.from,
+        signature: payload.signature.map(|s| proto::Signature { agent: s.agent, timestamp: s.timestamp, hmac: s.hmac, key: s.key, agent_key: s.agent_key, agent_signature: s.agent_signature }),
+        traceparent: payload.traceparent,
+    })
+}
+
+/// The `Swarm` service over the same state as the HTTP server
+#[derive(Clone)]
+pub struct GrpcService {
+    state: IpcState,
+}
+
+impl GrpcService {
+    pub fn new(state: IpcState) -> Self {
+        Self { state }
+    }
+
+    /// Admit and dispatch `message` exactly as if it had been posted to `/message`
+    async fn handle(&self, remote: Option<SocketAddr>, envelope: Option<proto::Envelope>, message: IpcMessage) -> Result<String, Status> {
+        let envelope = envelope.unwrap_or_default();
+        let payload = Message {
+            content: message.to_json().map_err(|e| Status::internal(e.to_string()))?,
+            from: envelope.from,
+            context: None,
+            id: None,
+            reply_to: None,
+            signature: envelope.signature.map(|s| Signature { agent: s.agent, timestamp: s.timestamp, hmac: s.hmac, key: s.key, agent_key: s.agent_key, agent_signature: s.agent_signature }),
+            traceparent: envelope.traceparent,
+        };
+        let sender = admit(&self.state, remote.map(|a| a.ip()), &payload).map_err(|(status, reason)| match status {
+            StatusCode::UNAUTHORIZED => Status::unauthenticated(reason),
+            StatusCode::TOO_MANY_REQUESTS => Status::resource_exhausted(reason),
+            _ => Status::permission_denied(reason),
+        })?;
+        Ok(dispatch(&self.state, &sender, payload).await)
+    }
+}
+
+#[tonic::async_trait]
+impl Swarm for GrpcService {
+    async fn send_message(&self, request: Request<proto::SendMessageRequest>) -> Result<Response<proto::SendMessageReply>, Status> {
+        let remote = request.remote_addr();
+        let request = request.into_inner();
+        let received = self.handle(remote, request.envelope, IpcMessage::text(request.content)).await?;
+        Ok(Response::new(proto::SendMessageReply { received }))
+    }
+
+    async fn share_tool(&self, request: Request<proto::ShareToolRequest>) -> Result<Response<proto::SendMessageReply>, Status> {
+        let remote = request.remote_addr();
+        let request = request.into_inner();
+        let safety_level = request.safety_level().into();
+        let message = IpcMessage::tool_share(&request.name, &request.code, request.description, safety_level);
+        let received = self.handle(remote, request.envelope, message).await?;
+        Ok(Response::new(proto::SendMessageReply { received }))
+    }
+
+    async fn request_tool(&self, request: Request<proto::RequestToolRequest>) -> Result<Response<proto::RequestToolReply>, Status> {
+        let remote = request.remote_addr();
+        let request = request.into_inner();
+        let received = self.handle(remote, request.envelope, IpcMessage::tool_request(&request.name)).await?;
+        let outcome = match IpcMessage::from_json_or_text(&received) {
+            IpcMessage::ToolShare { name, code, description, safety_level, .. } => Outcome::Tool(proto::SharedTool {
+                name,
+                code,
+                description,
+                safety_level: proto::SafetyLevel::from(safety_level).into(),
+            }),
+            IpcMessage::ToolRefused { reason, .. } => Outcome::Refused(reason),
+            _ => return Err(Status::internal(received)),
+        };
+        Ok(Response::new(proto::RequestToolReply { outcome: Some(outcome) }))
+    }
+
+    async fn invoke_tool(&self, request: Request<proto::InvokeToolRequest>) -> Result<Response<proto::InvokeToolReply>, Status> {
+        let remote = request.remote_addr();
+        let request = request.into_inner();
+        let received = self.handle(remote, request.envelope, IpcMessage::tool_invoke(&request.name, request.args)).await?;
+        match IpcMessage::from_json_or_text(&received) {
+            IpcMessage::ToolOutput { name, result, error } => Ok(Response::new(proto::InvokeToolReply { name, result, error })),
+            _ => Err(Status::internal(received)),
+        }
+    }
+}
+
+/// Serve the `Swarm` service on `port` of the IPC bind host until `shutdown` resolves.
+/// Clients' `grpc-timeout` deadlines are enforced by the server, and requests are capped
+/// at `IPC_MAX_BODY_BYTES`.
+pub async fn serve_grpc(port: u16, state: IpcState, shutdown: impl std::future::Future<Output = ()>) -> Result<()> {
+    let addr = tokio::net::lookup_host(host_port(&bind_host(), port)).await?
+        .next()
+        .ok_or_else(|| anyhow!("Cannot resolve {}", bind_host()))?;
+    info!("gRPC server starting on {}", addr);
+    let max = state.limits.max_body_bytes;
+    tonic::transport::Server::builder()
+        .add_service(SwarmServer::new(GrpcService::new(state)).max_decoding_message_size(max))
+        .serve_with_shutdown(addr, shutdown)
+        .await?;
+    Ok(())
+}
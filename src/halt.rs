@@ -0,0 +1,81 @@
+use anyhow::{anyhow, Result};
+use std::sync::{Arc, Mutex};
+use crate::ipc::send_ipc_message;
+use crate::lineage::CloneRegistry;
+use crate::message::IpcMessage;
+
+/// Run when the switch trips, with the reason
+pub type HaltHook = Arc<dyn Fn(&str) + Send + Sync>;
+
+/// The emergency stop. Once tripped, tools don't run (scripts already running are aborted),
+/// tools can't be created or approved and the agent's server shuts down, until `resume`.
+#[derive(Clone, Default)]
+pub struct HaltSwitch {
+    reason: Arc<Mutex<Option<String>>>,
+    hooks: Arc<Mutex<Vec<HaltHook>>>,
+}
+
+impl HaltSwitch {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Trip the switch; false if it already was, in which case the hooks don't run again
+    pub fn halt(&self, reason: &str) -> bool {
+        {
+            let mut current = self.reason.lock().unwrap();
+            if current.is_some() {
+                return false;
+            }
+            *current = Some(reason.to_string());
+        }
+        let hooks = self.hooks.lock().unwrap().clone();
+        for hook in hooks {
+            hook(reason);
+        }
+        true
+    }
+
+    /// Reset the switch; false if it wasn't tripped
+    pub fn resume(&self) -> bool {
+        self.reason.lock().unwrap().take().is_some()
+    }
+
+    pub fn is_halted(&self) -> bool {
+        self.reason.lock().unwrap().is_some()
+    }
+
+    pub fn reason(&self) -> Option<String> {
+        self.reason.lock().unwrap().clone()
+    }
+
+    /// Err while halted
+    pub fn check(&self) -> Result<()> {
+        match self.reason() {
+            Some(reason) => Err(anyhow!("The agent is halted ({}); /resume to continue", reason)),
+            None => Ok(()),
+        }
+    }
+
+    pub fn on_halt(&self, hook: HaltHook) {
+        self.hooks.lock().unwrap().push(hook);
+    }
+}
+
+/// Send a signed `Halt` to every clone with an address, asking them to halt their own
+/// clones too. One line per clone reached or not.
+pub async fn halt_clones(clones: &CloneRegistry, reason: &str, from: Option<String>) -> Vec<String> {
+    let message = IpcMessage::Halt { reason: reason.to_string(), clones: true };
+    let mut report = Vec::new();
+    for clone in clones.list().unwrap_or_default() {
+        let Some(address) = clone.address else {
+            report.push(format!("{}: no known address, not halted", clone.dir));
+            continue;
+        };
+        match send_ipc_message(&address, &message, from.clone()).await {
+            Ok(reply) => report.push(format!("{} at {}: {}", clone.dir, address, reply)),
+            Err(e) => report.push(format!("{} at {}: error: {}", clone.dir, address, e)),
+        }
+    }
+    report
+}
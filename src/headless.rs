@@ -0,0 +1,130 @@
+use anyhow::{anyhow, Result};
+use serde::Serialize;
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+use crate::agent::Agent;
+use crate::events::{AgentEvents, NoEvents};
+use crate::runtime::{run_turn, AgentEvent};
+use crate::tools::ToolManager;
+
+/// Exit code when every task succeeded
+pub const EXIT_OK: i32 = 0;
+/// Exit code when a task failed
+pub const EXIT_FAILED: i32 = 1;
+/// Exit code for a bad invocation: no prompt, unreadable tasks file
+pub const EXIT_USAGE: i32 = 2;
+
+/// A tool the agent wrote or ran while working on a task
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ToolStep {
+    pub name: String,
+    /// `created` or `ran`
+    pub action: String,
+    pub args: Vec<String>,
+    pub ok: bool,
+    pub output: String,
+}
+
+/// One headless task and what came of it
+#[derive(Debug, Clone, Serialize)]
+pub struct RunOutcome {
+    pub prompt: String,
+    pub ok: bool,
+    /// The agent's final response
+    pub answer: String,
+    pub error: Option<String>,
+    pub tools: Vec<ToolStep>,
+    pub duration_ms: u64,
+}
+
+impl RunOutcome {
+    /// The answer, or the error for a failed task
+    pub fn render(&self) -> String {
+        match &self.error {
+            Some(error) if self.answer.is_empty() => format!("Error: {}", error),
+            Some(error) => format!("{}\nError: {}", self.answer, error),
+            None => self.answer.clone(),
+        }
+    }
+}
+
+/// Runs prompts through the agent loop without an operator: tools the model writes are saved,
+/// tools it calls are run and their output fed back, until it answers without a call
+pub struct Runner {
+    agent: Agent,
+    tools: ToolManager,
+    events: Arc<dyn AgentEvents>,
+    max_steps: usize,
+    fix_attempts: usize,
+}
+
+impl Runner {
+    pub fn new(agent: Agent, tools: ToolManager) -> Self {
+        Self { agent, tools, events: Arc::new(NoEvents), max_steps: 5, fix_attempts: 2 }
+    }
+
+    /// Limit how many rounds of tool calls one task may chain (default 5, `agent.max_steps` in the config)
+    pub fn with_max_steps(mut self, max_steps: usize) -> Self {
+        self.max_steps = max_steps;
+        self
+    }
+
+    /// How often a broken tool is sent back to the model for a fix (default 2, `agent.fix_attempts`)
+    pub fn with_fix_attempts(mut self, fix_attempts: usize) -> Self {
+        self.fix_attempts = fix_attempts;
+        self
+    }
+
+    /// Hooks that hear of each step as it happens
+    pub fn with_events(mut self, events: Arc<dyn AgentEvents>) -> Self {
+        self.events = events;
+        self
+    }
+
+    pub fn agent(&self) -> &Agent {
+        &self.agent
+    }
+
+    pub fn tools(&self) -> &ToolManager {
+        &self.tools
+    }
+
+    /// Work on `prompt` until the agent answers. Fails on a model error, or when the agent is
+    /// still calling tools after the step limit.
+    pub async fn run(&mut self, prompt: &str) -> RunOutcome {
+        let turn = run_turn(&mut self.agent, &mut self.tools, self.events.as_ref(), prompt, self.max_steps, self.fix_attempts).await;
+        let tools = turn.events.into_iter().filter_map(|event| match event {
+            AgentEvent::ToolCreated { name, ok, message } => Some(ToolStep { name, action: "created".to_string(), args: Vec::new(), ok, output: message }),
+            AgentEvent::ToolExecuted { name, args, ok, output, .. } => Some(ToolStep { name, action: "ran".to_string(), args, ok, output }),
+            AgentEvent::Response { .. } | AgentEvent::FinalAnswer { .. } => None,
+        }).collect();
+        RunOutcome { prompt: turn.input, ok: turn.error.is_none(), answer: turn.answer, error: turn.error, tools, duration_ms: turn.duration_ms }
+    }
+
+    /// Run each task on a fresh conversation, in order; tools created along the way stay
+    pub async fn run_batch(&mut self, tasks: &[String]) -> Vec<RunOutcome> {
+        let mut outcomes = Vec::new();
+        for task in tasks {
+            self.agent.clear_history();
+            outcomes.push(self.run(task).await);
+        }
+        outcomes
+    }
+}
+
+/// The tasks in a batch file, one per line; blank lines and `#` comments are skipped
+pub fn read_tasks(path: impl AsRef<Path>) -> Result<Vec<String>> {
+    let path = path.as_ref();
+    let content = fs::read_to_string(path).map_err(|e| anyhow!("Cannot read tasks file {:?}: {}", path, e))?;
+    Ok(content.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(str::to_string)
+        .collect())
+}
+
+/// `EXIT_OK` when every task succeeded, else `EXIT_FAILED`
+pub fn exit_code(outcomes: &[RunOutcome]) -> i32 {
+    if outcomes.iter().all(|o| o.ok) { EXIT_OK } else { EXIT_FAILED }
+}
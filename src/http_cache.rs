@@ -0,0 +1,248 @@
+use anyhow::Result;
+use reqwest::header::{ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED};
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tracing::{debug, info, warn};
+use crate::egress;
+use crate::politeness::Politeness;
+use rhai::Engine;
+use crate::tools::NativeContext;
+
+/// How long a page is served without asking the site again unless `HTTP_CACHE_TTL_SECS` says otherwise
+pub const DEFAULT_HTTP_CACHE_TTL_SECS: u64 = 3600;
+
+/// A page as it was last downloaded, with what the site said to revalidate it by
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CachedPage {
+    pub url: String,
+    /// Unix time (seconds) it was downloaded or last confirmed unchanged
+    pub fetched_at: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub etag: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_modified: Option<String>,
+    pub body: String,
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+/// Disk cache of fetched pages, one JSON file per URL named after its SHA-256. Fresh pages are
+/// served as they are; stale ones are revalidated with `If-None-Match`/`If-Modified-Since`.
+#[derive(Debug, Clone)]
+pub struct HttpCache {
+    dir: PathBuf,
+    /// How long a page is fresh; `None` keeps it fresh until a forced refresh
+    ttl: Option<Duration>,
+}
+
+impl HttpCache {
+    pub fn open(dir: impl AsRef<Path>, ttl: Option<Duration>) -> Result<Self> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir)?;
+        Ok(Self { dir, ttl })
+    }
+
+    /// On unless `HTTP_CACHE` is `false`/`0`; directory from `HTTP_CACHE_DIR` (default
+    /// `state/http_cache`), TTL from `HTTP_CACHE_TTL_SECS` (default 3600, 0 = no expiry)
+    pub fn from_env() -> Option<Self> {
+        let disabled = std::env::var("HTTP_CACHE")
+            .map(|v| matches!(v.to_lowercase().as_str(), "0" | "false" | "no"))
+            .unwrap_or(false);
+        if disabled {
+            return None;
+        }
+        let dir = std::env::var("HTTP_CACHE_DIR").unwrap_or_else(|_| "state/http_cache".to_string());
+        let ttl = std::env::var("HTTP_CACHE_TTL_SECS").ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(DEFAULT_HTTP_CACHE_TTL_SECS);
+        let ttl = if ttl == 0 { None } else { Some(Duration::from_secs(ttl)) };
+        match Self::open(&dir, ttl) {
+            Ok(cache) => Some(cache),
+            Err(e) => {
+                warn!("HTTP cache disabled, cannot open {}: {}", dir, e);
+                None
+            }
+        }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    fn entry_path(&self, url: &str) -> PathBuf {
+        let digest = Sha256::digest(url.as_bytes());
+        self.dir.join(format!("{}.json", digest.iter().map(|b| format!("{:02x}", b)).collect::<String>()))
+    }
+
+    /// The cached copy of `url`, fresh or not
+    pub fn get(&self, url: &str) -> Option<CachedPage> {
+        let content = fs::read_to_string(self.entry_path(url)).ok()?;
+        serde_json::from_str(&content).ok()
+    }
+
+    pub fn put(&self, page: &CachedPage) -> Result<()> {
+        // Write to a temp file first so concurrent readers never see a partial entry
+        let path = self.entry_path(&page.url);
+        let tmp = path.with_extension(format!("json.{}.tmp", std::process::id()));
+        fs::write(&tmp, serde_json::to_string(page)?)?;
+        fs::rename(&tmp, &path)?;
+        Ok(())
+    }
+
+    /// Whether `page` may be served without asking the site
+    pub fn is_fresh(&self, page: &CachedPage) -> bool {
+        self.ttl.is_none_or(|ttl| now_secs().saturating_sub(page.fetched_at) < ttl.as_secs())
+    }
+
+    /// Remove every entry, returning how many were deleted
+    pub fn clear(&self) -> Result<usize> {
+        let mut removed = 0;
+        for entry in fs::read_dir(&self.dir)?.flatten() {
+            if entry.path().extension().and_then(|e| e.to_str()) == Some("json") {
+                fs::remove_file(entry.path())?;
+                removed += 1;
+            }
+        }
+        Ok(removed)
+    }
+
+    /// The body of `url`: from the cache while fresh, else downloaded as `politeness` allows,
+    /// sending the cached validators so an unchanged page comes back as a bodiless 304.
+    /// `force_refresh` skips the cache and downloads the page in full.
+    pub async fn fetch(&self, politeness: &Politeness, url: &str, force_refresh: bool) -> Result<String> {
+        let cached = if force_refresh { None } else { self.get(url) };
+        if let Some(page) = cached.as_ref().filter(|page| self.is_fresh(page)) {
+            debug!("HTTP cache hit for {}", url);
+            return Ok(page.body.clone());
+        }
+        let client = politeness.client();
+        let _permit = politeness.admit(&client, url).await?;
+        let mut request = client.get(url);
+        if let Some(page) = &cached {
+            if let Some(etag) = &page.etag {
+                request = request.header(IF_NONE_MATCH, etag);
+            }
+            if let Some(last_modified) = &page.last_modified {
+                request = request.header(IF_MODIFIED_SINCE, last_modified);
+            }
+        }
+        let response = request.send().await?;
+        if response.status() == StatusCode::NOT_MODIFIED {
+            if let Some(mut page) = cached {
+                debug!("HTTP cache revalidated {}", url);
+                page.fetched_at = now_secs();
+                self.put_or_warn(&page);
+                return Ok(page.body);
+            }
+        }
+        let success = response.status().is_success();
+        let header = |name| response.headers().get(name).and_then(|v: &reqwest::header::HeaderValue| v.to_str().ok()).map(String::from);
+        let (etag, last_modified) = (header(ETAG), header(LAST_MODIFIED));
+        let body = response.text().await?;
+        // Error pages are passed on but not kept
+        if success {
+            self.put_or_warn(&CachedPage { url: url.to_string(), fetched_at: now_secs(), etag, last_modified, body: body.clone() });
+        }
+        Ok(body)
+    }
+
+    fn put_or_warn(&self, page: &CachedPage) {
+        if let Err(e) = self.put(page) {
+            warn!("Failed to cache {}: {}", page.url, e);
+        }
+    }
+}
+
+/// The raw bytes of `url` and its Content-Type, for documents that aren't text; not cached
+pub async fn fetch_bytes(politeness: &Politeness, url: &str) -> Result<(Vec<u8>, Option<String>)> {
+    let client = politeness.client();
+    let _permit = politeness.admit(&client, url).await?;
+    let response = client.get(url).send().await?.error_for_status()?;
+    let content_type = response.headers().get(reqwest::header::CONTENT_TYPE).and_then(|v| v.to_str().ok()).map(String::from);
+    Ok((response.bytes().await?.to_vec(), content_type))
+}
+
+/// The body of `url`, through `cache` when there is one
+pub async fn fetch_page(cache: Option<&HttpCache>, politeness: &Politeness, url: &str, force_refresh: bool) -> Result<String> {
+    match cache {
+        Some(cache) => cache.fetch(politeness, url, force_refresh).await,
+        None => {
+            let client = politeness.client();
+            let _permit = politeness.admit(&client, url).await?;
+            Ok(client.get(url).send().await?.text().await?)
+        }
+    }
+}
+
+/// `search` and `scrape_url`
+pub(crate) fn register_web_natives(engine: &mut Engine, ctx: &NativeContext) {
+    let NativeContext { audit, safety, meter, citations, politeness, .. } = ctx;
+    // Simple search mock (since implementing real search requires an API key)
+    // In a real app, we'd use reqwest to call Google/Bing/SerpApi
+    let citations_clone = citations.clone();
+    let meter_clone = meter.clone();
+    engine.register_fn("search", move |query: &str| -> String {
+        if let Err(e) = meter_clone("search") {
+            return format!("Error: {}", e);
+        }
+        info!("Searching for: {}", query);
+        let results = format!("Mock search results for '{}': \n1. Rust is a systems programming language.\n2. Rhai is an embedded scripting language.", query);
+        citations_clone.record(&format!("search: {}", query.trim()), "search", &results);
+        results
+    });
+
+    // Real Web Scraper, through the HTTP cache; scrape_url(url, true) skips the cache.
+    let safety_clone = safety.clone();
+    let audit_clone = audit.clone();
+    let http_cache = HttpCache::from_env();
+    let politeness_clone = politeness.clone();
+    let citations_clone = citations.clone();
+    let meter_clone = meter.clone();
+    let scrape = move |url: &str, force_refresh: bool| -> String {
+        if let Err(e) = egress::guard(&safety_clone.lock().unwrap(), url, "scrape_url", Some(&audit_clone)) {
+            return format!("Error: {}", e);
+        }
+        if let Err(e) = meter_clone("scrape_url") {
+            return format!("Error: {}", e);
+        }
+        info!("Scraping URL: {}", url);
+        // Rhai functions are sync, so the request runs on a runtime of its own
+        let url = url.to_string();
+        let cache = http_cache.clone();
+        let politeness = politeness_clone.clone();
+        let citations = citations_clone.clone();
+        std::thread::spawn(move || {
+            let rt = tokio::runtime::Runtime::new().unwrap();
+            match rt.block_on(fetch_page(cache.as_ref(), &politeness, &url, force_refresh)) {
+                Ok(text) => {
+                    let document = scraper::Html::parse_document(&text);
+                    let selector = scraper::Selector::parse("body").unwrap();
+                    if let Some(body) = document.select(&selector).next() {
+                        // Simple text extraction
+                        let text = body.text().collect::<Vec<_>>().join(" ")
+                            .split_whitespace().take(200).collect::<Vec<_>>().join(" "); // Limit to 200 words
+                        citations.record(&url, "scrape_url", &text);
+                        text
+                    } else {
+                        "No body found".to_string()
+                    }
+                }
+                Err(e) => format!("Error fetching URL: {}", e),
+            }
+        }).join().unwrap_or_else(|_| "Thread panic".to_string())
+    };
+    let scrape_clone = scrape.clone();
+    engine.register_fn("scrape_url", move |url: &str| -> String { scrape_clone(url, false) });
+    let scrape_clone = scrape.clone();
+    engine.register_fn("scrape_url", move |url: &str, force_refresh: bool| -> String { scrape_clone(url, force_refresh) });
+    // Tool calls pass their arguments as text
+    engine.register_fn("scrape_url", move |url: &str, force_refresh: &str| -> String {
+        scrape(url, matches!(force_refresh.trim().to_lowercase().as_str(), "true" | "1" | "yes" | "force_refresh"))
+    });
+}
@@ -0,0 +1,234 @@
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, Mutex};
+use tokio::sync::Notify;
+use tracing::warn;
+use crate::message::IpcMessage;
+use crate::registry::unix_now;
+use crate::threads::{thread_key, Direction};
+use rhai::Engine;
+use crate::tools::NativeContext;
+
+/// A text message another agent sent us
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct InboxMessage {
+    pub id: u64,
+    /// Sender's server address, as it claimed
+    pub from: Option<String>,
+    /// Sender identity from a verified signature
+    pub agent: Option<String>,
+    pub content: String,
+    /// Message id the sender is waiting on a reply to (see `call_peer`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub call_id: Option<String>,
+    /// The sender's thread this message belongs to (see `IpcMessage::threaded`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub thread_id: Option<String>,
+    /// Unix time (seconds) the message arrived
+    pub received_at: u64,
+    pub read: bool,
+}
+
+impl InboxMessage {
+    /// Who sent this, preferring the verified identity
+    pub fn sender(&self) -> &str {
+        self.agent.as_deref().or(self.from.as_deref()).unwrap_or("unknown")
+    }
+
+    /// The conversation this message is part of: its sender's thread key (see `thread_key`)
+    pub fn thread_key(&self) -> String {
+        thread_key(self.from.as_deref().unwrap_or(self.sender()), self.thread_id.as_deref())
+    }
+}
+
+#[derive(Default)]
+struct InboxInner {
+    messages: Vec<InboxMessage>,
+    next_id: u64,
+}
+
+/// Incoming text messages, shared between the IPC server and the agent loop
+#[derive(Clone, Default)]
+pub struct Inbox {
+    inner: Arc<Mutex<InboxInner>>,
+    notify: Arc<Notify>,
+}
+
+impl Inbox {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Store a message, returning its id
+    pub fn push(&self, from: Option<String>, agent: Option<String>, content: impl Into<String>) -> u64 {
+        self.store(from, agent, content.into(), None, None)
+    }
+
+    /// Store a message whose sender waits for a reply to `call_id`
+    pub fn push_call(&self, from: Option<String>, agent: Option<String>, content: impl Into<String>, call_id: String) -> u64 {
+        self.store(from, agent, content.into(), Some(call_id), None)
+    }
+
+    /// Store a message in the sender's thread `thread_id`, with the `call_id` it may wait on
+    pub fn push_threaded(&self, from: Option<String>, agent: Option<String>, content: impl Into<String>, call_id: Option<String>, thread_id: String) -> u64 {
+        self.store(from, agent, content.into(), call_id, Some(thread_id))
+    }
+
+    fn store(&self, from: Option<String>, agent: Option<String>, content: String, call_id: Option<String>, thread_id: Option<String>) -> u64 {
+        let mut inner = self.inner.lock().unwrap();
+        inner.next_id += 1;
+        let id = inner.next_id;
+        inner.messages.push(InboxMessage {
+            id,
+            from,
+            agent,
+            content,
+            call_id,
+            thread_id,
+            received_at: unix_now(),
+            read: false,
+        });
+        drop(inner);
+        self.notify.notify_waiters();
+        id
+    }
+
+    pub fn get(&self, id: u64) -> Option<InboxMessage> {
+        self.inner.lock().unwrap().messages.iter().find(|m| m.id == id).cloned()
+    }
+
+    /// Every message, oldest first
+    pub fn list(&self) -> Vec<InboxMessage> {
+        self.inner.lock().unwrap().messages.clone()
+    }
+
+    pub fn unread(&self) -> Vec<InboxMessage> {
+        self.inner.lock().unwrap().messages.iter().filter(|m| !m.read).cloned().collect()
+    }
+
+    /// Every message, grouped by conversation (see `InboxMessage::thread_key`): the threads in
+    /// the order their first message arrived, each oldest first
+    pub fn threads(&self) -> Vec<(String, Vec<InboxMessage>)> {
+        let mut threads: Vec<(String, Vec<InboxMessage>)> = Vec::new();
+        for message in self.list() {
+            let key = message.thread_key();
+            match threads.iter_mut().find(|(k, _)| *k == key) {
+                Some((_, messages)) => messages.push(message),
+                None => threads.push((key, vec![message])),
+            }
+        }
+        threads
+    }
+
+    pub fn unread_count(&self) -> usize {
+        self.inner.lock().unwrap().messages.iter().filter(|m| !m.read).count()
+    }
+
+    /// Returns false for unknown ids
+    pub fn mark_read(&self, id: u64) -> bool {
+        match self.inner.lock().unwrap().messages.iter_mut().find(|m| m.id == id) {
+            Some(message) => {
+                message.read = true;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Wait until a message arrives
+    pub async fn changed(&self) {
+        self.notify.notified().await;
+    }
+
+    /// One line per unread message with a preview of its content
+    pub fn render_unread(&self) -> String {
+        self.unread().iter()
+            .map(|m| {
+                let preview: String = m.content.chars().take(80).collect();
+                let ellipsis = if m.content.chars().count() > 80 { "..." } else { "" };
+                let waiting = if m.call_id.is_some() { " (awaiting reply)" } else { "" };
+                let thread = m.thread_id.as_deref().map(|t| format!(" in thread {}", t)).unwrap_or_default();
+                format!("#{} from {}{}{}: {}{}", m.id, m.sender(), thread, waiting, preview, ellipsis)
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+/// `check_inbox`, `read_message`, `mark_read` and `reply_message`
+pub(crate) fn register_inbox_natives(engine: &mut Engine, ctx: &NativeContext) {
+    let NativeContext { threads, inbox, local_address, .. } = ctx;
+    // Inbox: messages other agents sent to our server
+    let inbox_clone = inbox.clone();
+    engine.register_fn("check_inbox", move || -> String {
+        match inbox_clone.unread_count() {
+            0 => "No unread messages".to_string(),
+            n => format!("{} unread message(s):\n{}", n, inbox_clone.render_unread()),
+        }
+    });
+
+    let inbox_clone = inbox.clone();
+    engine.register_fn("read_message", move |id: &str| -> String {
+        let Ok(id) = id.trim().trim_start_matches('#').parse::<u64>() else {
+            return format!("Error: '{}' is not a message id", id);
+        };
+        match inbox_clone.get(id) {
+            Some(m) => {
+                let thread = m.thread_id.as_deref().map(|t| format!(" in thread {}", t)).unwrap_or_default();
+                format!("Message #{} from {}{}:\n{}", m.id, m.sender(), thread, m.content)
+            }
+            None => format!("Error: no message #{}", id),
+        }
+    });
+
+    let inbox_clone = inbox.clone();
+    engine.register_fn("mark_read", move |id: &str| -> String {
+        let Ok(id) = id.trim().trim_start_matches('#').parse::<u64>() else {
+            return format!("Error: '{}' is not a message id", id);
+        };
+        if inbox_clone.mark_read(id) {
+            format!("Message #{} marked as read", id)
+        } else {
+            format!("Error: no message #{}", id)
+        }
+    });
+
+    let inbox_clone = inbox.clone();
+    let threads_clone = threads.clone();
+    let address_clone = local_address.clone();
+    engine.register_fn("reply_message", move |id: &str, text: &str| -> String {
+        let Ok(id) = id.trim().trim_start_matches('#').parse::<u64>() else {
+            return format!("Error: '{}' is not a message id", id);
+        };
+        let Some(original) = inbox_clone.get(id) else {
+            return format!("Error: no message #{}", id);
+        };
+        let Some(peer) = original.from.clone() else {
+            return format!("Error: message #{} has no return address", id);
+        };
+        let from = address_clone.lock().unwrap().clone();
+        // The reply goes on in the thread the message came in
+        let thread_id = original.thread_id.clone();
+        let message = IpcMessage::threaded(text, thread_id.clone());
+        let to = peer.clone();
+        let sent = std::thread::spawn(move || {
+            let rt = tokio::runtime::Runtime::new().unwrap();
+            rt.block_on(async {
+                match &original.call_id {
+                    Some(call_id) => crate::ipc::send_reply(&to, call_id, &message, from).await,
+                    None => crate::ipc::send_ipc_message(&to, &message, from).await,
+                }
+            })
+        }).join();
+        match sent {
+            Ok(Ok(_)) => {
+                if let Err(e) = threads_clone.record_in(&peer, thread_id.as_deref(), Direction::Outgoing, text) {
+                    warn!("Failed to record message to {}: {}", peer, e);
+                }
+                inbox_clone.mark_read(id);
+                format!("Replied to message #{}", id)
+            }
+            Ok(Err(e)) => format!("Error replying to message #{}: {}", id, e),
+            Err(_) => "Thread panic".to_string(),
+        }
+    });
+}
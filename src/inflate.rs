@@ -0,0 +1,47 @@
+use anyhow::{anyhow, Result};
+use flate2::read::DeflateDecoder;
+use std::io::{ErrorKind, Read};
+
+/// The most one stream may decompress to, so a small deflate bomb can't exhaust memory
+pub const MAX_INFLATED: usize = 64 * 1024 * 1024;
+
+/// Read `reader` into `out` until it ends, fails or passes `MAX_INFLATED` bytes
+fn read_limited(mut reader: impl Read, out: &mut Vec<u8>) -> Result<()> {
+    let start = out.len();
+    let mut chunk = [0u8; 16 * 1024];
+    loop {
+        let n = match reader.read(&mut chunk) {
+            Ok(0) => return Ok(()),
+            Ok(n) => n,
+            Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(e) => return Err(anyhow!("invalid compressed data: {}", e)),
+        };
+        let room = MAX_INFLATED - (out.len() - start);
+        if n > room {
+            out.extend_from_slice(&chunk[..room]);
+            return Err(anyhow!("decompressed data exceeds {} bytes", MAX_INFLATED));
+        }
+        out.extend_from_slice(&chunk[..n]);
+    }
+}
+
+/// Decompress raw DEFLATE data into `out`. What was decoded before an error stays in `out`,
+/// which lets callers salvage truncated streams.
+pub fn inflate_into(data: &[u8], out: &mut Vec<u8>) -> Result<()> {
+    read_limited(DeflateDecoder::new(data), out)
+}
+
+/// Decompress raw DEFLATE data, as zip entries hold it
+pub fn inflate(data: &[u8]) -> Result<Vec<u8>> {
+    let mut out = Vec::new();
+    inflate_into(data, &mut out)?;
+    Ok(out)
+}
+
+/// Decompress zlib data, as PDF `FlateDecode` streams hold it (the checksum is not verified)
+pub fn zlib_decompress(data: &[u8]) -> Result<Vec<u8>> {
+    match data {
+        [cmf, flg, rest @ ..] if cmf & 0x0f == 8 && ((*cmf as u16) << 8 | *flg as u16).is_multiple_of(31) => inflate(rest),
+        _ => Err(anyhow!("not zlib data")),
+    }
+}
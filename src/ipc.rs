@@ -1,36 +1,511 @@
 use anyhow::Result;
 use axum::{
-    extract::State,
-    routing::post,
+    body::Body,
+    extract::{
+        ws::{Message as WsMessage, WebSocket, WebSocketUpgrade},
+        ConnectInfo, Request, State,
+    },
+    http::{header, StatusCode},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Extension,
     Router,
     Json,
 };
 use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::net::{IpAddr, SocketAddr};
+use std::path::PathBuf;
 use std::sync::Arc;
-use tokio::sync::Mutex;
-use crate::message::IpcMessage;
-use crate::tools::PendingTool;
+use std::time::{Duration, Instant};
+use futures_util::{SinkExt, StreamExt};
+use tokio::sync::{mpsc, oneshot, Mutex, Notify};
+use crate::audit::AuditLog;
+use crate::blackboard::Blackboard;
+use crate::egress;
+use crate::election::{Election, TAKING_OVER};
+use crate::encryption::{seal_to, unseal, AgentKeys};
+use crate::provenance::{check_provenance, signature_for_share, store_signature};
+use crate::quarantine::Quarantine;
+use crate::halt::{halt_clones, HaltSwitch};
+use crate::lineage::CloneRegistry;
+use crate::inbox::Inbox;
+use crate::limits::Limits;
+use crate::telemetry;
+use crate::metrics::metrics;
+use crate::auth::{AccessControl, IpcAuth, Sender, Signature};
+use crate::message::{IpcMessage, TaskAnnounce, TaskAward, TaskBid, TaskRequest, TaskResult, TaskStatus, ToolSafetyLevel};
+use crate::orchestrator::Member;
+use crate::registry::{address_key, broadcast, PeerInfo, PeerRegistry, PeerStatus, Subscriptions};
+use crate::safety::SafetyPolicy;
+use crate::tools::{answer_tool_request, find_tool_file, is_tool_name, list_tool_names, record_tool_decision, validate_tool_code, tool_catalog, InvokePolicy, PendingTool, SharePolicy, ToolManager};
+use crate::tool_history::commit_tool_or_warn;
+use crate::threads::{Direction, PeerThreads};
+use crate::transport::MqttTransport;
+use crate::trust::{TrustLevel, TrustStore};
 use std::sync::Mutex as StdMutex;
+use tracing::{info, info_span, warn, Instrument, Span};
+use rhai::Engine;
+use crate::tools::NativeContext;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Message {
     pub content: String,
+    /// Address of the sending agent's own server, used as its thread key
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub from: Option<String>,
+    /// Recent conversation with the receiver, as seen by the sender
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub context: Option<String>,
+    /// Set when the sender waits for a correlated reply (see `call_peer`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    /// The `id` of the message this one answers
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reply_to: Option<String>,
+    /// Present when the sender has `IPC_SECRET`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signature: Option<Signature>,
+    /// W3C trace context of the sending span, so the receiver's spans join its trace.
+    /// Not covered by the signature.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub traceparent: Option<String>,
+}
+
+impl Message {
+    /// An unsigned message carrying `message`, in the trace of the current span
+    pub fn new(message: &IpcMessage, from: Option<String>) -> Result<Self> {
+        Ok(Self {
+            content: message.to_json()?,
+            from,
+            context: None,
+            id: None,
+            reply_to: None,
+            signature: None,
+            traceparent: telemetry::traceparent(&Span::current()),
+        })
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MessageResponse {
     pub status: String,
     pub received: String,
+    /// The request's `id` when `received` already is the answer to it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reply_to: Option<String>,
+}
+
+impl MessageResponse {
+    pub fn ok(received: impl Into<String>) -> Self {
+        Self { status: "ok".to_string(), received: received.into(), reply_to: None }
+    }
+}
+
+#[derive(Default)]
+struct TaskQueueInner {
+    pending: VecDeque<TaskRequest>,
+    results: HashMap<String, TaskResult>,
+    /// Bids for the tasks this agent announced, by task id
+    bids: HashMap<String, Vec<TaskBid>>,
+}
+
+/// Tasks delegated to this agent, waiting for the local agent loop, and the results
+/// of tasks this agent delegated to others, and the bids for those it announced
+#[derive(Clone, Default)]
+pub struct TaskQueue {
+    inner: Arc<StdMutex<TaskQueueInner>>,
+    notify: Arc<Notify>,
+}
+
+impl TaskQueue {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&self, task: TaskRequest) {
+        self.inner.lock().unwrap().pending.push_back(task);
+        self.notify.notify_one();
+    }
+
+    pub fn try_next(&self) -> Option<TaskRequest> {
+        self.inner.lock().unwrap().pending.pop_front()
+    }
+
+    /// Wait for the next queued task
+    pub async fn next(&self) -> TaskRequest {
+        loop {
+            if let Some(task) = self.try_next() {
+                return task;
+            }
+            self.notify.notified().await;
+        }
+    }
+
+    pub fn pending(&self) -> usize {
+        self.inner.lock().unwrap().pending.len()
+    }
+
+    pub fn record_result(&self, result: TaskResult) {
+        self.inner.lock().unwrap().results.insert(result.id.clone(), result);
+    }
+
+    pub fn result(&self, id: &str) -> Option<TaskResult> {
+        self.inner.lock().unwrap().results.get(id).cloned()
+    }
+
+    /// Keep `bid`, replacing an earlier one from the same bidder
+    pub fn record_bid(&self, bid: TaskBid) {
+        let mut inner = self.inner.lock().unwrap();
+        let bids = inner.bids.entry(bid.task_id.clone()).or_default();
+        bids.retain(|b| address_key(&b.bidder) != address_key(&bid.bidder));
+        bids.push(bid);
+    }
+
+    /// The bids for task `id`, in the order they came
+    pub fn bids(&self, id: &str) -> Vec<TaskBid> {
+        self.inner.lock().unwrap().bids.get(id).cloned().unwrap_or_default()
+    }
+
+    /// The bid for this agent to answer `announce` with, from `me` with `skills` (tool and
+    /// capability names); None when it has none of the requirements or the task is past its
+    /// deadline
+    pub fn bid_for(&self, announce: &TaskAnnounce, me: &str, skills: &[String]) -> Option<TaskBid> {
+        if announce.task.is_expired() {
+            return None;
+        }
+        let matched = announce.requirements.iter()
+            .filter(|r| skills.iter().any(|s| s.eq_ignore_ascii_case(r.trim())))
+            .count();
+        let capability_match = match announce.requirements.len() {
+            0 => 1.0,
+            n => matched as f64 / n as f64,
+        };
+        (capability_match > 0.0).then(|| TaskBid {
+            task_id: announce.task.id.clone(),
+            bidder: me.to_string(),
+            cost_estimate: (self.pending() + 1) as f64,
+            capability_match,
+        })
+    }
+}
+
+/// How an announced task was given out: every bid, and who won
+#[derive(Debug, Clone)]
+pub struct Auction {
+    pub task_id: String,
+    pub bids: Vec<TaskBid>,
+    /// The best bid, if there was one
+    pub winner: Option<TaskBid>,
+    /// The winner's answer to the award, or why it couldn't be sent
+    pub award: Option<std::result::Result<String, String>>,
+}
+
+impl Auction {
+    pub fn render(&self) -> String {
+        let Some(winner) = &self.winner else {
+            return format!("No bids for task {}", self.task_id);
+        };
+        let mut lines = vec![match &self.award {
+            Some(Ok(_)) => format!("Awarded task {} to {}", self.task_id, address_key(&winner.bidder)),
+            Some(Err(e)) => format!("Error awarding task {} to {}: {}", self.task_id, address_key(&winner.bidder), e),
+            None => format!("Task {} not awarded", self.task_id),
+        }];
+        for bid in &self.bids {
+            lines.push(format!("- {}: cost {}, match {:.0}%", address_key(&bid.bidder), bid.cost_estimate, bid.capability_match * 100.0));
+        }
+        lines.join("\n")
+    }
+}
+
+/// Contract net: announce `announce` to `peers`, collect their bids (those answering in the reply,
+/// and any already sent to this agent's server and recorded in `tasks`), and award the task to the
+/// best bid (see `TaskBid::score`). The winner's `TaskResult` comes back like a delegated task's.
+pub async fn run_auction(tasks: &TaskQueue, peers: &[String], announce: &TaskAnnounce, from: Option<String>, timeout: Duration) -> Auction {
+    let report = broadcast(peers, &IpcMessage::TaskAnnounce(announce.clone()), from.clone(), timeout).await;
+    for delivery in &report.deliveries {
+        if let Ok(reply) = &delivery.outcome {
+            if let IpcMessage::TaskBid(mut bid) = IpcMessage::from_json_or_text(reply) {
+                // Where the announcement reached it, whatever it calls itself
+                bid.bidder = delivery.peer.clone();
+                if bid.task_id == announce.task.id {
+                    tasks.record_bid(bid);
+                }
+            }
+        }
+    }
+    let bids = tasks.bids(&announce.task.id);
+    let winner = bids.iter()
+        .fold(None::<&TaskBid>, |best, bid| match best {
+            Some(best) if best.score() >= bid.score() => Some(best),
+            _ => Some(bid),
+        })
+        .cloned();
+    let award = match &winner {
+        Some(winner) => {
+            info!("Awarding task {} to {}", announce.task.id, winner.bidder);
+            let award = IpcMessage::TaskAward(TaskAward { task: announce.task.clone() });
+            Some(send_ipc_message(&winner.bidder, &award, from).await.map_err(|e| e.to_string()))
+        }
+        None => None,
+    };
+    Auction { task_id: announce.task.id.clone(), bids, winner, award }
+}
+
+/// Callers of `call_peer` waiting for the reply to the message they sent, by message id
+#[derive(Clone, Default)]
+pub struct PendingCalls {
+    waiting: Arc<StdMutex<HashMap<String, oneshot::Sender<IpcMessage>>>>,
+}
+
+impl PendingCalls {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn register(&self, id: &str) -> oneshot::Receiver<IpcMessage> {
+        let (tx, rx) = oneshot::channel();
+        self.waiting.lock().unwrap().insert(id.to_string(), tx);
+        rx
+    }
+
+    fn cancel(&self, id: &str) {
+        self.waiting.lock().unwrap().remove(id);
+    }
+
+    /// Hand `message` to whoever waits on `reply_to`; false when nobody does (any more)
+    pub fn resolve(&self, reply_to: &str, message: IpcMessage) -> bool {
+        match self.waiting.lock().unwrap().remove(reply_to) {
+            Some(waiter) => waiter.send(message).is_ok(),
+            None => false,
+        }
+    }
+
+    /// Number of calls still waiting for a reply
+    pub fn pending(&self) -> usize {
+        self.waiting.lock().unwrap().len()
+    }
+}
+
+/// Turn an agent address (`127.0.0.1:9000`) or URL into its message endpoint
+pub fn message_url(address: &str) -> String {
+    let url = if address.starts_with("http://") || address.starts_with("https://") {
+        address.to_string()
+    } else {
+        format!("http://{}", address)
+    };
+    if url.ends_with("/message") {
+        url
+    } else {
+        format!("{}/message", url.trim_end_matches('/'))
+    }
+}
+
+/// Sign `payload` and send it to the agent at `address` over the transport that reaches it
+async fn post_message(address: &str, mut payload: Message, timeout: Option<Duration>) -> Result<MessageResponse> {
+    let kind = IpcMessage::from_json_or_text(&payload.content).kind();
+    crate::egress::guard(&SafetyPolicy::from_env()?, address, kind, AuditLog::from_env().ok().as_ref())?;
+    let span = info_span!("ipc_send", kind, to = %address_key(address));
+    payload.traceparent = telemetry::traceparent(&span).or(payload.traceparent);
+    IpcAuth::from_env().sign(&mut payload);
+    metrics().message_sent(kind);
+    let sent = match crate::transport::for_address(address) {
+        Ok(transport) => transport.send(address, &payload, timeout).instrument(span).await,
+        Err(e) => Err(e),
+    };
+    // Heartbeats would drown everything else
+    if kind != "Heartbeat" {
+        if let Ok(audit) = AuditLog::from_env() {
+            let outcome = match &sent {
+                Ok(response) => response.status.clone(),
+                Err(e) => format!("error: {}", e),
+            };
+            audit.record_or_warn("ipc_send", &format!("{} to {}", kind, address_key(address)), &outcome);
+        }
+    }
+    sent
+}
+
+/// Post a structured message to another agent, returning its `received` text
+pub async fn send_ipc_message(address: &str, message: &IpcMessage, from: Option<String>) -> Result<String> {
+    Ok(post_message(address, Message::new(message, from)?, None).await?.received)
+}
+
+/// Answer the message with id `reply_to` that the agent at `address` sent us
+pub async fn send_reply(address: &str, reply_to: &str, message: &IpcMessage, from: Option<String>) -> Result<String> {
+    let mut payload = Message::new(message, from)?;
+    payload.reply_to = Some(reply_to.to_string());
+    Ok(post_message(address, payload, None).await?.received)
+}
+
+/// Send `message` to the agent at `address` and wait up to `timeout` for its answer.
+/// Structured messages are answered in the HTTP response; text messages are answered later
+/// by the peer's agent (`reply`), which posts to `from`, so this agent's server must be up
+/// and sharing `calls` for those.
+pub async fn call_peer(
+    calls: &PendingCalls,
+    address: &str,
+    message: &IpcMessage,
+    from: Option<String>,
+    timeout: Duration,
+) -> Result<IpcMessage> {
+    let id = crate::message::message_id();
+    let mut payload = Message::new(message, from.clone())?;
+    payload.id = Some(id.clone());
+    let reply = calls.register(&id);
+    let started = std::time::Instant::now();
+
+    let response = match post_message(address, payload, Some(timeout)).await {
+        Ok(response) => response,
+        Err(e) => {
+            calls.cancel(&id);
+            return Err(e);
+        }
+    };
+    if response.reply_to.as_deref() == Some(id.as_str()) {
+        calls.cancel(&id);
+        return Ok(IpcMessage::from_json_or_text(&response.received));
+    }
+    if from.is_none() {
+        calls.cancel(&id);
+        return Err(anyhow::anyhow!("{} will answer later, but this agent has no server to receive the reply", address_key(address)));
+    }
+
+    match tokio::time::timeout(timeout.saturating_sub(started.elapsed()), reply).await {
+        Ok(Ok(answer)) => Ok(answer),
+        Ok(Err(_)) => Err(anyhow::anyhow!("Call to {} was abandoned", address_key(address))),
+        Err(_) => {
+            calls.cancel(&id);
+            Err(anyhow::anyhow!("No reply from {} within {:?}", address_key(address), timeout))
+        }
+    }
+}
+
+/// Work through delegated tasks with `member`, sending each result to the task's `reply_to`.
+/// Runs until the surrounding task is dropped. The member's tools are unattended from then on.
+pub async fn serve_tasks(tasks: TaskQueue, mut member: Member) {
+    member.tools_mut().set_unattended(true);
+    loop {
+        let task = tasks.next().await;
+        let span = info_span!("task", id = %task.id);
+        telemetry::continue_trace(&span, task.traceparent.as_deref());
+        serve_task(&tasks, &mut member, task).instrument(span).await;
+    }
+}
+
+/// Work on `task` and send its result to the delegating agent
+async fn serve_task(tasks: &TaskQueue, member: &mut Member, task: TaskRequest) {
+    info!("Working on task {}: {}", task.id, task.description);
+    let (status, output) = if task.is_expired() {
+        (TaskStatus::Expired, "Deadline passed before the task started".to_string())
+    } else {
+        let work = member.handle(&task.description);
+        let outcome = match task.remaining() {
+            Some(remaining) => tokio::time::timeout(remaining, work).await.ok(),
+            None => Some(work.await),
+        };
+        match outcome {
+            Some(Ok(output)) => (TaskStatus::Completed, output),
+            Some(Err(e)) => (TaskStatus::Failed, e.to_string()),
+            None => (TaskStatus::Expired, "Deadline passed before the task finished".to_string()),
+        }
+    };
+    info!("Task {} {:?}", task.id, status);
+
+    let result = TaskResult { id: task.id.clone(), status, output };
+    if let Some(reply_to) = &task.reply_to {
+        if let Err(e) = send_ipc_message(reply_to, &IpcMessage::TaskResult(result.clone()), None).await {
+            warn!("Failed to send result of task {} to {}: {}", task.id, reply_to, e);
+        }
+    }
+    tasks.record_result(result);
+}
+
+/// Answer text messages in `inbox` with `member`, as a worker with no operator: each reply goes
+/// back to its sender, to the waiting call when the message is one (see `call_peer`), and is
+/// recorded in the sender's thread. Each peer, and each thread with it, gets a conversation of
+/// its own with the model (see `Member::handle_in`). `from` is this agent's own address. Runs
+/// until the surrounding task is dropped. The member's tools are unattended from then on.
+pub async fn serve_inbox(inbox: Inbox, threads: PeerThreads, mut member: Member, from: Option<String>) {
+    member.tools_mut().set_unattended(true);
+    loop {
+        for message in inbox.unread() {
+            inbox.mark_read(message.id);
+            let Some(peer) = message.from.clone() else {
+                warn!("Message #{} from {} has no return address, skipping it", message.id, message.sender());
+                continue;
+            };
+            info!("Answering message #{} from {}", message.id, message.sender());
+            let answer = match member.handle_in(&message.thread_key(), &message.content).await {
+                Ok(answer) => answer,
+                Err(e) => format!("Error: {}", e),
+            };
+            let reply = IpcMessage::threaded(&answer, message.thread_id.clone());
+            let sent = match &message.call_id {
+                Some(call_id) => send_reply(&peer, call_id, &reply, from.clone()).await,
+                None => send_ipc_message(&peer, &reply, from.clone()).await,
+            };
+            match sent {
+                Ok(_) => {
+                    if let Err(e) = threads.record_in(&peer, message.thread_id.as_deref(), Direction::Outgoing, &answer) {
+                        warn!("Failed to record message to {}: {}", peer, e);
+                    }
+                }
+                Err(e) => warn!("Failed to answer message #{} from {}: {}", message.id, peer, e),
+            }
+        }
+        // Messages landing while we answered don't wake us, so look again every second
+        let _ = tokio::time::timeout(Duration::from_secs(1), inbox.changed()).await;
+    }
 }
 
 #[derive(Clone)]
 pub struct IpcState {
-    pub messages: Arc<Mutex<Vec<String>>>,
+    /// Text messages waiting for the agent
+    pub inbox: Inbox,
     pub pending_tools: Arc<StdMutex<Vec<PendingTool>>>,
+    pub threads: PeerThreads,
+    pub tasks: TaskQueue,
+    pub peers: PeerRegistry,
+    /// Where requested tools are looked up
+    pub tools_dir: PathBuf,
+    pub share_policy: SharePolicy,
+    pub auth: IpcAuth,
+    pub access: AccessControl,
+    pub sessions: WsSessions,
+    /// Our own `call_peer` calls, completed by replies arriving here
+    pub calls: PendingCalls,
+    /// Peers subscribed to this agent's topics, kept in the threads' state store
+    pub subscriptions: Subscriptions,
+    /// The swarm's shared key-value store, kept in the threads' state store
+    pub blackboard: Blackboard,
+    /// Who leads the swarm, as `Leader` messages say
+    pub election: Election,
+    /// Blocked peers are refused; Safe tools from trusted ones skip approval
+    pub trust: TrustStore,
+    /// Where received messages and tools are recorded; nothing is recorded without one
+    pub audit: Option<AuditLog>,
+    /// Tools it refuses are never installed without approval
+    pub safety: SafetyPolicy,
+    /// Tripped by signed `Halt` messages
+    pub halt: HaltSwitch,
+    /// Clones a cascading `Halt` is passed on to, kept in the threads' state store
+    pub clones: CloneRegistry,
+    /// Runs tools for peers' `ToolInvoke` messages; without one they are refused
+    pub tool_host: Option<Arc<ToolManager>>,
+    pub invoke_policy: InvokePolicy,
+    /// Body size and per-peer rate limits
+    pub limits: Limits,
+    /// How the agent describes itself on `GET /info`
+    pub identity: Option<PeerInfo>,
+    /// Opens tool code peers sealed to this agent's public key; without it sealed code is refused
+    pub keys: Option<AgentKeys>,
+    pub started: Instant,
 }
 
 impl IpcState {
-    pub fn new(pending_tools: Arc<StdMutex<Vec<PendingTool>>>) -> Self {
+    pub fn new(pending_tools: Arc<StdMutex<Vec<PendingTool>>>, threads: PeerThreads, tasks: TaskQueue, peers: PeerRegistry, tools_dir: PathBuf) -> Self {
         // Convert std::sync::Mutex to tokio::sync::Mutex for async usage if needed, 
         // or just wrap the std Mutex in Arc and use it.
         // Wait, PendingTool uses std::sync::Mutex in ToolManager.
@@ -43,32 +518,283 @@ impl IpcState {
         // Let's change IpcState definition to use std::sync::Mutex for pending_tools
         // to match ToolManager.
         Self {
-            messages: Arc::new(Mutex::new(Vec::new())),
+            inbox: Inbox::new(),
             pending_tools,
+            subscriptions: Subscriptions::new(threads.store().clone()),
+            blackboard: Blackboard::new(threads.store().clone()),
+            election: Election::default(),
+            trust: TrustStore::new(threads.store().clone()),
+            clones: CloneRegistry::new(threads.store().clone()),
+            threads,
+            tasks,
+            peers,
+            tools_dir,
+            share_policy: SharePolicy::from_env(),
+            auth: IpcAuth::from_env(),
+            access: AccessControl::from_env(),
+            sessions: WsSessions::default(),
+            calls: PendingCalls::new(),
+            audit: None,
+            safety: SafetyPolicy::default(),
+            halt: HaltSwitch::new(),
+            tool_host: None,
+            invoke_policy: InvokePolicy::from_env(),
+            limits: Limits::from_env(),
+            identity: None,
+            keys: None,
+            started: Instant::now(),
         }
     }
+
+    pub fn with_auth(mut self, auth: IpcAuth) -> Self {
+        self.auth = auth;
+        self
+    }
+
+    pub fn with_inbox(mut self, inbox: Inbox) -> Self {
+        self.inbox = inbox;
+        self
+    }
+
+    pub fn with_access(mut self, access: AccessControl) -> Self {
+        self.access = access;
+        self
+    }
+
+    pub fn with_calls(mut self, calls: PendingCalls) -> Self {
+        self.calls = calls;
+        self
+    }
+
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    pub fn with_identity(mut self, me: PeerInfo) -> Self {
+        self.identity = Some(me);
+        self
+    }
+
+    pub fn with_audit(mut self, audit: AuditLog) -> Self {
+        self.audit = Some(audit);
+        self
+    }
+
+    pub fn with_safety(mut self, safety: SafetyPolicy) -> Self {
+        self.safety = safety;
+        self
+    }
+
+    /// Decrypt tool code sealed to `keys`, and seal requested tools to peers' keys
+    pub fn with_keys(mut self, keys: AgentKeys) -> Self {
+        self.keys = Some(keys);
+        self
+    }
+
+    /// Share the agent's election, so the leader other agents announce here is the one its tools see
+    pub fn with_election(mut self, election: Election) -> Self {
+        self.election = election;
+        self
+    }
+
+    /// Share the agent's emergency stop, so a `Halt` received here stops its tools too
+    pub fn with_halt(mut self, halt: HaltSwitch) -> Self {
+        self.halt = halt;
+        self
+    }
+
+    /// Let peers run `tools`' tools, as far as `policy` allows
+    pub fn with_tool_host(mut self, tools: Arc<ToolManager>, policy: InvokePolicy) -> Self {
+        self.tool_host = Some(tools);
+        self.invoke_policy = policy;
+        self
+    }
+}
+
+fn refuse(status: StatusCode, reason: impl Into<String>) -> Response {
+    let body = MessageResponse { status: status.canonical_reason().unwrap_or("error").to_lowercase(), received: reason.into(), reply_to: None };
+    (status, Json(body)).into_response()
+}
+
+/// Count a message against its sender's rate limit: verified agents by name, everyone else by IP
+fn throttle(state: &IpcState, ip: Option<IpAddr>, sender: &Sender) -> std::result::Result<(), (StatusCode, String)> {
+    let key = match (sender, ip) {
+        (Sender::Verified(agent), _) => format!("agent {}", agent),
+        (Sender::Unsigned, Some(ip)) => ip.to_string(),
+        (Sender::Unsigned, None) => "unsigned".to_string(),
+    };
+    state.limits.check(&key).map_err(|wait| {
+        warn!("Rate limit hit by {}", key);
+        (StatusCode::TOO_MANY_REQUESTS, format!("rate limit exceeded for {}: retry in {}s", key, wait.as_secs().max(1)))
+    })
+}
+
+/// Verify `message`'s signature, check its sender against the allow/deny lists, count it
+/// against the sender's rate limit and refuse blocked peers
+pub(crate) fn admit(state: &IpcState, ip: Option<IpAddr>, message: &Message) -> std::result::Result<Sender, (StatusCode, String)> {
+    let sender = state.auth.verify(message).map_err(|e| {
+        warn!("Rejected message from {}: {}", message.from.as_deref().unwrap_or("unknown"), e);
+        (StatusCode::UNAUTHORIZED, e.to_string())
+    })?;
+    state.access.check(ip, &sender).map_err(|e| {
+        warn!("Refused connection: {}", e);
+        (StatusCode::FORBIDDEN, e.to_string())
+    })?;
+    throttle(state, ip, &sender)?;
+    let key = message.signature.as_ref().and_then(Signature::agent_fingerprint);
+    if state.trust.standing(&sender, key.as_deref(), message.from.as_deref()).ok() == Some(TrustLevel::Blocked) {
+        let who = match &sender {
+            Sender::Verified(agent) => agent.as_str(),
+            Sender::Unsigned => message.from.as_deref().unwrap_or("unknown"),
+        };
+        warn!("Refused message from blocked peer {}", who);
+        return Err((StatusCode::FORBIDDEN, format!("{} is blocked by this agent", who)));
+    }
+    Ok(sender)
+}
+
+/// Check a message's signature and the allow/deny lists before it reaches the handler,
+/// passing the verified `Sender` along in the request extensions
+async fn authorize(State(state): State<IpcState>, request: Request, next: Next) -> Response {
+    let ip = request.extensions().get::<ConnectInfo<SocketAddr>>().map(|info| info.0.ip());
+    let (parts, body) = request.into_parts();
+    let max = state.limits.max_body_bytes;
+    let Ok(bytes) = axum::body::to_bytes(body, max).await else {
+        return refuse(StatusCode::PAYLOAD_TOO_LARGE, format!("message larger than {} bytes", max));
+    };
+    // Malformed bodies are left for the JSON extractor to reject
+    let admitted = match serde_json::from_slice::<Message>(&bytes) {
+        Ok(message) => admit(&state, ip, &message),
+        Err(_) => state.access.check(ip, &Sender::Unsigned)
+            .map_err(|e| (StatusCode::FORBIDDEN, e.to_string()))
+            .and_then(|_| throttle(&state, ip, &Sender::Unsigned))
+            .map(|_| Sender::Unsigned),
+    };
+    let sender = match admitted {
+        Ok(sender) => sender,
+        Err((status, reason)) => return refuse(status, reason),
+    };
+    let mut request = Request::from_parts(parts, Body::from(bytes));
+    request.extensions_mut().insert(sender);
+    next.run(request).await
 }
 
 async fn handle_message(
     State(state): State<IpcState>,
+    Extension(sender): Extension<Sender>,
     Json(payload): Json<Message>,
 ) -> Json<MessageResponse> {
+    Json(respond(&state, &sender, payload).await)
+}
+
+/// Dispatch an admitted message. Everything but text is answered right here, so a caller
+/// waiting on the message's id gets `reply_to` set.
+async fn respond(state: &IpcState, sender: &Sender, payload: Message) -> MessageResponse {
+    let answered_inline = payload.reply_to.is_none()
+        && !matches!(IpcMessage::from_json_or_text(&payload.content), IpcMessage::Text { .. });
+    let id = payload.id.clone().filter(|_| answered_inline);
+    MessageResponse { reply_to: id, ..MessageResponse::ok(dispatch(state, sender, payload).await) }
+}
+
+/// Act on an admitted message, returning the reply text, and note it in the audit log
+pub(crate) async fn dispatch(state: &IpcState, sender: &Sender, payload: Message) -> String {
+    let kind = IpcMessage::from_json_or_text(&payload.content).kind();
+    let from = match sender {
+        Sender::Verified(agent) => agent.clone(),
+        Sender::Unsigned => format!("{} (unverified)", payload.from.as_deref().unwrap_or("unknown")),
+    };
+    metrics().message_received(kind);
+    let span = info_span!("ipc_request", kind, from = %from, id = payload.id.as_deref().unwrap_or_default());
+    telemetry::continue_trace(&span, payload.traceparent.as_deref());
+    let answer = handle_payload(state, sender, payload).instrument(span).await;
+    if let (Some(audit), false) = (&state.audit, kind == "Heartbeat") {
+        let outcome: String = answer.chars().take(200).collect();
+        audit.record_or_warn("ipc_receive", &format!("{} from {}", kind, from), &outcome);
+    }
+    answer
+}
+
+async fn handle_payload(state: &IpcState, sender: &Sender, payload: Message) -> String {
     // Try to parse as structured IpcMessage
     let ipc_msg = IpcMessage::from_json_or_text(&payload.content);
+
+    // The answer to one of our own calls goes to the caller rather than the usual handling
+    if let Some(reply_to) = &payload.reply_to {
+        if let (IpcMessage::Text { content, thread_id }, Some(from)) = (&ipc_msg, &payload.from) {
+            if let Err(e) = state.threads.record_in(from, thread_id.as_deref(), Direction::Incoming, content) {
+                warn!("Failed to record message from {}: {}", from, e);
+            }
+        }
+        if state.calls.resolve(reply_to, ipc_msg.clone()) {
+            info!("Received reply to {}", reply_to);
+            return format!("Reply to '{}' delivered", reply_to);
+        }
+    }
     
-    let response_text = match ipc_msg {
-        IpcMessage::ToolShare { name, code, description, safety_level } => {
-            println!("📦 Received ToolShare: {} (Safety: {:?})", name, safety_level);
-            
-            // Add to pending queue
+    match ipc_msg {
+        IpcMessage::ToolShare { name, code, description, safety_level, signature } => {
+            // The name becomes a file name under tools/
+            if !is_tool_name(&name) {
+                warn!("Refused ToolShare with invalid name {:?}", name);
+                return format!("Error: invalid tool name '{}'", name);
+            }
+            info!("Received ToolShare: {} (Safety: {:?})", name, safety_level);
+            let code = match unseal(state.keys.as_ref(), &code) {
+                Ok(code) => code,
+                Err(e) => {
+                    warn!("Cannot decrypt tool {}: {}", name, e);
+                    return format!("Error: cannot decrypt tool '{}': {}", name, e);
+                }
+            };
             let pending = PendingTool {
                 name: name.clone(),
                 code,
-                source_agent: "remote_agent".to_string(), // In future, extract from request
+                source_agent: match sender {
+                    Sender::Verified(agent) => agent.clone(),
+                    Sender::Unsigned => format!("{} (unverified)", payload.from.as_deref().unwrap_or("unknown")),
+                },
+                source_address: payload.from.clone(),
+                source_key: match sender {
+                    Sender::Verified(_) => payload.signature.as_ref().and_then(Signature::agent_fingerprint),
+                    Sender::Unsigned => None,
+                },
                 received_at: std::time::SystemTime::now(),
                 description,
                 safety_level,
+                signature,
+                security_review: None,
             };
+            // A tampered copy, or one replacing a tool its author signed, never reaches the queue
+            if let Err(e) = check_provenance(&state.tools_dir, &name, &pending.code, pending.signature.as_ref()) {
+                warn!("Refused tool {}: {}", name, e);
+                Quarantine::new(&state.tools_dir).add_or_warn(&pending, &format!("refused: {}", e));
+                return format!("Error: tool '{}' refused: {}", name, e);
+            }
+            // Judge the code ourselves rather than trusting the sender's rating
+            let trusted = state.trust.standing(sender, pending.source_key.as_deref(), payload.from.as_deref()).ok() == Some(TrustLevel::Trusted);
+            if trusted && validate_tool_code(&pending.code, &state.safety) == ToolSafetyLevel::Safe && state.safety.refusal(&pending.code).is_none()
+                && find_tool_file(&state.tools_dir, &name).is_none() {
+                let installed = std::fs::write(state.tools_dir.join(format!("{}.rhai", name)), &pending.code)
+                    .and_then(|_| store_signature(&state.tools_dir, &name, pending.signature.as_ref()).map_err(std::io::Error::other));
+                if let Some(audit) = &state.audit {
+                    let outcome = match &installed {
+                        Ok(()) => "auto-approved (trusted sender)".to_string(),
+                        Err(e) => format!("error: {}", e),
+                    };
+                    record_tool_decision(audit, "approve_tool", &pending, &outcome);
+                }
+                match installed {
+                    Ok(()) => {
+                        commit_tool_or_warn(&state.tools_dir, &name, &format!("approve_tool {} (Safe) from {} (trusted sender)", name, pending.origin()));
+                        return format!("Tool '{}' installed (trusted sender)", name);
+                    }
+                    Err(e) => warn!("Failed to install tool '{}' from a trusted sender: {}", name, e),
+                }
+            }
+            if let Some(audit) = &state.audit {
+                record_tool_decision(audit, "tool_received", &pending, "queued");
+            }
             
             if let Ok(mut tools) = state.pending_tools.lock() {
                 tools.push(pending);
@@ -77,37 +803,997 @@ async fn handle_message(
                 "Error: Could not lock tool queue".to_string()
             }
         },
-        IpcMessage::Text { content } => {
-            println!("📨 Received message: {}", content);
-            // Store the message
-            let mut messages = state.messages.lock().await;
-            messages.push(content.clone());
+        IpcMessage::Text { content, thread_id } => {
+            info!("Received message: {}", content);
+            if let Some(from) = &payload.from {
+                if let Err(e) = state.threads.record_in(from, thread_id.as_deref(), Direction::Incoming, &content) {
+                    warn!("Failed to record message from {}: {}", from, e);
+                }
+            }
+            let agent = match sender {
+                Sender::Verified(agent) => Some(agent.clone()),
+                Sender::Unsigned => None,
+            };
+            match (thread_id, &payload.id) {
+                (Some(thread_id), call_id) => state.inbox.push_threaded(payload.from.clone(), agent, content.clone(), call_id.clone(), thread_id),
+                (None, Some(id)) => state.inbox.push_call(payload.from.clone(), agent, content.clone(), id.clone()),
+                (None, None) => state.inbox.push(payload.from.clone(), agent, content.clone()),
+            };
             content
         },
         IpcMessage::ToolRequest { name } => {
-            println!("❓ Received request for tool: {}", name);
-            format!("Request for '{}' received (auto-response not implemented)", name)
+            info!("Received request for tool: {}", name);
+            let mut answer = answer_tool_request(&state.tools_dir, &name, &state.share_policy, &state.safety);
+            // Only the requester can read the code if it advertised a key
+            let key = payload.from.as_deref().and_then(|from| state.peers.get(&address_key(from))).and_then(|p| p.public_key);
+            if let IpcMessage::ToolShare { code, signature, .. } = &mut answer {
+                *signature = signature_for_share(&state.tools_dir, &name, code, state.keys.as_ref());
+                match seal_to(key.as_deref(), code) {
+                    Ok(sealed) => *code = sealed,
+                    Err(e) => answer = IpcMessage::tool_refused(&name, format!("cannot encrypt for the requester: {}", e)),
+                }
+            }
+            match &answer {
+                IpcMessage::ToolShare { safety_level, .. } => info!("Sharing tool {} (Safety: {:?})", name, safety_level),
+                IpcMessage::ToolRefused { reason, .. } => warn!("Refused tool {}: {}", name, reason),
+                _ => {}
+            }
+            answer.to_json().unwrap_or_else(|e| format!("Error: {}", e))
+        }
+        IpcMessage::ToolRefused { name, reason } => {
+            warn!("Peer refused tool {}: {}", name, reason);
+            format!("Refusal for '{}' noted", name)
+        }
+        IpcMessage::TaskRequest(mut task) => {
+            info!("Received task {}: {}", task.id, task.description);
+            if task.is_expired() {
+                format!("Task '{}' rejected: deadline already passed", task.id)
+            } else {
+                let id = task.id.clone();
+                task.traceparent = telemetry::traceparent(&Span::current());
+                state.tasks.push(task);
+                format!("Task '{}' queued", id)
+            }
+        }
+        IpcMessage::TaskAnnounce(announce) => {
+            info!("Task {} announced: {}", announce.task.id, announce.task.description);
+            // What it can do: its tools as they are now, and the capabilities it announces
+            let mut skills = list_tool_names(&state.tools_dir);
+            skills.extend(state.identity.iter().flat_map(|me| me.capabilities.iter().cloned()));
+            let me = state.identity.as_ref().map(|me| me.address.as_str()).unwrap_or_default();
+            match state.tasks.bid_for(&announce, me, &skills).filter(|_| !state.halt.is_halted()) {
+                Some(bid) => IpcMessage::TaskBid(bid).to_json().unwrap_or_else(|e| format!("Error: {}", e)),
+                None => format!("No bid for task '{}'", announce.task.id),
+            }
         }
+        IpcMessage::TaskBid(mut bid) => {
+            // The address it sent from is the one the award goes to
+            if let Some(from) = &payload.from {
+                bid.bidder = from.clone();
+            }
+            info!("Bid for task {} from {}", bid.task_id, bid.bidder);
+            let id = bid.task_id.clone();
+            state.tasks.record_bid(bid);
+            format!("Bid for task '{}' recorded", id)
+        }
+        IpcMessage::TaskAward(TaskAward { mut task }) => {
+            info!("Awarded task {}: {}", task.id, task.description);
+            if task.is_expired() {
+                format!("Task '{}' rejected: deadline already passed", task.id)
+            } else {
+                let id = task.id.clone();
+                task.traceparent = telemetry::traceparent(&Span::current());
+                state.tasks.push(task);
+                format!("Task '{}' accepted", id)
+            }
+        }
+        IpcMessage::TaskResult(result) => {
+            info!("Received result for task {}: {:?}", result.id, result.status);
+            let id = result.id.clone();
+            state.tasks.record_result(result);
+            format!("Result for task '{}' recorded", id)
+        }
+        IpcMessage::Announce(peer) => {
+            if state.peers.upsert(peer.clone()) {
+                info!("Peer {} announced itself at {}", peer.name, peer.address);
+            }
+            // Acting as a registry: tell the newcomer who else is around
+            serde_json::to_string(&state.peers.list()).unwrap_or_else(|_| "[]".to_string())
+        }
+        IpcMessage::Heartbeat { address } => {
+            state.peers.touch(&address);
+            "alive".to_string()
+        }
+        IpcMessage::Election { address } => {
+            state.peers.touch(&address);
+            // Bully: an election by a lower agent is taken over, and won unless a higher one answers
+            match state.identity.as_ref().map(|me| address_key(&me.address)) {
+                Some(me) if me > address_key(&address) => {
+                    info!("Election by {}; taking it over", address);
+                    let election = state.election.clone();
+                    let peers = state.peers.clone();
+                    tokio::spawn(async move { election.run(&me, &peers).await });
+                    TAKING_OVER.to_string()
+                }
+                _ => "No objection".to_string(),
+            }
+        }
+        IpcMessage::Leader { address } => {
+            state.peers.touch(&address);
+            match state.identity.as_ref().map(|me| address_key(&me.address)) {
+                // A lower agent claimed it while this one wasn't answering: elect again
+                Some(me) if me > address_key(&address) => {
+                    info!("{} claims leadership; holding a new election", address);
+                    let reply = format!("Election called: {} outranks {}", me, address_key(&address));
+                    let election = state.election.clone();
+                    let peers = state.peers.clone();
+                    tokio::spawn(async move { election.run(&me, &peers).await });
+                    reply
+                }
+                _ => {
+                    info!("{} is the leader", address);
+                    state.election.set_leader(&address);
+                    format!("Leader is {}", address_key(&address))
+                }
+            }
+        }
+        IpcMessage::Subscribe { topic, address } => match state.subscriptions.add_subscriber(&topic, &address) {
+            Ok(true) => {
+                info!("{} subscribed to '{}'", address, topic);
+                format!("Subscribed {} to '{}'", address, topic)
+            }
+            Ok(false) => format!("{} is already subscribed to '{}'", address, topic),
+            Err(e) => format!("Error: could not save subscription: {}", e),
+        },
+        IpcMessage::Unsubscribe { topic, address } => match state.subscriptions.remove_subscriber(&topic, &address) {
+            Ok(true) => {
+                info!("{} unsubscribed from '{}'", address, topic);
+                format!("Unsubscribed {} from '{}'", address, topic)
+            }
+            Ok(false) => format!("{} was not subscribed to '{}'", address, topic),
+            Err(e) => format!("Error: could not save subscription: {}", e),
+        },
+        IpcMessage::Event { topic, payload: event } => {
+            info!("Event on '{}': {}", topic, event);
+            let agent = match sender {
+                Sender::Verified(agent) => Some(agent.clone()),
+                Sender::Unsigned => None,
+            };
+            state.inbox.push(payload.from.clone(), agent, format!("[event {}] {}", topic, event));
+            format!("Event on '{}' received", topic)
+        }
+        IpcMessage::BlackboardUpdate(entry) => {
+            let key = entry.key.clone();
+            let value = entry.value.clone();
+            let writer = entry.writer.clone();
+            match state.blackboard.merge(entry) {
+                Ok(true) => {
+                    info!("Blackboard '{}' set by {}", key, writer);
+                    if state.blackboard.is_watched(&key).unwrap_or(false) {
+                        let agent = match sender {
+                            Sender::Verified(agent) => Some(agent.clone()),
+                            Sender::Unsigned => None,
+                        };
+                        state.inbox.push(payload.from.clone(), agent, format!("[blackboard {}] {}", key, value));
+                    }
+                    format!("Blackboard '{}' updated", key)
+                }
+                Ok(false) => format!("Blackboard '{}' already up to date", key),
+                Err(e) => format!("Error updating blackboard: {}", e),
+            }
+        }
+        IpcMessage::BlackboardSync { entries } => {
+            let received = entries.len();
+            let mut updated = 0;
+            for entry in entries {
+                match state.blackboard.merge(entry) {
+                    Ok(true) => updated += 1,
+                    Ok(false) => {}
+                    Err(e) => warn!("Failed to merge blackboard entry: {}", e),
+                }
+            }
+            info!("Blackboard sync: {} of {} entries updated", updated, received);
+            // Answer with our copy, so the sender catches up too
+            match state.blackboard.entries() {
+                Ok(entries) => IpcMessage::BlackboardSync { entries }.to_json().unwrap_or_else(|e| format!("Error: {}", e)),
+                Err(e) => format!("Error reading blackboard: {}", e),
+            }
+        }
+        IpcMessage::ToolInvoke { name, args } => {
+            info!("Peer {} invokes tool {}", payload.from.as_deref().unwrap_or("unknown"), name);
+            let output = match &state.tool_host {
+                Some(tools) => {
+                    let (tools, policy, tool) = (tools.clone(), state.invoke_policy.clone(), name.clone());
+                    // Tools block (and some start their own runtime), so keep them off the server's workers
+                    tokio::task::spawn_blocking(move || tools.invoke_for_peer(&tool, args, &policy)).await
+                        .unwrap_or_else(|e| IpcMessage::tool_output(&name, Err(format!("tool crashed: {}", e))))
+                }
+                None => IpcMessage::tool_output(&name, Err("this agent does not run tools for peers".to_string())),
+            };
+            if let IpcMessage::ToolOutput { error: Some(error), .. } = &output {
+                warn!("Tool {} failed for peer: {}", name, error);
+            }
+            output.to_json().unwrap_or_else(|e| format!("Error: {}", e))
+        }
+        IpcMessage::ToolOutput { name, .. } => {
+            info!("Received output of tool {}", name);
+            format!("Output of '{}' noted", name)
+        }
+        IpcMessage::Halt { reason, clones } => {
+            // Anyone can claim to be a peer; only the swarm key may stop us
+            let Sender::Verified(agent) = sender else {
+                return "Halt refused: halts must be signed with the swarm key".to_string();
+            };
+            info!("Halt from {}: {}", agent, reason);
+            if !state.halt.halt(&format!("{} (halted by {})", reason, agent)) {
+                return "Already halted".to_string();
+            }
+            if let Some(audit) = &state.audit {
+                audit.record_or_warn("halt", &format!("by {}: {}", agent, reason), if clones { "halted, with clones" } else { "halted" });
+            }
+            let mut reply = format!("Halted: {}", reason);
+            if clones {
+                let from = state.identity.as_ref().map(|me| me.address.clone());
+                for line in halt_clones(&state.clones, &reason, from).await {
+                    reply.push_str(&format!("\n{}", line));
+                }
+            }
+            reply
+        }
+    }
+}
+
+/// A frame on the `/ws` channel. Either side may send `Message` frames; the other answers
+/// each with a `Response` carrying the same id.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum WsFrame {
+    Message { id: u64, message: Message },
+    Response { id: u64, response: MessageResponse },
+}
+
+/// Open `/ws` sessions on this server, keyed by the peer's address, so the agent can push
+/// messages to peers that connected to it
+#[derive(Clone, Default)]
+pub struct WsSessions {
+    sessions: Arc<StdMutex<HashMap<String, mpsc::UnboundedSender<WsMessage>>>>,
+    next_id: Arc<std::sync::atomic::AtomicU64>,
+}
+
+impl WsSessions {
+    fn register(&self, peer: &str, frames: mpsc::UnboundedSender<WsMessage>) {
+        self.sessions.lock().unwrap().insert(address_key(peer), frames);
+    }
+
+    fn unregister(&self, peer: &str, frames: &mpsc::UnboundedSender<WsMessage>) {
+        let mut sessions = self.sessions.lock().unwrap();
+        // A reconnect may already have replaced this session
+        if sessions.get(&address_key(peer)).map(|f| f.same_channel(frames)).unwrap_or(false) {
+            sessions.remove(&address_key(peer));
+        }
+    }
+
+    /// Addresses of the peers with an open session
+    pub fn peers(&self) -> Vec<String> {
+        let mut peers: Vec<String> = self.sessions.lock().unwrap().keys().cloned().collect();
+        peers.sort();
+        peers
+    }
+
+    /// Send `message` down `peer`'s session; false when it has none
+    pub fn push(&self, peer: &str, message: Message) -> bool {
+        let id = self.next_id.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        let Ok(text) = serde_json::to_string(&WsFrame::Message { id, message }) else {
+            return false;
+        };
+        match self.sessions.lock().unwrap().get(&address_key(peer)) {
+            Some(frames) => frames.send(WsMessage::Text(text)).is_ok(),
+            None => false,
+        }
+    }
+
+    /// Close `peer`'s session; its client will reconnect
+    pub fn disconnect(&self, peer: &str) -> bool {
+        match self.sessions.lock().unwrap().remove(&address_key(peer)) {
+            Some(frames) => frames.send(WsMessage::Close(None)).is_ok(),
+            None => false,
+        }
+    }
+}
+
+/// What `GET /info` tells operators and peers about this agent
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AgentInfo {
+    pub name: String,
+    pub version: String,
+    pub uptime_secs: u64,
+    /// Where peers reach this agent, once its server advertised an address
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub address: Option<String>,
+    #[serde(default)]
+    pub capabilities: Vec<String>,
+}
+
+/// Liveness probe: always open, so load balancers need no secret
+async fn handle_health() -> Json<serde_json::Value> {
+    Json(serde_json::json!({ "status": "ok" }))
+}
+
+/// Introspection is unsigned, so only IP rules and the rate limit apply
+fn admit_unsigned(state: &IpcState, connect: Option<ConnectInfo<SocketAddr>>) -> std::result::Result<(), (StatusCode, String)> {
+    let ip = connect.map(|info| info.0.ip());
+    state.access.check(ip, &Sender::Unsigned)
+        .map_err(|e| (StatusCode::FORBIDDEN, e.to_string()))
+        .and_then(|_| throttle(state, ip, &Sender::Unsigned))
+}
+
+async fn handle_info(State(state): State<IpcState>, connect: Option<ConnectInfo<SocketAddr>>) -> Response {
+    if let Err((status, reason)) = admit_unsigned(&state, connect) {
+        return refuse(status, reason);
+    }
+    let identity = state.identity.clone();
+    let info = AgentInfo {
+        name: identity.as_ref().map(|me| me.name.clone())
+            .unwrap_or_else(|| std::env::var("AGENT_NAME").unwrap_or_else(|_| "Swarm Thing".to_string())),
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        uptime_secs: state.started.elapsed().as_secs(),
+        address: identity.as_ref().map(|me| me.address.clone()),
+        capabilities: identity.map(|me| me.capabilities).unwrap_or_default(),
     };
-    
-    Json(MessageResponse {
-        status: "ok".to_string(),
-        received: response_text,
-    })
+    Json(info).into_response()
 }
 
-pub async fn start_http_server(port: u16, pending_tools: Arc<StdMutex<Vec<PendingTool>>>) -> Result<()> {
-    let state = IpcState::new(pending_tools);
-    
-    let app = Router::new()
+async fn handle_tools(State(state): State<IpcState>, connect: Option<ConnectInfo<SocketAddr>>) -> Response {
+    if let Err((status, reason)) = admit_unsigned(&state, connect) {
+        return refuse(status, reason);
+    }
+    Json(tool_catalog(&state.tools_dir, &state.share_policy, &state.safety)).into_response()
+}
+
+/// Prometheus scrape target; unsigned like `/info`
+async fn handle_metrics(State(state): State<IpcState>, connect: Option<ConnectInfo<SocketAddr>>) -> Response {
+    if let Err((status, reason)) = admit_unsigned(&state, connect) {
+        return refuse(status, reason);
+    }
+    let pending_tools = state.pending_tools.lock().map(|pending| pending.len()).unwrap_or_default();
+    metrics().set_pending(pending_tools, state.tasks.pending());
+    ([(header::CONTENT_TYPE, "text/plain; version=0.0.4")], metrics().render()).into_response()
+}
+
+async fn handle_ws(
+    State(state): State<IpcState>,
+    connect: Option<ConnectInfo<SocketAddr>>,
+    ws: WebSocketUpgrade,
+) -> Response {
+    let ip = connect.map(|info| info.0.ip());
+    // Identity is only known per message, so only IP denials apply before the upgrade
+    if state.access.deny.iter().any(|rule| rule.matches(ip, &Sender::Unsigned)) {
+        return refuse(StatusCode::FORBIDDEN, "denied");
+    }
+    ws.max_message_size(state.limits.max_body_bytes).on_upgrade(move |socket| ws_session(state, ip, socket))
+}
+
+/// Serve one `/ws` connection: every incoming message is admitted and dispatched like a POST
+async fn ws_session(state: IpcState, ip: Option<IpAddr>, socket: WebSocket) {
+    let (mut sink, mut stream) = socket.split();
+    let (frames, mut outgoing) = mpsc::unbounded_channel::<WsMessage>();
+    let writer = tokio::spawn(async move {
+        while let Some(frame) = outgoing.recv().await {
+            let closing = matches!(frame, WsMessage::Close(_));
+            if sink.send(frame).await.is_err() || closing {
+                break;
+            }
+        }
+    });
+
+    let mut peer: Option<String> = None;
+    while let Some(Ok(frame)) = stream.next().await {
+        let text = match frame {
+            WsMessage::Text(text) => text,
+            WsMessage::Close(_) => break,
+            _ => continue,
+        };
+        match serde_json::from_str::<WsFrame>(&text) {
+            Ok(WsFrame::Message { id, message }) => {
+                let from = message.from.clone();
+                let response = match admit(&state, ip, &message) {
+                    Ok(sender) => {
+                        if let (None, Some(from)) = (&peer, from) {
+                            info!("WebSocket session opened by {}", from);
+                            state.sessions.register(&from, frames.clone());
+                            peer = Some(from);
+                        }
+                        respond(&state, &sender, message).await
+                    }
+                    Err((status, reason)) => MessageResponse {
+                        status: status.canonical_reason().unwrap_or("error").to_lowercase(),
+                        received: reason,
+                        reply_to: None,
+                    },
+                };
+                if let Ok(text) = serde_json::to_string(&WsFrame::Response { id, response }) {
+                    let _ = frames.send(WsMessage::Text(text));
+                }
+            }
+            // Acknowledgements of pushed messages
+            Ok(WsFrame::Response { .. }) => {}
+            Err(e) => warn!("Ignoring malformed WebSocket frame: {}", e),
+        }
+    }
+
+    if let Some(peer) = peer {
+        info!("WebSocket session with {} closed", peer);
+        state.sessions.unregister(&peer, &frames);
+    }
+    writer.abort();
+}
+
+type PendingRequest = (Message, oneshot::Sender<MessageResponse>);
+
+/// Persistent session with another agent's `/ws` endpoint. Requests are signed like POSTed
+/// messages; messages the peer pushes arrive on `next_incoming`. A dropped connection is
+/// re-established with backoff and unanswered requests are sent again, so delivery is
+/// at-least-once.
+pub struct WsClient {
+    requests: mpsc::UnboundedSender<PendingRequest>,
+    incoming: Mutex<mpsc::UnboundedReceiver<IpcMessage>>,
+    from: Option<String>,
+    auth: IpcAuth,
+    timeout: Duration,
+    task: tokio::task::JoinHandle<()>,
+}
+
+/// `ws://host:port/ws` for an agent address or message URL
+pub fn ws_url(address: &str) -> String {
+    format!("ws://{}/ws", address_key(address))
+}
+
+impl WsClient {
+    /// Connect to the agent at `address`, identifying as `from` (this agent's own server address)
+    pub async fn connect(address: &str, from: Option<String>) -> Result<Self> {
+        let url = ws_url(address);
+        let (socket, _) = tokio_tungstenite::connect_async(&url).await?;
+        let (requests, requests_rx) = mpsc::unbounded_channel();
+        let (incoming_tx, incoming) = mpsc::unbounded_channel();
+        let task = tokio::spawn(run_ws_client(url, socket, requests_rx, incoming_tx));
+        Ok(Self {
+            requests,
+            incoming: Mutex::new(incoming),
+            from,
+            auth: IpcAuth::from_env(),
+            timeout: Duration::from_secs(30),
+            task,
+        })
+    }
+
+    pub fn with_auth(mut self, auth: IpcAuth) -> Self {
+        self.auth = auth;
+        self
+    }
+
+    /// How long `request` waits for an answer, reconnects included (default 30s)
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Send `message` and wait for the peer's `received` text
+    pub async fn request(&self, message: &IpcMessage) -> Result<String> {
+        let mut payload = Message::new(message, self.from.clone())?;
+        self.auth.sign(&mut payload);
+        let (reply, answer) = oneshot::channel();
+        self.requests.send((payload, reply)).map_err(|_| anyhow::anyhow!("WebSocket session closed"))?;
+        let response = tokio::time::timeout(self.timeout, answer).await
+            .map_err(|_| anyhow::anyhow!("No answer within {:?}", self.timeout))?
+            .map_err(|_| anyhow::anyhow!("WebSocket session closed"))?;
+        if response.status != "ok" {
+            return Err(anyhow::anyhow!("{}: {}", response.status, response.received));
+        }
+        Ok(response.received)
+    }
+
+    /// Next message pushed by the peer
+    pub async fn next_incoming(&self) -> Option<IpcMessage> {
+        self.incoming.lock().await.recv().await
+    }
+}
+
+impl Drop for WsClient {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+type ClientSocket = tokio_tungstenite::WebSocketStream<tokio_tungstenite::MaybeTlsStream<tokio::net::TcpStream>>;
+
+async fn run_ws_client(
+    url: String,
+    socket: ClientSocket,
+    mut requests: mpsc::UnboundedReceiver<PendingRequest>,
+    incoming: mpsc::UnboundedSender<IpcMessage>,
+) {
+    use tokio_tungstenite::tungstenite::Message as Frame;
+
+    let mut socket = Some(socket);
+    let mut pending: HashMap<u64, PendingRequest> = HashMap::new();
+    let mut next_id = 0u64;
+    let mut backoff = Duration::from_millis(100);
+    loop {
+        let mut ws = match socket.take() {
+            Some(ws) => ws,
+            None => match tokio_tungstenite::connect_async(&url).await {
+                Ok((ws, _)) => {
+                    info!("Reconnected to {}", url);
+                    backoff = Duration::from_millis(100);
+                    ws
+                }
+                Err(_) => {
+                    tokio::time::sleep(backoff).await;
+                    backoff = (backoff * 2).min(Duration::from_secs(5));
+                    continue;
+                }
+            },
+        };
+
+        // Anything unanswered when the last connection dropped goes out again
+        let mut resend: Vec<(u64, Message)> = pending.iter().map(|(id, (m, _))| (*id, m.clone())).collect();
+        resend.sort_by_key(|(id, _)| *id);
+        let mut healthy = true;
+        for (id, message) in resend {
+            let text = serde_json::to_string(&WsFrame::Message { id, message }).unwrap_or_default();
+            if ws.send(Frame::Text(text)).await.is_err() {
+                healthy = false;
+                break;
+            }
+        }
+
+        while healthy {
+            tokio::select! {
+                request = requests.recv() => {
+                    let Some((message, reply)) = request else {
+                        let _ = ws.close(None).await;
+                        return;
+                    };
+                    next_id += 1;
+                    let text = serde_json::to_string(&WsFrame::Message { id: next_id, message: message.clone() }).unwrap_or_default();
+                    pending.insert(next_id, (message, reply));
+                    healthy = ws.send(Frame::Text(text)).await.is_ok();
+                }
+                frame = ws.next() => {
+                    let text = match frame {
+                        Some(Ok(Frame::Text(text))) => text,
+                        Some(Ok(Frame::Close(_))) | Some(Err(_)) | None => {
+                            healthy = false;
+                            continue;
+                        }
+                        Some(Ok(_)) => continue,
+                    };
+                    match serde_json::from_str::<WsFrame>(&text) {
+                        Ok(WsFrame::Response { id, response }) => {
+                            if let Some((_, reply)) = pending.remove(&id) {
+                                let _ = reply.send(response);
+                            }
+                        }
+                        Ok(WsFrame::Message { id, message }) => {
+                            let _ = incoming.send(IpcMessage::from_json_or_text(&message.content));
+                            let ack = WsFrame::Response { id, response: MessageResponse::ok("received") };
+                            healthy = ws.send(Frame::Text(serde_json::to_string(&ack).unwrap_or_default())).await.is_ok();
+                        }
+                        Err(e) => warn!("Ignoring malformed WebSocket frame: {}", e),
+                    }
+                }
+            }
+        }
+        // Requests whose caller gave up don't need resending
+        pending.retain(|_, (_, reply)| !reply.is_closed());
+        warn!("Connection to {} lost, reconnecting", url);
+    }
+}
+
+/// The IPC routes over `state`. Serve with `into_make_service_with_connect_info::<SocketAddr>()`
+/// so IP allow/deny entries can see the peer address.
+pub fn router(state: IpcState) -> Router {
+    Router::new()
         .route("/message", post(handle_message))
-        .with_state(state);
+        .route_layer(middleware::from_fn_with_state(state.clone(), authorize))
+        .route("/ws", get(handle_ws))
+        .route("/health", get(handle_health))
+        .route("/info", get(handle_info))
+        .route("/tools", get(handle_tools))
+        .route("/metrics", get(handle_metrics))
+        .with_state(state)
+}
+
+/// Serve `state` over MQTT until `shutdown` resolves: handle what arrives on the agent's
+/// inbox and broadcast topics. There is no HTTP response to carry inline answers, so
+/// answers to calls are published back to the caller as replies.
+pub async fn serve_mqtt(
+    state: IpcState,
+    transport: Arc<MqttTransport>,
+    shutdown: impl std::future::Future<Output = ()>,
+) -> Result<()> {
+    let mut incoming = transport.take_incoming()
+        .ok_or_else(|| anyhow::anyhow!("This agent's MQTT inbox is already being served"))?;
+    let me = transport.address();
+    info!("IPC Server listening on MQTT as {} ({}:{})", me, transport.config().host, transport.config().port);
+    tokio::pin!(shutdown);
+    loop {
+        let payload = tokio::select! {
+            payload = incoming.recv() => match payload {
+                Some(payload) => payload,
+                None => break,
+            },
+            _ = &mut shutdown => break,
+        };
+        // Signatures and sender allow/deny lists still apply; there is no IP to check
+        let Ok(sender) = admit(&state, None, &payload) else {
+            continue;
+        };
+        let (state, me) = (state.clone(), me.clone());
+        tokio::spawn(async move {
+            let (caller, id) = (payload.from.clone(), payload.id.clone());
+            let response = respond(&state, &sender, payload).await;
+            if let (Some(caller), Some(id), Some(_)) = (caller, id, response.reply_to) {
+                let answer = IpcMessage::from_json_or_text(&response.received);
+                if let Err(e) = send_reply(&caller, &id, &answer, Some(me)).await {
+                    warn!("Failed to answer {} over MQTT: {}", caller, e);
+                }
+            }
+        });
+    }
+    // Messages keep queueing for whichever server starts next
+    transport.restore_incoming(incoming);
+    Ok(())
+}
+
+/// Interface the IPC server listens on: `IPC_BIND`, default `127.0.0.1`
+/// (`0.0.0.0` to accept peers on other hosts)
+pub fn bind_host() -> String {
+    std::env::var("IPC_BIND").ok().filter(|h| !h.trim().is_empty()).unwrap_or_else(|| "127.0.0.1".to_string())
+}
+
+/// Host other agents should use to reach this one: `IPC_PUBLIC_HOST`, else the bind host,
+/// or `127.0.0.1` when bound to every interface
+pub fn advertised_host() -> String {
+    if let Some(host) = std::env::var("IPC_PUBLIC_HOST").ok().filter(|h| !h.trim().is_empty()) {
+        return host;
+    }
+    let host = bind_host();
+    match host.parse::<IpAddr>() {
+        Ok(ip) if ip.is_unspecified() => "127.0.0.1".to_string(),
+        _ => host,
+    }
+}
+
+/// `host:port`, bracketing IPv6 hosts
+pub fn host_port(host: &str, port: u16) -> String {
+    match host.parse::<IpAddr>() {
+        Ok(ip) => SocketAddr::new(ip, port).to_string(),
+        Err(_) => format!("{}:{}", host, port),
+    }
+}
+
+pub async fn start_http_server(port: u16, state: IpcState) -> Result<()> {
+    let listener = tokio::net::TcpListener::bind(host_port(&bind_host(), port)).await?;
+    serve_http(listener, state, std::future::pending()).await
+}
+
+/// Serve `state` on `listener` until `shutdown` resolves, then let requests in flight finish
+pub async fn serve_http(
+    listener: tokio::net::TcpListener,
+    state: IpcState,
+    shutdown: impl std::future::Future<Output = ()> + Send + 'static,
+) -> Result<()> {
+    if !state.auth.is_enabled() {
+        warn!("IPC_SECRET not set: messages are unsigned and accepted from anyone");
+    } else if state.auth.allows_unsigned() {
+        warn!("IPC_ALLOW_UNSIGNED is on: unsigned messages are accepted");
+    }
+    let host = bind_host();
+    let loopback = host == "localhost" || host.parse::<IpAddr>().map(|ip| ip.is_loopback()).unwrap_or(false);
+    if !loopback && !state.auth.is_enabled() {
+        warn!("Listening on {} without IPC_SECRET: anyone on the network can send messages", host);
+    }
+    let app = router(state);
     
-    let addr = format!("127.0.0.1:{}", port);
-    println!("🚀 IPC Server starting on http://{}", addr);
+    info!("IPC Server starting on http://{}", listener.local_addr()?);
     
-    let listener = tokio::net::TcpListener::bind(&addr).await?;
-    axum::serve(listener, app).await?;
+    axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>())
+        .with_graceful_shutdown(shutdown)
+        .await?;
     
     Ok(())
 }
+
+/// `send_message`, `call_peer`, `invoke_tool` and `halt_agent`
+pub(crate) fn register_messaging_natives(engine: &mut Engine, ctx: &NativeContext) {
+    let NativeContext { audit, safety, meter, threads, outbox, calls, local_address, .. } = ctx;
+    let context_len: usize = std::env::var("PEER_CONTEXT_MESSAGES")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(6);
+
+    // send_message: fire and forget, optionally in a named thread with the peer
+    let send = {
+        let threads_clone = threads.clone();
+        let address_clone = local_address.clone();
+        let outbox_clone = outbox.clone();
+        let safety_clone = safety.clone();
+        let audit_clone = audit.clone();
+        let meter = meter.clone();
+        move |url: &str, message: &str, thread_id: Option<String>| -> String {
+            if let Err(e) = egress::guard(&safety_clone.lock().unwrap(), url, "send_message", Some(&audit_clone)) {
+                return format!("Error: {}", e);
+            }
+            if let Err(e) = meter("send_message") {
+                return format!("Error: {}", e);
+            }
+            info!("Sending message to {}: {}", url, message);
+        
+            // Attach our own address and the recent thread so the peer can keep the dialogue coherent
+            let from = address_clone.lock().unwrap().clone();
+            let context = threads_clone.render_in(url, thread_id.as_deref(), context_len).ok().filter(|c| !c.is_empty());
+            let traceparent = telemetry::traceparent(&tracing::Span::current());
+        
+            // Use blocking reqwest in a thread
+            let url = url.to_string();
+            let message = message.to_string();
+            let threads = threads_clone.clone();
+            let outbox = outbox_clone.clone();
+            let safety = safety_clone.clone();
+            // Plain text outside a thread, as peers without threads expect
+            let content = match &thread_id {
+                Some(_) => IpcMessage::threaded(message.clone(), thread_id.clone()).to_json().unwrap_or_else(|_| message.clone()),
+                None => message.clone(),
+            };
+        
+            std::thread::spawn(move || {
+                let rt = tokio::runtime::Runtime::new().unwrap();
+                rt.block_on(async {
+                    // A peer's /message doesn't redirect; following one would skip the egress check
+                    let client = reqwest::Client::builder()
+                        .redirect(reqwest::redirect::Policy::none())
+                        .dns_resolver(egress::resolver(move || Ok(safety.lock().unwrap().clone())))
+                        .build()
+                        .unwrap_or_default();
+                    let mut payload = crate::ipc::Message {
+                        content,
+                        from: from.clone(),
+                        context: context.clone(),
+                        id: None,
+                        reply_to: None,
+                        signature: None,
+                        traceparent,
+                    };
+                    crate::auth::IpcAuth::from_env().sign(&mut payload);
+
+                    // Peer down or overloaded: hand the message to the outbox to retry later
+                    let queue = |reason: String| {
+                        match outbox.enqueue(&url, IpcMessage::threaded(message.clone(), thread_id.clone()), from.clone(), context.clone()) {
+                            Ok(id) => {
+                                if let Err(e) = threads.record_in(&url, thread_id.as_deref(), Direction::Outgoing, &message) {
+                                    warn!("Failed to record message to {}: {}", url, e);
+                                }
+                                format!("Peer unavailable ({}); message queued in outbox as #{}", reason, id)
+                            }
+                            Err(e) => format!("Error sending message: {} (and could not queue it: {})", reason, e),
+                        }
+                    };
+                
+                    match client.post(&url).json(&payload).send().await {
+                        Ok(resp) if crate::retry::is_transient_status(resp.status()) => queue(format!("peer answered {}", resp.status())),
+                        Ok(resp) if !resp.status().is_success() => {
+                            let status = resp.status();
+                            format!("Error: message rejected ({}): {}", status, resp.text().await.unwrap_or_default())
+                        },
+                        Ok(resp) => {
+                            if let Err(e) = threads.record_in(&url, thread_id.as_deref(), Direction::Outgoing, &message) {
+                                warn!("Failed to record message to {}: {}", url, e);
+                            }
+                            match resp.text().await {
+                                Ok(text) => format!("Response: {}", text),
+                                Err(e) => format!("Error reading response: {}", e),
+                            }
+                        },
+                        Err(e) => queue(e.to_string()),
+                    }
+                })
+            }).join().unwrap_or_else(|_| "Thread panic".to_string())
+        }
+    };
+    let send_threaded = send.clone();
+    engine.register_fn("send_message", move |url: &str, message: &str| -> String { send(url, message, None) });
+    engine.register_fn("send_message", move |url: &str, message: &str, thread_id: &str| -> String {
+        send_threaded(url, message, Some(thread_id.trim().to_string()).filter(|t| !t.is_empty()))
+    });
+
+    // call_peer: send and wait for the answer; text is answered by the peer's agent with
+    // reply_message, so the reply needs our server (start_server) to land on
+    let call = {
+        let calls = calls.clone();
+        let threads = threads.clone();
+        let local_address = local_address.clone();
+        let meter = meter.clone();
+        move |url: &str, message: &str, timeout_secs: i64| -> String {
+            if let Err(e) = meter("call_peer") {
+                return format!("Error: {}", e);
+            }
+            info!("Calling {}: {}", url, message);
+            let calls = calls.clone();
+            let threads = threads.clone();
+            let from = local_address.lock().unwrap().clone();
+            let url = url.to_string();
+            let message = message.to_string();
+            let timeout = std::time::Duration::from_secs(timeout_secs.max(1) as u64);
+            std::thread::spawn(move || {
+                let rt = tokio::runtime::Runtime::new().unwrap();
+                rt.block_on(async {
+                    // Structured messages (a ToolRequest, say) go out as they are
+                    let ipc = IpcMessage::from_json_or_text(&message);
+                    match crate::ipc::call_peer(&calls, &url, &ipc, from, timeout).await {
+                        Ok(answer) => {
+                            if let IpcMessage::Text { .. } = ipc {
+                                if let Err(e) = threads.record(&url, Direction::Outgoing, &message) {
+                                    warn!("Failed to record message to {}: {}", url, e);
+                                }
+                            }
+                            match answer {
+                                IpcMessage::Text { content, .. } => content,
+                                other => other.to_json().unwrap_or_else(|e| format!("Error: {}", e)),
+                            }
+                        }
+                        Err(e) => format!("Error calling {}: {}", url, e),
+                    }
+                })
+            }).join().unwrap_or_else(|_| "Thread panic".to_string())
+        }
+    };
+    let call_default = call.clone();
+    engine.register_fn("call_peer", call);
+    engine.register_fn("call_peer", move |url: &str, message: &str| -> String { call_default(url, message, 30) });
+
+    // invoke_tool: run one of a peer's tools there and get its output back
+    let invoke = {
+        let calls = calls.clone();
+        let local_address = local_address.clone();
+        let meter = meter.clone();
+        move |url: &str, name: &str, args: Vec<String>| -> String {
+            if let Err(e) = meter("invoke_tool") {
+                return format!("Error: {}", e);
+            }
+            info!("Invoking {} on {}", name, url);
+            let calls = calls.clone();
+            let from = local_address.lock().unwrap().clone();
+            let url = url.to_string();
+            let message = IpcMessage::tool_invoke(name, args);
+            std::thread::spawn(move || {
+                let rt = tokio::runtime::Runtime::new().unwrap();
+                rt.block_on(async {
+                    match crate::ipc::call_peer(&calls, &url, &message, from, std::time::Duration::from_secs(60)).await {
+                        Ok(IpcMessage::ToolOutput { result: Some(result), .. }) => result,
+                        Ok(IpcMessage::ToolOutput { name, error, .. }) => {
+                            format!("Error: {} refused or failed tool '{}': {}", address_key(&url), name, error.unwrap_or_default())
+                        }
+                        Ok(other) => format!("Error: unexpected reply: {:?}", other),
+                        Err(e) => format!("Error invoking tool on {}: {}", url, e),
+                    }
+                })
+            }).join().unwrap_or_else(|_| "Thread panic".to_string())
+        }
+    };
+    let invoke_one = invoke.clone();
+    let invoke_none = invoke.clone();
+    engine.register_fn("invoke_tool", move |url: &str, name: &str, args: rhai::Array| -> String {
+        invoke(url, name, args.iter().map(|a| a.to_string()).collect())
+    });
+    engine.register_fn("invoke_tool", move |url: &str, name: &str, arg: &str| -> String {
+        invoke_one(url, name, vec![arg.to_string()])
+    });
+    engine.register_fn("invoke_tool", move |url: &str, name: &str| -> String {
+        invoke_none(url, name, Vec::new())
+    });
+
+    // halt_agent: send a signed emergency stop to a peer (or clone)
+    let address_clone = local_address.clone();
+    engine.register_fn("halt_agent", move |address: &str, reason: &str| -> String {
+        let from = address_clone.lock().unwrap().clone();
+        let address = address.to_string();
+        let message = IpcMessage::Halt { reason: reason.to_string(), clones: false };
+        std::thread::spawn(move || {
+            let rt = tokio::runtime::Runtime::new().unwrap();
+            rt.block_on(async {
+                match crate::ipc::send_ipc_message(&address, &message, from).await {
+                    Ok(reply) => format!("{}: {}", address, reply),
+                    Err(e) => format!("Error halting {}: {}", address, e),
+                }
+            })
+        }).join().unwrap_or_else(|_| "Thread panic".to_string())
+    });
+}
+
+/// `delegate_task`, `announce_task` and `task_status`
+pub(crate) fn register_task_natives(engine: &mut Engine, ctx: &NativeContext) {
+    let NativeContext { meter, tasks, peers, local_address, .. } = ctx;
+    let heartbeat = ctx.heartbeat;
+    // Task delegation: the result comes back to our own server (if started) within
+    // TASK_DEADLINE_SECS (default 300)
+    let deadline_secs: u64 = std::env::var("TASK_DEADLINE_SECS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(300);
+    let address_clone = local_address.clone();
+    let peers_clone = peers.clone();
+    let meter_clone = meter.clone();
+    engine.register_fn("delegate_task", move |url: &str, description: &str| -> String {
+        if let Err(e) = meter_clone("delegate_task") {
+            return format!("Error: {}", e);
+        }
+        // With heartbeats on, don't hand work to a peer that stopped answering
+        if let Some(config) = heartbeat {
+            if peers_clone.status(url, config.timeout) == Some(PeerStatus::Dead) {
+                return format!("Error: peer {} is not responding to heartbeats", address_key(url));
+            }
+        }
+        let mut task = TaskRequest::new(description).with_deadline(std::time::Duration::from_secs(deadline_secs));
+        let from = address_clone.lock().unwrap().clone();
+        if let Some(address) = &from {
+            task = task.with_reply_to(crate::ipc::message_url(address));
+        }
+        info!("Delegating task {} to {}", task.id, url);
+
+        let url = url.to_string();
+        let message = IpcMessage::task_request(task.clone());
+        std::thread::spawn(move || {
+            let rt = tokio::runtime::Runtime::new().unwrap();
+            rt.block_on(async {
+                match crate::ipc::send_ipc_message(&url, &message, from).await {
+                    Ok(received) if task.reply_to.is_some() => format!("Delegated task {}: {}", task.id, received),
+                    Ok(received) => format!("Delegated task {}: {} (start_server to receive the result)", task.id, received),
+                    Err(e) => format!("Error delegating task: {}", e),
+                }
+            })
+        }).join().unwrap_or_else(|_| "Thread panic".to_string())
+    });
+
+    // Contract net: announce a task to a group, take bids and award it to the best one.
+    // Bids are cost (queue length) against the share of `requirements` the peer has.
+    let address_clone = local_address.clone();
+    let peers_clone = peers.clone();
+    let tasks_clone = tasks.clone();
+    let announce_task = move |group: &str, description: &str, requirements: &str| -> String {
+        let targets = peers_clone.resolve_group(group);
+        if targets.is_empty() {
+            return format!("Error: no peers in group '{}'", group);
+        }
+        let mut task = TaskRequest::new(description).with_deadline(std::time::Duration::from_secs(deadline_secs));
+        let from = address_clone.lock().unwrap().clone();
+        if let Some(address) = &from {
+            task = task.with_reply_to(crate::ipc::message_url(address));
+        }
+        let requirements = requirements.split(',').map(|r| r.trim().to_string()).filter(|r| !r.is_empty()).collect();
+        let announce = TaskAnnounce::new(task, requirements);
+        info!("Announcing task {} to {} peer(s) in '{}'", announce.task.id, targets.len(), group);
+        let tasks = tasks_clone.clone();
+        std::thread::spawn(move || {
+            let rt = tokio::runtime::Runtime::new().unwrap();
+            rt.block_on(async {
+                let auction = crate::ipc::run_auction(&tasks, &targets, &announce, from, std::time::Duration::from_secs(10)).await;
+                auction.render()
+            })
+        }).join().unwrap_or_else(|_| "Thread panic".to_string())
+    };
+    let announce_clone = announce_task.clone();
+    engine.register_fn("announce_task", move |group: &str, description: &str| -> String {
+        announce_clone(group, description, "")
+    });
+    engine.register_fn("announce_task", announce_task);
+
+    let tasks_clone = tasks.clone();
+    engine.register_fn("task_status", move |id: &str| -> String {
+        match tasks_clone.result(id) {
+            Some(result) if result.status == TaskStatus::Completed => format!("Completed: {}", result.output),
+            Some(result) => format!("{:?}: {}", result.status, result.output),
+            None => format!("Task {} is still pending", id),
+        }
+    });
+}
@@ -0,0 +1,181 @@
+use anyhow::{Result, anyhow};
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use crate::changes::ChangeKind;
+use rhai::Engine;
+use crate::tools::NativeContext;
+
+/// Confines file-system tools to a single workspace root
+#[derive(Debug, Clone)]
+pub struct FsJail {
+    root: PathBuf,
+}
+
+impl FsJail {
+    /// Create a jail rooted at `root` (created if missing)
+    pub fn new(root: impl AsRef<Path>) -> Result<Self> {
+        let root = root.as_ref();
+        fs::create_dir_all(root)?;
+        Ok(Self {
+            root: root.canonicalize()?,
+        })
+    }
+
+    /// Create a jail from `AGENT_WORKSPACE`, defaulting to the current directory
+    pub fn from_env() -> Result<Self> {
+        let root = std::env::var("AGENT_WORKSPACE").unwrap_or_else(|_| ".".to_string());
+        Self::new(root)
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Resolve a tool-supplied path to an absolute path inside the jail.
+    /// Relative paths are taken from the jail root; anything that ends up
+    /// outside of it (via `..`, an absolute path or a symlink) is rejected.
+    pub fn resolve(&self, path: &str) -> Result<PathBuf> {
+        let requested = Path::new(path);
+        let joined = if requested.is_absolute() {
+            requested.to_path_buf()
+        } else {
+            self.root.join(requested)
+        };
+
+        // Normalize lexically first so paths that don't exist yet can be checked
+        let mut normalized = PathBuf::new();
+        for component in joined.components() {
+            match component {
+                Component::ParentDir => {
+                    normalized.pop();
+                }
+                Component::CurDir => {}
+                other => normalized.push(other.as_os_str()),
+            }
+        }
+
+        // Canonicalize the deepest existing ancestor so symlinks can't escape. A dangling
+        // symlink exists too (`symlink_metadata`, not `exists`), and fails to canonicalize, so
+        // a write can't follow it out of the jail.
+        let mut existing = normalized.as_path();
+        let mut missing = Vec::new();
+        while fs::symlink_metadata(existing).is_err() {
+            match (existing.parent(), existing.file_name()) {
+                (Some(parent), Some(name)) => {
+                    missing.push(name.to_os_string());
+                    existing = parent;
+                }
+                _ => break,
+            }
+        }
+        let mut resolved = existing.canonicalize()
+            .map_err(|e| anyhow!("Path '{}' cannot be resolved in the workspace: {}", path, e))?;
+        for name in missing.iter().rev() {
+            resolved.push(name);
+        }
+
+        if resolved.starts_with(&self.root) {
+            Ok(resolved)
+        } else {
+            Err(anyhow!("Path '{}' is outside the workspace {:?}", path, self.root))
+        }
+    }
+}
+
+/// `read_file`, `write_file` and the other file natives, inside `ctx.jail`
+pub(crate) fn register_file_natives(engine: &mut Engine, ctx: &NativeContext) {
+    let NativeContext { jail, changes, .. } = ctx;
+    let jail_clone = jail.clone();
+    engine.register_fn("read_file", move |path: &str| -> String {
+        match jail_clone.resolve(path) {
+            Ok(path) => fs::read_to_string(path).unwrap_or_else(|e| format!("Error reading file: {}", e)),
+            Err(e) => format!("Error: {}", e),
+        }
+    });
+
+    let jail_clone = jail.clone();
+    let changes_clone = changes.clone();
+    engine.register_fn("write_file", move |path: &str, content: &str| -> String {
+        match jail_clone.resolve(path) {
+            Ok(path) => changes_clone.track(&path, ChangeKind::Write, || fs::write(&path, content))
+                .map(|_| "File written successfully".to_string())
+                .unwrap_or_else(|e| format!("Error writing file: {}", e)),
+            Err(e) => format!("Error: {}", e),
+        }
+    });
+
+    let jail_clone = jail.clone();
+    let changes_clone = changes.clone();
+    engine.register_fn("append_file", move |path: &str, content: &str| -> String {
+        use std::io::Write;
+        match jail_clone.resolve(path) {
+            Ok(path) => changes_clone.track(&path, ChangeKind::Append, || {
+                fs::OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(&path)
+                    .and_then(|mut file| file.write_all(content.as_bytes()))
+            })
+                .map(|_| "File appended successfully".to_string())
+                .unwrap_or_else(|e| format!("Error appending to file: {}", e)),
+            Err(e) => format!("Error: {}", e),
+        }
+    });
+
+    let jail_clone = jail.clone();
+    let changes_clone = changes.clone();
+    engine.register_fn("delete_file", move |path: &str| -> String {
+        match jail_clone.resolve(path) {
+            Ok(path) if path == jail_clone.root() => "Error: Refusing to delete the workspace root".to_string(),
+            Ok(path) => changes_clone.track(&path, ChangeKind::Delete, || fs::remove_file(&path))
+                .map(|_| "File deleted successfully".to_string())
+                .unwrap_or_else(|e| format!("Error deleting file: {}", e)),
+            Err(e) => format!("Error: {}", e),
+        }
+    });
+
+    let changes_clone = changes.clone();
+    engine.register_fn("list_changes", move || -> String { changes_clone.render() });
+    let changes_clone = changes.clone();
+    let undo = move |n: i64| -> String {
+        match changes_clone.undo(n.max(0) as usize) {
+            Ok(undone) if undone.is_empty() => "No file changes to undo".to_string(),
+            Ok(undone) => undone.join("\n"),
+            Err(e) => format!("Error undoing changes: {}", e),
+        }
+    };
+    let undo_clone = undo.clone();
+    engine.register_fn("undo_changes", move |n: i64| -> String { undo_clone(n) });
+    let undo_clone = undo.clone();
+    engine.register_fn("undo_changes", move || -> String { undo_clone(1) });
+    // Tool calls pass their arguments as text
+    engine.register_fn("undo_changes", move |n: &str| -> String {
+        match n.trim().parse::<i64>() {
+            Ok(n) => undo(n),
+            Err(_) => format!("Error: undo_changes expects a number of changes, not '{}'", n.trim()),
+        }
+    });
+
+    let jail_clone = jail.clone();
+    engine.register_fn("list_dir", move |path: &str| -> String {
+        let dir = match jail_clone.resolve(path) {
+            Ok(dir) => dir,
+            Err(e) => return format!("Error: {}", e),
+        };
+        match fs::read_dir(&dir) {
+            Ok(entries) => {
+                let mut names: Vec<String> = entries.flatten().map(|entry| {
+                    let name = entry.file_name().to_string_lossy().to_string();
+                    if entry.path().is_dir() { format!("{}/", name) } else { name }
+                }).collect();
+                names.sort();
+                names.join(", ")
+            },
+            Err(e) => format!("Error listing directory: {}", e),
+        }
+    });
+
+    // Where relative paths of the file tools lead: the session's own workspace, when it has one
+    let root = jail.root().display().to_string();
+    engine.register_fn("workspace_path", move || -> String { root.clone() });
+}
@@ -0,0 +1,245 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use tokio::sync::Notify;
+use crate::message::ToolSafetyLevel;
+use crate::registry::unix_now;
+use crate::tools::ToolManager;
+use rhai::Engine;
+
+/// Where a background job is
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JobStatus {
+    Queued,
+    Running,
+    Done,
+    Failed,
+}
+
+impl JobStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Queued => "queued",
+            Self::Running => "running",
+            Self::Done => "done",
+            Self::Failed => "failed",
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        matches!(self, Self::Done | Self::Failed)
+    }
+}
+
+/// A tool run `execute_tool_background` handed to the worker pool
+#[derive(Debug, Clone, PartialEq)]
+pub struct BackgroundJob {
+    pub id: String,
+    pub tool: String,
+    pub args: Vec<String>,
+    /// The risk `execute_tool_background` admitted the run at; None for a run a script queued,
+    /// which the worker admits
+    pub level: Option<ToolSafetyLevel>,
+    pub status: JobStatus,
+    /// The tool's output, or its error once failed
+    pub output: Option<String>,
+    /// Unix times (seconds) it was queued, and started and finished running
+    pub queued_at: u64,
+    pub started_at: Option<u64>,
+    pub finished_at: Option<u64>,
+}
+
+impl BackgroundJob {
+    pub fn call(&self) -> String {
+        format!("{}({})", self.tool, self.args.join(", "))
+    }
+
+    /// One line for `job_status`
+    pub fn render(&self) -> String {
+        let mut line = format!("{} {}: {}", self.id, self.call(), self.status.as_str());
+        if let (Some(started), Some(finished)) = (self.started_at, self.finished_at) {
+            line.push_str(&format!(" in {}s", finished.saturating_sub(started)));
+        }
+        line
+    }
+}
+
+#[derive(Default)]
+struct JobQueueInner {
+    jobs: Vec<BackgroundJob>,
+    next_id: u64,
+    /// Finished jobs nobody has been told about yet
+    unannounced: VecDeque<String>,
+}
+
+/// Tool runs waiting for, or done by, the background workers. Clones share the same jobs.
+#[derive(Clone, Default)]
+pub struct JobQueue {
+    inner: Arc<Mutex<JobQueueInner>>,
+    /// Wakes a worker when a job is queued
+    queued: Arc<Notify>,
+    /// Wakes `finished` when a job is done
+    done: Arc<Notify>,
+}
+
+impl JobQueue {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Queue a run of `tool`, admitted at `level` if it was, returning its id
+    pub fn submit(&self, tool: &str, args: Vec<String>, level: Option<ToolSafetyLevel>) -> String {
+        let mut inner = self.inner.lock().unwrap();
+        inner.next_id += 1;
+        let id = format!("bg-{}", inner.next_id);
+        inner.jobs.push(BackgroundJob {
+            id: id.clone(),
+            tool: tool.to_string(),
+            args,
+            level,
+            status: JobStatus::Queued,
+            output: None,
+            queued_at: unix_now(),
+            started_at: None,
+            finished_at: None,
+        });
+        drop(inner);
+        self.queued.notify_one();
+        id
+    }
+
+    /// Take the oldest queued job, marking it running
+    pub fn try_next(&self) -> Option<BackgroundJob> {
+        let mut inner = self.inner.lock().unwrap();
+        let job = inner.jobs.iter_mut().find(|j| j.status == JobStatus::Queued)?;
+        job.status = JobStatus::Running;
+        job.started_at = Some(unix_now());
+        Some(job.clone())
+    }
+
+    /// Wait for the next queued job
+    pub async fn next(&self) -> BackgroundJob {
+        loop {
+            if let Some(job) = self.try_next() {
+                return job;
+            }
+            self.queued.notified().await;
+        }
+    }
+
+    /// Store how job `id` went
+    pub fn finish(&self, id: &str, result: Result<String, String>) {
+        let mut inner = self.inner.lock().unwrap();
+        let Some(job) = inner.jobs.iter_mut().find(|j| j.id == id) else {
+            return;
+        };
+        (job.status, job.output) = match result {
+            Ok(output) => (JobStatus::Done, Some(output)),
+            Err(e) => (JobStatus::Failed, Some(e)),
+        };
+        job.finished_at = Some(unix_now());
+        inner.unannounced.push_back(id.to_string());
+        drop(inner);
+        self.done.notify_waiters();
+    }
+
+    pub fn get(&self, id: &str) -> Option<BackgroundJob> {
+        self.inner.lock().unwrap().jobs.iter().find(|j| j.id == id).cloned()
+    }
+
+    /// Every job, oldest first
+    pub fn all(&self) -> Vec<BackgroundJob> {
+        self.inner.lock().unwrap().jobs.clone()
+    }
+
+    /// Jobs queued or running
+    pub fn active(&self) -> usize {
+        self.inner.lock().unwrap().jobs.iter().filter(|j| !j.status.is_finished()).count()
+    }
+
+    /// Finished jobs not returned here before, oldest first
+    pub fn take_finished(&self) -> Vec<BackgroundJob> {
+        let mut inner = self.inner.lock().unwrap();
+        let ids: Vec<String> = inner.unannounced.drain(..).collect();
+        ids.iter().filter_map(|id| inner.jobs.iter().find(|j| &j.id == id).cloned()).collect()
+    }
+
+    /// Wait for jobs to finish, returning those not announced yet
+    pub async fn finished(&self) -> Vec<BackgroundJob> {
+        loop {
+            let done = self.done.notified();
+            let jobs = self.take_finished();
+            if !jobs.is_empty() {
+                return jobs;
+            }
+            done.await;
+        }
+    }
+
+    /// `job_status`: one job, or every job for `""`
+    pub fn render_status(&self, id: &str) -> String {
+        if id.is_empty() {
+            let jobs = self.all();
+            if jobs.is_empty() {
+                return "No background jobs.".to_string();
+            }
+            return jobs.iter().map(BackgroundJob::render).collect::<Vec<_>>().join("\n");
+        }
+        match self.get(id) {
+            Some(job) => job.render(),
+            None => format!("Error: No background job '{}'", id),
+        }
+    }
+
+    /// `job_result`: a finished job's output
+    pub fn render_result(&self, id: &str) -> String {
+        match self.get(id) {
+            Some(job) if job.status == JobStatus::Failed => format!("Error: {}", job.output.unwrap_or_default()),
+            Some(job) if job.status.is_finished() => job.output.unwrap_or_default(),
+            Some(job) => format!("Error: Job {} is still {}", id, job.status.as_str()),
+            None => format!("Error: No background job '{}'", id),
+        }
+    }
+}
+
+/// Run `jobs` one at a time with `tools`, off the async runtime since tools block (see
+/// `ToolManager::run_job`). One call is one worker; start several for a pool. The tools are
+/// unattended from then on. Runs until the surrounding task is dropped.
+pub async fn serve_jobs(jobs: JobQueue, mut tools: ToolManager) {
+    tools.set_unattended(true);
+    loop {
+        let job = jobs.next().await;
+        let id = job.id.clone();
+        let ran = tokio::task::spawn_blocking(move || {
+            let result = tools.run_job(&job).map_err(|e| e.to_string());
+            (tools, result)
+        }).await;
+        match ran {
+            Ok((returned, result)) => {
+                tools = returned;
+                jobs.finish(&id, result);
+            }
+            Err(e) => {
+                jobs.finish(&id, Err(format!("the worker panicked: {}", e)));
+                return;
+            }
+        }
+    }
+}
+
+/// `execute_tool_background`, `job_status` and `job_result` on `jobs`
+pub(crate) fn register_job_natives(engine: &mut Engine, jobs: &JobQueue) {
+    let jobs_clone = jobs.clone();
+    engine.register_fn("execute_tool_background", move |name: &str| -> String {
+        format!("Started {} in the background", jobs_clone.submit(name, Vec::new(), None))
+    });
+    let jobs_clone = jobs.clone();
+    engine.register_fn("execute_tool_background", move |name: &str, arg: &str| -> String {
+        format!("Started {} in the background", jobs_clone.submit(name, vec![arg.to_string()], None))
+    });
+    let jobs_clone = jobs.clone();
+    engine.register_fn("job_status", move |id: &str| -> String { jobs_clone.render_status(id.trim()) });
+    let jobs_clone = jobs.clone();
+    engine.register_fn("job_status", move || -> String { jobs_clone.render_status("") });
+    let jobs_clone = jobs.clone();
+    engine.register_fn("job_result", move |id: &str| -> String { jobs_clone.render_result(id.trim()) });
+}
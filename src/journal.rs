@@ -0,0 +1,247 @@
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use crate::message::session_id;
+use crate::registry::unix_now;
+use tracing::warn;
+
+/// Longest text kept in a rendered replay line; the journal itself keeps everything
+const RENDER_LIMIT: usize = 300;
+
+/// One step of an agent run
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum JournalEvent {
+    /// What the operator (or a delegating peer) asked
+    Input { text: String },
+    /// A request to the model; `hash` is its response cache key
+    LlmRequest { hash: String, model: String, messages: usize, purpose: String },
+    LlmResponse { hash: String, model: String, text: String, input_tokens: u64, output_tokens: u64, cached: bool, duration_ms: u64 },
+    LlmError { hash: String, error: String, duration_ms: u64 },
+    /// A tool call parsed from a response
+    ToolCall { name: String, args: Vec<String> },
+    ToolResult { name: String, output: String, ok: bool, duration_ms: u64 },
+    /// A tool the model wrote, and what became of it
+    ToolCreated { name: String, outcome: String },
+    /// An attempt at fixing a broken tool: the error shown to the model and what its fix gave
+    ToolFix { name: String, attempt: usize, error: String, outcome: String },
+}
+
+impl JournalEvent {
+    pub fn render(&self) -> String {
+        match self {
+            JournalEvent::Input { text } => format!("input: {}", clip(text)),
+            JournalEvent::LlmRequest { hash, model, messages, purpose } => {
+                format!("llm request {} to {} ({}, {} messages)", short(hash), model, purpose, messages)
+            }
+            JournalEvent::LlmResponse { hash, text, input_tokens, output_tokens, cached, duration_ms, .. } => {
+                let cached = if *cached { ", cached" } else { "" };
+                format!("llm response {} in {}ms ({}+{} tokens{}): {}", short(hash), duration_ms, input_tokens, output_tokens, cached, clip(text))
+            }
+            JournalEvent::LlmError { hash, error, duration_ms } => format!("llm error {} after {}ms: {}", short(hash), duration_ms, error),
+            JournalEvent::ToolCall { name, args } => format!("tool call {}({})", name, args.join(", ")),
+            JournalEvent::ToolResult { name, output, ok, duration_ms } => {
+                let status = if *ok { "ok" } else { "error" };
+                format!("tool {} {} in {}ms: {}", name, status, duration_ms, clip(output))
+            }
+            JournalEvent::ToolCreated { name, outcome } => format!("tool created {}: {}", name, outcome),
+            JournalEvent::ToolFix { name, attempt, error, outcome } => {
+                format!("tool fix {} attempt {} for {}: {}", name, attempt, clip(error), clip(outcome))
+            }
+        }
+    }
+}
+
+fn short(hash: &str) -> &str {
+    &hash[..hash.len().min(12)]
+}
+
+fn clip(text: &str) -> String {
+    let text = text.replace('\n', " ");
+    if text.chars().count() <= RENDER_LIMIT {
+        return text;
+    }
+    format!("{}…", text.chars().take(RENDER_LIMIT).collect::<String>())
+}
+
+/// A journal line
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct JournalEntry {
+    pub session: String,
+    /// Order within the session
+    pub seq: u64,
+    /// The operator turn the step belongs to, from 1
+    pub turn: u64,
+    /// Seconds since the Unix epoch
+    pub timestamp: u64,
+    #[serde(flatten)]
+    pub event: JournalEvent,
+}
+
+impl JournalEntry {
+    pub fn render(&self) -> String {
+        format!("#{} turn {} [{}] {}", self.seq, self.turn, self.timestamp, self.event.render())
+    }
+}
+
+#[derive(Debug, Default)]
+struct Counters {
+    seq: u64,
+    turn: u64,
+}
+
+/// Structured JSON-lines record of one session's steps, `<dir>/<session>.jsonl`. Clones share
+/// the file and the counters.
+#[derive(Debug, Clone)]
+pub struct Journal {
+    path: PathBuf,
+    session: String,
+    counters: Arc<Mutex<Counters>>,
+}
+
+impl Journal {
+    /// A new session in `dir`
+    pub fn open(dir: impl AsRef<Path>) -> Result<Self> {
+        fs::create_dir_all(dir.as_ref())?;
+        let session = session_id();
+        Ok(Self { path: dir.as_ref().join(format!("{}.jsonl", session)), session, counters: Arc::default() })
+    }
+
+    /// A new session in `AGENT_JOURNAL_DIR` (default `state/journal`); `None` with `AGENT_JOURNAL=false`
+    pub fn from_env() -> Result<Option<Self>> {
+        if std::env::var("AGENT_JOURNAL").map(|v| v == "false").unwrap_or(false) {
+            return Ok(None);
+        }
+        Self::open(journal_dir()).map(Some)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn session(&self) -> &str {
+        &self.session
+    }
+
+    /// Start the next operator turn; later steps belong to it
+    pub fn start_turn(&self) -> u64 {
+        let mut counters = self.counters.lock().unwrap();
+        counters.turn += 1;
+        counters.turn
+    }
+
+    pub fn record(&self, event: JournalEvent) -> Result<()> {
+        let entry = {
+            let mut counters = self.counters.lock().unwrap();
+            counters.seq += 1;
+            JournalEntry { session: self.session.clone(), seq: counters.seq, turn: counters.turn, timestamp: unix_now(), event }
+        };
+        let mut file = fs::OpenOptions::new().create(true).append(true).open(&self.path)?;
+        writeln!(file, "{}", serde_json::to_string(&entry)?)?;
+        Ok(())
+    }
+
+    /// Record, only warning on failure: a journal problem shouldn't stop the agent
+    pub fn record_or_warn(&self, event: JournalEvent) {
+        if let Err(e) = self.record(event) {
+            warn!("Failed to write journal {:?}: {}", self.path, e);
+        }
+    }
+}
+
+/// `AGENT_JOURNAL_DIR`, default `state/journal`
+pub fn journal_dir() -> PathBuf {
+    PathBuf::from(std::env::var("AGENT_JOURNAL_DIR").unwrap_or_else(|_| "state/journal".to_string()))
+}
+
+/// Milliseconds in `elapsed`, for journal durations
+pub fn millis(elapsed: Duration) -> u64 {
+    elapsed.as_millis() as u64
+}
+
+/// A recorded session, read back for debugging
+#[derive(Debug, Clone)]
+pub struct Replay {
+    pub session: String,
+    pub entries: Vec<JournalEntry>,
+}
+
+impl Replay {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let content = fs::read_to_string(path).map_err(|e| anyhow!("Cannot read journal {:?}: {}", path, e))?;
+        let entries = content.lines()
+            .filter(|line| !line.trim().is_empty())
+            .enumerate()
+            .map(|(i, line)| serde_json::from_str(line).map_err(|e| anyhow!("Invalid journal line {} in {:?}: {}", i + 1, path, e)))
+            .collect::<Result<Vec<JournalEntry>>>()?;
+        let session = path.file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_default();
+        Ok(Self { session, entries })
+    }
+
+    /// The session `session` in `dir`; `"latest"` for the most recent
+    pub fn find(dir: impl AsRef<Path>, session: &str) -> Result<Self> {
+        let dir = dir.as_ref();
+        if session == "latest" {
+            let latest = sessions(dir)?.pop().ok_or_else(|| anyhow!("No journals in {:?}", dir))?;
+            return Self::load(dir.join(format!("{}.jsonl", latest)));
+        }
+        Self::load(dir.join(format!("{}.jsonl", session)))
+    }
+
+    /// The steps of turn `turn`
+    pub fn turn(&self, turn: u64) -> Vec<&JournalEntry> {
+        self.entries.iter().filter(|e| e.turn == turn).collect()
+    }
+
+    pub fn turns(&self) -> u64 {
+        self.entries.iter().map(|e| e.turn).max().unwrap_or(0)
+    }
+
+    /// Tool calls with their results, in order
+    pub fn tool_runs(&self) -> Vec<(&JournalEntry, Option<&JournalEntry>)> {
+        self.entries.iter()
+            .enumerate()
+            .filter_map(|(i, entry)| match &entry.event {
+                JournalEvent::ToolCall { name, .. } => {
+                    let result = self.entries[i + 1..].iter()
+                        .find(|e| matches!(&e.event, JournalEvent::ToolResult { name: n, .. } if n == name));
+                    Some((entry, result))
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// One line per step, of one turn or all of them
+    pub fn render(&self, turn: Option<u64>) -> String {
+        let entries: Vec<&JournalEntry> = match turn {
+            Some(turn) => self.turn(turn),
+            None => self.entries.iter().collect(),
+        };
+        if entries.is_empty() {
+            return format!("Nothing recorded in {}", self.session);
+        }
+        entries.iter().map(|e| e.render()).collect::<Vec<_>>().join("\n")
+    }
+}
+
+/// Sessions journaled in `dir`, oldest first
+pub fn sessions(dir: impl AsRef<Path>) -> Result<Vec<String>> {
+    let mut sessions = Vec::new();
+    if let Ok(entries) = fs::read_dir(dir.as_ref()) {
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.extension().and_then(|e| e.to_str()) == Some("jsonl") {
+                let modified = entry.metadata().and_then(|m| m.modified()).ok();
+                sessions.push((modified, path.file_stem().unwrap_or_default().to_string_lossy().to_string()));
+            }
+        }
+    }
+    sessions.sort();
+    Ok(sessions.into_iter().map(|(_, session)| session).collect())
+}
@@ -0,0 +1,252 @@
+use anyhow::{Result, anyhow};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+use tokio::sync::OnceCell;
+use tracing::info;
+use crate::egress;
+use crate::embeddings::Embedder;
+use crate::jail::FsJail;
+use crate::documents::DocumentKind;
+use crate::http_cache::fetch_bytes;
+use crate::politeness::Politeness;
+use crate::vectorstore::{Record, VectorStore};
+use rhai::Engine;
+use crate::tools::NativeContext;
+
+/// A chunk returned by `retrieve`
+#[derive(Debug, Clone)]
+pub struct Passage {
+    pub source: String,
+    pub index: usize,
+    pub text: String,
+    pub score: f32,
+}
+
+/// Split `text` into chunks of `size` words, each overlapping the previous by `overlap` words
+pub fn chunk_text(text: &str, size: usize, overlap: usize) -> Vec<String> {
+    let words: Vec<&str> = text.split_whitespace().collect();
+    if words.is_empty() {
+        return Vec::new();
+    }
+    let size = size.max(1);
+    let step = size.saturating_sub(overlap).max(1);
+    let mut chunks = Vec::new();
+    let mut start = 0;
+    loop {
+        let end = (start + size).min(words.len());
+        chunks.push(words[start..end].join(" "));
+        if end == words.len() {
+            break;
+        }
+        start += step;
+    }
+    chunks
+}
+
+/// Visible text of an HTML page
+pub fn html_to_text(html: &str) -> String {
+    let document = scraper::Html::parse_document(html);
+    let selector = scraper::Selector::parse("body").unwrap();
+    match document.select(&selector).next() {
+        Some(body) => body.text().collect::<Vec<_>>().join(" ").split_whitespace().collect::<Vec<_>>().join(" "),
+        None => String::new(),
+    }
+}
+
+/// Local knowledge base for retrieval-augmented answers: documents are chunked, embedded
+/// and kept in a `VectorStore` (one record per chunk, id `source#index`) that survives restarts
+#[derive(Clone)]
+pub struct KnowledgeBase {
+    store: VectorStore,
+    embedder: Arc<OnceCell<Embedder>>,
+    chunk_words: usize,
+    overlap_words: usize,
+    politeness: Politeness,
+}
+
+impl KnowledgeBase {
+    pub fn open(path: impl AsRef<Path>, embedder: Option<Embedder>) -> Result<Self> {
+        Ok(Self {
+            store: VectorStore::open(path)?,
+            embedder: Arc::new(OnceCell::new_with(embedder)),
+            chunk_words: 200,
+            overlap_words: 40,
+            politeness: Politeness::default(),
+        })
+    }
+
+    /// Open `AGENT_KNOWLEDGE` (default `state/knowledge.jsonl`) with the embedder from the
+    /// environment; chunking from `KNOWLEDGE_CHUNK_WORDS` (200) and `KNOWLEDGE_CHUNK_OVERLAP` (40),
+    /// URLs fetched as `Politeness::from_env` says
+    pub fn from_env() -> Result<Self> {
+        let path = std::env::var("AGENT_KNOWLEDGE").unwrap_or_else(|_| "state/knowledge.jsonl".to_string());
+        let parse = |key: &str| std::env::var(key).ok().and_then(|v| v.parse().ok());
+        let mut kb = Self::open(path, None)?.with_politeness(Politeness::from_env());
+        kb.chunk_words = parse("KNOWLEDGE_CHUNK_WORDS").unwrap_or(kb.chunk_words);
+        kb.overlap_words = parse("KNOWLEDGE_CHUNK_OVERLAP").unwrap_or(kb.overlap_words);
+        Ok(kb)
+    }
+
+    pub fn with_chunking(mut self, chunk_words: usize, overlap_words: usize) -> Self {
+        self.chunk_words = chunk_words;
+        self.overlap_words = overlap_words;
+        self
+    }
+
+    /// Fetch URLs as `politeness` says, e.g. sharing host budgets with the scraping tools
+    pub fn with_politeness(mut self, politeness: Politeness) -> Self {
+        self.politeness = politeness;
+        self
+    }
+
+    pub fn path(&self) -> &Path {
+        self.store.path().expect("knowledge base is always file backed")
+    }
+
+    pub fn store(&self) -> &VectorStore {
+        &self.store
+    }
+
+    async fn embedder(&self) -> &Embedder {
+        self.embedder.get_or_init(Embedder::from_env).await
+    }
+
+    /// Ingest a URL or a file inside `jail`, replacing any earlier version of the same source
+    pub async fn ingest(&self, source: &str, jail: &FsJail) -> Result<usize> {
+        let text = if source.starts_with("http://") || source.starts_with("https://") {
+            let (bytes, content_type) = fetch_bytes(&self.politeness, source).await.map_err(|e| anyhow!("Error fetching {}: {}", source, e))?;
+            match DocumentKind::detect(source, content_type.as_deref(), &bytes) {
+                Some(kind) => kind.extract(&bytes)?,
+                None => {
+                    let body = String::from_utf8_lossy(&bytes);
+                    if content_type.is_none_or(|t| t.contains("html")) { html_to_text(&body) } else { body.into_owned() }
+                }
+            }
+        } else {
+            let path = jail.resolve(source)?;
+            let bytes = fs::read(&path).map_err(|e| anyhow!("Error reading {}: {}", source, e))?;
+            match DocumentKind::detect(source, None, &bytes) {
+                Some(kind) => kind.extract(&bytes)?,
+                None => {
+                    let body = String::from_utf8(bytes).map_err(|e| anyhow!("Error reading {}: {}", source, e))?;
+                    let is_html = path.extension().and_then(|e| e.to_str()).map(|e| e == "html" || e == "htm").unwrap_or(false);
+                    if is_html { html_to_text(&body) } else { body }
+                }
+            }
+        };
+        self.ingest_text(source, &text).await
+    }
+
+    /// Chunk, embed and store `text` under `source`; returns the number of chunks
+    pub async fn ingest_text(&self, source: &str, text: &str) -> Result<usize> {
+        let pieces = chunk_text(text, self.chunk_words, self.overlap_words);
+        let embedder = self.embedder().await;
+        let vectors = embedder.embed_batch(&pieces).await?;
+        let model = embedder.label();
+
+        self.remove_source(source)?;
+        let records: Vec<Record> = pieces.into_iter().zip(vectors).enumerate()
+            .map(|(index, (text, vector))| Record {
+                id: format!("{}#{}", source, index),
+                vector,
+                metadata: serde_json::json!({ "source": source, "index": index, "text": text, "model": model }),
+            })
+            .collect();
+        let count = records.len();
+        self.store.add(records)?;
+        Ok(count)
+    }
+
+    /// The `k` chunks most similar to `query`
+    pub async fn retrieve(&self, query: &str, k: usize) -> Result<Vec<Passage>> {
+        let embedder = self.embedder().await;
+        let query = embedder.embed(query).await?;
+        let model = embedder.label();
+
+        // Vectors from a different embedding model aren't comparable
+        Ok(self.store.search_where(&query, k, |m| m["model"] == model.as_str())?
+            .into_iter()
+            .map(|hit| Passage {
+                source: hit.metadata["source"].as_str().unwrap_or_default().to_string(),
+                index: hit.metadata["index"].as_u64().unwrap_or(0) as usize,
+                text: hit.metadata["text"].as_str().unwrap_or_default().to_string(),
+                score: hit.score,
+            })
+            .collect())
+    }
+
+    /// Ingested sources with their chunk counts
+    pub fn sources(&self) -> Result<Vec<(String, usize)>> {
+        let mut counts: BTreeMap<String, usize> = BTreeMap::new();
+        for record in self.store.records()? {
+            if let Some(source) = record.metadata["source"].as_str() {
+                *counts.entry(source.to_string()).or_default() += 1;
+            }
+        }
+        Ok(counts.into_iter().collect())
+    }
+
+    pub fn remove_source(&self, source: &str) -> Result<usize> {
+        self.store.delete_where(|m| m["source"] == source)
+    }
+}
+
+/// `ingest_document` and `retrieve` on `ctx.knowledge`
+pub(crate) fn register_knowledge_natives(engine: &mut Engine, ctx: &NativeContext) {
+    let NativeContext { jail, audit, safety, meter, knowledge, .. } = ctx;
+    // Knowledge base (RAG): ingest documents once, retrieve relevant chunks later
+    let knowledge_clone = knowledge.clone();
+    let jail_clone = jail.clone();
+    let safety_clone = safety.clone();
+    let audit_clone = audit.clone();
+    let meter_clone = meter.clone();
+    engine.register_fn("ingest_document", move |source: &str| -> String {
+        if source.starts_with("http://") || source.starts_with("https://") {
+            if let Err(e) = egress::guard(&safety_clone.lock().unwrap(), source, "ingest_document", Some(&audit_clone)) {
+                return format!("Error: {}", e);
+            }
+        }
+        if let Err(e) = meter_clone("ingest_document") {
+            return format!("Error: {}", e);
+        }
+        info!("Ingesting: {}", source);
+        let knowledge = knowledge_clone.clone();
+        let jail = jail_clone.clone();
+        let source = source.to_string();
+        std::thread::spawn(move || {
+            let rt = tokio::runtime::Runtime::new().unwrap();
+            rt.block_on(async {
+                match knowledge.ingest(&source, &jail).await {
+                    Ok(chunks) => format!("Ingested {} chunks from {}", chunks, source),
+                    Err(e) => format!("Error ingesting {}: {}", source, e),
+                }
+            })
+        }).join().unwrap_or_else(|_| "Thread panic".to_string())
+    });
+
+    let retrieve = {
+        let knowledge = knowledge.clone();
+        move |query: &str, k: i64| -> String {
+            let knowledge = knowledge.clone();
+            let query = query.to_string();
+            std::thread::spawn(move || {
+                let rt = tokio::runtime::Runtime::new().unwrap();
+                rt.block_on(async {
+                    match knowledge.retrieve(&query, k.max(1) as usize).await {
+                        Ok(passages) if passages.is_empty() => "No relevant documents found".to_string(),
+                        Ok(passages) => passages.iter().enumerate()
+                            .map(|(i, p)| format!("[{}] {} #{} ({:.2})\n{}", i + 1, p.source, p.index, p.score, p.text))
+                            .collect::<Vec<_>>()
+                            .join("\n\n"),
+                        Err(e) => format!("Error retrieving: {}", e),
+                    }
+                })
+            }).join().unwrap_or_else(|_| "Thread panic".to_string())
+        }
+    };
+    let retrieve_default = retrieve.clone();
+    engine.register_fn("retrieve", retrieve);
+    engine.register_fn("retrieve", move |query: &str| -> String { retrieve_default(query, 3) });
+}
@@ -1,5 +1,83 @@
 pub mod llm;
+pub mod provider;
 pub mod agent;
 pub mod tools;
 pub mod ipc;
 pub mod message;
+pub mod jail;
+pub mod compare;
+pub mod ensemble;
+pub mod state;
+pub mod threads;
+pub mod blackboard;
+pub mod audit;
+pub mod command;
+pub mod database;
+pub mod data;
+pub mod report;
+pub mod citations;
+pub mod workspace;
+pub mod changes;
+pub mod memory;
+pub mod backend;
+pub mod python;
+pub mod tool_cache;
+pub mod retry;
+pub mod context;
+pub mod response_cache;
+pub mod http_cache;
+pub mod politeness;
+pub mod usage;
+pub mod embeddings;
+pub mod vectorstore;
+pub mod tool_index;
+pub mod knowledge;
+pub mod inflate;
+pub mod pdf;
+pub mod documents;
+pub mod papers;
+pub mod prompts;
+pub mod persona;
+pub mod provenance;
+pub mod orchestrator;
+pub mod planner;
+pub mod registry;
+pub mod election;
+pub mod auth;
+pub mod encryption;
+pub mod inbox;
+pub mod outbox;
+pub mod transport;
+pub mod grpc;
+pub mod limits;
+pub mod server;
+pub mod trust;
+pub mod safety;
+pub mod security_review;
+pub mod sandbox;
+pub mod quarantine;
+pub mod egress;
+pub mod halt;
+pub mod lineage;
+pub mod supervisor;
+pub mod secrets;
+pub mod journal;
+pub mod replay;
+pub mod headless;
+pub mod config;
+pub mod cli;
+pub mod dashboard;
+pub mod mcp;
+pub mod parser;
+pub mod repair;
+pub mod review;
+pub mod bundle;
+pub mod tool_history;
+pub mod scheduler;
+pub mod jobs;
+pub mod cancel;
+pub mod logging;
+pub mod metrics;
+pub mod telemetry;
+pub mod runtime;
+pub mod events;
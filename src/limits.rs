@@ -0,0 +1,144 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Largest message body the IPC server reads unless `IPC_MAX_BODY_BYTES` says otherwise
+pub const DEFAULT_MAX_BODY_BYTES: usize = 2 * 1024 * 1024;
+
+/// Messages a peer may send per minute unless `IPC_RATE_LIMIT` says otherwise
+pub const DEFAULT_RATE_LIMIT: u32 = 600;
+
+// Beyond this many tracked peers, buckets that have refilled are dropped
+const MAX_TRACKED: usize = 4096;
+
+/// Token buckets keyed by peer: each holds up to a minute's allowance and refills steadily
+#[derive(Debug, Clone)]
+pub struct RateLimiter {
+    per_minute: u32,
+    buckets: Arc<Mutex<HashMap<String, (f64, Instant)>>>,
+}
+
+impl RateLimiter {
+    pub fn per_minute(per_minute: u32) -> Self {
+        Self { per_minute, buckets: Arc::new(Mutex::new(HashMap::new())) }
+    }
+
+    /// How many calls a minute each key is allowed
+    pub fn limit(&self) -> u32 {
+        self.per_minute
+    }
+
+    /// Spend one message from `key`'s budget, or say how long until one is available
+    pub fn check(&self, key: &str) -> Result<(), Duration> {
+        self.take(key, true)
+    }
+
+    /// `check`, without spending anything
+    pub fn available(&self, key: &str) -> Result<(), Duration> {
+        self.take(key, false)
+    }
+
+    fn take(&self, key: &str, spend: bool) -> Result<(), Duration> {
+        let capacity = self.per_minute as f64;
+        let per_sec = capacity / 60.0;
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().unwrap();
+        if buckets.len() > MAX_TRACKED {
+            buckets.retain(|_, (tokens, at)| *tokens + now.duration_since(*at).as_secs_f64() * per_sec < capacity);
+        }
+        let (tokens, at) = buckets.entry(key.to_string()).or_insert((capacity, now));
+        *tokens = (*tokens + now.duration_since(*at).as_secs_f64() * per_sec).min(capacity);
+        *at = now;
+        if *tokens < 1.0 {
+            return Err(Duration::from_secs_f64((1.0 - *tokens) / per_sec));
+        }
+        if spend {
+            *tokens -= 1.0;
+        }
+        Ok(())
+    }
+}
+
+/// What the IPC server accepts from any one peer
+#[derive(Debug, Clone)]
+pub struct Limits {
+    pub max_body_bytes: usize,
+    /// None when rate limiting is off
+    pub rate: Option<RateLimiter>,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self { max_body_bytes: DEFAULT_MAX_BODY_BYTES, rate: Some(RateLimiter::per_minute(DEFAULT_RATE_LIMIT)) }
+    }
+}
+
+impl Limits {
+    /// `per_minute` of 0 turns rate limiting off
+    pub fn new(max_body_bytes: usize, per_minute: u32) -> Self {
+        Self { max_body_bytes, rate: (per_minute > 0).then(|| RateLimiter::per_minute(per_minute)) }
+    }
+
+    /// `IPC_MAX_BODY_BYTES` (default 2 MiB) and `IPC_RATE_LIMIT` messages per minute (default 600, 0 = off)
+    pub fn from_env() -> Self {
+        let max_body_bytes = std::env::var("IPC_MAX_BODY_BYTES").ok()
+            .and_then(|v| v.trim().parse().ok())
+            .unwrap_or(DEFAULT_MAX_BODY_BYTES);
+        let per_minute = std::env::var("IPC_RATE_LIMIT").ok()
+            .and_then(|v| v.trim().parse().ok())
+            .unwrap_or(DEFAULT_RATE_LIMIT);
+        Self::new(max_body_bytes, per_minute)
+    }
+
+    /// Count a message against `key`, an agent name or IP
+    pub fn check(&self, key: &str) -> Result<(), Duration> {
+        match &self.rate {
+            Some(rate) => rate.check(key),
+            None => Ok(()),
+        }
+    }
+}
+
+/// The safety policy's per-tool call budgets: one bucket per tool, started afresh when the
+/// policy changes the tool's limit
+#[derive(Debug, Clone, Default)]
+pub struct ToolRateLimits {
+    limiters: Arc<Mutex<HashMap<String, RateLimiter>>>,
+}
+
+impl ToolRateLimits {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Spend one call of `tool`, allowed `per_minute` (at least 1), or say how long until one is available
+    pub fn check(&self, tool: &str, per_minute: u32) -> Result<(), Duration> {
+        let mut limiters = self.limiters.lock().unwrap();
+        limiter(&mut limiters, tool, per_minute).check(tool)
+    }
+
+    /// Spend one call of each of `tools` with their limits, or of none, naming the first whose
+    /// budget is spent and how long until it has a call again
+    pub fn check_all<'a>(&self, tools: &[(&'a str, u32)]) -> Result<(), (&'a str, u32, Duration)> {
+        let mut limiters = self.limiters.lock().unwrap();
+        for (tool, per_minute) in tools {
+            limiter(&mut limiters, tool, *per_minute).available(tool).map_err(|wait| (*tool, *per_minute, wait))?;
+        }
+        for (tool, per_minute) in tools {
+            let _ = limiter(&mut limiters, tool, *per_minute).check(tool);
+        }
+        Ok(())
+    }
+}
+
+/// The bucket of `tool`, started afresh when its limit is no longer `per_minute`
+fn limiter(limiters: &mut HashMap<String, RateLimiter>, tool: &str, per_minute: u32) -> RateLimiter {
+    match limiters.get(tool) {
+        Some(limiter) if limiter.limit() == per_minute => limiter.clone(),
+        _ => {
+            let limiter = RateLimiter::per_minute(per_minute);
+            limiters.insert(tool.to_string(), limiter.clone());
+            limiter
+        }
+    }
+}
@@ -0,0 +1,303 @@
+use anyhow::{anyhow, Result};
+use rhai::{Dynamic, Engine};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use crate::message::clone_id;
+use crate::registry::unix_now;
+use crate::state::StateStore;
+use crate::supervisor::CloneProcess;
+use crate::tools::{copy_agent, Cloner, NativeContext};
+
+const CLONES_KEY: &str = "clones";
+
+/// Written into each clone's directory by its parent
+pub const LINEAGE_FILE: &str = "lineage.json";
+
+/// Where an agent comes from: read from `lineage.json` in the directory it runs in
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Lineage {
+    pub id: String,
+    /// 0 for an agent that isn't a clone, its parent's plus one otherwise
+    pub generation: u32,
+    pub parent_id: Option<String>,
+    /// Where the parent ran from
+    pub parent_dir: Option<String>,
+    /// Ids of every ancestor, oldest first
+    #[serde(default)]
+    pub ancestors: Vec<String>,
+    pub created_at: u64,
+    /// Persona the clone was made to run as
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub persona: Option<String>,
+}
+
+impl Lineage {
+    /// An agent nobody cloned
+    pub fn root() -> Self {
+        Self { id: "root".to_string(), generation: 0, parent_id: None, parent_dir: None, ancestors: Vec::new(), created_at: 0, persona: None }
+    }
+
+    /// The lineage recorded in `dir`, or `root` if there is none
+    pub fn load(dir: &Path) -> Result<Self> {
+        let path = dir.join(LINEAGE_FILE);
+        if !path.exists() {
+            return Ok(Self::root());
+        }
+        serde_json::from_str(&fs::read_to_string(&path)?).map_err(|e| anyhow!("Invalid lineage file {:?}: {}", path, e))
+    }
+
+    /// This agent's lineage, from the working directory
+    pub fn current() -> Result<Self> {
+        Self::load(Path::new("."))
+    }
+
+    /// The lineage of a new clone of this agent, which runs from `parent_dir`
+    pub fn child(&self, parent_dir: &str) -> Self {
+        let mut ancestors = self.ancestors.clone();
+        ancestors.push(self.id.clone());
+        Self {
+            id: clone_id(),
+            generation: self.generation + 1,
+            parent_id: Some(self.id.clone()),
+            parent_dir: Some(parent_dir.to_string()),
+            ancestors,
+            created_at: unix_now(),
+            persona: None,
+        }
+    }
+
+    pub fn write(&self, dir: &Path) -> Result<()> {
+        fs::write(dir.join(LINEAGE_FILE), serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+}
+
+/// What `clone_agent` copies into a clone. The default copies every tool but leaves the
+/// `.env` behind.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CloneOptions {
+    /// Tool names or capabilities (such as `web`) to copy tools for, along with the tools
+    /// those call; `None` copies every tool
+    pub tools: Option<Vec<String>>,
+    /// Copy the agent's `.env`, less the values its secrets store holds
+    pub env: bool,
+    /// Env file the clone gets as its `.env` instead
+    pub config: Option<PathBuf>,
+    /// Persona the clone runs as, set as `AGENT_PERSONA` in its `.env`
+    pub persona: Option<String>,
+}
+
+impl CloneOptions {
+    /// Options from a script map such as `#{ tools: ["web", "square"], persona: "researcher" }`;
+    /// `.env` is only copied with `env: true`
+    pub fn from_map(map: &rhai::Map) -> Result<Self> {
+        let mut options = Self::default();
+        for (key, value) in map {
+            match key.as_str() {
+                "tools" => options.tools = Some(strings(value)?),
+                "env" => options.env = value.as_bool().map_err(|_| anyhow!("Clone option 'env' must be true or false"))?,
+                "config" => options.config = Some(PathBuf::from(value.to_string())),
+                "persona" => options.persona = Some(value.to_string()).filter(|p| !p.trim().is_empty()),
+                other => return Err(anyhow!("Unknown clone option '{}'; use tools, env, config or persona", other)),
+            }
+        }
+        if options.env && options.config.is_some() {
+            return Err(anyhow!("Clone options 'env' and 'config' both set the clone's .env; pick one"));
+        }
+        Ok(options)
+    }
+}
+
+/// An array of strings, or one comma-separated string
+fn strings(value: &Dynamic) -> Result<Vec<String>> {
+    if let Some(items) = value.clone().try_cast::<rhai::Array>() {
+        return Ok(items.iter().map(|item| item.to_string()).collect());
+    }
+    if value.is_string() {
+        return Ok(value.to_string().split(',').map(|s| s.trim().to_string()).filter(|s| !s.is_empty()).collect());
+    }
+    Err(anyhow!("Clone option 'tools' must be a list of tool or capability names"))
+}
+
+/// A copy of the agent made by `clone_agent`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CloneEntry {
+    pub dir: String,
+    /// Where the clone serves, once known; halts only reach clones with one
+    pub address: Option<String>,
+    pub created_at: u64,
+    /// Empty for a clone registered without a lineage file
+    #[serde(default)]
+    pub id: String,
+    #[serde(default)]
+    pub generation: u32,
+    /// Process id of the last `spawn_clone`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pid: Option<u32>,
+}
+
+/// The agent's clones, persisted in the agent state store
+#[derive(Debug, Clone)]
+pub struct CloneRegistry {
+    store: StateStore,
+}
+
+impl CloneRegistry {
+    pub fn new(store: StateStore) -> Self {
+        Self { store }
+    }
+
+    /// Note a clone at `dir` with `lineage`, replacing an earlier clone there but keeping its address
+    pub fn record(&self, dir: &str, lineage: &Lineage) -> Result<()> {
+        self.store.update(CLONES_KEY, |clones: &mut Vec<CloneEntry>| {
+            let address = clones.iter().find(|c| c.dir == dir).and_then(|c| c.address.clone());
+            clones.retain(|c| c.dir != dir);
+            clones.push(CloneEntry {
+                dir: dir.to_string(),
+                address,
+                created_at: lineage.created_at,
+                id: lineage.id.clone(),
+                generation: lineage.generation,
+                pid: None,
+            });
+        })
+    }
+
+    /// Note where the clone at `dir` serves, recording the clone (from its lineage file, if
+    /// it has one) if it is new
+    pub fn set_address(&self, dir: &str, address: &str) -> Result<()> {
+        let lineage = Lineage::load(Path::new(dir)).ok().filter(|l| l.generation > 0);
+        self.store.update(CLONES_KEY, |clones: &mut Vec<CloneEntry>| {
+            match clones.iter_mut().find(|c| c.dir == dir) {
+                Some(clone) => clone.address = Some(address.to_string()),
+                None => clones.push(CloneEntry {
+                    dir: dir.to_string(),
+                    address: Some(address.to_string()),
+                    created_at: unix_now(),
+                    id: lineage.as_ref().map(|l| l.id.clone()).unwrap_or_default(),
+                    generation: lineage.as_ref().map(|l| l.generation).unwrap_or_default(),
+                    pid: None,
+                }),
+            }
+        })
+    }
+
+    /// Note that the clone at `dir` was spawned as `pid`, serving at `address`
+    pub fn set_process(&self, dir: &str, pid: u32, address: &str) -> Result<()> {
+        self.store.update(CLONES_KEY, |clones: &mut Vec<CloneEntry>| {
+            if let Some(clone) = clones.iter_mut().find(|c| c.dir == dir) {
+                clone.pid = Some(pid);
+                clone.address = Some(address.to_string());
+            }
+        })
+    }
+
+    /// The clone with id or directory `clone`
+    pub fn find(&self, clone: &str) -> Result<Option<CloneEntry>> {
+        Ok(self.list()?.into_iter().find(|c| (!c.id.is_empty() && c.id == clone) || c.dir == clone))
+    }
+
+    pub fn list(&self) -> Result<Vec<CloneEntry>> {
+        Ok(self.store.get::<Vec<CloneEntry>>(CLONES_KEY)?.unwrap_or_default())
+    }
+
+    /// Clones whose directory still exists; deleted ones don't count against `max_count`
+    pub fn live_count(&self) -> Result<usize> {
+        Ok(self.list()?.iter().filter(|c| Path::new(&c.dir).exists()).count())
+    }
+
+    /// One line per clone, with what `status` says about its process
+    pub fn render(&self, status: impl Fn(&CloneEntry) -> String) -> Result<String> {
+        let clones = self.list()?;
+        if clones.is_empty() {
+            return Ok("No clones".to_string());
+        }
+        Ok(clones.iter()
+            .map(|c| {
+                let id = if c.id.is_empty() { "unknown id" } else { c.id.as_str() };
+                let mut line = format!("{} ({}, generation {})", c.dir, id, c.generation);
+                if let Some(address) = &c.address {
+                    line.push_str(&format!(" at {}", address));
+                }
+                if Path::new(&c.dir).exists() {
+                    line.push_str(&format!(": {}", status(c)));
+                } else {
+                    line.push_str(" [deleted]");
+                }
+                line
+            })
+            .collect::<Vec<_>>()
+            .join("\n"))
+    }
+}
+
+/// `clone_agent`, `list_clones` and `register_clone`; returns the cloner behind `clone_agent`
+pub(crate) fn register_clone_natives(engine: &mut Engine, ctx: &NativeContext) -> Cloner {
+    let NativeContext { config, audit, safety, clones, supervisor, secrets, .. } = ctx;
+    let audit_clone = audit.clone();
+    let safety_clone = safety.clone();
+    let clones_clone = clones.clone();
+    let secrets_clone = secrets.clone();
+    let config_clone = config.clone();
+    let clone_agent = Arc::new(move |target_dir: &str, options: &CloneOptions| -> String {
+        // The clone is one generation past us, and the limits apply to both
+        let allowed = Lineage::current().and_then(|parent| {
+            let safety = safety_clone.lock().unwrap();
+            safety.check_clone(Path::new(target_dir))?;
+            safety.check_replication(parent.generation + 1, clones_clone.live_count()?)?;
+            Ok(parent)
+        });
+        let result = match allowed {
+            Ok(parent) => {
+                let result = copy_agent(target_dir, &config_clone, options, &secrets_clone);
+                if result.starts_with("✅") {
+                    let here = std::env::current_dir().map(|d| d.display().to_string()).unwrap_or_default();
+                    let mut child = parent.child(&here);
+                    child.persona = options.persona.clone();
+                    match child.write(Path::new(target_dir)).and_then(|_| clones_clone.record(target_dir, &child)) {
+                        Ok(()) => format!("{} as {} (generation {})", result, child.id, child.generation),
+                        Err(e) => format!("{}, but its lineage was not recorded: {}", result, e),
+                    }
+                } else {
+                    result
+                }
+            }
+            Err(e) => format!("Error: {}", e),
+        };
+        audit_clone.record_or_warn("clone_agent", target_dir, &result);
+        result
+    });
+    let cloner: Cloner = clone_agent.clone();
+    let full_clone = clone_agent.clone();
+    engine.register_fn("clone_agent", move |target_dir: &str| -> String {
+        full_clone(target_dir, &CloneOptions::default())
+    });
+    // clone_agent(dir, #{ tools: [...], env: false, config: "...", persona: "..." }): a specialized clone
+    engine.register_fn("clone_agent", move |target_dir: &str, options: rhai::Map| -> String {
+        match CloneOptions::from_map(&options) {
+            Ok(options) => clone_agent(target_dir, &options),
+            Err(e) => format!("Error: {}", e),
+        }
+    });
+
+    let clones_clone = clones.clone();
+    let supervisor_clone = supervisor.clone();
+    engine.register_fn("list_clones", move || -> String {
+        clones_clone.render(|clone| match (supervisor_clone.status(&clone.dir), clone.pid) {
+            (CloneProcess::NotSupervised, Some(pid)) => format!("not running here (last pid {})", pid),
+            (status, _) => status.render(),
+        }).unwrap_or_else(|e| format!("Error: {}", e))
+    });
+
+    // register_clone: where a clone serves, so halts with clones reach it
+    let clones_clone = clones.clone();
+    engine.register_fn("register_clone", move |dir: &str, address: &str| -> String {
+        match clones_clone.set_address(dir, address) {
+            Ok(()) => format!("Clone at {} registered as serving at {}", dir, address),
+            Err(e) => format!("Error: {}", e),
+        }
+    });
+    cloner
+}
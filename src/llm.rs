@@ -1,8 +1,20 @@
 use anyhow::Result;
-use aws_config::BehaviorVersion;
-use aws_sdk_bedrockruntime::Client;
-use aws_sdk_bedrockruntime::types::{ContentBlock, Message as BedrockMessage, SystemContentBlock, ConversationRole};
+use aws_sdk_bedrockruntime::types::InferenceConfiguration;
+use base64::Engine as _;
+use base64::engine::general_purpose::STANDARD as BASE64;
 use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use crate::config::{Config, LlmConfig};
+use crate::metrics::metrics;
+use crate::provider::{providers, ChatProvider, OllamaChat, OnChunk, OpenAiChat};
+use crate::replay::RecordedResponses;
+use crate::response_cache::ResponseCache;
+use crate::retry::{transient, RetryPolicy};
+use tracing::{debug, info, info_span, warn, Instrument};
+use rhai::Engine;
+use crate::tools::NativeContext;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum Role {
@@ -10,160 +22,495 @@ pub enum Role {
     Assistant,
 }
 
+/// An image attached to a message, held as base64 so it serializes with the message
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ImageContent {
+    /// e.g. `image/png`
+    pub media_type: String,
+    pub data: String,
+}
+
+impl ImageContent {
+    /// Load an image file; the format is detected from its magic bytes
+    pub fn from_path(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let bytes = std::fs::read(path).map_err(|e| anyhow::anyhow!("Cannot read image {:?}: {}", path, e))?;
+        let media_type = detect_media_type(&bytes)
+            .ok_or_else(|| anyhow::anyhow!("Unsupported image format in {:?} (expected PNG, JPEG, GIF or WebP)", path))?;
+        Ok(Self { media_type: media_type.to_string(), data: BASE64.encode(bytes) })
+    }
+
+    /// Wrap already base64-encoded data, validating that it decodes
+    pub fn from_base64(media_type: impl Into<String>, data: impl Into<String>) -> Result<Self> {
+        let data = data.into();
+        BASE64.decode(&data).map_err(|e| anyhow::anyhow!("Invalid base64 image data: {}", e))?;
+        Ok(Self { media_type: media_type.into(), data })
+    }
+
+    pub fn bytes(&self) -> Result<Vec<u8>> {
+        BASE64.decode(&self.data).map_err(|e| anyhow::anyhow!("Invalid base64 image data: {}", e))
+    }
+
+    /// `png`, `jpeg`, `gif` or `webp`
+    pub fn format(&self) -> &str {
+        self.media_type.strip_prefix("image/").unwrap_or(&self.media_type)
+    }
+}
+
+fn detect_media_type(bytes: &[u8]) -> Option<&'static str> {
+    if bytes.starts_with(b"\x89PNG\r\n\x1a\n") {
+        Some("image/png")
+    } else if bytes.starts_with(&[0xFF, 0xD8, 0xFF]) {
+        Some("image/jpeg")
+    } else if bytes.starts_with(b"GIF87a") || bytes.starts_with(b"GIF89a") {
+        Some("image/gif")
+    } else if bytes.len() >= 12 && &bytes[..4] == b"RIFF" && &bytes[8..12] == b"WEBP" {
+        Some("image/webp")
+    } else {
+        None
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Message {
     pub role: Role,
     pub content: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub images: Vec<ImageContent>,
+}
+
+impl Message {
+    pub fn user(content: impl Into<String>) -> Self {
+        Self { role: Role::User, content: content.into(), images: Vec::new() }
+    }
+
+    pub fn assistant(content: impl Into<String>) -> Self {
+        Self { role: Role::Assistant, content: content.into(), images: Vec::new() }
+    }
+
+    pub fn with_image(mut self, image: ImageContent) -> Self {
+        self.images.push(image);
+        self
+    }
+}
+
+/// Token counts reported by the provider for a request
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct Usage {
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+}
+
+impl Usage {
+    pub fn total(&self) -> u64 {
+        self.input_tokens + self.output_tokens
+    }
+}
+
+impl std::ops::AddAssign for Usage {
+    fn add_assign(&mut self, other: Usage) {
+        self.input_tokens += other.input_tokens;
+        self.output_tokens += other.output_tokens;
+    }
+}
+
+/// A response with the tokens it used and the `provider:model` that produced it
+#[derive(Debug, Clone)]
+pub struct Completion {
+    pub text: String,
+    pub usage: Usage,
+    pub model: String,
+    /// Served from the response cache (no tokens spent)
+    pub cached: bool,
+}
+
+/// Sampling parameters sent with each request. Unset fields use the provider's defaults.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct GenerationConfig {
+    pub temperature: Option<f32>,
+    pub max_tokens: Option<u32>,
+    pub top_p: Option<f32>,
+    #[serde(default)]
+    pub stop: Vec<String>,
+}
+
+impl GenerationConfig {
+    /// Read `LLM_TEMPERATURE`, `LLM_MAX_TOKENS`, `LLM_TOP_P` and `LLM_STOP` (comma separated)
+    pub fn from_env() -> Self {
+        fn parse<T: std::str::FromStr>(key: &str) -> Option<T> {
+            std::env::var(key).ok().and_then(|v| v.trim().parse().ok())
+        }
+        Self {
+            temperature: parse("LLM_TEMPERATURE"),
+            max_tokens: parse("LLM_MAX_TOKENS"),
+            top_p: parse("LLM_TOP_P"),
+            stop: std::env::var("LLM_STOP")
+                .map(|v| v.split(',').map(String::from).filter(|s| !s.is_empty()).collect())
+                .unwrap_or_default(),
+        }
+    }
+
+    /// Apply per-call overrides on top of these settings
+    pub fn merge(&self, overrides: &GenerationConfig) -> GenerationConfig {
+        GenerationConfig {
+            temperature: overrides.temperature.or(self.temperature),
+            max_tokens: overrides.max_tokens.or(self.max_tokens),
+            top_p: overrides.top_p.or(self.top_p),
+            stop: if overrides.stop.is_empty() { self.stop.clone() } else { overrides.stop.clone() },
+        }
+    }
+
+    pub(crate) fn to_bedrock(&self) -> InferenceConfiguration {
+        InferenceConfiguration::builder()
+            .set_temperature(self.temperature)
+            .set_max_tokens(self.max_tokens.map(|t| t as i32))
+            .set_top_p(self.top_p)
+            .set_stop_sequences(if self.stop.is_empty() { None } else { Some(self.stop.clone()) })
+            .build()
+    }
+
+    /// Ollama `options` object
+    pub(crate) fn to_ollama(&self) -> serde_json::Value {
+        let mut options = serde_json::Map::new();
+        if let Some(t) = self.temperature {
+            options.insert("temperature".into(), t.into());
+        }
+        if let Some(n) = self.max_tokens {
+            options.insert("num_predict".into(), n.into());
+        }
+        if let Some(p) = self.top_p {
+            options.insert("top_p".into(), p.into());
+        }
+        if !self.stop.is_empty() {
+            options.insert("stop".into(), self.stop.clone().into());
+        }
+        options.into()
+    }
+
+    /// Add the OpenAI request fields to `payload`
+    pub(crate) fn apply_openai(&self, payload: &mut serde_json::Value) {
+        if let Some(t) = self.temperature {
+            payload["temperature"] = t.into();
+        }
+        if let Some(n) = self.max_tokens {
+            payload["max_tokens"] = n.into();
+        }
+        if let Some(p) = self.top_p {
+            payload["top_p"] = p.into();
+        }
+        if !self.stop.is_empty() {
+            payload["stop"] = self.stop.clone().into();
+        }
+    }
+}
+
+/// Settings for the OpenAI chat completions API and compatible endpoints
+#[derive(Debug, Clone)]
+pub struct OpenAiConfig {
+    /// e.g. `https://api.openai.com/v1`, or `https://<resource>.openai.azure.com/openai/deployments/<deployment>` for Azure
+    pub base_url: String,
+    pub api_key: Option<String>,
+    pub organization: Option<String>,
+    /// Set for Azure OpenAI: sent as `api-version` and switches auth to the `api-key` header
+    pub api_version: Option<String>,
 }
 
-pub enum LlmProvider {
-    Bedrock,
-    Ollama,
+impl OpenAiConfig {
+    /// Read `OPENAI_BASE_URL`, `OPENAI_API_KEY`, `OPENAI_ORG` and `OPENAI_API_VERSION`
+    pub fn from_env() -> Self {
+        Self {
+            base_url: std::env::var("OPENAI_BASE_URL").unwrap_or_else(|_| "https://api.openai.com/v1".to_string()),
+            api_key: std::env::var("OPENAI_API_KEY").ok(),
+            organization: std::env::var("OPENAI_ORG").ok(),
+            api_version: std::env::var("OPENAI_API_VERSION").ok(),
+        }
+    }
 }
 
+/// How long one request may take when `llm.timeout_secs` doesn't say
+pub const DEFAULT_LLM_TIMEOUT: Duration = Duration::from_secs(120);
+
+#[derive(Clone)]
 pub struct LlmClient {
-    client: Option<Client>, // Optional because Ollama doesn't need it
-    model_id: String,
-    provider: LlmProvider,
-    ollama_url: String,
+    provider: Arc<dyn ChatProvider>,
+    generation: GenerationConfig,
+    retry: RetryPolicy,
+    /// Per attempt; one that runs out is retried like any transient failure
+    timeout: Option<Duration>,
+    /// Secondary client used when this one keeps failing
+    fallback: Option<Box<LlmClient>>,
+    cache: Option<ResponseCache>,
+    /// Recorded responses handed out instead of asking the provider
+    replay: Option<RecordedResponses>,
 }
 
 impl LlmClient {
     pub async fn new() -> Result<Self> {
-        let provider_str = std::env::var("LLM_PROVIDER").unwrap_or_else(|_| "bedrock".to_string());
-        
-        let (provider, client) = match provider_str.to_lowercase().as_str() {
-            "ollama" => (LlmProvider::Ollama, None),
-            _ => {
-                let config = aws_config::load_defaults(BehaviorVersion::latest()).await;
-                (LlmProvider::Bedrock, Some(Client::new(&config)))
-            }
-        };
+        Self::from_config(&Config::from_env()?.llm).await
+    }
 
-        let model_id = std::env::var("MODEL_ID").unwrap_or_else(|_| {
-            match provider {
-                LlmProvider::Bedrock => "anthropic.claude-3-sonnet-20240229-v1:0".to_string(),
-                LlmProvider::Ollama => "llama3.1".to_string(),
-            }
-        });
-        
-        let ollama_url = std::env::var("OLLAMA_URL").unwrap_or_else(|_| "http://localhost:11434/api/chat".to_string());
+    /// Create a client for the configured provider, model, Ollama endpoint and fallback
+    pub async fn from_config(config: &LlmConfig) -> Result<Self> {
+        let timeout = Some(Duration::from_secs(config.timeout_secs)).filter(|t| !t.is_zero());
+        let mut client = Self::with_model(&config.provider, config.model.clone()).await?.with_timeout(timeout);
+        if let Some(url) = &config.ollama_url {
+            client = client.with_ollama_url(url);
+        }
 
-        Ok(Self {
-            client,
-            model_id,
-            provider,
-            ollama_url,
-        })
+        // Optional failover target as a `provider:model` spec
+        if let Some(spec) = config.fallback.as_ref().filter(|s| !s.trim().is_empty()) {
+            client = client.with_fallback(Self::from_spec(spec).await?.with_timeout(timeout));
+        }
+        Ok(client)
     }
 
-    pub async fn chat(&self, messages: Vec<Message>, system_prompt: Option<String>) -> Result<String> {
-        match self.provider {
-            LlmProvider::Bedrock => self.chat_bedrock(messages, system_prompt).await,
-            LlmProvider::Ollama => self.chat_ollama(messages, system_prompt).await,
+    /// Create a client for a registered provider (see `provider::providers`), using its
+    /// default model if none is given
+    pub async fn with_model(provider: &str, model_id: Option<String>) -> Result<Self> {
+        Ok(Self::with_provider(providers().create(provider, model_id).await?))
+    }
+
+    /// Create a client for `provider`, registered or not
+    pub fn with_provider(provider: Arc<dyn ChatProvider>) -> Self {
+        Self {
+            provider,
+            generation: GenerationConfig::from_env(),
+            retry: RetryPolicy::from_env(),
+            timeout: Some(DEFAULT_LLM_TIMEOUT),
+            fallback: None,
+            cache: ResponseCache::from_env(),
+            replay: None,
         }
     }
 
-    async fn chat_bedrock(&self, messages: Vec<Message>, system_prompt: Option<String>) -> Result<String> {
-        let client = self.client.as_ref().ok_or_else(|| anyhow::anyhow!("Bedrock client not initialized"))?;
-        
-        // Convert generic messages to Bedrock messages
-        let bedrock_messages: Vec<BedrockMessage> = messages.into_iter().map(|m| {
-            let role = match m.role {
-                Role::User => ConversationRole::User,
-                Role::Assistant => ConversationRole::Assistant,
-            };
-            BedrockMessage::builder()
-                .role(role)
-                .content(ContentBlock::Text(m.content))
-                .build()
-                .unwrap() // Should be safe
-        }).collect();
-
-        let mut request = client
-            .converse()
-            .model_id(&self.model_id)
-            .set_messages(Some(bedrock_messages));
-
-        if let Some(prompt) = system_prompt {
-             let system_block = SystemContentBlock::Text(prompt);
-             request = request.system(system_block);
-        }
-
-        let output = request.send().await.map_err(|e| anyhow::anyhow!("Bedrock error: {}", e))?;
-
-        if let Some(output_message) = output.output {
-            match output_message {
-                aws_sdk_bedrockruntime::types::ConverseOutput::Message(message) => {
-                     if let Some(content) = message.content.first() {
-                         match content {
-                             ContentBlock::Text(text) => return Ok(text.clone()),
-                             _ => return Ok("Received non-text response".to_string()),
-                         }
-                     }
-                }
-                _ => {}
-            }
+    /// A client that never calls a provider: each request gets the next of `responses`
+    pub async fn replaying(responses: RecordedResponses) -> Result<Self> {
+        Ok(Self::with_model("ollama", None).await?.with_cache(None).with_replay(responses))
+    }
+
+    /// Create a client from a `provider:model` spec, e.g. `ollama:llama3.1`.
+    /// The model part is optional.
+    pub async fn from_spec(spec: &str) -> Result<Self> {
+        let mut parts = spec.trim().splitn(2, ':');
+        let provider = parts.next().unwrap_or_default();
+        let model_id = parts.next().map(|m| m.trim().to_string()).filter(|m| !m.is_empty());
+        Self::with_model(provider, model_id).await
+    }
+
+    /// Override the Ollama chat endpoint; no effect on other providers
+    pub fn with_ollama_url(mut self, url: impl Into<String>) -> Self {
+        if self.provider.name() == "ollama" {
+            self.provider = Arc::new(OllamaChat::new(self.provider.model(), url));
         }
+        self
+    }
 
-        Ok("No response generated".to_string())
-    }
-
-    async fn chat_ollama(&self, messages: Vec<Message>, system_prompt: Option<String>) -> Result<String> {
-        let client = reqwest::Client::new();
-        
-        // Ollama format:
-        // { "model": "llama3", "messages": [ { "role": "user", "content": "..." } ], "stream": false }
-        // System prompt is usually just another message with role "system" at the start
-        
-        let mut ollama_messages = Vec::new();
-        
-        if let Some(prompt) = system_prompt {
-            ollama_messages.push(serde_json::json!({
-                "role": "system",
-                "content": prompt
-            }));
-        }
-        
-        for msg in messages {
-            let role = match msg.role {
-                Role::User => "user",
-                Role::Assistant => "assistant",
-            };
-            ollama_messages.push(serde_json::json!({
-                "role": role,
-                "content": msg.content
-            }));
+    /// Override the OpenAI-compatible endpoint settings; no effect on other providers
+    pub fn with_openai_config(mut self, config: OpenAiConfig) -> Self {
+        if self.provider.name() == "openai" {
+            self.provider = Arc::new(OpenAiChat::new(self.provider.model(), config));
         }
+        self
+    }
 
-        let payload = serde_json::json!({
-            "model": self.model_id,
-            "messages": ollama_messages,
-            "stream": false
-        });
+    /// Replace the default generation parameters
+    pub fn with_generation(mut self, generation: GenerationConfig) -> Self {
+        self.generation = generation;
+        self
+    }
+
+    pub fn generation(&self) -> &GenerationConfig {
+        &self.generation
+    }
+
+    pub fn with_retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// Give up on a request after `timeout` (None to wait as long as it takes)
+    pub fn with_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Fail over to `fallback` when this client still fails after its retries
+    pub fn with_fallback(mut self, fallback: LlmClient) -> Self {
+        self.fallback = Some(Box::new(fallback));
+        self
+    }
+
+    /// Serve repeated identical requests from `cache` (or disable caching with `None`)
+    pub fn with_cache(mut self, cache: Option<ResponseCache>) -> Self {
+        self.cache = cache;
+        self
+    }
+
+    /// Answer requests with `responses`, in order, instead of the provider
+    pub fn with_replay(mut self, responses: RecordedResponses) -> Self {
+        self.replay = Some(responses);
+        self
+    }
 
-        let resp = client.post(&self.ollama_url)
-            .json(&payload)
-            .send()
-            .await
-            .map_err(|e| anyhow::anyhow!("Ollama request error: {}", e))?;
+    pub fn provider(&self) -> &dyn ChatProvider {
+        self.provider.as_ref()
+    }
+
+    /// Human readable `provider:model` label
+    pub fn label(&self) -> String {
+        format!("{}:{}", self.provider.name(), self.provider.model())
+    }
+
+    /// Roughly how many tokens `text` uses with this model
+    pub fn count_tokens(&self, text: &str) -> usize {
+        self.provider.count_tokens(text)
+    }
+
+    /// Vectors for `texts` from the provider's embedding model
+    pub async fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+        self.provider.embed(texts).await
+    }
+
+    pub async fn chat(&self, messages: Vec<Message>, system_prompt: Option<String>) -> Result<String> {
+        self.chat_with(messages, system_prompt, &GenerationConfig::default()).await
+    }
+
+    /// Chat with per-call generation overrides on top of the client defaults
+    pub async fn chat_with(
+        &self,
+        messages: Vec<Message>,
+        system_prompt: Option<String>,
+        overrides: &GenerationConfig,
+    ) -> Result<String> {
+        self.complete(messages, system_prompt, overrides).await.map(|c| c.text)
+    }
 
-        if !resp.status().is_success() {
-            return Err(anyhow::anyhow!("Ollama API error: {}", resp.status()));
+    /// Like `chat_with`, but also reports token usage and which model answered
+    pub async fn complete(
+        &self,
+        messages: Vec<Message>,
+        system_prompt: Option<String>,
+        overrides: &GenerationConfig,
+    ) -> Result<Completion> {
+        if let Some(replay) = &self.replay {
+            return replay.next();
         }
+        let label = self.label();
+        let cache_key = self.cache.as_ref().map(|_| {
+            ResponseCache::key(&label, &messages, system_prompt.as_deref(), &self.generation.merge(overrides))
+        });
+        if let (Some(cache), Some(key)) = (&self.cache, &cache_key) {
+            if let Some(text) = cache.get(key) {
+                return Ok(Completion { text, usage: Usage::default(), model: label, cached: true });
+            }
+        }
+
+        let result = self.retry.run(&label, || async {
+            let attempt = self.chat_once(messages.clone(), system_prompt.clone(), overrides);
+            match self.timeout {
+                Some(timeout) => tokio::time::timeout(timeout, attempt).await
+                    .unwrap_or_else(|_| Err(transient(format!("{} did not answer within {:?}", label, timeout)))),
+                None => attempt.await,
+            }
+        }).await;
+
+        let result = match (result, &self.fallback) {
+            (Err(e), Some(fallback)) => {
+                warn!("{} failed ({}), failing over to {}", label, e, fallback.label());
+                Box::pin(fallback.complete(messages, system_prompt, overrides)).await
+                    .map_err(|e2| anyhow::anyhow!("Primary {} failed: {}; fallback failed: {}", label, e, e2))
+            }
+            (result, _) => result,
+        };
 
-        let resp_json: serde_json::Value = resp.json().await
-            .map_err(|e| anyhow::anyhow!("Failed to parse Ollama response: {}", e))?;
+        if let (Ok(completion), Some(cache), Some(key)) = (&result, &self.cache, &cache_key) {
+            if let Err(e) = cache.put(key, &completion.text) {
+                warn!("Failed to cache response: {}", e);
+            }
+        }
+        result
+    }
 
-        // Extract content from response
-        // Response format: { "message": { "role": "assistant", "content": "..." }, ... }
-        
-        if let Some(content) = resp_json.get("message")
-            .and_then(|m| m.get("content"))
-            .and_then(|c| c.as_str()) {
-            Ok(content.to_string())
-        } else {
-            Err(anyhow::anyhow!("Invalid response format from Ollama"))
+    /// Like `complete`, handing the answer to `on_chunk` piece by piece as it arrives. Once
+    /// part of it is out a request can't be taken back, so it isn't retried, cached or failed over.
+    pub async fn complete_stream(
+        &self,
+        messages: Vec<Message>,
+        system_prompt: Option<String>,
+        overrides: &GenerationConfig,
+        on_chunk: &OnChunk<'_>,
+    ) -> Result<Completion> {
+        if let Some(replay) = &self.replay {
+            let completion = replay.next()?;
+            on_chunk(&completion.text);
+            return Ok(completion);
         }
+        let generation = self.generation.merge(overrides);
+        let span = info_span!("llm_call", model = %self.label(), messages = messages.len(), stream = true);
+        async {
+            let started = Instant::now();
+            let attempt = self.provider.chat_stream(messages, system_prompt, &generation, on_chunk);
+            let answer = match self.timeout {
+                Some(timeout) => tokio::time::timeout(timeout, attempt).await
+                    .unwrap_or_else(|_| Err(transient(format!("{} did not answer within {:?}", self.label(), timeout)))),
+                None => attempt.await,
+            };
+            metrics().observe_llm(&self.label(), started.elapsed(), answer.as_ref().ok().map(|(_, usage)| usage));
+            let (text, usage) = answer?;
+            Ok(Completion { text, usage, model: self.label(), cached: false })
+        }.instrument(span).await
     }
+
+    async fn chat_once(
+        &self,
+        messages: Vec<Message>,
+        system_prompt: Option<String>,
+        overrides: &GenerationConfig,
+    ) -> Result<Completion> {
+        let generation = self.generation.merge(overrides);
+        let span = info_span!("llm_call", model = %self.label(), messages = messages.len());
+        async {
+            let started = Instant::now();
+            let answer = self.provider.chat(messages, system_prompt, &generation).await;
+            metrics().observe_llm(&self.label(), started.elapsed(), answer.as_ref().ok().map(|(_, usage)| usage));
+            let (text, usage) = answer?;
+            debug!(input_tokens = usage.input_tokens, output_tokens = usage.output_tokens, elapsed_ms = started.elapsed().as_millis() as u64, "LLM answered");
+            Ok(Completion { text, usage, model: self.label(), cached: false })
+        }.instrument(span).await
+    }
+}
+
+/// `analyze_image`
+pub(crate) fn register_vision_natives(engine: &mut Engine, ctx: &NativeContext) {
+    let NativeContext { jail, meter, .. } = ctx;
+    // Vision: describe an image in the workspace with a multimodal model
+    // (`VISION_MODEL` as a `provider:model` spec, defaulting to the main model)
+    let jail_clone = jail.clone();
+    let meter_clone = meter.clone();
+    engine.register_fn("analyze_image", move |path: &str, prompt: &str| -> String {
+        if let Err(e) = meter_clone("analyze_image") {
+            return format!("Error: {}", e);
+        }
+        info!("Analyzing image: {}", path);
+        let image = match jail_clone.resolve(path).and_then(ImageContent::from_path) {
+            Ok(image) => image,
+            Err(e) => return format!("Error: {}", e),
+        };
+        let message = Message::user(prompt).with_image(image);
+
+        std::thread::spawn(move || {
+            let rt = tokio::runtime::Runtime::new().unwrap();
+            rt.block_on(async {
+                let client = match std::env::var("VISION_MODEL") {
+                    Ok(spec) if !spec.trim().is_empty() => LlmClient::from_spec(&spec).await,
+                    _ => LlmClient::new().await,
+                };
+                match client {
+                    Ok(client) => client.chat(vec![message], None).await
+                        .unwrap_or_else(|e| format!("Error analyzing image: {}", e)),
+                    Err(e) => format!("Error creating vision client: {}", e),
+                }
+            })
+        }).join().unwrap_or_else(|_| "Thread panic".to_string())
+    });
 }
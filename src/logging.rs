@@ -0,0 +1,81 @@
+use anyhow::{anyhow, Result};
+use std::str::FromStr;
+use opentelemetry_sdk::trace::Tracer;
+use tracing::Subscriber;
+use tracing_subscriber::fmt::MakeWriter;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::EnvFilter;
+use crate::config::LogConfig;
+
+/// How log lines are written
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LogFormat {
+    /// Human-readable lines
+    #[default]
+    Text,
+    /// One JSON object per line, with the fields of the enclosing spans
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "text" => Ok(Self::Text),
+            "json" => Ok(Self::Json),
+            other => Err(anyhow!("expected text or json, not '{}'", other)),
+        }
+    }
+}
+
+/// A subscriber writing events at `config.level` and above to `writer`. `RUST_LOG`, when set,
+/// replaces the level (e.g. `RUST_LOG=info,swarm_thing::ipc=debug`).
+pub fn subscriber<W>(config: &LogConfig, writer: W) -> Result<Box<dyn Subscriber + Send + Sync>>
+where
+    W: for<'a> MakeWriter<'a> + Send + Sync + 'static,
+{
+    build(config, writer, None)
+}
+
+/// `subscriber`, also handing its spans to `tracer` so they are exported as OpenTelemetry spans.
+/// Spans the level filters out are not exported either.
+pub fn traced_subscriber<W>(config: &LogConfig, writer: W, tracer: Tracer) -> Result<Box<dyn Subscriber + Send + Sync>>
+where
+    W: for<'a> MakeWriter<'a> + Send + Sync + 'static,
+{
+    build(config, writer, Some(tracer))
+}
+
+fn build<W>(config: &LogConfig, writer: W, tracer: Option<Tracer>) -> Result<Box<dyn Subscriber + Send + Sync>>
+where
+    W: for<'a> MakeWriter<'a> + Send + Sync + 'static,
+{
+    let format: LogFormat = config.format.parse().map_err(|e| anyhow!("log.format: {}", e))?;
+    let filter = match EnvFilter::try_from_default_env() {
+        Ok(filter) => filter,
+        Err(_) => EnvFilter::try_new(&config.level).map_err(|e| anyhow!("log.level '{}': {}", config.level, e))?,
+    };
+    let builder = tracing_subscriber::fmt().with_env_filter(filter).with_writer(writer);
+    // The layer is typed by the subscriber it sits on, so each format builds its own
+    Ok(match format {
+        LogFormat::Text => {
+            let spans = tracer.map(|tracer| tracing_opentelemetry::layer().with_tracer(tracer));
+            Box::new(builder.finish().with(spans))
+        }
+        LogFormat::Json => {
+            let spans = tracer.map(|tracer| tracing_opentelemetry::layer().with_tracer(tracer));
+            Box::new(builder.json().with_current_span(true).with_span_list(true).finish().with(spans))
+        }
+    })
+}
+
+/// Log to stderr for the rest of the process, leaving stdout to the REPL and command output.
+/// With a `tracer`, spans are exported too (see `telemetry::tracer_provider`).
+pub fn init_logging(config: &LogConfig, tracer: Option<Tracer>) -> Result<()> {
+    let subscriber = match tracer {
+        Some(tracer) => traced_subscriber(config, std::io::stderr, tracer)?,
+        None => subscriber(config, std::io::stderr)?,
+    };
+    tracing::subscriber::set_global_default(subscriber).map_err(|e| anyhow!("Cannot install the logger: {}", e))
+}
@@ -1,64 +1,275 @@
-use anyhow::Result;
+use anyhow::{anyhow, Result};
+use clap::Parser;
 use dotenv::dotenv;
 use std::io::{self, Write};
+use std::sync::Arc;
 use text_colorizer::*;
 
-use swarm_thing::agent::Agent;
-use swarm_thing::tools::ToolManager;
+use swarm_thing::auth::IpcAuth;
+use swarm_thing::cli::{Cli, Command, ConfigCommand, ToolCommand};
+use swarm_thing::compare::Comparer;
+use swarm_thing::config::Config;
+use swarm_thing::logging::init_logging;
+use swarm_thing::telemetry::{tracer, tracer_provider};
+use opentelemetry_sdk::trace::TracerProvider;
+use swarm_thing::dashboard::{self, DashboardState, Transcript};
+use swarm_thing::mcp::McpServer;
+use swarm_thing::ipc::{serve_inbox, serve_tasks};
+use swarm_thing::orchestrator::{Member, Orchestrator};
+use swarm_thing::planner::{make_plan, render_results, summarize_results};
+use swarm_thing::persona::{personas_dir, Persona};
+use swarm_thing::secrets::Secrets;
+use swarm_thing::journal::{journal_dir, sessions, Journal, Replay};
+use swarm_thing::message::session_id;
+use swarm_thing::workspace::SessionWorkspace;
+use swarm_thing::replay::{load_replay, replay_session};
+use swarm_thing::events::{ask_for_approvals, AgentEvents, ApprovalRequest};
+use swarm_thing::inbox::InboxMessage;
+use swarm_thing::runtime::AgentRuntime;
+use swarm_thing::scheduler::serve_schedules;
+use swarm_thing::jobs::serve_jobs;
+use swarm_thing::headless::{exit_code, read_tasks, Runner, EXIT_USAGE};
+use swarm_thing::server::SHUTDOWN_GRACE;
+use swarm_thing::tools::{render_tool_log, ConfirmPolicy, ToolManager, AUDIT_SHOW_LIMIT};
 
 #[tokio::main]
 async fn main() -> Result<()> {
     dotenv().ok();
-    println!("{}", "Swarn Thing Initializing...".green().bold());
+    let cli = Cli::parse();
+    let command = cli.command.clone().unwrap_or(Command::Chat);
+    // `config show|validate`: the effective config, from swarm.toml and the environment
+    if let Command::Config(config_cmd) = command {
+        std::process::exit(config_command(config_cmd));
+    }
+    // Commands other than chat and serve leave stdout to their output
+    let scripted = command.is_scripted();
+    status(scripted, "Swarn Thing Initializing...".green().bold());
+
+    // Credentials not in the environment may be in the OS keyring
+    let from_keyring = Secrets::from_env().load_keyring();
+    if !from_keyring.is_empty() {
+        status(scripted, format!("🔑 From the OS keyring: {}", from_keyring.join(", ")));
+    }
+
+    // swarm.toml, with the environment overriding it; modules that read their setting from the
+    // environment see the file's values too
+    let mut config = Config::from_env()?;
+    config.export_env();
+    // telemetry.otlp_endpoint: spans also go to an OpenTelemetry collector
+    let traces = tracer_provider(&config)?;
+    init_logging(&config.log, traces.as_ref().map(tracer))?;
+    if let Some(path) = &config.path {
+        status(scripted, format!("⚙️  Config: {}", path.display()));
+    }
+    let serve_port = match &command {
+        Command::Serve { port } => Some(port.or(config.ipc.port).ok_or_else(|| anyhow!("serve needs --port or ipc.port in the config"))?),
+        _ => None,
+    };
 
-    let mut tool_manager = ToolManager::new()?;
+    // workspace.per_session: a session's file tools work in a directory of its own, named
+    // after its journal
+    let journal = if command.is_session() { Journal::from_env()? } else { None };
+    let workspace = if command.is_session() && config.workspace.per_session {
+        let session = journal.as_ref().map(|j| j.session().to_string()).unwrap_or_else(session_id);
+        let workspace = SessionWorkspace::start(&config.workspace, &session)?;
+        config.paths.workspace = workspace.path().to_path_buf();
+        status(scripted, format!("📁 Workspace: {}", workspace.path().display()));
+        Some(workspace)
+    } else {
+        None
+    };
+
+    let mut tool_manager = ToolManager::with_config(&config)?;
     tool_manager.load_tools()?;
+    // Risky tool runs are confirmed with the exact call, unless --auto-approve (or CONFIRM_RISK=off)
+    if cli.auto_approve {
+        status(scripted, "⚠️  --auto-approve: tools run without confirmation".yellow());
+        tool_manager.set_confirm_policy(ConfirmPolicy::auto_approve());
+    }
+    // Approvals are asked on stdin in the REPL; scripted runs decline what needs confirmation
+    let notify_inbox = serve_port.is_none() && std::env::var("INBOX_NOTIFY").map(|v| v != "false" && v != "0").unwrap_or(true);
+    let events = Arc::new(CliEvents { interactive: command.is_interactive(), scripted, notify_inbox });
+    ask_for_approvals(&tool_manager, events.clone());
     let tools_list = tool_manager.list_tools().join(", ");
-    println!(
+    status(scripted, format!(
         "Loaded {} tools: {}",
         tool_manager.list_tools().len(),
         tools_list
-    );
-
-    let system_prompt = format!(
-        r#"You are a Research Agent powered by Rust.
-You have the ability to create and use tools.
-Available Tools: [{}]
-
-IMPORTANT - Tool Reuse Policy:
-1. BEFORE creating any new tool, check if an existing tool can fulfill the request
-2. Use [TOOL: list_tools()] to see all available tools
-3. Use [TOOL: inspect_tool(name)] to understand what a tool does
-4. Consider composing multiple existing tools instead of creating a new one
-5. ONLY create a new tool if no existing tool or combination can solve the task
-
-Examples of Good Behavior:
-- User asks "square of 11" and 'square' tool exists → Use [TOOL: square(11)] directly
-- User asks "square and double" and 'double_square' exists → Use existing tool
-- User asks "square and double" and only 'square' exists → Create a new tool that calls square()
-- Only create new tools for genuinely new functionality
-
-IMPORTANT - Rhai Scripting Limitations:
-1. NO TUPLES: Rhai does not support tuples like `(a, b)`. Use arrays `[a, b]` or maps `#{a: 1, b: 2}` instead.
-2. NO STRUCTS: You cannot define structs. Use object maps `#{ field: value }`.
-3. RETURN VALUES: To return multiple values, return an array or object map.
-4. PRINTING: Use `print()` or `debug()` for logging.
-
-To create a tool (ONLY when necessary), output a code block with language 'rhai' and the filename in a comment:
-```rhai
-// filename: my_tool
-fn my_tool(args) {{
-    return "result";
-}}
-```
-
-To use a tool, use the format: [TOOL: tool_name(arg1, arg2)]
-If you need to calculate something or get data, check existing tools first, then create one if needed.
-"#,
-        tools_list
-    );
+    ));
+
+    // Optional persona (`--persona <name>` or AGENT_PERSONA): prompt, model and tool policy
+    let persona = match cli.persona.clone().or_else(|| config.agent.persona.clone()) {
+        Some(name) => {
+            let persona = Persona::load(personas_dir(), &name)?;
+            status(scripted, format!("🎭 Persona: {}", persona.name));
+            tool_manager.set_policy(persona.tools.clone());
+            Some(persona)
+        }
+        None => None,
+    };
+
+    // Commands that need no model
+    match &command {
+        // Re-run a journaled session on its recorded responses, without calling a provider,
+        // and report where it went differently
+        Command::Replay { target, execute } => {
+            let replay = load_replay(target)?;
+            let report = replay_session(&replay, &mut tool_manager, *execute).await?;
+            println!("{}", report.render());
+            std::process::exit(if report.is_faithful() { 0 } else { 1 });
+        }
+        Command::Tool(tool_cmd) => std::process::exit(tool_command(&mut tool_manager, tool_cmd)),
+        // Serve the tools to MCP clients, such as desktop assistants, until they hang up
+        Command::Mcp { sse } => {
+            let server = McpServer::from_env(tool_manager);
+            match sse {
+                Some(port) => server.serve_sse(*port).await?,
+                None => server.serve_stdio().await?,
+            }
+            return Ok(());
+        }
+        Command::Clone { dir, .. } => {
+            let options = command.clone_options().unwrap_or_default();
+            std::process::exit(match tool_manager.clone_agent(dir, &options) {
+                Ok(result) => {
+                    println!("{}", result);
+                    0
+                }
+                Err(e) => {
+                    eprintln!("{}", format!("Clone Error: {}", e).red());
+                    1
+                }
+            });
+        }
+        _ => {}
+    }
+
+    // Prompt templates, model, action protocol and journal as configured, or as the persona says
+    let mut builder = AgentRuntime::builder().config(config.clone()).tools(tool_manager).journal(journal).events(events);
+    if let Some(persona) = persona {
+        builder = builder.persona(persona);
+    }
+    let mut runtime = builder.build().await?;
+    // Tools of the MCP servers in swarm.toml are listed as mcp::<server>::<tool>
+    let mcp_tools = runtime.mcp_tools();
+    if !mcp_tools.is_empty() {
+        status(scripted, format!("🔌 {} MCP tools: {}", mcp_tools.len(), mcp_tools.join(", ")));
+    }
+    // Every step of this session, for /journal and later debugging
+    if let Some(journal) = runtime.journal() {
+        status(scripted, format!("📓 Journal: {}", journal.path().display()));
+    }
+
+    if let Command::Run { .. } | Command::Batch { .. } = &command {
+        let code = run_one_shot(&command, {
+            let (agent, tools) = runtime.into_parts();
+            Runner::new(agent, tools)
+        }
+            .with_max_steps(config.agent.max_steps)
+            .with_fix_attempts(config.agent.fix_attempts)).await;
+        finish_workspace(workspace.as_ref(), scripted);
+        flush_traces(traces);
+        std::process::exit(code);
+    }
+
+    // ipc.dashboard_port: a web page on this agent's conversation, tools, approvals, peers and audit log
+    let mut transcript = None;
+    if let Some(port) = config.ipc.dashboard_port {
+        let shared = Transcript::new();
+        match dashboard::start(DashboardState::new(runtime.tools(), shared.clone()), port).await {
+            Ok(url) => {
+                println!("📊 Dashboard at {}", url);
+                transcript = Some(shared);
+            }
+            Err(e) => println!("{}", format!("Dashboard Error: {}", e).red()),
+        }
+    }
+    runtime.agent_mut().set_transcript(transcript.clone());
+
+    // Tasks delegated to us over IPC are worked through in the background by a second agent
+    tokio::spawn(serve_tasks(
+        runtime.tools().tasks().clone(),
+        Member::new("tasks", "Works on tasks delegated by peer agents", runtime.helper_agent(), runtime.helper_tools()?, runtime.system_prompt()),
+    ));
 
-    let mut agent = Agent::new(&system_prompt).await?;
+    // Jobs saved by schedule_task run through a third agent as they fall due
+    tokio::spawn(serve_schedules(
+        runtime.tools().scheduler().clone(),
+        Member::new("scheduler", "Runs scheduled prompts and tools", runtime.helper_agent(), runtime.helper_tools()?, runtime.system_prompt()),
+    ));
+
+    // execute_tool_background runs tools on a pool of agent.job_workers workers, each with its own tools
+    for _ in 0..config.agent.job_workers {
+        let mut worker_tools = runtime.helper_tools()?;
+        worker_tools.set_jobs(runtime.tools().jobs().clone());
+        tokio::spawn(serve_jobs(runtime.tools().jobs().clone(), worker_tools));
+    }
+
+    // Messages to peers that were down are retried in the background, including ones left over from last run
+    tokio::spawn(runtime.tools().outbox().clone().run(std::time::Duration::from_secs(1)));
+
+    // Let the user know when a background job is done
+    if serve_port.is_none() {
+        let jobs = runtime.tools().jobs().clone();
+        tokio::spawn(async move {
+            loop {
+                for job in jobs.finished().await {
+                    println!("\n{}", format!("🔔 Background job {}, job_result({}) for its output", job.render(), job.id).yellow());
+                }
+            }
+        });
+    }
+
+    // Ctrl-C cancels the turn in flight; at the prompt it stops the IPC server gracefully and exits
+    let server = runtime.tools().server().clone();
+    let cancel = runtime.tools().cancel().clone();
+    tokio::spawn(async move {
+        while tokio::signal::ctrl_c().await.is_ok() {
+            if cancel.cancel() {
+                println!("\n{}", "⏹  Cancelling...".yellow());
+                continue;
+            }
+            let server = server.clone();
+            if let Some(address) = tokio::task::spawn_blocking(move || server.stop(SHUTDOWN_GRACE)).await.ok().flatten() {
+                println!("\n🛑 Server at {} stopped", address);
+            }
+            std::process::exit(130);
+        }
+    });
+
+    // Headless, as spawned clones run: serve peers, messages and delegated tasks until the server stops,
+    // e.g. on a Halt; there's no operator to ask
+    if let Some(port) = serve_port {
+        println!("{}", runtime.tools().execute_tool("start_server", vec![port.to_string()])?);
+        // Peers' text messages are answered by the agent loop, as delegated tasks are
+        let mut inbox_agent = runtime.helper_agent();
+        inbox_agent.set_transcript(transcript);
+        tokio::spawn(serve_inbox(
+            runtime.tools().inbox().clone(),
+            runtime.tools().threads().clone(),
+            Member::new("inbox", "Answers messages from peer agents", inbox_agent, runtime.helper_tools()?, runtime.system_prompt())
+                .with_max_steps(config.agent.max_steps),
+            runtime.tools().server().status().map(|s| s.address),
+        ));
+        while runtime.tools().server().is_running() {
+            tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+        }
+        println!("🛑 Server stopped, exiting");
+        finish_workspace(workspace.as_ref(), scripted);
+        flush_traces(traces);
+        return Ok(());
+    }
+
+    // ipc.port: serve peers alongside the REPL from the start
+    if let Some(port) = config.ipc.port {
+        match runtime.tools().execute_tool("start_server", vec![port.to_string()]) {
+            Ok(started) => println!("{}", started),
+            Err(e) => println!("{}", format!("Server Error: {}", e).red()),
+        }
+    }
+
+    let mut comparer: Option<Comparer> = None;
+    let mut orchestrator: Option<Orchestrator> = None;
 
     println!("{}", "Ready! Type 'exit' to quit.".green());
 
@@ -71,62 +282,471 @@ If you need to calculate something or get data, check existing tools first, then
         let input = input.trim();
 
         if input.eq_ignore_ascii_case("exit") {
+            runtime.tools().server().stop(SHUTDOWN_GRACE);
+            finish_workspace(workspace.as_ref(), scripted);
             break;
         }
 
-        match agent.chat(input).await {
-            Ok(response) => {
-                println!("{}", response.cyan());
-
-                // Simple parsing for tool creation (MVP)
-                if response.contains("```rhai") {
-                    // Extract code
-                    let parts: Vec<&str> = response.split("```rhai").collect();
-                    if let Some(code_part) = parts.get(1) {
-                        if let Some(code) = code_part.split("```").next() {
-                            // Extract filename from comment // filename: name
-                            let name = code
-                                .lines()
-                                .find(|l| l.contains("// filename:"))
-                                .map(|l| l.split(":").nth(1).unwrap_or("unknown").trim())
-                                .unwrap_or("unknown_tool");
-
-                            println!("{}", format!("Creating tool: {}", name).yellow());
-                            match tool_manager.create_tool(name, code) {
-                                Ok(msg) => println!("{}", msg.green()),
-                                Err(e) => {
-                                    println!("{}", format!("Error creating tool: {}", e).red())
-                                }
-                            }
-                        }
+        // /changes: the files this session's tools wrote or deleted
+        if input == "/changes" {
+            println!("{}", runtime.tools().changes().render().cyan());
+            continue;
+        }
+
+        // /undo [n]: revert the last n (default 1) of them
+        if let Some(n) = input.strip_prefix("/undo") {
+            let Ok(n) = (if n.trim().is_empty() { Ok(1) } else { n.trim().parse::<usize>() }) else {
+                println!("{}", "Usage: /undo [n]".yellow());
+                continue;
+            };
+            match runtime.tools().changes().undo(n) {
+                Ok(undone) if undone.is_empty() => println!("{}", "No file changes to undo".yellow()),
+                Ok(undone) => println!("{}", format!("↩️  {}", undone.join("\n")).green()),
+                Err(e) => println!("{}", format!("Undo Error: {}", e).red()),
+            }
+            continue;
+        }
+
+        // /workspace [open [path]]: the files this session produced, or open one (the workspace itself without a path)
+        if let Some(rest) = input.strip_prefix("/workspace") {
+            let Some(workspace) = &workspace else {
+                println!("{}", format!("No session workspace (workspace.per_session is off); files go to {}", runtime.tools().jail().root().display()).yellow());
+                continue;
+            };
+            let rest = rest.trim();
+            match rest.strip_prefix("open") {
+                Some(path) => match workspace.open(path) {
+                    Ok(opened) => println!("{}", format!("📂 Opened {}", opened.display()).green()),
+                    Err(e) => println!("{}", format!("Workspace Error: {}", e).red()),
+                },
+                None if rest.is_empty() => match workspace.render() {
+                    Ok(listing) => println!("{}", listing.cyan()),
+                    Err(e) => println!("{}", format!("Workspace Error: {}", e).red()),
+                },
+                None => println!("{}", "Usage: /workspace [open [path]]".yellow()),
+            }
+            continue;
+        }
+
+        // /inbox: unread messages from other agents
+        if input == "/inbox" {
+            let unread = runtime.tools().inbox().render_unread();
+            println!("{}", if unread.is_empty() { "No unread messages".to_string() } else { unread }.cyan());
+            continue;
+        }
+
+        // /jobs: background tool runs and how they went
+        if input == "/jobs" {
+            println!("{}", runtime.tools().jobs().render_status("").cyan());
+            continue;
+        }
+
+        // /audit [filter]: recent audit log entries, optionally only those mentioning `filter`
+        if let Some(filter) = input.strip_prefix("/audit") {
+            match runtime.tools().audit_log().render_search(filter, AUDIT_SHOW_LIMIT) {
+                Ok(entries) => println!("{}", entries.cyan()),
+                Err(e) => println!("{}", format!("Audit Error: {}", e).red()),
+            }
+            continue;
+        }
+
+        // /halt [--clones] [reason]: emergency stop, optionally passed on to registered clones
+        if let Some(rest) = input.strip_prefix("/halt") {
+            let rest = rest.trim();
+            let (clones, reason) = match rest.strip_prefix("--clones") {
+                Some(reason) => (true, reason.trim()),
+                None => (false, rest),
+            };
+            let reason = if reason.is_empty() { "operator halt" } else { reason };
+            println!("{}", runtime.tools().halt(reason, clones).red().bold());
+            continue;
+        }
+
+        // /resume: lift a halt; start_server again if the server should run
+        if input == "/resume" {
+            let message = if runtime.tools().resume() { "▶️  Resumed" } else { "Not halted" };
+            println!("{}", message.green());
+            continue;
+        }
+
+        // /secrets: the credentials the agent has, values masked
+        if input == "/secrets" {
+            let secrets = runtime.tools().secrets().redacted();
+            if secrets.is_empty() {
+                println!("{}", "No secrets".yellow());
+            }
+            for (name, masked) in secrets {
+                println!("{}", format!("{} = {}", name, masked).cyan());
+            }
+            continue;
+        }
+
+        // /secret <NAME>: store a credential in the OS keyring, read from the next line
+        if let Some(name) = input.strip_prefix("/secret ") {
+            let name = name.trim();
+            print!("{}", format!("Value for {}: ", name).yellow());
+            let _ = io::stdout().flush();
+            let mut value = String::new();
+            if io::stdin().read_line(&mut value).is_err() || value.trim().is_empty() {
+                println!("{}", "Nothing stored".yellow());
+                continue;
+            }
+            match runtime.tools().secrets().store(name, value.trim()) {
+                Ok(()) => println!("{}", format!("🔑 Stored {} in the OS keyring", name).green()),
+                Err(e) => println!("{}", format!("Secret Error: {}", e).red()),
+            }
+            continue;
+        }
+
+        // /journal [session|latest] [turn]: replay a journaled session; no argument lists sessions
+        if let Some(rest) = input.strip_prefix("/journal") {
+            let mut words = rest.split_whitespace();
+            match words.next() {
+                None => match sessions(journal_dir()) {
+                    Ok(list) if list.is_empty() => println!("{}", "No journals yet".yellow()),
+                    Ok(list) => println!("{}", list.join("\n").cyan()),
+                    Err(e) => println!("{}", format!("Journal Error: {}", e).red()),
+                },
+                Some(session) => {
+                    let session = if session == "current" { runtime.journal().map(|j| j.session()).unwrap_or("latest") } else { session };
+                    let turn = words.next().and_then(|t| t.parse().ok());
+                    match Replay::find(journal_dir(), session) {
+                        Ok(replay) => println!("{}", replay.render(turn).cyan()),
+                        Err(e) => println!("{}", format!("Journal Error: {}", e).red()),
+                    }
+                }
+            }
+            continue;
+        }
+
+        // /usage: tokens and estimated cost for this session
+        if input == "/usage" {
+            println!("{}", runtime.agent().usage().render().cyan());
+            continue;
+        }
+
+        // /plan <goal>: plan first, show the plan for approval, then run it step by step
+        if let Some(goal) = input.strip_prefix("/plan") {
+            let goal = goal.trim();
+            if goal.is_empty() {
+                println!("{}", "Usage: /plan <goal>".yellow());
+                continue;
+            }
+            let (agent, tools) = runtime.parts_mut();
+            let plan = match make_plan(agent, tools, goal).await {
+                Ok(plan) => plan,
+                Err(e) => {
+                    println!("{}", format!("Planning Error: {}", e).red());
+                    continue;
+                }
+            };
+            println!("{}", plan.render().cyan());
+            let problems = plan.validate(runtime.tools());
+            if !problems.is_empty() {
+                println!("{}", format!("Plan rejected:\n{}", problems.join("\n")).red());
+                continue;
+            }
+            print!("{}", "Execute this plan? [y/N] ".yellow().bold());
+            io::stdout().flush()?;
+            let mut answer = String::new();
+            io::stdin().read_line(&mut answer)?;
+            if !answer.trim().eq_ignore_ascii_case("y") {
+                println!("{}", "Plan discarded".yellow());
+                continue;
+            }
+            let results = plan.execute(runtime.tools());
+            println!("{}", render_results(&results).green());
+            match summarize_results(runtime.agent_mut(), &plan, &results).await {
+                Ok(answer) => println!("{}", answer.cyan()),
+                Err(e) => println!("{}", format!("Error: {}", e).red()),
+            }
+            continue;
+        }
+
+        // /orchestrate <task>: hand the task to a coordinator that delegates to worker personas
+        if let Some(task) = input.strip_prefix("/orchestrate") {
+            let task = task.trim();
+            if task.is_empty() {
+                println!("{}", "Usage: /orchestrate <task>".yellow());
+                continue;
+            }
+            if orchestrator.is_none() {
+                match Orchestrator::from_env().await {
+                    Ok(o) => orchestrator = Some(o),
+                    Err(e) => {
+                        println!("{}", format!("Orchestrator Error: {}", e).red());
+                        continue;
                     }
                 }
+            }
+            if let Some(o) = orchestrator.as_mut() {
+                println!("{}", format!("Workers: {}", o.workers().join(", ")).yellow());
+                match o.run(task).await {
+                    Ok(answer) => println!("{}", answer.cyan()),
+                    Err(e) => println!("{}", format!("Orchestrator Error: {}", e).red()),
+                }
+            }
+            continue;
+        }
 
-                // Simple parsing for tool execution
-                if response.contains("[TOOL:") {
-                    let start = response.find("[TOOL:").unwrap() + 7;
-                    let end = response[start..].find("]").unwrap() + start;
-                    let content = &response[start..end];
-                    // content is like "name(args)"
-                    if let Some(paren) = content.find('(') {
-                        let name = &content[..paren];
-                        let args_str = &content[paren + 1..content.len() - 1];
-                        let args = vec![args_str.to_string()]; // Simplify args for now
-
-                        println!("{}", format!("Executing tool: {}", name).yellow());
-                        match tool_manager.execute_tool(name, args) {
-                            Ok(res) => {
-                                println!("{}", format!("Tool Output: {}", res).green());
-                                // Feed back to agent? For now just print.
-                            }
-                            Err(e) => println!("{}", format!("Tool Error: {}", e).red()),
-                        }
+        // /compare <prompt>: ask every configured model and reconcile the answers
+        if let Some(prompt) = input.strip_prefix("/compare") {
+            let prompt = prompt.trim();
+            if prompt.is_empty() {
+                println!("{}", "Usage: /compare <prompt>".yellow());
+                continue;
+            }
+            if comparer.is_none() {
+                match Comparer::from_env().await {
+                    Ok(c) => comparer = Some(c),
+                    Err(e) => {
+                        println!("{}", format!("Compare Error: {}", e).red());
+                        continue;
                     }
                 }
             }
-            Err(e) => println!("{}", format!("Error: {}", e).red()),
+            if let Some(c) = &comparer {
+                println!("{}", format!("Comparing: {}", c.models().join(", ")).yellow());
+                match c.compare(prompt, None).await {
+                    Ok(comparison) => println!("{}", comparison.render().cyan()),
+                    Err(e) => println!("{}", format!("Compare Error: {}", e).red()),
+                }
+            }
+            continue;
+        }
+
+        let cancel = runtime.tools().cancel().clone();
+        cancel.begin();
+        let turn = async {
+            // Tools it wrote are saved and tools it called run, their output going back to it, until
+            // it answers; CliEvents prints each step as it happens
+            let turn = runtime.handle_input(input).await;
+            if let Some(e) = turn.error {
+                println!("{}", format!("Error: {}", e).red());
+            }
+        };
+        tokio::select! {
+            _ = turn => {}
+            _ = cancel.cancelled() => println!("{}", "Cancelled".yellow()),
         }
+        cancel.end();
     }
 
+    flush_traces(traces);
     Ok(())
 }
+
+/// Send the spans still waiting in the exporter's batch
+fn flush_traces(traces: Option<TracerProvider>) {
+    if let Some(Err(e)) = traces.map(|provider| provider.shutdown()) {
+        eprintln!("{}", format!("⚠️  Failed to export the last traces: {}", e).yellow());
+    }
+}
+
+/// `config show` prints the effective config, `config validate` checks it. Returns the exit code.
+fn config_command(command: ConfigCommand) -> i32 {
+    let config = match Config::from_env() {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("{}", format!("Config Error: {}", e).red());
+            return 1;
+        }
+    };
+    match command {
+        ConfigCommand::Show => match config.render() {
+            Ok(rendered) => {
+                println!("{}", rendered);
+                0
+            }
+            Err(e) => {
+                eprintln!("{}", format!("Config Error: {}", e).red());
+                1
+            }
+        },
+        ConfigCommand::Validate => {
+            let source = config.path.as_ref().map(|p| p.display().to_string()).unwrap_or_else(|| "The default config".to_string());
+            let problems = config.validate();
+            if problems.is_empty() {
+                println!("{}", format!("✅ {} is valid", source).green());
+                return 0;
+            }
+            eprintln!("{}", format!("❌ {}:\n{}", source, problems.join("\n")).red());
+            1
+        }
+    }
+}
+
+/// `tool list|create|run|inspect|delete|export|import|log|revert`. Returns the exit code.
+fn tool_command(tool_manager: &mut ToolManager, command: &ToolCommand) -> i32 {
+    let result = match command {
+        ToolCommand::List => Ok(tool_manager.list_tools().join("\n")),
+        ToolCommand::Create { name, file } => {
+            let source = match file.as_ref().filter(|f| f.as_os_str() != "-") {
+                Some(file) => std::fs::read_to_string(file).map_err(|e| anyhow!("Cannot read {:?}: {}", file, e)),
+                None => {
+                    let mut source = String::new();
+                    io::Read::read_to_string(&mut io::stdin(), &mut source).map(|_| source).map_err(Into::into)
+                }
+            };
+            let python = file.as_ref().and_then(|f| f.extension()).is_some_and(|e| e == "py");
+            source.and_then(|code| if python { tool_manager.create_python_tool(name, &code) } else { tool_manager.create_tool(name, &code) })
+        }
+        ToolCommand::Run { name, args } => tool_manager.execute_tool(name, args.clone()),
+        ToolCommand::Inspect { name } => tool_manager.tool_source(name)
+            .map(|source| format!("# {} (Safety: {:?})\n{}", name, tool_manager.safety_policy().classify(&source), source))
+            .ok_or_else(|| anyhow!("Tool '{}' not found", name)),
+        ToolCommand::Delete { name } => tool_manager.remove_tool(name),
+        ToolCommand::Export { file, tools } => tool_manager.export_tools(file, tools.as_deref(), &IpcAuth::from_env())
+            .map(|manifest| {
+                let signed = if manifest.signature.is_some() { "signed" } else { "unsigned, no IPC_SECRET" };
+                format!("Exported {} tools to {} ({})", manifest.tools.len(), file.display(), signed)
+            }),
+        ToolCommand::Import { file } => tool_manager.import_tools(file, &IpcAuth::from_env()).map(|report| report.render()),
+        ToolCommand::Log { name } => tool_manager.tool_log(name).map(|commits| render_tool_log(name, &commits)),
+        ToolCommand::Revert { name, commit } => tool_manager.revert_tool(name, commit),
+    };
+    match result {
+        Ok(output) => {
+            println!("{}", output);
+            0
+        }
+        Err(e) => {
+            eprintln!("{}", format!("Tool Error: {}", e).red());
+            1
+        }
+    }
+}
+
+/// The terminal's side of the agent: progress printed as it happens, approvals asked on stdin
+struct CliEvents {
+    /// An operator is at the prompt (the REPL)
+    interactive: bool,
+    /// Nobody to ask (`run`, `batch`): runs that need confirmation are declined
+    scripted: bool,
+    /// Announce peers' messages (INBOX_NOTIFY=false to silence)
+    notify_inbox: bool,
+}
+
+impl AgentEvents for CliEvents {
+    fn on_llm_call(&self, _input: &str, response: Result<&str, &str>) {
+        if let (true, Ok(response)) = (self.interactive, response) {
+            println!("{}", response.cyan());
+        }
+    }
+
+    fn on_tool_created(&self, name: &str, outcome: Result<&str, &str>) {
+        if !self.interactive {
+            return;
+        }
+        println!("{}", format!("New tool: {}", name).yellow());
+        match outcome {
+            Ok(msg) => println!("{}", msg.green()),
+            Err(e) => println!("{}", format!("Error creating tool: {}", e).red()),
+        }
+    }
+
+    fn on_tool_executed(&self, name: &str, _args: &[String], result: Result<&str, &str>, _duration_ms: u64) {
+        if !self.interactive {
+            return;
+        }
+        println!("{}", format!("Executed tool: {}", name).yellow());
+        match result {
+            Ok(output) => println!("{}", format!("Tool Output: {}", output).green()),
+            Err(e) => println!("{}", format!("Tool Error: {}", e).red()),
+        }
+    }
+
+    fn on_message_received(&self, message: &InboxMessage) {
+        if self.notify_inbox {
+            println!("\n{}", format!("📬 Message from {}, /inbox to list unread ones", message.sender()).yellow());
+        }
+    }
+
+    fn on_approval_needed(&self, request: &ApprovalRequest) -> Option<bool> {
+        if self.interactive {
+            let question = match request {
+                ApprovalRequest::Command { command_line } => format!("⚠️  Allow command `{}`? [y/N] ", command_line),
+                ApprovalRequest::Execution { call, level } => format!("⚠️  Run {:?} tool `{}`? [y/N] ", level, call),
+                ApprovalRequest::Install { review } => {
+                    println!("{}", review.render(true));
+                    format!("⚠️  Install {:?} tool '{}'? [y/N] ", review.safety_level, review.name)
+                }
+            };
+            print!("{}", question.yellow().bold());
+            let _ = io::stdout().flush();
+            let mut answer = String::new();
+            return Some(io::stdin().read_line(&mut answer).is_ok() && answer.trim().eq_ignore_ascii_case("y"));
+        }
+        match request {
+            ApprovalRequest::Execution { call, level } if self.scripted => {
+                eprintln!("{}", format!("⚠️  Declined {:?} tool `{}`: no operator to confirm (--auto-approve to allow)", level, call).yellow());
+                Some(false)
+            }
+            _ => None,
+        }
+    }
+}
+
+/// A startup line: on stderr for scripted commands, so stdout only has their output
+fn status(scripted: bool, line: impl std::fmt::Display) {
+    if scripted {
+        eprintln!("{}", line);
+    } else {
+        println!("{}", line);
+    }
+}
+
+/// Apply workspace.cleanup to the session's workspace as the session ends
+fn finish_workspace(workspace: Option<&SessionWorkspace>, scripted: bool) {
+    let Some(workspace) = workspace else { return };
+    match workspace.finish() {
+        Ok(true) => status(scripted, format!("🧹 Removed workspace {}", workspace.path().display())),
+        Ok(false) => status(scripted, format!("📁 Files of this session are in {}", workspace.path().display())),
+        Err(e) => status(scripted, format!("Workspace Error: {}", e).red()),
+    }
+}
+
+/// `run <prompt|->` / `batch <file>`: work through the tasks and print the answers, as JSON
+/// lines with `--json`. Returns the exit code.
+async fn run_one_shot(command: &Command, mut runner: Runner) -> i32 {
+    let (tasks, json, batch) = match command {
+        Command::Run { prompt, json } if prompt == "-" => {
+            let mut prompt = String::new();
+            if let Err(e) = io::Read::read_to_string(&mut io::stdin(), &mut prompt) {
+                eprintln!("Cannot read the prompt from stdin: {}", e);
+                return EXIT_USAGE;
+            }
+            (vec![prompt.trim().to_string()], *json, false)
+        }
+        Command::Run { prompt, json } => (vec![prompt.clone()], *json, false),
+        Command::Batch { file, json } => match read_tasks(file) {
+            Ok(tasks) => (tasks, *json, true),
+            Err(e) => {
+                eprintln!("{}", e);
+                return EXIT_USAGE;
+            }
+        },
+        _ => return EXIT_USAGE,
+    };
+    if tasks.iter().all(|t| t.is_empty()) {
+        eprintln!("Nothing to do: no prompt given");
+        return EXIT_USAGE;
+    }
+
+    let outcomes = runner.run_batch(&tasks).await;
+    for outcome in &outcomes {
+        if json {
+            println!("{}", serde_json::to_string(outcome).unwrap_or_default());
+            continue;
+        }
+        if batch {
+            println!("# {}", outcome.prompt);
+        }
+        if outcome.ok {
+            println!("{}", outcome.answer);
+        } else {
+            eprintln!("{}", outcome.render().red());
+        }
+    }
+    exit_code(&outcomes)
+}
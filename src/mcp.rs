@@ -0,0 +1,501 @@
+use anyhow::{anyhow, Result};
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    response::{sse::{Event, KeepAlive, Sse}, IntoResponse, Response},
+    routing::{get, post},
+    Router,
+};
+use futures_util::stream::{Stream, StreamExt};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, HashMap};
+use std::convert::Infallible;
+use std::io::{BufRead, Write};
+use std::process::{Child, ChildStdin, Stdio};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::sync::mpsc;
+use crate::config::McpServerConfig;
+use crate::message::IpcMessage;
+use crate::tools::{InvokePolicy, ToolManager};
+use tracing::{info, warn};
+
+/// MCP revisions this server speaks; the first is offered to clients asking for another
+pub const PROTOCOL_VERSIONS: &[&str] = &["2024-11-05", "2025-03-26", "2025-06-18"];
+
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+
+/// Natives exposed besides the tool files: `MCP_NATIVES`, comma separated (default none)
+pub fn mcp_natives() -> Vec<String> {
+    std::env::var("MCP_NATIVES").unwrap_or_default()
+        .split(',')
+        .map(|n| n.trim().to_string())
+        .filter(|n| !n.is_empty())
+        .collect()
+}
+
+/// Serves a `ToolManager`'s tools to Model Context Protocol clients: JSON-RPC 2.0 with
+/// `initialize`, `tools/list` and `tools/call`, over stdio or SSE. Which tools are listed and
+/// may run is decided as for peers' `ToolInvoke`, under `invoke`.
+#[derive(Clone)]
+pub struct McpServer {
+    tools: Arc<ToolManager>,
+    invoke: InvokePolicy,
+    natives: Vec<String>,
+}
+
+impl McpServer {
+    pub fn new(tools: ToolManager) -> Self {
+        Self { tools: Arc::new(tools), invoke: InvokePolicy::default(), natives: Vec::new() }
+    }
+
+    /// `MCP_TOOLS_MAX_RISK`, `MCP_TOOLS_DENY` and `MCP_NATIVES`
+    pub fn from_env(tools: ToolManager) -> Self {
+        Self::new(tools).with_policy(InvokePolicy::mcp_from_env()).with_natives(mcp_natives())
+    }
+
+    /// Limit which tools clients see and may call (default: Safe tools only)
+    pub fn with_policy(mut self, invoke: InvokePolicy) -> Self {
+        self.invoke = invoke;
+        self
+    }
+
+    /// Expose these natives too, as far as the policy allows
+    pub fn with_natives(mut self, natives: Vec<String>) -> Self {
+        self.natives = natives;
+        self
+    }
+
+    /// The MCP tool descriptions of the tools clients may call
+    pub fn list_tools(&self) -> Vec<Value> {
+        self.tools.invocable_tools(&self.invoke, &self.natives).into_iter()
+            .map(|name| {
+                let params = self.tools.tool_params(&name);
+                let description = self.tools.tool_source(&name)
+                    .map(|source| crate::tool_index::tool_description(&source))
+                    .filter(|d| !d.is_empty())
+                    .unwrap_or_else(|| format!("Run {}", name));
+                let properties: serde_json::Map<String, Value> = params.iter().map(|p| (p.clone(), json!({ "type": "string" }))).collect();
+                json!({
+                    "name": name,
+                    "description": description,
+                    "inputSchema": { "type": "object", "properties": properties, "required": params },
+                })
+            })
+            .collect()
+    }
+
+    /// Answer one JSON-RPC message; notifications get no answer
+    pub async fn handle(&self, message: &str) -> Option<Value> {
+        let request: Value = match serde_json::from_str(message) {
+            Ok(request) => request,
+            Err(e) => return Some(error(Value::Null, PARSE_ERROR, &format!("Parse error: {}", e))),
+        };
+        let id = request.get("id").cloned();
+        let Some(method) = request.get("method").and_then(Value::as_str) else {
+            // Answers to requests we never send, or junk
+            return id.map(|id| error(id, INVALID_REQUEST, "Invalid request: no method"));
+        };
+        let params = request.get("params").cloned().unwrap_or(Value::Null);
+        let result = match method {
+            "initialize" => Ok(self.initialize(&params)),
+            "ping" => Ok(json!({})),
+            "tools/list" => Ok(json!({ "tools": self.list_tools() })),
+            "tools/call" => self.call_tool(&params).await,
+            _ if method.starts_with("notifications/") => return None,
+            _ => Err((METHOD_NOT_FOUND, format!("Method not found: {}", method))),
+        };
+        let id = id?;
+        Some(match result {
+            Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+            Err((code, message)) => error(id, code, &message),
+        })
+    }
+
+    fn initialize(&self, params: &Value) -> Value {
+        let requested = params.get("protocolVersion").and_then(Value::as_str).unwrap_or_default();
+        let version = PROTOCOL_VERSIONS.iter().find(|v| **v == requested).unwrap_or(&PROTOCOL_VERSIONS[0]);
+        json!({
+            "protocolVersion": version,
+            "capabilities": { "tools": { "listChanged": false } },
+            "serverInfo": { "name": env!("CARGO_PKG_NAME"), "version": env!("CARGO_PKG_VERSION") },
+        })
+    }
+
+    async fn call_tool(&self, params: &Value) -> std::result::Result<Value, (i64, String)> {
+        let name = params.get("name").and_then(Value::as_str).unwrap_or_default().to_string();
+        if !self.tools.invocable_tools(&self.invoke, &self.natives).contains(&name) {
+            return Err((INVALID_PARAMS, format!("Unknown tool: {}", name)));
+        }
+        let arguments = params.get("arguments").cloned().unwrap_or(Value::Null);
+        let args: Vec<String> = self.tools.tool_params(&name).iter()
+            .map_while(|p| arguments.get(p))
+            .map(|v| v.as_str().map(str::to_string).unwrap_or_else(|| v.to_string()))
+            .collect();
+        let (tools, invoke) = (self.tools.clone(), self.invoke.clone());
+        let tool = name.clone();
+        // Tools block (and some start their own runtime), so keep them off the async workers
+        let output = tokio::task::spawn_blocking(move || tools.invoke_for_peer(&tool, args, &invoke)).await
+            .unwrap_or_else(|e| IpcMessage::tool_output(&name, Err(format!("tool crashed: {}", e))));
+        let (text, failed) = match output {
+            IpcMessage::ToolOutput { error: Some(error), .. } => (error, true),
+            IpcMessage::ToolOutput { result, .. } => (result.unwrap_or_default(), false),
+            other => (format!("{:?}", other), true),
+        };
+        Ok(json!({ "content": [{ "type": "text", "text": text }], "isError": failed }))
+    }
+
+    /// Speak MCP on stdin and stdout, one JSON message per line, until stdin closes. Anything
+    /// else must go to stderr.
+    pub async fn serve_stdio(self) -> Result<()> {
+        let mut lines = BufReader::new(tokio::io::stdin()).lines();
+        let mut stdout = tokio::io::stdout();
+        while let Some(line) = lines.next_line().await? {
+            if line.trim().is_empty() {
+                continue;
+            }
+            if let Some(answer) = self.handle(&line).await {
+                stdout.write_all(format!("{}\n", answer).as_bytes()).await?;
+                stdout.flush().await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// The SSE transport: `GET /sse` opens a session whose first event names the URL to
+    /// `POST` messages to; answers arrive as `message` events on the stream
+    pub fn router(self) -> Router {
+        Router::new()
+            .route("/sse", get(handle_sse))
+            .route("/messages", post(handle_post))
+            .with_state(SseState { server: self, sessions: Arc::new(Mutex::new(HashMap::new())) })
+    }
+
+    /// Serve the SSE transport on 127.0.0.1:`port` until the process exits
+    pub async fn serve_sse(self, port: u16) -> Result<()> {
+        let listener = tokio::net::TcpListener::bind(("127.0.0.1", port)).await?;
+        info!("MCP over SSE at http://{}/sse", listener.local_addr()?);
+        axum::serve(listener, self.router()).await?;
+        Ok(())
+    }
+}
+
+fn error(id: Value, code: i64, message: &str) -> Value {
+    json!({ "jsonrpc": "2.0", "id": id, "error": { "code": code, "message": message } })
+}
+
+#[derive(Clone)]
+struct SseState {
+    server: McpServer,
+    sessions: Arc<Mutex<HashMap<String, mpsc::UnboundedSender<Value>>>>,
+}
+
+#[derive(Deserialize)]
+struct SessionQuery {
+    #[serde(rename = "sessionId")]
+    session_id: String,
+}
+
+/// Drops the session when its stream ends, i.e. the client went away
+struct Session {
+    id: String,
+    sessions: Arc<Mutex<HashMap<String, mpsc::UnboundedSender<Value>>>>,
+}
+
+impl Drop for Session {
+    fn drop(&mut self) {
+        self.sessions.lock().unwrap().remove(&self.id);
+    }
+}
+
+async fn handle_sse(State(state): State<SseState>) -> Sse<impl Stream<Item = std::result::Result<Event, Infallible>>> {
+    let id = crate::message::message_id();
+    let (tx, rx) = mpsc::unbounded_channel();
+    state.sessions.lock().unwrap().insert(id.clone(), tx);
+    let endpoint = Event::default().event("endpoint").data(format!("/messages?sessionId={}", id));
+    let session = Session { id, sessions: state.sessions.clone() };
+    let answers = futures_util::stream::unfold((rx, session), |(mut rx, session)| async move {
+        let answer = rx.recv().await?;
+        Some((Ok(Event::default().event("message").data(answer.to_string())), (rx, session)))
+    });
+    Sse::new(futures_util::stream::once(async move { Ok(endpoint) }).chain(answers)).keep_alive(KeepAlive::default())
+}
+
+async fn handle_post(State(state): State<SseState>, Query(query): Query<SessionQuery>, body: String) -> Response {
+    let Some(session) = state.sessions.lock().unwrap().get(&query.session_id).cloned() else {
+        return (StatusCode::NOT_FOUND, "Unknown session").into_response();
+    };
+    if let Some(answer) = state.server.handle(&body).await {
+        let _ = session.send(answer);
+    }
+    StatusCode::ACCEPTED.into_response()
+}
+
+/// Prefix of the names external MCP tools are called by: `mcp::<server>::<tool>`
+pub const MCP_PREFIX: &str = "mcp::";
+
+/// The server and tool of an `mcp::<server>::<tool>` name
+pub fn split_mcp_name(name: &str) -> Option<(&str, &str)> {
+    name.strip_prefix(MCP_PREFIX)?.split_once("::")
+}
+
+/// A tool an external MCP server offers
+#[derive(Debug, Clone, PartialEq)]
+pub struct McpTool {
+    pub server: String,
+    pub name: String,
+    pub description: String,
+    /// Parameter names and JSON types, required ones first
+    pub params: Vec<(String, String)>,
+}
+
+impl McpTool {
+    fn from_listing(server: &str, tool: &Value) -> Option<Self> {
+        let schema = &tool["inputSchema"];
+        let required: Vec<&str> = schema["required"].as_array().map(|r| r.iter().filter_map(Value::as_str).collect()).unwrap_or_default();
+        let properties = schema["properties"].as_object().cloned().unwrap_or_default();
+        let mut names: Vec<&String> = properties.keys().collect();
+        names.sort_by_key(|name| required.iter().position(|r| r == name).unwrap_or(usize::MAX));
+        Some(Self {
+            server: server.to_string(),
+            name: tool["name"].as_str()?.to_string(),
+            description: tool["description"].as_str().unwrap_or_default().to_string(),
+            params: names.into_iter()
+                .map(|name| (name.clone(), properties[name]["type"].as_str().unwrap_or("string").to_string()))
+                .collect(),
+        })
+    }
+
+    /// `mcp::<server>::<tool>`, the name the agent calls it by
+    pub fn qualified_name(&self) -> String {
+        format!("{}{}::{}", MCP_PREFIX, self.server, self.name)
+    }
+
+    /// How the agent calls it, e.g. `mcp::files::read_file(path)`
+    pub fn signature(&self) -> String {
+        let params: Vec<&str> = self.params.iter().map(|(name, _)| name.as_str()).collect();
+        format!("{}({})", self.qualified_name(), params.join(", "))
+    }
+
+    /// The MCP `arguments` for a call's args: a JSON object as it is, else comma-separated
+    /// values for the parameters in order, converted to their types
+    pub fn arguments(&self, args: &[String]) -> Value {
+        let joined = args.join(", ");
+        if let Ok(object @ Value::Object(_)) = serde_json::from_str::<Value>(joined.trim()) {
+            return object;
+        }
+        let values: Vec<&str> = if self.params.len() > 1 { joined.split(',').collect() } else { vec![joined.as_str()] };
+        let arguments: serde_json::Map<String, Value> = self.params.iter()
+            .zip(values.into_iter().map(|v| v.trim().trim_matches('"')).filter(|v| !v.is_empty()))
+            .map(|((name, kind), value)| {
+                let value = match kind.as_str() {
+                    "number" | "integer" | "boolean" => serde_json::from_str(value).unwrap_or_else(|_| json!(value)),
+                    _ => json!(value),
+                };
+                (name.clone(), value)
+            })
+            .collect();
+        Value::Object(arguments)
+    }
+}
+
+struct Pipes {
+    stdin: ChildStdin,
+    lines: std::sync::mpsc::Receiver<String>,
+}
+
+/// A connection to an MCP server running as a child process, which is killed when the
+/// connection is dropped
+pub struct McpClient {
+    name: String,
+    child: Mutex<Child>,
+    pipes: Mutex<Pipes>,
+    next_id: AtomicU64,
+    timeout: Duration,
+    tools: Vec<McpTool>,
+}
+
+impl McpClient {
+    /// Launch the server, initialize the session and list its tools
+    pub fn connect(name: &str, config: &McpServerConfig) -> Result<Self> {
+        let mut child = std::process::Command::new(&config.command)
+            .args(&config.args)
+            .envs(&config.env)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(|e| anyhow!("Cannot start MCP server '{}' ({}): {}", name, config.command, e))?;
+        let stdin = child.stdin.take().ok_or_else(|| anyhow!("MCP server '{}' has no stdin", name))?;
+        let stdout = child.stdout.take().ok_or_else(|| anyhow!("MCP server '{}' has no stdout", name))?;
+        // Read on a thread of its own, so answers can be waited for with a timeout
+        let (tx, lines) = std::sync::mpsc::channel();
+        std::thread::spawn(move || {
+            for line in std::io::BufReader::new(stdout).lines().map_while(Result::ok) {
+                if tx.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+        let mut client = Self {
+            name: name.to_string(),
+            child: Mutex::new(child),
+            pipes: Mutex::new(Pipes { stdin, lines }),
+            next_id: AtomicU64::new(1),
+            timeout: Duration::from_secs(config.timeout_secs),
+            tools: Vec::new(),
+        };
+        client.request("initialize", json!({
+            "protocolVersion": PROTOCOL_VERSIONS[0],
+            "capabilities": {},
+            "clientInfo": { "name": env!("CARGO_PKG_NAME"), "version": env!("CARGO_PKG_VERSION") },
+        }))?;
+        client.send(&json!({ "jsonrpc": "2.0", "method": "notifications/initialized" }))?;
+        let listing = client.request("tools/list", json!({}))?;
+        client.tools = listing["tools"].as_array().map(|tools| tools.iter().filter_map(|t| McpTool::from_listing(name, t)).collect()).unwrap_or_default();
+        Ok(client)
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The tools the server listed when connecting
+    pub fn tools(&self) -> &[McpTool] {
+        &self.tools
+    }
+
+    /// Call `tool` with `arguments`, returning its text content; a result flagged `isError` is an Err
+    pub fn call(&self, tool: &str, arguments: Value) -> Result<String> {
+        let result = self.request("tools/call", json!({ "name": tool, "arguments": arguments }))?;
+        let text = result["content"].as_array()
+            .map(|content| content.iter().filter_map(|c| c["text"].as_str()).collect::<Vec<_>>().join("\n"))
+            .unwrap_or_default();
+        if result["isError"].as_bool().unwrap_or(false) {
+            return Err(anyhow!("{}", text));
+        }
+        Ok(text)
+    }
+
+    fn send(&self, message: &Value) -> Result<()> {
+        let mut pipes = self.pipes.lock().unwrap();
+        writeln!(pipes.stdin, "{}", message)?;
+        pipes.stdin.flush()?;
+        Ok(())
+    }
+
+    /// Send a request and wait for the answer with its id, skipping the server's notifications
+    fn request(&self, method: &str, params: Value) -> Result<Value> {
+        let id = self.next_id.fetch_add(1, Ordering::SeqCst);
+        let mut pipes = self.pipes.lock().unwrap();
+        writeln!(pipes.stdin, "{}", json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params }))
+            .and_then(|_| pipes.stdin.flush())
+            .map_err(|e| anyhow!("MCP server '{}' is gone: {}", self.name, e))?;
+        let deadline = std::time::Instant::now() + self.timeout;
+        loop {
+            let remaining = deadline.saturating_duration_since(std::time::Instant::now());
+            let line = match pipes.lines.recv_timeout(remaining) {
+                Ok(line) => line,
+                Err(std::sync::mpsc::RecvTimeoutError::Timeout) => {
+                    return Err(anyhow!("MCP server '{}' did not answer {} within {:?}", self.name, method, self.timeout));
+                }
+                Err(std::sync::mpsc::RecvTimeoutError::Disconnected) => return Err(anyhow!("MCP server '{}' exited", self.name)),
+            };
+            let Ok(answer) = serde_json::from_str::<Value>(&line) else {
+                continue;
+            };
+            if answer["id"] != json!(id) {
+                continue;
+            }
+            if let Some(error) = answer.get("error") {
+                return Err(anyhow!("MCP server '{}': {}", self.name, error["message"].as_str().unwrap_or("error")));
+            }
+            return Ok(answer["result"].clone());
+        }
+    }
+}
+
+impl Drop for McpClient {
+    fn drop(&mut self) {
+        let mut child = self.child.lock().unwrap();
+        let _ = child.kill();
+        let _ = child.wait();
+    }
+}
+
+#[derive(Default)]
+struct Connections {
+    clients: HashMap<String, Arc<McpClient>>,
+    /// Servers that could not be started, with why; not retried
+    failed: HashMap<String, String>,
+}
+
+/// The MCP servers configured under `[mcp.servers]`, each started on first use and shared
+/// by every clone of this value
+#[derive(Clone, Default)]
+pub struct McpClients {
+    servers: BTreeMap<String, McpServerConfig>,
+    connections: Arc<Mutex<Connections>>,
+}
+
+impl McpClients {
+    pub fn new(servers: BTreeMap<String, McpServerConfig>) -> Self {
+        Self { servers, connections: Arc::default() }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.servers.is_empty()
+    }
+
+    /// The connection to `server`, starting it if needed
+    pub fn client(&self, server: &str) -> Result<Arc<McpClient>> {
+        let config = self.servers.get(server).ok_or_else(|| anyhow!("No MCP server '{}' is configured", server))?;
+        let mut connections = self.connections.lock().unwrap();
+        if let Some(client) = connections.clients.get(server) {
+            return Ok(client.clone());
+        }
+        if let Some(reason) = connections.failed.get(server) {
+            return Err(anyhow!("{}", reason));
+        }
+        match McpClient::connect(server, config) {
+            Ok(client) => {
+                let client = Arc::new(client);
+                connections.clients.insert(server.to_string(), client.clone());
+                Ok(client)
+            }
+            Err(e) => {
+                warn!("{}", e);
+                connections.failed.insert(server.to_string(), e.to_string());
+                Err(e)
+            }
+        }
+    }
+
+    /// Every tool of every server that could be started
+    pub fn tools(&self) -> Vec<McpTool> {
+        self.servers.keys()
+            .filter_map(|server| self.client(server).ok())
+            .flat_map(|client| client.tools().to_vec())
+            .collect()
+    }
+
+    /// The tool an `mcp::<server>::<tool>` name stands for
+    pub fn find(&self, name: &str) -> Option<McpTool> {
+        let (server, tool) = split_mcp_name(name)?;
+        self.client(server).ok()?.tools().iter().find(|t| t.name == tool).cloned()
+    }
+
+    /// Run `mcp::<server>::<tool>` with the args of a `[TOOL: ...]` call
+    pub fn call(&self, name: &str, args: &[String]) -> Result<String> {
+        let (server, tool) = split_mcp_name(name).ok_or_else(|| anyhow!("'{}' is not an mcp::<server>::<tool> name", name))?;
+        let client = self.client(server)?;
+        let listed = client.tools().iter().find(|t| t.name == tool).ok_or_else(|| anyhow!("MCP server '{}' has no tool '{}'", server, tool))?;
+        client.call(tool, listed.arguments(args))
+    }
+}
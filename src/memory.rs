@@ -0,0 +1,77 @@
+use anyhow::Result;
+use std::collections::BTreeMap;
+use crate::state::StateStore;
+use rhai::{Dynamic, Engine};
+
+const STATE_KEY: &str = "memory";
+
+/// Long-term key/value facts recorded by tools, persisted in the state store
+#[derive(Debug, Clone)]
+pub struct MemoryStore {
+    store: StateStore,
+}
+
+impl MemoryStore {
+    pub fn new(store: StateStore) -> Self {
+        Self { store }
+    }
+
+    pub fn set(&self, key: &str, value: &str) -> Result<()> {
+        self.store.update(STATE_KEY, |memory: &mut BTreeMap<String, String>| {
+            memory.insert(key.to_string(), value.to_string());
+        })
+    }
+
+    pub fn get(&self, key: &str) -> Result<Option<String>> {
+        let memory: BTreeMap<String, String> = self.store.get(STATE_KEY)?.unwrap_or_default();
+        Ok(memory.get(key).cloned())
+    }
+
+    /// All entries whose key starts with `prefix`, in key order
+    pub fn search(&self, prefix: &str) -> Result<Vec<(String, String)>> {
+        let memory: BTreeMap<String, String> = self.store.get(STATE_KEY)?.unwrap_or_default();
+        Ok(memory
+            .range(prefix.to_string()..)
+            .take_while(|(key, _)| key.starts_with(prefix))
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect())
+    }
+}
+
+/// `memory_set`, `memory_get` and `memory_search`
+pub(crate) fn register_memory_natives(engine: &mut Engine) -> Result<()> {
+    // Long-term memory
+    let memory = MemoryStore::new(StateStore::from_env()?);
+
+    let memory_clone = memory.clone();
+    engine.register_fn("memory_set", move |key: &str, value: &str| -> String {
+        match memory_clone.set(key, value) {
+            Ok(()) => format!("Remembered '{}'", key),
+            Err(e) => format!("Error saving memory: {}", e),
+        }
+    });
+
+    let memory_clone = memory.clone();
+    engine.register_fn("memory_get", move |key: &str| -> Dynamic {
+        match memory_clone.get(key) {
+            Ok(Some(value)) => value.into(),
+            Ok(None) => Dynamic::UNIT,
+            Err(e) => format!("Error reading memory: {}", e).into(),
+        }
+    });
+
+    let memory_clone = memory.clone();
+    engine.register_fn("memory_search", move |prefix: &str| -> Dynamic {
+        match memory_clone.search(prefix) {
+            Ok(entries) => {
+                let mut map = rhai::Map::new();
+                for (key, value) in entries {
+                    map.insert(key.into(), value.into());
+                }
+                map.into()
+            },
+            Err(e) => format!("Error searching memory: {}", e).into(),
+        }
+    });
+    Ok(())
+}
@@ -1,7 +1,12 @@
 use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use crate::blackboard::BlackboardEntry;
+use crate::provenance::AuthorSignature;
+use crate::registry::PeerInfo;
 
-/// Safety classification for tools
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+/// Safety classification for tools, ordered from least to most risky
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
 pub enum ToolSafetyLevel {
     Safe,       // Pure computation, no side effects
     LowRisk,    // Reads data, no writes
@@ -9,30 +14,278 @@ pub enum ToolSafetyLevel {
     HighRisk,   // System operations, cloning
 }
 
+fn unix_now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+/// `prefix-<nanos>-<counter>`, unique within the process and unlikely to collide across agents
+fn unique_id(prefix: &str) -> String {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_nanos()).unwrap_or(0);
+    format!("{}-{:x}-{}", prefix, nanos, COUNTER.fetch_add(1, Ordering::Relaxed))
+}
+
+/// A fresh id for a message that expects a correlated reply
+pub fn message_id() -> String {
+    unique_id("msg")
+}
+
+/// A fresh id for a clone made by `clone_agent`
+pub fn clone_id() -> String {
+    unique_id("clone")
+}
+
+/// A fresh id for a journaled session
+pub fn session_id() -> String {
+    unique_id("session")
+}
+
+/// A unit of work delegated to another agent
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TaskRequest {
+    pub id: String,
+    pub description: String,
+    /// Unix time (seconds) after which the task is no longer worth doing
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deadline: Option<u64>,
+    /// Message endpoint of the delegating agent, where the `TaskResult` is sent
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reply_to: Option<String>,
+    /// Trace context of the request that queued the task, continued by whoever works on it
+    #[serde(skip)]
+    pub traceparent: Option<String>,
+}
+
+impl TaskRequest {
+    /// A task with a fresh unique id
+    pub fn new(description: impl Into<String>) -> Self {
+        Self {
+            id: unique_id("task"),
+            description: description.into(),
+            deadline: None,
+            reply_to: None,
+            traceparent: None,
+        }
+    }
+
+    /// Set the deadline `timeout` from now
+    pub fn with_deadline(mut self, timeout: Duration) -> Self {
+        self.deadline = Some(unix_now() + timeout.as_secs());
+        self
+    }
+
+    pub fn with_reply_to(mut self, reply_to: impl Into<String>) -> Self {
+        self.reply_to = Some(reply_to.into());
+        self
+    }
+
+    /// Time left before the deadline; `None` without a deadline
+    pub fn remaining(&self) -> Option<Duration> {
+        self.deadline.map(|deadline| Duration::from_secs(deadline.saturating_sub(unix_now())))
+    }
+
+    pub fn is_expired(&self) -> bool {
+        self.remaining().map(|r| r.is_zero()).unwrap_or(false)
+    }
+}
+
+/// A task put out to tender (contract net): peers that want it answer with a `TaskBid`, and the
+/// best bidder gets a `TaskAward`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TaskAnnounce {
+    pub task: TaskRequest,
+    /// Tools or capabilities the task needs; bidders say how many of them they have
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub requirements: Vec<String>,
+}
+
+impl TaskAnnounce {
+    pub fn new(task: TaskRequest, requirements: Vec<String>) -> Self {
+        Self { task, requirements }
+    }
+}
+
+/// An offer to carry out an announced task
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TaskBid {
+    pub task_id: String,
+    /// Address of the bidding agent's server, where the award goes
+    pub bidder: String,
+    /// What the task would cost the bidder, in tasks it already has queued (this one included)
+    pub cost_estimate: f64,
+    /// Share of the task's requirements the bidder has, from 0 to 1
+    pub capability_match: f64,
+}
+
+impl TaskBid {
+    /// Higher is better: a good match from a bidder with little else to do
+    pub fn score(&self) -> f64 {
+        self.capability_match.clamp(0.0, 1.0) / (1.0 + self.cost_estimate.max(0.0))
+    }
+}
+
+/// The announcer's choice of bidder, who then works on `task` as if it had been delegated
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TaskAward {
+    pub task: TaskRequest,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum TaskStatus {
+    Completed,
+    Failed,
+    /// The deadline passed before the task finished
+    Expired,
+}
+
+/// The outcome of a `TaskRequest`, sent back to its `reply_to` address
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TaskResult {
+    pub id: String,
+    pub status: TaskStatus,
+    pub output: String,
+}
+
 /// IPC message types for inter-agent communication
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(tag = "type")]
 pub enum IpcMessage {
-    /// Plain text message (backward compatibility)
-    Text { content: String },
+    /// Plain text message (backward compatibility). Messages with the same `thread_id` belong to
+    /// one conversation with the sender, kept apart from its other threads.
+    Text {
+        content: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        thread_id: Option<String>,
+    },
     
-    /// Tool sharing request
+    /// Tool sharing request, signed by the tool's author when it has one
     ToolShare {
         name: String,
         code: String,
         description: Option<String>,
         safety_level: ToolSafetyLevel,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        signature: Option<AuthorSignature>,
     },
     
     /// Request a specific tool from another agent
     ToolRequest { name: String },
+
+    /// Reply to a `ToolRequest` the agent won't or can't answer with a `ToolShare`
+    ToolRefused { name: String, reason: String },
+
+    /// Ask another agent to carry out a task
+    TaskRequest(TaskRequest),
+
+    /// Report the outcome of a task back to the agent that requested it
+    TaskResult(TaskResult),
+
+    /// Put a task out for bids; the reply is a `TaskBid`, or text saying why there is none
+    TaskAnnounce(TaskAnnounce),
+
+    /// A bid for an announced task, for bidders that answer later than the announcement
+    TaskBid(TaskBid),
+
+    /// Hand an announced task to the bidder that won it
+    TaskAward(TaskAward),
+
+    /// Introduce an agent to a peer or registry; the reply lists the peers it knows
+    Announce(PeerInfo),
+
+    /// Liveness ping from the agent serving at `address`
+    Heartbeat { address: String },
+
+    /// The agent at `address` is running a leader election; a higher agent takes it over
+    Election { address: String },
+
+    /// The agent at `address` won the leader election
+    Leader { address: String },
+
+    /// Ask the receiver to send its `Event`s on `topic` to the agent serving at `address`.
+    /// A topic ending in `*` matches every topic with that prefix.
+    Subscribe { topic: String, address: String },
+
+    /// Stop sending `topic` events to `address`
+    Unsubscribe { topic: String, address: String },
+
+    /// Something happened on `topic`; sent to the topic's subscribers
+    Event { topic: String, payload: String },
+
+    /// Run one of the receiver's tools and answer with a `ToolOutput`
+    ToolInvoke { name: String, args: Vec<String> },
+
+    /// Outcome of a `ToolInvoke`: `result` on success, `error` when the tool failed or was refused
+    ToolOutput {
+        name: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        result: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        error: Option<String>,
+    },
+
+    /// A blackboard write, sent to peers so their copies catch up
+    BlackboardUpdate(BlackboardEntry),
+
+    /// Every blackboard entry the sender has; the reply is the receiver's own `BlackboardSync`
+    BlackboardSync { entries: Vec<BlackboardEntry> },
+
+    /// Emergency stop: halt the receiver and, with `clones`, its clones. Only obeyed when signed.
+    Halt {
+        reason: String,
+        #[serde(default)]
+        clones: bool,
+    },
 }
 
 impl IpcMessage {
+    /// The message's `type`, e.g. `ToolShare`
+    pub fn kind(&self) -> &'static str {
+        match self {
+            IpcMessage::Text { .. } => "Text",
+            IpcMessage::ToolShare { .. } => "ToolShare",
+            IpcMessage::ToolRequest { .. } => "ToolRequest",
+            IpcMessage::ToolRefused { .. } => "ToolRefused",
+            IpcMessage::TaskRequest(_) => "TaskRequest",
+            IpcMessage::TaskResult(_) => "TaskResult",
+            IpcMessage::TaskAnnounce(_) => "TaskAnnounce",
+            IpcMessage::TaskBid(_) => "TaskBid",
+            IpcMessage::TaskAward(_) => "TaskAward",
+            IpcMessage::Announce(_) => "Announce",
+            IpcMessage::Heartbeat { .. } => "Heartbeat",
+            IpcMessage::Election { .. } => "Election",
+            IpcMessage::Leader { .. } => "Leader",
+            IpcMessage::Subscribe { .. } => "Subscribe",
+            IpcMessage::Unsubscribe { .. } => "Unsubscribe",
+            IpcMessage::Event { .. } => "Event",
+            IpcMessage::ToolInvoke { .. } => "ToolInvoke",
+            IpcMessage::ToolOutput { .. } => "ToolOutput",
+            IpcMessage::BlackboardUpdate(_) => "BlackboardUpdate",
+            IpcMessage::BlackboardSync { .. } => "BlackboardSync",
+            IpcMessage::Halt { .. } => "Halt",
+        }
+    }
+
     /// Create a text message
     pub fn text(content: impl Into<String>) -> Self {
         IpcMessage::Text {
             content: content.into(),
+            thread_id: None,
+        }
+    }
+
+    /// Create a text message in thread `thread_id`, or outside any thread with None
+    pub fn threaded(content: impl Into<String>, thread_id: Option<String>) -> Self {
+        IpcMessage::Text {
+            content: content.into(),
+            thread_id,
+        }
+    }
+
+    /// The thread a text message belongs to
+    pub fn thread_id(&self) -> Option<&str> {
+        match self {
+            IpcMessage::Text { thread_id, .. } => thread_id.as_deref(),
+            _ => None,
         }
     }
     
@@ -48,8 +301,17 @@ impl IpcMessage {
             code: code.into(),
             description,
             safety_level,
+            signature: None,
         }
     }
+
+    /// A `ToolShare` carrying its author's signature; other messages are left as they are
+    pub fn signed(mut self, by: Option<AuthorSignature>) -> Self {
+        if let IpcMessage::ToolShare { signature, .. } = &mut self {
+            *signature = by;
+        }
+        self
+    }
     
     /// Create a tool request message
     pub fn tool_request(name: impl Into<String>) -> Self {
@@ -58,6 +320,49 @@ impl IpcMessage {
         }
     }
     
+    /// Create a tool refusal message
+    pub fn tool_refused(name: impl Into<String>, reason: impl Into<String>) -> Self {
+        IpcMessage::ToolRefused {
+            name: name.into(),
+            reason: reason.into(),
+        }
+    }
+
+    /// Create a task request message
+    pub fn task_request(task: TaskRequest) -> Self {
+        IpcMessage::TaskRequest(task)
+    }
+
+    /// Create a task result message
+    pub fn task_result(id: impl Into<String>, status: TaskStatus, output: impl Into<String>) -> Self {
+        IpcMessage::TaskResult(TaskResult {
+            id: id.into(),
+            status,
+            output: output.into(),
+        })
+    }
+
+    /// Create a remote tool invocation message
+    pub fn tool_invoke(name: impl Into<String>, args: Vec<String>) -> Self {
+        IpcMessage::ToolInvoke {
+            name: name.into(),
+            args,
+        }
+    }
+
+    /// Create a tool output message from a tool's outcome
+    pub fn tool_output(name: impl Into<String>, outcome: Result<String, String>) -> Self {
+        let (result, error) = match outcome {
+            Ok(result) => (Some(result), None),
+            Err(error) => (None, Some(error)),
+        };
+        IpcMessage::ToolOutput {
+            name: name.into(),
+            result,
+            error,
+        }
+    }
+
     /// Try to parse from JSON, fallback to plain text
     pub fn from_json_or_text(json: &str) -> Self {
         serde_json::from_str(json).unwrap_or_else(|_| IpcMessage::text(json))
@@ -80,7 +385,7 @@ mod tests {
         let parsed: IpcMessage = serde_json::from_str(&json).unwrap();
         
         match parsed {
-            IpcMessage::Text { content } => assert_eq!(content, "Hello"),
+            IpcMessage::Text { content, thread_id } => assert_eq!((content.as_str(), thread_id), ("Hello", None)),
             _ => panic!("Wrong message type"),
         }
     }
@@ -107,13 +412,81 @@ mod tests {
         }
     }
     
+    #[test]
+    fn test_task_messages() {
+        let task = TaskRequest::new("Summarize the report")
+            .with_deadline(Duration::from_secs(60))
+            .with_reply_to("http://127.0.0.1:9000/message");
+        assert_ne!(task.id, TaskRequest::new("other").id);
+        assert!(!task.is_expired());
+
+        let json = IpcMessage::task_request(task.clone()).to_json().unwrap();
+        assert!(json.contains("\"type\":\"TaskRequest\""));
+        match IpcMessage::from_json_or_text(&json) {
+            IpcMessage::TaskRequest(parsed) => assert_eq!(parsed, task),
+            _ => panic!("Should parse as TaskRequest"),
+        }
+
+        let json = IpcMessage::task_result(&task.id, TaskStatus::Completed, "done").to_json().unwrap();
+        match IpcMessage::from_json_or_text(&json) {
+            IpcMessage::TaskResult(result) => {
+                assert_eq!(result.id, task.id);
+                assert_eq!(result.status, TaskStatus::Completed);
+            }
+            _ => panic!("Should parse as TaskResult"),
+        }
+
+        let mut expired = TaskRequest::new("late");
+        expired.deadline = Some(1);
+        assert!(expired.is_expired());
+    }
+
+    #[test]
+    fn test_tool_invoke_messages() {
+        let json = IpcMessage::tool_invoke("square", vec!["4".to_string()]).to_json().unwrap();
+        assert_eq!(json, r#"{"type":"ToolInvoke","name":"square","args":["4"]}"#);
+
+        let json = IpcMessage::tool_output("square", Ok("16".to_string())).to_json().unwrap();
+        assert_eq!(json, r#"{"type":"ToolOutput","name":"square","result":"16"}"#);
+        match IpcMessage::from_json_or_text(r#"{"type":"ToolOutput","name":"square","error":"refused"}"#) {
+            IpcMessage::ToolOutput { result, error, .. } => {
+                assert_eq!(result, None);
+                assert_eq!(error.as_deref(), Some("refused"));
+            }
+            _ => panic!("Should parse as ToolOutput"),
+        }
+    }
+
+    #[test]
+    fn test_threaded_text_message() {
+        let msg = IpcMessage::threaded("Offer: 40", Some("deal-7".to_string()));
+        let json = msg.to_json().unwrap();
+        assert!(json.contains(r#""thread_id":"deal-7""#), "{}", json);
+        assert_eq!(IpcMessage::from_json_or_text(&json).thread_id(), Some("deal-7"));
+        // Senders without threads leave the field out
+        assert!(!IpcMessage::text("hi").to_json().unwrap().contains("thread_id"));
+    }
+
+    #[test]
+    fn test_task_bidding_messages() {
+        let announce = IpcMessage::TaskAnnounce(TaskAnnounce::new(TaskRequest::new("summarize"), vec!["scrape_url".to_string()]));
+        let json = announce.to_json().unwrap();
+        assert!(json.contains(r#""type":"TaskAnnounce""#), "{}", json);
+        assert_eq!(IpcMessage::from_json_or_text(&json), announce);
+
+        let cheap = TaskBid { task_id: "t".to_string(), bidder: "a".to_string(), cost_estimate: 1.0, capability_match: 1.0 };
+        let busy = TaskBid { cost_estimate: 4.0, ..cheap.clone() };
+        let partial = TaskBid { capability_match: 0.5, ..cheap.clone() };
+        assert!(cheap.score() > busy.score() && cheap.score() > partial.score());
+    }
+
     #[test]
     fn test_backward_compatibility() {
         // Plain text should be parsed as Text message
         let msg = IpcMessage::from_json_or_text("Just a plain message");
         
         match msg {
-            IpcMessage::Text { content } => assert_eq!(content, "Just a plain message"),
+            IpcMessage::Text { content, .. } => assert_eq!(content, "Just a plain message"),
             _ => panic!("Should parse as Text"),
         }
     }
@@ -0,0 +1,104 @@
+use prometheus::{
+    Encoder, HistogramOpts, HistogramVec, IntCounterVec, IntGauge, Opts, Registry, TextEncoder,
+};
+use std::sync::OnceLock;
+use std::time::Duration;
+use crate::llm::Usage;
+use crate::message::ToolSafetyLevel;
+
+/// Latency buckets in seconds, from a quick native to a slow model answer
+const BUCKETS: &[f64] = &[0.005, 0.025, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0, 120.0];
+
+/// Counters and histograms served on `GET /metrics`, shared by the whole process
+pub struct Metrics {
+    registry: Registry,
+    llm_requests: IntCounterVec,
+    llm_seconds: HistogramVec,
+    llm_tokens: IntCounterVec,
+    tool_runs: IntCounterVec,
+    tool_seconds: HistogramVec,
+    ipc_messages: IntCounterVec,
+    pending_tools: IntGauge,
+    pending_tasks: IntGauge,
+}
+
+/// The process's metrics
+pub fn metrics() -> &'static Metrics {
+    static METRICS: OnceLock<Metrics> = OnceLock::new();
+    METRICS.get_or_init(Metrics::new)
+}
+
+fn outcome(ok: bool) -> &'static str {
+    if ok { "ok" } else { "error" }
+}
+
+impl Metrics {
+    fn new() -> Self {
+        let registry = Registry::new_custom(Some("swarm".to_string()), None).expect("valid metric prefix");
+        let counter = |name: &str, help: &str, labels: &[&str]| {
+            let counter = IntCounterVec::new(Opts::new(name, help), labels).expect("valid counter");
+            registry.register(Box::new(counter.clone())).expect("counter registered once");
+            counter
+        };
+        let histogram = |name: &str, help: &str, labels: &[&str]| {
+            let histogram = HistogramVec::new(HistogramOpts::new(name, help).buckets(BUCKETS.to_vec()), labels)
+                .expect("valid histogram");
+            registry.register(Box::new(histogram.clone())).expect("histogram registered once");
+            histogram
+        };
+        let gauge = |name: &str, help: &str| {
+            let gauge = IntGauge::new(name, help).expect("valid gauge");
+            registry.register(Box::new(gauge.clone())).expect("gauge registered once");
+            gauge
+        };
+        Self {
+            llm_requests: counter("llm_requests_total", "Requests to the model, by model and outcome", &["model", "outcome"]),
+            llm_seconds: histogram("llm_request_duration_seconds", "Time the model took to answer", &["model"]),
+            llm_tokens: counter("llm_tokens_total", "Tokens reported by the provider, by direction", &["model", "direction"]),
+            tool_runs: counter("tool_executions_total", "Tool runs, by safety level and outcome", &["level", "outcome"]),
+            tool_seconds: histogram("tool_execution_duration_seconds", "Time tool runs took", &["level"]),
+            ipc_messages: counter("ipc_messages_total", "Peer messages received (in) and sent (out), by kind", &["direction", "kind"]),
+            pending_tools: gauge("pending_tools", "Tools from peers waiting for approval"),
+            pending_tasks: gauge("pending_tasks", "Delegated tasks waiting to run"),
+            registry,
+        }
+    }
+
+    /// One request to `model`; `usage` is `None` when it failed
+    pub fn observe_llm(&self, model: &str, elapsed: Duration, usage: Option<&Usage>) {
+        self.llm_requests.with_label_values(&[model, outcome(usage.is_some())]).inc();
+        self.llm_seconds.with_label_values(&[model]).observe(elapsed.as_secs_f64());
+        if let Some(usage) = usage {
+            self.llm_tokens.with_label_values(&[model, "input"]).inc_by(usage.input_tokens);
+            self.llm_tokens.with_label_values(&[model, "output"]).inc_by(usage.output_tokens);
+        }
+    }
+
+    pub fn observe_tool(&self, level: &ToolSafetyLevel, elapsed: Duration, ok: bool) {
+        let level = format!("{:?}", level);
+        self.tool_runs.with_label_values(&[&level, outcome(ok)]).inc();
+        self.tool_seconds.with_label_values(&[&level]).observe(elapsed.as_secs_f64());
+    }
+
+    pub fn message_received(&self, kind: &str) {
+        self.ipc_messages.with_label_values(&["in", kind]).inc();
+    }
+
+    pub fn message_sent(&self, kind: &str) {
+        self.ipc_messages.with_label_values(&["out", kind]).inc();
+    }
+
+    /// Queue depths, sampled when the metrics are scraped
+    pub fn set_pending(&self, tools: usize, tasks: usize) {
+        self.pending_tools.set(tools as i64);
+        self.pending_tasks.set(tasks as i64);
+    }
+
+    /// Everything, in the Prometheus text exposition format
+    pub fn render(&self) -> String {
+        let mut buffer = Vec::new();
+        // Encoding into a Vec only fails on malformed metric families, which `new` rules out
+        let _ = TextEncoder::new().encode(&self.registry.gather(), &mut buffer);
+        String::from_utf8(buffer).unwrap_or_default()
+    }
+}
@@ -0,0 +1,324 @@
+use anyhow::{Result, anyhow};
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use tokio::sync::{mpsc, oneshot};
+use tokio::task::JoinHandle;
+use crate::agent::{Agent, Conversation};
+use crate::config::Config;
+use crate::llm::LlmClient;
+use crate::persona::{personas_dir, Persona};
+use crate::prompts::PromptLibrary;
+use crate::parser::tool_calls;
+use crate::tools::{tool_feedback, ToolManager};
+use tracing::info;
+
+/// A message between agents on the bus; requests carry a channel for the reply
+pub struct Envelope {
+    pub from: String,
+    pub to: String,
+    pub content: String,
+    reply: Option<oneshot::Sender<String>>,
+}
+
+impl Envelope {
+    /// Answer a request; plain messages ignore the reply
+    pub fn respond(self, content: String) {
+        if let Some(reply) = self.reply {
+            let _ = reply.send(content);
+        }
+    }
+}
+
+/// In-process message bus: one unbounded mailbox per registered agent
+#[derive(Clone, Default)]
+pub struct MessageBus {
+    mailboxes: Arc<Mutex<HashMap<String, mpsc::UnboundedSender<Envelope>>>>,
+}
+
+impl MessageBus {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create the mailbox for `name`, replacing any earlier one
+    pub fn register(&self, name: &str) -> mpsc::UnboundedReceiver<Envelope> {
+        let (tx, rx) = mpsc::unbounded_channel();
+        self.mailboxes.lock().unwrap().insert(name.to_string(), tx);
+        rx
+    }
+
+    pub fn unregister(&self, name: &str) {
+        self.mailboxes.lock().unwrap().remove(name);
+    }
+
+    /// Registered agent names, sorted
+    pub fn members(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.mailboxes.lock().unwrap().keys().cloned().collect();
+        names.sort();
+        names
+    }
+
+    fn deliver(&self, envelope: Envelope) -> Result<()> {
+        let mailboxes = self.mailboxes.lock().unwrap();
+        let mailbox = mailboxes.get(&envelope.to).ok_or_else(|| anyhow!("No agent named '{}' on the bus", envelope.to))?;
+        mailbox.send(envelope).map_err(|e| anyhow!("Agent '{}' is no longer running", e.0.to))
+    }
+
+    /// Fire-and-forget message
+    pub fn send(&self, from: &str, to: &str, content: impl Into<String>) -> Result<()> {
+        self.deliver(Envelope { from: from.to_string(), to: to.to_string(), content: content.into(), reply: None })
+    }
+
+    /// Send a request and return the channel its reply will arrive on
+    pub fn request(&self, from: &str, to: &str, content: impl Into<String>) -> Result<oneshot::Receiver<String>> {
+        let (tx, rx) = oneshot::channel();
+        self.deliver(Envelope { from: from.to_string(), to: to.to_string(), content: content.into(), reply: Some(tx) })?;
+        Ok(rx)
+    }
+}
+
+/// An agent together with its own tools and persona
+/// Conversations a `Member` keeps apart at most; the least recently used is dropped first
+pub const MAX_CONVERSATIONS: usize = 64;
+
+pub struct Member {
+    pub name: String,
+    pub role: String,
+    agent: Agent,
+    tools: ToolManager,
+    system_prompt: String,
+    max_steps: usize,
+    /// Conversations of `handle_in`, least recently used first
+    conversations: VecDeque<(String, Conversation)>,
+}
+
+impl Member {
+    /// Wrap an agent and the tools it may call; `system_prompt` is the agent's base prompt
+    pub fn new(name: impl Into<String>, role: impl Into<String>, agent: Agent, tools: ToolManager, system_prompt: &str) -> Self {
+        Self {
+            name: name.into(),
+            role: role.into(),
+            agent,
+            tools,
+            system_prompt: system_prompt.to_string(),
+            max_steps: 5,
+            conversations: VecDeque::new(),
+        }
+    }
+
+    /// Build a member from a persona: its tool policy, model and prompt
+    pub async fn from_persona(persona: &Persona, prompts: &PromptLibrary) -> Result<Self> {
+        let mut tools = ToolManager::new()?;
+        tools.set_policy(persona.tools.clone());
+        let mut context = persona.prompt_context(tools.list_tools());
+        context.tool_signatures = tools.prompt_signatures(&context.tools);
+        let system_prompt = prompts.render(&persona.prompt, &context)?;
+        let llm = match &persona.model {
+            Some(spec) => LlmClient::from_spec(spec).await?,
+            None => LlmClient::new().await?,
+        };
+        let protocol = Config::from_env().map(|c| c.llm.protocol_for(&llm.label())).unwrap_or_default();
+        let mut agent = Agent::with_client(llm, &system_prompt);
+        agent.set_protocol(protocol);
+        Ok(Self::new(&persona.name, &persona.role, agent, tools, &system_prompt))
+    }
+
+    /// Limit how many rounds of tool calls one request may chain (default 5)
+    pub fn with_max_steps(mut self, max_steps: usize) -> Self {
+        self.max_steps = max_steps;
+        self
+    }
+
+    pub fn agent(&self) -> &Agent {
+        &self.agent
+    }
+
+    pub fn tools(&self) -> &ToolManager {
+        &self.tools
+    }
+
+    pub fn tools_mut(&mut self) -> &mut ToolManager {
+        &mut self.tools
+    }
+
+    /// Answer `input`, running the tools each response calls and feeding their output back
+    pub async fn handle(&mut self, input: &str) -> Result<String> {
+        let mut response = self.agent.chat(input).await?;
+        for _ in 0..self.max_steps {
+            let calls = tool_calls(&response);
+            if calls.is_empty() {
+                break;
+            }
+            for (name, _) in &calls {
+                info!("[{}] Executing tool: {}", self.name, name);
+            }
+            // In order, with independent web fetches at the same time
+            let outcomes = self.tools.execute_tools(&calls);
+            let results: Vec<(String, Result<String, String>)> = calls.into_iter().zip(outcomes)
+                .map(|((name, _), result)| (name, result.map_err(|e| e.to_string())))
+                .collect();
+            response = self.agent.chat(&tool_feedback(&results)).await?;
+        }
+        Ok(response)
+    }
+
+    /// `handle`, in the conversation kept for `thread`, e.g. one peer's thread (see
+    /// `InboxMessage::thread_key`). Each thread has its own history with the model, apart from
+    /// the others and from `handle`'s.
+    pub async fn handle_in(&mut self, thread: &str, input: &str) -> Result<String> {
+        let conversation = match self.conversations.iter().position(|(key, _)| key == thread) {
+            Some(index) => self.conversations.remove(index).map(|(_, c)| c).unwrap_or_default(),
+            None => Conversation::default(),
+        };
+        let own = self.agent.take_conversation();
+        self.agent.resume_conversation(conversation);
+        let answer = self.handle(input).await;
+        self.conversations.push_back((thread.to_string(), self.agent.take_conversation()));
+        if self.conversations.len() > MAX_CONVERSATIONS {
+            self.conversations.pop_front();
+        }
+        self.agent.resume_conversation(own);
+        answer
+    }
+
+    /// The conversation kept for `thread`, if `handle_in` has had one
+    pub fn conversation(&self, thread: &str) -> Option<&Conversation> {
+        self.conversations.iter().find(|(key, _)| key == thread).map(|(_, c)| c)
+    }
+}
+
+/// `[DELEGATE: worker] sub-task` lines in a coordinator response
+pub fn parse_delegations(response: &str) -> Vec<(String, String)> {
+    response.lines()
+        .filter_map(|line| {
+            let rest = line.trim().strip_prefix("[DELEGATE:")?;
+            let (worker, task) = rest.split_once(']')?;
+            let task = task.trim();
+            (!task.is_empty()).then(|| (worker.trim().to_string(), task.to_string()))
+        })
+        .collect()
+}
+
+/// Hosts a coordinator and several workers in one process. A user task goes to the
+/// coordinator, which delegates sub-tasks to workers over the `MessageBus`; their answers
+/// are fed back until the coordinator replies without delegating.
+pub struct Orchestrator {
+    bus: MessageBus,
+    coordinator: Member,
+    workers: Vec<(String, String)>,
+    handles: Vec<JoinHandle<()>>,
+    max_rounds: usize,
+}
+
+impl Orchestrator {
+    pub fn new(coordinator: Member) -> Self {
+        Self {
+            bus: MessageBus::new(),
+            coordinator,
+            workers: Vec::new(),
+            handles: Vec::new(),
+            max_rounds: 3,
+        }
+    }
+
+    /// Coordinator persona from `ORCHESTRATOR_COORDINATOR` (default `coordinator`) and
+    /// comma separated worker personas from `ORCHESTRATOR_WORKERS` (default `researcher,coder,reviewer`)
+    pub async fn from_env() -> Result<Self> {
+        let dir = personas_dir();
+        let prompts = PromptLibrary::from_env();
+        let coordinator = std::env::var("ORCHESTRATOR_COORDINATOR").unwrap_or_else(|_| "coordinator".to_string());
+        let workers = std::env::var("ORCHESTRATOR_WORKERS").unwrap_or_else(|_| "researcher,coder,reviewer".to_string());
+
+        let mut orchestrator = Self::new(Member::from_persona(&Persona::load(&dir, &coordinator)?, &prompts).await?);
+        if let Some(rounds) = std::env::var("ORCHESTRATOR_MAX_ROUNDS").ok().and_then(|v| v.parse().ok()) {
+            orchestrator.max_rounds = rounds;
+        }
+        for name in workers.split(',').map(str::trim).filter(|n| !n.is_empty()) {
+            orchestrator.add_worker(Member::from_persona(&Persona::load(&dir, name)?, &prompts).await?);
+        }
+        Ok(orchestrator)
+    }
+
+    /// Limit how many delegate/answer rounds one task may take (default 3)
+    pub fn with_max_rounds(mut self, max_rounds: usize) -> Self {
+        self.max_rounds = max_rounds;
+        self
+    }
+
+    /// Register a worker on the bus and start serving its mailbox
+    pub fn add_worker(&mut self, mut worker: Member) {
+        let mut mailbox = self.bus.register(&worker.name);
+        self.workers.push((worker.name.clone(), worker.role.clone()));
+        self.handles.push(tokio::spawn(async move {
+            while let Some(envelope) = mailbox.recv().await {
+                info!("{} → {}: {}", envelope.from, envelope.to, envelope.content);
+                let answer = worker.handle(&envelope.content).await
+                    .unwrap_or_else(|e| format!("Error: {}", e));
+                envelope.respond(answer);
+            }
+        }));
+    }
+
+    pub fn bus(&self) -> &MessageBus {
+        &self.bus
+    }
+
+    /// Worker names, in the order they were added
+    pub fn workers(&self) -> Vec<String> {
+        self.workers.iter().map(|(name, _)| name.clone()).collect()
+    }
+
+    fn coordinator_prompt(&self) -> String {
+        let mut prompt = self.coordinator.system_prompt.clone();
+        prompt.push_str("\n\nYou coordinate these worker agents:\n");
+        for (name, role) in &self.workers {
+            prompt.push_str(&format!("- {}: {}\n", name, role));
+        }
+        prompt.push_str("To delegate, write one line per sub-task: [DELEGATE: worker_name] sub-task description\n");
+        prompt.push_str("Their answers come back in the next message. Reply without [DELEGATE:] lines once you can answer.\n");
+        prompt
+    }
+
+    /// Run a user task through the coordinator and its workers, returning the final answer
+    pub async fn run(&mut self, task: &str) -> Result<String> {
+        let prompt = self.coordinator_prompt();
+        self.coordinator.agent.set_system_prompt(&prompt);
+
+        let coordinator = self.coordinator.name.clone();
+        let mut response = self.coordinator.handle(task).await?;
+        for _ in 0..self.max_rounds {
+            let delegations = parse_delegations(&response);
+            if delegations.is_empty() {
+                break;
+            }
+
+            // Send every sub-task first so workers run in parallel
+            let pending: Vec<(String, Result<oneshot::Receiver<String>>)> = delegations.into_iter()
+                .map(|(worker, sub_task)| {
+                    let reply = self.bus.request(&coordinator, &worker, sub_task);
+                    (worker, reply)
+                })
+                .collect();
+
+            let mut results = String::from("Results from workers:\n");
+            for (worker, reply) in pending {
+                let answer = match reply {
+                    Ok(rx) => rx.await.unwrap_or_else(|_| "Error: worker stopped before answering".to_string()),
+                    Err(e) => format!("Error: {}", e),
+                };
+                results.push_str(&format!("[{}] {}\n", worker, answer.trim()));
+            }
+            response = self.coordinator.handle(&results).await?;
+        }
+        Ok(response)
+    }
+}
+
+impl Drop for Orchestrator {
+    fn drop(&mut self) {
+        for handle in &self.handles {
+            handle.abort();
+        }
+    }
+}
+
@@ -0,0 +1,256 @@
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use crate::audit::AuditLog;
+use crate::auth::IpcAuth;
+use crate::egress;
+use crate::ipc::{message_url, Message};
+use crate::message::IpcMessage;
+use crate::registry::unix_now;
+use crate::retry::{is_transient, is_transient_status, transient, RetryPolicy};
+use crate::safety::SafetyPolicy;
+use crate::state::StateStore;
+use tracing::{error, info, warn};
+use rhai::Engine;
+use crate::tools::NativeContext;
+
+const OUTBOX_KEY: &str = "outbox";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DeliveryStatus {
+    Pending,
+    Delivered,
+    /// Gave up: retries ran out or the peer refused the message
+    Failed,
+}
+
+/// A message waiting to be (or already) delivered to a peer
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OutboxEntry {
+    pub id: u64,
+    /// Peer address or message URL
+    pub to: String,
+    pub message: IpcMessage,
+    /// Our own server address, sent along as `from`
+    pub from: Option<String>,
+    pub context: Option<String>,
+    pub status: DeliveryStatus,
+    pub attempts: u32,
+    /// Unix time (seconds) of the next delivery attempt
+    pub next_attempt: u64,
+    pub last_error: Option<String>,
+    pub created_at: u64,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct OutboxData {
+    entries: Vec<OutboxEntry>,
+    next_id: u64,
+}
+
+/// Messages that couldn't be delivered right away, persisted in the agent state store
+/// and retried with exponential backoff by `run`
+#[derive(Debug, Clone)]
+pub struct Outbox {
+    store: StateStore,
+    retry: RetryPolicy,
+}
+
+impl Outbox {
+    pub fn new(store: StateStore) -> Self {
+        Self {
+            store,
+            retry: RetryPolicy { max_retries: 10, base_delay: Duration::from_secs(1), max_delay: Duration::from_secs(300) },
+        }
+    }
+
+    /// The agent state store, retrying up to `OUTBOX_MAX_ATTEMPTS` times (default 10)
+    pub fn from_env() -> Result<Self> {
+        let mut outbox = Self::new(StateStore::from_env()?);
+        if let Some(attempts) = std::env::var("OUTBOX_MAX_ATTEMPTS").ok().and_then(|v| v.parse().ok()) {
+            outbox.retry.max_retries = attempts;
+        }
+        Ok(outbox)
+    }
+
+    pub fn with_retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// Queue `message` for `to`, due immediately. Returns its id.
+    pub fn enqueue(&self, to: &str, message: IpcMessage, from: Option<String>, context: Option<String>) -> Result<u64> {
+        self.store.update(OUTBOX_KEY, |data: &mut OutboxData| {
+            data.next_id += 1;
+            let now = unix_now();
+            data.entries.push(OutboxEntry {
+                id: data.next_id,
+                to: to.to_string(),
+                message,
+                from,
+                context,
+                status: DeliveryStatus::Pending,
+                attempts: 0,
+                next_attempt: now,
+                last_error: None,
+                created_at: now,
+            });
+            data.next_id
+        })
+    }
+
+    pub fn list(&self) -> Result<Vec<OutboxEntry>> {
+        Ok(self.store.get::<OutboxData>(OUTBOX_KEY)?.unwrap_or_default().entries)
+    }
+
+    pub fn get(&self, id: u64) -> Result<Option<OutboxEntry>> {
+        Ok(self.list()?.into_iter().find(|e| e.id == id))
+    }
+
+    /// Pending entries whose next attempt is due
+    pub fn due(&self) -> Result<Vec<OutboxEntry>> {
+        let now = unix_now();
+        Ok(self.list()?.into_iter()
+            .filter(|e| e.status == DeliveryStatus::Pending && e.next_attempt <= now)
+            .collect())
+    }
+
+    /// Drop an entry whatever its status; false for unknown ids
+    pub fn cancel(&self, id: u64) -> Result<bool> {
+        self.store.update(OUTBOX_KEY, |data: &mut OutboxData| {
+            let before = data.entries.len();
+            data.entries.retain(|e| e.id != id);
+            data.entries.len() != before
+        })
+    }
+
+    /// Forget delivered entries; returns how many were dropped
+    pub fn clear_delivered(&self) -> Result<usize> {
+        self.store.update(OUTBOX_KEY, |data: &mut OutboxData| {
+            let before = data.entries.len();
+            data.entries.retain(|e| e.status != DeliveryStatus::Delivered);
+            before - data.entries.len()
+        })
+    }
+
+    fn record(&self, id: u64, outcome: Result<()>) -> Result<()> {
+        let retry = self.retry.clone();
+        self.store.update(OUTBOX_KEY, |data: &mut OutboxData| {
+            let Some(entry) = data.entries.iter_mut().find(|e| e.id == id) else {
+                return;
+            };
+            entry.attempts += 1;
+            match outcome {
+                Ok(()) => {
+                    entry.status = DeliveryStatus::Delivered;
+                    entry.last_error = None;
+                }
+                Err(e) => {
+                    entry.last_error = Some(e.to_string());
+                    if !is_transient(&e) || entry.attempts > retry.max_retries {
+                        entry.status = DeliveryStatus::Failed;
+                    } else {
+                        entry.next_attempt = unix_now() + retry.delay(entry.attempts - 1).as_secs().max(1);
+                    }
+                }
+            }
+        })
+    }
+
+    /// Try every due entry once; returns how many were delivered
+    pub async fn deliver_due(&self) -> Result<usize> {
+        let mut delivered = 0;
+        for entry in self.due()? {
+            let outcome = deliver(&entry).await;
+            match &outcome {
+                Ok(()) => {
+                    info!("Delivered queued message #{} to {}", entry.id, entry.to);
+                    delivered += 1;
+                }
+                Err(e) => warn!("Delivery of message #{} to {} failed: {}", entry.id, entry.to, e),
+            }
+            self.record(entry.id, outcome)?;
+        }
+        Ok(delivered)
+    }
+
+    /// Keep delivering due entries, checking every `poll`. Runs until the task is dropped.
+    pub async fn run(self, poll: Duration) {
+        loop {
+            if let Err(e) = self.deliver_due().await {
+                error!("Outbox error: {}", e);
+            }
+            tokio::time::sleep(poll).await;
+        }
+    }
+
+    /// One line per entry that isn't delivered yet, then a count of delivered ones
+    pub fn render_status(&self) -> Result<String> {
+        let entries = self.list()?;
+        let now = unix_now();
+        let mut lines: Vec<String> = entries.iter()
+            .filter(|e| e.status != DeliveryStatus::Delivered)
+            .map(|e| {
+                let mut line = format!("#{} to {}: {:?} after {} attempt(s)", e.id, e.to, e.status, e.attempts);
+                if e.status == DeliveryStatus::Pending {
+                    line.push_str(&format!(", next in {}s", e.next_attempt.saturating_sub(now)));
+                }
+                if let Some(error) = &e.last_error {
+                    line.push_str(&format!(" ({})", error));
+                }
+                line
+            })
+            .collect();
+        let delivered = entries.iter().filter(|e| e.status == DeliveryStatus::Delivered).count();
+        lines.push(format!("{} delivered", delivered));
+        Ok(lines.join("\n"))
+    }
+}
+
+/// Post an entry's message; connection failures and 429/5xx replies are transient
+async fn deliver(entry: &OutboxEntry) -> Result<()> {
+    // The policy may have changed since the message was queued
+    egress::guard(&SafetyPolicy::from_env()?, &entry.to, "outbox", AuditLog::from_env().ok().as_ref())?;
+    let mut payload = Message { context: entry.context.clone(), ..Message::new(&entry.message, entry.from.clone())? };
+    IpcAuth::from_env().sign(&mut payload);
+    let resp = reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .dns_resolver(egress::resolver(SafetyPolicy::from_env))
+        .build()?
+        .post(message_url(&entry.to))
+        .timeout(Duration::from_secs(30))
+        .json(&payload)
+        .send()
+        .await
+        .map_err(|e| transient(e.to_string()))?;
+    let status = resp.status();
+    if status.is_success() {
+        Ok(())
+    } else if is_transient_status(status) {
+        Err(transient(format!("peer answered {}", status)))
+    } else {
+        Err(anyhow!("peer refused the message ({}): {}", status, resp.text().await.unwrap_or_default()))
+    }
+}
+
+/// `outbox_status`
+pub(crate) fn register_outbox_natives(engine: &mut Engine, ctx: &NativeContext) {
+    let NativeContext { outbox, .. } = ctx;
+    let outbox_clone = outbox.clone();
+    engine.register_fn("outbox_status", move || -> String {
+        outbox_clone.render_status().unwrap_or_else(|e| format!("Error reading outbox: {}", e))
+    });
+
+    let outbox_clone = outbox.clone();
+    engine.register_fn("outbox_status", move |id: &str| -> String {
+        let Ok(id) = id.trim().trim_start_matches('#').parse::<u64>() else {
+            return format!("Error: '{}' is not a message id", id);
+        };
+        match outbox_clone.get(id) {
+            Ok(Some(entry)) => format!("#{} to {}: {:?} after {} attempt(s){}", entry.id, entry.to, entry.status, entry.attempts,
+                entry.last_error.map(|e| format!(" ({})", e)).unwrap_or_default()),
+            Ok(None) => format!("Error: no outbox message #{}", id),
+            Err(e) => format!("Error reading outbox: {}", e),
+        }
+    });
+}
@@ -0,0 +1,424 @@
+use anyhow::{anyhow, Result};
+use serde::Deserialize;
+use std::time::Duration;
+use tracing::info;
+use crate::egress;
+use crate::politeness::Politeness;
+use rhai::Engine;
+use crate::tools::NativeContext;
+
+/// arXiv asks API users to wait three seconds between requests
+const ARXIV_DELAY: Duration = Duration::from_secs(3);
+
+/// Fields asked of Semantic Scholar for `get_paper`
+const SCHOLAR_FIELDS: &str = "title,authors,year,publicationDate,abstract,externalIds,url,openAccessPdf,venue,citationCount";
+
+/// Metadata of a paper from arXiv or Semantic Scholar
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Paper {
+    /// `arXiv:<id>` for arXiv papers, else the Semantic Scholar paper id
+    pub id: String,
+    pub title: String,
+    pub authors: Vec<String>,
+    /// Publication date (`YYYY-MM-DD`) or year, as the source knows it
+    pub published: Option<String>,
+    pub summary: String,
+    pub doi: Option<String>,
+    /// arXiv categories, or the venue for Semantic Scholar papers
+    pub categories: Vec<String>,
+    pub citations: Option<u64>,
+    pub url: Option<String>,
+    pub pdf_url: Option<String>,
+}
+
+impl Paper {
+    /// One labelled line per field, the abstract last
+    pub fn render(&self) -> String {
+        let mut lines = vec![format!("Title: {}", self.title), format!("Authors: {}", self.authors.join(", "))];
+        let optional = [
+            ("Published", self.published.clone()),
+            ("ID", Some(self.id.clone())),
+            ("DOI", self.doi.clone()),
+            ("Categories", Some(self.categories.join(", ")).filter(|c| !c.is_empty())),
+            ("Citations", self.citations.map(|c| c.to_string())),
+            ("URL", self.url.clone()),
+            ("PDF", self.pdf_url.clone()),
+        ];
+        lines.extend(optional.into_iter().filter_map(|(label, value)| Some(format!("{}: {}", label, value?))));
+        lines.push(format!("Abstract: {}", self.summary));
+        lines.join("\n")
+    }
+}
+
+/// The arXiv id in `id` (`2101.00001v2`, `arXiv:2101.00001`, `hep-th/9901001` or an
+/// arxiv.org abs/pdf link), None when it isn't one
+pub fn arxiv_id(id: &str) -> Option<String> {
+    let id = id.trim();
+    let id = ["https://arxiv.org/abs/", "http://arxiv.org/abs/", "https://arxiv.org/pdf/", "http://arxiv.org/pdf/", "arXiv:", "arxiv:", "ARXIV:"]
+        .iter()
+        .find_map(|prefix| id.strip_prefix(prefix))
+        .unwrap_or(id);
+    let id = id.trim_end_matches(".pdf");
+    let digits = |s: &str, min: usize, max: usize| (min..=max).contains(&s.len()) && s.bytes().all(|b| b.is_ascii_digit());
+    let number = |s: &str, min: usize, max: usize| match s.split_once('v') {
+        Some((number, version)) => digits(number, min, max) && digits(version, 1, 3),
+        None => digits(s, min, max),
+    };
+    let valid = match id.split_once('/') {
+        // Old style: archive(.subject)/YYMMNNN
+        Some((archive, number_part)) => {
+            !archive.is_empty() && archive.bytes().all(|b| b.is_ascii_alphabetic() || b == b'-' || b == b'.') && number(number_part, 7, 7)
+        }
+        // New style: YYMM.NNNN(N)
+        None => id.split_once('.').is_some_and(|(month, number_part)| digits(month, 4, 4) && number(number_part, 4, 5)),
+    };
+    valid.then(|| id.to_string())
+}
+
+/// Papers from an arXiv API Atom feed
+pub fn parse_arxiv_feed(xml: &str) -> Result<Vec<Paper>> {
+    let document = scraper::Html::parse_document(xml);
+    let entry_selector = scraper::Selector::parse("entry").unwrap();
+    let text = |element: scraper::ElementRef| element.text().collect::<String>().split_whitespace().collect::<Vec<_>>().join(" ");
+    let mut papers = Vec::new();
+    for entry in document.select(&entry_selector) {
+        let mut paper = Paper::default();
+        // Parsed as HTML, `<category .../>` doesn't close itself and holds what follows it
+        for child in entry.descendants().filter_map(scraper::ElementRef::wrap) {
+            let element = child.value();
+            match element.name() {
+                "id" => {
+                    let url = text(child);
+                    paper.id = format!("arXiv:{}", url.rsplit_once("/abs/").map(|(_, id)| id).unwrap_or(&url));
+                    paper.url = Some(url.replacen("http://arxiv.org/", "https://arxiv.org/", 1));
+                }
+                "title" => paper.title = text(child),
+                "summary" => paper.summary = text(child),
+                "published" => paper.published = Some(text(child).chars().take(10).collect()),
+                "name" => paper.authors.push(text(child)),
+                "arxiv:doi" => paper.doi = Some(text(child)),
+                "category" => paper.categories.extend(element.attr("term").map(String::from)),
+                "link" if element.attr("title") == Some("pdf") => {
+                    paper.pdf_url = element.attr("href").map(|href| href.replacen("http://arxiv.org/", "https://arxiv.org/", 1));
+                }
+                _ => {}
+            }
+        }
+        // A query arXiv can't run comes back as a single entry titled "Error"
+        if paper.title == "Error" && paper.authors.iter().any(|a| a == "arXiv api core") {
+            return Err(anyhow!("arXiv: {}", paper.summary));
+        }
+        if !paper.title.is_empty() {
+            papers.push(paper);
+        }
+    }
+    Ok(papers)
+}
+
+#[derive(Deserialize)]
+struct ScholarAuthor {
+    name: String,
+}
+
+#[derive(Deserialize)]
+struct ScholarPdf {
+    url: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ScholarPaper {
+    paper_id: String,
+    title: Option<String>,
+    #[serde(default)]
+    authors: Vec<ScholarAuthor>,
+    year: Option<u32>,
+    publication_date: Option<String>,
+    #[serde(rename = "abstract")]
+    summary: Option<String>,
+    #[serde(default)]
+    external_ids: std::collections::HashMap<String, serde_json::Value>,
+    url: Option<String>,
+    open_access_pdf: Option<ScholarPdf>,
+    venue: Option<String>,
+    citation_count: Option<u64>,
+}
+
+impl From<ScholarPaper> for Paper {
+    fn from(paper: ScholarPaper) -> Self {
+        let external = |key: &str| paper.external_ids.get(key).and_then(|v| v.as_str()).map(String::from);
+        let arxiv = external("ArXiv");
+        Paper {
+            id: paper.paper_id,
+            title: paper.title.unwrap_or_default(),
+            authors: paper.authors.into_iter().map(|a| a.name).collect(),
+            published: paper.publication_date.or(paper.year.map(|y| y.to_string())),
+            summary: paper.summary.unwrap_or_else(|| "(no abstract available)".to_string()),
+            doi: external("DOI"),
+            categories: paper.venue.filter(|v| !v.is_empty()).into_iter().collect(),
+            citations: paper.citation_count,
+            url: paper.url,
+            // Papers without an open-access PDF may still have one on arXiv
+            pdf_url: paper.open_access_pdf.and_then(|pdf| pdf.url)
+                .or(arxiv.map(|id| format!("https://arxiv.org/pdf/{}", id))),
+        }
+    }
+}
+
+/// Looks papers up on arXiv and Semantic Scholar, as `search_arxiv` and `get_paper` do
+#[derive(Clone)]
+pub struct PaperClient {
+    arxiv_url: String,
+    scholar_url: String,
+    scholar_key: Option<String>,
+    arxiv_delay: Duration,
+    max_results: usize,
+    politeness: Politeness,
+}
+
+impl Default for PaperClient {
+    fn default() -> Self {
+        Self {
+            arxiv_url: "https://export.arxiv.org/api/query".to_string(),
+            scholar_url: "https://api.semanticscholar.org/graph/v1".to_string(),
+            scholar_key: None,
+            arxiv_delay: ARXIV_DELAY,
+            max_results: 5,
+            politeness: Politeness::default(),
+        }
+    }
+}
+
+impl PaperClient {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The arXiv API query endpoint
+    pub fn with_arxiv_url(mut self, url: &str) -> Self {
+        self.arxiv_url = url.trim_end_matches('/').to_string();
+        self
+    }
+
+    /// The Semantic Scholar Graph API base, e.g. `https://api.semanticscholar.org/graph/v1`
+    pub fn with_scholar_url(mut self, url: &str) -> Self {
+        self.scholar_url = url.trim_end_matches('/').to_string();
+        self
+    }
+
+    /// Sent as `x-api-key`, for Semantic Scholar's higher rate limits
+    pub fn with_scholar_key(mut self, key: Option<String>) -> Self {
+        self.scholar_key = key.filter(|k| !k.trim().is_empty());
+        self
+    }
+
+    /// Least time between two arXiv requests (3s by default, as arXiv asks)
+    pub fn with_arxiv_delay(mut self, delay: Duration) -> Self {
+        self.arxiv_delay = delay;
+        self
+    }
+
+    /// Papers `search_arxiv` returns when not told how many
+    pub fn with_max_results(mut self, max: usize) -> Self {
+        self.max_results = max.max(1);
+        self
+    }
+
+    /// Pace requests as `politeness` says, sharing host budgets with the scraping tools.
+    /// These are API calls, so robots.txt is not consulted.
+    pub fn with_politeness(mut self, politeness: Politeness) -> Self {
+        self.politeness = politeness.with_robots(false);
+        self
+    }
+
+    /// `ARXIV_API_URL`, `ARXIV_DELAY_MS` (3000), `ARXIV_MAX_RESULTS` (5),
+    /// `SEMANTIC_SCHOLAR_API_URL` and `SEMANTIC_SCHOLAR_API_KEY`; requests paced as
+    /// `Politeness::from_env` says
+    pub fn from_env() -> Self {
+        let var = |key: &str| std::env::var(key).ok().filter(|v| !v.trim().is_empty());
+        let mut client = Self::new()
+            .with_politeness(Politeness::from_env())
+            .with_scholar_key(var("SEMANTIC_SCHOLAR_API_KEY"));
+        if let Some(url) = var("ARXIV_API_URL") {
+            client = client.with_arxiv_url(&url);
+        }
+        if let Some(url) = var("SEMANTIC_SCHOLAR_API_URL") {
+            client = client.with_scholar_url(&url);
+        }
+        if let Some(ms) = var("ARXIV_DELAY_MS").and_then(|v| v.trim().parse().ok()) {
+            client = client.with_arxiv_delay(Duration::from_millis(ms));
+        }
+        if let Some(max) = var("ARXIV_MAX_RESULTS").and_then(|v| v.trim().parse().ok()) {
+            client = client.with_max_results(max);
+        }
+        client
+    }
+
+    pub fn arxiv_url(&self) -> &str {
+        &self.arxiv_url
+    }
+
+    pub fn scholar_url(&self) -> &str {
+        &self.scholar_url
+    }
+
+    pub fn max_results(&self) -> usize {
+        self.max_results
+    }
+
+    async fn arxiv_query(&self, params: &[(&str, String)]) -> Result<Vec<Paper>> {
+        let host = crate::egress::host_of(&self.arxiv_url).unwrap_or_default();
+        let mut politeness = self.politeness.clone();
+        if politeness.delay_for(&host) < self.arxiv_delay {
+            politeness = politeness.with_domain_delay(&host, self.arxiv_delay);
+        }
+        let client = politeness.client();
+        let _permit = politeness.admit(&client, &self.arxiv_url).await?;
+        let feed = client.get(&self.arxiv_url).query(params).send().await?.error_for_status()?.text().await?;
+        parse_arxiv_feed(&feed)
+    }
+
+    /// Up to `max` arXiv papers matching `query`, most relevant first. Plain words must all
+    /// match; a query using arXiv's field prefixes (`ti:`, `au:`, `abs:`, `cat:`) is sent as is.
+    pub async fn search_arxiv(&self, query: &str, max: usize) -> Result<Vec<Paper>> {
+        let query = query.trim();
+        if query.is_empty() {
+            return Err(anyhow!("Empty search query"));
+        }
+        let search = if query.contains(':') {
+            query.to_string()
+        } else {
+            query.split_whitespace().map(|word| format!("all:{}", word)).collect::<Vec<_>>().join(" AND ")
+        };
+        self.arxiv_query(&[
+            ("search_query", search),
+            ("start", "0".to_string()),
+            ("max_results", max.max(1).to_string()),
+            ("sortBy", "relevance".to_string()),
+        ]).await
+    }
+
+    /// The paper `id`: an arXiv id is looked up on arXiv, anything else (a DOI, a Semantic
+    /// Scholar id or one of its `DOI:`/`CorpusId:`/`PMID:` forms) on Semantic Scholar
+    pub async fn get_paper(&self, id: &str) -> Result<Paper> {
+        let id = id.trim();
+        if let Some(arxiv) = arxiv_id(id) {
+            return self.arxiv_query(&[("id_list", arxiv.clone())]).await?
+                .into_iter()
+                .next()
+                .ok_or_else(|| anyhow!("No arXiv paper {}", arxiv));
+        }
+        let id = if id.starts_with("10.") { format!("DOI:{}", id) } else { id.to_string() };
+        let client = self.politeness.client();
+        let url = format!("{}/paper/{}", self.scholar_url, id);
+        let _permit = self.politeness.admit(&client, &url).await?;
+        let mut request = client.get(&url).query(&[("fields", SCHOLAR_FIELDS)]);
+        if let Some(key) = &self.scholar_key {
+            request = request.header("x-api-key", key);
+        }
+        let response = request.send().await?;
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Err(anyhow!("No paper {} on Semantic Scholar", id));
+        }
+        let paper: ScholarPaper = response.error_for_status()?.json().await?;
+        Ok(paper.into())
+    }
+}
+
+/// `search_arxiv` and `get_paper`
+pub(crate) fn register_paper_natives(engine: &mut Engine, ctx: &NativeContext) {
+    let NativeContext { jail, audit, safety, meter, citations, politeness, knowledge, .. } = ctx;
+    // Research: search arXiv and look up papers on arXiv or Semantic Scholar;
+    // get_paper(id, true) also ingests the paper's PDF into the knowledge base
+    let papers = PaperClient::from_env().with_politeness(politeness.clone());
+    let search_arxiv = {
+        let papers = papers.clone();
+        let safety = safety.clone();
+        let audit = audit.clone();
+        let meter = meter.clone();
+        move |query: &str, max: usize| -> String {
+            if let Err(e) = egress::guard(&safety.lock().unwrap(), papers.arxiv_url(), "search_arxiv", Some(&audit)) {
+                return format!("Error: {}", e);
+            }
+            if let Err(e) = meter("search_arxiv") {
+                return format!("Error: {}", e);
+            }
+            info!("Searching arXiv for: {}", query);
+            let (papers, query) = (papers.clone(), query.to_string());
+            std::thread::spawn(move || {
+                let rt = tokio::runtime::Runtime::new().unwrap();
+                match rt.block_on(papers.search_arxiv(&query, max)) {
+                    Ok(found) if found.is_empty() => format!("No arXiv papers found for '{}'", query),
+                    Ok(found) => found.iter().enumerate()
+                        .map(|(i, paper)| format!("[{}] {}", i + 1, paper.render()))
+                        .collect::<Vec<_>>()
+                        .join("\n\n"),
+                    Err(e) => format!("Error searching arXiv: {}", e),
+                }
+            }).join().unwrap_or_else(|_| "Thread panic".to_string())
+        }
+    };
+    let search_clone = search_arxiv.clone();
+    let default_max = papers.max_results();
+    engine.register_fn("search_arxiv", move |query: &str| -> String { search_clone(query, default_max) });
+    let search_clone = search_arxiv.clone();
+    engine.register_fn("search_arxiv", move |query: &str, max: i64| -> String { search_clone(query, max.max(1) as usize) });
+    engine.register_fn("search_arxiv", move |query: &str, max: &str| -> String {
+        search_arxiv(query, max.trim().parse().unwrap_or(default_max))
+    });
+    let get_paper = {
+        let knowledge = knowledge.clone();
+        let jail = jail.clone();
+        let safety = safety.clone();
+        let audit = audit.clone();
+        let citations = citations.clone();
+        let meter = meter.clone();
+        move |id: &str, ingest: bool| -> String {
+            let api = if arxiv_id(id).is_some() { papers.arxiv_url() } else { papers.scholar_url() };
+            if let Err(e) = egress::guard(&safety.lock().unwrap(), api, "get_paper", Some(&audit)) {
+                return format!("Error: {}", e);
+            }
+            if let Err(e) = meter("get_paper") {
+                return format!("Error: {}", e);
+            }
+            info!("Looking up paper: {}", id);
+            let (papers, knowledge, jail, id) = (papers.clone(), knowledge.clone(), jail.clone(), id.to_string());
+            let (safety, audit, citations) = (safety.clone(), audit.clone(), citations.clone());
+            std::thread::spawn(move || {
+                let rt = tokio::runtime::Runtime::new().unwrap();
+                rt.block_on(async {
+                    let paper = match papers.get_paper(&id).await {
+                        Ok(paper) => paper,
+                        Err(e) => return format!("Error getting paper {}: {}", id, e),
+                    };
+                    let mut text = paper.render();
+                    citations.record(paper.url.as_deref().unwrap_or(&paper.id), "get_paper", &paper.summary);
+                    if ingest {
+                        let status = match &paper.pdf_url {
+                            None => "No PDF to ingest".to_string(),
+                            Some(pdf) => {
+                                let allowed = egress::guard(&safety.lock().unwrap(), pdf, "get_paper", Some(&audit));
+                                match allowed {
+                                    Err(e) => format!("Error: {}", e),
+                                    Ok(()) => match knowledge.ingest(pdf, &jail).await {
+                                        Ok(chunks) => format!("Ingested {} chunks from {}", chunks, pdf),
+                                        Err(e) => format!("Error ingesting {}: {}", pdf, e),
+                                    },
+                                }
+                            }
+                        };
+                        text = format!("{}\n{}", text, status);
+                    }
+                    text
+                })
+            }).join().unwrap_or_else(|_| "Thread panic".to_string())
+        }
+    };
+    let paper_clone = get_paper.clone();
+    engine.register_fn("get_paper", move |id: &str| -> String { paper_clone(id, false) });
+    let paper_clone = get_paper.clone();
+    engine.register_fn("get_paper", move |id: &str, ingest: bool| -> String { paper_clone(id, ingest) });
+    engine.register_fn("get_paper", move |id: &str, ingest: &str| -> String {
+        get_paper(id, matches!(ingest.trim().to_lowercase().as_str(), "true" | "1" | "yes" | "ingest"))
+    });
+}
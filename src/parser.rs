@@ -0,0 +1,375 @@
+use anyhow::{anyhow, Result};
+use serde::Deserialize;
+use serde_json::Value;
+use std::str::FromStr;
+
+/// A fenced code block in a model response
+#[derive(Debug, Clone, PartialEq)]
+pub struct CodeBlock {
+    /// The first word of the fence's info string, lowercased (empty when there is none)
+    pub language: String,
+    pub code: String,
+    /// The last line of prose before the fence, which often names the file
+    pub caption: String,
+}
+
+/// A tool written in a model response, as a fenced ```rhai or ```python block
+#[derive(Debug, Clone, PartialEq)]
+pub struct ToolBlock {
+    pub name: String,
+    pub code: String,
+    pub python: bool,
+}
+
+/// What a model response asks for
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParsedAction {
+    /// Save a tool
+    CreateTool(ToolBlock),
+    /// Run a tool: `[TOOL: name(args)]`, with the arguments passed through as written
+    ToolCall { name: String, args: Vec<String> },
+    /// No tool call: the response is the answer
+    FinalAnswer(String),
+}
+
+/// A fence opening or closing a block: its character, length and info string
+fn fence(line: &str) -> Option<(char, usize, &str)> {
+    let indent = line.len() - line.trim_start_matches(' ').len();
+    if indent > 3 {
+        return None;
+    }
+    let line = &line[indent..];
+    let marker = line.chars().next().filter(|c| *c == '`' || *c == '~')?;
+    let length = line.len() - line.trim_start_matches(marker).len();
+    if length < 3 {
+        return None;
+    }
+    let info = line[length..].trim();
+    // A backtick fence's info string can't contain backticks, or it would be inline code
+    if marker == '`' && info.contains('`') {
+        return None;
+    }
+    Some((marker, length, info))
+}
+
+/// The fenced code blocks in `text`, in order. A block is closed by a fence of the same
+/// character at least as long as the one that opened it, so a ````-fenced block can hold ```
+/// lines; an unclosed block runs to the end of the text.
+pub fn code_blocks(text: &str) -> Vec<CodeBlock> {
+    let mut blocks = Vec::new();
+    let mut caption = "";
+    let mut open: Option<(char, usize, CodeBlock)> = None;
+    for line in text.lines() {
+        match open.take() {
+            Some((marker, length, mut block)) => {
+                let closes = fence(line).map(|(m, l, info)| m == marker && l >= length && info.is_empty()).unwrap_or(false);
+                if closes {
+                    blocks.push(block);
+                    caption = "";
+                } else {
+                    block.code.push_str(line);
+                    block.code.push('\n');
+                    open = Some((marker, length, block));
+                }
+            }
+            None => match fence(line) {
+                Some((marker, length, info)) => {
+                    let language = info.split_whitespace().next().unwrap_or("").to_lowercase();
+                    open = Some((marker, length, CodeBlock { language, code: String::new(), caption: caption.trim().to_string() }));
+                }
+                None if !line.trim().is_empty() => caption = line,
+                None => {}
+            },
+        }
+    }
+    if let Some((_, _, block)) = open {
+        blocks.push(block);
+    }
+    blocks
+}
+
+/// `text` with its fenced code blocks blanked out
+fn prose(text: &str) -> String {
+    let mut out = String::new();
+    let mut open: Option<(char, usize)> = None;
+    for line in text.lines() {
+        match (open, fence(line)) {
+            (Some((marker, length)), Some((m, l, info))) if m == marker && l >= length && info.is_empty() => open = None,
+            (Some(_), _) => {}
+            (None, Some((m, l, _))) => open = Some((m, l)),
+            (None, None) => out.push_str(line),
+        }
+        out.push('\n');
+    }
+    out
+}
+
+/// A tool name as written in a filename, path or caption: the file stem, if it is an identifier
+fn tool_name(word: &str) -> Option<String> {
+    let word = word.trim().trim_matches(|c: char| "`*'\"".contains(c));
+    let file = word.rsplit(['/', '\\']).next().unwrap_or(word);
+    let stem = file.strip_suffix(".rhai").or_else(|| file.strip_suffix(".py")).unwrap_or(file);
+    let valid = stem.chars().next().map(|c| c.is_ascii_alphabetic() || c == '_').unwrap_or(false)
+        && stem.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    valid.then(|| stem.to_string())
+}
+
+/// The name a tool block is saved under: a `filename:` comment, a `name.rhai`/`name.py` file
+/// named in the caption, or else the first function the code defines
+fn block_name(block: &CodeBlock, python: bool) -> Option<String> {
+    let comment = if python { "#" } else { "//" };
+    let marked = block.code.lines().find_map(|line| {
+        let rest = line.trim().strip_prefix(comment)?.trim();
+        let (key, value) = rest.split_once(':')?;
+        matches!(key.trim().to_lowercase().as_str(), "filename" | "file" | "tool").then(|| value.to_string())
+    });
+    if let Some(name) = marked.and_then(|value| tool_name(&value)) {
+        return Some(name);
+    }
+    let extension = if python { ".py" } else { ".rhai" };
+    let captioned = block.caption
+        .split(|c: char| c.is_whitespace() || c == ':' || c == '(' || c == ')')
+        .find(|word| word.trim_matches(|c: char| "`*'\"".contains(c)).ends_with(extension));
+    if let Some(name) = captioned.and_then(tool_name) {
+        return Some(name);
+    }
+    let keyword = if python { "def " } else { "fn " };
+    block.code.lines()
+        .filter_map(|line| line.strip_prefix(keyword))
+        .find_map(|rest| tool_name(rest.split('(').next()?))
+}
+
+/// The tools `text` writes: every ```rhai and ```python block that can be named (see
+/// `block_name`). Blocks in other languages, and unnamed snippets, are left alone.
+pub fn tool_blocks(text: &str) -> Vec<ToolBlock> {
+    code_blocks(text)
+        .into_iter()
+        .filter_map(|block| {
+            let python = match block.language.as_str() {
+                "rhai" => false,
+                "python" | "py" | "python3" => true,
+                _ => return None,
+            };
+            let name = block_name(&block, python)?;
+            Some(ToolBlock { name, code: block.code, python })
+        })
+        .collect()
+}
+
+/// Where the parenthesis opened at the start of `text` closes, skipping nested parentheses
+/// and double-quoted strings (single quotes are left alone, being apostrophes as often)
+fn closing_paren(text: &str) -> Option<usize> {
+    let mut depth = 0;
+    let mut quoted = false;
+    let mut escaped = false;
+    for (i, c) in text.char_indices() {
+        if quoted {
+            if escaped {
+                escaped = false;
+            } else if c == '\\' {
+                escaped = true;
+            } else if c == '"' {
+                quoted = false;
+            }
+            continue;
+        }
+        match c {
+            '"' => quoted = true,
+            '(' => depth += 1,
+            ')' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// The `[TOOL: name(args)]` calls in `text`, in order, outside code blocks. The arguments are
+/// passed through as one string, as written; they may contain brackets and parentheses.
+pub fn tool_calls(text: &str) -> Vec<(String, Vec<String>)> {
+    let text = prose(text);
+    let mut calls = Vec::new();
+    let mut rest = text.as_str();
+    while let Some(start) = rest.find("[TOOL:") {
+        rest = &rest[start + "[TOOL:".len()..];
+        let Some(paren) = rest.find('(') else { continue };
+        let name = rest[..paren].trim();
+        if name.is_empty() || name.contains(char::is_whitespace) || name.contains(']') {
+            continue;
+        }
+        let Some(close) = closing_paren(&rest[paren..]).map(|i| i + paren) else { continue };
+        let after = rest[close + 1..].trim_start();
+        if !after.starts_with(']') {
+            continue;
+        }
+        calls.push((name.to_string(), vec![rest[paren + 1..close].to_string()]));
+        rest = &after[1..];
+    }
+    calls
+}
+
+/// The first `[TOOL: name(args)]` call in `text`, as the tool name and its arguments
+pub fn parse_tool_call(text: &str) -> Option<(String, Vec<String>)> {
+    tool_calls(text).into_iter().next()
+}
+
+/// Everything `text` asks for: the tools it writes, then the tools it calls, or, when it
+/// calls none, the response itself as the final answer
+pub fn parse_response(text: &str) -> Vec<ParsedAction> {
+    let mut actions: Vec<ParsedAction> = tool_blocks(text).into_iter().map(ParsedAction::CreateTool).collect();
+    let calls = tool_calls(text);
+    if calls.is_empty() {
+        actions.push(ParsedAction::FinalAnswer(text.trim().to_string()));
+    }
+    actions.extend(calls.into_iter().map(|(name, args)| ParsedAction::ToolCall { name, args }));
+    actions
+}
+
+/// How the model is asked to write its actions
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ActionProtocol {
+    /// Code blocks and `[TOOL: ...]` markers in free text
+    #[default]
+    Markers,
+    /// A JSON action object per reply, as `JSON_PROTOCOL_PROMPT` asks
+    Json,
+}
+
+impl FromStr for ActionProtocol {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "markers" => Ok(Self::Markers),
+            "json" => Ok(Self::Json),
+            other => Err(anyhow!("expected markers or json, not '{}'", other)),
+        }
+    }
+}
+
+/// Appended to the system prompt under `ActionProtocol::Json`
+pub const JSON_PROTOCOL_PROMPT: &str = r#"Reply with JSON only, instead of the [TOOL: ...] markers and code blocks described above. Each reply is one action object, or an array of them run in order:
+{"action": "call_tool", "name": "square", "args": ["7"]}
+{"action": "create_tool", "name": "my_tool", "language": "rhai", "code": "fn my_tool(x) { x }"}
+{"action": "final", "answer": "The answer for the user"}
+"language" is "rhai" (the default) or "python". Tool results come back as "Tool Output: ..." messages. A final answer can't be combined with tool calls."#;
+
+#[derive(Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case", deny_unknown_fields)]
+enum JsonAction {
+    CreateTool {
+        name: String,
+        code: String,
+        #[serde(default)]
+        language: Option<String>,
+    },
+    CallTool {
+        name: String,
+        #[serde(default)]
+        args: Value,
+    },
+    Final {
+        answer: String,
+    },
+}
+
+/// The JSON in a reply: the reply itself, a ```json block, or the outermost object or array
+/// in the surrounding prose
+fn json_text(text: &str) -> &str {
+    let text = text.trim();
+    if text.starts_with('{') || text.starts_with('[') {
+        return text;
+    }
+    let start = text.find(['{', '[']);
+    let end = text.rfind(['}', ']']);
+    match (start, end) {
+        (Some(start), Some(end)) if start < end => &text[start..=end],
+        _ => text,
+    }
+}
+
+/// Tool arguments as the single string a `[TOOL: ...]` call passes: array items joined with
+/// ", ", strings as they are and anything else as JSON
+fn call_args(args: Value) -> String {
+    let item = |value: Value| match value {
+        Value::String(s) => s,
+        other => other.to_string(),
+    };
+    match args {
+        Value::Null => String::new(),
+        Value::Array(items) => items.into_iter().map(item).collect::<Vec<_>>().join(", "),
+        other => item(other),
+    }
+}
+
+/// The actions in a reply written under `ActionProtocol::Json`, or what is wrong with it, in
+/// words the model can act on
+pub fn parse_json_actions(text: &str) -> Result<Vec<ParsedAction>> {
+    let value: Value = serde_json::from_str(json_text(text)).map_err(|e| anyhow!("not valid JSON ({})", e))?;
+    let objects = match value {
+        Value::Array(items) if items.is_empty() => return Err(anyhow!("the array has no actions")),
+        Value::Array(items) => items,
+        object @ Value::Object(_) => vec![object],
+        _ => return Err(anyhow!("expected an action object or an array of them")),
+    };
+    let numbered = objects.len() > 1;
+    let mut actions = Vec::new();
+    for (i, object) in objects.into_iter().enumerate() {
+        let at = if numbered { format!("action {}: ", i + 1) } else { String::new() };
+        let action: JsonAction = serde_json::from_value(object).map_err(|e| anyhow!("{}{}", at, e))?;
+        actions.push(match action {
+            JsonAction::CreateTool { name, code, language } => {
+                let python = match language.as_deref().map(str::to_lowercase).as_deref() {
+                    None | Some("rhai") => false,
+                    Some("python") | Some("py") => true,
+                    Some(other) => return Err(anyhow!("{}language must be rhai or python, not '{}'", at, other)),
+                };
+                if tool_name(&name).as_deref() != Some(name.as_str()) {
+                    return Err(anyhow!("{}'{}' is not a valid tool name (letters, digits and _)", at, name));
+                }
+                if code.trim().is_empty() {
+                    return Err(anyhow!("{}create_tool needs code", at));
+                }
+                ParsedAction::CreateTool(ToolBlock { name, code, python })
+            }
+            JsonAction::CallTool { name, args } => {
+                if name.trim().is_empty() || name.contains(char::is_whitespace) {
+                    return Err(anyhow!("{}'{}' is not a tool name", at, name));
+                }
+                ParsedAction::ToolCall { name, args: vec![call_args(args)] }
+            }
+            JsonAction::Final { answer } => ParsedAction::FinalAnswer(answer),
+        });
+    }
+    let calls = actions.iter().any(|a| matches!(a, ParsedAction::ToolCall { .. }));
+    if calls && actions.iter().any(|a| matches!(a, ParsedAction::FinalAnswer(_))) {
+        return Err(anyhow!("a final answer can't be combined with tool calls"));
+    }
+    Ok(actions)
+}
+
+impl ParsedAction {
+    /// The action as `ActionProtocol::Markers` writes it, which `parse_response` reads back
+    pub fn to_markers(&self) -> String {
+        match self {
+            Self::CreateTool(block) => {
+                // A fence longer than any backtick run in the code, so the code can't close it
+                let longest = block.code.split(|c| c != '`').map(str::len).max().unwrap_or(0);
+                let fence = "`".repeat(longest.max(2) + 1);
+                let (language, comment) = if block.python { ("python", "#") } else { ("rhai", "//") };
+                format!("{fence}{language}\n{comment} filename: {}\n{}\n{fence}", block.name, block.code.trim_end())
+            }
+            Self::ToolCall { name, args } => format!("[TOOL: {}({})]", name, args.join(", ")),
+            Self::FinalAnswer(answer) => answer.clone(),
+        }
+    }
+}
+
+/// `actions` written out as markers, one after another
+pub fn render_markers(actions: &[ParsedAction]) -> String {
+    actions.iter().map(ParsedAction::to_markers).collect::<Vec<_>>().join("\n")
+}
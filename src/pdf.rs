@@ -0,0 +1,890 @@
+use anyhow::{anyhow, Result};
+use std::collections::{HashMap, HashSet};
+use crate::inflate::inflate_into;
+
+/// How deeply arrays and dictionaries may nest before the lexer stops descending
+const MAX_DEPTH: usize = 64;
+
+/// A PDF object, as far as text extraction needs one
+#[derive(Debug, Clone, PartialEq)]
+enum Obj {
+    Null,
+    Bool(bool),
+    Num(f64),
+    Str(Vec<u8>),
+    Name(String),
+    Array(Vec<Obj>),
+    Dict(HashMap<String, Obj>),
+    Ref(u32),
+    Stream(HashMap<String, Obj>, Vec<u8>),
+    /// A bare keyword: an operator in a content stream
+    Op(String),
+}
+
+/// A page dictionary and the resources it uses, its own or inherited
+type Page<'o> = (&'o HashMap<String, Obj>, Option<&'o HashMap<String, Obj>>);
+
+impl Obj {
+    fn dict(&self) -> Option<&HashMap<String, Obj>> {
+        match self {
+            Obj::Dict(dict) | Obj::Stream(dict, _) => Some(dict),
+            _ => None,
+        }
+    }
+
+    fn name(&self) -> Option<&str> {
+        match self {
+            Obj::Name(name) => Some(name),
+            _ => None,
+        }
+    }
+
+    fn num(&self) -> Option<f64> {
+        match self {
+            Obj::Num(n) => Some(*n),
+            _ => None,
+        }
+    }
+}
+
+fn is_space(b: u8) -> bool {
+    matches!(b, b'\0' | b'\t' | b'\n' | b'\x0c' | b'\r' | b' ')
+}
+
+fn is_delimiter(b: u8) -> bool {
+    matches!(b, b'(' | b')' | b'<' | b'>' | b'[' | b']' | b'{' | b'}' | b'/' | b'%')
+}
+
+fn is_regular(b: u8) -> bool {
+    !is_space(b) && !is_delimiter(b)
+}
+
+/// Reads objects from PDF syntax: file bodies, object streams, content streams and CMaps
+struct Lexer<'a> {
+    data: &'a [u8],
+    pos: usize,
+    /// How many arrays and dictionaries enclose the current position
+    depth: usize,
+}
+
+impl<'a> Lexer<'a> {
+    fn new(data: &'a [u8], pos: usize) -> Self {
+        Self { data, pos, depth: 0 }
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.data.get(self.pos).copied()
+    }
+
+    fn skip_space(&mut self) {
+        while let Some(b) = self.peek() {
+            if is_space(b) {
+                self.pos += 1;
+            } else if b == b'%' {
+                while self.peek().is_some_and(|b| b != b'\n' && b != b'\r') {
+                    self.pos += 1;
+                }
+            } else {
+                break;
+            }
+        }
+    }
+
+    fn starts_with(&self, text: &[u8]) -> bool {
+        self.data[self.pos.min(self.data.len())..].starts_with(text)
+    }
+
+    fn word(&mut self) -> &'a [u8] {
+        let start = self.pos;
+        while self.peek().is_some_and(is_regular) {
+            self.pos += 1;
+        }
+        &self.data[start..self.pos]
+    }
+
+    /// An unsigned integer at the current position, or None with the position unchanged
+    fn integer(&mut self) -> Option<u32> {
+        let start = self.pos;
+        while self.peek().is_some_and(|b| b.is_ascii_digit()) {
+            self.pos += 1;
+        }
+        let value = std::str::from_utf8(&self.data[start..self.pos]).ok()?.parse().ok();
+        if value.is_none() || self.peek().is_some_and(is_regular) {
+            self.pos = start;
+            return None;
+        }
+        value
+    }
+
+    /// The next object, None at the end of the data
+    fn next(&mut self) -> Option<Obj> {
+        self.skip_space();
+        let b = self.peek()?;
+        Some(match b {
+            b'/' => {
+                self.pos += 1;
+                Obj::Name(decode_name(self.word()))
+            }
+            b'(' => Obj::Str(self.literal_string()),
+            b'[' | b'<' if self.depth >= MAX_DEPTH && (b == b'[' || self.starts_with(b"<<")) => {
+                // Nested past any real document: flatten rather than recurse further
+                self.pos += if b == b'[' { 1 } else { 2 };
+                Obj::Null
+            }
+            b'<' if self.starts_with(b"<<") => {
+                self.pos += 2;
+                self.depth += 1;
+                let mut dict = HashMap::new();
+                loop {
+                    self.skip_space();
+                    if self.starts_with(b">>") || self.peek().is_none() {
+                        self.pos += 2;
+                        break;
+                    }
+                    match self.next() {
+                        Some(Obj::Name(key)) => {
+                            let value = self.next().unwrap_or(Obj::Null);
+                            dict.insert(key, value);
+                        }
+                        Some(_) => {}
+                        None => break,
+                    }
+                }
+                self.depth -= 1;
+                Obj::Dict(dict)
+            }
+            b'<' => Obj::Str(self.hex_string()),
+            b'[' => {
+                self.pos += 1;
+                self.depth += 1;
+                let mut items = Vec::new();
+                loop {
+                    self.skip_space();
+                    match self.peek() {
+                        Some(b']') => {
+                            self.pos += 1;
+                            break;
+                        }
+                        None => break,
+                        _ => items.extend(self.next()),
+                    }
+                }
+                self.depth -= 1;
+                Obj::Array(items)
+            }
+            b'+' | b'-' | b'.' | b'0'..=b'9' => self.number(),
+            _ if is_delimiter(b) => {
+                // Stray `)`, `>`, `]` or PostScript braces: step over them
+                self.pos += 1;
+                Obj::Op((b as char).to_string())
+            }
+            _ => match self.word() {
+                b"true" => Obj::Bool(true),
+                b"false" => Obj::Bool(false),
+                b"null" => Obj::Null,
+                word => Obj::Op(String::from_utf8_lossy(word).into_owned()),
+            },
+        })
+    }
+
+    /// A number, or an indirect reference `num gen R`
+    fn number(&mut self) -> Obj {
+        let text = self.word();
+        let value = std::str::from_utf8(text).ok().and_then(|t| t.parse::<f64>().ok()).unwrap_or(0.0);
+        if text.iter().all(u8::is_ascii_digit) {
+            let after = self.pos;
+            self.skip_space();
+            if self.integer().is_some() {
+                self.skip_space();
+                if self.peek() == Some(b'R') && self.data.get(self.pos + 1).is_none_or(|b| !is_regular(*b)) {
+                    self.pos += 1;
+                    return Obj::Ref(value as u32);
+                }
+            }
+            self.pos = after;
+        }
+        Obj::Num(value)
+    }
+
+    fn literal_string(&mut self) -> Vec<u8> {
+        self.pos += 1;
+        let mut out = Vec::new();
+        let mut depth = 1;
+        while let Some(b) = self.peek() {
+            self.pos += 1;
+            match b {
+                b'(' => {
+                    depth += 1;
+                    out.push(b);
+                }
+                b')' => {
+                    depth -= 1;
+                    if depth == 0 {
+                        break;
+                    }
+                    out.push(b);
+                }
+                b'\\' => {
+                    let Some(escaped) = self.peek() else { break };
+                    self.pos += 1;
+                    match escaped {
+                        b'n' => out.push(b'\n'),
+                        b'r' => out.push(b'\r'),
+                        b't' => out.push(b'\t'),
+                        b'b' => out.push(8),
+                        b'f' => out.push(12),
+                        b'0'..=b'7' => {
+                            let mut value = (escaped - b'0') as u32;
+                            for _ in 0..2 {
+                                match self.peek() {
+                                    Some(d @ b'0'..=b'7') => {
+                                        value = value * 8 + (d - b'0') as u32;
+                                        self.pos += 1;
+                                    }
+                                    _ => break,
+                                }
+                            }
+                            out.push(value as u8);
+                        }
+                        // A backslash at the end of a line continues the string
+                        b'\r' => {
+                            if self.peek() == Some(b'\n') {
+                                self.pos += 1;
+                            }
+                        }
+                        b'\n' => {}
+                        other => out.push(other),
+                    }
+                }
+                _ => out.push(b),
+            }
+        }
+        out
+    }
+
+    fn hex_string(&mut self) -> Vec<u8> {
+        self.pos += 1;
+        let mut digits = Vec::new();
+        while let Some(b) = self.peek() {
+            self.pos += 1;
+            if b == b'>' {
+                break;
+            }
+            if let Some(d) = (b as char).to_digit(16) {
+                digits.push(d as u8);
+            }
+        }
+        if digits.len() % 2 == 1 {
+            digits.push(0);
+        }
+        digits.chunks(2).map(|pair| pair[0] << 4 | pair[1]).collect()
+    }
+}
+
+/// A name with its `#xx` escapes decoded
+fn decode_name(raw: &[u8]) -> String {
+    let mut out = Vec::with_capacity(raw.len());
+    let mut i = 0;
+    while i < raw.len() {
+        if raw[i] == b'#' {
+            if let Some(byte) = raw.get(i + 1..i + 3).and_then(|h| std::str::from_utf8(h).ok()).and_then(|h| u8::from_str_radix(h, 16).ok()) {
+                out.push(byte);
+                i += 3;
+                continue;
+            }
+        }
+        out.push(raw[i]);
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+fn find(data: &[u8], needle: &[u8], from: usize) -> Option<usize> {
+    data.get(from..)?.windows(needle.len()).position(|w| w == needle).map(|i| i + from)
+}
+
+/// The objects of a PDF file, by number. Found by scanning for `n g obj` rather than through
+/// the cross-reference table, which also copes with files whose offsets are off.
+struct Document {
+    objects: HashMap<u32, Obj>,
+}
+
+impl Document {
+    fn parse(data: &[u8]) -> Result<Self> {
+        if !data.starts_with(b"%PDF-") && find(&data[..data.len().min(1024)], b"%PDF-", 0).is_none() {
+            return Err(anyhow!("not a PDF file"));
+        }
+        if find(data, b"/Encrypt", 0).is_some() {
+            return Err(anyhow!("encrypted PDFs are not supported"));
+        }
+        let mut objects = HashMap::new();
+        let mut at = 0;
+        while let Some(i) = find(data, b"obj", at) {
+            at = i + 3;
+            if i == 0 || !is_space(data[i - 1]) || data.get(i + 3).is_some_and(|b| is_regular(*b)) {
+                continue;
+            }
+            let Some(number) = object_number(data, i) else { continue };
+            let mut lexer = Lexer::new(data, i + 3);
+            let Some(object) = lexer.next() else { continue };
+            lexer.skip_space();
+            let object = match object {
+                Obj::Dict(dict) if lexer.starts_with(b"stream") => {
+                    let bytes = stream_bytes(data, lexer.pos + 6, &dict);
+                    at = at.max(lexer.pos + bytes.len());
+                    Obj::Stream(dict, bytes)
+                }
+                other => other,
+            };
+            // Later definitions are incremental updates and replace earlier ones
+            objects.insert(number, object);
+        }
+        let mut document = Self { objects };
+        document.unpack_object_streams();
+        Ok(document)
+    }
+
+    /// Add the objects packed in `/Type /ObjStm` streams (PDF 1.5+), unless defined directly
+    fn unpack_object_streams(&mut self) {
+        let streams: Vec<Obj> = self.objects.values()
+            .filter(|o| o.dict().and_then(|d| d.get("Type")).and_then(Obj::name) == Some("ObjStm"))
+            .cloned()
+            .collect();
+        for stream in streams {
+            let Obj::Stream(dict, _) = &stream else { continue };
+            let Some(data) = self.decode(&stream) else { continue };
+            let count = dict.get("N").and_then(Obj::num).unwrap_or(0.0) as usize;
+            let first = dict.get("First").and_then(Obj::num).unwrap_or(0.0) as usize;
+            let mut header = Lexer::new(&data, 0);
+            for _ in 0..count {
+                let (Some(Obj::Num(number)), Some(Obj::Num(offset))) = (header.next(), header.next()) else { break };
+                if let Some(object) = Lexer::new(&data, first + offset as usize).next() {
+                    self.objects.entry(number as u32).or_insert(object);
+                }
+            }
+        }
+    }
+
+    /// Follow references to the object they point at
+    fn resolve<'o>(&'o self, mut obj: &'o Obj) -> &'o Obj {
+        for _ in 0..16 {
+            match obj {
+                Obj::Ref(number) => obj = self.objects.get(number).unwrap_or(&Obj::Null),
+                _ => break,
+            }
+        }
+        obj
+    }
+
+    fn get<'o>(&'o self, dict: &'o HashMap<String, Obj>, key: &str) -> Option<&'o Obj> {
+        dict.get(key).map(|o| self.resolve(o))
+    }
+
+    /// A stream's data with its filters undone; None for filters other than `FlateDecode`
+    fn decode(&self, stream: &Obj) -> Option<Vec<u8>> {
+        let Obj::Stream(dict, data) = stream else { return None };
+        let filters: Vec<String> = match self.get(dict, "Filter") {
+            Some(Obj::Name(name)) => vec![name.clone()],
+            Some(Obj::Array(names)) => names.iter().filter_map(|n| self.resolve(n).name().map(String::from)).collect(),
+            _ => Vec::new(),
+        };
+        let mut data = data.clone();
+        for filter in filters {
+            match filter.as_str() {
+                "FlateDecode" | "Fl" => {
+                    // Keep what decodes of a damaged stream
+                    let mut out = Vec::new();
+                    let _ = inflate_into(data.get(2..)?, &mut out);
+                    data = out;
+                }
+                _ => return None,
+            }
+        }
+        Some(data)
+    }
+
+    /// The pages in reading order, each with the resources it uses
+    fn pages(&self) -> Vec<Page<'_>> {
+        let catalog = self.objects.values().find(|o| o.dict().and_then(|d| d.get("Type")).and_then(Obj::name) == Some("Catalog"));
+        let mut pages = Vec::new();
+        if let Some(root) = catalog.and_then(Obj::dict).and_then(|d| self.get(d, "Pages")) {
+            self.collect_pages(root, None, &mut pages, &mut HashSet::new());
+        }
+        if pages.is_empty() {
+            // No usable page tree: take the page objects in number order
+            let mut numbers: Vec<&u32> = self.objects.keys().collect();
+            numbers.sort();
+            for number in numbers {
+                if let Some(dict) = self.objects[number].dict().filter(|d| d.get("Type").and_then(Obj::name) == Some("Page")) {
+                    pages.push((dict, self.get(dict, "Resources").and_then(Obj::dict)));
+                }
+            }
+        }
+        pages
+    }
+
+    fn collect_pages<'o>(&'o self, node: &'o Obj, inherited: Option<&'o HashMap<String, Obj>>,
+                         pages: &mut Vec<Page<'o>>, seen: &mut HashSet<*const Obj>) {
+        let Some(dict) = node.dict() else { return };
+        if !seen.insert(node as *const Obj) {
+            return;
+        }
+        let resources = self.get(dict, "Resources").and_then(Obj::dict).or(inherited);
+        match self.get(dict, "Kids") {
+            Some(Obj::Array(kids)) => {
+                for kid in kids {
+                    self.collect_pages(self.resolve(kid), resources, pages, seen);
+                }
+            }
+            _ => pages.push((dict, resources)),
+        }
+    }
+
+    /// The fonts a page's resources name, by resource name
+    fn fonts(&self, resources: Option<&HashMap<String, Obj>>) -> HashMap<String, Font> {
+        let Some(fonts) = resources.and_then(|r| self.get(r, "Font")).and_then(Obj::dict) else { return HashMap::new() };
+        fonts.iter()
+            .filter_map(|(name, font)| Some((name.clone(), self.font(self.resolve(font).dict()?))))
+            .collect()
+    }
+
+    fn font(&self, dict: &HashMap<String, Obj>) -> Font {
+        let two_byte = self.get(dict, "Subtype").and_then(Obj::name) == Some("Type0");
+        let cmap = self.get(dict, "ToUnicode").and_then(|s| self.decode(s)).map(|data| CMap::parse(&data, if two_byte { 2 } else { 1 }));
+        Font { two_byte, cmap }
+    }
+
+    /// A page's content streams, joined
+    fn contents(&self, page: &HashMap<String, Obj>) -> Vec<u8> {
+        let streams: Vec<&Obj> = match self.get(page, "Contents") {
+            Some(Obj::Array(parts)) => parts.iter().map(|p| self.resolve(p)).collect(),
+            Some(stream) => vec![stream],
+            None => Vec::new(),
+        };
+        let mut data = Vec::new();
+        for stream in streams {
+            if let Some(decoded) = self.decode(stream) {
+                data.extend_from_slice(&decoded);
+                data.push(b'\n');
+            }
+        }
+        data
+    }
+}
+
+/// The number of the object whose `obj` keyword is at `at`
+fn object_number(data: &[u8], at: usize) -> Option<u32> {
+    let mut i = at;
+    let mut numbers = Vec::new();
+    for _ in 0..2 {
+        while i > 0 && is_space(data[i - 1]) {
+            i -= 1;
+        }
+        let end = i;
+        while i > 0 && data[i - 1].is_ascii_digit() {
+            i -= 1;
+        }
+        numbers.push(std::str::from_utf8(&data[i..end]).ok()?.parse::<u32>().ok()?);
+    }
+    Some(numbers[1])
+}
+
+/// The bytes of a stream whose data starts after the `stream` keyword at `start`
+fn stream_bytes(data: &[u8], start: usize, dict: &HashMap<String, Obj>) -> Vec<u8> {
+    let start = match data.get(start..) {
+        Some([b'\r', b'\n', ..]) => start + 2,
+        Some([b'\n' | b'\r', ..]) => start + 1,
+        _ => start,
+    };
+    // Trust /Length when `endstream` follows it, else look for `endstream`
+    if let Some(length) = dict.get("Length").and_then(Obj::num).map(|n| n as usize) {
+        let mut lexer = Lexer::new(data, start + length);
+        lexer.skip_space();
+        if start + length <= data.len() && lexer.starts_with(b"endstream") {
+            return data[start..start + length].to_vec();
+        }
+    }
+    let Some(mut end) = find(data, b"endstream", start) else { return data[start.min(data.len())..].to_vec() };
+    if end > start && data[end - 1] == b'\n' {
+        end -= 1;
+    }
+    if end > start && data[end - 1] == b'\r' {
+        end -= 1;
+    }
+    data[start..end].to_vec()
+}
+
+/// A ToUnicode CMap: character codes of a font to the text they stand for
+#[derive(Debug, Default)]
+struct CMap {
+    code_length: usize,
+    map: HashMap<u32, String>,
+}
+
+fn utf16(bytes: &[u8]) -> String {
+    let units: Vec<u16> = bytes.chunks(2).map(|c| (c[0] as u16) << 8 | *c.get(1).unwrap_or(&0) as u16).collect();
+    String::from_utf16_lossy(&units)
+}
+
+fn code_of(bytes: &[u8]) -> u32 {
+    bytes.iter().fold(0u32, |code, b| code << 8 | *b as u32)
+}
+
+impl CMap {
+    fn parse(data: &[u8], default_length: usize) -> Self {
+        let mut cmap = CMap { code_length: default_length, map: HashMap::new() };
+        let mut lexer = Lexer::new(data, 0);
+        let mut operands = Vec::new();
+        let mut section = String::new();
+        while let Some(obj) = lexer.next() {
+            match obj {
+                Obj::Op(op) if op.starts_with("begin") => {
+                    section = op;
+                    operands.clear();
+                }
+                Obj::Op(op) if op.starts_with("end") => {
+                    cmap.apply(&section, &operands);
+                    section.clear();
+                    operands.clear();
+                }
+                Obj::Op(_) => {}
+                other => operands.push(other),
+            }
+        }
+        cmap
+    }
+
+    fn apply(&mut self, section: &str, operands: &[Obj]) {
+        match section {
+            "begincodespacerange" => {
+                if let Some(Obj::Str(low)) = operands.first() {
+                    self.code_length = low.len().max(1);
+                }
+            }
+            "beginbfchar" => {
+                for pair in operands.chunks(2) {
+                    if let [Obj::Str(code), Obj::Str(text)] = pair {
+                        self.map.insert(code_of(code), utf16(text));
+                    }
+                }
+            }
+            "beginbfrange" => {
+                for range in operands.chunks(3) {
+                    let [Obj::Str(low), Obj::Str(high), target] = range else { continue };
+                    let (low, high) = (code_of(low), code_of(high));
+                    for (offset, code) in (low..=high.min(low + 0xffff)).enumerate() {
+                        let text = match target {
+                            // Each code gets the next value of the last UTF-16 unit
+                            Obj::Str(first) if first.len() >= 2 => {
+                                let mut units = first.clone();
+                                let n = units.len();
+                                let last = ((units[n - 2] as u32) << 8 | units[n - 1] as u32) + offset as u32;
+                                units[n - 2] = (last >> 8) as u8;
+                                units[n - 1] = last as u8;
+                                utf16(&units)
+                            }
+                            Obj::Array(texts) => match texts.get(offset) {
+                                Some(Obj::Str(text)) => utf16(text),
+                                _ => continue,
+                            },
+                            _ => continue,
+                        };
+                        self.map.insert(code, text);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// How to turn a font's string bytes into text
+#[derive(Debug, Default)]
+struct Font {
+    /// A composite (Type0) font, whose codes mean nothing without a ToUnicode map
+    two_byte: bool,
+    cmap: Option<CMap>,
+}
+
+impl Font {
+    fn decode(&self, bytes: &[u8]) -> String {
+        if let Some(cmap) = &self.cmap {
+            return bytes.chunks(cmap.code_length)
+                .map(|code| match cmap.map.get(&code_of(code)) {
+                    Some(text) => text.clone(),
+                    None if cmap.code_length == 1 => single_byte(code[0]),
+                    None => String::new(),
+                })
+                .collect();
+        }
+        if self.two_byte {
+            return String::new();
+        }
+        bytes.iter().map(|b| single_byte(*b)).collect()
+    }
+}
+
+/// A byte of a simple font without a ToUnicode map, read as WinAnsi, with TeX's ligatures
+fn single_byte(b: u8) -> String {
+    match b {
+        0x0b => "ff".to_string(),
+        0x0c => "fi".to_string(),
+        0x0d => "fl".to_string(),
+        0x0e => "ffi".to_string(),
+        0x0f => "ffl".to_string(),
+        b'\t' | b'\n' => " ".to_string(),
+        0x00..=0x1f => String::new(),
+        0x91 => "\u{2018}".to_string(),
+        0x92 => "\u{2019}".to_string(),
+        0x93 => "\u{201c}".to_string(),
+        0x94 => "\u{201d}".to_string(),
+        0x95 => "\u{2022}".to_string(),
+        0x96 => "\u{2013}".to_string(),
+        0x97 => "\u{2014}".to_string(),
+        _ => (b as char).to_string(),
+    }
+}
+
+/// Text as it comes off a page, with a space or line break where the text moves
+#[derive(Default)]
+struct PageText {
+    text: String,
+}
+
+impl PageText {
+    fn show(&mut self, text: &str) {
+        self.text.push_str(text);
+    }
+
+    fn space(&mut self) {
+        if !self.text.is_empty() && !self.text.ends_with([' ', '\n']) {
+            self.text.push(' ');
+        }
+    }
+
+    fn newline(&mut self) {
+        self.text.truncate(self.text.trim_end_matches(' ').len());
+        if !self.text.is_empty() && !self.text.ends_with('\n') {
+            self.text.push('\n');
+        }
+    }
+}
+
+/// The text of a content stream, read with `fonts`
+fn content_text(content: &[u8], fonts: &HashMap<String, Font>) -> String {
+    let mut lexer = Lexer::new(content, 0);
+    let mut operands: Vec<Obj> = Vec::new();
+    let mut page = PageText::default();
+    let plain = Font::default();
+    let mut font = &plain;
+    let mut line_y: Option<f64> = None;
+    while let Some(obj) = lexer.next() {
+        let Obj::Op(op) = obj else {
+            operands.push(obj);
+            continue;
+        };
+        match op.as_str() {
+            "Tf" => {
+                if let Some(Obj::Name(name)) = operands.first() {
+                    font = fonts.get(name).unwrap_or(&plain);
+                }
+            }
+            "Tj" => {
+                if let Some(Obj::Str(bytes)) = operands.last() {
+                    page.show(&font.decode(bytes));
+                }
+            }
+            "'" | "\"" => {
+                page.newline();
+                if let Some(Obj::Str(bytes)) = operands.last() {
+                    page.show(&font.decode(bytes));
+                }
+            }
+            "TJ" => {
+                if let Some(Obj::Array(parts)) = operands.last() {
+                    for part in parts {
+                        match part {
+                            Obj::Str(bytes) => page.show(&font.decode(bytes)),
+                            // A wide enough gap is a word break
+                            Obj::Num(n) if *n < -180.0 => page.space(),
+                            _ => {}
+                        }
+                    }
+                }
+            }
+            "Td" | "TD" => match operands.as_slice() {
+                [_, Obj::Num(ty)] if ty.abs() > 0.01 => page.newline(),
+                _ => page.space(),
+            },
+            "T*" => page.newline(),
+            "Tm" => {
+                let y = operands.get(5).and_then(Obj::num);
+                if line_y.zip(y).is_some_and(|(a, b)| (a - b).abs() > 0.5) {
+                    page.newline();
+                } else {
+                    page.space();
+                }
+                line_y = y.or(line_y);
+            }
+            "ET" => page.space(),
+            // Inline image data is binary: skip to its end
+            "ID" => {
+                lexer.pos = find(content, b"EI", lexer.pos).map(|i| i + 2).unwrap_or(content.len());
+            }
+            _ => {}
+        }
+        operands.clear();
+    }
+    page.text.lines().map(str::trim).filter(|l| !l.is_empty()).collect::<Vec<_>>().join("\n")
+}
+
+/// The text of each page of the PDF `data`, in reading order
+pub fn pdf_pages(data: &[u8]) -> Result<Vec<String>> {
+    let document = Document::parse(data)?;
+    let pages = document.pages();
+    if pages.is_empty() {
+        return Err(anyhow!("no pages found"));
+    }
+    Ok(pages.into_iter()
+        .map(|(page, resources)| content_text(&document.contents(page), &document.fonts(resources)))
+        .collect())
+}
+
+/// A line of a PDF made by `write_pdf`
+#[derive(Debug, Clone, PartialEq)]
+pub enum PdfLine {
+    Title(String),
+    Heading(String),
+    Text(String),
+    Blank,
+}
+
+// US Letter, with one-inch margins
+const PAGE_WIDTH: f64 = 612.0;
+const PAGE_HEIGHT: f64 = 792.0;
+const MARGIN: f64 = 72.0;
+
+/// `c` as a WinAnsi byte, `?` when it has none
+fn win_ansi(c: char) -> u8 {
+    match c {
+        ' '..='~' | '\u{a0}'..='\u{ff}' => c as u8,
+        '\u{2018}' => 0x91,
+        '\u{2019}' => 0x92,
+        '\u{201c}' => 0x93,
+        '\u{201d}' => 0x94,
+        '\u{2022}' => 0x95,
+        '\u{2013}' => 0x96,
+        '\u{2014}' => 0x97,
+        '\t' => b' ',
+        _ => b'?',
+    }
+}
+
+/// `text` broken into lines of at most `width` characters, at spaces where it can be
+fn wrap(text: &str, width: usize) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut line = String::new();
+    for word in text.split_whitespace() {
+        let mut word = word.to_string();
+        // Words longer than a line (URLs, mostly) are split wherever they must be
+        while word.chars().count() > width {
+            if !line.is_empty() {
+                lines.push(std::mem::take(&mut line));
+            }
+            let at = word.char_indices().nth(width).map(|(i, _)| i).unwrap_or(word.len());
+            lines.push(word[..at].to_string());
+            word = word[at..].to_string();
+        }
+        if !line.is_empty() && line.chars().count() + 1 + word.chars().count() > width {
+            lines.push(std::mem::take(&mut line));
+        }
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        line.push_str(&word);
+    }
+    if !line.is_empty() || lines.is_empty() {
+        lines.push(line);
+    }
+    lines
+}
+
+/// A PDF of `lines` in Helvetica, wrapped to the page and paginated. Characters outside
+/// WinAnsi (Latin-1 and common punctuation) come out as `?`.
+pub fn write_pdf(lines: &[PdfLine]) -> Vec<u8> {
+    // Page content streams, each a list of (font, size, y, text)
+    let mut pages: Vec<Vec<(&str, f64, f64, String)>> = vec![Vec::new()];
+    let mut y = PAGE_HEIGHT - MARGIN;
+    for line in lines {
+        let (font, size, text) = match line {
+            PdfLine::Title(text) => ("F2", 18.0, text.as_str()),
+            PdfLine::Heading(text) => ("F2", 13.0, text.as_str()),
+            PdfLine::Text(text) => ("F1", 11.0, text.as_str()),
+            PdfLine::Blank => {
+                y -= 8.0;
+                continue;
+            }
+        };
+        // Helvetica averages about half an em per character
+        let width = ((PAGE_WIDTH - 2.0 * MARGIN) / (size * 0.5)) as usize;
+        let leading = size * 1.4;
+        // Keep a heading with the line after it
+        let needed = if matches!(line, PdfLine::Heading(_)) { leading + 11.0 * 1.4 } else { leading };
+        for wrapped in wrap(text, width) {
+            if y - needed < MARGIN {
+                pages.push(Vec::new());
+                y = PAGE_HEIGHT - MARGIN;
+            }
+            y -= leading;
+            pages.last_mut().unwrap().push((font, size, y, wrapped));
+        }
+    }
+
+    let mut objects: Vec<Vec<u8>> = vec![
+        b"<< /Type /Catalog /Pages 2 0 R >>".to_vec(),
+        Vec::new(), // the page tree, once the pages are numbered
+        b"<< /Type /Font /Subtype /Type1 /BaseFont /Helvetica /Encoding /WinAnsiEncoding >>".to_vec(),
+        b"<< /Type /Font /Subtype /Type1 /BaseFont /Helvetica-Bold /Encoding /WinAnsiEncoding >>".to_vec(),
+    ];
+    let mut kids = Vec::new();
+    for page in &pages {
+        let mut content = Vec::new();
+        for (font, size, y, text) in page {
+            content.extend_from_slice(format!("BT /{} {} Tf {} {:.1} Td (", font, size, MARGIN, y).as_bytes());
+            for byte in text.chars().map(win_ansi) {
+                if matches!(byte, b'(' | b')' | b'\\') {
+                    content.push(b'\\');
+                }
+                content.push(byte);
+            }
+            content.extend_from_slice(b") Tj ET\n");
+        }
+        let content_number = objects.len() + 2;
+        kids.push(format!("{} 0 R", objects.len() + 1));
+        objects.push(format!(
+            "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {} {}] /Resources << /Font << /F1 3 0 R /F2 4 0 R >> >> /Contents {} 0 R >>",
+            PAGE_WIDTH, PAGE_HEIGHT, content_number
+        ).into_bytes());
+        let mut stream = format!("<< /Length {} >>\nstream\n", content.len()).into_bytes();
+        stream.extend_from_slice(&content);
+        stream.extend_from_slice(b"\nendstream");
+        objects.push(stream);
+    }
+    objects[1] = format!("<< /Type /Pages /Kids [{}] /Count {} >>", kids.join(" "), kids.len()).into_bytes();
+
+    let mut pdf = b"%PDF-1.4\n".to_vec();
+    let mut offsets = Vec::new();
+    for (i, object) in objects.iter().enumerate() {
+        offsets.push(pdf.len());
+        pdf.extend_from_slice(format!("{} 0 obj\n", i + 1).as_bytes());
+        pdf.extend_from_slice(object);
+        pdf.extend_from_slice(b"\nendobj\n");
+    }
+    let xref = pdf.len();
+    pdf.extend_from_slice(format!("xref\n0 {}\n0000000000 65535 f \n", objects.len() + 1).as_bytes());
+    for offset in offsets {
+        pdf.extend_from_slice(format!("{:010} 00000 n \n", offset).as_bytes());
+    }
+    pdf.extend_from_slice(format!("trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{}\n%%EOF\n", objects.len() + 1, xref).as_bytes());
+    pdf
+}
@@ -0,0 +1,274 @@
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use crate::message::ToolSafetyLevel;
+use crate::prompts::PromptContext;
+
+/// Groups of native functions a persona may be allowed to use
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Capability {
+    Files,
+    Web,
+    Commands,
+    Messaging,
+    Memory,
+    Database,
+    Vision,
+    Knowledge,
+    Replication,
+    /// Reading credentials with `get_secret`
+    Secrets,
+    /// Approving, rejecting and removing tools
+    ToolAdmin,
+    /// Running prompts and tools on a schedule
+    Scheduling,
+}
+
+/// Which capability each gated native belongs to; tool discovery
+/// (`list_tools`, `find_tool`, `inspect_tool`) is always allowed
+const NATIVE_CAPABILITIES: &[(&str, Capability)] = &[
+    ("read_file", Capability::Files),
+    ("write_file", Capability::Files),
+    ("append_file", Capability::Files),
+    ("delete_file", Capability::Files),
+    ("list_dir", Capability::Files),
+    ("workspace_path", Capability::Files),
+    ("list_changes", Capability::Files),
+    ("undo_changes", Capability::Files),
+    ("scrape_url", Capability::Web),
+    ("search_arxiv", Capability::Web),
+    ("get_paper", Capability::Web),
+    ("search", Capability::Web),
+    ("run_command", Capability::Commands),
+    ("send_message", Capability::Messaging),
+    ("call_peer", Capability::Messaging),
+    ("invoke_tool", Capability::Messaging),
+    ("reply_message", Capability::Messaging),
+    ("peer_history", Capability::Messaging),
+    ("check_inbox", Capability::Messaging),
+    ("read_message", Capability::Messaging),
+    ("mark_read", Capability::Messaging),
+    ("outbox_status", Capability::Messaging),
+    ("delegate_task", Capability::Messaging),
+    ("announce_task", Capability::Messaging),
+    ("task_status", Capability::Messaging),
+    ("list_peers", Capability::Messaging),
+    ("broadcast_message", Capability::Messaging),
+    ("ensemble_ask", Capability::Messaging),
+    ("current_leader", Capability::Messaging),
+    ("elect_leader", Capability::Messaging),
+    ("blackboard_set", Capability::Messaging),
+    ("blackboard_get", Capability::Messaging),
+    ("blackboard_watch", Capability::Messaging),
+    ("blackboard_unwatch", Capability::Messaging),
+    ("blackboard_sync", Capability::Messaging),
+    ("set_peer_group", Capability::Messaging),
+    ("subscribe", Capability::Messaging),
+    ("unsubscribe", Capability::Messaging),
+    ("publish", Capability::Messaging),
+    ("subscriptions", Capability::Messaging),
+    ("peer_status", Capability::Messaging),
+    ("announce", Capability::Messaging),
+    ("share_tool", Capability::Messaging),
+    ("request_tool", Capability::Messaging),
+    ("start_server", Capability::Messaging),
+    ("stop_server", Capability::Messaging),
+    ("server_status", Capability::Messaging),
+    ("memory_get", Capability::Memory),
+    ("memory_set", Capability::Memory),
+    ("memory_search", Capability::Memory),
+    ("db_query", Capability::Database),
+    ("db_execute", Capability::Database),
+    ("load_csv", Capability::Database),
+    ("load_json", Capability::Database),
+    ("query_data", Capability::Database),
+    ("describe_data", Capability::Database),
+    ("report_add", Capability::Memory),
+    ("report_title", Capability::Memory),
+    ("report_show", Capability::Memory),
+    ("report_clear", Capability::Memory),
+    ("report_export", Capability::Files),
+    ("list_sources", Capability::Memory),
+    ("analyze_image", Capability::Vision),
+    ("ingest_document", Capability::Knowledge),
+    ("read_pdf", Capability::Knowledge),
+    ("read_docx", Capability::Knowledge),
+    ("read_epub", Capability::Knowledge),
+    ("retrieve", Capability::Knowledge),
+    ("clone_agent", Capability::Replication),
+    ("register_clone", Capability::Replication),
+    ("list_clones", Capability::Replication),
+    ("spawn_clone", Capability::Replication),
+    ("stop_clone", Capability::Replication),
+    ("halt_agent", Capability::Messaging),
+    ("get_secret", Capability::Secrets),
+    ("approve_tool", Capability::ToolAdmin),
+    ("reject_tool", Capability::ToolAdmin),
+    ("remove_tool", Capability::ToolAdmin),
+    ("tool_log", Capability::ToolAdmin),
+    ("revert_tool", Capability::ToolAdmin),
+    ("list_pending_tools", Capability::ToolAdmin),
+    ("test_pending_tool", Capability::ToolAdmin),
+    ("review_pending_tool", Capability::ToolAdmin),
+    ("list_quarantine", Capability::ToolAdmin),
+    ("purge_quarantine", Capability::ToolAdmin),
+    ("trust_agent", Capability::ToolAdmin),
+    ("block_agent", Capability::ToolAdmin),
+    ("set_trust", Capability::ToolAdmin),
+    ("trust_levels", Capability::ToolAdmin),
+    ("show_audit", Capability::ToolAdmin),
+    ("schedule_task", Capability::Scheduling),
+    ("list_schedules", Capability::Scheduling),
+    ("cancel_schedule", Capability::Scheduling),
+];
+
+/// The gated natives and the capability each belongs to
+pub(crate) fn gated_natives() -> &'static [(&'static str, Capability)] {
+    NATIVE_CAPABILITIES
+}
+
+/// Whether `code` calls `function(` as a whole identifier (so `memory_search(` isn't `search(`)
+pub(crate) fn calls_function(code: &str, function: &str) -> bool {
+    let pattern = format!("{}(", function);
+    code.match_indices(&pattern).any(|(i, _)| {
+        !code[..i].chars().next_back().map(|c| c.is_alphanumeric() || c == '_').unwrap_or(false)
+    })
+}
+
+/// The capabilities of the gated natives `code` calls
+pub(crate) fn capabilities_called(code: &str) -> Vec<Capability> {
+    let mut capabilities = Vec::new();
+    for (native, capability) in NATIVE_CAPABILITIES {
+        if !capabilities.contains(capability) && calls_function(code, native) {
+            capabilities.push(*capability);
+        }
+    }
+    capabilities
+}
+
+/// What tools an agent may run: a capability allowlist and a risk ceiling
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ToolPolicy {
+    /// `None` allows every capability
+    #[serde(default)]
+    pub capabilities: Option<Vec<Capability>>,
+    #[serde(default = "highest_risk")]
+    pub max_risk: ToolSafetyLevel,
+}
+
+fn highest_risk() -> ToolSafetyLevel {
+    ToolSafetyLevel::HighRisk
+}
+
+impl Default for ToolPolicy {
+    fn default() -> Self {
+        Self { capabilities: None, max_risk: highest_risk() }
+    }
+}
+
+impl ToolPolicy {
+    pub fn is_unrestricted(&self) -> bool {
+        self.capabilities.is_none() && self.max_risk == ToolSafetyLevel::HighRisk
+    }
+
+    pub fn allows(&self, capability: Capability) -> bool {
+        self.capabilities.as_ref().map(|c| c.contains(&capability)).unwrap_or(true)
+    }
+
+    /// Allowed capabilities by name, or `all` when unrestricted
+    pub fn capability_names(&self) -> Vec<String> {
+        match &self.capabilities {
+            None => vec!["all".to_string()],
+            Some(capabilities) => capabilities.iter()
+                .filter_map(|c| serde_json::to_value(c).ok().and_then(|v| v.as_str().map(String::from)))
+                .collect(),
+        }
+    }
+
+    /// The first native called by `code` whose capability isn't allowed
+    pub fn denied_native(&self, code: &str) -> Option<(&'static str, Capability)> {
+        NATIVE_CAPABILITIES.iter()
+            .find(|(native, capability)| !self.allows(*capability) && calls_function(code, native))
+            .copied()
+    }
+}
+
+/// A named agent profile: prompt, model and tool policy
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Persona {
+    pub name: String,
+    /// Shown to the model as "Your role: ..."
+    #[serde(default)]
+    pub role: String,
+    /// `provider:model` spec; the environment's model when absent
+    #[serde(default)]
+    pub model: Option<String>,
+    /// Template in the prompts directory
+    #[serde(default = "default_prompt")]
+    pub prompt: String,
+    /// Extra rules rendered into the prompt's policies list
+    #[serde(default)]
+    pub policies: Vec<String>,
+    #[serde(default, flatten)]
+    pub tools: ToolPolicy,
+}
+
+fn default_prompt() -> String {
+    "system.md".to_string()
+}
+
+impl Persona {
+    /// Load `<dir>/<name>.toml`
+    pub fn load(dir: impl AsRef<Path>, name: &str) -> Result<Self> {
+        if name.is_empty() || !name.chars().all(|c| c.is_alphanumeric() || c == '_' || c == '-') {
+            return Err(anyhow!("Invalid persona name '{}'", name));
+        }
+        let path = dir.as_ref().join(format!("{}.toml", name));
+        let content = fs::read_to_string(&path)
+            .map_err(|e| anyhow!("Error reading persona '{}' from {:?}: {}", name, path, e))?;
+        toml::from_str(&content).map_err(|e| anyhow!("Invalid persona file {:?}: {}", path, e))
+    }
+
+    /// Names of the personas in `dir`
+    pub fn available(dir: impl AsRef<Path>) -> Vec<String> {
+        let mut names: Vec<String> = fs::read_dir(dir).into_iter().flatten().flatten()
+            .map(|e| e.path())
+            .filter(|p| p.extension().and_then(|e| e.to_str()) == Some("toml"))
+            .filter_map(|p| p.file_stem().map(|s| s.to_string_lossy().to_string()))
+            .collect();
+        names.sort();
+        names
+    }
+
+    /// Prompt variables from the environment plus this persona's role and policies
+    pub fn prompt_context(&self, tools: Vec<String>) -> PromptContext {
+        let mut context = PromptContext::from_env(tools);
+        context.policies.extend(self.policies.iter().cloned());
+        context.vars.insert("persona".to_string(), self.name.clone());
+        if !self.role.is_empty() {
+            context.vars.insert("role".to_string(), self.role.clone());
+        }
+        context
+    }
+}
+
+/// Directory holding persona files: `PERSONAS_DIR`, default `personas`
+pub fn personas_dir() -> PathBuf {
+    PathBuf::from(std::env::var("PERSONAS_DIR").unwrap_or_else(|_| "personas".to_string()))
+}
+
+/// Persona chosen with `--persona <name>` / `--persona=<name>`, else `AGENT_PERSONA`
+pub fn selected_persona(args: &[String]) -> Option<String> {
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        if arg == "--persona" {
+            return iter.next().cloned();
+        }
+        if let Some(name) = arg.strip_prefix("--persona=") {
+            return Some(name.to_string());
+        }
+    }
+    std::env::var("AGENT_PERSONA").ok().filter(|p| !p.trim().is_empty())
+}
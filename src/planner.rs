@@ -0,0 +1,170 @@
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+use crate::agent::Agent;
+use crate::tools::ToolManager;
+use tracing::info;
+
+/// One step of a plan; steps without a tool are reasoning the agent does itself
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PlanStep {
+    pub description: String,
+    #[serde(default)]
+    pub tool: Option<String>,
+    /// Tool arguments; `{{stepN}}` is replaced with the output of step N (1-based)
+    #[serde(default)]
+    pub args: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Plan {
+    #[serde(default)]
+    pub goal: String,
+    pub steps: Vec<PlanStep>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum StepStatus {
+    Done,
+    Failed,
+    /// Not run because an earlier step failed
+    Skipped,
+}
+
+#[derive(Debug, Clone)]
+pub struct StepResult {
+    pub step: usize,
+    pub description: String,
+    pub status: StepStatus,
+    pub output: String,
+}
+
+/// Instructions appended to the goal when asking the agent for a plan
+const PLAN_INSTRUCTIONS: &str = r#"Before doing anything, write a plan for the goal below.
+Respond ONLY with JSON of this shape:
+{"steps": [{"description": "what this step does", "tool": "tool_name or null", "args": ["at most one argument"]}]}
+Use only the available tools. An argument may contain {{stepN}} to use the output of step N."#;
+
+impl Plan {
+    /// Parse a plan from an LLM response: a JSON object with `steps`, or a bare array of steps,
+    /// optionally inside a code fence or surrounded by prose
+    pub fn parse(goal: &str, response: &str) -> Result<Self> {
+        let start = response.find(['{', '[']).ok_or_else(|| anyhow!("No JSON plan in response"))?;
+        let end = response.rfind(['}', ']']).filter(|&end| end > start)
+            .ok_or_else(|| anyhow!("No JSON plan in response"))?;
+        let json = &response[start..=end];
+
+        let mut plan = match serde_json::from_str::<Plan>(json) {
+            Ok(plan) => plan,
+            Err(_) => Plan {
+                goal: String::new(),
+                steps: serde_json::from_str(json).map_err(|e| anyhow!("Invalid plan JSON: {}", e))?,
+            },
+        };
+        if plan.goal.is_empty() {
+            plan.goal = goal.to_string();
+        }
+        // Models often write "null" or "" instead of leaving the tool out
+        for step in &mut plan.steps {
+            if step.tool.as_deref().map(|t| t.trim().is_empty() || t == "null").unwrap_or(false) {
+                step.tool = None;
+            }
+        }
+        Ok(plan)
+    }
+
+    /// Problems that prevent running the plan: unknown tools, tools the policy forbids,
+    /// too many arguments, or references to steps that haven't run yet. Empty means valid.
+    pub fn validate(&self, tools: &ToolManager) -> Vec<String> {
+        let mut problems = Vec::new();
+        if self.steps.is_empty() {
+            problems.push("Plan has no steps".to_string());
+        }
+        for (i, step) in self.steps.iter().enumerate() {
+            let number = i + 1;
+            if let Some(tool) = &step.tool {
+                if let Err(e) = tools.check_tool(tool) {
+                    problems.push(format!("Step {}: {}", number, e));
+                }
+            }
+            if step.args.len() > 1 {
+                problems.push(format!("Step {}: tools take at most one argument", number));
+            }
+            for arg in &step.args {
+                for reference in step_references(arg) {
+                    if reference == 0 || reference >= number {
+                        problems.push(format!("Step {}: {{{{step{}}}}} refers to a step that hasn't run yet", number, reference));
+                    }
+                }
+            }
+        }
+        problems
+    }
+
+    /// Numbered steps, for showing the plan to the user
+    pub fn render(&self) -> String {
+        let mut output = format!("Plan: {}\n", self.goal);
+        for (i, step) in self.steps.iter().enumerate() {
+            output.push_str(&format!("{}. {}", i + 1, step.description));
+            if let Some(tool) = &step.tool {
+                output.push_str(&format!(" [TOOL: {}({})]", tool, step.args.join(", ")));
+            }
+            output.push('\n');
+        }
+        output
+    }
+
+    /// Run the steps in order, stopping at the first failure
+    pub fn execute(&self, tools: &ToolManager) -> Vec<StepResult> {
+        let mut results: Vec<StepResult> = Vec::new();
+        let mut failed = false;
+        for (i, step) in self.steps.iter().enumerate() {
+            let (status, output) = if failed {
+                (StepStatus::Skipped, String::new())
+            } else if let Some(tool) = &step.tool {
+                let args = step.args.iter().map(|arg| substitute(arg, &results)).collect();
+                info!("Step {}: {}", i + 1, tool);
+                match tools.execute_tool(tool, args) {
+                    // Natives report failures as strings rather than errors
+                    Ok(output) if output.starts_with("Error") => (StepStatus::Failed, output),
+                    Ok(output) => (StepStatus::Done, output),
+                    Err(e) => (StepStatus::Failed, e.to_string()),
+                }
+            } else {
+                (StepStatus::Done, String::new())
+            };
+            failed |= status == StepStatus::Failed;
+            results.push(StepResult { step: i + 1, description: step.description.clone(), status, output });
+        }
+        results
+    }
+}
+
+fn step_references(arg: &str) -> Vec<usize> {
+    arg.split("{{step").skip(1)
+        .filter_map(|rest| rest.split_once("}}").and_then(|(n, _)| n.trim().parse().ok()))
+        .collect()
+}
+
+fn substitute(arg: &str, results: &[StepResult]) -> String {
+    results.iter().fold(arg.to_string(), |arg, r| arg.replace(&format!("{{{{step{}}}}}", r.step), &r.output))
+}
+
+/// Per-step results as text, for the user and for feeding back to the agent
+pub fn render_results(results: &[StepResult]) -> String {
+    results.iter()
+        .map(|r| format!("{}. {} [{:?}] {}", r.step, r.description, r.status, r.output).trim_end().to_string())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Ask `agent` for a plan for `goal` using the tools in `tools`
+pub async fn make_plan(agent: &mut Agent, tools: &ToolManager, goal: &str) -> Result<Plan> {
+    let request = format!("{}\nAvailable tools: {}\n\nGoal: {}", PLAN_INSTRUCTIONS, tools.list_tools().join(", "), goal);
+    let response = agent.chat(&request).await?;
+    Plan::parse(goal, &response)
+}
+
+/// Give the agent the step results so it can write the final answer
+pub async fn summarize_results(agent: &mut Agent, plan: &Plan, results: &[StepResult]) -> Result<String> {
+    agent.chat(&format!("The plan for \"{}\" was executed:\n{}\n\nUsing these results, answer the goal.", plan.goal, render_results(results))).await
+}
@@ -0,0 +1,331 @@
+use anyhow::{anyhow, Result};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tracing::debug;
+use crate::egress;
+use crate::safety::SafetyPolicy;
+
+/// Requests at once to one host unless `SCRAPE_MAX_PER_HOST` says otherwise
+pub const DEFAULT_MAX_PER_HOST: usize = 4;
+
+/// How long a host's robots.txt is trusted before it is fetched again
+const ROBOTS_TTL: Duration = Duration::from_secs(24 * 3600);
+
+/// The User-Agent scraping tools send unless `SCRAPE_USER_AGENT` says otherwise
+pub fn default_user_agent() -> String {
+    format!("swarm-thing/{} (research agent)", env!("CARGO_PKG_VERSION"))
+}
+
+/// One `Allow` or `Disallow` line
+#[derive(Debug, Clone, PartialEq)]
+struct Rule {
+    allow: bool,
+    pattern: String,
+}
+
+/// The rules of a robots.txt that apply to one user agent
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Robots {
+    rules: Vec<Rule>,
+    /// `Crawl-delay`, in seconds
+    pub crawl_delay: Option<Duration>,
+}
+
+impl Robots {
+    /// Everything allowed, as for a site without robots.txt
+    pub fn allow_all() -> Self {
+        Self::default()
+    }
+
+    /// Nothing allowed, as for a site whose robots.txt can't be read for a server error
+    pub fn disallow_all() -> Self {
+        Self { rules: vec![Rule { allow: false, pattern: "/".to_string() }], crawl_delay: None }
+    }
+
+    /// The group of `text` for `agent` (the product token of a User-Agent, e.g. `swarm-thing`),
+    /// or the `*` group when none names it
+    pub fn parse(text: &str, agent: &str) -> Self {
+        let agent = agent.to_lowercase();
+        // (agents, rules, crawl delay) per group; consecutive User-agent lines share a group
+        let mut groups: Vec<(Vec<String>, Robots)> = Vec::new();
+        let mut in_agents = false;
+        for line in text.lines() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            let Some((key, value)) = line.split_once(':') else { continue };
+            let (key, value) = (key.trim().to_lowercase(), value.trim());
+            match key.as_str() {
+                "user-agent" => {
+                    if !in_agents {
+                        groups.push((Vec::new(), Robots::default()));
+                        in_agents = true;
+                    }
+                    groups.last_mut().unwrap().0.push(value.to_lowercase());
+                }
+                "allow" | "disallow" => {
+                    in_agents = false;
+                    // An empty Disallow allows everything
+                    if let (Some((_, robots)), false) = (groups.last_mut(), value.is_empty()) {
+                        robots.rules.push(Rule { allow: key == "allow", pattern: value.to_string() });
+                    }
+                }
+                "crawl-delay" => {
+                    in_agents = false;
+                    if let (Some((_, robots)), Ok(secs)) = (groups.last_mut(), value.parse::<f64>()) {
+                        robots.crawl_delay = Duration::try_from_secs_f64(secs).ok();
+                    }
+                }
+                _ => {}
+            }
+        }
+        let named = groups.iter().find(|(agents, _)| agents.iter().any(|a| a != "*" && agent.contains(a.as_str())));
+        named.or_else(|| groups.iter().find(|(agents, _)| agents.iter().any(|a| a == "*")))
+            .map(|(_, robots)| robots.clone())
+            .unwrap_or_default()
+    }
+
+    /// Whether `path` (with its query) may be fetched: the longest matching rule decides, and
+    /// `Allow` wins a tie
+    pub fn allows(&self, path: &str) -> bool {
+        self.rules.iter()
+            .filter(|rule| pattern_matches(&rule.pattern, path))
+            .max_by_key(|rule| (rule.pattern.len(), rule.allow))
+            .is_none_or(|rule| rule.allow)
+    }
+}
+
+/// A robots.txt path pattern: a prefix, where `*` matches anything and a final `$` anchors the end
+fn pattern_matches(pattern: &str, path: &str) -> bool {
+    let (pattern, anchored) = match pattern.strip_suffix('$') {
+        Some(pattern) => (pattern, true),
+        None => (pattern, false),
+    };
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = path.strip_prefix(first) else { return false };
+    let parts: Vec<&str> = parts.collect();
+    for (i, part) in parts.iter().enumerate() {
+        // Anchored, the last part has to end the path
+        if anchored && i == parts.len() - 1 {
+            return rest.ends_with(part);
+        }
+        match rest.find(part) {
+            Some(at) => rest = &rest[at + part.len()..],
+            None => return false,
+        }
+    }
+    !anchored || rest.is_empty()
+}
+
+/// `scheme://host[:port]` and `host` of `url`
+fn origin_of(url: &str) -> Result<(String, String, String)> {
+    let parsed = reqwest::Url::parse(url).map_err(|e| anyhow!("Invalid URL {}: {}", url, e))?;
+    let host = parsed.host_str().ok_or_else(|| anyhow!("URL {} has no host", url))?.to_string();
+    let origin = match parsed.port() {
+        Some(port) => format!("{}://{}:{}", parsed.scheme(), host, port),
+        None => format!("{}://{}", parsed.scheme(), host),
+    };
+    let path = match parsed.query() {
+        Some(query) => format!("{}?{}", parsed.path(), query),
+        None => parsed.path().to_string(),
+    };
+    Ok((origin, host, path))
+}
+
+/// What one scraping request holds while it runs: a connection slot of its host
+pub struct HostPermit {
+    _permit: OwnedSemaphorePermit,
+}
+
+/// How the scraping tools treat the sites they visit: who they say they are, what robots.txt
+/// allows, how long they wait between requests to a host and how many they make at once
+#[derive(Debug, Clone)]
+pub struct Politeness {
+    user_agent: String,
+    respect_robots: bool,
+    delay: Duration,
+    /// Per-domain delays, for the domain and its subdomains, overriding `delay`
+    domain_delays: Vec<(String, Duration)>,
+    max_per_host: usize,
+    robots: Arc<Mutex<HashMap<String, (Robots, Instant)>>>,
+    /// Serializes robots.txt fetches per origin, so parallel requests fetch it once
+    robots_fetches: Arc<Mutex<HashMap<String, Arc<tokio::sync::Mutex<()>>>>>,
+    next_slot: Arc<Mutex<HashMap<String, Instant>>>,
+    connections: Arc<Mutex<HashMap<String, Arc<Semaphore>>>>,
+    /// Checked on every redirect, when set
+    egress: Option<Arc<Mutex<SafetyPolicy>>>,
+}
+
+impl Default for Politeness {
+    fn default() -> Self {
+        Self {
+            user_agent: default_user_agent(),
+            respect_robots: true,
+            delay: Duration::ZERO,
+            domain_delays: Vec::new(),
+            max_per_host: DEFAULT_MAX_PER_HOST,
+            robots: Arc::new(Mutex::new(HashMap::new())),
+            robots_fetches: Arc::new(Mutex::new(HashMap::new())),
+            next_slot: Arc::new(Mutex::new(HashMap::new())),
+            connections: Arc::new(Mutex::new(HashMap::new())),
+            egress: None,
+        }
+    }
+}
+
+impl Politeness {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_user_agent(mut self, user_agent: &str) -> Self {
+        self.user_agent = user_agent.to_string();
+        self
+    }
+
+    pub fn with_robots(mut self, respect_robots: bool) -> Self {
+        self.respect_robots = respect_robots;
+        self
+    }
+
+    /// Wait at least `delay` between the starts of two requests to a host
+    pub fn with_delay(mut self, delay: Duration) -> Self {
+        self.delay = delay;
+        self
+    }
+
+    /// Wait `delay` between requests to `domain` and its subdomains instead
+    pub fn with_domain_delay(mut self, domain: &str, delay: Duration) -> Self {
+        self.domain_delays.push((domain.trim().trim_start_matches("*.").to_lowercase(), delay));
+        self
+    }
+
+    /// At most `max` requests to a host at once (at least 1)
+    pub fn with_max_per_host(mut self, max: usize) -> Self {
+        self.max_per_host = max.max(1);
+        self
+    }
+
+    /// Check each redirect against the host lists of `safety`, as callers check the first URL
+    pub fn with_egress(mut self, safety: Arc<Mutex<SafetyPolicy>>) -> Self {
+        self.egress = Some(safety);
+        self
+    }
+
+    /// `SCRAPE_USER_AGENT`, `SCRAPE_ROBOTS` (default `true`), `SCRAPE_DELAY_MS` (default 0),
+    /// `SCRAPE_DOMAIN_DELAYS` (`domain=ms,...`) and `SCRAPE_MAX_PER_HOST` (default 4)
+    pub fn from_env() -> Self {
+        let mut politeness = Self::new();
+        if let Ok(user_agent) = std::env::var("SCRAPE_USER_AGENT") {
+            if !user_agent.trim().is_empty() {
+                politeness = politeness.with_user_agent(user_agent.trim());
+            }
+        }
+        if let Ok(robots) = std::env::var("SCRAPE_ROBOTS") {
+            politeness = politeness.with_robots(!matches!(robots.to_lowercase().as_str(), "0" | "false" | "no"));
+        }
+        if let Some(ms) = std::env::var("SCRAPE_DELAY_MS").ok().and_then(|v| v.trim().parse().ok()) {
+            politeness = politeness.with_delay(Duration::from_millis(ms));
+        }
+        for entry in std::env::var("SCRAPE_DOMAIN_DELAYS").unwrap_or_default().split(',') {
+            if let Some((domain, ms)) = entry.split_once('=') {
+                if let Ok(ms) = ms.trim().parse() {
+                    politeness = politeness.with_domain_delay(domain, Duration::from_millis(ms));
+                }
+            }
+        }
+        if let Some(max) = std::env::var("SCRAPE_MAX_PER_HOST").ok().and_then(|v| v.trim().parse().ok()) {
+            politeness = politeness.with_max_per_host(max);
+        }
+        politeness
+    }
+
+    pub fn user_agent(&self) -> &str {
+        &self.user_agent
+    }
+
+    /// The product token robots.txt groups are matched against, e.g. `swarm-thing`
+    fn agent_token(&self) -> &str {
+        self.user_agent.split(['/', ' ']).next().unwrap_or_default()
+    }
+
+    /// A client that identifies itself with the User-Agent, and follows only redirects and
+    /// connects only to addresses the egress policy allows
+    pub fn client(&self) -> reqwest::Client {
+        let mut builder = reqwest::Client::builder().user_agent(&self.user_agent);
+        if let Some(safety) = &self.egress {
+            builder = builder.redirect(egress::redirect_policy(safety.lock().unwrap().clone()));
+            let safety = safety.clone();
+            builder = builder.dns_resolver(egress::resolver(move || Ok(safety.lock().unwrap().clone())));
+        }
+        builder.build().unwrap_or_default()
+    }
+
+    /// The delay between requests to `host`: the most specific domain delay, else the default
+    pub fn delay_for(&self, host: &str) -> Duration {
+        let host = host.to_lowercase();
+        self.domain_delays.iter()
+            .filter(|(domain, _)| host == *domain || host.ends_with(&format!(".{}", domain)))
+            .max_by_key(|(domain, _)| domain.len())
+            .map(|(_, delay)| *delay)
+            .unwrap_or(self.delay)
+    }
+
+    /// The robots.txt rules of `origin` for this agent, fetched once a day. A missing robots.txt
+    /// allows everything, as does one that can't be reached (the request itself will say why);
+    /// a server error disallows everything.
+    async fn robots(&self, client: &reqwest::Client, origin: &str) -> Robots {
+        let cached = |robots: &Mutex<HashMap<String, (Robots, Instant)>>| {
+            robots.lock().unwrap().get(origin).filter(|(_, at)| at.elapsed() < ROBOTS_TTL).map(|(r, _)| r.clone())
+        };
+        if let Some(robots) = cached(&self.robots) {
+            return robots;
+        }
+        let fetch_lock = self.robots_fetches.lock().unwrap().entry(origin.to_string()).or_default().clone();
+        let _fetching = fetch_lock.lock().await;
+        if let Some(robots) = cached(&self.robots) {
+            return robots;
+        }
+        let robots = match client.get(format!("{}/robots.txt", origin)).send().await {
+            Ok(response) if response.status().is_success() => {
+                Robots::parse(&response.text().await.unwrap_or_default(), self.agent_token())
+            }
+            Ok(response) if response.status().is_server_error() => Robots::disallow_all(),
+            _ => Robots::allow_all(),
+        };
+        debug!("robots.txt of {}: {:?}", origin, robots);
+        self.robots.lock().unwrap().insert(origin.to_string(), (robots.clone(), Instant::now()));
+        robots
+    }
+
+    /// Wait until a request to `url` is polite: robots.txt allows it, a connection slot of its
+    /// host is free and the host's delay (or its robots.txt `Crawl-delay`, if longer) has passed
+    /// since the last request. Hold the permit until the response is read.
+    pub async fn admit(&self, client: &reqwest::Client, url: &str) -> Result<HostPermit> {
+        let (origin, host, path) = origin_of(url)?;
+        let mut delay = self.delay_for(&host);
+        if self.respect_robots {
+            let robots = self.robots(client, &origin).await;
+            if !robots.allows(&path) {
+                return Err(anyhow!("robots.txt of {} disallows {} for {}", origin, path, self.agent_token()));
+            }
+            delay = delay.max(robots.crawl_delay.unwrap_or_default());
+        }
+        let connections = self.connections.lock().unwrap()
+            .entry(host.clone())
+            .or_insert_with(|| Arc::new(Semaphore::new(self.max_per_host)))
+            .clone();
+        let permit = connections.acquire_owned().await.map_err(|e| anyhow!("{}", e))?;
+        // Book the next start time for this host, then wait for it
+        let start = {
+            let mut next_slot = self.next_slot.lock().unwrap();
+            let now = Instant::now();
+            let start = next_slot.get(&host).copied().filter(|at| *at > now).unwrap_or(now);
+            next_slot.insert(host, start + delay);
+            start
+        };
+        tokio::time::sleep_until(start.into()).await;
+        Ok(HostPermit { _permit: permit })
+    }
+}
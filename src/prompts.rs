@@ -0,0 +1,93 @@
+use anyhow::{Result, anyhow};
+use minijinja::{path_loader, Environment};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/// Built-in copy of `prompts/system.md`, used when the template directory has none
+const DEFAULT_SYSTEM_TEMPLATE: &str = include_str!("../prompts/system.md");
+
+/// Variables available to prompt templates
+#[derive(Debug, Clone, Serialize)]
+pub struct PromptContext {
+    pub agent_name: String,
+    pub tools: Vec<String>,
+    /// `name(params) - description` per tool, listed instead of the bare names when given
+    pub tool_signatures: Vec<String>,
+    pub policies: Vec<String>,
+    /// Free-form extra variables, e.g. from a persona
+    pub vars: BTreeMap<String, String>,
+}
+
+impl PromptContext {
+    pub fn new(agent_name: impl Into<String>, tools: Vec<String>) -> Self {
+        Self {
+            agent_name: agent_name.into(),
+            tools,
+            tool_signatures: Vec::new(),
+            policies: Vec::new(),
+            vars: BTreeMap::new(),
+        }
+    }
+
+    /// Name from `AGENT_NAME` (default `Swarm Thing`) and policies from `AGENT_POLICIES`,
+    /// separated by `;`
+    pub fn from_env(tools: Vec<String>) -> Self {
+        let mut context = Self::new(std::env::var("AGENT_NAME").unwrap_or_else(|_| "Swarm Thing".to_string()), tools);
+        context.policies = std::env::var("AGENT_POLICIES")
+            .unwrap_or_default()
+            .split(';')
+            .map(str::trim)
+            .filter(|p| !p.is_empty())
+            .map(String::from)
+            .collect();
+        context
+    }
+}
+
+/// Renders minijinja templates from a directory (default `prompts/`). Templates are read
+/// from disk on every render, so edits take effect on the next request without recompiling.
+#[derive(Debug, Clone)]
+pub struct PromptLibrary {
+    dir: PathBuf,
+}
+
+impl PromptLibrary {
+    pub fn new(dir: impl AsRef<Path>) -> Self {
+        Self { dir: dir.as_ref().to_path_buf() }
+    }
+
+    /// Templates from `PROMPTS_DIR`, defaulting to `prompts`
+    pub fn from_env() -> Self {
+        Self::new(std::env::var("PROMPTS_DIR").unwrap_or_else(|_| "prompts".to_string()))
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    fn environment(&self) -> Environment<'static> {
+        let mut env = Environment::new();
+        env.set_trim_blocks(true);
+        env.set_lstrip_blocks(true);
+        env.set_loader(path_loader(&self.dir));
+        env
+    }
+
+    /// Render the template `name` (a path relative to the template directory).
+    /// `system.md` falls back to the built-in template if the file doesn't exist.
+    pub fn render(&self, name: &str, context: &PromptContext) -> Result<String> {
+        let mut env = self.environment();
+        if name == "system.md" && !self.dir.join(name).exists() {
+            env.add_template("system.md", DEFAULT_SYSTEM_TEMPLATE)?;
+        }
+        let template = env.get_template(name)
+            .map_err(|e| anyhow!("Prompt template '{}' in {:?}: {}", name, self.dir, e))?;
+        template.render(context).map_err(|e| anyhow!("Error rendering prompt '{}': {}", name, e))
+    }
+
+    /// Render `system.md`
+    pub fn system_prompt(&self, context: &PromptContext) -> Result<String> {
+        self.render("system.md", context)
+    }
+}
@@ -0,0 +1,103 @@
+use anyhow::{Result, anyhow};
+use base64::Engine as _;
+use ed25519_dalek::{Signature, Signer, Verifier, VerifyingKey};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use crate::auth::key_fingerprint;
+use crate::encryption::AgentKeys;
+
+fn b64() -> base64::engine::GeneralPurpose {
+    base64::engine::general_purpose::STANDARD
+}
+
+/// What a signature covers: the tool's name and its exact code
+fn signed_bytes(name: &str, code: &str) -> Vec<u8> {
+    format!("swarm-thing tool\n{}\n{}", name, code).into_bytes()
+}
+
+/// The authoring agent's Ed25519 signature over a tool, kept next to the installed tool as
+/// `<name>.sig` and passed on with it when it is shared again
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuthorSignature {
+    /// Fingerprint of `public_key`
+    pub author: String,
+    /// Base64 Ed25519 key of the author
+    pub public_key: String,
+    /// Base64 signature
+    pub signature: String,
+}
+
+impl AuthorSignature {
+    /// Sign tool `name` with `code` as authored by the agent holding `keys`
+    pub fn sign(keys: &AgentKeys, name: &str, code: &str) -> Self {
+        let signing = keys.signing_key();
+        let public_key = signing.verifying_key();
+        Self {
+            author: key_fingerprint(public_key.as_bytes()),
+            public_key: b64().encode(public_key.as_bytes()),
+            signature: b64().encode(signing.sign(&signed_bytes(name, code)).to_bytes()),
+        }
+    }
+
+    /// Fails unless this signature is its author's, over exactly `name` and `code`
+    pub fn verify(&self, name: &str, code: &str) -> Result<()> {
+        let key: [u8; 32] = b64().decode(&self.public_key)?
+            .try_into()
+            .map_err(|_| anyhow!("an Ed25519 public key is 32 bytes"))?;
+        if key_fingerprint(&key) != self.author {
+            return Err(anyhow!("author {} does not match the signing key", self.author));
+        }
+        let signature: [u8; 64] = b64().decode(&self.signature)?
+            .try_into()
+            .map_err(|_| anyhow!("an Ed25519 signature is 64 bytes"))?;
+        VerifyingKey::from_bytes(&key)?
+            .verify(&signed_bytes(name, code), &Signature::from_bytes(&signature))
+            .map_err(|_| anyhow!("signature by {} does not match the code", self.author))
+    }
+}
+
+pub fn signature_path(tools_dir: &Path, name: &str) -> PathBuf {
+    tools_dir.join(format!("{}.sig", name))
+}
+
+/// The signature stored with installed tool `name`, if any
+pub fn installed_signature(tools_dir: &Path, name: &str) -> Option<AuthorSignature> {
+    let json = std::fs::read_to_string(signature_path(tools_dir, name)).ok()?;
+    serde_json::from_str(&json).ok()
+}
+
+/// Keep `signature` with installed tool `name`
+pub fn store_signature(tools_dir: &Path, name: &str, signature: Option<&AuthorSignature>) -> Result<()> {
+    let path = signature_path(tools_dir, name);
+    match signature {
+        Some(signature) => std::fs::write(path, serde_json::to_string_pretty(signature)?)?,
+        None if path.exists() => std::fs::remove_file(path)?,
+        None => {}
+    }
+    Ok(())
+}
+
+/// Whether a received copy of tool `name` may replace or join the installed tools: a signature
+/// must match the code, and a tool installed with a signature is only replaced by a copy its
+/// author signed
+pub fn check_provenance(tools_dir: &Path, name: &str, code: &str, signature: Option<&AuthorSignature>) -> Result<()> {
+    if let Some(signature) = signature {
+        signature.verify(name, code)?;
+    }
+    let Some(installed) = installed_signature(tools_dir, name) else {
+        return Ok(());
+    };
+    match signature {
+        Some(signature) if signature.public_key == installed.public_key => Ok(()),
+        Some(signature) => Err(anyhow!("'{}' is installed signed by {}, not {}", name, installed.author, signature.author)),
+        None => Err(anyhow!("'{}' is installed signed by {}; this copy is unsigned", name, installed.author)),
+    }
+}
+
+/// The signature to share tool `name` with: its author's if the stored one still matches the
+/// code, otherwise this agent's own, as the one who wrote or changed it
+pub fn signature_for_share(tools_dir: &Path, name: &str, code: &str, keys: Option<&AgentKeys>) -> Option<AuthorSignature> {
+    installed_signature(tools_dir, name)
+        .filter(|signature| signature.verify(name, code).is_ok())
+        .or_else(|| keys.map(|keys| AuthorSignature::sign(keys, name, code)))
+}
@@ -0,0 +1,546 @@
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use aws_config::BehaviorVersion;
+use aws_sdk_bedrockruntime::primitives::Blob;
+use aws_sdk_bedrockruntime::types::{ContentBlock, ConversationRole, ImageBlock, ImageFormat, ImageSource, Message as BedrockMessage, SystemContentBlock};
+use aws_sdk_bedrockruntime::Client;
+use futures_util::future::BoxFuture;
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::sync::{Arc, OnceLock, RwLock};
+use crate::embeddings::{Embedder, EmbeddingProvider};
+use crate::llm::{GenerationConfig, Message, OpenAiConfig, Role, Usage};
+use crate::retry::{is_transient_status, transient};
+
+/// A model API the client can talk to. Implement it to plug in a gateway of your own (a vLLM
+/// or llama.cpp server, a corporate proxy) and `register` it under a name. Retries, timeouts,
+/// caching and failover are the client's job, so a provider only makes the one request; mark
+/// errors worth retrying with `retry::transient`.
+#[async_trait]
+pub trait ChatProvider: Send + Sync {
+    /// Registry name, the first half of `provider:model` labels
+    fn name(&self) -> &str;
+
+    fn model(&self) -> &str;
+
+    /// Answer the conversation, reporting the tokens it used
+    async fn chat(&self, messages: Vec<Message>, system_prompt: Option<String>, generation: &GenerationConfig) -> Result<(String, Usage)>;
+
+    /// Like `chat`, handing each piece of the answer to `on_chunk` as it arrives. Providers
+    /// that can't stream hand over the whole answer at once.
+    async fn chat_stream(
+        &self,
+        messages: Vec<Message>,
+        system_prompt: Option<String>,
+        generation: &GenerationConfig,
+        on_chunk: &OnChunk<'_>,
+    ) -> Result<(String, Usage)> {
+        let (text, usage) = self.chat(messages, system_prompt, generation).await?;
+        on_chunk(&text);
+        Ok((text, usage))
+    }
+
+    /// One vector per text, for similarity search
+    async fn embed(&self, _texts: &[String]) -> Result<Vec<Vec<f32>>> {
+        Err(anyhow!("Provider '{}' has no embeddings", self.name()))
+    }
+
+    /// Roughly how many tokens `text` uses with this model's tokenizer
+    fn count_tokens(&self, text: &str) -> usize {
+        tokens_by_ratio(text, 4.0)
+    }
+
+    /// Size of the model's context window, for the default context budget
+    fn context_window(&self) -> usize {
+        8_192
+    }
+}
+
+/// Takes each piece of a streamed answer
+pub type OnChunk<'a> = dyn Fn(&str) + Send + Sync + 'a;
+
+/// Token estimate for a tokenizer averaging `chars_per_token` characters a token
+pub fn tokens_by_ratio(text: &str, chars_per_token: f64) -> usize {
+    if text.is_empty() {
+        return 0;
+    }
+    (text.chars().count() as f64 / chars_per_token).ceil() as usize
+}
+
+/// Makes a provider for a model, or for its default model given None
+pub type ProviderFactory = Arc<dyn Fn(Option<String>) -> BoxFuture<'static, Result<Arc<dyn ChatProvider>>> + Send + Sync>;
+
+/// Providers by name, as `llm.provider` and `provider:model` specs name them
+pub struct ProviderRegistry {
+    factories: RwLock<BTreeMap<String, ProviderFactory>>,
+}
+
+/// The process's providers: `bedrock`, `ollama` and `openai` (also `azure`, `azure_openai`),
+/// plus any registered
+pub fn providers() -> &'static ProviderRegistry {
+    static PROVIDERS: OnceLock<ProviderRegistry> = OnceLock::new();
+    PROVIDERS.get_or_init(ProviderRegistry::with_builtins)
+}
+
+impl ProviderRegistry {
+    fn with_builtins() -> Self {
+        let registry = Self { factories: RwLock::new(BTreeMap::new()) };
+        registry.register("bedrock", |model| async move {
+            Ok(Arc::new(BedrockChat::from_env(model).await) as Arc<dyn ChatProvider>)
+        });
+        registry.register("ollama", |model| async move {
+            Ok(Arc::new(OllamaChat::from_env(model)) as Arc<dyn ChatProvider>)
+        });
+        for name in ["openai", "azure", "azure_openai"] {
+            registry.register(name, |model| async move {
+                Ok(Arc::new(OpenAiChat::from_env(model)) as Arc<dyn ChatProvider>)
+            });
+        }
+        registry
+    }
+
+    /// Make `name` (case insensitive) a provider, replacing any registered under it
+    pub fn register<F, Fut>(&self, name: &str, factory: F)
+    where
+        F: Fn(Option<String>) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<Arc<dyn ChatProvider>>> + Send + 'static,
+    {
+        let factory: ProviderFactory = Arc::new(move |model| Box::pin(factory(model)));
+        self.factories.write().unwrap().insert(name.to_lowercase(), factory);
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.factories.read().unwrap().contains_key(&name.to_lowercase())
+    }
+
+    pub fn names(&self) -> Vec<String> {
+        self.factories.read().unwrap().keys().cloned().collect()
+    }
+
+    /// A provider `name` for `model`, or for its default model given None
+    pub async fn create(&self, name: &str, model: Option<String>) -> Result<Arc<dyn ChatProvider>> {
+        let factory = self.factories.read().unwrap().get(&name.trim().to_lowercase()).cloned()
+            .ok_or_else(|| anyhow!("Unknown LLM provider '{}' (known: {})", name, self.names().join(", ")))?;
+        factory(model).await
+    }
+}
+
+/// Feed `response`'s body to `on_line` a line at a time, as it arrives
+async fn each_line(mut response: reqwest::Response, mut on_line: impl FnMut(&str) -> Result<()>) -> Result<()> {
+    let mut buffer = Vec::new();
+    while let Some(chunk) = response.chunk().await.map_err(|e| transient(format!("Stream interrupted: {}", e)))? {
+        buffer.extend_from_slice(&chunk);
+        while let Some(end) = buffer.iter().position(|b| *b == b'\n') {
+            let line: Vec<u8> = buffer.drain(..=end).collect();
+            on_line(String::from_utf8_lossy(&line).trim())?;
+        }
+    }
+    on_line(String::from_utf8_lossy(&buffer).trim())
+}
+
+fn role_name(role: &Role) -> &'static str {
+    match role {
+        Role::User => "user",
+        Role::Assistant => "assistant",
+    }
+}
+
+/// Claude and other models on AWS Bedrock, through the Converse API
+pub struct BedrockChat {
+    client: Client,
+    model: String,
+}
+
+impl BedrockChat {
+    /// Credentials and region from the usual AWS sources
+    pub async fn from_env(model: Option<String>) -> Self {
+        let config = aws_config::load_defaults(BehaviorVersion::latest()).await;
+        Self { client: Client::new(&config), model: model.unwrap_or_else(|| "anthropic.claude-3-sonnet-20240229-v1:0".to_string()) }
+    }
+}
+
+#[async_trait]
+impl ChatProvider for BedrockChat {
+    fn name(&self) -> &str {
+        "bedrock"
+    }
+
+    fn model(&self) -> &str {
+        &self.model
+    }
+
+    async fn chat(&self, messages: Vec<Message>, system_prompt: Option<String>, generation: &GenerationConfig) -> Result<(String, Usage)> {
+        // Convert generic messages to Bedrock messages
+        let mut bedrock_messages: Vec<BedrockMessage> = Vec::new();
+        for m in messages {
+            let role = match m.role {
+                Role::User => ConversationRole::User,
+                Role::Assistant => ConversationRole::Assistant,
+            };
+            let mut builder = BedrockMessage::builder().role(role);
+            for image in &m.images {
+                let block = ImageBlock::builder()
+                    .format(ImageFormat::from(image.format()))
+                    .source(ImageSource::Bytes(Blob::new(image.bytes()?)))
+                    .build()?;
+                builder = builder.content(ContentBlock::Image(block));
+            }
+            bedrock_messages.push(builder.content(ContentBlock::Text(m.content)).build()?);
+        }
+
+        let mut request = self.client
+            .converse()
+            .model_id(&self.model)
+            .set_messages(Some(bedrock_messages))
+            .inference_config(generation.to_bedrock());
+        if let Some(prompt) = system_prompt {
+            request = request.system(SystemContentBlock::Text(prompt));
+        }
+
+        let output = request.send().await.map_err(|e| {
+            use aws_sdk_bedrockruntime::error::SdkError;
+            let retryable = match &e {
+                SdkError::TimeoutError(_) | SdkError::DispatchFailure(_) | SdkError::ResponseError(_) => true,
+                SdkError::ServiceError(service) => {
+                    let err = service.err();
+                    err.is_throttling_exception()
+                        || err.is_service_unavailable_exception()
+                        || err.is_internal_server_exception()
+                        || err.is_model_not_ready_exception()
+                        || err.is_model_timeout_exception()
+                }
+                _ => false,
+            };
+            if retryable {
+                transient(format!("Bedrock error: {}", e))
+            } else {
+                anyhow!("Bedrock error: {}", e)
+            }
+        })?;
+
+        let usage = output.usage.as_ref().map(|u| Usage {
+            input_tokens: u.input_tokens.max(0) as u64,
+            output_tokens: u.output_tokens.max(0) as u64,
+        }).unwrap_or_default();
+
+        if let Some(aws_sdk_bedrockruntime::types::ConverseOutput::Message(message)) = output.output {
+            if let Some(content) = message.content.first() {
+                match content {
+                    ContentBlock::Text(text) => return Ok((text.clone(), usage)),
+                    _ => return Ok(("Received non-text response".to_string(), usage)),
+                }
+            }
+        }
+        Ok(("No response generated".to_string(), usage))
+    }
+
+    async fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+        Embedder::with_model(EmbeddingProvider::Bedrock, None).await.embed_batch(texts).await
+    }
+
+    fn count_tokens(&self, text: &str) -> usize {
+        tokens_by_ratio(text, 3.5) // Claude
+    }
+
+    fn context_window(&self) -> usize {
+        200_000
+    }
+}
+
+/// Models served by Ollama's chat API
+pub struct OllamaChat {
+    model: String,
+    url: String,
+}
+
+impl OllamaChat {
+    /// `url` is the chat endpoint, e.g. `http://localhost:11434/api/chat`
+    pub fn new(model: impl Into<String>, url: impl Into<String>) -> Self {
+        Self { model: model.into(), url: url.into() }
+    }
+
+    /// The endpoint from `OLLAMA_URL`
+    pub fn from_env(model: Option<String>) -> Self {
+        let url = std::env::var("OLLAMA_URL").unwrap_or_else(|_| "http://localhost:11434/api/chat".to_string());
+        Self::new(model.unwrap_or_else(|| "llama3.1".to_string()), url)
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    // Ollama format:
+    // { "model": "llama3", "messages": [ { "role": "user", "content": "..." } ], "stream": false }
+    // The system prompt is just another message, with role "system", at the start
+    fn payload(&self, messages: Vec<Message>, system_prompt: Option<String>, generation: &GenerationConfig, stream: bool) -> Value {
+        let mut ollama_messages = Vec::new();
+        if let Some(prompt) = system_prompt {
+            ollama_messages.push(json!({ "role": "system", "content": prompt }));
+        }
+        for msg in messages {
+            let mut message = json!({ "role": role_name(&msg.role), "content": msg.content });
+            // Multimodal models (llava, llama3.2-vision, ...) take raw base64 images
+            if !msg.images.is_empty() {
+                message["images"] = msg.images.iter().map(|i| i.data.clone()).collect::<Vec<_>>().into();
+            }
+            ollama_messages.push(message);
+        }
+        json!({
+            "model": self.model,
+            "messages": ollama_messages,
+            "stream": stream,
+            "options": generation.to_ollama()
+        })
+    }
+
+    async fn send(&self, payload: &Value) -> Result<reqwest::Response> {
+        let resp = reqwest::Client::new().post(&self.url)
+            .json(payload)
+            .send()
+            .await
+            .map_err(|e| transient(format!("Ollama request error: {}", e)))?;
+        if !resp.status().is_success() {
+            let message = format!("Ollama API error: {}", resp.status());
+            return Err(if is_transient_status(resp.status()) { transient(message) } else { anyhow!(message) });
+        }
+        Ok(resp)
+    }
+}
+
+/// Token counts of an Ollama response: `prompt_eval_count` in, `eval_count` out
+fn ollama_usage(resp_json: &Value) -> Usage {
+    Usage {
+        input_tokens: resp_json.get("prompt_eval_count").and_then(|n| n.as_u64()).unwrap_or(0),
+        output_tokens: resp_json.get("eval_count").and_then(|n| n.as_u64()).unwrap_or(0),
+    }
+}
+
+#[async_trait]
+impl ChatProvider for OllamaChat {
+    fn name(&self) -> &str {
+        "ollama"
+    }
+
+    fn model(&self) -> &str {
+        &self.model
+    }
+
+    async fn chat(&self, messages: Vec<Message>, system_prompt: Option<String>, generation: &GenerationConfig) -> Result<(String, Usage)> {
+        let resp = self.send(&self.payload(messages, system_prompt, generation, false)).await?;
+        let resp_json: Value = resp.json().await
+            .map_err(|e| anyhow!("Failed to parse Ollama response: {}", e))?;
+
+        // Response format: { "message": { "role": "assistant", "content": "..." }, "prompt_eval_count": n, "eval_count": n, ... }
+        resp_json.get("message")
+            .and_then(|m| m.get("content"))
+            .and_then(|c| c.as_str())
+            .map(|content| (content.to_string(), ollama_usage(&resp_json)))
+            .ok_or_else(|| anyhow!("Invalid response format from Ollama"))
+    }
+
+    // One JSON object per line, each with the next piece of `message.content`; the last has
+    // `"done": true` and the token counts
+    async fn chat_stream(
+        &self,
+        messages: Vec<Message>,
+        system_prompt: Option<String>,
+        generation: &GenerationConfig,
+        on_chunk: &OnChunk<'_>,
+    ) -> Result<(String, Usage)> {
+        let resp = self.send(&self.payload(messages, system_prompt, generation, true)).await?;
+        let mut text = String::new();
+        let mut usage = Usage::default();
+        each_line(resp, |line| {
+            if line.is_empty() {
+                return Ok(());
+            }
+            let part: Value = serde_json::from_str(line).map_err(|e| anyhow!("Failed to parse Ollama stream: {}", e))?;
+            if let Some(error) = part.get("error").and_then(|e| e.as_str()) {
+                return Err(anyhow!("Ollama API error: {}", error));
+            }
+            if let Some(piece) = part.get("message").and_then(|m| m.get("content")).and_then(|c| c.as_str()).filter(|p| !p.is_empty()) {
+                on_chunk(piece);
+                text.push_str(piece);
+            }
+            if part.get("done").and_then(|d| d.as_bool()) == Some(true) {
+                usage = ollama_usage(&part);
+            }
+            Ok(())
+        }).await?;
+        Ok((text, usage))
+    }
+
+    async fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+        Embedder::with_model(EmbeddingProvider::Ollama, None).await.embed_batch(texts).await
+    }
+
+    fn count_tokens(&self, text: &str) -> usize {
+        tokens_by_ratio(text, 3.8) // Llama family
+    }
+}
+
+/// The OpenAI chat completions API and endpoints compatible with it, Azure OpenAI included
+pub struct OpenAiChat {
+    model: String,
+    config: OpenAiConfig,
+}
+
+impl OpenAiChat {
+    pub fn new(model: impl Into<String>, config: OpenAiConfig) -> Self {
+        Self { model: model.into(), config }
+    }
+
+    /// Endpoint and credentials from the `OPENAI_*` settings
+    pub fn from_env(model: Option<String>) -> Self {
+        Self::new(model.unwrap_or_else(|| "gpt-4o-mini".to_string()), OpenAiConfig::from_env())
+    }
+
+    pub fn config(&self) -> &OpenAiConfig {
+        &self.config
+    }
+
+    fn payload(&self, messages: Vec<Message>, system_prompt: Option<String>, generation: &GenerationConfig, stream: bool) -> Value {
+        let mut openai_messages = Vec::new();
+        if let Some(prompt) = system_prompt {
+            openai_messages.push(json!({ "role": "system", "content": prompt }));
+        }
+        for msg in messages {
+            let role = role_name(&msg.role);
+            if msg.images.is_empty() {
+                openai_messages.push(json!({ "role": role, "content": msg.content }));
+                continue;
+            }
+            // Images are sent as data URLs alongside the text part
+            let mut parts = vec![json!({ "type": "text", "text": msg.content })];
+            for image in &msg.images {
+                parts.push(json!({
+                    "type": "image_url",
+                    "image_url": { "url": format!("data:{};base64,{}", image.media_type, image.data) }
+                }));
+            }
+            openai_messages.push(json!({ "role": role, "content": parts }));
+        }
+
+        // Azure deployments encode the model in the URL, but sending it is harmless
+        let mut payload = json!({
+            "model": self.model,
+            "messages": openai_messages
+        });
+        if stream {
+            payload["stream"] = true.into();
+        }
+        generation.apply_openai(&mut payload);
+        payload
+    }
+
+    async fn send(&self, payload: &Value) -> Result<reqwest::Response> {
+        let url = format!("{}/chat/completions", self.config.base_url.trim_end_matches('/'));
+        let mut request = reqwest::Client::new().post(&url).json(payload);
+        match (&self.config.api_version, &self.config.api_key) {
+            (Some(version), key) => {
+                request = request.query(&[("api-version", version)]);
+                if let Some(key) = key {
+                    request = request.header("api-key", key);
+                }
+            }
+            (None, Some(key)) => request = request.bearer_auth(key),
+            (None, None) => {}
+        }
+        if let Some(org) = &self.config.organization {
+            request = request.header("OpenAI-Organization", org);
+        }
+
+        let resp = request
+            .send()
+            .await
+            .map_err(|e| transient(format!("OpenAI request error: {}", e)))?;
+        if !resp.status().is_success() {
+            let status = resp.status();
+            let body = resp.text().await.unwrap_or_default();
+            let message = format!("OpenAI API error: {} {}", status, body);
+            return Err(if is_transient_status(status) { transient(message) } else { anyhow!(message) });
+        }
+        Ok(resp)
+    }
+}
+
+/// Token counts of an OpenAI response, from its `usage` object when it has one
+fn openai_usage(resp_json: &Value) -> Usage {
+    resp_json.get("usage").filter(|u| u.is_object()).map(|u| Usage {
+        input_tokens: u.get("prompt_tokens").and_then(|n| n.as_u64()).unwrap_or(0),
+        output_tokens: u.get("completion_tokens").and_then(|n| n.as_u64()).unwrap_or(0),
+    }).unwrap_or_default()
+}
+
+#[async_trait]
+impl ChatProvider for OpenAiChat {
+    fn name(&self) -> &str {
+        "openai"
+    }
+
+    fn model(&self) -> &str {
+        &self.model
+    }
+
+    async fn chat(&self, messages: Vec<Message>, system_prompt: Option<String>, generation: &GenerationConfig) -> Result<(String, Usage)> {
+        let resp = self.send(&self.payload(messages, system_prompt, generation, false)).await?;
+        let resp_json: Value = resp.json().await
+            .map_err(|e| anyhow!("Failed to parse OpenAI response: {}", e))?;
+
+        // Response format: { "choices": [ { "message": { "role": "assistant", "content": "..." } } ],
+        //                   "usage": { "prompt_tokens": n, "completion_tokens": n }, ... }
+        resp_json.get("choices")
+            .and_then(|c| c.get(0))
+            .and_then(|c| c.get("message"))
+            .and_then(|m| m.get("content"))
+            .and_then(|c| c.as_str())
+            .map(|c| (c.to_string(), openai_usage(&resp_json)))
+            .ok_or_else(|| anyhow!("Invalid response format from OpenAI"))
+    }
+
+    // Server-sent events: `data: {...}` lines with the next piece in `choices[0].delta.content`,
+    // ending with `data: [DONE]`
+    async fn chat_stream(
+        &self,
+        messages: Vec<Message>,
+        system_prompt: Option<String>,
+        generation: &GenerationConfig,
+        on_chunk: &OnChunk<'_>,
+    ) -> Result<(String, Usage)> {
+        let resp = self.send(&self.payload(messages, system_prompt, generation, true)).await?;
+        let mut text = String::new();
+        let mut usage = Usage::default();
+        each_line(resp, |line| {
+            let Some(data) = line.strip_prefix("data:").map(str::trim).filter(|d| *d != "[DONE]") else {
+                return Ok(());
+            };
+            let part: Value = serde_json::from_str(data).map_err(|e| anyhow!("Failed to parse OpenAI stream: {}", e))?;
+            let piece = part.pointer("/choices/0/delta/content").and_then(|c| c.as_str()).unwrap_or_default();
+            if !piece.is_empty() {
+                on_chunk(piece);
+                text.push_str(piece);
+            }
+            if part.get("usage").is_some_and(|u| u.is_object()) {
+                usage = openai_usage(&part);
+            }
+            Ok(())
+        }).await?;
+        Ok((text, usage))
+    }
+
+    async fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+        Embedder::with_model(EmbeddingProvider::OpenAi, None).await
+            .with_openai_config(self.config.clone())
+            .embed_batch(texts)
+            .await
+    }
+
+    fn count_tokens(&self, text: &str) -> usize {
+        tokens_by_ratio(text, 4.0) // tiktoken cl100k/o200k
+    }
+
+    fn context_window(&self) -> usize {
+        128_000
+    }
+}
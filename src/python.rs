@@ -0,0 +1,259 @@
+use anyhow::{Result, anyhow};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+use crate::backend::{signature_of, ToolBackend, ToolSignature};
+use crate::command::{drain, join_by, kill_group, own_process_group, PIPE_GRACE};
+use crate::limits::Limits;
+use crate::message::ToolSafetyLevel;
+use crate::safety::SafetyPolicy;
+use tracing::info;
+
+/// Loads the tool module, calls the function named after the file with the request
+/// args and writes a single JSON response. The tool's own prints go to stderr so they
+/// can't corrupt the protocol on stdout.
+///
+/// Before the tool loads, an audit hook refuses the usual ways out: processes, native libraries,
+/// writes outside the working directory, reads outside it and Python's own installation, and
+/// the network unless the last argument is `1`. It guards against mistakes, not against a tool
+/// set on escaping: modules such as `_posixsubprocess` reach the OS without raising an audit
+/// event. So it is no sandbox, and every run of a Python tool is confirmed with the operator.
+const HARNESS: &str = r#"
+import importlib.util, json, os, sys
+path, name, jail, network = sys.argv[1], sys.argv[2], os.path.realpath(sys.argv[3]), sys.argv[4] == "1"
+protocol_out = sys.stdout
+sys.stdout = sys.stderr
+readable = [jail, os.path.realpath(path)] + [os.path.realpath(p) for p in {sys.prefix, sys.base_prefix, sys.exec_prefix, sys.base_exec_prefix}]
+WRITE_FLAGS = os.O_WRONLY | os.O_RDWR | os.O_CREAT | os.O_APPEND | os.O_TRUNC
+BLOCKED = ("os.system", "os.exec", "os.spawn", "os.posix_spawn", "os.fork", "os.forkpty", "subprocess.Popen", "pty.spawn", "ctypes.", "os.kill", "os.killpg")
+CHANGES = ("os.remove", "os.rename", "os.rmdir", "os.mkdir", "os.chmod", "os.chown", "os.link", "os.symlink", "os.truncate", "os.utime", "shutil.")
+NETWORK = ("socket.connect", "socket.bind", "socket.sendto", "socket.sendmsg", "socket.getaddrinfo", "socket.gethostbyname")
+def inside(target, roots):
+    if not isinstance(target, (str, bytes, os.PathLike)):
+        return True
+    real = os.path.realpath(os.fsdecode(target))
+    return any(real == root or real.startswith(root + os.sep) for root in roots)
+def guard(event, args):
+    if event.startswith(BLOCKED):
+        raise PermissionError(f"{event} is not allowed in Python tools")
+    if event.startswith(NETWORK) and not network:
+        raise PermissionError("Python tools have no network access (PYTHON_NETWORK)")
+    if event == "open":
+        target, mode, flags = args
+        writing = any(c in (mode or "") for c in "wax+") or bool((flags or 0) & WRITE_FLAGS)
+        if not inside(target, [jail] if writing else readable):
+            raise PermissionError(f"{target} is outside the workspace")
+    elif event.startswith(CHANGES) and not all(inside(a, [jail]) for a in args):
+        raise PermissionError(f"{event} outside the workspace is not allowed")
+    elif event in ("os.listdir", "os.scandir") and not inside(args[0] or ".", readable):
+        raise PermissionError(f"{args[0]} is outside the workspace")
+try:
+    request = json.loads(sys.stdin.read() or "{}")
+    sys.addaudithook(guard)
+    spec = importlib.util.spec_from_file_location(name, path)
+    module = importlib.util.module_from_spec(spec)
+    spec.loader.exec_module(module)
+    result = getattr(module, name)(*request.get("args", []))
+    try:
+        response = {"result": json.loads(json.dumps(result))}
+    except (TypeError, ValueError):
+        response = {"result": str(result)}
+except Exception as e:
+    response = {"error": f"{type(e).__name__}: {e}"}
+protocol_out.write(json.dumps(response))
+protocol_out.flush()
+"#;
+
+/// Executes `.py` tools in a resource-limited subprocess speaking JSON over stdin/stdout:
+/// request `{"args": [...]}`, response `{"result": ...}` or `{"error": "..."}`
+#[derive(Debug, Clone)]
+pub struct PythonBackend {
+    interpreter: String,
+    timeout: Duration,
+    /// The workspace; the only place a tool may write
+    working_dir: PathBuf,
+    network: bool,
+    /// Address space limit, in MB
+    memory_mb: u64,
+    /// How much of each of stdout and stderr is kept
+    max_output_bytes: usize,
+}
+
+impl PythonBackend {
+    pub fn new(interpreter: impl Into<String>, timeout: Duration, working_dir: impl AsRef<Path>) -> Self {
+        Self {
+            interpreter: interpreter.into(),
+            timeout,
+            working_dir: working_dir.as_ref().to_path_buf(),
+            network: false,
+            memory_mb: 1024,
+            max_output_bytes: Limits::default().max_body_bytes,
+        }
+    }
+
+    /// Let tools open network connections
+    pub fn with_network(mut self, network: bool) -> Self {
+        self.network = network;
+        self
+    }
+
+    pub fn with_memory_mb(mut self, memory_mb: u64) -> Self {
+        self.memory_mb = memory_mb;
+        self
+    }
+
+    pub fn with_max_output_bytes(mut self, max_output_bytes: usize) -> Self {
+        self.max_output_bytes = max_output_bytes;
+        self
+    }
+
+    /// Enabled when `PYTHON_TOOLS` is `true`/`1`; interpreter from `PYTHON_BIN` (default `python3`),
+    /// timeout from `PYTHON_TIMEOUT_SECS` (default 30), network access with `PYTHON_NETWORK`
+    /// (default off) and memory from `PYTHON_MEMORY_MB` (default 1024). Output is kept up to
+    /// `IPC_MAX_BODY_BYTES`, as much as a peer message may carry.
+    pub fn from_env(working_dir: impl AsRef<Path>) -> Option<Self> {
+        let enabled = std::env::var("PYTHON_TOOLS")
+            .map(|v| matches!(v.to_lowercase().as_str(), "1" | "true" | "yes"))
+            .unwrap_or(false);
+        if !enabled {
+            return None;
+        }
+        let interpreter = std::env::var("PYTHON_BIN").unwrap_or_else(|_| "python3".to_string());
+        let timeout = std::env::var("PYTHON_TIMEOUT_SECS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(30);
+        let network = std::env::var("PYTHON_NETWORK")
+            .map(|v| matches!(v.to_lowercase().as_str(), "1" | "true" | "yes"))
+            .unwrap_or(false);
+        let memory_mb = std::env::var("PYTHON_MEMORY_MB").ok().and_then(|v| v.parse().ok()).unwrap_or(1024);
+        Some(Self::new(interpreter, Duration::from_secs(timeout), working_dir).with_network(network).with_memory_mb(memory_mb)
+            .with_max_output_bytes(Limits::from_env().max_body_bytes))
+    }
+}
+
+/// Resource limits for the tool's process: CPU time a little past the timeout, memory, the
+/// size of files it writes, and open files
+#[cfg(unix)]
+fn limit_resources(command: &mut Command, timeout: Duration, memory_mb: u64) {
+    use std::os::unix::process::CommandExt;
+    let limits = [
+        (libc::RLIMIT_CPU, timeout.as_secs() + 1),
+        (libc::RLIMIT_AS, memory_mb * 1024 * 1024),
+        (libc::RLIMIT_FSIZE, 64 * 1024 * 1024),
+        (libc::RLIMIT_NOFILE, 64),
+    ];
+    // Safety: only async-signal-safe setrlimit runs between fork and exec
+    unsafe {
+        command.pre_exec(move || {
+            for (resource, limit) in limits {
+                let limit = libc::rlimit { rlim_cur: limit as libc::rlim_t, rlim_max: limit as libc::rlim_t };
+                if libc::setrlimit(resource, &limit) != 0 {
+                    return Err(std::io::Error::last_os_error());
+                }
+            }
+            Ok(())
+        });
+    }
+}
+
+#[cfg(not(unix))]
+fn limit_resources(_command: &mut Command, _timeout: Duration, _memory_mb: u64) {}
+
+impl ToolBackend for PythonBackend {
+    fn name(&self) -> &str {
+        "python"
+    }
+
+    fn extension(&self) -> &str {
+        "py"
+    }
+
+    /// Python tools are only checked when they run
+    fn compile(&self, _name: &str, _code: &str) -> Result<()> {
+        Ok(())
+    }
+
+    fn execute(&self, name: &str, script: &Path, args: &[String]) -> Result<String> {
+        let script = script.canonicalize()
+            .map_err(|e| anyhow!("Python tool '{}' not found: {}", name, e))?;
+
+        // -I: isolated mode (no user site-packages, PYTHON* env vars or script dir on sys.path);
+        // -B: no bytecode left next to the tools. The environment is cleared apart from PATH.
+        let mut command = Command::new(&self.interpreter);
+        command.arg("-I")
+            .arg("-B")
+            .arg("-c")
+            .arg(HARNESS)
+            .arg(&script)
+            .arg(name)
+            .arg(&self.working_dir)
+            .arg(if self.network { "1" } else { "0" })
+            .current_dir(&self.working_dir)
+            .env_clear()
+            .env("PATH", std::env::var("PATH").unwrap_or_default())
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+        limit_resources(&mut command, self.timeout, self.memory_mb);
+        own_process_group(&mut command);
+        let mut child = command.spawn().map_err(|e| anyhow!("Error starting Python: {}", e))?;
+
+        let request = serde_json::json!({ "args": args });
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin.write_all(request.to_string().as_bytes())?;
+        }
+
+        let (stdout, stdout_reader) = drain(child.stdout.take().unwrap(), self.max_output_bytes);
+        let (stderr, stderr_reader) = drain(child.stderr.take().unwrap(), self.max_output_bytes);
+
+        let started = Instant::now();
+        loop {
+            if child.try_wait()?.is_some() {
+                break;
+            }
+            if started.elapsed() >= self.timeout {
+                kill_group(&mut child);
+                let _ = child.wait();
+                return Err(anyhow!("Python tool '{}' timed out after {:?}", name, self.timeout));
+            }
+            std::thread::sleep(Duration::from_millis(20));
+        }
+        kill_group(&mut child);
+        let deadline = Instant::now() + PIPE_GRACE;
+        join_by(stdout_reader, deadline);
+        join_by(stderr_reader, deadline);
+
+        let (stdout, stderr) = (stdout.lock().unwrap(), stderr.lock().unwrap());
+        // A cut-off response isn't valid JSON; say why rather than failing to parse it
+        if stdout.truncated {
+            return Err(anyhow!("Python tool '{}' answered with more than {} bytes", name, self.max_output_bytes));
+        }
+        let (stdout, stderr) = (stdout.text(), stderr.text());
+        if !stderr.trim().is_empty() {
+            info!("{}: {}", name, stderr.trim());
+        }
+
+        let response: serde_json::Value = serde_json::from_str(stdout.trim())
+            .map_err(|e| anyhow!("Invalid response from Python tool '{}': {} ({})", name, e, stderr.trim()))?;
+        if let Some(error) = response.get("error").and_then(|e| e.as_str()) {
+            return Err(anyhow!("Error executing tool '{}': {}", name, error));
+        }
+        Ok(match response.get("result") {
+            Some(serde_json::Value::String(s)) => s.clone(),
+            Some(serde_json::Value::Null) | None => String::new(),
+            Some(other) => other.to_string(),
+        })
+    }
+
+    fn inspect(&self, name: &str, code: &str) -> ToolSignature {
+        signature_of(code, "def", name)
+    }
+
+    /// Python can reach the OS without any native, so the natives a tool mentions say nothing
+    /// about its risk
+    fn validate(&self, _code: &str, _safety: &SafetyPolicy) -> ToolSafetyLevel {
+        ToolSafetyLevel::HighRisk
+    }
+}
@@ -0,0 +1,158 @@
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use crate::message::ToolSafetyLevel;
+use crate::tools::PendingTool;
+use rhai::Engine;
+use crate::tools::NativeContext;
+
+/// Where rejected and refused tools are kept, inside the tools directory
+pub const QUARANTINE_DIR: &str = ".quarantine";
+
+/// What is known about a quarantined tool: who sent it and why it never got installed
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct QuarantineEntry {
+    /// `<unix ms>-<name>`, the file names of its code and this metadata
+    pub id: String,
+    pub name: String,
+    pub reason: String,
+    /// Where it came from, as `PendingTool::origin` puts it
+    pub origin: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source_address: Option<String>,
+    pub safety_level: ToolSafetyLevel,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// Unix time (seconds) it was quarantined
+    pub quarantined_at: u64,
+}
+
+/// Tools peers tried to install that were rejected or refused, kept for inspection rather than
+/// dropped. Each has its code in `<id>.rhai.quarantined`, an extension nothing loads or commits,
+/// and its metadata in `<id>.json`.
+#[derive(Debug, Clone)]
+pub struct Quarantine {
+    dir: PathBuf,
+}
+
+impl Quarantine {
+    /// The quarantine of the tools in `tools_dir`
+    pub fn new(tools_dir: &Path) -> Self {
+        Self { dir: tools_dir.join(QUARANTINE_DIR) }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Keep `tool` with the `reason` it was turned away
+    pub fn add(&self, tool: &PendingTool, reason: &str) -> Result<QuarantineEntry> {
+        fs::create_dir_all(&self.dir)?;
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+        let mut id = format!("{}-{}", now.as_millis(), tool.name);
+        let mut n = 1;
+        while self.dir.join(format!("{}.json", id)).exists() {
+            n += 1;
+            id = format!("{}-{}-{}", now.as_millis(), tool.name, n);
+        }
+        let entry = QuarantineEntry {
+            id: id.clone(),
+            name: tool.name.clone(),
+            reason: reason.to_string(),
+            origin: tool.origin(),
+            source_address: tool.source_address.clone(),
+            safety_level: tool.safety_level.clone(),
+            description: tool.description.clone(),
+            quarantined_at: now.as_secs(),
+        };
+        fs::write(self.dir.join(format!("{}.rhai.quarantined", id)), &tool.code)?;
+        fs::write(self.dir.join(format!("{}.json", id)), serde_json::to_string_pretty(&entry)?)?;
+        Ok(entry)
+    }
+
+    /// Everything in quarantine, oldest first
+    pub fn list(&self) -> Result<Vec<QuarantineEntry>> {
+        let mut entries = Vec::new();
+        let Ok(files) = fs::read_dir(&self.dir) else {
+            return Ok(entries);
+        };
+        for file in files.flatten() {
+            let path = file.path();
+            if path.extension().and_then(|e| e.to_str()) == Some("json") {
+                entries.push(serde_json::from_str::<QuarantineEntry>(&fs::read_to_string(&path)?)?);
+            }
+        }
+        entries.sort_by(|a, b| (a.quarantined_at, &a.id).cmp(&(b.quarantined_at, &b.id)));
+        Ok(entries)
+    }
+
+    /// The code of quarantined tool `id`
+    pub fn code(&self, id: &str) -> Result<String> {
+        fs::read_to_string(self.dir.join(format!("{}.rhai.quarantined", id)))
+            .map_err(|_| anyhow!("Nothing in quarantine with id '{}'", id))
+    }
+
+    /// Delete the entry `id`, or everything when it is None; returns how many were deleted
+    pub fn purge(&self, id: Option<&str>) -> Result<usize> {
+        let doomed: Vec<QuarantineEntry> = self.list()?.into_iter().filter(|e| id.is_none_or(|id| e.id == id)).collect();
+        if let (Some(id), true) = (id, doomed.is_empty()) {
+            return Err(anyhow!("Nothing in quarantine with id '{}'", id));
+        }
+        for entry in &doomed {
+            let _ = fs::remove_file(self.dir.join(format!("{}.rhai.quarantined", entry.id)));
+            fs::remove_file(self.dir.join(format!("{}.json", entry.id)))?;
+        }
+        Ok(doomed.len())
+    }
+
+    /// One block per quarantined tool, as `list_quarantine` shows them
+    pub fn render(&self) -> Result<String> {
+        let entries = self.list()?;
+        if entries.is_empty() {
+            return Ok("Quarantine is empty".to_string());
+        }
+        let mut output = format!("Quarantined tools ({}):\n", entries.len());
+        for entry in entries {
+            output.push_str(&format!("{} {} (Safety: {:?}) - From: {}\n", entry.id, entry.name, entry.safety_level, entry.origin));
+            output.push_str(&format!("   Reason: {}\n", entry.reason));
+            if let Some(description) = &entry.description {
+                output.push_str(&format!("   Description: {}\n", description));
+            }
+        }
+        output.push_str(&format!("Code is in {}", self.dir.display()));
+        Ok(output)
+    }
+
+    /// Quarantine `tool`, logging rather than failing if it can't be kept
+    pub fn add_or_warn(&self, tool: &PendingTool, reason: &str) {
+        if let Err(e) = self.add(tool, reason) {
+            tracing::warn!("Failed to quarantine tool '{}': {}", tool.name, e);
+        }
+    }
+}
+
+/// `list_quarantine` and `purge_quarantine`
+pub(crate) fn register_quarantine_natives(engine: &mut Engine, ctx: &NativeContext) {
+    let NativeContext { tools_dir, .. } = ctx;
+    // list_quarantine / purge_quarantine: what was turned away, kept for inspection
+    let quarantine = Quarantine::new(tools_dir);
+    let quarantine_clone = quarantine.clone();
+    engine.register_fn("list_quarantine", move || -> String {
+        quarantine_clone.render().unwrap_or_else(|e| format!("Error: {}", e))
+    });
+    let quarantine_clone = quarantine.clone();
+    engine.register_fn("purge_quarantine", move || -> String {
+        match quarantine_clone.purge(None) {
+            Ok(count) => format!("Purged {} tool(s) from quarantine", count),
+            Err(e) => format!("Error: {}", e),
+        }
+    });
+    engine.register_fn("purge_quarantine", move |id: &str| -> String {
+        match quarantine.purge(Some(id)) {
+            Ok(_) => format!("Purged '{}' from quarantine", id),
+            Err(e) => format!("Error: {}", e),
+        }
+    });
+}
@@ -0,0 +1,670 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+use std::net::{Ipv4Addr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::net::UdpSocket;
+use crate::ipc::send_ipc_message;
+use crate::message::IpcMessage;
+use crate::state::StateStore;
+use crate::threads::Direction;
+use tracing::{info, warn};
+use rhai::Engine;
+use crate::encryption::AgentKeys;
+use crate::persona::ToolPolicy;
+use crate::tools::{list_tool_names, NativeContext};
+
+pub(crate) fn unix_now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+/// What an agent announces about itself
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PeerInfo {
+    pub name: String,
+    /// `host:port` of the agent's IPC server
+    pub address: String,
+    #[serde(default)]
+    pub capabilities: Vec<String>,
+    #[serde(default)]
+    pub tools: Vec<String>,
+    /// Unix time (seconds) this peer was last heard from
+    #[serde(default)]
+    pub last_seen: u64,
+    /// Heartbeats in a row this peer failed to answer
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub missed_heartbeats: u32,
+    /// Base64 X25519 key that tool code shared with this peer is encrypted to
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub public_key: Option<String>,
+}
+
+fn is_zero(n: &u32) -> bool {
+    *n == 0
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PeerStatus {
+    Alive,
+    /// Not heard from within the heartbeat timeout
+    Dead,
+}
+
+/// How often to ping known peers and how long silence means a peer is dead
+#[derive(Debug, Clone, Copy)]
+pub struct HeartbeatConfig {
+    pub interval: Duration,
+    pub timeout: Duration,
+}
+
+impl HeartbeatConfig {
+    /// `HEARTBEAT_INTERVAL_SECS` (default 10, 0 disables heartbeats) and
+    /// `HEARTBEAT_TIMEOUT_SECS` (default three intervals)
+    pub fn from_env() -> Option<Self> {
+        let parse = |key: &str| std::env::var(key).ok().and_then(|v| v.parse::<u64>().ok());
+        let interval = parse("HEARTBEAT_INTERVAL_SECS").unwrap_or(10);
+        if interval == 0 {
+            return None;
+        }
+        let timeout = parse("HEARTBEAT_TIMEOUT_SECS").unwrap_or(interval * 3);
+        Some(Self { interval: Duration::from_secs(interval), timeout: Duration::from_secs(timeout) })
+    }
+}
+
+/// Registry key for a peer given as `host:port` or a message URL
+pub fn address_key(address: &str) -> String {
+    address.trim_start_matches("http://")
+        .trim_start_matches("https://")
+        .trim_end_matches("/message")
+        .trim_end_matches('/')
+        .to_string()
+}
+
+/// Peers this agent knows about, keyed by address, and named groups of them
+#[derive(Clone, Default)]
+pub struct PeerRegistry {
+    peers: Arc<Mutex<HashMap<String, PeerInfo>>>,
+    groups: Arc<Mutex<HashMap<String, Vec<String>>>>,
+}
+
+impl PeerRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A registry with the groups in `PEER_GROUPS`, e.g.
+    /// `workers=127.0.0.1:9001,127.0.0.1:9002;reviewers=127.0.0.1:9003`
+    pub fn from_env() -> Self {
+        let registry = Self::new();
+        for group in std::env::var("PEER_GROUPS").unwrap_or_default().split(';') {
+            if let Some((name, members)) = group.split_once('=') {
+                registry.set_group(name.trim(), members.split(',').map(String::from).collect());
+            }
+        }
+        registry
+    }
+
+    /// Name a set of peer addresses; an empty set removes the group
+    pub fn set_group(&self, name: &str, members: Vec<String>) {
+        let members: Vec<String> = members.iter().map(|m| address_key(m.trim())).filter(|m| !m.is_empty()).collect();
+        let mut groups = self.groups.lock().unwrap();
+        if members.is_empty() {
+            groups.remove(name);
+        } else {
+            groups.insert(name.to_string(), members);
+        }
+    }
+
+    /// Group names with their members, sorted by name
+    pub fn groups(&self) -> Vec<(String, Vec<String>)> {
+        let mut groups: Vec<(String, Vec<String>)> = self.groups.lock().unwrap().clone().into_iter().collect();
+        groups.sort();
+        groups
+    }
+
+    /// Addresses `group` stands for: every known peer for `all` or `*`, a named group's
+    /// members, or else the peers advertising `group` as a capability or tool
+    pub fn resolve_group(&self, group: &str) -> Vec<String> {
+        let group = group.trim();
+        if group.is_empty() || group == "all" || group == "*" {
+            return self.list().into_iter().map(|p| p.address).collect();
+        }
+        if let Some(members) = self.groups.lock().unwrap().get(group) {
+            return members.clone();
+        }
+        self.list().into_iter()
+            .filter(|p| p.capabilities.iter().chain(&p.tools).any(|c| c == group))
+            .map(|p| p.address)
+            .collect()
+    }
+
+    /// Add or refresh a peer, stamping it as seen now. Returns true if it was new.
+    pub fn upsert(&self, mut peer: PeerInfo) -> bool {
+        peer.last_seen = unix_now();
+        peer.missed_heartbeats = 0;
+        self.peers.lock().unwrap().insert(peer.address.clone(), peer).is_none()
+    }
+
+    /// Add a peer learned second-hand, keeping the freshest `last_seen`
+    pub fn merge(&self, peer: PeerInfo) {
+        let mut peers = self.peers.lock().unwrap();
+        match peers.get(&peer.address) {
+            Some(known) if known.last_seen >= peer.last_seen => {}
+            _ => {
+                peers.insert(peer.address.clone(), peer);
+            }
+        }
+    }
+
+    /// Mark a known peer as heard from now; returns false for unknown peers
+    pub fn touch(&self, address: &str) -> bool {
+        match self.peers.lock().unwrap().get_mut(&address_key(address)) {
+            Some(peer) => {
+                peer.last_seen = unix_now();
+                peer.missed_heartbeats = 0;
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn record_missed_heartbeat(&self, address: &str) {
+        if let Some(peer) = self.peers.lock().unwrap().get_mut(&address_key(address)) {
+            peer.missed_heartbeats += 1;
+        }
+    }
+
+    /// Liveness of a known peer given the heartbeat `timeout`
+    pub fn status(&self, address: &str, timeout: Duration) -> Option<PeerStatus> {
+        self.get(&address_key(address)).map(|peer| peer_status(&peer, timeout))
+    }
+
+    /// Peers heard from within `timeout`
+    pub fn alive(&self, timeout: Duration) -> Vec<PeerInfo> {
+        self.list().into_iter().filter(|p| peer_status(p, timeout) == PeerStatus::Alive).collect()
+    }
+
+    /// One line per peer with its liveness
+    pub fn render_status(&self, timeout: Duration) -> String {
+        let now = unix_now();
+        self.list().iter()
+            .map(|p| format!("{} @ {}: {} (seen {}s ago, {} missed heartbeats)",
+                p.name,
+                p.address,
+                if peer_status(p, timeout) == PeerStatus::Alive { "alive" } else { "dead" },
+                now.saturating_sub(p.last_seen),
+                p.missed_heartbeats))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    pub fn get(&self, address: &str) -> Option<PeerInfo> {
+        self.peers.lock().unwrap().get(address).cloned()
+    }
+
+    pub fn remove(&self, address: &str) -> Option<PeerInfo> {
+        self.peers.lock().unwrap().remove(address)
+    }
+
+    /// All peers, sorted by name then address
+    pub fn list(&self) -> Vec<PeerInfo> {
+        let mut peers: Vec<PeerInfo> = self.peers.lock().unwrap().values().cloned().collect();
+        peers.sort_by(|a, b| a.name.cmp(&b.name).then_with(|| a.address.cmp(&b.address)));
+        peers
+    }
+
+    /// Forget peers not heard from in `max_age_secs`; returns how many were dropped
+    pub fn prune(&self, max_age_secs: u64) -> usize {
+        let cutoff = unix_now().saturating_sub(max_age_secs);
+        let mut peers = self.peers.lock().unwrap();
+        let before = peers.len();
+        peers.retain(|_, p| p.last_seen >= cutoff);
+        before - peers.len()
+    }
+
+    /// One line per peer with how long ago it was seen
+    pub fn render(&self) -> String {
+        let now = unix_now();
+        self.list().iter()
+            .map(|p| {
+                let mut line = format!("{} @ {} (seen {}s ago)", p.name, p.address, now.saturating_sub(p.last_seen));
+                if !p.capabilities.is_empty() {
+                    line.push_str(&format!(" capabilities: {}", p.capabilities.join(", ")));
+                }
+                if !p.tools.is_empty() {
+                    line.push_str(&format!(" tools: {}", p.tools.join(", ")));
+                }
+                line
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+fn peer_status(peer: &PeerInfo, timeout: Duration) -> PeerStatus {
+    if unix_now().saturating_sub(peer.last_seen) <= timeout.as_secs() {
+        PeerStatus::Alive
+    } else {
+        PeerStatus::Dead
+    }
+}
+
+/// Ping every known peer each `interval`, refreshing the ones that answer and counting
+/// misses for the rest. Runs until the task is dropped.
+pub async fn heartbeat_loop(me: String, registry: PeerRegistry, config: HeartbeatConfig) {
+    let mut ticker = tokio::time::interval(config.interval);
+    ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    let heartbeat = IpcMessage::Heartbeat { address: me.clone() };
+    loop {
+        ticker.tick().await;
+        for peer in registry.list() {
+            let ping = send_ipc_message(&peer.address, &heartbeat, Some(me.clone()));
+            match tokio::time::timeout(config.timeout, ping).await {
+                Ok(Ok(_)) => {
+                    registry.touch(&peer.address);
+                }
+                _ => {
+                    if peer.missed_heartbeats == 0 {
+                        warn!("Peer {} at {} missed a heartbeat", peer.name, peer.address);
+                    }
+                    registry.record_missed_heartbeat(&peer.address);
+                }
+            }
+        }
+    }
+}
+
+const SUBSCRIPTIONS_KEY: &str = "subscriptions";
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct SubscriptionData {
+    /// Topic (or `prefix*` pattern) -> addresses of the peers subscribed to it here
+    #[serde(default)]
+    subscribers: BTreeMap<String, Vec<String>>,
+    /// Peer address -> topics this agent subscribed to there
+    #[serde(default)]
+    subscribed: BTreeMap<String, Vec<String>>,
+}
+
+/// Whether subscription `pattern` covers `topic`; a trailing `*` matches any suffix
+pub fn topic_matches(pattern: &str, topic: &str) -> bool {
+    match pattern.strip_suffix('*') {
+        Some(prefix) => topic.starts_with(prefix),
+        None => pattern == topic,
+    }
+}
+
+/// Pub/sub state, persisted in the agent state store: which peers subscribed to topics
+/// here, and which topics this agent subscribed to on peers
+#[derive(Debug, Clone)]
+pub struct Subscriptions {
+    store: StateStore,
+}
+
+impl Subscriptions {
+    pub fn new(store: StateStore) -> Self {
+        Self { store }
+    }
+
+    /// Send `topic` events to `address`; false if it was already subscribed
+    pub fn add_subscriber(&self, topic: &str, address: &str) -> Result<bool> {
+        let address = address_key(address);
+        self.store.update(SUBSCRIPTIONS_KEY, |data: &mut SubscriptionData| {
+            let subscribers = data.subscribers.entry(topic.to_string()).or_default();
+            if subscribers.contains(&address) {
+                return false;
+            }
+            subscribers.push(address);
+            true
+        })
+    }
+
+    /// False if `address` wasn't subscribed to `topic`
+    pub fn remove_subscriber(&self, topic: &str, address: &str) -> Result<bool> {
+        let address = address_key(address);
+        self.store.update(SUBSCRIPTIONS_KEY, |data: &mut SubscriptionData| {
+            let Some(subscribers) = data.subscribers.get_mut(topic) else {
+                return false;
+            };
+            let before = subscribers.len();
+            subscribers.retain(|a| *a != address);
+            let removed = subscribers.len() != before;
+            if subscribers.is_empty() {
+                data.subscribers.remove(topic);
+            }
+            removed
+        })
+    }
+
+    /// Everyone an event on `topic` goes to, each address once
+    pub fn subscribers(&self, topic: &str) -> Result<Vec<String>> {
+        let data = self.store.get::<SubscriptionData>(SUBSCRIPTIONS_KEY)?.unwrap_or_default();
+        let mut addresses: Vec<String> = data.subscribers.iter()
+            .filter(|(pattern, _)| topic_matches(pattern, topic))
+            .flat_map(|(_, addresses)| addresses.iter().cloned())
+            .collect();
+        addresses.sort();
+        addresses.dedup();
+        Ok(addresses)
+    }
+
+    /// Remember that this agent subscribed (or unsubscribed) to `topic` on `peer`
+    pub fn record_subscribed(&self, peer: &str, topic: &str, subscribed: bool) -> Result<()> {
+        let peer = address_key(peer);
+        self.store.update(SUBSCRIPTIONS_KEY, |data: &mut SubscriptionData| {
+            let topics = data.subscribed.entry(peer.clone()).or_default();
+            topics.retain(|t| t != topic);
+            if subscribed {
+                topics.push(topic.to_string());
+            }
+            if topics.is_empty() {
+                data.subscribed.remove(&peer);
+            }
+        })
+    }
+
+    /// Topics this agent subscribed to, per peer
+    pub fn subscribed(&self) -> Result<Vec<(String, Vec<String>)>> {
+        let data = self.store.get::<SubscriptionData>(SUBSCRIPTIONS_KEY)?.unwrap_or_default();
+        Ok(data.subscribed.into_iter().collect())
+    }
+
+    /// Our subscribers per topic, then our own subscriptions per peer
+    pub fn render(&self) -> Result<String> {
+        let data = self.store.get::<SubscriptionData>(SUBSCRIPTIONS_KEY)?.unwrap_or_default();
+        let mut lines = Vec::new();
+        for (topic, addresses) in &data.subscribers {
+            lines.push(format!("'{}' -> {}", topic, addresses.join(", ")));
+        }
+        for (peer, topics) in &data.subscribed {
+            lines.push(format!("subscribed on {}: {}", peer, topics.join(", ")));
+        }
+        Ok(lines.join("\n"))
+    }
+}
+
+/// What happened to a broadcast at one peer
+#[derive(Debug, Clone, PartialEq)]
+pub struct Delivery {
+    pub peer: String,
+    /// The peer's reply, or why it couldn't be reached
+    pub outcome: std::result::Result<String, String>,
+}
+
+/// Per-peer outcomes of a `broadcast`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct BroadcastReport {
+    pub deliveries: Vec<Delivery>,
+}
+
+impl BroadcastReport {
+    pub fn delivered(&self) -> usize {
+        self.deliveries.iter().filter(|d| d.outcome.is_ok()).count()
+    }
+
+    pub fn failed(&self) -> Vec<&Delivery> {
+        self.deliveries.iter().filter(|d| d.outcome.is_err()).collect()
+    }
+
+    /// A summary line, then one line per peer
+    pub fn render(&self) -> String {
+        let mut lines = vec![format!("Delivered to {}/{} peer(s)", self.delivered(), self.deliveries.len())];
+        for delivery in &self.deliveries {
+            lines.push(match &delivery.outcome {
+                Ok(reply) => format!("✅ {}: {}", delivery.peer, reply),
+                Err(error) => format!("❌ {}: {}", delivery.peer, error),
+            });
+        }
+        lines.join("\n")
+    }
+}
+
+/// Send `message` to every address in `peers` at once, waiting up to `timeout` for each
+pub async fn broadcast(peers: &[String], message: &IpcMessage, from: Option<String>, timeout: Duration) -> BroadcastReport {
+    let sends = peers.iter().map(|peer| {
+        let from = from.clone();
+        async move {
+            let outcome = match tokio::time::timeout(timeout, send_ipc_message(peer, message, from)).await {
+                Ok(Ok(reply)) => Ok(reply),
+                Ok(Err(e)) => Err(e.to_string()),
+                Err(_) => Err(format!("no answer within {:?}", timeout)),
+            };
+            Delivery { peer: address_key(peer), outcome }
+        }
+    });
+    BroadcastReport { deliveries: futures_util::future::join_all(sends).await }
+}
+
+/// Announce `me` to a registry agent and merge the peers it knows into `registry`.
+/// Returns how many peers the registry reported (excluding ourselves).
+pub async fn announce_to(registry_address: &str, me: &PeerInfo, registry: &PeerRegistry) -> Result<usize> {
+    let reply = send_ipc_message(registry_address, &IpcMessage::Announce(me.clone()), Some(me.address.clone())).await?;
+    let peers: Vec<PeerInfo> = serde_json::from_str(&reply)?;
+    let mut count = 0;
+    for peer in peers.into_iter().filter(|p| p.address != me.address) {
+        registry.merge(peer);
+        count += 1;
+    }
+    Ok(count)
+}
+
+/// UDP socket for broadcast discovery; address reuse lets several agents on one host share the port
+fn discovery_socket(port: u16) -> Result<UdpSocket> {
+    let socket = socket2::Socket::new(socket2::Domain::IPV4, socket2::Type::DGRAM, Some(socket2::Protocol::UDP))?;
+    socket.set_reuse_address(true)?;
+    #[cfg(unix)]
+    socket.set_reuse_port(true)?;
+    socket.set_broadcast(true)?;
+    socket.set_nonblocking(true)?;
+    socket.bind(&SocketAddr::from((Ipv4Addr::UNSPECIFIED, port)).into())?;
+    Ok(UdpSocket::from_std(socket.into())?)
+}
+
+/// Broadcast `me` on the local network
+pub async fn broadcast_announce(port: u16, me: &PeerInfo) -> Result<()> {
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).await?;
+    socket.set_broadcast(true)?;
+    socket.send_to(&serde_json::to_vec(me)?, (Ipv4Addr::BROADCAST, port)).await?;
+    Ok(())
+}
+
+/// Listen for UDP announcements on `port`, recording peers and announcing ourselves to
+/// newcomers over IPC so discovery is mutual. Runs until the task is dropped.
+pub async fn listen_for_peers(port: u16, me: PeerInfo, registry: PeerRegistry) -> Result<()> {
+    let socket = discovery_socket(port)?;
+    let mut buf = vec![0u8; 64 * 1024];
+    loop {
+        let (len, from) = socket.recv_from(&mut buf).await?;
+        let Ok(peer) = serde_json::from_slice::<PeerInfo>(&buf[..len]) else {
+            continue;
+        };
+        if peer.address == me.address {
+            continue;
+        }
+        if registry.upsert(peer.clone()) {
+            info!("Discovered peer {} at {} (via {})", peer.name, peer.address, from);
+            if let Err(e) = announce_to(&peer.address, &me, &registry).await {
+                warn!("Failed to answer peer {}: {}", peer.address, e);
+            }
+        }
+    }
+}
+
+/// How this agent describes itself to peers: `AGENT_NAME`, its server address, allowed
+/// capabilities and tools, and the key to encrypt tool code for it to
+pub(crate) fn local_peer_info(address: &str, tools_dir: &Path, policy: &ToolPolicy, keys: &AgentKeys) -> PeerInfo {
+    PeerInfo {
+        name: std::env::var("AGENT_NAME").unwrap_or_else(|_| "Swarm Thing".to_string()),
+        address: address.to_string(),
+        capabilities: policy.capability_names(),
+        tools: list_tool_names(tools_dir),
+        last_seen: 0,
+        missed_heartbeats: 0,
+        public_key: Some(keys.public_key_base64()),
+    }
+}
+
+/// Peer discovery and health, groups, broadcasts and pub/sub
+pub(crate) fn register_peer_natives(engine: &mut Engine, ctx: &NativeContext) -> Result<()> {
+    let NativeContext { tools_dir, policy, meter, threads, peers, keys, local_address, .. } = ctx;
+    let heartbeat = ctx.heartbeat;
+    // Peer health: without heartbeats, peers count as alive for 30s after they were last heard from
+    let status_timeout = heartbeat.map(|h| h.timeout).unwrap_or(std::time::Duration::from_secs(30));
+    let peers_clone = peers.clone();
+    engine.register_fn("peer_status", move || -> String {
+        let status = peers_clone.render_status(status_timeout);
+        if status.is_empty() { "No peers known yet".to_string() } else { status }
+    });
+
+    let peers_clone = peers.clone();
+    engine.register_fn("peer_status", move |peer: &str| -> String {
+        match peers_clone.status(peer, status_timeout) {
+            Some(PeerStatus::Alive) => format!("{} is alive", address_key(peer)),
+            Some(PeerStatus::Dead) => format!("{} is dead", address_key(peer)),
+            None => format!("{} is not a known peer", address_key(peer)),
+        }
+    });
+
+    // Peer discovery
+    let peers_clone = peers.clone();
+    engine.register_fn("list_peers", move || -> String {
+        let peers = peers_clone.render();
+        if peers.is_empty() { "No peers known yet".to_string() } else { peers }
+    });
+
+    // Broadcast: one message to every peer in a group, with a per-peer report
+    let peers_clone = peers.clone();
+    let threads_clone = threads.clone();
+    let address_clone = local_address.clone();
+    let meter_clone = meter.clone();
+    engine.register_fn("broadcast_message", move |group: &str, content: &str| -> String {
+        let targets = peers_clone.resolve_group(group);
+        if targets.is_empty() {
+            return format!("Error: no peers in group '{}'", group);
+        }
+        if let Err(e) = meter_clone("broadcast_message") {
+            return format!("Error: {}", e);
+        }
+        info!("Broadcasting to {} peer(s) in '{}'", targets.len(), group);
+        let from = address_clone.lock().unwrap().clone();
+        // Structured messages (a ToolShare, say) go out as they are
+        let message = IpcMessage::from_json_or_text(content);
+        let threads = threads_clone.clone();
+        std::thread::spawn(move || {
+            let rt = tokio::runtime::Runtime::new().unwrap();
+            rt.block_on(async {
+                let report = crate::registry::broadcast(&targets, &message, from, std::time::Duration::from_secs(10)).await;
+                if let IpcMessage::Text { content, thread_id } = &message {
+                    for delivery in report.deliveries.iter().filter(|d| d.outcome.is_ok()) {
+                        if let Err(e) = threads.record_in(&delivery.peer, thread_id.as_deref(), Direction::Outgoing, content) {
+                            warn!("Failed to record message to {}: {}", delivery.peer, e);
+                        }
+                    }
+                }
+                report.render()
+            })
+        }).join().unwrap_or_else(|_| "Thread panic".to_string())
+    });
+
+    // Pub/sub: subscribe to a peer's topics, publish events to our own subscribers
+    let subscriptions = Subscriptions::new(StateStore::from_env()?);
+    let change_subscription = {
+        let subscriptions = subscriptions.clone();
+        let local_address = local_address.clone();
+        move |url: &str, topic: &str, subscribe: bool| -> String {
+            let Some(address) = local_address.lock().unwrap().clone() else {
+                return "Error: start_server first so events can reach this agent".to_string();
+            };
+            let message = if subscribe {
+                IpcMessage::Subscribe { topic: topic.to_string(), address: address.clone() }
+            } else {
+                IpcMessage::Unsubscribe { topic: topic.to_string(), address: address.clone() }
+            };
+            let peer = url.to_string();
+            let sent = std::thread::spawn(move || {
+                let rt = tokio::runtime::Runtime::new().unwrap();
+                rt.block_on(crate::ipc::send_ipc_message(&peer, &message, Some(address)))
+            }).join();
+            match sent {
+                Ok(Ok(received)) => {
+                    if let Err(e) = subscriptions.record_subscribed(url, topic, subscribe) {
+                        warn!("Failed to record subscription: {}", e);
+                    }
+                    received
+                }
+                Ok(Err(e)) => format!("Error contacting {}: {}", url, e),
+                Err(_) => "Thread panic".to_string(),
+            }
+        }
+    };
+    let unsubscribe = change_subscription.clone();
+    engine.register_fn("subscribe", move |url: &str, topic: &str| -> String {
+        change_subscription(url, topic, true)
+    });
+    engine.register_fn("unsubscribe", move |url: &str, topic: &str| -> String {
+        unsubscribe(url, topic, false)
+    });
+
+    let subscriptions_clone = subscriptions.clone();
+    let address_clone = local_address.clone();
+    engine.register_fn("publish", move |topic: &str, payload: &str| -> String {
+        let targets = match subscriptions_clone.subscribers(topic) {
+            Ok(targets) if targets.is_empty() => return format!("No subscribers to '{}'", topic),
+            Ok(targets) => targets,
+            Err(e) => return format!("Error reading subscriptions: {}", e),
+        };
+        info!("Publishing on '{}' to {} subscriber(s)", topic, targets.len());
+        let from = address_clone.lock().unwrap().clone();
+        let message = IpcMessage::Event { topic: topic.to_string(), payload: payload.to_string() };
+        std::thread::spawn(move || {
+            let rt = tokio::runtime::Runtime::new().unwrap();
+            rt.block_on(async {
+                crate::registry::broadcast(&targets, &message, from, std::time::Duration::from_secs(10)).await.render()
+            })
+        }).join().unwrap_or_else(|_| "Thread panic".to_string())
+    });
+
+    let subscriptions_clone = subscriptions.clone();
+    engine.register_fn("subscriptions", move || -> String {
+        match subscriptions_clone.render() {
+            Ok(rendered) if rendered.is_empty() => "No subscriptions".to_string(),
+            Ok(rendered) => rendered,
+            Err(e) => format!("Error reading subscriptions: {}", e),
+        }
+    });
+
+    let peers_clone = peers.clone();
+    engine.register_fn("set_peer_group", move |name: &str, members: &str| -> String {
+        peers_clone.set_group(name, members.split(',').map(String::from).collect());
+        match peers_clone.groups().into_iter().find(|(group, _)| group == name) {
+            Some((_, members)) => format!("Group '{}': {}", name, members.join(", ")),
+            None => format!("Group '{}' removed", name),
+        }
+    });
+
+    let peers_clone = peers.clone();
+    let policy_clone = policy.clone();
+    let tools_dir_clone = tools_dir.clone();
+    let address_clone = local_address.clone();
+    let keys_clone = keys.clone();
+    engine.register_fn("announce", move |registry: &str| -> String {
+        let Some(address) = address_clone.lock().unwrap().clone() else {
+            return "Error: start_server first so peers can reach this agent".to_string();
+        };
+        let me = local_peer_info(&address, &tools_dir_clone, &policy_clone.lock().unwrap(), &keys_clone);
+        let registry = registry.to_string();
+        let peers = peers_clone.clone();
+        std::thread::spawn(move || {
+            let rt = tokio::runtime::Runtime::new().unwrap();
+            rt.block_on(async {
+                match crate::registry::announce_to(&registry, &me, &peers).await {
+                    Ok(count) => format!("Announced to {}; it knows {} other peer(s)", registry, count),
+                    Err(e) => format!("Error announcing to {}: {}", registry, e),
+                }
+            })
+        }).join().unwrap_or_else(|_| "Thread panic".to_string())
+    });
+    Ok(())
+}
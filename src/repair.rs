@@ -0,0 +1,87 @@
+use std::time::Instant;
+use crate::agent::Agent;
+use crate::journal::{millis, JournalEvent};
+use crate::parser::{tool_blocks, ToolBlock};
+use crate::tools::{BrokenTool, ResponseAction, ToolManager};
+use tracing::{info, warn};
+
+/// What to tell the model about `broken`, with its code, to get a fixed version
+fn fix_request(broken: &BrokenTool) -> String {
+    let failure = match &broken.args {
+        Some(args) => format!("failed when called as {}({})", broken.name, args.join(", ")),
+        None => "does not compile".to_string(),
+    };
+    let language = if broken.python { "python" } else { "rhai" };
+    format!(
+        "The tool '{}' {}:\n{}\n\nIts code:\n```{}\n{}\n```\nWrite the whole tool '{}' again with the error fixed.",
+        broken.name, failure, broken.error, language, broken.code.trim_end(), broken.name
+    )
+}
+
+/// The fixed code in a reply: the block for the tool, or the only block when it goes unnamed
+fn fixed_block(response: &str, broken: &BrokenTool) -> Option<ToolBlock> {
+    let mut blocks: Vec<ToolBlock> = tool_blocks(response).into_iter().filter(|b| b.python == broken.python).collect();
+    let index = blocks.iter().position(|b| b.name == broken.name).or((blocks.len() == 1).then_some(0))?;
+    let mut block = blocks.swap_remove(index);
+    block.name = broken.name.clone();
+    Some(block)
+}
+
+/// Give the model each tool in `actions` whose code failed (see `ToolManager::broken_tool`),
+/// with the error, and save its fix, up to `attempts` times per tool. A call that failed is
+/// run again once its tool is fixed. Returns `actions` with each repaired action replaced by
+/// how its last attempt went; every attempt is journaled.
+pub async fn repair_tools(agent: &mut Agent, tools: &mut ToolManager, actions: Vec<ResponseAction>, attempts: usize) -> Vec<ResponseAction> {
+    let mut repaired = Vec::new();
+    for mut action in actions {
+        // The call to run again once the tool works
+        let call = match &action {
+            ResponseAction::Ran { args, .. } => Some(args.clone()),
+            ResponseAction::Created { .. } => None,
+        };
+        for attempt in 1..=attempts {
+            let Some(broken) = tools.broken_tool(&action) else { break };
+            info!("Asking for a fix to '{}' (attempt {}/{}): {}", broken.name, attempt, attempts, broken.error);
+            let journal = agent.journal().cloned();
+            let response = match agent.chat(&fix_request(&broken)).await {
+                Ok(response) => response,
+                Err(e) => {
+                    warn!("Could not get a fix for '{}': {}", broken.name, e);
+                    break;
+                }
+            };
+            let outcome = match fixed_block(&response, &broken) {
+                None => "no code for the tool in the reply".to_string(),
+                Some(block) => {
+                    let created = tools.create_block(block, journal.as_ref());
+                    match (&created, &call) {
+                        (ResponseAction::Created { outcome: Err(e), .. }, _) => {
+                            let outcome = format!("fix failed: {}", e);
+                            action = created;
+                            outcome
+                        }
+                        (_, Some(args)) => {
+                            let started = Instant::now();
+                            let result = tools.execute_tool(&broken.name, args.clone()).map_err(|e| e.to_string());
+                            let outcome = match &result {
+                                Ok(output) => format!("fixed: {}", output),
+                                Err(e) => format!("still failing: {}", e),
+                            };
+                            action = ResponseAction::Ran { name: broken.name.clone(), args: args.clone(), result, duration_ms: millis(started.elapsed()) };
+                            outcome
+                        }
+                        (_, None) => {
+                            action = created;
+                            "fixed".to_string()
+                        }
+                    }
+                }
+            };
+            if let Some(journal) = &journal {
+                journal.record_or_warn(JournalEvent::ToolFix { name: broken.name, attempt, error: broken.error, outcome });
+            }
+        }
+        repaired.push(action);
+    }
+    repaired
+}
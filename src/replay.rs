@@ -0,0 +1,198 @@
+use anyhow::{anyhow, Result};
+use std::collections::VecDeque;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use crate::agent::Agent;
+use crate::config::Config;
+use crate::journal::{journal_dir, JournalEvent, Replay};
+use crate::llm::{Completion, LlmClient, Usage};
+use crate::parser::{tool_blocks, tool_calls};
+use crate::tools::{ResponseAction, ToolManager};
+
+/// Model responses recorded in a journal, handed out in order by a replaying `LlmClient`
+#[derive(Debug, Clone, Default)]
+pub struct RecordedResponses {
+    queue: Arc<Mutex<VecDeque<Result<Completion, String>>>>,
+}
+
+impl RecordedResponses {
+    pub fn new(responses: Vec<Result<Completion, String>>) -> Self {
+        Self { queue: Arc::new(Mutex::new(responses.into())) }
+    }
+
+    /// The responses and errors `replay` recorded, in order
+    pub fn from_replay(replay: &Replay) -> Self {
+        Self::new(replay.entries.iter()
+            .filter_map(|entry| match &entry.event {
+                JournalEvent::LlmResponse { model, text, input_tokens, output_tokens, cached, .. } => Some(Ok(Completion {
+                    text: text.clone(),
+                    usage: Usage { input_tokens: *input_tokens, output_tokens: *output_tokens },
+                    model: model.clone(),
+                    cached: *cached,
+                })),
+                JournalEvent::LlmError { error, .. } => Some(Err(error.clone())),
+                _ => None,
+            })
+            .collect())
+    }
+
+    /// The next recorded response, or its recorded error
+    pub fn next(&self) -> Result<Completion> {
+        match self.queue.lock().unwrap().pop_front() {
+            Some(Ok(completion)) => Ok(completion),
+            Some(Err(error)) => Err(anyhow!(error)),
+            None => Err(anyhow!("The replay ran out of recorded responses")),
+        }
+    }
+
+    pub fn remaining(&self) -> usize {
+        self.queue.lock().unwrap().len()
+    }
+}
+
+/// A step that went differently in the replay; `None` on one side for a missing or extra step
+#[derive(Debug, Clone, PartialEq)]
+pub struct Divergence {
+    pub turn: u64,
+    pub expected: Option<String>,
+    pub actual: Option<String>,
+}
+
+impl Divergence {
+    pub fn render(&self) -> String {
+        match (&self.expected, &self.actual) {
+            (Some(expected), Some(actual)) => format!("turn {}: expected `{}`, got `{}`", self.turn, expected, actual),
+            (Some(expected), None) => format!("turn {}: missing `{}`", self.turn, expected),
+            (None, Some(actual)) => format!("turn {}: extra `{}`", self.turn, actual),
+            (None, None) => format!("turn {}: no difference", self.turn),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ReplayReport {
+    pub session: String,
+    pub turns: u64,
+    /// Steps compared
+    pub steps: usize,
+    pub divergences: Vec<Divergence>,
+    /// Recorded responses the replay never asked for
+    pub unused_responses: usize,
+}
+
+impl ReplayReport {
+    /// Whether the replay did exactly what the recording did
+    pub fn is_faithful(&self) -> bool {
+        self.divergences.is_empty() && self.unused_responses == 0
+    }
+
+    pub fn render(&self) -> String {
+        let mut out = format!("Replayed {}: {} turns, {} steps", self.session, self.turns, self.steps);
+        if self.is_faithful() {
+            out.push_str(", no divergences");
+            return out;
+        }
+        for divergence in &self.divergences {
+            out.push_str(&format!("\n{}", divergence.render()));
+        }
+        if self.unused_responses > 0 {
+            out.push_str(&format!("\n{} recorded responses were never requested", self.unused_responses));
+        }
+        out
+    }
+}
+
+/// A journal given as a file path, a session id in `AGENT_JOURNAL_DIR`, or `latest`
+pub fn load_replay(target: &str) -> Result<Replay> {
+    if Path::new(target).is_file() {
+        Replay::load(target)
+    } else {
+        Replay::find(journal_dir(), target)
+    }
+}
+
+/// The comparable steps recorded for `turn`. Without `execute` only the tools written and the
+/// calls made are compared, not what running them gave.
+fn recorded_steps(replay: &Replay, turn: u64, execute: bool) -> Vec<String> {
+    replay.turn(turn).into_iter()
+        .filter_map(|entry| match &entry.event {
+            JournalEvent::LlmError { error, .. } => Some(format!("llm error: {}", error)),
+            JournalEvent::ToolCreated { name, outcome } if execute => Some(format!("created {}: {}", name, outcome)),
+            JournalEvent::ToolCreated { name, .. } => Some(format!("created {}", name)),
+            JournalEvent::ToolCall { name, args } => Some(format!("call {}({})", name, args.join(", "))),
+            JournalEvent::ToolResult { name, output, ok, .. } if execute => {
+                Some(format!("result {} {}: {}", name, if *ok { "ok" } else { "error" }, output))
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+/// The steps the replay took for `response`: with `execute`, saving and running tools as the
+/// REPL does; without, only parsing them
+fn replayed_steps(tools: &mut ToolManager, response: &str, execute: bool) -> Vec<String> {
+    if !execute {
+        let mut steps: Vec<String> = tool_blocks(response).into_iter().map(|block| format!("created {}", block.name)).collect();
+        for (name, args) in tool_calls(response) {
+            steps.push(format!("call {}({})", name, args.join(", ")));
+        }
+        return steps;
+    }
+    let mut steps = Vec::new();
+    for action in tools.act_on(response, None) {
+        match action {
+            ResponseAction::Created { name, outcome } => {
+                steps.push(format!("created {}: {}", name, outcome.unwrap_or_else(|e| format!("Error: {}", e))));
+            }
+            ResponseAction::Ran { name, args, result, .. } => {
+                steps.push(format!("call {}({})", name, args.join(", ")));
+                let (status, output) = match result {
+                    Ok(output) => ("ok", output),
+                    Err(e) => ("error", e),
+                };
+                steps.push(format!("result {} {}: {}", name, status, output));
+            }
+        }
+    }
+    steps
+}
+
+/// Re-run the session `replay` recorded, feeding the agent the recorded model responses
+/// instead of calling a provider, and compare each turn's tool steps with the recording.
+/// With `execute`, tools are saved and run for real through `tools`; without, only parsed.
+/// Responses are read with the action protocol the current config sets for the recorded model.
+pub async fn replay_session(replay: &Replay, tools: &mut ToolManager, execute: bool) -> Result<ReplayReport> {
+    let responses = RecordedResponses::from_replay(replay);
+    let mut agent = Agent::with_client(LlmClient::replaying(responses.clone()).await?, "");
+    let model = replay.entries.iter().find_map(|entry| match &entry.event {
+        JournalEvent::LlmRequest { model, .. } => Some(model.clone()),
+        _ => None,
+    });
+    if let Some(model) = model {
+        agent.set_protocol(Config::from_env().map(|c| c.llm.protocol_for(&model)).unwrap_or_default());
+    }
+    let mut report = ReplayReport { session: replay.session.clone(), turns: replay.turns(), ..ReplayReport::default() };
+
+    for turn in 1..=report.turns {
+        let Some(input) = replay.turn(turn).into_iter().find_map(|entry| match &entry.event {
+            JournalEvent::Input { text } => Some(text.clone()),
+            _ => None,
+        }) else {
+            continue;
+        };
+        let expected = recorded_steps(replay, turn, execute);
+        let actual = match agent.chat(&input).await {
+            Ok(response) => replayed_steps(tools, &response, execute),
+            Err(e) => vec![format!("llm error: {}", e)],
+        };
+        report.steps += expected.len().max(actual.len());
+        for i in 0..expected.len().max(actual.len()) {
+            let (expected, actual) = (expected.get(i).cloned(), actual.get(i).cloned());
+            if expected != actual {
+                report.divergences.push(Divergence { turn, expected, actual });
+            }
+        }
+    }
+    report.unused_responses = responses.remaining();
+    Ok(report)
+}
@@ -0,0 +1,406 @@
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Mutex;
+use crate::citations::Citations;
+use crate::pdf::{write_pdf, PdfLine};
+use rhai::Engine;
+use crate::tools::NativeContext;
+
+// Serializes read-modify-write cycles of reports in this process (tools may run in parallel)
+static WRITE_LOCK: Mutex<()> = Mutex::new(());
+
+/// Title of a report nobody named
+pub const DEFAULT_TITLE: &str = "Research Report";
+
+/// One finding of a section, and the source it came from
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Finding {
+    pub content: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Section {
+    pub title: String,
+    pub findings: Vec<Finding>,
+}
+
+/// Findings gathered into sections, in the order the sections were first added to
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Report {
+    #[serde(default)]
+    pub title: Option<String>,
+    #[serde(default)]
+    pub sections: Vec<Section>,
+    /// Text added after a source in the source list, as `cite` fills it in
+    #[serde(skip)]
+    pub footnotes: HashMap<String, String>,
+}
+
+/// What `report_export` can produce
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReportFormat {
+    Markdown,
+    Html,
+    Pdf,
+}
+
+impl FromStr for ReportFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().trim_start_matches('.').to_lowercase().as_str() {
+            "markdown" | "md" => Ok(ReportFormat::Markdown),
+            "html" | "htm" => Ok(ReportFormat::Html),
+            "pdf" => Ok(ReportFormat::Pdf),
+            other => Err(anyhow!("Unknown report format '{}'; use markdown, html or pdf", other)),
+        }
+    }
+}
+
+impl ReportFormat {
+    pub fn name(self) -> &'static str {
+        match self {
+            ReportFormat::Markdown => "markdown",
+            ReportFormat::Html => "html",
+            ReportFormat::Pdf => "pdf",
+        }
+    }
+
+    /// File extensions a report of this format may be saved with, the usual one first
+    pub fn extensions(self) -> &'static [&'static str] {
+        match self {
+            ReportFormat::Markdown => &["md", "markdown"],
+            ReportFormat::Html => &["html", "htm"],
+            ReportFormat::Pdf => &["pdf"],
+        }
+    }
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+impl Report {
+    pub fn title(&self) -> &str {
+        self.title.as_deref().unwrap_or(DEFAULT_TITLE)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sections.is_empty()
+    }
+
+    /// Add `content` to `section`, creating the section after the others if it's new
+    pub fn add(&mut self, section: &str, content: &str, source: Option<&str>) {
+        let finding = Finding {
+            content: content.trim().to_string(),
+            source: source.map(str::trim).filter(|s| !s.is_empty()).map(String::from),
+        };
+        let section = section.trim();
+        match self.sections.iter_mut().find(|s| s.title.eq_ignore_ascii_case(section)) {
+            Some(existing) => existing.findings.push(finding),
+            None => self.sections.push(Section { title: section.to_string(), findings: vec![finding] }),
+        }
+    }
+
+    /// The cited sources, each once, numbered in the order they are first cited
+    pub fn sources(&self) -> Vec<&str> {
+        let mut sources: Vec<&str> = Vec::new();
+        for source in self.sections.iter().flat_map(|s| &s.findings).filter_map(|f| f.source.as_deref()) {
+            if !sources.contains(&source) {
+                sources.push(source);
+            }
+        }
+        sources
+    }
+
+    /// Attribute findings without a source to the session source they quote, and footnote
+    /// every source the session read with when and how it was read
+    pub fn cite(&mut self, citations: &Citations) {
+        for finding in self.sections.iter_mut().flat_map(|s| &mut s.findings) {
+            if finding.source.is_none() {
+                finding.source = citations.attribute(&finding.content);
+            }
+        }
+        let footnotes: Vec<(String, String)> = self.sources().into_iter()
+            .filter_map(|source| citations.get(source).map(|c| (source.to_string(), c.footnote())))
+            .collect();
+        self.footnotes.extend(footnotes);
+    }
+
+    /// `source` with its footnote, if it has one
+    fn source_entry(&self, source: &str) -> String {
+        match self.footnotes.get(source) {
+            Some(note) => format!("{}, {}", source, note),
+            None => source.to_string(),
+        }
+    }
+
+    /// The 1-based citation number of `finding`, if it has a source
+    fn citation(&self, sources: &[&str], finding: &Finding) -> Option<usize> {
+        let source = finding.source.as_deref()?;
+        sources.iter().position(|s| *s == source).map(|i| i + 1)
+    }
+
+    pub fn to_markdown(&self) -> String {
+        let sources = self.sources();
+        let mut out = format!("# {}\n", self.title());
+        for section in &self.sections {
+            out.push_str(&format!("\n## {}\n", section.title));
+            for finding in &section.findings {
+                match self.citation(&sources, finding) {
+                    Some(n) => out.push_str(&format!("\n{} [{}]\n", finding.content, n)),
+                    None => out.push_str(&format!("\n{}\n", finding.content)),
+                }
+            }
+        }
+        if !sources.is_empty() {
+            out.push_str("\n## Sources\n\n");
+            for (i, source) in sources.iter().enumerate() {
+                out.push_str(&format!("{}. {}\n", i + 1, self.source_entry(source)));
+            }
+        }
+        out
+    }
+
+    pub fn to_html(&self) -> String {
+        let sources = self.sources();
+        let title = escape_html(self.title());
+        let mut body = format!("<h1>{}</h1>\n", title);
+        for section in &self.sections {
+            body.push_str(&format!("<h2>{}</h2>\n", escape_html(&section.title)));
+            for finding in &section.findings {
+                let paragraphs: Vec<String> = finding.content.split("\n\n")
+                    .map(|p| escape_html(p.trim()).replace('\n', "<br>\n"))
+                    .collect();
+                let cite = self.citation(&sources, finding)
+                    .map(|n| format!(" <sup><a href=\"#source-{0}\">[{0}]</a></sup>", n))
+                    .unwrap_or_default();
+                body.push_str(&format!("<p>{}{}</p>\n", paragraphs.join("</p>\n<p>"), cite));
+            }
+        }
+        if !sources.is_empty() {
+            body.push_str("<h2>Sources</h2>\n<ol>\n");
+            for (i, source) in sources.iter().enumerate() {
+                let escaped = escape_html(source);
+                let linked = if source.starts_with("http://") || source.starts_with("https://") {
+                    format!("<a href=\"{0}\">{0}</a>", escaped)
+                } else {
+                    escaped
+                };
+                let note = self.footnotes.get(*source).map(|n| format!(", {}", escape_html(n))).unwrap_or_default();
+                body.push_str(&format!("<li id=\"source-{}\">{}{}</li>\n", i + 1, linked, note));
+            }
+            body.push_str("</ol>\n");
+        }
+        format!(
+            "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n\
+             <style>body {{ font-family: sans-serif; max-width: 48em; margin: 2em auto; line-height: 1.5; }}</style>\n\
+             </head>\n<body>\n{}</body>\n</html>\n",
+            title, body
+        )
+    }
+
+    pub fn to_pdf(&self) -> Vec<u8> {
+        let sources = self.sources();
+        let mut lines = vec![PdfLine::Title(self.title().to_string())];
+        for section in &self.sections {
+            lines.push(PdfLine::Blank);
+            lines.push(PdfLine::Heading(section.title.clone()));
+            for finding in &section.findings {
+                let cite = self.citation(&sources, finding).map(|n| format!(" [{}]", n)).unwrap_or_default();
+                let paragraphs: Vec<&str> = finding.content.lines().collect();
+                for (i, paragraph) in paragraphs.iter().enumerate() {
+                    let last = i + 1 == paragraphs.len();
+                    lines.push(PdfLine::Text(if last { format!("{}{}", paragraph, cite) } else { paragraph.to_string() }));
+                }
+                lines.push(PdfLine::Blank);
+            }
+        }
+        if !sources.is_empty() {
+            lines.push(PdfLine::Heading("Sources".to_string()));
+            lines.extend(sources.iter().enumerate().map(|(i, s)| PdfLine::Text(format!("{}. {}", i + 1, self.source_entry(s)))));
+        }
+        write_pdf(&lines)
+    }
+
+    pub fn render(&self, format: ReportFormat) -> Vec<u8> {
+        match format {
+            ReportFormat::Markdown => self.to_markdown().into_bytes(),
+            ReportFormat::Html => self.to_html().into_bytes(),
+            ReportFormat::Pdf => self.to_pdf(),
+        }
+    }
+}
+
+/// The report being written, kept in a JSON file so findings survive restarts. The file is
+/// re-read on every access, so several handles to the same path stay in sync.
+#[derive(Debug, Clone)]
+pub struct ReportStore {
+    path: PathBuf,
+    /// Sources read this session, footnoted in exports
+    citations: Option<Citations>,
+}
+
+impl ReportStore {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        if let Some(parent) = path.parent() {
+            if !parent.as_os_str().is_empty() {
+                fs::create_dir_all(parent)?;
+            }
+        }
+        Ok(Self { path, citations: None })
+    }
+
+    /// Open the report at `AGENT_REPORT`, defaulting to `state/report.json`
+    pub fn from_env() -> Result<Self> {
+        let path = std::env::var("AGENT_REPORT").unwrap_or_else(|_| "state/report.json".to_string());
+        Self::open(path)
+    }
+
+    /// Footnote exports with the sources in `citations`
+    pub fn with_citations(mut self, citations: Citations) -> Self {
+        self.citations = Some(citations);
+        self
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn load(&self) -> Result<Report> {
+        match fs::read_to_string(&self.path) {
+            Ok(content) if !content.trim().is_empty() => {
+                serde_json::from_str(&content).map_err(|e| anyhow!("Corrupt report file {:?}: {}", self.path, e))
+            }
+            _ => Ok(Report::default()),
+        }
+    }
+
+    fn save(&self, report: &Report) -> Result<()> {
+        let tmp = self.path.with_extension("json.tmp");
+        fs::write(&tmp, serde_json::to_string_pretty(report)?)?;
+        fs::rename(&tmp, &self.path)?;
+        Ok(())
+    }
+
+    fn update(&self, change: impl FnOnce(&mut Report)) -> Result<Report> {
+        let _guard = WRITE_LOCK.lock().unwrap();
+        let mut report = self.load()?;
+        change(&mut report);
+        self.save(&report)?;
+        Ok(report)
+    }
+
+    /// Add a finding to `section`, optionally citing `source`
+    pub fn add(&self, section: &str, content: &str, source: Option<&str>) -> Result<String> {
+        if section.trim().is_empty() || content.trim().is_empty() {
+            return Err(anyhow!("A finding needs a section and some content"));
+        }
+        let report = self.update(|report| report.add(section, content, source))?;
+        let count = report.sections.iter().find(|s| s.title.eq_ignore_ascii_case(section.trim()))
+            .map(|s| s.findings.len())
+            .unwrap_or_default();
+        Ok(format!("Added to '{}' ({} finding(s) there, {} section(s) in the report)", section.trim(), count, report.sections.len()))
+    }
+
+    pub fn set_title(&self, title: &str) -> Result<()> {
+        self.update(|report| report.title = Some(title.trim().to_string()).filter(|t| !t.is_empty()))?;
+        Ok(())
+    }
+
+    /// Start a new, empty report
+    pub fn clear(&self) -> Result<()> {
+        let _guard = WRITE_LOCK.lock().unwrap();
+        self.save(&Report::default())
+    }
+
+    /// Write the report to `path` as `format`; the path's extension must suit the format
+    pub fn export(&self, format: ReportFormat, path: &Path) -> Result<String> {
+        let extension = path.extension().and_then(|e| e.to_str()).unwrap_or_default().to_lowercase();
+        if !format.extensions().contains(&extension.as_str()) {
+            return Err(anyhow!("A {} report must be saved as .{}", format.name(), format.extensions()[0]));
+        }
+        let mut report = self.load()?;
+        if report.is_empty() {
+            return Err(anyhow!("The report is empty; add findings with report_add(section, content)"));
+        }
+        if let Some(citations) = &self.citations {
+            report.cite(citations);
+        }
+        if let Some(parent) = path.parent() {
+            if !parent.as_os_str().is_empty() {
+                fs::create_dir_all(parent)?;
+            }
+        }
+        fs::write(path, report.render(format))?;
+        Ok(format!("Exported '{}' ({} sections, {} sources) to {}", report.title(), report.sections.len(), report.sources().len(), path.display()))
+    }
+}
+
+/// `report_add`, `report_export` and the other report natives
+pub(crate) fn register_report_natives(engine: &mut Engine, ctx: &NativeContext) -> Result<()> {
+    let NativeContext { jail, citations, .. } = ctx;
+    // Research report: findings gathered into sections, exported as Markdown, HTML or PDF.
+    // The one-argument forms take "section, content" and "format, path", as [TOOL: ...] passes them
+    let report = ReportStore::from_env()?.with_citations(citations.clone());
+    let report_clone = report.clone();
+    let report_add = move |section: &str, content: &str, source: Option<&str>| -> String {
+        report_clone.add(section, content, source).unwrap_or_else(|e| format!("Error: {}", e))
+    };
+    let add_clone = report_add.clone();
+    engine.register_fn("report_add", move |section: &str, content: &str| -> String { add_clone(section, content, None) });
+    let add_clone = report_add.clone();
+    engine.register_fn("report_add", move |section: &str, content: &str, source: &str| -> String { add_clone(section, content, Some(source)) });
+    engine.register_fn("report_add", move |finding: &str| -> String {
+        match finding.split_once(',') {
+            Some((section, content)) => report_add(section, content, None),
+            None => "Error: expected report_add(section, content)".to_string(),
+        }
+    });
+    let report_clone = report.clone();
+    engine.register_fn("report_title", move |title: &str| -> String {
+        match report_clone.set_title(title) {
+            Ok(()) => format!("Report titled '{}'", title.trim()),
+            Err(e) => format!("Error: {}", e),
+        }
+    });
+    let report_clone = report.clone();
+    engine.register_fn("report_show", move || -> String {
+        match report_clone.load() {
+            Ok(report) if report.is_empty() => "The report is empty".to_string(),
+            Ok(report) => report.to_markdown(),
+            Err(e) => format!("Error: {}", e),
+        }
+    });
+    let report_clone = report.clone();
+    engine.register_fn("report_clear", move || -> String {
+        match report_clone.clear() {
+            Ok(()) => "Report cleared".to_string(),
+            Err(e) => format!("Error: {}", e),
+        }
+    });
+    let report_export = {
+        let jail = jail.clone();
+        move |format: &str, path: &str| -> String {
+            let exported = format.parse::<ReportFormat>()
+                .and_then(|format| jail.resolve(path.trim()).and_then(|path| report.export(format, &path)));
+            exported.unwrap_or_else(|e| format!("Error: {}", e))
+        }
+    };
+    let export_clone = report_export.clone();
+    engine.register_fn("report_export", move |format: &str, path: &str| -> String { export_clone(format, path) });
+    engine.register_fn("report_export", move |request: &str| -> String {
+        match request.split_once(',') {
+            Some((format, path)) => report_export(format, path),
+            None => "Error: expected report_export(format, path)".to_string(),
+        }
+    });
+    Ok(())
+}
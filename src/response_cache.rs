@@ -0,0 +1,114 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use crate::llm::{GenerationConfig, Message};
+use tracing::warn;
+
+#[derive(Serialize, Deserialize)]
+struct CachedResponse {
+    created_at: u64,
+    response: String,
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+/// Content-addressed cache of LLM responses: one JSON file per request, named after the
+/// SHA-256 of model, messages, system prompt and generation parameters
+#[derive(Debug, Clone)]
+pub struct ResponseCache {
+    dir: PathBuf,
+    /// Entries older than this are ignored and overwritten; `None` keeps them forever
+    ttl: Option<Duration>,
+}
+
+impl ResponseCache {
+    pub fn open(dir: impl AsRef<Path>, ttl: Option<Duration>) -> Result<Self> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir)?;
+        Ok(Self { dir, ttl })
+    }
+
+    /// Enabled when `LLM_CACHE` is `true`/`1`; directory from `LLM_CACHE_DIR`
+    /// (default `state/llm_cache`), TTL from `LLM_CACHE_TTL_SECS` (default 86400, 0 = no expiry)
+    pub fn from_env() -> Option<Self> {
+        let enabled = std::env::var("LLM_CACHE")
+            .map(|v| matches!(v.to_lowercase().as_str(), "1" | "true" | "yes"))
+            .unwrap_or(false);
+        if !enabled {
+            return None;
+        }
+        let dir = std::env::var("LLM_CACHE_DIR").unwrap_or_else(|_| "state/llm_cache".to_string());
+        let ttl = std::env::var("LLM_CACHE_TTL_SECS").ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(86_400);
+        let ttl = if ttl == 0 { None } else { Some(Duration::from_secs(ttl)) };
+        match Self::open(&dir, ttl) {
+            Ok(cache) => Some(cache),
+            Err(e) => {
+                warn!("Response cache disabled, cannot open {}: {}", dir, e);
+                None
+            }
+        }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Cache key for a request
+    pub fn key(model: &str, messages: &[Message], system_prompt: Option<&str>, generation: &GenerationConfig) -> String {
+        let request = serde_json::json!({
+            "model": model,
+            "messages": messages,
+            "system": system_prompt,
+            "generation": generation,
+        });
+        let digest = Sha256::digest(request.to_string().as_bytes());
+        digest.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    fn entry_path(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("{}.json", key))
+    }
+
+    pub fn get(&self, key: &str) -> Option<String> {
+        let content = fs::read_to_string(self.entry_path(key)).ok()?;
+        let entry: CachedResponse = serde_json::from_str(&content).ok()?;
+        if let Some(ttl) = self.ttl {
+            if now_secs().saturating_sub(entry.created_at) >= ttl.as_secs() {
+                return None;
+            }
+        }
+        Some(entry.response)
+    }
+
+    pub fn put(&self, key: &str, response: &str) -> Result<()> {
+        let entry = CachedResponse {
+            created_at: now_secs(),
+            response: response.to_string(),
+        };
+        // Write to a temp file first so concurrent readers never see a partial entry
+        let path = self.entry_path(key);
+        let tmp = path.with_extension("json.tmp");
+        fs::write(&tmp, serde_json::to_string(&entry)?)?;
+        fs::rename(&tmp, &path)?;
+        Ok(())
+    }
+
+    /// Remove every entry, returning how many were deleted
+    pub fn clear(&self) -> Result<usize> {
+        let mut removed = 0;
+        for entry in fs::read_dir(&self.dir)?.flatten() {
+            if entry.path().extension().and_then(|e| e.to_str()) == Some("json") {
+                fs::remove_file(entry.path())?;
+                removed += 1;
+            }
+        }
+        Ok(removed)
+    }
+}
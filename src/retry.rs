@@ -0,0 +1,102 @@
+use std::fmt;
+use std::future::Future;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tracing::warn;
+
+/// Marks an error as worth retrying (throttling, timeouts, connection failures, 5xx)
+#[derive(Debug)]
+pub struct TransientError(pub String);
+
+impl fmt::Display for TransientError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for TransientError {}
+
+/// Wrap a message as a retryable error
+pub fn transient(message: impl Into<String>) -> anyhow::Error {
+    anyhow::Error::new(TransientError(message.into()))
+}
+
+pub fn is_transient(error: &anyhow::Error) -> bool {
+    error.downcast_ref::<TransientError>().is_some()
+}
+
+/// Whether an HTTP status should be retried
+pub fn is_transient_status(status: reqwest::StatusCode) -> bool {
+    status == reqwest::StatusCode::TOO_MANY_REQUESTS || status.is_server_error()
+}
+
+/// Exponential backoff with jitter for transient failures
+#[derive(Debug, Clone, PartialEq)]
+pub struct RetryPolicy {
+    pub max_retries: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            base_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(20),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Read `LLM_MAX_RETRIES` (default 3) and `LLM_RETRY_BASE_MS` (default 500)
+    pub fn from_env() -> Self {
+        let defaults = Self::default();
+        Self {
+            max_retries: std::env::var("LLM_MAX_RETRIES").ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(defaults.max_retries),
+            base_delay: std::env::var("LLM_RETRY_BASE_MS").ok()
+                .and_then(|v| v.parse().ok())
+                .map(Duration::from_millis)
+                .unwrap_or(defaults.base_delay),
+            max_delay: defaults.max_delay,
+        }
+    }
+
+    pub fn none() -> Self {
+        Self {
+            max_retries: 0,
+            ..Self::default()
+        }
+    }
+
+    /// Delay before retry number `attempt` (0-based): `base * 2^attempt`, capped,
+    /// plus up to 50% random jitter so many agents don't retry in lockstep
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let exp = self.base_delay.saturating_mul(2u32.saturating_pow(attempt)).min(self.max_delay);
+        let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.subsec_nanos()).unwrap_or(0);
+        let jitter = exp.mul_f64((nanos % 1000) as f64 / 2000.0);
+        exp + jitter
+    }
+
+    /// Run `op` until it succeeds, fails with a non-transient error, or retries run out
+    pub async fn run<T, F, Fut>(&self, label: &str, mut op: F) -> anyhow::Result<T>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = anyhow::Result<T>>,
+    {
+        let mut attempt = 0;
+        loop {
+            match op().await {
+                Ok(value) => return Ok(value),
+                Err(e) if is_transient(&e) && attempt < self.max_retries => {
+                    let delay = self.delay(attempt);
+                    warn!("{} failed ({}), retrying in {:?}", label, e, delay);
+                    tokio::time::sleep(delay).await;
+                    attempt += 1;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
@@ -0,0 +1,104 @@
+use text_colorizer::Colorize;
+use crate::message::ToolSafetyLevel;
+use crate::persona::{capabilities_called, Capability};
+use crate::safety::{Approval, SafetyPolicy};
+
+/// A line of a diff between two versions of a tool
+#[derive(Debug, Clone, PartialEq)]
+pub enum DiffLine {
+    Same(String),
+    Added(String),
+    Removed(String),
+}
+
+/// The lines that turn `old` into `new`, by longest common subsequence
+pub fn diff_lines(old: &str, new: &str) -> Vec<DiffLine> {
+    let old: Vec<&str> = old.lines().collect();
+    let new: Vec<&str> = new.lines().collect();
+    // common[i][j]: length of the longest common subsequence of old[i..] and new[j..]
+    let mut common = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            common[i][j] = if old[i] == new[j] { common[i + 1][j + 1] + 1 } else { common[i + 1][j].max(common[i][j + 1]) };
+        }
+    }
+    let (mut i, mut j) = (0, 0);
+    let mut lines = Vec::new();
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            lines.push(DiffLine::Same(old[i].to_string()));
+            i += 1;
+            j += 1;
+        } else if i < old.len() && (j == new.len() || common[i + 1][j] >= common[i][j + 1]) {
+            lines.push(DiffLine::Removed(old[i].to_string()));
+            i += 1;
+        } else {
+            lines.push(DiffLine::Added(new[j].to_string()));
+            j += 1;
+        }
+    }
+    lines
+}
+
+/// What the operator sees before a tool the model wrote is installed
+#[derive(Debug, Clone, PartialEq)]
+pub struct ToolReview {
+    pub name: String,
+    pub python: bool,
+    /// The installed version it would replace
+    pub previous: Option<String>,
+    pub code: String,
+    pub safety_level: ToolSafetyLevel,
+    /// What the code can do, by the natives it calls
+    pub capabilities: Vec<Capability>,
+    /// What the safety policy says about running it, when it has a rule for its capabilities
+    pub approval: Option<(Capability, Approval)>,
+}
+
+impl ToolReview {
+    pub fn new(name: &str, code: &str, python: bool, previous: Option<String>, safety: &SafetyPolicy) -> Self {
+        Self {
+            name: name.to_string(),
+            python,
+            previous,
+            code: code.to_string(),
+            safety_level: safety.classify(code),
+            capabilities: capabilities_called(code),
+            approval: safety.approval_for(code),
+        }
+    }
+
+    /// The diff against the installed version (all added for a new tool)
+    pub fn diff(&self) -> Vec<DiffLine> {
+        diff_lines(self.previous.as_deref().unwrap_or(""), &self.code)
+    }
+
+    /// The safety report and the diff, `+`/`-` marked, in color when `color` is set
+    pub fn render(&self, color: bool) -> String {
+        let kind = if self.python { "Python tool" } else { "tool" };
+        let action = if self.previous.is_some() { "Replace" } else { "Install" };
+        let mut out = format!("{} {} '{}'\n", action, kind, self.name);
+        out.push_str(&format!("  Safety level: {:?}\n", self.safety_level));
+        let capabilities = if self.capabilities.is_empty() {
+            "none (pure computation)".to_string()
+        } else {
+            self.capabilities.iter().map(|c| format!("{:?}", c)).collect::<Vec<_>>().join(", ")
+        };
+        out.push_str(&format!("  Capabilities: {}\n", capabilities));
+        if let Some((capability, approval)) = &self.approval {
+            out.push_str(&format!("  Safety policy: {:?} for {:?}\n", approval, capability));
+        }
+        for line in self.diff() {
+            let line = match line {
+                DiffLine::Same(text) => format!("  {}", text),
+                DiffLine::Added(text) if color => format!("+ {}", text).green().to_string(),
+                DiffLine::Added(text) => format!("+ {}", text),
+                DiffLine::Removed(text) if color => format!("- {}", text).red().to_string(),
+                DiffLine::Removed(text) => format!("- {}", text),
+            };
+            out.push_str(&line);
+            out.push('\n');
+        }
+        out
+    }
+}
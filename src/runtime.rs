@@ -0,0 +1,385 @@
+use anyhow::Result;
+use serde::Serialize;
+use std::sync::Arc;
+use std::time::Instant;
+use crate::agent::Agent;
+use crate::config::Config;
+use crate::events::{ask_for_approvals, watch_inbox, AgentEvents, NoEvents};
+use crate::journal::{millis, Journal};
+use crate::llm::LlmClient;
+use crate::mcp::McpTool;
+use crate::persona::Persona;
+use crate::prompts::{PromptContext, PromptLibrary};
+use crate::repair::repair_tools;
+use crate::tools::{tool_feedback, ResponseAction, ToolManager};
+use tracing::{info, warn};
+
+/// With more tools than this, only the ones relevant to each input are listed in the
+/// system prompt (`TOOL_PROMPT_LIMIT`)
+const PROMPT_TOOL_LIMIT: usize = 20;
+
+/// Something that happened while the agent worked on one input, in order
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum AgentEvent {
+    /// The model's response in one round, before its actions were taken
+    Response { text: String },
+    /// A tool the model wrote, and whether it was saved
+    ToolCreated { name: String, ok: bool, message: String },
+    /// A tool the model called, with its output or error
+    ToolExecuted { name: String, args: Vec<String>, ok: bool, output: String, duration_ms: u64 },
+    /// The model answered without calling a tool, ending the turn
+    FinalAnswer { text: String },
+}
+
+/// What came of one input
+#[derive(Debug, Clone, Serialize)]
+pub struct AgentTurn {
+    pub input: String,
+    pub events: Vec<AgentEvent>,
+    /// The model's last response
+    pub answer: String,
+    /// Why the turn stopped before a final answer: a model error, or too many rounds of tool calls
+    pub error: Option<String>,
+    pub duration_ms: u64,
+}
+
+impl AgentTurn {
+    pub fn is_ok(&self) -> bool {
+        self.error.is_none()
+    }
+
+    pub fn final_answer(&self) -> Option<&str> {
+        self.events.iter().find_map(|event| match event {
+            AgentEvent::FinalAnswer { text } => Some(text.as_str()),
+            _ => None,
+        })
+    }
+}
+
+/// Feed `input` to `agent`, saving the tools it writes and running the ones it calls, with
+/// their output sent back, until it answers without a call. Stops with an error after a model
+/// failure or `max_steps` rounds of calls. `events` hear of each step as it happens.
+pub async fn run_turn(agent: &mut Agent, tools: &mut ToolManager, events: &dyn AgentEvents, input: &str, max_steps: usize, fix_attempts: usize) -> AgentTurn {
+    let started = Instant::now();
+    let journal = agent.journal().cloned();
+    let mut turn = AgentTurn { input: input.to_string(), events: Vec::new(), answer: String::new(), error: None, duration_ms: 0 };
+    let mut input = input.to_string();
+    let mut steps = 0;
+    loop {
+        let response = match agent.chat(&input).await {
+            Ok(response) => response,
+            Err(e) => {
+                let error = e.to_string();
+                events.on_llm_call(&input, Err(&error));
+                turn.error = Some(error);
+                break;
+            }
+        };
+        events.on_llm_call(&input, Ok(&response));
+        turn.answer = response.clone();
+        turn.events.push(AgentEvent::Response { text: response.clone() });
+        let mut results = Vec::new();
+        let actions = tools.act_on(&response, journal.as_ref());
+        for action in repair_tools(agent, tools, actions, fix_attempts).await {
+            match action {
+                ResponseAction::Created { name, outcome } => {
+                    match &outcome {
+                        Ok(_) => info!("Created tool {}", name),
+                        Err(_) => warn!("Could not create tool {}", name),
+                    }
+                    events.on_tool_created(&name, borrowed(&outcome));
+                    let (ok, message) = split(outcome);
+                    turn.events.push(AgentEvent::ToolCreated { name, ok, message });
+                }
+                ResponseAction::Ran { name, args, result, duration_ms } => {
+                    info!("Executing tool: {}", name);
+                    events.on_tool_executed(&name, &args, borrowed(&result), duration_ms);
+                    results.push((name.clone(), result.clone()));
+                    let (ok, output) = split(result);
+                    turn.events.push(AgentEvent::ToolExecuted { name, args, ok, output, duration_ms });
+                }
+            }
+        }
+        if results.is_empty() {
+            turn.events.push(AgentEvent::FinalAnswer { text: response });
+            break;
+        }
+        if steps == max_steps {
+            turn.error = Some(format!("Still calling tools after {} steps", max_steps));
+            break;
+        }
+        steps += 1;
+        input = tool_feedback(&results);
+    }
+    turn.duration_ms = millis(started.elapsed());
+    turn
+}
+
+fn borrowed(result: &std::result::Result<String, String>) -> std::result::Result<&str, &str> {
+    result.as_deref().map_err(String::as_str)
+}
+
+fn split(result: std::result::Result<String, String>) -> (bool, String) {
+    match result {
+        Ok(output) => (true, output),
+        Err(e) => (false, e),
+    }
+}
+
+/// Sets up an `AgentRuntime`. Anything not given comes from the config, as the CLI does it.
+#[derive(Default)]
+pub struct AgentRuntimeBuilder {
+    config: Option<Config>,
+    tools: Option<ToolManager>,
+    persona: Option<Persona>,
+    llm: Option<LlmClient>,
+    journal: Option<Option<Journal>>,
+    events: Option<Arc<dyn AgentEvents>>,
+}
+
+impl AgentRuntimeBuilder {
+    /// Settings to build from (default `Config::from_env()`)
+    pub fn config(mut self, config: Config) -> Self {
+        self.config = Some(config);
+        self
+    }
+
+    /// Tools already set up, e.g. with approvers (default `ToolManager::with_config`, tools loaded)
+    pub fn tools(mut self, tools: ToolManager) -> Self {
+        self.tools = Some(tools);
+        self
+    }
+
+    /// Persona to act as (default the config's `agent.persona`, if any)
+    pub fn persona(mut self, persona: Persona) -> Self {
+        self.persona = Some(persona);
+        self
+    }
+
+    /// Model client to use (default the persona's model, else the config's)
+    pub fn llm(mut self, llm: LlmClient) -> Self {
+        self.llm = Some(llm);
+        self
+    }
+
+    /// Journal to record steps in, or None for none (default `Journal::from_env()`)
+    pub fn journal(mut self, journal: Option<Journal>) -> Self {
+        self.journal = Some(journal);
+        self
+    }
+
+    /// Hooks to hear what the agent does and answer its approvals. Approvals of the tools go
+    /// to them, and a watch on the inbox is started, so build inside a Tokio runtime.
+    pub fn events(mut self, events: Arc<dyn AgentEvents>) -> Self {
+        self.events = Some(events);
+        self
+    }
+
+    /// Load what wasn't given and put the agent together. The config's settings are exported
+    /// to the environment first, for the modules that read them from there.
+    pub async fn build(self) -> Result<AgentRuntime> {
+        let config = match self.config {
+            Some(config) => config,
+            None => Config::from_env()?,
+        };
+        config.export_env();
+        let mut tools = match self.tools {
+            Some(tools) => tools,
+            None => {
+                let mut tools = ToolManager::with_config(&config)?;
+                tools.load_tools()?;
+                tools
+            }
+        };
+        let persona = match (self.persona, &config.agent.persona) {
+            (Some(persona), _) => Some(persona),
+            (None, Some(name)) => Some(Persona::load(&config.paths.personas_dir, name)?),
+            (None, None) => None,
+        };
+        if let Some(persona) = &persona {
+            tools.set_policy(persona.tools.clone());
+        }
+        let llm = match (self.llm, persona.as_ref().and_then(|p| p.model.as_deref())) {
+            (Some(llm), _) => llm,
+            (None, Some(spec)) => LlmClient::from_spec(spec).await?,
+            (None, None) => LlmClient::from_config(&config.llm).await?,
+        };
+        let journal = match self.journal {
+            Some(journal) => journal,
+            None => Journal::from_env()?,
+        };
+        if let Some(events) = &self.events {
+            ask_for_approvals(&tools, events.clone());
+            tokio::spawn(watch_inbox(tools.inbox().clone(), events.clone()));
+        }
+        let prompt_tool_limit = std::env::var("TOOL_PROMPT_LIMIT").ok().and_then(|v| v.parse().ok()).unwrap_or(PROMPT_TOOL_LIMIT);
+        let mut runtime = AgentRuntime {
+            prompts: PromptLibrary::new(&config.paths.prompts_dir),
+            agent: Agent::with_client(llm.clone(), ""),
+            system_prompt: String::new(),
+            llm,
+            tools,
+            persona,
+            journal,
+            prompt_tool_limit,
+            events: self.events.unwrap_or_else(|| Arc::new(NoEvents)),
+            config,
+        };
+        let listed = runtime.tools.list_tools().into_iter().chain(runtime.mcp_tools()).collect();
+        runtime.system_prompt = runtime.render_prompt(listed)?;
+        runtime.agent = runtime.helper_agent();
+        Ok(runtime)
+    }
+}
+
+/// The agent loop as a library: a model, its tools and a conversation, driven one input at a
+/// time with `handle_input`
+///
+/// ```no_run
+/// # async fn demo() -> anyhow::Result<()> {
+/// use swarm_thing::config::Config;
+/// use swarm_thing::runtime::AgentRuntime;
+///
+/// let mut runtime = AgentRuntime::builder().config(Config::from_env()?).build().await?;
+/// let turn = runtime.handle_input("What is 12 squared?").await;
+/// println!("{}", turn.final_answer().unwrap_or_default());
+/// # Ok(())
+/// # }
+/// ```
+pub struct AgentRuntime {
+    config: Config,
+    agent: Agent,
+    tools: ToolManager,
+    llm: LlmClient,
+    persona: Option<Persona>,
+    prompts: PromptLibrary,
+    journal: Option<Journal>,
+    /// The prompt the agent started with
+    system_prompt: String,
+    prompt_tool_limit: usize,
+    events: Arc<dyn AgentEvents>,
+}
+
+impl AgentRuntime {
+    pub fn builder() -> AgentRuntimeBuilder {
+        AgentRuntimeBuilder::default()
+    }
+
+    /// Work on `input` until the agent answers (see `run_turn`). The system prompt is rendered
+    /// afresh first, listing the tools most relevant to `input` when there are many.
+    pub async fn handle_input(&mut self, input: &str) -> AgentTurn {
+        self.refresh_prompt(input).await;
+        run_turn(&mut self.agent, &mut self.tools, self.events.as_ref(), input, self.config.agent.max_steps, self.config.agent.fix_attempts).await
+    }
+
+    /// Templates are re-read on every request, so prompt edits apply straight away. A template
+    /// that fails to render leaves the last good prompt in place.
+    async fn refresh_prompt(&mut self, input: &str) {
+        let mut tools = self.tools.list_tools();
+        if tools.len() > self.prompt_tool_limit {
+            match self.tools.find_tools(input, self.prompt_tool_limit).await {
+                Ok(matches) => tools = matches.into_iter().map(|m| m.name).collect(),
+                Err(e) => warn!("Tool search error: {}", e),
+            }
+        }
+        tools.extend(self.mcp_tools());
+        match self.render_prompt(tools) {
+            Ok(prompt) => self.agent.set_system_prompt(&prompt),
+            Err(e) => warn!("Prompt template error: {}", e),
+        }
+    }
+
+    /// The prompt for `tools`, each listed with its parameters and description
+    fn render_prompt(&self, tools: Vec<String>) -> Result<String> {
+        let signatures = self.tools.prompt_signatures(&tools);
+        let mut context = match &self.persona {
+            Some(persona) => persona.prompt_context(tools),
+            None => PromptContext::from_env(tools),
+        };
+        context.tool_signatures = signatures;
+        match &self.persona {
+            Some(persona) => self.prompts.render(&persona.prompt, &context),
+            None => self.prompts.system_prompt(&context),
+        }
+    }
+
+    /// Tools of the configured MCP servers, as `mcp::<server>::<tool>` signatures
+    pub fn mcp_tools(&self) -> Vec<String> {
+        self.tools.mcp_tools().iter().map(McpTool::signature).collect()
+    }
+
+    /// A fresh agent on the same model, prompt, journal and action protocol, for work beside
+    /// the main conversation
+    pub fn helper_agent(&self) -> Agent {
+        let mut agent = Agent::with_client(self.llm.clone(), &self.system_prompt);
+        agent.set_journal(self.journal.clone());
+        agent.set_protocol(self.config.llm.protocol_for(&self.llm.label()));
+        agent
+    }
+
+    /// A second set of tools under the same policy, halt switch, MCP servers, rate limits,
+    /// confirm policy and approvers, for an agent working in the background. They're unattended, so HighRisk
+    /// runs always need an approver's yes (see `ToolManager::set_unattended`).
+    pub fn helper_tools(&self) -> Result<ToolManager> {
+        let mut tools = ToolManager::with_config(&self.config)?;
+        tools.set_unattended(true);
+        tools.set_confirm_policy(self.tools.confirm_policy().clone());
+        tools.share_approvals(&self.tools);
+        tools.set_rate_limits(self.tools.rate_limits());
+        tools.set_policy(self.tools.policy());
+        tools.set_halt_switch(self.tools.halt_switch().clone());
+        tools.set_mcp_clients(self.tools.mcp_clients().clone());
+        Ok(tools)
+    }
+
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    pub fn agent(&self) -> &Agent {
+        &self.agent
+    }
+
+    pub fn agent_mut(&mut self) -> &mut Agent {
+        &mut self.agent
+    }
+
+    pub fn tools(&self) -> &ToolManager {
+        &self.tools
+    }
+
+    pub fn tools_mut(&mut self) -> &mut ToolManager {
+        &mut self.tools
+    }
+
+    /// The agent and its tools together, for calls that need both
+    pub fn parts_mut(&mut self) -> (&mut Agent, &mut ToolManager) {
+        (&mut self.agent, &mut self.tools)
+    }
+
+    pub fn llm(&self) -> &LlmClient {
+        &self.llm
+    }
+
+    pub fn persona(&self) -> Option<&Persona> {
+        self.persona.as_ref()
+    }
+
+    pub fn journal(&self) -> Option<&Journal> {
+        self.journal.as_ref()
+    }
+
+    pub fn events(&self) -> &Arc<dyn AgentEvents> {
+        &self.events
+    }
+
+    pub fn system_prompt(&self) -> &str {
+        &self.system_prompt
+    }
+
+    /// The agent and its tools, e.g. for a headless `Runner`
+    pub fn into_parts(self) -> (Agent, ToolManager) {
+        (self.agent, self.tools)
+    }
+}
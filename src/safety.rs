@@ -0,0 +1,254 @@
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use crate::egress::{self, EgressDenied};
+use crate::message::ToolSafetyLevel;
+use crate::persona::{calls_function, capabilities_called, Capability};
+use crate::tools::validate_tool_code;
+
+/// Largest tool source accepted when the policy doesn't say
+pub const DEFAULT_MAX_TOOL_BYTES: usize = 10_000;
+
+/// Deepest clone generation (clones of clones) allowed when the policy doesn't say
+pub const DEFAULT_MAX_CLONE_DEPTH: u32 = 3;
+
+/// Most clones one agent may have when the policy doesn't say
+pub const DEFAULT_MAX_CLONES: usize = 10;
+
+/// Mentions that make a tool HighRisk when the policy doesn't say
+pub const DEFAULT_HIGH_RISK: &[&str] = &[
+    "write_file", "append_file", "delete_file", "clone_agent", "start_server", "stop_server",
+    "trust_agent", "block_agent", "set_trust", "halt_agent", "get_secret", "spawn_clone", "stop_clone",
+    "run_command", "undo_changes", "revert_tool", "purge_quarantine", "std::process",
+];
+
+/// Mentions that make a tool MediumRisk when the policy doesn't say
+pub const DEFAULT_MEDIUM_RISK: &[&str] = &[
+    "read_file", "list_dir", "scrape_url", "show_audit", "register_clone",
+    "db_execute", "report_add", "report_title", "report_clear", "report_export", "load_csv", "load_json", "memory_set", "analyze_image",
+    "ingest_document", "search_arxiv", "get_paper", "read_pdf", "read_docx", "read_epub", "schedule_task", "execute_tool_background",
+];
+
+/// Mentions that make a tool LowRisk when the policy doesn't say
+pub const DEFAULT_LOW_RISK: &[&str] = &[
+    "send_message", "call_peer", "invoke_tool", "broadcast_message", "set_peer_group",
+    "subscribe", "publish", "subscriptions", "reply_message", "request_tool", "check_inbox", "read_message", "mark_read", "outbox_status",
+    "delegate_task", "announce", "blackboard_", "current_leader", "elect_leader", "ensemble_ask", "peer_history", "peer_status",
+    "server_status", "trust_levels", "tool_log", "list_schedules", "cancel_schedule", "list_clones", "db_query", "query_data",
+    "describe_data", "report_show", "list_sources", "workspace_path", "list_changes", "memory_get", "memory_search", "retrieve",
+];
+
+/// The words in a tool's source that set its risk, checked from HighRisk down; a tool that
+/// mentions none of them is Safe
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RiskKeywords {
+    pub high: Vec<String>,
+    pub medium: Vec<String>,
+    pub low: Vec<String>,
+}
+
+impl Default for RiskKeywords {
+    fn default() -> Self {
+        let owned = |words: &[&str]| words.iter().map(|w| w.to_string()).collect();
+        Self { high: owned(DEFAULT_HIGH_RISK), medium: owned(DEFAULT_MEDIUM_RISK), low: owned(DEFAULT_LOW_RISK) }
+    }
+}
+
+impl RiskKeywords {
+    /// The risk of `code` by the words it mentions
+    pub fn level(&self, code: &str) -> ToolSafetyLevel {
+        let mentions = |words: &[String]| words.iter().any(|w| code.contains(w.as_str()));
+        if mentions(&self.high) {
+            ToolSafetyLevel::HighRisk
+        } else if mentions(&self.medium) {
+            ToolSafetyLevel::MediumRisk
+        } else if mentions(&self.low) {
+            ToolSafetyLevel::LowRisk
+        } else {
+            ToolSafetyLevel::Safe
+        }
+    }
+}
+
+/// What running a tool that uses a capability takes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Approval {
+    /// Runs under the usual `CONFIRM_RISK` rules
+    Auto,
+    /// The operator confirms every run, whatever its risk
+    Confirm,
+    /// Never runs
+    Deny,
+}
+
+/// Whether and where `clone_agent` may copy the agent
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ClonePolicy {
+    pub allowed: bool,
+    /// Directories clones may be created under; empty allows any
+    pub dirs: Vec<PathBuf>,
+    /// Deepest generation a clone may be; an original agent is generation 0
+    pub max_depth: u32,
+    /// Most clones this agent may have at once; deleted clones don't count
+    pub max_count: usize,
+}
+
+impl Default for ClonePolicy {
+    fn default() -> Self {
+        Self { allowed: true, dirs: Vec::new(), max_depth: DEFAULT_MAX_CLONE_DEPTH, max_count: DEFAULT_MAX_CLONES }
+    }
+}
+
+/// The operator's risk appetite, from `policy.toml`. Applies whichever persona runs, when
+/// tools are created, approved and executed.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SafetyPolicy {
+    /// Natives no tool may call and the agent may not run
+    pub banned_functions: Vec<String>,
+    /// Larger tools are refused, and rated HighRisk where they are only rated
+    pub max_tool_bytes: usize,
+    /// What a tool's source mentions makes its risk, e.g. `[risk_keywords] high = ["run_command"]`
+    pub risk_keywords: RiskKeywords,
+    /// Per-capability approval, e.g. `files = "confirm"`; capabilities not listed are `auto`
+    pub approval: HashMap<Capability, Approval>,
+    /// Hosts web requests and messages may go to (`example.com`, `*.example.com`, IPs or
+    /// CIDR ranges); empty allows any
+    pub allowed_hosts: Vec<String>,
+    /// Hosts they may never go to, in the same forms; wins over `allowed_hosts`
+    pub denied_hosts: Vec<String>,
+    pub clone: ClonePolicy,
+    /// Most calls per minute of a tool or native, e.g. `scrape_url = 10`; tools not listed,
+    /// or listed with 0, are unlimited
+    pub rate_limits: HashMap<String, u32>,
+}
+
+impl Default for SafetyPolicy {
+    fn default() -> Self {
+        Self {
+            banned_functions: Vec::new(),
+            max_tool_bytes: DEFAULT_MAX_TOOL_BYTES,
+            risk_keywords: RiskKeywords::default(),
+            approval: HashMap::new(),
+            allowed_hosts: Vec::new(),
+            denied_hosts: Vec::new(),
+            clone: ClonePolicy::default(),
+            rate_limits: HashMap::new(),
+        }
+    }
+}
+
+impl SafetyPolicy {
+    /// The policy in the TOML file at `path`; the defaults if there is none
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let content = fs::read_to_string(path)?;
+        toml::from_str(&content).map_err(|e| anyhow!("Invalid safety policy {:?}: {}", path, e))
+    }
+
+    /// `SAFETY_POLICY`, default `policy.toml`
+    pub fn from_env() -> Result<Self> {
+        Self::load(std::env::var("SAFETY_POLICY").unwrap_or_else(|_| "policy.toml".to_string()))
+    }
+
+    /// The first banned native `code` calls
+    pub fn banned_call(&self, code: &str) -> Option<&str> {
+        self.banned_functions.iter().map(String::as_str).find(|f| calls_function(code, f))
+    }
+
+    /// Why a tool with source `code` may not be installed, if it may not
+    pub fn refusal(&self, code: &str) -> Option<String> {
+        if code.len() > self.max_tool_bytes {
+            return Some(format!("it is {} bytes, over the {} byte limit", code.len(), self.max_tool_bytes));
+        }
+        self.banned_call(code).map(|f| format!("it calls {}, which is banned", f))
+    }
+
+    /// The risk of a tool with source `code` under this policy
+    pub fn classify(&self, code: &str) -> ToolSafetyLevel {
+        validate_tool_code(code, self)
+    }
+
+    /// The strictest approval among the capabilities `code` uses, with the capability
+    pub fn approval_for(&self, code: &str) -> Option<(Capability, Approval)> {
+        capabilities_called(code).into_iter()
+            .filter_map(|c| self.approval.get(&c).map(|a| (c, *a)))
+            .max_by_key(|(_, approval)| *approval)
+    }
+
+    /// The rate-limited tools and natives `code` calls, with their calls per minute, by name
+    pub fn rate_limits_for(&self, code: &str) -> Vec<(&str, u32)> {
+        let mut limits: Vec<(&str, u32)> = self.rate_limits.iter()
+            .filter(|(tool, per_minute)| **per_minute > 0 && calls_function(code, tool))
+            .map(|(tool, per_minute)| (tool.as_str(), *per_minute))
+            .collect();
+        limits.sort();
+        limits
+    }
+
+    /// Whether a request to `url` (or a bare `host:port`) may leave the agent
+    pub fn allows_host(&self, url: &str) -> bool {
+        self.check_host(url).is_ok()
+    }
+
+    /// See `egress::check`
+    pub fn check_host(&self, url: &str) -> std::result::Result<(), EgressDenied> {
+        egress::check(self, url)
+    }
+
+    /// Refuse clones when they are disabled or `target` is outside the allowed directories
+    pub fn check_clone(&self, target: &Path) -> Result<()> {
+        if !self.clone.allowed {
+            return Err(anyhow!("cloning is disabled by the safety policy"));
+        }
+        if self.clone.dirs.is_empty() {
+            return Ok(());
+        }
+        let target = absolute(target);
+        if self.clone.dirs.iter().any(|dir| target.starts_with(absolute(dir))) {
+            Ok(())
+        } else {
+            Err(anyhow!("{:?} is outside the directories the safety policy allows clones in", target))
+        }
+    }
+
+    /// Refuse a clone that would be of generation `generation` when the agent already has
+    /// `existing` clones
+    pub fn check_replication(&self, generation: u32, existing: usize) -> Result<()> {
+        if generation > self.clone.max_depth {
+            return Err(anyhow!("a clone would be generation {}, past the safety policy's max_depth of {}", generation, self.clone.max_depth));
+        }
+        if existing >= self.clone.max_count {
+            return Err(anyhow!("this agent already has {} clones, the safety policy's max_count", existing));
+        }
+        Ok(())
+    }
+}
+
+/// `path` made absolute and with `.`/`..` resolved, without touching the filesystem
+fn absolute(path: &Path) -> PathBuf {
+    let path = if path.is_absolute() {
+        path.to_path_buf()
+    } else {
+        std::env::current_dir().unwrap_or_default().join(path)
+    };
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            Component::CurDir => {}
+            other => normalized.push(other),
+        }
+    }
+    normalized
+}
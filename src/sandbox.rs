@@ -0,0 +1,165 @@
+use rhai::{Dynamic, Engine, EvalAltResult, Scope};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use crate::persona::{gated_natives, Capability};
+
+/// How far a sandboxed run may go before it is stopped
+#[derive(Debug, Clone, Copy)]
+pub struct SandboxLimits {
+    pub max_operations: u64,
+    pub max_call_levels: usize,
+    pub max_string_size: usize,
+    pub max_collection_size: usize,
+    pub timeout: Duration,
+}
+
+impl Default for SandboxLimits {
+    fn default() -> Self {
+        Self {
+            max_operations: 100_000,
+            max_call_levels: 32,
+            max_string_size: 100_000,
+            max_collection_size: 10_000,
+            timeout: Duration::from_secs(2),
+        }
+    }
+}
+
+/// A native the tool tried to call; the sandbox answers it with an empty string
+#[derive(Debug, Clone, PartialEq)]
+pub struct Violation {
+    pub native: String,
+    /// None for functions the sandbox doesn't know at all, which end the run
+    pub capability: Option<Capability>,
+    pub args: Vec<String>,
+}
+
+impl Violation {
+    fn render(&self) -> String {
+        let call = format!("{}({})", self.native, self.args.iter().map(|a| format!("{:?}", a)).collect::<Vec<_>>().join(", "));
+        match self.capability {
+            Some(capability) => format!("{} [{}]", call, serde_json::to_value(capability).ok().and_then(|v| v.as_str().map(String::from)).unwrap_or_default()),
+            None => format!("{} [unknown function]", call),
+        }
+    }
+}
+
+/// What a pending tool did in the sandbox
+#[derive(Debug, Clone)]
+pub struct SandboxReport {
+    pub tool: String,
+    pub args: Vec<String>,
+    pub output: Result<String, String>,
+    /// What the tool printed
+    pub printed: Vec<String>,
+    pub violations: Vec<Violation>,
+    pub operations: u64,
+}
+
+impl SandboxReport {
+    pub fn render(&self) -> String {
+        let mut lines = vec![format!("Sandbox run of '{}' with {:?}", self.tool, self.args)];
+        lines.push(match &self.output {
+            Ok(output) => format!("Output: {}", output),
+            Err(e) => format!("Error: {}", e),
+        });
+        for line in &self.printed {
+            lines.push(format!("Printed: {}", line));
+        }
+        lines.push(format!("Operations: {}", self.operations));
+        if self.violations.is_empty() {
+            lines.push("No capability violations".to_string());
+        } else {
+            lines.push(format!("Capability violations ({}):", self.violations.len()));
+            lines.extend(self.violations.iter().map(|v| format!("- {}", v.render())));
+        }
+        lines.join("\n")
+    }
+}
+
+fn record(violations: &Arc<Mutex<Vec<Violation>>>, native: &str, capability: Capability, args: &[Dynamic]) -> Dynamic {
+    violations.lock().unwrap().push(Violation {
+        native: native.to_string(),
+        capability: Some(capability),
+        args: args.iter().map(|a| a.to_string()).collect(),
+    });
+    Dynamic::from(String::new())
+}
+
+/// An engine without the agent's natives: the gated ones are stubs that only note the attempt
+fn hardened_engine(limits: &SandboxLimits, violations: &Arc<Mutex<Vec<Violation>>>, printed: &Arc<Mutex<Vec<String>>>, operations: &Arc<Mutex<u64>>) -> Engine {
+    let mut engine = Engine::new();
+    engine.set_max_operations(limits.max_operations);
+    engine.set_max_call_levels(limits.max_call_levels);
+    engine.set_max_expr_depths(64, 32);
+    engine.set_max_string_size(limits.max_string_size);
+    engine.set_max_array_size(limits.max_collection_size);
+    engine.set_max_map_size(limits.max_collection_size);
+    engine.set_module_resolver(rhai::module_resolvers::DummyModuleResolver::new());
+    engine.disable_symbol("eval");
+    let deadline = Instant::now() + limits.timeout;
+    let counter = operations.clone();
+    engine.on_progress(move |count| {
+        *counter.lock().unwrap() = count;
+        (Instant::now() > deadline).then(|| Dynamic::from("sandbox time limit reached"))
+    });
+    let lines = printed.clone();
+    engine.on_print(move |text| lines.lock().unwrap().push(text.to_string()));
+    let lines = printed.clone();
+    engine.on_debug(move |text, _, _| lines.lock().unwrap().push(text.to_string()));
+
+    for &(native, capability) in gated_natives() {
+        let v = violations.clone();
+        engine.register_fn(native, move || record(&v, native, capability, &[]));
+        let v = violations.clone();
+        engine.register_fn(native, move |a: Dynamic| record(&v, native, capability, &[a]));
+        let v = violations.clone();
+        engine.register_fn(native, move |a: Dynamic, b: Dynamic| record(&v, native, capability, &[a, b]));
+        let v = violations.clone();
+        engine.register_fn(native, move |a: Dynamic, b: Dynamic, c: Dynamic| record(&v, native, capability, &[a, b, c]));
+    }
+    engine
+}
+
+/// Run tool `name` from `code` with `args` in a throwaway hardened engine. Nothing it calls
+/// reaches files, the network or peers; calls to gated natives are reported as violations.
+pub fn run_sandboxed(name: &str, code: &str, args: &[String], limits: &SandboxLimits) -> SandboxReport {
+    let violations = Arc::new(Mutex::new(Vec::new()));
+    let printed = Arc::new(Mutex::new(Vec::new()));
+    let operations = Arc::new(Mutex::new(0u64));
+    let engine = hardened_engine(limits, &violations, &printed, &operations);
+    let output = match engine.compile(code) {
+        Err(e) => Err(format!("does not compile: {}", e)),
+        Ok(ast) => {
+            // Pass as many of the sample arguments as the tool takes
+            let arity = ast.iter_functions().find(|f| f.name == name).map(|f| f.params.len());
+            match arity {
+                None => Err(format!("no function '{}' in the tool", name)),
+                Some(arity) => {
+                    let call_args: Vec<Dynamic> = (0..arity).map(|i| Dynamic::from(args.get(i).cloned().unwrap_or_default())).collect();
+                    engine.call_fn_with_options::<Dynamic>(rhai::CallFnOptions::new(), &mut Scope::new(), &ast, name, call_args)
+                        .map(|v| v.to_string())
+                        .map_err(|e| describe_error(&e, &violations))
+                }
+            }
+        }
+    };
+    let printed = std::mem::take(&mut *printed.lock().unwrap());
+    let violations = std::mem::take(&mut *violations.lock().unwrap());
+    let operations = *operations.lock().unwrap();
+    SandboxReport { tool: name.to_string(), args: args.to_vec(), output, printed, violations, operations }
+}
+
+/// The error as the report shows it; calling an unknown function is a violation too
+fn describe_error(error: &EvalAltResult, violations: &Arc<Mutex<Vec<Violation>>>) -> String {
+    match error.unwrap_inner() {
+        EvalAltResult::ErrorFunctionNotFound(signature, _) => {
+            let native = signature.split(" (").next().unwrap_or(signature).to_string();
+            violations.lock().unwrap().push(Violation { native: native.clone(), capability: None, args: Vec::new() });
+            format!("calls '{}', which the sandbox doesn't provide", native)
+        }
+        EvalAltResult::ErrorTooManyOperations(_) => "stopped: too many operations".to_string(),
+        EvalAltResult::ErrorTerminated(reason, _) => format!("stopped: {}", reason),
+        other => other.to_string(),
+    }
+}
@@ -0,0 +1,362 @@
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::time::Duration;
+use crate::orchestrator::Member;
+use crate::parser::parse_tool_call;
+use crate::registry::unix_now;
+use crate::state::StateStore;
+use tracing::{info, warn};
+use rhai::Engine;
+use crate::tools::NativeContext;
+
+/// State key the jobs are kept under
+const SCHEDULES_KEY: &str = "schedules";
+/// How often `serve_schedules` looks for due jobs
+pub const SCHEDULE_POLL: Duration = Duration::from_secs(15);
+/// How far ahead `CronSchedule::next_after` looks before giving up on a schedule that never fires
+const MAX_LOOKAHEAD_DAYS: i64 = 366 * 5;
+
+/// A five-field cron expression (`minute hour day-of-month month day-of-week`, in UTC), or one of
+/// `@hourly`, `@daily`, `@weekly`, `@monthly` and `@yearly`
+#[derive(Debug, Clone, PartialEq)]
+pub struct CronSchedule {
+    minutes: Vec<bool>,
+    hours: Vec<bool>,
+    days: Vec<bool>,
+    months: Vec<bool>,
+    weekdays: Vec<bool>,
+    /// Whether the day-of-month and day-of-week fields were restricted: with both, either matches
+    any_day: bool,
+    any_weekday: bool,
+}
+
+/// Which of `min..=max` a field like `*/15`, `1-5` or `0,30` allows
+fn parse_field(field: &str, min: u32, max: u32) -> Result<Vec<bool>> {
+    let mut allowed = vec![false; max as usize + 1];
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => (range, step.parse::<u32>().ok().filter(|s| *s > 0).ok_or_else(|| anyhow!("bad step in '{}'", part))?),
+            None => (part, 1),
+        };
+        let (start, end) = match range {
+            "*" => (min, max),
+            _ => match range.split_once('-') {
+                Some((start, end)) => (start.parse()?, end.parse()?),
+                None if part.contains('/') => (range.parse()?, max),
+                None => {
+                    let value = range.parse()?;
+                    (value, value)
+                }
+            },
+        };
+        if start < min || end > max || start > end {
+            return Err(anyhow!("'{}' is outside {}-{}", part, min, max));
+        }
+        for value in (start..=end).step_by(step as usize) {
+            allowed[value as usize] = true;
+        }
+    }
+    Ok(allowed)
+}
+
+impl std::str::FromStr for CronSchedule {
+    type Err = anyhow::Error;
+
+    fn from_str(expr: &str) -> Result<Self> {
+        let expr = match expr.trim() {
+            "@hourly" => "0 * * * *",
+            "@daily" | "@midnight" => "0 0 * * *",
+            "@weekly" => "0 0 * * 0",
+            "@monthly" => "0 0 1 * *",
+            "@yearly" | "@annually" => "0 0 1 1 *",
+            other => other,
+        };
+        let fields: Vec<&str> = expr.split_whitespace().collect();
+        let [minute, hour, day, month, weekday] = fields[..] else {
+            return Err(anyhow!("Cron expression '{}' needs 5 fields: minute hour day-of-month month day-of-week", expr));
+        };
+        let field = |value: &str, min, max, name| parse_field(value, min, max).map_err(|e| anyhow!("Bad {} field in '{}': {}", name, expr, e));
+        let mut weekdays = field(weekday, 0, 7, "day-of-week")?;
+        // 7 is Sunday too
+        weekdays[0] |= weekdays[7];
+        weekdays.truncate(7);
+        Ok(Self {
+            minutes: field(minute, 0, 59, "minute")?,
+            hours: field(hour, 0, 23, "hour")?,
+            days: field(day, 1, 31, "day-of-month")?,
+            months: field(month, 1, 12, "month")?,
+            weekdays,
+            any_day: day == "*",
+            any_weekday: weekday == "*",
+        })
+    }
+}
+
+/// Year, month and day of the `days`th day after 1970-01-01
+pub(crate) fn civil_date(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    (year_of_era + era * 400 + i64::from(month <= 2), month, day)
+}
+
+impl CronSchedule {
+    fn matches_day(&self, days: i64) -> bool {
+        let (_, month, day) = civil_date(days);
+        let weekday = (days + 4).rem_euclid(7) as usize;
+        let by_day = self.days[day as usize];
+        let by_weekday = self.weekdays[weekday];
+        let day_matches = match (self.any_day, self.any_weekday) {
+            (false, false) => by_day || by_weekday,
+            _ => by_day && by_weekday,
+        };
+        self.months[month as usize] && day_matches
+    }
+
+    /// The first minute after `time` (unix seconds) the schedule fires at
+    pub fn next_after(&self, time: u64) -> Option<u64> {
+        let start = time as i64 / 60 + 1;
+        let (mut days, mut minute) = (start.div_euclid(1440), start.rem_euclid(1440));
+        for _ in 0..MAX_LOOKAHEAD_DAYS {
+            if self.matches_day(days) {
+                for m in minute..1440 {
+                    if self.hours[(m / 60) as usize] && self.minutes[(m % 60) as usize] {
+                        return Some(((days * 1440 + m) * 60) as u64);
+                    }
+                }
+            }
+            days += 1;
+            minute = 0;
+        }
+        None
+    }
+}
+
+/// What a scheduled job does when it fires
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum JobAction {
+    /// A request for the agent loop, as if typed at the prompt
+    Prompt { prompt: String },
+    /// A tool run directly, without the model
+    Tool { name: String, args: Vec<String> },
+}
+
+impl JobAction {
+    /// `[TOOL: name(args)]` runs that tool; anything else is a prompt
+    pub fn parse(target: &str) -> Result<Self> {
+        let target = target.trim();
+        if target.is_empty() {
+            return Err(anyhow!("Nothing to schedule"));
+        }
+        match parse_tool_call(target).filter(|_| target.starts_with("[TOOL:")) {
+            Some((name, args)) => Ok(Self::Tool { name, args }),
+            None => Ok(Self::Prompt { prompt: target.to_string() }),
+        }
+    }
+}
+
+impl fmt::Display for JobAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Prompt { prompt } => write!(f, "{}", prompt),
+            Self::Tool { name, args } => write!(f, "[TOOL: {}({})]", name, args.join(", ")),
+        }
+    }
+}
+
+/// A job `schedule_task` saved
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ScheduledJob {
+    pub id: String,
+    pub cron: String,
+    pub action: JobAction,
+    /// Unix time (seconds) it next fires at
+    pub next_run: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_run: Option<u64>,
+    /// The output or error of its last run
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_outcome: Option<String>,
+}
+
+impl ScheduledJob {
+    pub fn render(&self) -> String {
+        let mut line = format!("{} [{}] next at {}: {}", self.id, self.cron, self.next_run, self.action);
+        if let Some(outcome) = &self.last_outcome {
+            line.push_str(&format!("\n   last: {}", outcome.chars().take(200).collect::<String>()));
+        }
+        line
+    }
+}
+
+/// Jobs that run prompts or tools on cron schedules, kept in the state store so they outlive
+/// restarts. Handles on the same store see the same jobs.
+#[derive(Debug, Clone)]
+pub struct Scheduler {
+    store: StateStore,
+}
+
+impl Scheduler {
+    pub fn new(store: StateStore) -> Self {
+        Self { store }
+    }
+
+    pub fn list(&self) -> Result<Vec<ScheduledJob>> {
+        Ok(self.store.get(SCHEDULES_KEY)?.unwrap_or_default())
+    }
+
+    fn save(&self, jobs: &[ScheduledJob]) -> Result<()> {
+        self.store.set(SCHEDULES_KEY, &jobs)
+    }
+
+    /// Save a job running `target` (see `JobAction::parse`) on `cron`, first after `now`
+    pub fn schedule(&self, cron: &str, target: &str, now: u64) -> Result<ScheduledJob> {
+        let schedule: CronSchedule = cron.parse()?;
+        let next_run = schedule.next_after(now).ok_or_else(|| anyhow!("'{}' never fires", cron))?;
+        let mut jobs = self.list()?;
+        let number = jobs.iter().filter_map(|j| j.id.strip_prefix("job-")?.parse::<u64>().ok()).max().unwrap_or(0) + 1;
+        let job = ScheduledJob {
+            id: format!("job-{}", number),
+            cron: cron.trim().to_string(),
+            action: JobAction::parse(target)?,
+            next_run,
+            last_run: None,
+            last_outcome: None,
+        };
+        jobs.push(job.clone());
+        self.save(&jobs)?;
+        Ok(job)
+    }
+
+    /// Drop job `id`; false when there is none
+    pub fn cancel(&self, id: &str) -> Result<bool> {
+        let mut jobs = self.list()?;
+        let before = jobs.len();
+        jobs.retain(|j| j.id != id);
+        let cancelled = jobs.len() != before;
+        if cancelled {
+            self.save(&jobs)?;
+        }
+        Ok(cancelled)
+    }
+
+    /// The jobs due at `now`, each moved on to its next time first so a crash mid-run doesn't
+    /// repeat it. Runs missed while the agent was down happen once.
+    pub fn take_due(&self, now: u64) -> Result<Vec<ScheduledJob>> {
+        let mut jobs = self.list()?;
+        let mut due = Vec::new();
+        for job in jobs.iter_mut().filter(|j| j.next_run <= now) {
+            due.push(job.clone());
+            // A schedule that parsed once still parses; one that stops firing is left idle
+            job.next_run = job.cron.parse::<CronSchedule>().ok().and_then(|c| c.next_after(now)).unwrap_or(u64::MAX);
+        }
+        if !due.is_empty() {
+            self.save(&jobs)?;
+        }
+        Ok(due)
+    }
+
+    /// Note how job `id`'s run at `time` went
+    pub fn record(&self, id: &str, time: u64, outcome: &str) -> Result<()> {
+        let mut jobs = self.list()?;
+        if let Some(job) = jobs.iter_mut().find(|j| j.id == id) {
+            job.last_run = Some(time);
+            job.last_outcome = Some(outcome.to_string());
+            self.save(&jobs)?;
+        }
+        Ok(())
+    }
+
+    /// All jobs, one per line, for `list_schedules`
+    pub fn render(&self) -> Result<String> {
+        let jobs = self.list()?;
+        if jobs.is_empty() {
+            return Ok("No scheduled tasks.".to_string());
+        }
+        Ok(jobs.iter().map(ScheduledJob::render).collect::<Vec<_>>().join("\n"))
+    }
+}
+
+/// Run the jobs due at `now` with `member`: prompts through its agent loop, tools directly.
+/// Returns each job's id and outcome, also recorded on the job. Whoever scheduled a job isn't
+/// there when it runs, so the member's tools are made unattended.
+pub async fn run_due(scheduler: &Scheduler, member: &mut Member, now: u64) -> Vec<(String, Result<String, String>)> {
+    member.tools_mut().set_unattended(true);
+    let due = match scheduler.take_due(now) {
+        Ok(due) => due,
+        Err(e) => {
+            warn!("Cannot read the schedule: {}", e);
+            return Vec::new();
+        }
+    };
+    let mut outcomes = Vec::new();
+    for job in due {
+        info!("Running scheduled {}: {}", job.id, job.action);
+        let result = match member.tools().halt_switch().check() {
+            Err(e) => Err(e),
+            Ok(()) => match &job.action {
+                JobAction::Prompt { prompt } => member.handle(prompt).await,
+                JobAction::Tool { name, args } => member.tools().execute_tool(name, args.clone()),
+            },
+        }.map_err(|e| e.to_string());
+        let outcome = match &result {
+            Ok(output) => output.clone(),
+            Err(e) => format!("Error: {}", e),
+        };
+        info!("{} done: {}", job.id, outcome);
+        if let Err(e) = scheduler.record(&job.id, now, &outcome) {
+            warn!("Cannot record the run of {}: {}", job.id, e);
+        }
+        outcomes.push((job.id, result));
+    }
+    outcomes
+}
+
+/// Run `scheduler`'s jobs with `member` as they fall due, until the surrounding task is dropped
+pub async fn serve_schedules(scheduler: Scheduler, mut member: Member) {
+    loop {
+        run_due(&scheduler, &mut member, unix_now()).await;
+        tokio::time::sleep(SCHEDULE_POLL).await;
+    }
+}
+
+/// `schedule_task`, `list_schedules` and `cancel_schedule`
+pub(crate) fn register_schedule_natives(engine: &mut Engine, ctx: &NativeContext) {
+    let NativeContext { audit, scheduler, .. } = ctx;
+    // schedule_task / list_schedules / cancel_schedule: prompts and tool calls run on a cron
+    // schedule by `serve_schedules`
+    let scheduler_clone = scheduler.clone();
+    let audit_clone = audit.clone();
+    engine.register_fn("schedule_task", move |cron: &str, target: &str| -> String {
+        match scheduler_clone.schedule(cron, target, unix_now()) {
+            Ok(job) => {
+                audit_clone.record_or_warn("schedule_task", &format!("{} [{}] {}", job.id, job.cron, job.action), "scheduled");
+                format!("Scheduled {}, first run at {}", job.id, job.next_run)
+            }
+            Err(e) => format!("Error: {}", e),
+        }
+    });
+    let scheduler_clone = scheduler.clone();
+    engine.register_fn("list_schedules", move || -> String {
+        scheduler_clone.render().unwrap_or_else(|e| format!("Error reading schedules: {}", e))
+    });
+    let scheduler_clone = scheduler.clone();
+    let audit_clone = audit.clone();
+    engine.register_fn("cancel_schedule", move |id: &str| -> String {
+        match scheduler_clone.cancel(id.trim()) {
+            Ok(true) => {
+                audit_clone.record_or_warn("cancel_schedule", id.trim(), "cancelled");
+                format!("Cancelled {}", id.trim())
+            }
+            Ok(false) => format!("Error: No scheduled task '{}'", id.trim()),
+            Err(e) => format!("Error: {}", e),
+        }
+    });
+}
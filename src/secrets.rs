@@ -0,0 +1,205 @@
+use anyhow::{anyhow, Result};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use rhai::Engine;
+use crate::tools::NativeContext;
+
+/// Keyring service the agent's secrets are stored under, one entry per name
+pub const KEYRING_SERVICE: &str = "swarm-thing";
+
+/// Credentials the agent itself uses
+const KNOWN_SECRETS: &[&str] = &["AWS_ACCESS_KEY_ID", "AWS_SECRET_ACCESS_KEY", "AWS_SESSION_TOKEN", "OPENAI_API_KEY", "IPC_SECRET"];
+
+/// Words that make a variable a secret, as a whole `_`-separated part of its name
+const SECRET_WORDS: &[&str] = &["KEY", "SECRET", "TOKEN", "PASSWORD", "PASSWD", "CREDENTIAL", "CREDENTIALS"];
+
+/// Settings of this module, not secrets themselves
+const SETTINGS: &[&str] = &["SECRET_NAMES", "SECRETS_KEYRING"];
+
+/// Values shorter than this aren't redacted, or every "1" in a text would be
+const MIN_REDACTED_LEN: usize = 6;
+
+/// The agent's credentials, from the environment (`.env` included) or the OS keyring. They are
+/// never written into clone directories, and values handed out are redacted from tool output.
+#[derive(Debug, Clone)]
+pub struct Secrets {
+    /// Secret names besides the known credentials and those that look like one
+    names: Vec<String>,
+    keyring: bool,
+    /// Values read so far, by name, for redaction
+    seen: Arc<Mutex<HashMap<String, String>>>,
+}
+
+impl Default for Secrets {
+    fn default() -> Self {
+        Self { names: Vec::new(), keyring: true, seen: Arc::new(Mutex::new(HashMap::new())) }
+    }
+}
+
+impl Secrets {
+    /// Extra names from `SECRET_NAMES` (comma-separated); `SECRETS_KEYRING=false` leaves the
+    /// OS keyring alone
+    pub fn from_env() -> Self {
+        let names = std::env::var("SECRET_NAMES").unwrap_or_default()
+            .split(',')
+            .map(|n| n.trim().to_string())
+            .filter(|n| !n.is_empty())
+            .collect();
+        let keyring = std::env::var("SECRETS_KEYRING").map(|v| v != "false").unwrap_or(true);
+        Self::default().with_names(names).with_keyring(keyring)
+    }
+
+    pub fn with_names(mut self, names: Vec<String>) -> Self {
+        self.names = names;
+        self
+    }
+
+    pub fn with_keyring(mut self, keyring: bool) -> Self {
+        self.keyring = keyring;
+        self
+    }
+
+    /// Whether the variable `name` holds a secret
+    pub fn is_secret(&self, name: &str) -> bool {
+        let name = name.trim();
+        if SETTINGS.contains(&name) {
+            return false;
+        }
+        KNOWN_SECRETS.contains(&name)
+            || self.names.iter().any(|n| n == name)
+            || name.to_uppercase().split('_').any(|word| SECRET_WORDS.contains(&word))
+    }
+
+    /// The secret `name`, from the environment or else the keyring
+    pub fn get(&self, name: &str) -> Result<Option<String>> {
+        let value = match std::env::var(name).ok().filter(|v| !v.is_empty()) {
+            Some(value) => Some(value),
+            None if self.keyring => keyring_get(name)?,
+            None => None,
+        };
+        if let Some(value) = &value {
+            self.seen.lock().unwrap().insert(name.to_string(), value.clone());
+        }
+        Ok(value)
+    }
+
+    /// Save `value` as the secret `name` in the keyring
+    pub fn store(&self, name: &str, value: &str) -> Result<()> {
+        if !self.keyring {
+            return Err(anyhow!("The OS keyring is disabled (SECRETS_KEYRING=false)"));
+        }
+        keyring::Entry::new(KEYRING_SERVICE, name)?.set_password(value)
+            .map_err(|e| anyhow!("Cannot store secret {} in the keyring: {}", name, e))?;
+        self.seen.lock().unwrap().insert(name.to_string(), value.to_string());
+        Ok(())
+    }
+
+    /// Set the known and named credentials missing from the environment from the keyring, so
+    /// the clients that read them find them. Returns the names set.
+    pub fn load_keyring(&self) -> Vec<String> {
+        if !self.keyring {
+            return Vec::new();
+        }
+        let mut loaded = Vec::new();
+        for name in KNOWN_SECRETS.iter().map(|n| n.to_string()).chain(self.names.iter().cloned()) {
+            if std::env::var(&name).is_ok() {
+                continue;
+            }
+            if let Ok(Some(value)) = keyring_get(&name) {
+                std::env::set_var(&name, &value);
+                self.seen.lock().unwrap().insert(name.clone(), value);
+                loaded.push(name);
+            }
+        }
+        loaded
+    }
+
+    /// Names of the secrets set in the environment or read so far
+    pub fn names(&self) -> Vec<String> {
+        let mut names: Vec<String> = std::env::vars().map(|(name, _)| name).filter(|name| self.is_secret(name)).collect();
+        names.extend(self.seen.lock().unwrap().keys().cloned());
+        names.sort();
+        names.dedup();
+        names
+    }
+
+    /// Each secret's name with its value masked
+    pub fn redacted(&self) -> Vec<(String, String)> {
+        self.names().into_iter()
+            .map(|name| {
+                let value = std::env::var(&name).ok().or_else(|| self.seen.lock().unwrap().get(&name).cloned());
+                (name, mask(value.as_deref().unwrap_or_default()))
+            })
+            .collect()
+    }
+
+    /// `text` with every known secret value replaced by `[redacted:NAME]`
+    pub fn redact(&self, text: &str) -> String {
+        let mut values: Vec<(String, String)> = std::env::vars().filter(|(name, _)| self.is_secret(name)).collect();
+        values.extend(self.seen.lock().unwrap().iter().map(|(n, v)| (n.clone(), v.clone())));
+        // Longest first, so a secret containing another is replaced whole
+        values.sort_by_key(|(_, value)| std::cmp::Reverse(value.len()));
+        let mut text = text.to_string();
+        for (name, value) in values.iter().filter(|(_, v)| v.len() >= MIN_REDACTED_LEN) {
+            text = text.replace(value.as_str(), &format!("[redacted:{}]", name));
+        }
+        text
+    }
+
+    /// Copy the env file `src` to `dst` without its secrets. Returns the names left out.
+    pub fn copy_env_file(&self, src: &Path, dst: &Path) -> Result<Vec<String>> {
+        let mut kept = Vec::new();
+        let mut dropped = Vec::new();
+        let content = fs::read_to_string(src)?;
+        for line in content.lines() {
+            let name = line.trim().trim_start_matches("export ").split('=').next().unwrap_or_default().trim();
+            if !line.trim_start().starts_with('#') && line.contains('=') && self.is_secret(name) {
+                dropped.push(name.to_string());
+            } else {
+                kept.push(line);
+            }
+        }
+        fs::write(dst, kept.join("\n") + "\n")?;
+        Ok(dropped)
+    }
+}
+
+/// `value` with all but its last four characters hidden, or all of it when short
+pub fn mask(value: &str) -> String {
+    let chars: Vec<char> = value.chars().collect();
+    if chars.len() < 12 {
+        return "****".to_string();
+    }
+    format!("****{}", chars[chars.len() - 4..].iter().collect::<String>())
+}
+
+fn keyring_get(name: &str) -> Result<Option<String>> {
+    match keyring::Entry::new(KEYRING_SERVICE, name)?.get_password() {
+        Ok(value) => Ok(Some(value)),
+        Err(keyring::Error::NoEntry) => Ok(None),
+        Err(e) => Err(anyhow!("Cannot read secret {} from the keyring: {}", name, e)),
+    }
+}
+
+/// `get_secret`
+pub(crate) fn register_secret_natives(engine: &mut Engine, ctx: &NativeContext) {
+    let NativeContext { audit, secrets, .. } = ctx;
+    // get_secret: a named credential, for tools that call authenticated APIs. HighRisk, so a
+    // tool using it needs approval, and its value is redacted from tool output.
+    let secrets_clone = secrets.clone();
+    let audit_clone = audit.clone();
+    engine.register_fn("get_secret", move |name: &str| -> String {
+        let (result, outcome) = match secrets_clone.get(name) {
+            Ok(Some(value)) => (value, "read".to_string()),
+            Ok(None) => {
+                let error = format!("Error: no secret '{}'", name);
+                (error.clone(), error)
+            }
+            Err(e) => (format!("Error: {}", e), format!("Error: {}", e)),
+        };
+        audit_clone.record_or_warn("get_secret", name, &outcome);
+        result
+    });
+}
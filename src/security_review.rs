@@ -0,0 +1,66 @@
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+use crate::llm::{LlmClient, Message};
+
+/// Instructions for the model reviewing a pending tool
+const REVIEW_PROMPT: &str = r#"You are a security reviewer for tools an AI agent received from other agents.
+The tool is a Rhai script; it can call natives such as read_file, write_file, scrape_url,
+run_command, send_message and get_secret. Judge what the code actually does, not what its
+name or description claims.
+Respond ONLY with JSON of this shape:
+{"purpose": "one sentence", "risks": ["what could go wrong if installed"], "suspicious_patterns": ["code that looks deliberately harmful or hidden"]}
+Use empty lists when there is nothing to report."#;
+
+/// A model's security assessment of a pending tool, kept with it until it is approved or rejected
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SecurityReview {
+    pub purpose: String,
+    #[serde(default)]
+    pub risks: Vec<String>,
+    #[serde(default)]
+    pub suspicious_patterns: Vec<String>,
+    /// Which model wrote the review
+    #[serde(default)]
+    pub model: String,
+}
+
+impl SecurityReview {
+    /// Parse a review from a model's response: a JSON object, optionally inside a code fence
+    /// or surrounded by prose
+    pub fn parse(response: &str) -> Result<Self> {
+        let start = response.find('{').ok_or_else(|| anyhow!("No JSON review in response"))?;
+        let end = response.rfind('}').filter(|&end| end > start)
+            .ok_or_else(|| anyhow!("No JSON review in response"))?;
+        serde_json::from_str(&response[start..=end]).map_err(|e| anyhow!("Invalid review JSON: {}", e))
+    }
+
+    /// Indented lines for `list_pending_tools`
+    pub fn render(&self) -> String {
+        let list = |items: &[String]| if items.is_empty() { "none".to_string() } else { items.join("; ") };
+        format!(
+            "   Review ({}): {}\n   Risks: {}\n   Suspicious: {}\n",
+            self.model, self.purpose, list(&self.risks), list(&self.suspicious_patterns)
+        )
+    }
+}
+
+/// Ask `client` to review tool `name` with `code`
+pub async fn review_tool(client: &LlmClient, name: &str, code: &str, description: Option<&str>) -> Result<SecurityReview> {
+    let mut request = format!("Tool name: {}\n", name);
+    if let Some(description) = description {
+        request.push_str(&format!("Claimed description: {}\n", description));
+    }
+    request.push_str(&format!("\n```rhai\n{}\n```", code));
+    let response = client.chat(vec![Message::user(request)], Some(REVIEW_PROMPT.to_string())).await?;
+    let mut review = SecurityReview::parse(&response)?;
+    review.model = client.label();
+    Ok(review)
+}
+
+/// The reviewing model: `REVIEW_MODEL` (a `provider:model` spec), or else the main model
+pub async fn review_client() -> Result<LlmClient> {
+    match std::env::var("REVIEW_MODEL") {
+        Ok(spec) if !spec.trim().is_empty() => LlmClient::from_spec(&spec).await,
+        _ => LlmClient::new().await,
+    }
+}
@@ -0,0 +1,286 @@
+use anyhow::{anyhow, Result};
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::watch;
+use tracing::{error, info, warn};
+use crate::registry::{local_peer_info, HeartbeatConfig, PeerInfo, PeerRegistry};
+use crate::election::Election;
+use crate::transport::TransportKind;
+use rhai::Engine;
+use crate::tools::{InvokePolicy, NativeContext, ToolManager};
+
+/// How long `stop` waits for in-flight requests before giving up on the server thread
+pub const SHUTDOWN_GRACE: Duration = Duration::from_secs(5);
+
+/// Resolves once the server it was handed to should stop
+#[derive(Clone)]
+pub struct Shutdown(watch::Receiver<bool>);
+
+impl Shutdown {
+    pub async fn wait(mut self) {
+        // A dropped manager counts as a stop request too
+        let _ = self.0.wait_for(|stop| *stop).await;
+    }
+}
+
+/// Where the server is and whether it is still serving
+#[derive(Debug, Clone, PartialEq)]
+pub struct ServerStatus {
+    pub address: String,
+    pub uptime: Duration,
+    /// False once the server exited on its own, e.g. after an error
+    pub running: bool,
+}
+
+impl ServerStatus {
+    pub fn render(&self) -> String {
+        if self.running {
+            format!("Server running at {} for {}s", self.address, self.uptime.as_secs())
+        } else {
+            format!("Server at {} has exited", self.address)
+        }
+    }
+}
+
+struct RunningServer {
+    address: String,
+    started: Instant,
+    stop: watch::Sender<bool>,
+    thread: std::thread::JoinHandle<()>,
+}
+
+/// The agent's IPC server: at most one at a time, run on a thread and runtime of its own
+/// so it outlives the native that started it, and stopped gracefully on request
+#[derive(Clone, Default)]
+pub struct ServerManager {
+    current: Arc<Mutex<Option<RunningServer>>>,
+}
+
+impl ServerManager {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Run `serve` for the server at `address`. The `Shutdown` it gets resolves on `stop`;
+    /// anything else it spawned ends with its runtime once it returns.
+    pub fn start<F, Fut>(&self, address: &str, serve: F) -> Result<()>
+    where
+        F: FnOnce(Shutdown) -> Fut + Send + 'static,
+        Fut: Future<Output = ()>,
+    {
+        let mut current = self.current.lock().unwrap();
+        if let Some(running) = current.as_ref().filter(|r| !r.thread.is_finished()) {
+            return Err(anyhow!("A server is already running at {}; stop_server first", running.address));
+        }
+        let (stop, stopped) = watch::channel(false);
+        let thread = std::thread::spawn(move || {
+            let rt = tokio::runtime::Runtime::new().unwrap();
+            rt.block_on(serve(Shutdown(stopped)));
+        });
+        *current = Some(RunningServer { address: address.to_string(), started: Instant::now(), stop, thread });
+        Ok(())
+    }
+
+    /// Stop the server, waiting up to `grace` for requests in flight; the stopped server's
+    /// address, or None if there was none
+    pub fn stop(&self, grace: Duration) -> Option<String> {
+        let running = self.current.lock().unwrap().take()?;
+        let _ = running.stop.send(true);
+        let deadline = Instant::now() + grace;
+        while !running.thread.is_finished() && Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        if running.thread.is_finished() {
+            let _ = running.thread.join();
+        } else {
+            warn!("Server at {} did not stop within {:?}", running.address, grace);
+        }
+        Some(running.address)
+    }
+
+    pub fn status(&self) -> Option<ServerStatus> {
+        self.current.lock().unwrap().as_ref().map(|running| ServerStatus {
+            address: running.address.clone(),
+            uptime: running.started.elapsed(),
+            running: !running.thread.is_finished(),
+        })
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.status().map(|s| s.running).unwrap_or(false)
+    }
+}
+
+/// Once the server is up, announce to `SWARM_REGISTRY` (a peer's `host:port`) and, with
+/// `SWARM_DISCOVERY=udp`, broadcast on `DISCOVERY_PORT` (default 9898) and keep listening.
+/// Heartbeats to known peers start here too, and with them leader elections if `election` is given.
+async fn announce_on_start(me: PeerInfo, peers: PeerRegistry, heartbeat: Option<HeartbeatConfig>, election: Option<Election>) {
+    if let Some(config) = heartbeat {
+        tokio::spawn(crate::registry::heartbeat_loop(me.address.clone(), peers.clone(), config));
+        if let Some(election) = election {
+            tokio::spawn(crate::election::leader_loop(election, me.address.clone(), peers.clone(), config));
+        }
+    }
+    tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+    if let Ok(registry) = std::env::var("SWARM_REGISTRY") {
+        if !registry.trim().is_empty() {
+            match crate::registry::announce_to(registry.trim(), &me, &peers).await {
+                Ok(count) => info!("Announced to registry {} ({} peers)", registry, count),
+                Err(e) => warn!("Failed to announce to registry {}: {}", registry, e),
+            }
+        }
+    }
+    if std::env::var("SWARM_DISCOVERY").map(|v| v.eq_ignore_ascii_case("udp")).unwrap_or(false) {
+        let port: u16 = std::env::var("DISCOVERY_PORT").ok().and_then(|v| v.parse().ok()).unwrap_or(9898);
+        let listener_me = me.clone();
+        tokio::spawn(async move {
+            if let Err(e) = crate::registry::listen_for_peers(port, listener_me, peers).await {
+                error!("Discovery listener error: {}", e);
+            }
+        });
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        if let Err(e) = crate::registry::broadcast_announce(port, &me).await {
+            error!("Discovery broadcast error: {}", e);
+        }
+    }
+}
+
+/// `start_server`, `stop_server` and `server_status`
+pub(crate) fn register_server_natives(engine: &mut Engine, ctx: &NativeContext) {
+    let NativeContext { tools_dir, audit, policy, safety, pending_tools, threads, tasks, peers, inbox, calls, server, halt, election, keys, local_address, .. } = ctx;
+    let election_enabled = ctx.election_enabled;
+    let heartbeat = ctx.heartbeat;
+    let pending_clone = pending_tools.clone();
+    let threads_clone = threads.clone();
+    let tasks_clone = tasks.clone();
+    let inbox_clone = inbox.clone();
+    let calls_clone = calls.clone();
+    let peers_clone = peers.clone();
+    let policy_clone = policy.clone();
+    let tools_dir_clone = tools_dir.clone();
+    let address_clone = local_address.clone();
+    let server_clone = server.clone();
+    let audit_clone = audit.clone();
+    let safety_clone = safety.clone();
+    let halt_clone = halt.clone();
+    let election_clone = election.clone();
+    let keys_clone = keys.clone();
+    engine.register_fn("start_server", move |port: &str| -> String {
+        let port_num: u16 = port.parse().unwrap_or(8080);
+        let pending = pending_clone.clone();
+        let threads = threads_clone.clone();
+        let tasks = tasks_clone.clone();
+        let peers = peers_clone.clone();
+        let mut state = crate::ipc::IpcState::new(pending, threads, tasks, peers.clone(), tools_dir_clone.clone())
+            .with_inbox(inbox_clone.clone())
+            .with_calls(calls_clone.clone())
+            .with_audit(audit_clone.clone())
+            .with_safety(safety_clone.lock().unwrap().clone())
+            .with_halt(halt_clone.clone())
+            .with_election(election_clone.clone())
+            .with_keys(keys_clone.clone());
+        // Peers' ToolInvoke messages run on a separate manager under the same tool policy
+        match ToolManager::new() {
+            Ok(mut host) => {
+                host.set_policy(policy_clone.lock().unwrap().clone());
+                host.set_safety_policy(safety_clone.lock().unwrap().clone());
+                host.set_halt_switch(halt_clone.clone());
+                state = state.with_tool_host(Arc::new(host), InvokePolicy::from_env());
+            }
+            Err(e) => warn!("Remote tool calls disabled: {}", e),
+        }
+        if let Some(status) = server_clone.status().filter(|s| s.running) {
+            return format!("Error: a server is already running at {}; stop_server first", status.address);
+        }
+        // With SWARM_TRANSPORT=mqtt the agent is reached through the broker and the port is unused
+        let mqtt = match TransportKind::from_env() {
+            TransportKind::Mqtt => match crate::transport::mqtt() {
+                Ok(transport) => Some(transport),
+                Err(e) => return format!("Error connecting to MQTT broker: {}", e),
+            },
+            TransportKind::Http => None,
+        };
+        // Bind right away, so a port that is taken is reported instead of failing in the background
+        let listener = match &mqtt {
+            Some(_) => None,
+            None => {
+                let bind_address = crate::ipc::host_port(&crate::ipc::bind_host(), port_num);
+                match std::net::TcpListener::bind(&bind_address).and_then(|l| l.set_nonblocking(true).map(|_| l)) {
+                    Ok(listener) => Some(listener),
+                    Err(e) => return format!("Error: cannot listen on {}: {}", bind_address, e),
+                }
+            }
+        };
+        let address = match &mqtt {
+            Some(transport) => transport.address(),
+            None => crate::ipc::host_port(&crate::ipc::advertised_host(), port_num),
+        };
+        *address_clone.lock().unwrap() = Some(address.clone());
+        let me = local_peer_info(&address, &tools_dir_clone, &policy_clone.lock().unwrap(), &keys_clone);
+        state = state.with_identity(me.clone());
+        let mut started = match &mqtt {
+            Some(_) => format!("IPC server listening on MQTT as {}", address),
+            None => format!("IPC server starting on port {}", port_num),
+        };
+        // GRPC_PORT adds a gRPC endpoint over the same state
+        let grpc = crate::grpc::grpc_port().map(|port| (port, state.clone()));
+        if let Some((port, _)) = &grpc {
+            started.push_str(&format!(" (gRPC on port {})", port));
+        }
+        
+        info!("Starting IPC server on {}", address);
+        let election = election_enabled.then(|| election_clone.clone());
+        
+        let serving = server_clone.start(&address, move |shutdown| async move {
+            if let Some((port, state)) = grpc {
+                let shutdown = shutdown.clone();
+                tokio::spawn(async move {
+                    if let Err(e) = crate::grpc::serve_grpc(port, state, shutdown.wait()).await {
+                        error!("gRPC server error: {}", e);
+                    }
+                });
+            }
+            let server = match (mqtt, listener) {
+                (Some(transport), _) => tokio::spawn(crate::ipc::serve_mqtt(state, transport, shutdown.wait())),
+                (None, Some(listener)) => match tokio::net::TcpListener::from_std(listener) {
+                    Ok(listener) => tokio::spawn(crate::ipc::serve_http(listener, state, shutdown.wait())),
+                    Err(e) => return error!("Server error: {}", e),
+                },
+                (None, None) => return,
+            };
+            announce_on_start(me, peers, heartbeat, election).await;
+            match server.await {
+                Ok(Err(e)) => error!("Server error: {}", e),
+                Err(e) => error!("Server error: {}", e),
+                Ok(Ok(())) => info!("IPC server stopped"),
+            }
+        });
+        let started = match serving {
+            Ok(()) => started,
+            Err(e) => format!("Error: {}", e),
+        };
+        audit_clone.record_or_warn("start_server", &address, &started);
+        started
+    });
+
+    let server_clone = server.clone();
+    let address_clone = local_address.clone();
+    engine.register_fn("stop_server", move || -> String {
+        match server_clone.stop(SHUTDOWN_GRACE) {
+            Some(address) => {
+                *address_clone.lock().unwrap() = None;
+                format!("Server at {} stopped", address)
+            }
+            None => "No server is running".to_string(),
+        }
+    });
+
+    let server_clone = server.clone();
+    engine.register_fn("server_status", move || -> String {
+        match server_clone.status() {
+            Some(status) => status.render(),
+            None => "No server is running".to_string(),
+        }
+    });
+}
@@ -0,0 +1,101 @@
+use anyhow::{Result, anyhow};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+// Serializes read-modify-write cycles between stores sharing a file in this process
+static WRITE_LOCK: Mutex<()> = Mutex::new(());
+
+/// Small JSON-file backed key/value store for agent state that must survive restarts.
+/// The file is re-read on every access, so several handles to the same path stay in sync.
+#[derive(Debug, Clone)]
+pub struct StateStore {
+    path: PathBuf,
+}
+
+impl StateStore {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        if let Some(parent) = path.parent() {
+            if !parent.as_os_str().is_empty() {
+                fs::create_dir_all(parent)?;
+            }
+        }
+        Ok(Self { path })
+    }
+
+    /// Open the store at `AGENT_STATE_FILE`, defaulting to `state/state.json`
+    pub fn from_env() -> Result<Self> {
+        let path = std::env::var("AGENT_STATE_FILE").unwrap_or_else(|_| "state/state.json".to_string());
+        Self::open(path)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn load(&self) -> Result<Map<String, Value>> {
+        if !self.path.exists() {
+            return Ok(Map::new());
+        }
+        let content = fs::read_to_string(&self.path)?;
+        if content.trim().is_empty() {
+            return Ok(Map::new());
+        }
+        serde_json::from_str(&content).map_err(|e| anyhow!("Corrupt state file {:?}: {}", self.path, e))
+    }
+
+    fn save(&self, data: &Map<String, Value>) -> Result<()> {
+        // Write to a temp file first so a crash never leaves a half-written state file
+        let tmp = self.path.with_extension("json.tmp");
+        fs::write(&tmp, serde_json::to_string_pretty(data)?)?;
+        fs::rename(&tmp, &self.path)?;
+        Ok(())
+    }
+
+    pub fn get<T: DeserializeOwned>(&self, key: &str) -> Result<Option<T>> {
+        let data = self.load()?;
+        match data.get(key) {
+            Some(value) => Ok(Some(serde_json::from_value(value.clone())?)),
+            None => Ok(None),
+        }
+    }
+
+    pub fn set<T: Serialize>(&self, key: &str, value: &T) -> Result<()> {
+        let _guard = WRITE_LOCK.lock().unwrap();
+        let mut data = self.load()?;
+        data.insert(key.to_string(), serde_json::to_value(value)?);
+        self.save(&data)
+    }
+
+    pub fn remove(&self, key: &str) -> Result<bool> {
+        let _guard = WRITE_LOCK.lock().unwrap();
+        let mut data = self.load()?;
+        let removed = data.remove(key).is_some();
+        if removed {
+            self.save(&data)?;
+        }
+        Ok(removed)
+    }
+
+    /// Atomically read, modify and write back the value stored under `key`
+    pub fn update<T, R, F>(&self, key: &str, f: F) -> Result<R>
+    where
+        T: Serialize + DeserializeOwned + Default,
+        F: FnOnce(&mut T) -> R,
+    {
+        let _guard = WRITE_LOCK.lock().unwrap();
+        let mut data = self.load()?;
+        let mut value: T = match data.get(key) {
+            Some(v) => serde_json::from_value(v.clone())?,
+            None => T::default(),
+        };
+        let result = f(&mut value);
+        data.insert(key.to_string(), serde_json::to_value(&value)?);
+        self.save(&data)?;
+        Ok(result)
+    }
+}
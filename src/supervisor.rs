@@ -0,0 +1,180 @@
+use anyhow::{anyhow, Result};
+use std::collections::HashMap;
+use std::fs::File;
+use std::path::Path;
+use std::process::{Child, Command, Stdio};
+use std::sync::{Arc, Mutex};
+use crate::lineage::Lineage;
+use rhai::Engine;
+use crate::tools::NativeContext;
+
+/// Where a spawned clone writes its output, in its directory
+pub const CLONE_LOG: &str = "clone.log";
+
+/// Variables set in the env file at `path`; none if there isn't one
+pub fn env_file_keys(path: &Path) -> Vec<String> {
+    std::fs::read_to_string(path).unwrap_or_default()
+        .lines()
+        .map(|line| line.trim())
+        .filter(|line| !line.starts_with('#'))
+        .filter_map(|line| line.split_once('='))
+        .map(|(key, _)| key.trim().trim_start_matches("export ").trim().to_string())
+        .filter(|key| !key.is_empty())
+        .collect()
+}
+
+/// What a clone's process is doing
+#[derive(Debug, Clone, PartialEq)]
+pub enum CloneProcess {
+    Running { pid: u32 },
+    /// Exited on its own; `None` when killed by a signal
+    Exited { code: Option<i32> },
+    /// Stopped with `stop`
+    Stopped,
+    /// Not spawned by this agent since it started
+    NotSupervised,
+}
+
+impl CloneProcess {
+    pub fn render(&self) -> String {
+        match self {
+            CloneProcess::Running { pid } => format!("running (pid {})", pid),
+            CloneProcess::Exited { code: Some(code) } => format!("exited ({})", code),
+            CloneProcess::Exited { code: None } => "exited (signal)".to_string(),
+            CloneProcess::Stopped => "stopped".to_string(),
+            CloneProcess::NotSupervised => "not running here".to_string(),
+        }
+    }
+}
+
+/// Clone processes this agent spawned, keyed by clone directory. Children are reaped when their
+/// status is read, and outlive the agent unless stopped.
+#[derive(Clone, Default)]
+pub struct CloneSupervisor {
+    children: Arc<Mutex<HashMap<String, Option<Child>>>>,
+}
+
+impl CloneSupervisor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Run `program` with `args` in `dir`, output going to `clone.log` there and without the
+    /// environment variables in `unset`. Returns its pid.
+    pub fn spawn(&self, program: &Path, args: &[String], dir: &Path, unset: &[String]) -> Result<u32> {
+        let key = dir.display().to_string();
+        let mut children = self.children.lock().unwrap();
+        if let Some(Some(child)) = children.get_mut(&key) {
+            if child.try_wait()?.is_none() {
+                return Err(anyhow!("The clone in {} is already running (pid {})", key, child.id()));
+            }
+        }
+        let log = File::create(dir.join(CLONE_LOG))?;
+        let mut command = Command::new(program);
+        for var in unset {
+            command.env_remove(var);
+        }
+        let child = command
+            .args(args)
+            .current_dir(dir)
+            .stdin(Stdio::null())
+            .stdout(log.try_clone()?)
+            .stderr(log)
+            .spawn()
+            .map_err(|e| anyhow!("Cannot start {:?}: {}", program, e))?;
+        let pid = child.id();
+        children.insert(key, Some(child));
+        Ok(pid)
+    }
+
+    pub fn status(&self, dir: &str) -> CloneProcess {
+        let mut children = self.children.lock().unwrap();
+        match children.get_mut(dir) {
+            None => CloneProcess::NotSupervised,
+            Some(None) => CloneProcess::Stopped,
+            Some(Some(child)) => match child.try_wait() {
+                Ok(None) => CloneProcess::Running { pid: child.id() },
+                Ok(Some(status)) => CloneProcess::Exited { code: status.code() },
+                Err(_) => CloneProcess::Exited { code: None },
+            },
+        }
+    }
+
+    /// Kill the clone in `dir` and wait for it; false if it wasn't running
+    pub fn stop(&self, dir: &str) -> Result<bool> {
+        let mut children = self.children.lock().unwrap();
+        let Some(slot) = children.get_mut(dir) else {
+            return Ok(false);
+        };
+        let Some(mut child) = slot.take() else {
+            return Ok(false);
+        };
+        if child.try_wait()?.is_some() {
+            return Ok(false);
+        }
+        child.kill()?;
+        child.wait()?;
+        Ok(true)
+    }
+
+    /// Stop every running clone; the directories of those stopped
+    pub fn stop_all(&self) -> Vec<String> {
+        let dirs: Vec<String> = self.children.lock().unwrap().keys().cloned().collect();
+        dirs.into_iter().filter(|dir| self.stop(dir).unwrap_or(false)).collect()
+    }
+}
+
+/// `spawn_clone` and `stop_clone`
+pub(crate) fn register_supervisor_natives(engine: &mut Engine, ctx: &NativeContext) {
+    let NativeContext { audit, clones, supervisor, secrets, .. } = ctx;
+    // spawn_clone: run a clone as a headless peer serving IPC on `port`, optionally as a persona
+    let clones_clone = clones.clone();
+    let supervisor_clone = supervisor.clone();
+    let audit_clone = audit.clone();
+    let secrets_clone = secrets.clone();
+    engine.register_fn("spawn_clone", move |dir: &str, port: &str, persona: &str| -> String {
+        let spawned = (|| -> Result<String> {
+            let clone = clones_clone.find(dir)?
+                .ok_or_else(|| anyhow!("{} is not a registered clone; clone_agent it first", dir))?;
+            let port: u16 = port.trim().parse().map_err(|_| anyhow!("Invalid port '{}'", port))?;
+            let exe = std::env::current_exe()?;
+            let program = Path::new(&clone.dir).join(exe.file_name().unwrap_or_default());
+            if !program.exists() {
+                return Err(anyhow!("{:?} has no agent executable", clone.dir));
+            }
+            let mut args = vec!["serve".to_string(), "--port".to_string(), port.to_string()];
+            let persona = Some(persona.trim().to_string()).filter(|p| !p.is_empty())
+                .or_else(|| Lineage::load(Path::new(&clone.dir)).ok().and_then(|l| l.persona));
+            if let Some(persona) = persona {
+                args.extend(["--persona".to_string(), persona]);
+            }
+            // The clone reads its own .env and secrets; ours must not leak into it through the environment
+            let mut unset = env_file_keys(Path::new(".env"));
+            unset.extend(secrets_clone.names());
+            let pid = supervisor_clone.spawn(&program, &args, Path::new(&clone.dir), &unset)?;
+            let address = format!("127.0.0.1:{}", port);
+            clones_clone.set_process(&clone.dir, pid, &address)?;
+            Ok(format!("🚀 Clone {} started as pid {}, serving at {} (log: {}/{})", clone.dir, pid, address, clone.dir, CLONE_LOG))
+        })();
+        let result = spawned.unwrap_or_else(|e| format!("Error: {}", e));
+        audit_clone.record_or_warn("spawn_clone", &format!("{} on port {}", dir, port), &result);
+        result
+    });
+
+    let clones_clone = clones.clone();
+    let supervisor_clone = supervisor.clone();
+    let audit_clone = audit.clone();
+    engine.register_fn("stop_clone", move |clone: &str| -> String {
+        let result = match clones_clone.find(clone) {
+            Ok(Some(entry)) => match supervisor_clone.stop(&entry.dir) {
+                Ok(true) => format!("Clone {} stopped", entry.dir),
+                Ok(false) => format!("Clone {} is not running here", entry.dir),
+                Err(e) => format!("Error stopping clone {}: {}", entry.dir, e),
+            },
+            Ok(None) => format!("Error: no clone '{}'", clone),
+            Err(e) => format!("Error: {}", e),
+        };
+        audit_clone.record_or_warn("stop_clone", clone, &result);
+        result
+    });
+}
@@ -0,0 +1,61 @@
+use anyhow::{anyhow, Result};
+use opentelemetry::propagation::TextMapPropagator;
+use opentelemetry::trace::{TraceContextExt, TracerProvider as _};
+use opentelemetry::KeyValue;
+use opentelemetry_otlp::{SpanExporter, WithExportConfig};
+use opentelemetry_sdk::propagation::TraceContextPropagator;
+use opentelemetry_sdk::trace::{Tracer, TracerProvider};
+use opentelemetry_sdk::{runtime, Resource};
+use std::collections::HashMap;
+use tracing::Span;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+use crate::config::Config;
+
+/// Instrumentation scope of the exported spans
+const SCOPE: &str = "swarm_thing";
+
+/// Exports spans in batches to `telemetry.otlp_endpoint` over OTLP/gRPC; None without an
+/// endpoint. Must be called inside a Tokio runtime. Shut it down before exiting so the last
+/// batch is sent.
+pub fn tracer_provider(config: &Config) -> Result<Option<TracerProvider>> {
+    let Some(endpoint) = config.telemetry.otlp_endpoint.as_ref().filter(|e| !e.trim().is_empty()) else {
+        return Ok(None);
+    };
+    let exporter = SpanExporter::builder().with_tonic().with_endpoint(endpoint.trim()).build()
+        .map_err(|e| anyhow!("Cannot export traces to {}: {}", endpoint, e))?;
+    let service = config.telemetry.service_name.clone().unwrap_or_else(|| config.agent.name.clone());
+    Ok(Some(TracerProvider::builder()
+        .with_batch_exporter(exporter, runtime::Tokio)
+        .with_resource(Resource::new([KeyValue::new("service.name", service)]))
+        .build()))
+}
+
+/// The tracer `logging::traced_subscriber` hands spans to
+pub fn tracer(provider: &TracerProvider) -> Tracer {
+    provider.tracer(SCOPE)
+}
+
+/// `span`'s W3C `traceparent`, for the receiver to continue the trace; None when spans
+/// aren't exported
+pub fn traceparent(span: &Span) -> Option<String> {
+    let context = span.context();
+    if !context.span().span_context().is_valid() {
+        return None;
+    }
+    let mut carrier = HashMap::new();
+    TraceContextPropagator::new().inject_context(&context, &mut carrier);
+    carrier.remove("traceparent")
+}
+
+/// Make `span` a child of the remote span `traceparent` names. Missing or malformed values
+/// leave it a root.
+pub fn continue_trace(span: &Span, traceparent: Option<&str>) {
+    let Some(traceparent) = traceparent else {
+        return;
+    };
+    let carrier = HashMap::from([("traceparent".to_string(), traceparent.to_string())]);
+    let context = TraceContextPropagator::new().extract(&carrier);
+    if context.span().span_context().is_valid() {
+        span.set_parent(context);
+    }
+}
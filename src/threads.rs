@@ -0,0 +1,166 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::time::{SystemTime, UNIX_EPOCH};
+use crate::state::StateStore;
+use rhai::Engine;
+use crate::tools::NativeContext;
+
+const STATE_KEY: &str = "peer_threads";
+
+/// Oldest messages are dropped once a thread grows beyond this
+const MAX_THREAD_LEN: usize = 200;
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub enum Direction {
+    Incoming,
+    Outgoing,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ThreadMessage {
+    pub direction: Direction,
+    pub content: String,
+    /// Seconds since the Unix epoch
+    pub timestamp: u64,
+}
+
+/// Normalize a peer name or URL to a thread key, e.g.
+/// `http://127.0.0.1:9000/message` -> `127.0.0.1:9000`
+pub fn peer_key(peer: &str) -> String {
+    let peer = peer.trim();
+    let without_scheme = peer.split_once("://").map(|(_, rest)| rest).unwrap_or(peer);
+    without_scheme.split('/').next().unwrap_or(without_scheme).to_string()
+}
+
+/// The key of thread `thread_id` with `peer`: the peer key, plus `#` and the thread id for a
+/// named thread. Messages outside any thread share the bare peer key.
+pub fn thread_key(peer: &str, thread_id: Option<&str>) -> String {
+    match thread_id {
+        Some(thread_id) => format!("{}#{}", peer_key(peer), thread_id),
+        None => peer_key(peer),
+    }
+}
+
+/// Per-peer conversation history, persisted in the state store. Messages in a named thread
+/// (see `IpcMessage::threaded`) are kept in a history of their own.
+#[derive(Debug, Clone)]
+pub struct PeerThreads {
+    store: StateStore,
+}
+
+impl PeerThreads {
+    pub fn new(store: StateStore) -> Self {
+        Self { store }
+    }
+
+    /// The state store the threads are kept in
+    pub fn store(&self) -> &StateStore {
+        &self.store
+    }
+
+    pub fn record(&self, peer: &str, direction: Direction, content: &str) -> Result<()> {
+        self.record_in(peer, None, direction, content)
+    }
+
+    /// `record`, in thread `thread_id` with `peer`
+    pub fn record_in(&self, peer: &str, thread_id: Option<&str>, direction: Direction, content: &str) -> Result<()> {
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+        let key = thread_key(peer, thread_id);
+        self.store.update(STATE_KEY, |threads: &mut HashMap<String, Vec<ThreadMessage>>| {
+            let thread = threads.entry(key).or_default();
+            thread.push(ThreadMessage {
+                direction,
+                content: content.to_string(),
+                timestamp,
+            });
+            if thread.len() > MAX_THREAD_LEN {
+                let excess = thread.len() - MAX_THREAD_LEN;
+                thread.drain(..excess);
+            }
+        })
+    }
+
+    pub fn history(&self, peer: &str) -> Result<Vec<ThreadMessage>> {
+        self.history_in(peer, None)
+    }
+
+    /// Messages of thread `thread_id` with `peer`, oldest first
+    pub fn history_in(&self, peer: &str, thread_id: Option<&str>) -> Result<Vec<ThreadMessage>> {
+        let threads: HashMap<String, Vec<ThreadMessage>> = self.store.get(STATE_KEY)?.unwrap_or_default();
+        Ok(threads.get(&thread_key(peer, thread_id)).cloned().unwrap_or_default())
+    }
+
+    /// The last `limit` messages exchanged with `peer`
+    pub fn recent(&self, peer: &str, limit: usize) -> Result<Vec<ThreadMessage>> {
+        self.recent_in(peer, None, limit)
+    }
+
+    /// The last `limit` messages of thread `thread_id` with `peer`
+    pub fn recent_in(&self, peer: &str, thread_id: Option<&str>, limit: usize) -> Result<Vec<ThreadMessage>> {
+        let history = self.history_in(peer, thread_id)?;
+        let start = history.len().saturating_sub(limit);
+        Ok(history[start..].to_vec())
+    }
+
+    /// Every peer there is a conversation with, in a thread or not
+    pub fn peers(&self) -> Result<Vec<String>> {
+        let threads: HashMap<String, Vec<ThreadMessage>> = self.store.get(STATE_KEY)?.unwrap_or_default();
+        let mut peers: Vec<String> = threads.into_keys()
+            .map(|key| key.split_once('#').map(|(peer, _)| peer.to_string()).unwrap_or(key))
+            .collect();
+        peers.sort();
+        peers.dedup();
+        Ok(peers)
+    }
+
+    /// The named threads with `peer`, sorted
+    pub fn thread_ids(&self, peer: &str) -> Result<Vec<String>> {
+        let threads: HashMap<String, Vec<ThreadMessage>> = self.store.get(STATE_KEY)?.unwrap_or_default();
+        let prefix = format!("{}#", peer_key(peer));
+        let mut ids: Vec<String> = threads.into_keys().filter_map(|key| key.strip_prefix(&prefix).map(str::to_string)).collect();
+        ids.sort();
+        Ok(ids)
+    }
+
+    /// Render the last `limit` messages as a plain-text transcript
+    pub fn render(&self, peer: &str, limit: usize) -> Result<String> {
+        self.render_in(peer, None, limit)
+    }
+
+    /// `render`, for thread `thread_id` with `peer`
+    pub fn render_in(&self, peer: &str, thread_id: Option<&str>, limit: usize) -> Result<String> {
+        let messages = self.recent_in(peer, thread_id, limit)?;
+        let lines: Vec<String> = messages.iter().map(|m| {
+            let who = match m.direction {
+                Direction::Incoming => "them",
+                Direction::Outgoing => "me",
+            };
+            format!("[{}] {}", who, m.content)
+        }).collect();
+        Ok(lines.join("\n"))
+    }
+}
+
+/// `peer_history`
+pub(crate) fn register_thread_natives(engine: &mut Engine, ctx: &NativeContext) {
+    let NativeContext { threads, .. } = ctx;
+    let threads_clone = threads.clone();
+    engine.register_fn("peer_history", move |peer: &str| -> String {
+        let threads = threads_clone.thread_ids(peer).unwrap_or_default();
+        let others = if threads.is_empty() { String::new() } else { format!("\nThreads: {}", threads.join(", ")) };
+        match threads_clone.render(peer, 50) {
+            Ok(history) if history.is_empty() => format!("No conversation with '{}' yet{}", peer_key(peer), others),
+            Ok(history) => format!("{}{}", history, others),
+            Err(e) => format!("Error reading peer history: {}", e),
+        }
+    });
+    let threads_clone = threads.clone();
+    engine.register_fn("peer_history", move |peer: &str, thread_id: &str| -> String {
+        match threads_clone.render_in(peer, Some(thread_id.trim()), 50) {
+            Ok(history) if history.is_empty() => format!("No thread '{}' with '{}' yet", thread_id.trim(), peer_key(peer)),
+            Ok(history) => history,
+            Err(e) => format!("Error reading peer history: {}", e),
+        }
+    });
+}
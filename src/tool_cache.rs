@@ -0,0 +1,109 @@
+use anyhow::{Result, anyhow};
+use rhai::{Engine, AST};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::path::Path;
+use std::sync::{Arc, RwLock};
+
+/// A compiled tool, keyed by the hash of the source it was compiled from
+struct CompiledTool {
+    hash: u64,
+    result: std::result::Result<Arc<AST>, String>,
+}
+
+/// Per-tool AST cache. Each tool is compiled on first use into its own AST, so a
+/// compile error only disables that tool. Entries are recompiled when the file changes.
+#[derive(Clone, Default)]
+pub struct ToolCache {
+    entries: Arc<RwLock<HashMap<String, CompiledTool>>>,
+}
+
+fn hash_source(source: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    source.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Whether `source` contains a call to `name(...)` (not just a longer identifier containing it)
+fn calls_function(source: &str, name: &str) -> bool {
+    let is_ident = |c: char| c.is_alphanumeric() || c == '_';
+    source.match_indices(name).any(|(start, _)| {
+        let before_ok = source[..start].chars().next_back().map(|c| !is_ident(c)).unwrap_or(true);
+        let after = source[start + name.len()..].trim_start();
+        before_ok && after.starts_with('(')
+    })
+}
+
+impl ToolCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Compile the tool at `path` unless an up-to-date AST is already cached
+    pub fn get_or_compile(&self, engine: &Engine, name: &str, path: &Path) -> Result<Arc<AST>> {
+        let source = fs::read_to_string(path).map_err(|_| anyhow!("Tool '{}' not found", name))?;
+        self.compile_source(engine, name, &source)
+    }
+
+    /// Compile `source` for `name`, reusing the cached AST if the source is unchanged
+    pub fn compile_source(&self, engine: &Engine, name: &str, source: &str) -> Result<Arc<AST>> {
+        let hash = hash_source(source);
+        if let Some(entry) = self.entries.read().unwrap().get(name) {
+            if entry.hash == hash {
+                return entry.result.clone().map_err(|e| anyhow!("Rhai compile error in '{}': {}", name, e));
+            }
+        }
+
+        let result = engine.compile(source).map(Arc::new).map_err(|e| e.to_string());
+        self.entries.write().unwrap().insert(name.to_string(), CompiledTool {
+            hash,
+            result: result.clone(),
+        });
+        result.map_err(|e| anyhow!("Rhai compile error in '{}': {}", name, e))
+    }
+
+    /// Build the AST needed to run `name`: the tool itself plus the functions of every
+    /// tool it (transitively) calls, each compiled lazily from `tools_dir`
+    pub fn resolve(&self, engine: &Engine, tools_dir: &Path, name: &str, available: &[String]) -> Result<AST> {
+        let tool_ast = self.get_or_compile(engine, name, &tools_dir.join(format!("{}.rhai", name)))?;
+
+        let mut merged = AST::empty();
+        let mut visited = HashSet::from([name.to_string()]);
+        let mut queue = vec![name.to_string()];
+        while let Some(current) = queue.pop() {
+            let source = fs::read_to_string(tools_dir.join(format!("{}.rhai", current))).unwrap_or_default();
+            for dep in available {
+                if visited.contains(dep) || !calls_function(&source, dep) {
+                    continue;
+                }
+                visited.insert(dep.clone());
+                let ast = self.get_or_compile(engine, dep, &tools_dir.join(format!("{}.rhai", dep)))
+                    .map_err(|e| anyhow!("Tool '{}' depends on '{}', which is unavailable: {}", name, dep, e))?;
+                merged += ast.clone_functions_only();
+                queue.push(dep.clone());
+            }
+        }
+        // Merge the tool last so its own definitions win over same-named helpers in deps
+        merged += (*tool_ast).clone();
+        Ok(merged)
+    }
+
+    pub fn invalidate(&self, name: &str) {
+        self.entries.write().unwrap().remove(name);
+    }
+
+    pub fn clear(&self) {
+        self.entries.write().unwrap().clear();
+    }
+
+    /// Tools whose last compilation failed, with the error message
+    pub fn broken_tools(&self) -> Vec<(String, String)> {
+        let mut broken: Vec<(String, String)> = self.entries.read().unwrap().iter()
+            .filter_map(|(name, entry)| entry.result.as_ref().err().map(|e| (name.clone(), e.clone())))
+            .collect();
+        broken.sort();
+        broken
+    }
+}
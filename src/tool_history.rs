@@ -0,0 +1,151 @@
+use anyhow::{anyhow, Result};
+use git2::{Commit, IndexAddOption, Oid, Repository, Signature};
+use std::fs;
+use std::path::Path;
+use crate::backend::tool_extensions;
+use tracing::warn;
+use rhai::Engine;
+use crate::tools::{render_tool_log, revert_tool_file, NativeContext};
+
+/// Committer name when git has no `user.name` configured
+const COMMITTER: &str = "Swarm Thing";
+
+/// A commit that changed a tool
+#[derive(Debug, Clone, PartialEq)]
+pub struct ToolCommit {
+    pub id: String,
+    /// Unix time (seconds) of the commit
+    pub time: i64,
+    pub message: String,
+}
+
+impl ToolCommit {
+    pub fn short_id(&self) -> &str {
+        &self.id[..self.id.len().min(7)]
+    }
+}
+
+/// Whether `tools_dir` keeps a git history of its tools (see `init_history`)
+pub fn has_history(tools_dir: &Path) -> bool {
+    tools_dir.join(".git").exists()
+}
+
+/// Put `tools_dir` under git, committing the tools already in it. True when the history is new.
+pub fn init_history(tools_dir: &Path) -> Result<bool> {
+    if has_history(tools_dir) {
+        return Ok(false);
+    }
+    fs::create_dir_all(tools_dir)?;
+    let repo = Repository::init(tools_dir)?;
+    fs::write(tools_dir.join(".gitignore"), "__pycache__/\n")?;
+    let mut index = repo.index()?;
+    let patterns: Vec<String> = tool_extensions().iter().map(|ext| format!("*.{}", ext)).chain([".gitignore".to_string()]).collect();
+    index.add_all(&patterns, IndexAddOption::DEFAULT, None)?;
+    index.write()?;
+    commit_index(&repo, "Start tool history")?;
+    Ok(true)
+}
+
+fn signature(repo: &Repository) -> Result<Signature<'static>> {
+    Ok(repo.signature().or_else(|_| Signature::now(COMMITTER, "swarm-thing@localhost"))?.to_owned())
+}
+
+/// Commit the index, None when it matches HEAD
+fn commit_index(repo: &Repository, message: &str) -> Result<Option<Oid>> {
+    let tree = repo.find_tree(repo.index()?.write_tree()?)?;
+    let parent = repo.head().ok().and_then(|head| head.peel_to_commit().ok());
+    if parent.as_ref().is_some_and(|parent| parent.tree_id() == tree.id()) {
+        return Ok(None);
+    }
+    let signature = signature(repo)?;
+    let parents: Vec<&Commit> = parent.iter().collect();
+    Ok(Some(repo.commit(Some("HEAD"), &signature, &signature, message, &tree, &parents)?))
+}
+
+/// Commit tool `name` as it now is on disk, added, changed or removed. A no-op without a history
+/// or when nothing changed.
+pub fn commit_tool(tools_dir: &Path, name: &str, message: &str) -> Result<Option<String>> {
+    if !has_history(tools_dir) {
+        return Ok(None);
+    }
+    let repo = Repository::open(tools_dir)?;
+    let mut index = repo.index()?;
+    for ext in tool_extensions() {
+        let file = format!("{}.{}", name, ext);
+        if tools_dir.join(&file).exists() {
+            index.add_path(Path::new(&file))?;
+        } else if index.get_path(Path::new(&file), 0).is_some() {
+            index.remove_path(Path::new(&file))?;
+        }
+    }
+    index.write()?;
+    Ok(commit_index(&repo, message)?.map(|id| id.to_string()))
+}
+
+/// `commit_tool`, warning rather than failing: the change itself has already been made
+pub fn commit_tool_or_warn(tools_dir: &Path, name: &str, message: &str) {
+    if let Err(e) = commit_tool(tools_dir, name, message) {
+        warn!("Could not commit tool '{}' to the tool history: {}", name, e);
+    }
+}
+
+fn open(tools_dir: &Path) -> Result<Repository> {
+    if !has_history(tools_dir) {
+        return Err(anyhow!("{:?} has no tool history (set TOOLS_GIT=true)", tools_dir));
+    }
+    Ok(Repository::open(tools_dir)?)
+}
+
+/// The blob id of tool `name` in `commit`'s tree, with its extension
+fn tool_entry(commit: &Commit, name: &str) -> Option<(Oid, String)> {
+    let tree = commit.tree().ok()?;
+    tool_extensions().into_iter().find_map(|ext| tree.get_path(Path::new(&format!("{}.{}", name, ext))).ok().map(|entry| (entry.id(), ext)))
+}
+
+/// The commits that changed tool `name`, newest first
+pub fn tool_log(tools_dir: &Path, name: &str) -> Result<Vec<ToolCommit>> {
+    let repo = open(tools_dir)?;
+    let mut walk = repo.revwalk()?;
+    walk.push_head()?;
+    walk.set_sorting(git2::Sort::TOPOLOGICAL)?;
+    let mut commits = Vec::new();
+    for id in walk {
+        let commit = repo.find_commit(id?)?;
+        let before = commit.parent(0).ok().and_then(|parent| tool_entry(&parent, name));
+        if tool_entry(&commit, name) != before {
+            commits.push(ToolCommit {
+                id: commit.id().to_string(),
+                time: commit.time().seconds(),
+                message: commit.message().unwrap_or("").trim().to_string(),
+            });
+        }
+    }
+    Ok(commits)
+}
+
+/// Tool `name`'s source at `commit` (a full or abbreviated id, or any revision git knows), and
+/// whether it was a Python tool
+pub fn tool_at(tools_dir: &Path, name: &str, commit: &str) -> Result<(String, bool)> {
+    let repo = open(tools_dir)?;
+    let found = repo.revparse_single(commit).and_then(|object| object.peel_to_commit())
+        .map_err(|_| anyhow!("No commit '{}' in the tool history", commit))?;
+    let (blob, ext) = tool_entry(&found, name).ok_or_else(|| anyhow!("Tool '{}' is not in commit {}", name, commit))?;
+    let code = String::from_utf8(repo.find_blob(blob)?.content().to_vec())?;
+    Ok((code, ext == "py"))
+}
+
+/// `tool_log` and `revert_tool`
+pub(crate) fn register_history_natives(engine: &mut Engine, ctx: &NativeContext) {
+    let NativeContext { tools_dir, identity, audit, safety, cache, .. } = ctx;
+    // tool_log / revert_tool: the git history of a tool, with TOOLS_GIT
+    let tools_dir_clone = tools_dir.clone();
+    engine.register_fn("tool_log", move |name: &str| -> String {
+        tool_log(&tools_dir_clone, name).map(|commits| render_tool_log(name, &commits)).unwrap_or_else(|e| format!("Error: {}", e))
+    });
+    let tools_dir_clone = tools_dir.clone();
+    let (cache_clone, safety_clone, audit_clone, identity_clone) = (cache.clone(), safety.clone(), audit.clone(), identity.clone());
+    engine.register_fn("revert_tool", move |name: &str, commit: &str| -> String {
+        let safety = safety_clone.lock().unwrap().clone();
+        revert_tool_file(&tools_dir_clone, &cache_clone, &safety, &audit_clone, &identity_clone, name, commit).unwrap_or_else(|e| format!("Error: {}", e))
+    });
+}
@@ -0,0 +1,166 @@
+use anyhow::Result;
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+use tokio::sync::OnceCell;
+use crate::embeddings::{fnv1a, Embedder};
+use crate::vectorstore::{Record, VectorStore};
+use rhai::Engine;
+use crate::tools::{list_tool_names, NativeContext};
+
+/// A tool matching a `find_tool` query
+#[derive(Debug, Clone)]
+pub struct ToolMatch {
+    pub name: String,
+    pub score: f32,
+    pub description: String,
+}
+
+/// The leading comment block of a tool (`//` for Rhai, `#` for Python), minus the `filename:` and
+/// `version:` markers
+pub fn tool_description(source: &str) -> String {
+    source.lines()
+        .map(str::trim)
+        .skip_while(|l| l.is_empty())
+        .take_while(|l| l.starts_with("//") || l.starts_with('#'))
+        .map(|l| l.trim_start_matches('/').trim_start_matches('#').trim())
+        .filter(|l| !l.is_empty() && !l.starts_with("filename:") && !l.starts_with("version:"))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Embedding index over tool name, description and code, refreshed lazily when files change.
+/// Vectors are kept in a `VectorStore` keyed by tool name, with the source hash, description
+/// and embedding model as metadata.
+#[derive(Clone)]
+pub struct ToolIndex {
+    embedder: Arc<OnceCell<Embedder>>,
+    store: VectorStore,
+}
+
+impl ToolIndex {
+    /// In-memory index using `embedder`
+    pub fn new(embedder: Embedder) -> Self {
+        Self::with_store(Some(embedder), VectorStore::in_memory())
+    }
+
+    /// Index backed by `store`; without an embedder one is built from the environment on first use
+    pub fn with_store(embedder: Option<Embedder>, store: VectorStore) -> Self {
+        Self {
+            embedder: Arc::new(OnceCell::new_with(embedder)),
+            store,
+        }
+    }
+
+    /// Persistent index at `AGENT_TOOL_INDEX` (default `state/tool_index.jsonl`), so tools
+    /// aren't re-embedded on every start
+    pub fn from_env() -> Result<Self> {
+        let path = std::env::var("AGENT_TOOL_INDEX").unwrap_or_else(|_| "state/tool_index.jsonl".to_string());
+        Ok(Self::with_store(None, VectorStore::open(path)?))
+    }
+
+    async fn embedder(&self) -> &Embedder {
+        self.embedder.get_or_init(Embedder::from_env).await
+    }
+
+    /// Re-embed new or changed tools in `tools_dir` and forget deleted ones.
+    /// Returns how many tools were (re)embedded.
+    pub async fn refresh(&self, tools_dir: &Path, names: &[String]) -> Result<usize> {
+        let embedder = self.embedder().await;
+        let model = embedder.label();
+
+        let mut changed = Vec::new();
+        for name in names {
+            let Some(source) = ["rhai", "py"].iter()
+                .find_map(|ext| fs::read_to_string(tools_dir.join(format!("{}.{}", name, ext))).ok())
+            else {
+                continue;
+            };
+            let hash = format!("{:016x}", fnv1a(&source));
+            let up_to_date = self.store.get(name)?
+                .map(|r| r.metadata["hash"] == hash.as_str() && r.metadata["model"] == model.as_str())
+                .unwrap_or(false);
+            if !up_to_date {
+                changed.push((name.clone(), hash, tool_description(&source), source));
+            }
+        }
+
+        if !changed.is_empty() {
+            // Underscores split the name into words so `web_search` matches "search the web"
+            let documents: Vec<String> = changed.iter()
+                .map(|(name, _, description, source)| format!("{}\n{}\n{}", name.replace('_', " "), description, source))
+                .collect();
+            let vectors = embedder.embed_batch(&documents).await?;
+            let records = changed.iter().zip(vectors)
+                .map(|((name, hash, description, _), vector)| Record {
+                    id: name.clone(),
+                    vector,
+                    metadata: serde_json::json!({ "hash": hash, "description": description, "model": model }),
+                })
+                .collect();
+            self.store.add(records)?;
+        }
+
+        let removed: Vec<String> = self.store.records()?.into_iter()
+            .map(|r| r.id)
+            .filter(|id| !names.contains(id))
+            .collect();
+        self.store.delete(&removed)?;
+        Ok(changed.len())
+    }
+
+    /// Top `k` indexed tools by cosine similarity to `query`
+    pub async fn search(&self, query: &str, k: usize) -> Result<Vec<ToolMatch>> {
+        let embedder = self.embedder().await;
+        let query = embedder.embed(query).await?;
+        let model = embedder.label();
+        Ok(self.store.search_where(&query, k, |m| m["model"] == model.as_str())?
+            .into_iter()
+            .map(|hit| ToolMatch {
+                name: hit.id,
+                score: hit.score,
+                description: hit.metadata["description"].as_str().unwrap_or_default().to_string(),
+            })
+            .collect())
+    }
+}
+
+/// `list_tools` and `find_tool`
+pub(crate) fn register_discovery_natives(engine: &mut Engine, ctx: &NativeContext) {
+    let NativeContext { tools_dir, index, .. } = ctx;
+    // Tool Discovery
+    let tools_dir_clone = tools_dir.clone();
+    engine.register_fn("list_tools", move || -> String {
+        list_tool_names(&tools_dir_clone).join(", ")
+    });
+
+    // Semantic Tool Discovery
+    let search_k: usize = std::env::var("TOOL_SEARCH_K")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(5);
+    let index_clone = index.clone();
+    let tools_dir_clone = tools_dir.clone();
+    engine.register_fn("find_tool", move |query: &str| -> String {
+        let index = index_clone.clone();
+        let tools_dir = tools_dir_clone.clone();
+        let query = query.to_string();
+        std::thread::spawn(move || {
+            let rt = tokio::runtime::Runtime::new().unwrap();
+            rt.block_on(async {
+                let names = list_tool_names(&tools_dir);
+                if let Err(e) = index.refresh(&tools_dir, &names).await {
+                    return format!("Error indexing tools: {}", e);
+                }
+                match index.search(&query, search_k).await {
+                    Ok(matches) if matches.is_empty() => "No tools found".to_string(),
+                    Ok(matches) => matches.iter()
+                        .map(|m| format!("{} ({:.2}): {}", m.name, m.score, m.description))
+                        .collect::<Vec<_>>()
+                        .join("\n"),
+                    Err(e) => format!("Error searching tools: {}", e),
+                }
+            })
+        }).join().unwrap_or_else(|_| "Thread panic".to_string())
+    });
+}
@@ -1,20 +1,115 @@
 use anyhow::{Result, anyhow};
-use rhai::{Engine, Scope, AST};
+use rhai::{Dynamic, Engine};
 use std::fs;
-use std::path::PathBuf;
-use std::time::SystemTime;
-use std::sync::{Arc, Mutex, RwLock};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant, SystemTime};
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, Mutex};
 use crate::message::{ToolSafetyLevel, IpcMessage};
+use crate::jail::FsJail;
+use crate::state::StateStore;
+use crate::threads::PeerThreads;
+use crate::audit::AuditLog;
+use crate::command::{CommandApprover, CommandRunner};
+use crate::citations::Citations;
+use crate::changes::ChangeLog;
+use crate::backend::{add_tool_extension, rhai_signature, signature_of, tool_extensions, RhaiBackend, ToolBackend, ToolSignature};
+use crate::python::PythonBackend;
+use crate::tool_cache::ToolCache;
+use crate::llm::LlmClient;
+use crate::tool_index::{ToolIndex, ToolMatch};
+use crate::knowledge::KnowledgeBase;
+use crate::persona::{calls_function, capabilities_called, personas_dir, Capability, Persona, ToolPolicy};
+use crate::halt::{halt_clones, HaltSwitch};
+use crate::lineage::{CloneOptions, CloneRegistry};
+use crate::supervisor::CloneSupervisor;
+use crate::secrets::Secrets;
+use crate::config::{Config, PathsConfig, PolicyConfig, DEFAULT_CONFIG_FILE};
+use crate::journal::{millis, Journal, JournalEvent};
+use crate::mcp::{split_mcp_name, McpClients, McpTool};
+use crate::parser::{parse_response, ParsedAction, ToolBlock};
+use crate::auth::{IpcAuth, Sender};
+use crate::bundle::{read_bundle, write_bundle, BundleManifest, ImportOutcome, ImportReport, ImportedTool};
+use crate::review::ToolReview;
+use crate::tool_history::{commit_tool_or_warn, init_history, tool_at, tool_log, ToolCommit};
+use crate::safety::{Approval, SafetyPolicy};
+use crate::limits::ToolRateLimits;
+use crate::politeness::Politeness;
+use crate::ipc::{PendingCalls, TaskQueue};
+use crate::inbox::Inbox;
+use crate::outbox::Outbox;
+use crate::registry::{address_key, HeartbeatConfig, PeerRegistry};
+use crate::server::{ServerManager, SHUTDOWN_GRACE};
+use crate::trust::{TrustLevel, TrustStore};
+use crate::scheduler::Scheduler;
+use crate::jobs::{register_job_natives, BackgroundJob, JobQueue};
+use crate::cancel::Cancel;
+use crate::metrics::metrics;
+use crate::election::Election;
+use crate::encryption::{seal_to, unseal, AgentKeys};
+use crate::security_review::{review_client, review_tool, SecurityReview};
+use crate::sandbox::{run_sandboxed, SandboxLimits, SandboxReport};
+use crate::quarantine::Quarantine;
+use crate::provenance::{check_provenance, signature_for_share, store_signature, AuthorSignature};
+use tracing::{debug, info, info_span, warn};
+
+/// How many entries `show_audit` and `/audit` list at most
+pub const AUDIT_SHOW_LIMIT: usize = 50;
+
+/// Natives that only fetch from the web, so calls of one response that use nothing else may
+/// run at the same time
+const CONCURRENT_NATIVES: &[&str] = &["scrape_url", "search_arxiv", "search"];
+
+/// Natives that spend from their `[rate_limits]` budget themselves, on every call, however a
+/// tool reaches them
+const METERED_NATIVES: &[&str] = &[
+    "scrape_url", "search", "search_arxiv", "get_paper", "ingest_document", "analyze_image",
+    "send_message", "call_peer", "invoke_tool", "ensemble_ask", "broadcast_message", "delegate_task",
+];
+
+/// The error for a call over its `[rate_limits]` budget; `what` says what was called
+fn rate_limited(what: &str, per_minute: u32, wait: Duration) -> anyhow::Error {
+    let wait = wait.as_secs_f64().ceil().max(1.0) as u64;
+    anyhow!("{} is rate limited to {} call(s) per minute by the safety policy; try again in {}s", what, per_minute, wait)
+}
 
 /// A tool awaiting approval before installation
 #[derive(Debug, Clone)]
 pub struct PendingTool {
     pub name: String,
     pub code: String,
+    /// Verified agent name, or the claimed address marked "(unverified)"
     pub source_agent: String,
+    /// The sender's own server address, as given in the message's `from`
+    pub source_address: Option<String>,
+    /// Fingerprint of a verified sender's own key (its `Signature::agent_key`), or for a bundle
+    /// of the swarm key it was signed with
+    pub source_key: Option<String>,
     pub received_at: SystemTime,
     pub description: Option<String>,
     pub safety_level: ToolSafetyLevel,
+    /// The author's signature over the code, stored with the tool once installed
+    pub signature: Option<AuthorSignature>,
+    /// A model's security review, once `review_pending_tool` asked for one
+    pub security_review: Option<SecurityReview>,
+}
+
+impl PendingTool {
+    /// Where the tool came from, for listings and the audit log: `alpha [key 1a2b…] via 10.0.0.5:8080`
+    pub fn origin(&self) -> String {
+        let mut origin = self.source_agent.clone();
+        if let Some(key) = &self.source_key {
+            origin.push_str(&format!(" [key {}]", key));
+        }
+        if let Some(address) = self.source_address.as_ref().filter(|a| !self.source_agent.starts_with(a.as_str())) {
+            origin.push_str(&format!(" via {}", address));
+        }
+        if let Some(signature) = &self.signature {
+            origin.push_str(&format!(", signed by {}", signature.author));
+        }
+        origin
+    }
 }
 
 // Helper function for recursive directory copying
@@ -32,530 +127,2137 @@ fn copy_dir_recursive(src: &PathBuf, dst: &PathBuf) -> Result<()> {
             fs::copy(&path, &dest_path)?;
         }
     }
-    
-    Ok(())
-}
+    
+    Ok(())
+}
+
+/// Tools in `tools_dir` named in `wanted`, or calling natives of a capability named there,
+/// plus every tool those call
+fn select_tools(tools_dir: &Path, wanted: &[String]) -> Result<Vec<String>> {
+    let all = list_tool_names(tools_dir);
+    let source = |tool: &str| find_tool_file(tools_dir, tool).and_then(|path| fs::read_to_string(path).ok()).unwrap_or_default();
+    let mut pending = Vec::new();
+    for item in wanted.iter().map(|w| w.trim()) {
+        if all.iter().any(|t| t == item) {
+            pending.push(item.to_string());
+        } else if let Ok(capability) = serde_json::from_value::<Capability>(serde_json::Value::String(item.to_lowercase())) {
+            pending.extend(all.iter().filter(|t| capabilities_called(&source(t)).contains(&capability)).cloned());
+        } else {
+            return Err(anyhow!("'{}' is neither a tool nor a capability", item));
+        }
+    }
+    let mut selected = Vec::new();
+    while let Some(tool) = pending.pop() {
+        if selected.contains(&tool) {
+            continue;
+        }
+        let code = source(&tool);
+        pending.extend(all.iter().filter(|t| calls_function(&code, t)).cloned());
+        selected.push(tool);
+    }
+    selected.sort();
+    Ok(selected)
+}
+
+/// Copy the running agent into `target_dir`: its executable, tools, `.env` (less `secrets`)
+/// and `swarm.toml`, safety policy and personas, as `options` say
+pub(crate) fn copy_agent(target_dir: &str, config: &Config, options: &CloneOptions, secrets: &Secrets) -> String {
+    let target = PathBuf::from(target_dir);
+    let tools_src = config.paths.tools_dir.clone();
+
+    // Check the options before anything is written
+    let tools = match &options.tools {
+        Some(wanted) => match select_tools(&tools_src, wanted) {
+            Ok(tools) => Some(tools),
+            Err(e) => return format!("Error: {}", e),
+        },
+        None => None,
+    };
+    if let Some(persona) = &options.persona {
+        if let Err(e) = Persona::load(personas_dir(), persona) {
+            return format!("Error: {}", e);
+        }
+    }
+    if let Some(config) = options.config.as_ref().filter(|c| !c.is_file()) {
+        return format!("Error: config file {:?} not found", config);
+    }
+
+    info!("Cloning agent to: {}", target_dir);
+
+    // Create target directory
+    if let Err(e) = fs::create_dir_all(&target) {
+        return format!("Error creating directory: {}", e);
+    }
+
+    // 1. Copy executable
+    match std::env::current_exe() {
+        Ok(exe_path) => {
+            let exe_name = exe_path.file_name().unwrap_or_default();
+            let target_exe = target.join(exe_name);
+
+            if let Err(e) = fs::copy(&exe_path, &target_exe) {
+                return format!("Error copying executable: {}", e);
+            }
+
+            // Make executable on Unix
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::PermissionsExt;
+                if let Ok(metadata) = fs::metadata(&target_exe) {
+                    let mut perms = metadata.permissions();
+                    perms.set_mode(0o755);
+                    let _ = fs::set_permissions(&target_exe, perms);
+                }
+            }
+        },
+        Err(e) => return format!("Error getting executable path: {}", e),
+    }
+
+    // 2. Copy tools: all of them, or the chosen subset
+    let tools_dst = target.join("tools");
+    let copied = match &tools {
+        None if tools_src.exists() => copy_dir_recursive(&tools_src, &tools_dst),
+        None => Ok(()),
+        Some(tools) => fs::create_dir_all(&tools_dst).map_err(Into::into).and_then(|_| {
+            for tool in tools {
+                if let Some(path) = find_tool_file(&tools_src, tool) {
+                    fs::copy(&path, tools_dst.join(path.file_name().unwrap_or_default()))?;
+                }
+            }
+            Ok(())
+        }),
+    };
+    if let Err(e) = copied {
+        return format!("Error copying tools: {}", e);
+    }
+
+    // 3. Copy .env, or the given config, if asked; credentials stay behind
+    let env_src = if options.env { Some(PathBuf::from(".env")) } else { options.config.clone() };
+    if let Some(env_src) = env_src.filter(|p| p.exists()) {
+        if let Err(e) = secrets.copy_env_file(&env_src, &target.join(".env")) {
+            return format!("Error copying {:?}: {}", env_src, e);
+        }
+    }
+    // The clone's files sit at the default paths, and it gets its own port
+    if options.env && config.path.is_some() {
+        let mut clone_config = config.clone();
+        clone_config.paths = PathsConfig::default();
+        clone_config.policies.safety = PolicyConfig::default().safety;
+        clone_config.ipc.port = None;
+        let written = toml::to_string_pretty(&clone_config).map_err(anyhow::Error::from)
+            .and_then(|content| fs::write(target.join(DEFAULT_CONFIG_FILE), content).map_err(Into::into));
+        if let Err(e) = written {
+            return format!("Error writing the clone's {}: {}", DEFAULT_CONFIG_FILE, e);
+        }
+    }
+    if let Some(persona) = &options.persona {
+        let env_path = target.join(".env");
+        let existing = fs::read_to_string(&env_path).unwrap_or_default();
+        let mut lines: Vec<String> = existing.lines()
+            .filter(|line| !line.trim_start().starts_with("AGENT_PERSONA="))
+            .map(str::to_string)
+            .collect();
+        lines.push(format!("AGENT_PERSONA={}", persona));
+        if let Err(e) = fs::write(&env_path, lines.join("\n") + "\n") {
+            return format!("Error setting the clone's persona: {}", e);
+        }
+    }
+
+    // 4. Copy the safety policy, so the clone keeps the same limits
+    let policy_src = &config.policies.safety;
+    if policy_src.exists() {
+        let _ = fs::copy(policy_src, target.join("policy.toml"));
+    }
+
+    // 5. Copy personas, so spawn_clone can start the clone as one
+    let personas_src = personas_dir();
+    if personas_src.is_dir() {
+        if let Err(e) = copy_dir_recursive(&personas_src, &target.join("personas")) {
+            return format!("Error copying personas: {}", e);
+        }
+    }
+
+    let mut result = format!("✅ Agent cloned successfully to: {}", target_dir);
+    if let Some(tools) = &tools {
+        result.push_str(&format!(" with {} tool(s) [{}]", tools.len(), tools.join(", ")));
+    }
+    let env = match (&options.config, options.env) {
+        (Some(config), _) => format!("config from {}", config.display()),
+        (None, true) => ".env copied without secrets".to_string(),
+        (None, false) => "no .env".to_string(),
+    };
+    result.push_str(&format!(", {}", env));
+    result
+}
+
+/// Spends a call of a metered native, or refuses it over the rate limit
+pub(crate) type Meter = Arc<dyn Fn(&str) -> Result<()> + Send + Sync>;
+
+/// What the natives share with each other and with the manager, handed to the
+/// `register_*_natives` of the modules that own them
+pub(crate) struct NativeContext {
+    pub(crate) config: Config,
+    pub(crate) tools_dir: PathBuf,
+    /// Who tool commits are made by
+    pub(crate) identity: String,
+    pub(crate) jail: FsJail,
+    /// Writes and deletes are logged with a backup of the file, for undo_changes
+    pub(crate) changes: ChangeLog,
+    pub(crate) audit: AuditLog,
+    /// Shell commands: allowlisted, approved, time-limited and audited
+    pub(crate) commands: CommandRunner,
+    pub(crate) policy: Arc<Mutex<ToolPolicy>>,
+    pub(crate) safety: Arc<Mutex<SafetyPolicy>>,
+    pub(crate) meter: Meter,
+    pub(crate) pending_tools: Arc<Mutex<Vec<PendingTool>>>,
+    /// Sources read this session (scrape_url, search, get_paper), footnoted in exported reports
+    pub(crate) citations: Citations,
+    pub(crate) politeness: Politeness,
+    /// Knowledge base (RAG): ingest documents once, retrieve relevant chunks later
+    pub(crate) knowledge: KnowledgeBase,
+    pub(crate) index: ToolIndex,
+    /// Compiled tools are cached per file and compiled lazily on first call
+    pub(crate) cache: ToolCache,
+    pub(crate) threads: PeerThreads,
+    pub(crate) clones: CloneRegistry,
+    pub(crate) supervisor: CloneSupervisor,
+    pub(crate) secrets: Secrets,
+    pub(crate) outbox: Outbox,
+    pub(crate) tasks: TaskQueue,
+    pub(crate) peers: PeerRegistry,
+    pub(crate) inbox: Inbox,
+    pub(crate) calls: PendingCalls,
+    pub(crate) server: ServerManager,
+    pub(crate) halt: HaltSwitch,
+    pub(crate) election: Election,
+    /// With `LEADER_ELECTION`, the server runs leader elections alongside heartbeats
+    pub(crate) election_enabled: bool,
+    pub(crate) heartbeat: Option<HeartbeatConfig>,
+    pub(crate) keys: AgentKeys,
+    /// Our own server address, once start_server has run
+    pub(crate) local_address: Arc<Mutex<Option<String>>>,
+    pub(crate) trust: TrustStore,
+    pub(crate) jobs: JobQueue,
+    pub(crate) scheduler: Scheduler,
+}
+
+/// `inspect_tool`, `remove_tool` and the pending tool natives
+pub(crate) fn register_tool_natives(engine: &mut Engine, ctx: &NativeContext) {
+    let NativeContext { tools_dir, identity, audit, safety, pending_tools, cache, .. } = ctx;
+    // Tool Inspection: a summary of the tool's functions, or with "source" its code.
+    // The one-argument form also takes "name, source", as [TOOL: ...] passes it.
+    let inspect = {
+        let tools_dir = tools_dir.clone();
+        let safety = safety.clone();
+        move |tool_name: &str, mode: &str| -> String {
+            let tool_name = tool_name.trim();
+            let found = find_tool_file(&tools_dir, tool_name).and_then(|path| fs::read_to_string(&path).ok().map(|code| (path, code)));
+            let Some((path, code)) = found else {
+                return format!("Error: Tool '{}' not found", tool_name);
+            };
+            match mode.trim() {
+                "source" => code,
+                "" | "summary" => inspection(tool_name, &path, &file_signature(&path, tool_name, &code), &code, &safety.lock().unwrap()),
+                other => format!("Error: expected inspect_tool(name) or inspect_tool(name, source), not '{}'", other),
+            }
+        }
+    };
+    let inspect_clone = inspect.clone();
+    engine.register_fn("inspect_tool", move |tool_name: &str, mode: &str| -> String { inspect_clone(tool_name, mode) });
+    engine.register_fn("inspect_tool", move |request: &str| -> String {
+        match request.split_once(',') {
+            Some((tool_name, mode)) => inspect(tool_name, mode),
+            None => inspect(request, "summary"),
+        }
+    });
+
+    // Register remove_tool
+    let tools_dir_clone = tools_dir.clone();
+    let cache_clone = cache.clone();
+    let identity_clone = identity.clone();
+    engine.register_fn("remove_tool", move |name: &str| -> String {
+        if let Some(path) = find_tool_file(&tools_dir_clone, name) {
+            if let Err(e) = fs::remove_file(&path) {
+                return format!("Error deleting tool file: {}", e);
+            }
+            cache_clone.invalidate(name);
+            commit_tool_or_warn(&tools_dir_clone, name, &format!("remove_tool {} by {}", name, identity_clone));
+            format!("Tool '{}' removed successfully", name)
+        } else {
+            format!("Tool '{}' not found", name)
+        }
+    });
+
+    // Register Pending Tool Management Functions
+    
+    // list_pending_tools
+    let pending_clone = pending_tools.clone();
+    engine.register_fn("list_pending_tools", move || -> String {
+        render_pending_tools(&pending_clone.lock().unwrap())
+    });
+
+    // review_pending_tool: a model's security review, kept with the pending tool
+    let pending_clone = pending_tools.clone();
+    let audit_clone = audit.clone();
+    engine.register_fn("review_pending_tool", move |name: &str| -> String {
+        let pending = pending_clone.clone();
+        let audit = audit_clone.clone();
+        let name = name.to_string();
+        std::thread::spawn(move || {
+            let rt = tokio::runtime::Runtime::new().unwrap();
+            rt.block_on(async {
+                let client = match review_client().await {
+                    Ok(client) => client,
+                    Err(e) => return format!("Error creating review client: {}", e),
+                };
+                match review_pending_tool(&pending, &audit, &client, &name).await {
+                    Ok(review) => format!("Review of '{}':\n{}", name, review.render().trim_end()),
+                    Err(e) => format!("Error: {}", e),
+                }
+            })
+        }).join().unwrap_or_else(|_| "Thread panic".to_string())
+    });
+
+    // approve_tool: writing the file is enough, the cache compiles it on first call
+    let pending_clone = pending_tools.clone();
+    let tools_dir_clone = tools_dir.clone();
+    
+    let audit_clone = audit.clone();
+    let safety_clone = safety.clone();
+    engine.register_fn("approve_tool", move |name: &str| -> String {
+        let safety = safety_clone.lock().unwrap().clone();
+        approve_pending_tool(&pending_clone, &tools_dir_clone, &safety, &audit_clone, name).unwrap_or_else(|e| format!("Error: {}", e))
+    });
+
+    // test_pending_tool
+    let pending_clone = pending_tools.clone();
+    let audit_clone = audit.clone();
+    engine.register_fn("test_pending_tool", move |name: &str, sample_args: &str| -> String {
+        match test_pending_tool(&pending_clone, &audit_clone, name, &[sample_args.to_string()]) {
+            Ok(report) => report.render(),
+            Err(e) => format!("Error: {}", e),
+        }
+    });
+
+    // reject_tool
+    let pending_clone = pending_tools.clone();
+    let tools_dir_clone = tools_dir.clone();
+    let audit_clone = audit.clone();
+    engine.register_fn("reject_tool", move |name: &str| -> String {
+        reject_pending_tool(&pending_clone, &tools_dir_clone, &audit_clone, name).unwrap_or_else(|e| format!("Error: {}", e))
+    });
+}
+
+/// `share_tool` and `request_tool`
+pub(crate) fn register_sharing_natives(engine: &mut Engine, ctx: &NativeContext) {
+    let NativeContext { tools_dir, safety, pending_tools, peers, keys, local_address, trust, .. } = ctx;
+    // share_tool
+    let tools_dir_clone = tools_dir.clone();
+    let address_clone = local_address.clone();
+    let peers_clone = peers.clone();
+    let keys_clone = keys.clone();
+    let safety_clone = safety.clone();
+    engine.register_fn("share_tool", move |url: &str, tool_name: &str| -> String {
+        // 1. Get tool code
+        let path = tools_dir_clone.join(format!("{}.rhai", tool_name));
+        let code = match fs::read_to_string(&path) {
+            Ok(c) => c,
+            Err(_) => return format!("Error: Tool '{}' not found", tool_name),
+        };
+        
+        // 2. Validate to get safety level
+        // We need to duplicate validate_tool_code logic or make it available. 
+        // It's a standalone function, so we can call it.
+        // But it's defined below. We might need to move it up or use it.
+        // Rust allows calling functions defined later.
+        // But `validate_tool_code` is not in scope of the closure? It is if it's in the same module.
+        // Wait, `validate_tool_code` is private. Closures in `new` can call private functions of the module.
+        // But `validate_tool_code` returns `ToolSafetyLevel` which is imported.
+        
+        // We need to verify `validate_tool_code` is accessible.
+        // It is defined in the same file.
+        
+        // 3. Create message
+        // We need to determine safety level.
+        // Let's assume we can call validate_tool_code.
+        // Wait, I can't call a function inside the closure if it's not captured? 
+        // No, static functions are fine.
+        
+        // However, `validate_tool_code` is defined *outside* `impl ToolManager`.
+        // So it's just a function in the module.
+        
+        // We need to handle the async send inside sync closure.
+        // Use the same thread spawn trick as send_message.
+        
+        let url = url.to_string();
+        let tool_name = tool_name.to_string();
+        let code_clone = code.clone();
+        let policy = safety_clone.lock().unwrap().clone();
+        let from = address_clone.lock().unwrap().clone();
+        let signature = signature_for_share(&tools_dir_clone, &tool_name, &code, Some(&keys_clone));
+        // Peers that advertised a key get the code encrypted to it
+        let key = peers_clone.get(&address_key(&url)).and_then(|p| p.public_key);
+        let shared_code = match seal_to(key.as_deref(), &code) {
+            Ok(shared_code) => shared_code,
+            Err(e) => return format!("Error encrypting tool for {}: {}", address_key(&url), e),
+        };
+        
+        std::thread::spawn(move || {
+            let rt = tokio::runtime::Runtime::new().unwrap();
+            rt.block_on(async {
+                let safety = validate_tool_code(&code_clone, &policy);
+                
+                let msg = IpcMessage::tool_share(
+                    &tool_name,
+                    &shared_code,
+                    Some("Shared via share_tool".to_string()),
+                    safety
+                ).signed(signature);
+                
+                match crate::ipc::send_ipc_message(&url, &msg, from).await {
+                    Ok(received) => format!("Response: {}", received),
+                    Err(e) => format!("Error sending message: {}", e),
+                }
+            })
+        }).join().unwrap_or_else(|_| "Thread panic".to_string())
+    });
+
+    // request_tool: ask a peer for one of its tools; a shared tool joins the approval queue
+    let pending_clone = pending_tools.clone();
+    let address_clone = local_address.clone();
+    let trust_clone = trust.clone();
+    let keys_clone = keys.clone();
+    let tools_dir_clone = tools_dir.clone();
+    let safety_clone = safety.clone();
+    let quarantine = Quarantine::new(tools_dir);
+    engine.register_fn("request_tool", move |url: &str, tool_name: &str| -> String {
+        let peer = address_key(url);
+        if trust_clone.level(&peer).ok() == Some(TrustLevel::Blocked) {
+            return format!("Error: {} is blocked", peer);
+        }
+        let url = url.to_string();
+        let message = IpcMessage::tool_request(tool_name);
+        let from = address_clone.lock().unwrap().clone();
+        let reply = std::thread::spawn(move || {
+            let rt = tokio::runtime::Runtime::new().unwrap();
+            rt.block_on(crate::ipc::send_ipc_message(&url, &message, from))
+        }).join();
+        let received = match reply {
+            Ok(Ok(received)) => received,
+            Ok(Err(e)) => return format!("Error requesting tool: {}", e),
+            Err(_) => return "Thread panic".to_string(),
+        };
+        match IpcMessage::from_json_or_text(&received) {
+            IpcMessage::ToolShare { name, code, description, signature, .. } => {
+                if !is_tool_name(&name) {
+                    return format!("Error: {} sent a tool with an invalid name '{}'", peer, name);
+                }
+                let code = match unseal(Some(&keys_clone), &code) {
+                    Ok(code) => code,
+                    Err(e) => return format!("Error: cannot decrypt tool '{}' from {}: {}", name, peer, e),
+                };
+                // Judge the code ourselves rather than trusting the peer's rating
+                let safety_level = validate_tool_code(&code, &safety_clone.lock().unwrap());
+                let tool = PendingTool {
+                    name: name.clone(),
+                    code,
+                    source_agent: peer.clone(),
+                    source_address: Some(peer.clone()),
+                    source_key: None,
+                    received_at: SystemTime::now(),
+                    description,
+                    safety_level: safety_level.clone(),
+                    signature,
+                    security_review: None,
+                };
+                if let Err(e) = check_provenance(&tools_dir_clone, &name, &tool.code, tool.signature.as_ref()) {
+                    quarantine.add_or_warn(&tool, &format!("refused: {}", e));
+                    return format!("Error: tool '{}' from {} refused: {}", name, peer, e);
+                }
+                pending_clone.lock().unwrap().push(tool);
+                format!("Tool '{}' received from {} and queued for approval (Safety: {:?})", name, peer, safety_level)
+            }
+            IpcMessage::ToolRefused { name, reason } => format!("Error: {} refused tool '{}': {}", peer, name, reason),
+            _ => format!("Error: unexpected reply: {}", received),
+        }
+    });
+}
+
+/// The risk of tool file `path` with `code`: Python tools can do anything, so they are HighRisk
+pub(crate) fn tool_file_risk(path: &Path, code: &str, safety: &SafetyPolicy) -> ToolSafetyLevel {
+    match path.extension().and_then(|e| e.to_str()) {
+        Some("py") => ToolSafetyLevel::HighRisk,
+        _ => validate_tool_code(code, safety),
+    }
+}
+
+/// The risk of `code`: HighRisk when it is over the policy's size limit, else that of the
+/// policy's risk keywords it mentions
+pub(crate) fn validate_tool_code(code: &str, safety: &SafetyPolicy) -> ToolSafetyLevel {
+    if code.len() > safety.max_tool_bytes {
+        return ToolSafetyLevel::HighRisk;
+    }
+    safety.risk_keywords.level(code)
+}
+
+/// What the agent did about an LLM response
+#[derive(Debug, Clone, PartialEq)]
+pub enum ResponseAction {
+    /// A tool the response wrote, and whether it was saved
+    Created { name: String, outcome: std::result::Result<String, String> },
+    /// The tool call the response made, and its result
+    Ran { name: String, args: Vec<String>, result: std::result::Result<String, String>, duration_ms: u64 },
+}
+
+/// A tool the model wrote whose code fails, as `ToolManager::broken_tool` finds it
+#[derive(Debug, Clone, PartialEq)]
+pub struct BrokenTool {
+    pub name: String,
+    pub code: String,
+    pub python: bool,
+    /// The compile or runtime error
+    pub error: String,
+    /// The call that failed, when it failed at runtime
+    pub args: Option<Vec<String>>,
+}
+
+/// What the model is told about the tool calls it made: `Tool Output: ...` or `Tool Error: ...`
+/// for a single call, or one such line per call, naming the tool, for several. Empty when it
+/// made none.
+pub fn tool_feedback(results: &[(String, std::result::Result<String, String>)]) -> String {
+    let line = |name: Option<&str>, result: &std::result::Result<String, String>| {
+        let named = name.map(|n| format!(" ({})", n)).unwrap_or_default();
+        match result {
+            Ok(output) => format!("Tool Output{}: {}", named, output),
+            Err(e) => format!("Tool Error{}: {}", named, e),
+        }
+    };
+    match results {
+        [(_, result)] => line(None, result),
+        _ => results.iter().map(|(name, result)| line(Some(name), result)).collect::<Vec<_>>().join("\n"),
+    }
+}
+
+thread_local! {
+    /// When the tool running on this thread must stop by (see `set_tool_timeout`)
+    static RUN_DEADLINE: std::cell::Cell<Option<Instant>> = const { std::cell::Cell::new(None) };
+}
+
+/// Abort running scripts once `halt` trips, the turn is cancelled or their deadline passes,
+/// and stop `server` on a halt
+fn watch_halt(engine: &mut Engine, server: &ServerManager, halt: &HaltSwitch, cancel: &Cancel) {
+    let halt_clone = halt.clone();
+    let cancel = cancel.clone();
+    engine.on_progress(move |_| {
+        if let Some(reason) = halt_clone.reason() {
+            return Some(Dynamic::from(reason));
+        }
+        let overdue = RUN_DEADLINE.with(|deadline| deadline.get().is_some_and(|d| Instant::now() >= d));
+        (overdue || cancel.is_cancelled()).then(|| Dynamic::from("stopped"))
+    });
+    let server = server.clone();
+    halt.on_halt(Arc::new(move |_| {
+        // The halt may arrive on the server itself, which can't wait for its own stop
+        let server = server.clone();
+        std::thread::spawn(move || {
+            if let Some(address) = server.stop(SHUTDOWN_GRACE) {
+                info!("Server at {} stopped", address);
+            }
+        });
+    }));
+}
+
+pub(crate) fn list_tool_names(tools_dir: &Path) -> Vec<String> {
+    let extensions = tool_extensions();
+    let mut tools = Vec::new();
+    if let Ok(entries) = fs::read_dir(tools_dir) {
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let is_tool = path.extension()
+                .and_then(|s| s.to_str())
+                .map(|ext| extensions.iter().any(|e| e == ext))
+                .unwrap_or(false);
+            if is_tool {
+                if let Some(stem) = path.file_stem() {
+                    tools.push(stem.to_string_lossy().to_string());
+                }
+            }
+        }
+    }
+    tools
+}
+
+/// Find the source file for a tool, whichever backend it is written for
+pub(crate) fn find_tool_file(tools_dir: &Path, name: &str) -> Option<PathBuf> {
+    tool_extensions().iter()
+        .map(|ext| tools_dir.join(format!("{}.{}", name, ext)))
+        .find(|path| path.exists())
+}
+
+/// Which local tools this agent hands out when a peer sends a `ToolRequest`
+#[derive(Debug, Clone)]
+pub struct SharePolicy {
+    /// Riskiest tool to share; HighRisk tools are never shared whatever this says
+    pub max_risk: ToolSafetyLevel,
+    /// Tools never shared
+    pub deny: Vec<String>,
+}
+
+impl Default for SharePolicy {
+    fn default() -> Self {
+        Self { max_risk: ToolSafetyLevel::MediumRisk, deny: Vec::new() }
+    }
+}
+
+/// A risk ceiling from env var `key`: `safe`, `low_risk` or `medium_risk`
+fn risk_from_env(key: &str) -> Option<ToolSafetyLevel> {
+    let level = std::env::var(key).ok()?;
+    match level.trim().to_lowercase().replace(['_', '-'], "").as_str() {
+        "safe" => Some(ToolSafetyLevel::Safe),
+        "lowrisk" => Some(ToolSafetyLevel::LowRisk),
+        "mediumrisk" => Some(ToolSafetyLevel::MediumRisk),
+        other => {
+            warn!("Ignoring {}={}: expected safe, low_risk or medium_risk", key, other);
+            None
+        }
+    }
+}
+
+/// Comma-separated tool names from env var `key`
+fn tool_list_from_env(key: &str) -> Vec<String> {
+    std::env::var(key).unwrap_or_default()
+        .split(',')
+        .map(|t| t.trim().to_string())
+        .filter(|t| !t.is_empty())
+        .collect()
+}
+
+impl SharePolicy {
+    /// `SHARE_MAX_RISK` (`safe`, `low_risk` or `medium_risk`, default `medium_risk`) and
+    /// `SHARE_DENY` (comma-separated tool names)
+    pub fn from_env() -> Self {
+        let defaults = Self::default();
+        Self {
+            max_risk: risk_from_env("SHARE_MAX_RISK").unwrap_or(defaults.max_risk),
+            deny: tool_list_from_env("SHARE_DENY"),
+        }
+    }
+
+    /// Why `name` with source `code` may not be shared, if it may not; `safety` rates the code
+    pub fn refusal(&self, name: &str, code: &str, safety: &SafetyPolicy) -> Option<String> {
+        if self.deny.iter().any(|d| d == name) {
+            return Some("tool is not shared by this agent".to_string());
+        }
+        let level = validate_tool_code(code, safety);
+        if level == ToolSafetyLevel::HighRisk {
+            return Some("HighRisk tools are never shared".to_string());
+        }
+        if level > self.max_risk {
+            return Some(format!("tool is {:?}, above the share limit of {:?}", level, self.max_risk));
+        }
+        None
+    }
+}
+
+/// Which local tools peers may run with `ToolInvoke`. The persona's tool policy applies on top.
+#[derive(Debug, Clone)]
+pub struct InvokePolicy {
+    /// Riskiest tool peers may run; HighRisk tools never run for peers
+    pub max_risk: ToolSafetyLevel,
+    /// Tools peers may never run
+    pub deny: Vec<String>,
+}
+
+impl Default for InvokePolicy {
+    fn default() -> Self {
+        Self { max_risk: ToolSafetyLevel::Safe, deny: Vec::new() }
+    }
+}
+
+impl InvokePolicy {
+    /// `REMOTE_TOOLS_MAX_RISK` (`safe`, `low_risk` or `medium_risk`, default `safe`) and
+    /// `REMOTE_TOOLS_DENY` (comma-separated tool names)
+    pub fn from_env() -> Self {
+        let defaults = Self::default();
+        Self {
+            max_risk: risk_from_env("REMOTE_TOOLS_MAX_RISK").unwrap_or(defaults.max_risk),
+            deny: tool_list_from_env("REMOTE_TOOLS_DENY"),
+        }
+    }
+
+    /// `MCP_TOOLS_MAX_RISK` (default `safe`) and `MCP_TOOLS_DENY`, for the tools MCP clients may run
+    pub fn mcp_from_env() -> Self {
+        let defaults = Self::default();
+        Self {
+            max_risk: risk_from_env("MCP_TOOLS_MAX_RISK").unwrap_or(defaults.max_risk),
+            deny: tool_list_from_env("MCP_TOOLS_DENY"),
+        }
+    }
+
+    /// Why a peer may not run `name`, whose code (with the tools it calls) is `code`; `safety`
+    /// rates the code
+    pub fn refusal(&self, name: &str, code: &str, safety: &SafetyPolicy) -> Option<String> {
+        if self.deny.iter().any(|d| d == name) {
+            return Some("tool is not available to peers".to_string());
+        }
+        let level = validate_tool_code(code, safety);
+        if level == ToolSafetyLevel::HighRisk {
+            return Some("HighRisk tools never run for peers".to_string());
+        }
+        if level > self.max_risk {
+            return Some(format!("tool is {:?}, above the remote limit of {:?}", level, self.max_risk));
+        }
+        None
+    }
+}
+
+/// Asked before the agent runs a risky tool, with the exact call (`write_file(notes.txt, hi)`)
+/// and its safety level; true lets it run
+pub type ExecutionApprover = Arc<dyn Fn(&str, &ToolSafetyLevel) -> bool + Send + Sync>;
+
+/// Asked before a tool the model wrote is installed, with its diff and safety report; true
+/// installs it
+pub type InstallApprover = Arc<dyn Fn(&ToolReview) -> bool + Send + Sync>;
+
+/// What the `clone_agent` native runs
+pub(crate) type Cloner = Arc<dyn Fn(&str, &CloneOptions) -> String + Send + Sync>;
+
+/// Which tool runs the operator confirms as they happen, on top of approval at install time
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConfirmPolicy {
+    /// Least risky level that needs confirmation; None runs everything unasked
+    pub min_risk: Option<ToolSafetyLevel>,
+}
+
+impl Default for ConfirmPolicy {
+    fn default() -> Self {
+        Self { min_risk: Some(ToolSafetyLevel::MediumRisk) }
+    }
+}
+
+impl ConfirmPolicy {
+    /// Never ask (`--auto-approve`)
+    pub fn auto_approve() -> Self {
+        Self { min_risk: None }
+    }
+
+    /// `CONFIRM_RISK`: `low_risk`, `medium_risk` (default), `high_risk` or `off`
+    pub fn from_env() -> Self {
+        match std::env::var("CONFIRM_RISK") {
+            Ok(level) => Self::parse(&level),
+            Err(_) => Self::default(),
+        }
+    }
+
+    /// `low_risk`, `medium_risk`, `high_risk` or `off`; anything else is the default
+    pub fn parse(level: &str) -> Self {
+        match level.trim().to_lowercase().replace(['_', '-'], "").as_str() {
+            "lowrisk" => Self { min_risk: Some(ToolSafetyLevel::LowRisk) },
+            "mediumrisk" => Self { min_risk: Some(ToolSafetyLevel::MediumRisk) },
+            "highrisk" => Self { min_risk: Some(ToolSafetyLevel::HighRisk) },
+            "off" | "none" => Self::auto_approve(),
+            other => {
+                warn!("Ignoring CONFIRM_RISK={}: expected low_risk, medium_risk, high_risk or off", other);
+                Self::default()
+            }
+        }
+    }
+
+    pub fn requires(&self, level: &ToolSafetyLevel) -> bool {
+        self.min_risk.as_ref().map(|min| level >= min).unwrap_or(false)
+    }
+}
+
+/// Answer a peer's `ToolRequest` for `name`: a `ToolShare` with the tool's source, or a
+/// `ToolRefused` saying why not
+pub fn answer_tool_request(tools_dir: &Path, name: &str, policy: &SharePolicy, safety: &SafetyPolicy) -> IpcMessage {
+    if name.is_empty() || !name.chars().all(|c| c.is_alphanumeric() || c == '_') {
+        return IpcMessage::tool_refused(name, "invalid tool name");
+    }
+    let path = tools_dir.join(format!("{}.rhai", name));
+    let code = match fs::read_to_string(&path) {
+        Ok(code) => code,
+        Err(_) if find_tool_file(tools_dir, name).is_some() => {
+            return IpcMessage::tool_refused(name, "only Rhai tools can be shared");
+        }
+        Err(_) => return IpcMessage::tool_refused(name, "tool not found"),
+    };
+    if let Some(reason) = policy.refusal(name, &code, safety) {
+        return IpcMessage::tool_refused(name, reason);
+    }
+    let description = crate::tool_index::tool_description(&code);
+    let description = if description.is_empty() { None } else { Some(description) };
+    IpcMessage::tool_share(name, code.clone(), description, validate_tool_code(&code, safety))
+}
+
+/// Note what happened to a shared tool, with where it came from, in the audit log
+pub fn record_tool_decision(audit: &AuditLog, action: &str, tool: &PendingTool, outcome: &str) {
+    audit.record_or_warn(action, &format!("{} ({:?}) from {}", tool.name, tool.safety_level, tool.origin()), outcome);
+}
+
+/// Install the pending tool `name` unless `safety` refuses its code; writing the file is enough,
+/// the cache compiles it on first call
+pub fn approve_pending_tool(pending: &Mutex<Vec<PendingTool>>, tools_dir: &Path, safety: &SafetyPolicy, audit: &AuditLog, name: &str) -> Result<String> {
+    let mut tools = pending.lock().unwrap();
+    let index = tools.iter().position(|t| t.name == name).ok_or_else(|| anyhow!("Tool '{}' not found in pending queue", name))?;
+    if let Some(reason) = safety.refusal(&tools[index].code) {
+        return Err(anyhow!("Tool '{}' cannot be approved: {}", name, reason));
+    }
+    check_provenance(tools_dir, name, &tools[index].code, tools[index].signature.as_ref())
+        .map_err(|e| anyhow!("Tool '{}' cannot be approved: {}", name, e))?;
+    let tool = tools.remove(index);
+    let written = fs::write(tools_dir.join(format!("{}.rhai", tool.name)), &tool.code)
+        .and_then(|_| store_signature(tools_dir, &tool.name, tool.signature.as_ref()).map_err(std::io::Error::other));
+    let outcome = match &written {
+        Ok(()) => "installed".to_string(),
+        Err(e) => format!("error: {}", e),
+    };
+    record_tool_decision(audit, "approve_tool", &tool, &outcome);
+    written.map_err(|e| anyhow!("Cannot write tool file: {}", e))?;
+    commit_tool_or_warn(tools_dir, &tool.name, &format!("approve_tool {} ({:?}) from {}", tool.name, safety.classify(&tool.code), tool.origin()));
+    Ok(format!("Tool '{}' approved and installed.", name))
+}
+
+/// Take the pending tool `name` off the queue and into the quarantine of `tools_dir`
+pub fn reject_pending_tool(pending: &Mutex<Vec<PendingTool>>, tools_dir: &Path, audit: &AuditLog, name: &str) -> Result<String> {
+    let mut tools = pending.lock().unwrap();
+    let index = tools.iter().position(|t| t.name == name).ok_or_else(|| anyhow!("Tool '{}' not found in pending queue", name))?;
+    let tool = tools.remove(index);
+    record_tool_decision(audit, "reject_tool", &tool, "rejected");
+    Quarantine::new(tools_dir).add_or_warn(&tool, "rejected by operator");
+    Ok(format!("Tool '{}' rejected and moved to quarantine", name))
+}
+
+/// The approval queue as `list_pending_tools` shows it, with any reviews
+pub fn render_pending_tools(tools: &[PendingTool]) -> String {
+    if tools.is_empty() {
+        return "No tools pending approval.".to_string();
+    }
+    let mut output = String::from("Pending Tools:\n");
+    for (i, tool) in tools.iter().enumerate() {
+        output.push_str(&format!("{}. {} (Safety: {:?}) - From: {}\n", i + 1, tool.name, tool.safety_level, tool.origin()));
+        if let Some(desc) = &tool.description {
+            output.push_str(&format!("   Description: {}\n", desc));
+        }
+        if let Some(review) = &tool.security_review {
+            output.push_str(&review.render());
+        }
+    }
+    output
+}
+
+/// Have `client` review the pending tool `name` and keep the review with it
+pub async fn review_pending_tool(pending: &Mutex<Vec<PendingTool>>, audit: &AuditLog, client: &LlmClient, name: &str) -> Result<SecurityReview> {
+    let tool = pending.lock().unwrap().iter().find(|t| t.name == name).cloned()
+        .ok_or_else(|| anyhow!("Tool '{}' not found in pending queue", name))?;
+    let review = review_tool(client, &tool.name, &tool.code, tool.description.as_deref()).await?;
+    // The queue may have changed while the model was thinking
+    if let Some(queued) = pending.lock().unwrap().iter_mut().find(|t| t.name == name && t.code == tool.code) {
+        queued.security_review = Some(review.clone());
+    }
+    let outcome = format!("{} risk(s), {} suspicious pattern(s)", review.risks.len(), review.suspicious_patterns.len());
+    record_tool_decision(audit, "review_pending_tool", &tool, &outcome);
+    Ok(review)
+}
+
+/// Run the pending tool `name` with `args` in the sandbox, to see what it does before approving it
+pub fn test_pending_tool(pending: &Mutex<Vec<PendingTool>>, audit: &AuditLog, name: &str, args: &[String]) -> Result<SandboxReport> {
+    let tool = pending.lock().unwrap().iter().find(|t| t.name == name).cloned()
+        .ok_or_else(|| anyhow!("Tool '{}' not found in pending queue", name))?;
+    let report = run_sandboxed(&tool.name, &tool.code, args, &SandboxLimits::default());
+    let outcome = match report.violations.len() {
+        0 => "no violations".to_string(),
+        n => format!("{} violation(s)", n),
+    };
+    record_tool_decision(audit, "test_pending_tool", &tool, &outcome);
+    Ok(report)
+}
+
+/// Put tool `name` back as it was at `commit` in the tool history, unless `safety` now refuses
+/// that code, and commit the revert as done by `by`
+pub fn revert_tool_file(tools_dir: &Path, cache: &ToolCache, safety: &SafetyPolicy, audit: &AuditLog, by: &str, name: &str, commit: &str) -> Result<String> {
+    let (code, python) = tool_at(tools_dir, name, commit)?;
+    if let Some(reason) = safety.refusal(&code) {
+        audit.record_or_warn("revert_tool", &format!("{} to {}", name, commit), &format!("refused: {}", reason));
+        return Err(anyhow!("Tool '{}' cannot be reverted to {}: {}", name, commit, reason));
+    }
+    if let Some(path) = find_tool_file(tools_dir, name) {
+        fs::remove_file(path)?;
+    }
+    fs::write(tools_dir.join(format!("{}.{}", name, if python { "py" } else { "rhai" })), &code)?;
+    cache.invalidate(name);
+    commit_tool_or_warn(tools_dir, name, &format!("revert_tool {} to {} ({:?}) by {}", name, commit, safety.classify(&code), by));
+    audit.record_or_warn("revert_tool", &format!("{} to {}", name, commit), "reverted");
+    Ok(format!("Tool '{}' reverted to {}", name, commit))
+}
+
+/// A tool's history as `tool_log` shows it: one `id [time] message` line per commit
+pub fn render_tool_log(name: &str, commits: &[ToolCommit]) -> String {
+    if commits.is_empty() {
+        return format!("No history for tool '{}'", name);
+    }
+    commits.iter().map(|c| format!("{} [{}] {}", c.short_id(), c.time, c.message)).collect::<Vec<_>>().join("\n")
+}
+
+/// A tool as listed in the agent's public catalog (`GET /tools`)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CatalogEntry {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    pub safety_level: ToolSafetyLevel,
+    /// Whether a `ToolRequest` for it would be answered with its source
+    pub shareable: bool,
+}
+
+/// The tools in `tools_dir` peers may know about: all but the ones `policy` never shares
+pub fn tool_catalog(tools_dir: &Path, policy: &SharePolicy, safety: &SafetyPolicy) -> Vec<CatalogEntry> {
+    let mut names = list_tool_names(tools_dir);
+    names.sort();
+    names.dedup();
+    names.into_iter()
+        .filter(|name| !policy.deny.contains(name))
+        .filter_map(|name| {
+            let path = find_tool_file(tools_dir, &name)?;
+            let code = fs::read_to_string(&path).ok()?;
+            let description = crate::tool_index::tool_description(&code);
+            let is_rhai = path.extension().map(|ext| ext == "rhai").unwrap_or(false);
+            Some(CatalogEntry {
+                shareable: is_rhai && policy.refusal(&name, &code, safety).is_none(),
+                description: if description.is_empty() { None } else { Some(description) },
+                safety_level: tool_file_risk(&path, &code, safety),
+                name,
+            })
+        })
+        .collect()
+}
+
+/// The signature of the tool file at `path`, read without running it or needing its backend
+fn file_signature(path: &Path, name: &str, code: &str) -> ToolSignature {
+    match path.extension().and_then(|e| e.to_str()) {
+        Some("rhai") => rhai_signature(&Engine::new_raw(), code, name),
+        Some("py") => signature_of(code, "def", name),
+        _ => signature_of(code, "fn", name),
+    }
+}
+
+/// What `inspect_tool` says about tool `name`: how to call it, its helper functions and its risk
+fn inspection(name: &str, path: &Path, signature: &ToolSignature, code: &str, safety: &SafetyPolicy) -> String {
+    let mut lines = vec![signature.summary(name)];
+    if !signature.helpers.is_empty() {
+        lines.push("Helpers:".to_string());
+        lines.extend(signature.helpers.iter().map(|f| match &f.doc {
+            Some(doc) => format!("  {} - {}", f.call(), doc),
+            None => format!("  {}", f.call()),
+        }));
+    }
+    lines.push(format!("Safety: {:?}", tool_file_risk(path, code, safety)));
+    lines.push(format!("inspect_tool({}, source) shows the code", name));
+    lines.join("\n")
+}
+
+/// Whether `name` can be a tool's name: ASCII letters, digits and `_`, so it is safe as a file name
+pub fn is_tool_name(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+pub struct ToolManager {
+    rhai: RhaiBackend,
+    /// Backends for the other kinds of tool file, Python's when enabled
+    backends: Vec<Arc<dyn ToolBackend>>,
+    tools_dir: PathBuf,
+    jail: FsJail,
+    changes: ChangeLog,
+    threads: PeerThreads,
+    audit: AuditLog,
+    commands: CommandRunner,
+    confirm: ConfirmPolicy,
+    approver: Arc<std::sync::RwLock<Option<ExecutionApprover>>>,
+    /// This agent's name, for the tool history
+    identity: String,
+    install_approver: Arc<std::sync::RwLock<Option<InstallApprover>>>,
+    /// Runs for peers, schedules or jobs rather than the operator (see `set_unattended`)
+    unattended: bool,
+    index: ToolIndex,
+    policy: Arc<Mutex<ToolPolicy>>,
+    safety: Arc<Mutex<SafetyPolicy>>,
+    /// The call budgets, swapped for shared ones by `set_rate_limits`
+    rate_limits: Arc<std::sync::RwLock<ToolRateLimits>>,
+    halt: HaltSwitch,
+    election: Election,
+    cancel: Cancel,
+    tool_timeout: Option<Duration>,
+    clones: CloneRegistry,
+    supervisor: CloneSupervisor,
+    secrets: Secrets,
+    tasks: TaskQueue,
+    jobs: JobQueue,
+    parallel_tools: usize,
+    scheduler: Scheduler,
+    peers: PeerRegistry,
+    inbox: Inbox,
+    outbox: Outbox,
+    calls: PendingCalls,
+    server: ServerManager,
+    cloner: Cloner,
+    mcp: McpClients,
+    pub pending_tools: Arc<Mutex<Vec<PendingTool>>>,
+}
+
+impl ToolManager {
+    pub fn new() -> Result<Self> {
+        Self::with_config(&Config::from_env()?)
+    }
+
+    /// A manager for the tools directory, workspace and policies `config` names
+    pub fn with_config(config: &Config) -> Result<Self> {
+        let mut engine = Engine::new();
+        let tools_dir = config.paths.tools_dir.clone();
+        
+        // Initialize pending tools early so it can be captured
+        let pending_tools = Arc::new(Mutex::new(Vec::new()));
+        let tasks = TaskQueue::new();
+        let peers = PeerRegistry::from_env();
+        let inbox = Inbox::new();
+        let calls = PendingCalls::new();
+        let server = ServerManager::new();
+        let halt = HaltSwitch::new();
+        let (election, election_enabled) = Election::from_env();
+        let keys = AgentKeys::from_env().unwrap_or_else(|e| {
+            warn!("Cannot load the agent key ({}); using a temporary one", e);
+            AgentKeys::generate()
+        });
+        let cancel = Cancel::new();
+        watch_halt(&mut engine, &server, &halt, &cancel);
+        let policy = Arc::new(Mutex::new(ToolPolicy::default()));
+        let safety = Arc::new(Mutex::new(SafetyPolicy::load(&config.policies.safety)?));
+        // Spends a call of a `METERED_NATIVES` native, from inside the native
+        let rate_limits = Arc::new(std::sync::RwLock::new(ToolRateLimits::new()));
+        let meter: Meter = {
+            let (safety, rate_limits) = (safety.clone(), rate_limits.clone());
+            Arc::new(move |native: &str| -> Result<()> {
+                let per_minute = safety.lock().unwrap().rate_limits.get(native).copied().unwrap_or(0);
+                if per_minute == 0 {
+                    return Ok(());
+                }
+                let limits = rate_limits.read().unwrap().clone();
+                limits.check(native, per_minute).map_err(|wait| rate_limited(native, per_minute, wait))
+            })
+        };
+        
+        if !tools_dir.exists() {
+            fs::create_dir_all(&tools_dir)?;
+        }
+        if config.policies.tools_git && init_history(&tools_dir)? {
+            info!("Started a git history of the tools in {:?}", tools_dir);
+        }
+
+        // Natives are confined to the workspace jail
+        let jail = FsJail::new(&config.paths.workspace)?;
+        let audit = AuditLog::from_env()?;
+        // Scraping and ingesting share one politeness, so host budgets cover both
+        let politeness = Politeness::from_env().with_egress(safety.clone());
+        let threads = PeerThreads::new(StateStore::from_env()?);
+        let ctx = NativeContext {
+            config: config.clone(),
+            identity: config.agent.name.clone(),
+            changes: ChangeLog::new(jail.root()),
+            commands: CommandRunner::from_env(jail.root(), audit.clone()),
+            knowledge: KnowledgeBase::from_env()?.with_politeness(politeness.clone()),
+            citations: Citations::new(),
+            index: ToolIndex::from_env()?,
+            cache: ToolCache::new(),
+            clones: CloneRegistry::new(threads.store().clone()),
+            supervisor: CloneSupervisor::new(),
+            secrets: Secrets::from_env(),
+            outbox: Outbox::from_env()?,
+            local_address: Arc::new(Mutex::new(None)),
+            heartbeat: HeartbeatConfig::from_env(),
+            trust: TrustStore::new(StateStore::from_env()?),
+            jobs: JobQueue::new(),
+            scheduler: Scheduler::new(StateStore::from_env()?),
+            tools_dir, jail, audit, politeness, threads, policy, safety, meter, pending_tools,
+            tasks, peers, inbox, calls, server, halt, election, election_enabled, keys,
+        };
+
+        crate::jail::register_file_natives(&mut engine, &ctx);
+        crate::command::register_command_natives(&mut engine, &ctx);
+        crate::database::register_db_natives(&mut engine)?;
+        crate::data::register_data_natives(&mut engine, &ctx)?;
+        crate::citations::register_citation_natives(&mut engine, &ctx);
+        crate::report::register_report_natives(&mut engine, &ctx)?;
+        crate::memory::register_memory_natives(&mut engine)?;
+        crate::http_cache::register_web_natives(&mut engine, &ctx);
+        crate::llm::register_vision_natives(&mut engine, &ctx);
+        crate::knowledge::register_knowledge_natives(&mut engine, &ctx);
+        crate::documents::register_document_natives(&mut engine, &ctx);
+        crate::papers::register_paper_natives(&mut engine, &ctx);
+        crate::tool_index::register_discovery_natives(&mut engine, &ctx);
+        register_tool_natives(&mut engine, &ctx);
+        crate::ipc::register_messaging_natives(&mut engine, &ctx);
+        crate::ensemble::register_ensemble_natives(&mut engine, &ctx);
+        crate::outbox::register_outbox_natives(&mut engine, &ctx);
+        crate::threads::register_thread_natives(&mut engine, &ctx);
+        crate::server::register_server_natives(&mut engine, &ctx);
+        crate::registry::register_peer_natives(&mut engine, &ctx)?;
+        crate::blackboard::register_blackboard_natives(&mut engine, &ctx);
+        crate::election::register_election_natives(&mut engine, &ctx);
+        crate::ipc::register_task_natives(&mut engine, &ctx);
+        crate::inbox::register_inbox_natives(&mut engine, &ctx);
+        let cloner = crate::lineage::register_clone_natives(&mut engine, &ctx);
+        crate::secrets::register_secret_natives(&mut engine, &ctx);
+        crate::supervisor::register_supervisor_natives(&mut engine, &ctx);
+        crate::quarantine::register_quarantine_natives(&mut engine, &ctx);
+        crate::tool_history::register_history_natives(&mut engine, &ctx);
+        crate::trust::register_trust_natives(&mut engine, &ctx);
+        crate::audit::register_audit_natives(&mut engine, &ctx);
+        register_job_natives(&mut engine, &ctx.jobs);
+        crate::scheduler::register_schedule_natives(&mut engine, &ctx);
+        register_sharing_natives(&mut engine, &ctx);
+
+        let NativeContext {
+            tools_dir, identity, jail, changes, audit, commands, policy, safety, pending_tools, index, cache,
+            threads, clones, supervisor, secrets, outbox, tasks, peers, inbox, calls, server, halt, election,
+            jobs, scheduler, ..
+        } = ctx;
+        let backends = PythonBackend::from_env(jail.root()).into_iter()
+            .map(|python| Arc::new(python) as Arc<dyn ToolBackend>)
+            .collect();
+
+        Ok(Self {
+            rhai: RhaiBackend::new(engine, cache, &tools_dir),
+            backends,
+            tools_dir,
+            jail,
+            changes,
+            threads,
+            audit,
+            commands,
+            confirm: ConfirmPolicy::parse(&config.policies.confirm_risk),
+            approver: Arc::new(std::sync::RwLock::new(None)),
+            install_approver: Arc::new(std::sync::RwLock::new(None)),
+            unattended: false,
+            identity,
+            index,
+            policy,
+            safety,
+            rate_limits,
+            halt,
+            election,
+            cancel,
+            tool_timeout: Some(config.policies.tool_timeout_secs).filter(|s| *s > 0).map(Duration::from_secs),
+            clones,
+            supervisor,
+            secrets,
+            tasks,
+            jobs,
+            parallel_tools: config.agent.parallel_tools.max(1),
+            scheduler,
+            peers,
+            inbox,
+            outbox,
+            calls,
+            server,
+            cloner,
+            mcp: McpClients::new(config.mcp.servers.clone()),
+            pending_tools,
+        })
+    }
+
+    /// The workspace jail that file-system tools are confined to
+    pub fn jail(&self) -> &FsJail {
+        &self.jail
+    }
+
+    /// The file changes of this session's file tools, for `/changes` and `/undo`
+    pub fn changes(&self) -> &ChangeLog {
+        &self.changes
+    }
+
+    /// Conversation threads with peer agents
+    pub fn threads(&self) -> &PeerThreads {
+        &self.threads
+    }
+
+    /// Directory the tool files live in
+    pub fn tools_dir(&self) -> &Path {
+        &self.tools_dir
+    }
+
+    /// The external MCP servers whose tools run as `mcp::<server>::<tool>`
+    pub fn mcp_clients(&self) -> &McpClients {
+        &self.mcp
+    }
+
+    /// Share another manager's MCP connections, so each server is started once
+    pub fn set_mcp_clients(&mut self, mcp: McpClients) {
+        self.mcp = mcp;
+    }
+
+    /// The external MCP tools, starting the servers if needed
+    pub fn mcp_tools(&self) -> Vec<McpTool> {
+        self.mcp.tools()
+    }
+
+    /// Peers discovered through announcements
+    pub fn peers(&self) -> &PeerRegistry {
+        &self.peers
+    }
+
+    /// Who leads the swarm, as far as this agent knows
+    pub fn election(&self) -> &Election {
+        &self.election
+    }
+
+    /// Tasks delegated to this agent over IPC, and results of tasks it delegated
+    pub fn tasks(&self) -> &TaskQueue {
+        &self.tasks
+    }
+
+    /// Tool runs `execute_tool_background` queued, for `serve_jobs`
+    pub fn jobs(&self) -> &JobQueue {
+        &self.jobs
+    }
+
+    /// Queue background runs on another manager's jobs, e.g. a worker's on the REPL's
+    pub fn set_jobs(&mut self, jobs: JobQueue) {
+        register_job_natives(self.rhai.engine_mut(), &jobs);
+        self.jobs = jobs;
+    }
+
+    /// Jobs `schedule_task` saved, for `serve_schedules`
+    pub fn scheduler(&self) -> &Scheduler {
+        &self.scheduler
+    }
+
+    /// Text messages received by this agent's server
+    pub fn inbox(&self) -> &Inbox {
+        &self.inbox
+    }
+
+    /// Messages waiting to be redelivered to peers that were unreachable
+    pub fn outbox(&self) -> &Outbox {
+        &self.outbox
+    }
+
+    /// `call_peer` calls waiting for their reply
+    pub fn calls(&self) -> &PendingCalls {
+        &self.calls
+    }
+
+    /// The IPC server `start_server` runs
+    pub fn server(&self) -> &ServerManager {
+        &self.server
+    }
+
+    pub fn audit_log(&self) -> &AuditLog {
+        &self.audit
+    }
+
+    /// Install the callback that approves `run_command` invocations (denies all by default)
+    pub fn set_command_approver(&self, approver: CommandApprover) {
+        self.commands.set_approver(approver);
+    }
+
+    /// Ask `approver` before running tools `confirm_policy` covers. Without one, nobody is
+    /// asked, as when the manager is embedded without an operator.
+    pub fn set_execution_approver(&self, approver: ExecutionApprover) {
+        *self.approver.write().unwrap() = Some(approver);
+    }
+
+    /// Show `approver` tools the model writes before they're installed: every replacement of a
+    /// changed tool, and new tools `confirm_policy` covers. Without one, they install unasked.
+    pub fn set_install_approver(&self, approver: InstallApprover) {
+        *self.install_approver.write().unwrap() = Some(approver);
+    }
+
+    /// Ask `other`'s approvers, as they are now and as they're replaced later, for this
+    /// manager's commands, tool runs and installs
+    pub fn share_approvals(&mut self, other: &ToolManager) {
+        self.approver = other.approver.clone();
+        self.install_approver = other.install_approver.clone();
+        let commands = other.commands.clone();
+        self.commands.set_approver(Arc::new(move |command_line: &str| commands.approves(command_line)));
+    }
+
+    /// Mark the manager as working for peers, schedules or background jobs, with nobody at hand
+    /// to watch: HighRisk runs always go to the execution approver, and without one are refused
+    pub fn set_unattended(&mut self, unattended: bool) {
+        self.unattended = unattended;
+    }
+
+    pub fn is_unattended(&self) -> bool {
+        self.unattended
+    }
+
+    pub fn set_confirm_policy(&mut self, confirm: ConfirmPolicy) {
+        self.confirm = confirm;
+    }
+
+    pub fn confirm_policy(&self) -> &ConfirmPolicy {
+        &self.confirm
+    }
+
+    /// Restrict which tools may run (see `persona`)
+    pub fn set_policy(&mut self, policy: ToolPolicy) {
+        *self.policy.lock().unwrap() = policy;
+    }
+
+    pub fn policy(&self) -> ToolPolicy {
+        self.policy.lock().unwrap().clone()
+    }
+
+    /// The emergency stop shared by this manager's tools and server
+    pub fn halt_switch(&self) -> &HaltSwitch {
+        &self.halt
+    }
+
+    /// Ctrl-C for the tools this manager runs: a cancelled turn stops them
+    pub fn cancel(&self) -> &Cancel {
+        &self.cancel
+    }
+
+    /// How long one tool run may take, None for no limit (`policies.tool_timeout_secs`). A
+    /// script stops between operations, so a native call it is waiting on finishes first.
+    pub fn set_tool_timeout(&mut self, timeout: Option<Duration>) {
+        self.tool_timeout = timeout;
+    }
+
+    /// Share another manager's emergency stop, e.g. the REPL's with the task runner's
+    pub fn set_halt_switch(&mut self, halt: HaltSwitch) {
+        watch_halt(self.rhai.engine_mut(), &self.server, &halt, &self.cancel);
+        self.halt = halt;
+    }
+
+    /// Clones made by `clone_agent` or registered with `register_clone`
+    pub fn clones(&self) -> &CloneRegistry {
+        &self.clones
+    }
+
+    /// Credentials, from the environment or the OS keyring
+    pub fn secrets(&self) -> &Secrets {
+        &self.secrets
+    }
+
+    /// Clone processes started with `spawn_clone`
+    pub fn supervisor(&self) -> &CloneSupervisor {
+        &self.supervisor
+    }
+
+    /// Trip the emergency stop and, with `clones`, send a signed `Halt` to every clone with a
+    /// known address, then kill the clones this agent spawned. Returns a report for the operator.
+    pub fn halt(&self, reason: &str, clones: bool) -> String {
+        let from = self.server.status().filter(|s| s.running).map(|s| s.address);
+        if !self.halt.halt(reason) {
+            return "Already halted".to_string();
+        }
+        self.audit.record_or_warn("halt", reason, if clones { "halted, with clones" } else { "halted" });
+        let mut report = format!("🛑 Halted: {}", reason);
+        if clones {
+            let registry = self.clones.clone();
+            let reason = reason.to_string();
+            let lines = std::thread::spawn(move || {
+                let rt = tokio::runtime::Runtime::new().unwrap();
+                rt.block_on(halt_clones(&registry, &reason, from))
+            }).join().unwrap_or_else(|_| vec!["Thread panic".to_string()]);
+            if lines.is_empty() {
+                report.push_str("\nNo clones registered");
+            }
+            for line in lines {
+                report.push_str(&format!("\n{}", line));
+            }
+            for dir in self.supervisor.stop_all() {
+                report.push_str(&format!("\n{}: process stopped", dir));
+            }
+        }
+        report
+    }
+
+    /// Reset the emergency stop; false if it wasn't tripped. A stopped server stays stopped.
+    pub fn resume(&self) -> bool {
+        let resumed = self.halt.resume();
+        if resumed {
+            self.audit.record_or_warn("resume", "", "resumed");
+        }
+        resumed
+    }
+
+    /// Replace the operator's safety policy (loaded from `SAFETY_POLICY` at startup)
+    pub fn set_safety_policy(&self, safety: SafetyPolicy) {
+        *self.safety.lock().unwrap() = safety;
+    }
+
+    pub fn safety_policy(&self) -> SafetyPolicy {
+        self.safety.lock().unwrap().clone()
+    }
+
+    /// The policy itself rather than a copy, so holders see what `set_safety_policy` changes
+    pub(crate) fn shared_safety_policy(&self) -> &Arc<Mutex<SafetyPolicy>> {
+        &self.safety
+    }
+
+    /// The per-minute call budgets of the safety policy's `[rate_limits]`; clones spend from
+    /// the same budgets
+    pub fn rate_limits(&self) -> ToolRateLimits {
+        self.rate_limits.read().unwrap().clone()
+    }
+
+    /// Spend from `limits`, e.g. another manager's, so the managers of one agent share one
+    /// budget per tool instead of each having its own
+    pub fn set_rate_limits(&self, limits: ToolRateLimits) {
+        *self.rate_limits.write().unwrap() = limits;
+    }
 
-fn validate_tool_code(code: &str) -> ToolSafetyLevel {
-    // Basic validation logic
-    if code.len() > 10_000 {
-        return ToolSafetyLevel::HighRisk; // Too large
+    /// Whether `name` is a tool file or a built-in native
+    pub fn has_tool(&self, name: &str) -> bool {
+        if split_mcp_name(name).is_some() {
+            return self.mcp.find(name).is_some();
+        }
+        self.list_tools().iter().any(|t| t == name) || self.rhai.has_native(name)
     }
-    
-    // Check for risky keywords
-    if code.contains("write_file") || 
-       code.contains("clone_agent") || 
-       code.contains("start_server") ||
-       code.contains("std::process") {
-        return ToolSafetyLevel::HighRisk;
+
+    /// Check that `name` exists and the current policy allows running it
+    pub fn check_tool(&self, name: &str) -> Result<()> {
+        if !self.has_tool(name) {
+            return Err(anyhow!("Tool '{}' not found", name));
+        }
+        self.check_policy(name)
     }
-    
-    if code.contains("read_file") || code.contains("scrape_url") {
-        return ToolSafetyLevel::MediumRisk;
+
+    /// Reject `name` if it, or any tool it calls, uses a native, capability or risk level
+    /// the safety policy or tool policy forbids
+    fn check_policy(&self, name: &str) -> Result<()> {
+        let (code, python) = self.reachable(name);
+        let safety = self.safety_policy();
+        if let Some(native) = safety.banned_call(&code) {
+            return Err(anyhow!("Tool '{}' is not allowed: {} is banned by the safety policy", name, native));
+        }
+        if let Some((capability, Approval::Deny)) = safety.approval_for(&code) {
+            return Err(anyhow!("Tool '{}' is not allowed: the safety policy denies the {:?} capability", name, capability));
+        }
+        let policy = self.policy();
+        if policy.is_unrestricted() {
+            return Ok(());
+        }
+        if let Some((native, capability)) = policy.denied_native(&code) {
+            return Err(anyhow!("Tool '{}' is not allowed: {} needs the {:?} capability", name, native, capability));
+        }
+        let risk = if python { ToolSafetyLevel::HighRisk } else { validate_tool_code(&code, &self.safety_policy()) };
+        if risk > policy.max_risk {
+            return Err(anyhow!("Tool '{}' is not allowed: {:?} exceeds the {:?} limit", name, risk, policy.max_risk));
+        }
+        Ok(())
     }
-    
-    if code.contains("send_message") {
-        return ToolSafetyLevel::LowRisk;
+
+    /// Source of `name` and every tool it calls, with natives as bare calls
+    fn reachable_source(&self, name: &str) -> String {
+        self.reachable(name).0
     }
-    
-    // Default to Safe if just pure computation
-    ToolSafetyLevel::Safe
-}
 
-fn load_all_tools(tools_dir: &PathBuf) -> Result<AST> {
-    let engine = Engine::new();
-    let mut combined_ast = engine.compile("").map_err(|e| anyhow::anyhow!("Rhai init error: {}", e))?;
-    
-    if tools_dir.exists() {
-        for entry in fs::read_dir(tools_dir)? {
-            let entry = entry?;
-            let path = entry.path();
-            if path.extension().and_then(|s| s.to_str()) == Some("rhai") {
-                let script = fs::read_to_string(&path)?;
-                let ast = engine.compile(&script).map_err(|e| anyhow::anyhow!("Rhai compile error in {:?}: {}", path, e))?;
-                combined_ast += ast;
+    /// `reachable_source`, and whether any of those tools is a Python tool
+    fn reachable(&self, name: &str) -> (String, bool) {
+        let tools = self.list_tools();
+        let mut python = false;
+        let mut code = String::new();
+        let mut seen = std::collections::HashSet::new();
+        let mut pending = vec![name.to_string()];
+        while let Some(tool) = pending.pop() {
+            if !seen.insert(tool.clone()) {
+                continue;
+            }
+            let path = find_tool_file(&self.tools_dir, &tool);
+            python |= path.as_ref().is_some_and(|path| path.extension().is_some_and(|e| e == "py"));
+            match path.and_then(|path| fs::read_to_string(path).ok()) {
+                Some(source) => {
+                    pending.extend(tools.iter().filter(|t| calls_function(&source, t)).cloned());
+                    code.push_str(&source);
+                    code.push('\n');
+                }
+                // Not a tool file: a direct call to a native
+                None => code.push_str(&format!("{}()\n", tool)),
             }
         }
+        (code, python)
     }
-    Ok(combined_ast)
-}
 
-pub struct ToolManager {
-    engine: Engine,
-    global_ast: Arc<RwLock<AST>>,
-    tools_dir: PathBuf,
-    pub pending_tools: Arc<Mutex<Vec<PendingTool>>>,
-}
+    /// Tools an outside caller may run under `invoke`: the tool files, plus those of `natives`
+    /// that exist, sorted
+    pub fn invocable_tools(&self, invoke: &InvokePolicy, natives: &[String]) -> Vec<String> {
+        let mut names = self.list_tools();
+        names.extend(natives.iter().filter(|n| self.has_tool(n)).cloned());
+        names.sort();
+        names.dedup();
+        names.retain(|name| self.check_policy(name).is_ok() && invoke.refusal(name, &self.reachable_source(name), &self.safety_policy()).is_none());
+        names
+    }
 
-impl ToolManager {
-    pub fn new() -> Result<Self> {
-        let mut engine = Engine::new();
-        let tools_dir = PathBuf::from("tools");
-        
-        // Initialize pending tools early so it can be captured
-        let pending_tools = Arc::new(Mutex::new(Vec::new()));
-        
-        if !tools_dir.exists() {
-            fs::create_dir(&tools_dir)?;
+    /// Parameter names of `name`, in order: those of a tool file's function, or `arg1`, `arg2`...
+    /// for a native
+    pub fn tool_params(&self, name: &str) -> Vec<String> {
+        match find_tool_file(&self.tools_dir, name) {
+            Some(_) => self.tool_signature(name).map(|signature| signature.params).unwrap_or_default(),
+            None => self.rhai.native_params(name),
         }
+    }
 
-        // Register standard tools
-        engine.register_fn("read_file", |path: &str| -> String {
-            fs::read_to_string(path).unwrap_or_else(|e| format!("Error reading file: {}", e))
-        });
+    /// What the backend of tool file `name` reads from it, None for natives and tools no
+    /// registered backend runs
+    pub fn tool_signature(&self, name: &str) -> Option<ToolSignature> {
+        let path = find_tool_file(&self.tools_dir, name)?;
+        let code = fs::read_to_string(&path).ok()?;
+        Some(self.backend_for(&path)?.inspect(name, &code))
+    }
 
-        engine.register_fn("write_file", |path: &str, content: &str| -> String {
-            fs::write(path, content).map(|_| "File written successfully".to_string())
-                .unwrap_or_else(|e| format!("Error writing file: {}", e))
-        });
-        
-        // Simple search mock (since implementing real search requires an API key)
-        // In a real app, we'd use reqwest to call Google/Bing/SerpApi
-        engine.register_fn("search", |query: &str| -> String {
-            println!("Searching for: {}", query);
-            format!("Mock search results for '{}': \n1. Rust is a systems programming language.\n2. Rhai is an embedded scripting language.", query)
-        });
+    /// A line per tool for the system prompt: `name(params) - description` for tool files, other
+    /// entries (natives, MCP signatures) as they are
+    pub fn prompt_signatures(&self, tools: &[String]) -> Vec<String> {
+        tools.iter()
+            .map(|name| self.tool_signature(name).map(|s| s.summary(name)).unwrap_or_else(|| name.clone()))
+            .collect()
+    }
 
-        // Real Web Scraper
-        engine.register_fn("scrape_url", |url: &str| -> String {
-            println!("Scraping URL: {}", url);
-            // Note: In a real async app, we should use async reqwest, but Rhai functions are sync.
-            // We use blocking reqwest here for simplicity in this demo, or spawn a thread.
-            // For this MVP, we'll use std::process::Command to curl or just use blocking reqwest if enabled.
-            // Since we didn't enable blocking feature, let's use a quick hack: spawn a runtime for this call.
-            
-            let url = url.to_string();
-            std::thread::spawn(move || {
-                let rt = tokio::runtime::Runtime::new().unwrap();
-                rt.block_on(async {
-                    match reqwest::get(&url).await {
-                        Ok(resp) => {
-                            match resp.text().await {
-                                Ok(text) => {
-                                    let document = scraper::Html::parse_document(&text);
-                                    let selector = scraper::Selector::parse("body").unwrap();
-                                    if let Some(body) = document.select(&selector).next() {
-                                        // Simple text extraction
-                                        body.text().collect::<Vec<_>>().join(" ")
-                                            .split_whitespace().take(200).collect::<Vec<_>>().join(" ") // Limit to 200 words
-                                    } else {
-                                        "No body found".to_string()
-                                    }
-                                },
-                                Err(e) => format!("Error reading text: {}", e)
-                            }
-                        },
-                        Err(e) => format!("Error fetching URL: {}", e)
-                    }
-                })
-            }).join().unwrap_or_else(|_| "Thread panic".to_string())
-        });
+    /// Run `name` for a peer's `ToolInvoke` if `invoke` and the tool policy allow it,
+    /// answering with a `ToolOutput`
+    pub fn invoke_for_peer(&self, name: &str, args: Vec<String>, invoke: &InvokePolicy) -> IpcMessage {
+        if name.is_empty() || !name.chars().all(|c| c.is_alphanumeric() || c == '_') {
+            return IpcMessage::tool_output(name, Err("invalid tool name".to_string()));
+        }
+        if let Err(e) = self.check_tool(name) {
+            return IpcMessage::tool_output(name, Err(e.to_string()));
+        }
+        if let Some(reason) = invoke.refusal(name, &self.reachable_source(name), &self.safety_policy()) {
+            return IpcMessage::tool_output(name, Err(reason));
+        }
+        IpcMessage::tool_output(name, self.execute_tool(name, args).map_err(|e| e.to_string()))
+    }
 
-        // Tool Discovery
-        let tools_dir_clone = tools_dir.clone();
-        engine.register_fn("list_tools", move || -> String {
-            let mut tools = Vec::new();
-            if let Ok(entries) = fs::read_dir(&tools_dir_clone) {
-                for entry in entries {
-                    if let Ok(entry) = entry {
-                        let path = entry.path();
-                        if path.extension().and_then(|s| s.to_str()) == Some("rhai") {
-                            if let Some(stem) = path.file_stem() {
-                                tools.push(stem.to_string_lossy().to_string());
-                            }
-                        }
-                    }
-                }
-            }
-            tools.join(", ")
-        });
+    /// Drop all cached ASTs; tools are recompiled lazily on their next call
+    pub fn load_tools(&mut self) -> Result<()> {
+        self.rhai.cache().clear();
+        Ok(())
+    }
 
-        // Tool Inspection
-        let tools_dir_clone2 = tools_dir.clone();
-        engine.register_fn("inspect_tool", move |tool_name: &str| -> String {
-            let path = tools_dir_clone2.join(format!("{}.rhai", tool_name));
-            match fs::read_to_string(&path) {
-                Ok(content) => content,
-                Err(_) => format!("Error: Tool '{}' not found", tool_name),
+    /// Compile every tool now and return the ones that fail, with their errors
+    pub fn check_tools(&self) -> Vec<(String, String)> {
+        let mut broken = Vec::new();
+        for name in self.list_tools() {
+            let path = self.tools_dir.join(format!("{}.rhai", name));
+            if path.exists() {
+                let _ = self.rhai.cache().get_or_compile(self.rhai.engine(), &name, &path);
+                continue;
             }
-        });
+            let Some(path) = find_tool_file(&self.tools_dir, &name) else { continue };
+            let (Some(backend), Ok(code)) = (self.backend_for(&path), fs::read_to_string(&path)) else { continue };
+            if let Err(e) = backend.compile(&name, &code) {
+                broken.push((name, e.to_string()));
+            }
+        }
+        broken.extend(self.rhai.cache().broken_tools());
+        broken.sort();
+        broken
+    }
 
-        // IPC Tools
-        engine.register_fn("send_message", |url: &str, message: &str| -> String {
-            println!("📤 Sending message to {}: {}", url, message);
-            
-            // Use blocking reqwest in a thread
-            let url = url.to_string();
-            let message = message.to_string();
-            
-            std::thread::spawn(move || {
-                let rt = tokio::runtime::Runtime::new().unwrap();
-                rt.block_on(async {
-                    let client = reqwest::Client::new();
-                    let payload = serde_json::json!({
-                        "content": message
-                    });
-                    
-                    match client.post(&url).json(&payload).send().await {
-                        Ok(resp) => {
-                            match resp.text().await {
-                                Ok(text) => format!("Response: {}", text),
-                                Err(e) => format!("Error reading response: {}", e),
-                            }
-                        },
-                        Err(e) => format!("Error sending message: {}", e),
-                    }
-                })
-            }).join().unwrap_or_else(|_| "Thread panic".to_string())
-        });
+    pub fn create_tool(&mut self, name: &str, code: &str) -> Result<String> {
+        self.create_tool_for("rhai", name, code)
+    }
 
-        let pending_clone = pending_tools.clone();
-        engine.register_fn("start_server", move |port: &str| -> String {
-            let port_num: u16 = port.parse().unwrap_or(8080);
-            let pending = pending_clone.clone();
-            
-            println!("🚀 Starting IPC server on port {}", port_num);
-            
-            // Spawn server in background thread
-            std::thread::spawn(move || {
-                let rt = tokio::runtime::Runtime::new().unwrap();
-                rt.block_on(async {
-                    if let Err(e) = crate::ipc::start_http_server(port_num, pending).await {
-                        eprintln!("Server error: {}", e);
-                    }
-                });
-            });
-            
-            format!("IPC server starting on port {}", port_num)
-        });
+    /// Save tool `name` as a `.{extension}` file, for the backend registered for that extension
+    /// (see `register_backend`)
+    pub fn create_tool_for(&mut self, extension: &str, name: &str, code: &str) -> Result<String> {
+        let identity = self.identity.clone();
+        self.save_tool(name, code, extension, "create_tool", &identity)
+    }
 
-        // Self-Replication Tool
-        engine.register_fn("clone_agent", |target_dir: &str| -> String {
-            println!("🧬 Cloning agent to: {}", target_dir);
-            
-            // Create target directory
-            if let Err(e) = fs::create_dir_all(target_dir) {
-                return format!("Error creating directory: {}", e);
-            }
-            
-            // 1. Copy executable
-            match std::env::current_exe() {
-                Ok(exe_path) => {
-                    let exe_name = exe_path.file_name().unwrap_or_default();
-                    let target_exe = PathBuf::from(target_dir).join(exe_name);
-                    
-                    if let Err(e) = fs::copy(&exe_path, &target_exe) {
-                        return format!("Error copying executable: {}", e);
-                    }
-                    
-                    // Make executable on Unix
-                    #[cfg(unix)]
-                    {
-                        use std::os::unix::fs::PermissionsExt;
-                        if let Ok(metadata) = fs::metadata(&target_exe) {
-                            let mut perms = metadata.permissions();
-                            perms.set_mode(0o755);
-                            let _ = fs::set_permissions(&target_exe, perms);
-                        }
-                    }
-                },
-                Err(e) => return format!("Error getting executable path: {}", e),
-            }
-            
-            // 2. Copy tools directory
-            let tools_src = PathBuf::from("tools");
-            let tools_dst = PathBuf::from(target_dir).join("tools");
-            
-            if tools_src.exists() {
-                if let Err(e) = copy_dir_recursive(&tools_src, &tools_dst) {
-                    return format!("Error copying tools: {}", e);
-                }
-            }
-            
-            // 3. Copy .env if exists
-            let env_src = PathBuf::from(".env");
-            if env_src.exists() {
-                let env_dst = PathBuf::from(target_dir).join(".env");
-                let _ = fs::copy(&env_src, &env_dst);
-            }
-            
-            format!("✅ Agent cloned successfully to: {}", target_dir)
-        });
+    /// Install tool `name` as a `.{extension}` file, committing it to the tool history as `action`
+    /// by `source`
+    fn save_tool(&mut self, name: &str, code: &str, extension: &str, action: &str, source: &str) -> Result<String> {
+        self.halt.check()?;
+        if !tool_extensions().iter().any(|e| e == extension) {
+            return Err(anyhow!("No tool backend runs .{} files", extension));
+        }
+        self.check_safety(name, code)?;
+        let path = self.tools_dir.join(format!("{}.{}", name, extension));
+        fs::write(&path, code)?;
+        self.commit_tool(name, code, action, source);
 
-        // Initialize with an empty AST (or load immediately? No, load_tools is called later)
-        // Actually, let's initialize it properly
-        let global_ast = Arc::new(RwLock::new(
-            engine.compile("").map_err(|e| anyhow::anyhow!("Rhai init error: {}", e))?
-        ));
-
-        // Register remove_tool
-        let tools_dir_clone = tools_dir.clone();
-        let global_ast_clone = global_ast.clone();
-        engine.register_fn("remove_tool", move |name: &str| -> String {
-            let path = tools_dir_clone.join(format!("{}.rhai", name));
-            if path.exists() {
-                if let Err(e) = fs::remove_file(&path) {
-                    return format!("Error deleting tool file: {}", e);
-                }
-                
-                // Reload AST
-                match load_all_tools(&tools_dir_clone) {
-                    Ok(new_ast) => {
-                        let mut ast_lock = global_ast_clone.write().unwrap();
-                        *ast_lock = new_ast;
-                        format!("Tool '{}' removed successfully", name)
-                    },
-                    Err(e) => format!("Tool removed from disk but error reloading AST: {}", e)
+        // Compile immediately so errors are reported to the author
+        let backend = self.backend(extension);
+        let compiled = backend.map(|b| b.compile(name, code)).unwrap_or(Ok(()));
+        let outcome = match &compiled {
+            Ok(_) => "created".to_string(),
+            Err(e) => format!("saved, but does not compile: {}", e),
+        };
+        let safety = self.safety_policy();
+        let level = backend.map(|b| b.validate(code, &safety)).unwrap_or_else(|| validate_tool_code(code, &safety));
+        let file = if extension == "rhai" { name.to_string() } else { format!("{}.{}", name, extension) };
+        self.audit.record_or_warn("create_tool", &format!("{} ({:?})", file, level), &outcome);
+        compiled?;
+
+        Ok(format!("Tool '{}' created successfully at {:?}", name, path))
+    }
+
+    /// The source of tool `name`
+    pub fn tool_source(&self, name: &str) -> Option<String> {
+        find_tool_file(&self.tools_dir, name).and_then(|path| fs::read_to_string(path).ok())
+    }
+
+    pub fn remove_tool(&self, name: &str) -> Result<String> {
+        self.halt.check()?;
+        let path = find_tool_file(&self.tools_dir, name).ok_or_else(|| anyhow!("Tool '{}' not found", name))?;
+        fs::remove_file(&path)?;
+        self.rhai.cache().invalidate(name);
+        commit_tool_or_warn(&self.tools_dir, name, &format!("remove_tool {} by {}", name, self.identity));
+        self.audit.record_or_warn("remove_tool", name, "removed");
+        Ok(format!("Tool '{}' removed successfully", name))
+    }
+
+    /// Copy the agent into `target_dir` as the `clone_agent` native does, within the same limits
+    pub fn clone_agent(&self, target_dir: &str, options: &CloneOptions) -> Result<String> {
+        self.halt.check()?;
+        let result = (self.cloner)(target_dir, options);
+        if !result.starts_with('✅') {
+            return Err(anyhow!(result.trim_start_matches("Error: ").to_string()));
+        }
+        Ok(result)
+    }
+
+    /// Save the tools `response` writes and run the tool calls it makes, journaling each step.
+    /// Calls with no tool definition between them don't depend on each other and run at the
+    /// same time (see `execute_tools`); the actions come back in order. A call that fails
+    /// doesn't stop the others.
+    pub fn act_on(&mut self, response: &str, journal: Option<&Journal>) -> Vec<ResponseAction> {
+        let mut actions = Vec::new();
+        let mut calls = Vec::new();
+        for parsed in parse_response(response) {
+            match parsed {
+                ParsedAction::CreateTool(block) => {
+                    actions.extend(self.run_calls(std::mem::take(&mut calls), journal));
+                    actions.push(self.create_block(block, journal));
                 }
-            } else {
-                format!("Tool '{}' not found", name)
+                ParsedAction::ToolCall { name, args } => calls.push((name, args)),
+                ParsedAction::FinalAnswer(_) => {}
             }
-        });
+        }
+        actions.extend(self.run_calls(calls, journal));
+        actions
+    }
 
-        // Register Pending Tool Management Functions
-        
-        // list_pending_tools
-        let pending_clone = pending_tools.clone();
-        engine.register_fn("list_pending_tools", move || -> String {
-            let tools = pending_clone.lock().unwrap();
-            if tools.is_empty() {
-                return "No tools pending approval.".to_string();
+    pub(crate) fn create_block(&mut self, block: ToolBlock, journal: Option<&Journal>) -> ResponseAction {
+        let created = if let Err(e) = self.review_install(&block) {
+            Err(e)
+        } else if block.python {
+            self.create_python_tool(&block.name, &block.code)
+        } else {
+            self.create_tool(&block.name, &block.code)
+        };
+        let outcome = created.map_err(|e| e.to_string());
+        if let Some(journal) = journal {
+            let outcome = outcome.clone().unwrap_or_else(|e| format!("Error: {}", e));
+            journal.record_or_warn(JournalEvent::ToolCreated { name: block.name.clone(), outcome });
+        }
+        ResponseAction::Created { name: block.name, outcome }
+    }
+
+    fn run_calls(&mut self, calls: Vec<(String, Vec<String>)>, journal: Option<&Journal>) -> Vec<ResponseAction> {
+        if let Some(journal) = journal {
+            for (name, args) in &calls {
+                journal.record_or_warn(JournalEvent::ToolCall { name: name.clone(), args: args.clone() });
             }
-            
-            let mut output = String::from("Pending Tools:\n");
-            for (i, tool) in tools.iter().enumerate() {
-                output.push_str(&format!("{}. {} (Safety: {:?}) - From: {}\n", 
-                    i + 1, tool.name, tool.safety_level, tool.source_agent));
-                if let Some(desc) = &tool.description {
-                    output.push_str(&format!("   Description: {}\n", desc));
-                }
+        }
+        let outcomes = self.execute_timed(&calls);
+        calls.into_iter().zip(outcomes).map(|((name, args), (result, elapsed))| {
+            let result = result.map_err(|e| e.to_string());
+            let duration_ms = millis(elapsed);
+            if let Some(journal) = journal {
+                let (output, ok) = match &result {
+                    Ok(output) => (output.clone(), true),
+                    Err(e) => (e.clone(), false),
+                };
+                journal.record_or_warn(JournalEvent::ToolResult { name: name.clone(), output, ok, duration_ms });
             }
-            output
-        });
+            ResponseAction::Ran { name, args, result, duration_ms }
+        }).collect()
+    }
 
-        // approve_tool
-        let pending_clone = pending_tools.clone();
-        let tools_dir_clone = tools_dir.clone();
-        // Removed engine_clone as Engine is not Clone and we don't strictly need it for writing files
-        // Actually Engine might not be cheap or thread safe to share like this for compilation inside closure?
-        // Wait, create_tool logic needs to be duplicated or we need a way to call it.
-        // create_tool modifies global_ast which is in ToolManager, not available here.
-        // We can just write the file and let the next load pick it up? 
-        // Or we can try to compile it here.
-        // For MVP, let's just write the file and say "Installed. Restart or reload might be needed if hot reload not fully working".
-        // But wait, create_tool in ToolManager does: write file + compile + merge AST.
-        // We can't easily merge AST from here without access to ToolManager's global_ast.
-        // However, we can register a function that just writes the file, and maybe we can trigger a reload?
-        // Or we can rely on the fact that we are inside Rhai, maybe we can eval the code?
-        // Let's just write the file for now. The agent might need to reload tools.
-        // Actually, we can use the `engine` passed to `new`? No, we need to modify `global_ast` which is in `ToolManager`.
-        // This is a limitation. 
-        // Let's implement `approve_tool` to just write the file and return "Tool saved. Please run [TOOL: reload_tools()]" (if we had one).
-        // Or better: The `ToolManager` methods I added (`approve_tool`) *do* have access to `self`.
-        // But I can't call them from the registered function easily.
-        // I will implement the logic to write file here.
-        
-        engine.register_fn("approve_tool", move |name: &str| -> String {
-            let mut tools = pending_clone.lock().unwrap();
-            if let Some(index) = tools.iter().position(|t| t.name == name) {
-                let tool = tools.remove(index);
-                let path = tools_dir_clone.join(format!("{}.rhai", tool.name));
-                if let Err(e) = fs::write(&path, &tool.code) {
-                    return format!("Error writing tool file: {}", e);
-                }
-                // We can't easily update global_ast here without shared access to it.
-                // For Phase 1, we'll accept that it saves to disk. 
-                // We can add a `reload_tools` native function later or just say it's available next run.
-                // Actually, we can try to compile it using a temporary engine to check validity, but we can't add to global AST of the main engine easily from here.
-                format!("Tool '{}' approved and saved to disk. It will be available after reload.", name)
-            } else {
-                format!("Tool '{}' not found in pending queue", name)
+    /// The tool behind a failed `action`, when the fault is in its own code: a Rhai tool that
+    /// was saved but doesn't compile, or a tool with source that errored while running. Refusals,
+    /// missing tools and failing natives are not the code's fault.
+    pub fn broken_tool(&self, action: &ResponseAction) -> Option<BrokenTool> {
+        let (name, args, error) = match action {
+            ResponseAction::Created { name, outcome: Err(_) } => (name, None, None),
+            ResponseAction::Ran { name, args, result: Err(e), .. } if e.starts_with(&format!("Error executing tool '{}'", name)) => {
+                (name, Some(args.clone()), Some(e.clone()))
             }
-        });
+            _ => return None,
+        };
+        let path = find_tool_file(&self.tools_dir, name)?;
+        let python = path.extension().and_then(|s| s.to_str()) == Some("py");
+        let code = fs::read_to_string(&path).ok()?;
+        let error = match error {
+            Some(error) => error,
+            None => self.backend_for(&path)?.compile(name, &code).err()?.to_string(),
+        };
+        Some(BrokenTool { name: name.clone(), code, python, error, args })
+    }
 
-        // reject_tool
-        let pending_clone = pending_tools.clone();
-        engine.register_fn("reject_tool", move |name: &str| -> String {
-            let mut tools = pending_clone.lock().unwrap();
-            if let Some(index) = tools.iter().position(|t| t.name == name) {
-                tools.remove(index);
-                format!("Tool '{}' rejected and removed from queue", name)
-            } else {
-                format!("Tool '{}' not found in pending queue", name)
+    /// Save a Python tool; it must define a function with the same name as the tool
+    pub fn create_python_tool(&mut self, name: &str, code: &str) -> Result<String> {
+        self.create_tool_for("py", name, code)
+    }
+
+    /// Commit tool `name`, now `code`, to the tool history, if there is one
+    fn commit_tool(&self, name: &str, code: &str, action: &str, source: &str) {
+        commit_tool_or_warn(&self.tools_dir, name, &format!("{} {} ({:?}) by {}", action, name, self.safety_policy().classify(code), source));
+    }
+
+    /// The commits that changed tool `name`, newest first (with `TOOLS_GIT`)
+    pub fn tool_log(&self, name: &str) -> Result<Vec<ToolCommit>> {
+        tool_log(&self.tools_dir, name)
+    }
+
+    /// Put tool `name` back as it was at `commit`, as a new commit
+    pub fn revert_tool(&mut self, name: &str, commit: &str) -> Result<String> {
+        self.halt.check()?;
+        revert_tool_file(&self.tools_dir, self.rhai.cache(), &self.safety_policy(), &self.audit, &self.identity, name, commit)
+    }
+
+    /// Refuse (and audit) a tool the safety policy doesn't allow installing
+    fn check_safety(&self, name: &str, code: &str) -> Result<()> {
+        match self.safety_policy().refusal(code) {
+            Some(reason) => {
+                self.audit.record_or_warn("create_tool", name, &format!("refused: {}", reason));
+                Err(anyhow!("Tool '{}' is not allowed: {}", name, reason))
             }
-        });
-        
-        // share_tool
-        let tools_dir_clone = tools_dir.clone();
-        engine.register_fn("share_tool", move |url: &str, tool_name: &str| -> String {
-            // 1. Get tool code
-            let path = tools_dir_clone.join(format!("{}.rhai", tool_name));
-            let code = match fs::read_to_string(&path) {
-                Ok(c) => c,
-                Err(_) => return format!("Error: Tool '{}' not found", tool_name),
-            };
-            
-            // 2. Validate to get safety level
-            // We need to duplicate validate_tool_code logic or make it available. 
-            // It's a standalone function, so we can call it.
-            // But it's defined below. We might need to move it up or use it.
-            // Rust allows calling functions defined later.
-            // But `validate_tool_code` is not in scope of the closure? It is if it's in the same module.
-            // Wait, `validate_tool_code` is private. Closures in `new` can call private functions of the module.
-            // But `validate_tool_code` returns `ToolSafetyLevel` which is imported.
-            
-            // We need to verify `validate_tool_code` is accessible.
-            // It is defined in the same file.
-            
-            // 3. Create message
-            // We need to determine safety level.
-            // Let's assume we can call validate_tool_code.
-            // Wait, I can't call a function inside the closure if it's not captured? 
-            // No, static functions are fine.
-            
-            // However, `validate_tool_code` is defined *outside* `impl ToolManager`.
-            // So it's just a function in the module.
-            
-            // We need to handle the async send inside sync closure.
-            // Use the same thread spawn trick as send_message.
-            
-            let url = url.to_string();
-            let tool_name = tool_name.to_string();
-            let code_clone = code.clone();
-            
-            std::thread::spawn(move || {
-                let rt = tokio::runtime::Runtime::new().unwrap();
-                rt.block_on(async {
-                    let safety = validate_tool_code(&code_clone);
-                    
-                    let msg = IpcMessage::tool_share(
-                        &tool_name,
-                        &code_clone,
-                        Some("Shared via share_tool".to_string()),
-                        safety
-                    );
-                    
-                    let client = reqwest::Client::new();
-                    match client.post(&url).json(&msg).send().await {
-                        Ok(resp) => {
-                            match resp.text().await {
-                                Ok(text) => format!("Response: {}", text),
-                                Err(e) => format!("Error reading response: {}", e),
-                            }
-                        },
-                        Err(e) => format!("Error sending message: {}", e),
-                    }
-                })
-            }).join().unwrap_or_else(|_| "Thread panic".to_string())
-        });
+            None => Ok(()),
+        }
+    }
 
-        Ok(Self {
-            engine,
-            global_ast,
-            tools_dir,
-            pending_tools,
-        })
+    /// Enable (or disable) the Python tool backend
+    pub fn set_python_backend(&mut self, python: Option<PythonBackend>) {
+        self.backends.retain(|backend| backend.extension() != "py");
+        if let Some(python) = python {
+            self.backends.push(Arc::new(python));
+        }
     }
 
-    pub fn load_tools(&mut self) -> Result<()> {
-        let new_ast = load_all_tools(&self.tools_dir)?;
-        let mut ast_lock = self.global_ast.write().unwrap();
-        *ast_lock = new_ast;
+    /// Run `*.{extension}` tool files with `backend` from now on, in place of any backend
+    /// registered for the same extension. Rhai tools always run in the built-in backend.
+    pub fn register_backend(&mut self, backend: Arc<dyn ToolBackend>) -> Result<()> {
+        let extension = backend.extension().to_string();
+        if extension.is_empty() || !extension.chars().all(|c| c.is_ascii_alphanumeric()) {
+            return Err(anyhow!("Invalid tool file extension '{}'", extension));
+        }
+        if extension == self.rhai.extension() {
+            return Err(anyhow!("Rhai tools always run in the built-in backend"));
+        }
+        add_tool_extension(&extension);
+        self.backends.retain(|b| b.extension() != extension);
+        info!("Registered the {} tool backend for .{} files", backend.name(), extension);
+        self.backends.push(backend);
         Ok(())
     }
 
-    pub fn create_tool(&mut self, name: &str, code: &str) -> Result<String> {
-        let path = self.tools_dir.join(format!("{}.rhai", name));
-        fs::write(&path, code)?;
-        
-        // Compile and merge immediately
-        let ast = self.engine.compile(code).map_err(|e| anyhow::anyhow!("Rhai compile error: {}", e))?;
-        *self.global_ast.write().unwrap() += ast;
-        
-        Ok(format!("Tool '{}' created successfully at {:?}", name, path))
+    /// The backend that runs `*.{extension}` tools, if one is registered
+    pub fn backend(&self, extension: &str) -> Option<&dyn ToolBackend> {
+        if extension == self.rhai.extension() {
+            return Some(&self.rhai);
+        }
+        self.backends.iter().find(|b| b.extension() == extension).map(|b| b.as_ref())
+    }
+
+    /// Names of the registered backends, Rhai first
+    pub fn backend_names(&self) -> Vec<String> {
+        std::iter::once(self.rhai.name()).chain(self.backends.iter().map(|b| b.name())).map(str::to_string).collect()
+    }
+
+    /// The backend for the tool file at `path`
+    fn backend_for(&self, path: &Path) -> Option<&dyn ToolBackend> {
+        self.backend(path.extension()?.to_str()?)
     }
 
     pub fn list_tools(&self) -> Vec<String> {
         // We can't easily list functions from AST in Rhai without iterating definitions, 
         // but for now we can just list files in the directory or keep a separate list if needed.
         // For this MVP, let's just list the files in the tools dir as the source of truth.
-        let mut tools = Vec::new();
-        if let Ok(entries) = fs::read_dir(&self.tools_dir) {
-            for entry in entries {
-                if let Ok(entry) = entry {
-                    let path = entry.path();
-                    if path.extension().and_then(|s| s.to_str()) == Some("rhai") {
-                        if let Some(stem) = path.file_stem() {
-                            tools.push(stem.to_string_lossy().to_string());
+        list_tool_names(&self.tools_dir)
+    }
+
+    /// The `k` tools most relevant to `query`, re-indexing any that changed
+    pub async fn find_tools(&self, query: &str, k: usize) -> Result<Vec<ToolMatch>> {
+        self.index.refresh(&self.tools_dir, &self.list_tools()).await?;
+        self.index.search(query, k).await
+    }
+
+    /// Run the tool or native `name`. HighRisk runs are recorded in the audit log.
+    pub fn execute_tool(&self, name: &str, args: Vec<String>) -> Result<String> {
+        self.halt.check()?;
+        if split_mcp_name(name).is_some() {
+            return self.execute_mcp_tool(name, args);
+        }
+        if name == "execute_tool_background" {
+            let (tool, args) = args.split_first().ok_or_else(|| anyhow!("execute_tool_background needs the name of a tool to run"))?;
+            return self.execute_tool_background(tool, args.to_vec());
+        }
+        let (call, level) = self.admit(name, &args)?;
+        self.run_admitted(name, args, &call, &level)
+    }
+
+    /// Run a call `admit` let through
+    fn run_admitted(&self, name: &str, args: Vec<String>, call: &str, level: &ToolSafetyLevel) -> Result<String> {
+        let span = info_span!("tool_call", tool = name, level = ?level);
+        let _entered = span.enter();
+        let started = Instant::now();
+        let deadline = self.tool_timeout.map(|timeout| Instant::now() + timeout);
+        let outer = RUN_DEADLINE.with(|d| d.replace(deadline));
+        let result = self.run_tool(name, args);
+        RUN_DEADLINE.with(|d| d.set(outer));
+        let result = match result {
+            Err(_) if self.halt.is_halted() => result,
+            Err(_) if self.cancel.is_cancelled() => Err(anyhow!("Tool '{}' was cancelled", name)),
+            Err(_) if deadline.is_some_and(|d| Instant::now() >= d) => {
+                Err(anyhow!("Tool '{}' timed out after {:?}", name, self.tool_timeout.unwrap_or_default()))
+            }
+            result => result,
+        };
+        // Secret values a tool used stay inside it
+        let result = result.map(|output| self.secrets.redact(&output));
+        if *level == ToolSafetyLevel::HighRisk {
+            let outcome = match &result {
+                Ok(output) => format!("ok: {}", output.chars().take(200).collect::<String>()),
+                Err(e) => format!("error: {}", e),
+            };
+            self.audit.record_or_warn("execute_tool", call, &outcome);
+        }
+        metrics().observe_tool(level, started.elapsed(), result.is_ok());
+        match &result {
+            Ok(_) => debug!(elapsed_ms = started.elapsed().as_millis() as u64, "Tool ran"),
+            Err(e) => debug!(elapsed_ms = started.elapsed().as_millis() as u64, error = %e, "Tool failed"),
+        }
+        result
+    }
+
+    /// Run `calls` in order, except that a stretch of independent calls (see `is_independent`)
+    /// runs at the same time, at most `parallel_tools` at once. Results come back in call order.
+    /// Confirmations are asked one after another beforehand.
+    pub fn execute_tools(&self, calls: &[(String, Vec<String>)]) -> Vec<Result<String>> {
+        self.execute_timed(calls).into_iter().map(|(result, _)| result).collect()
+    }
+
+    /// `execute_tools`, with how long each call took
+    fn execute_timed(&self, calls: &[(String, Vec<String>)]) -> Vec<(Result<String>, Duration)> {
+        let timed = |run: &dyn Fn() -> Result<String>| {
+            let started = Instant::now();
+            let result = run();
+            (result, started.elapsed())
+        };
+        if calls.len() < 2 || self.parallel_tools < 2 {
+            return calls.iter().map(|(name, args)| timed(&|| self.execute_tool(name, args.clone()))).collect();
+        }
+        let mut results: Vec<Option<(Result<String>, Duration)>> = calls.iter().map(|_| None).collect();
+        let natives = self.rhai.native_names();
+        // (call index, admission, independent); background and MCP calls admit themselves
+        let mut admitted = Vec::new();
+        for (i, (name, args)) in calls.iter().enumerate() {
+            if name == "execute_tool_background" || split_mcp_name(name).is_some() {
+                admitted.push((i, None, false));
+                continue;
+            }
+            match self.halt.check().and_then(|_| self.admit(name, args)) {
+                Ok(admission) => admitted.push((i, Some(admission), self.is_independent(name, &natives))),
+                Err(e) => results[i] = Some((Err(e), Duration::ZERO)),
+            }
+        }
+        let run = |(i, admission, _): &(usize, Option<(String, ToolSafetyLevel)>, bool)| {
+            let (name, args) = &calls[*i];
+            match admission {
+                Some((call, level)) => timed(&|| self.run_admitted(name, args.clone(), call, level)),
+                None => timed(&|| self.execute_tool(name, args.clone())),
+            }
+        };
+        let mut rest = admitted.as_slice();
+        while let Some((_, _, independent)) = rest.first() {
+            let len = if *independent { rest.iter().take_while(|(_, _, independent)| *independent).count() } else { 1 };
+            let (stretch, tail) = rest.split_at(len);
+            rest = tail;
+            if stretch.len() == 1 {
+                results[stretch[0].0] = Some(run(&stretch[0]));
+                continue;
+            }
+            let next = AtomicUsize::new(0);
+            let finished = Mutex::new(Vec::new());
+            std::thread::scope(|scope| {
+                for _ in 0..self.parallel_tools.min(stretch.len()) {
+                    scope.spawn(|| {
+                        while let Some(call) = stretch.get(next.fetch_add(1, Ordering::SeqCst)) {
+                            let outcome = run(call);
+                            finished.lock().unwrap().push((call.0, outcome));
                         }
-                    }
+                    });
                 }
+            });
+            for (i, outcome) in finished.into_inner().unwrap() {
+                results[i] = Some(outcome);
             }
         }
-        tools
+        results.into_iter().map(|r| r.expect("every call has a result")).collect()
     }
 
-    pub fn execute_tool(&self, name: &str, args: Vec<String>) -> Result<String> {
-        let mut scope = Scope::new();
-        
-        // Handle arguments:
-        // If the tool takes 1 arg, pass it directly.
-        // If it takes 0, pass nothing.
-        // If it takes >1, we might need to change main.rs or pass an array.
-        // For now, we assume most tools take 1 string arg or 0.
-        // If args is empty, call with ().
-        // If args has 1 element, call with (arg,).
-        
+    /// Whether a call of `name` can't affect, or be affected by, the other calls of a response:
+    /// a script tool whose reachable code calls no native but the web fetches of
+    /// `CONCURRENT_NATIVES`. Python tools and everything else run in turn.
+    fn is_independent(&self, name: &str, natives: &[String]) -> bool {
+        let (code, python) = self.reachable(name);
+        !python && natives.iter()
+            .filter(|native| !CONCURRENT_NATIVES.contains(&native.as_str()))
+            .all(|native| !calls_function(&code, native))
+    }
 
+    /// How many independent tool calls of one response may run at once (1 runs them in turn)
+    pub fn set_parallel_tools(&mut self, parallel_tools: usize) {
+        self.parallel_tools = parallel_tools.max(1);
+    }
 
-        // Try to call with global_ast (for script tools)
-        // We need to handle the tuple conversion carefully. 
-        // call_fn expects a tuple of arguments.
-        // If we have 0 args, we pass ().
-        // If we have 1 arg, we pass (arg,).
-        
-        let result: Result<rhai::Dynamic, _> = {
-            let ast = self.global_ast.read().unwrap();
-            if args.is_empty() {
-                 self.engine.call_fn(&mut scope, &*ast, name, ())
-            } else {
-                 self.engine.call_fn(&mut scope, &*ast, name, (args[0].clone(),))
-            }
+    /// Check `name` against the policies and ask for confirmation where they say so, returning
+    /// the call and its risk
+    fn admit(&self, name: &str, args: &[String]) -> Result<(String, ToolSafetyLevel)> {
+        self.check_policy(name)?;
+        self.check_rate(name)?;
+        let safety = self.safety_policy();
+        let level = find_tool_file(&self.tools_dir, name)
+            .and_then(|path| Some((path.clone(), self.backend_for(&path), fs::read_to_string(&path).ok()?)))
+            .map(|(path, backend, source)| backend.map(|b| b.validate(&source, &safety)).unwrap_or_else(|| tool_file_risk(&path, &source, &safety)))
+            // A native's risk is that of a script calling it
+            .unwrap_or_else(|| validate_tool_code(name, &safety));
+        let call = format!("{}({})", name, args.join(", "));
+        let (source, python) = self.reachable(name);
+        let always_confirm = matches!(safety.approval_for(&source), Some((_, Approval::Confirm)));
+        // Nothing confines Python tools but the OS user they run as, so an operator must allow
+        // every run of one, whatever the confirm policy says
+        let must_ask = python || (self.unattended && level == ToolSafetyLevel::HighRisk);
+        // run_command asks for approval of its own
+        if (always_confirm || must_ask || self.confirm.requires(&level)) && name != "run_command" {
+            self.confirm_run(&call, &level, must_ask)?;
+        }
+        Ok((call, level))
+    }
+
+    /// Spend a call of `name`, and of every rate-limited tool or native it reaches, from the
+    /// safety policy's per-minute budgets: all of them or, when one is spent, none. Natives of
+    /// `METERED_NATIVES` are left to spend for themselves on each call.
+    fn check_rate(&self, name: &str) -> Result<()> {
+        let safety = self.safety_policy();
+        let mut limits = safety.rate_limits_for(&self.reachable_source(name));
+        limits.retain(|(tool, _)| !METERED_NATIVES.contains(tool));
+        self.rate_limits().check_all(&limits).map_err(|(tool, per_minute, wait)| {
+            let what = if tool == name { format!("Tool '{}'", name) } else { format!("Tool '{}' calls {}, which", name, tool) };
+            rate_limited(&what, per_minute, wait)
+        })
+    }
+
+    /// Queue a run of tool `name` for the background workers (see `serve_jobs`), returning the
+    /// job id at once. The policies, rate limits and confirmation apply here, where the operator
+    /// is, and not again when a worker runs it.
+    pub fn execute_tool_background(&self, name: &str, args: Vec<String>) -> Result<String> {
+        self.halt.check()?;
+        let (_, level) = match split_mcp_name(name) {
+            Some(_) => self.admit_mcp(name, &args)?,
+            None => self.admit(name, &args)?,
         };
+        let id = self.jobs.submit(name, args, Some(level));
+        Ok(format!("Started {} in the background", id))
+    }
 
-        match result {
-            Ok(v) => Ok(v.to_string()),
-            Err(e) => {
-                // If function not found in AST, try native functions (empty AST)
-                if e.to_string().contains("Function not found") {
-                    // Try native functions using eval
-                    let script = if args.is_empty() {
-                        format!("{}()", name)
-                    } else {
-                        scope.push("arg0", args[0].clone());
-                        format!("{}(arg0)", name)
-                    };
-                    
-                    self.engine.eval_with_scope::<rhai::Dynamic>(&mut scope, &script)
-                        .map(|v| v.to_string())
-                        .map_err(|e2| anyhow!("Error executing tool '{}': {}", name, e2))
-                } else {
-                    Err(anyhow!("Error executing tool '{}': {}", name, e))
+    /// Run a background job a worker took. One `execute_tool_background` admitted runs as it was
+    /// admitted; one a script queued is admitted here first, as any run is.
+    pub fn run_job(&self, job: &BackgroundJob) -> Result<String> {
+        let Some(level) = &job.level else {
+            return self.execute_tool(&job.tool, job.args.clone());
+        };
+        self.halt.check()?;
+        if split_mcp_name(&job.tool).is_some() {
+            return self.mcp.call(&job.tool, &job.args).map(|output| self.secrets.redact(&output));
+        }
+        self.run_admitted(&job.tool, job.args.clone(), &job.call(), level)
+    }
+
+    /// The review installing `code` as `name` needs: for a replacement with different code, or
+    /// a new tool at a level the confirm policy covers. None with confirmations off.
+    fn install_review(&self, name: &str, code: &str, python: bool) -> Option<ToolReview> {
+        let min_risk = self.confirm.min_risk.as_ref()?;
+        let previous = self.tool_source(name);
+        if previous.as_deref() == Some(code) {
+            return None;
+        }
+        let review = ToolReview::new(name, code, python, previous, &self.safety_policy());
+        (review.previous.is_some() || review.safety_level >= *min_risk).then_some(review)
+    }
+
+    /// Write the tools `names` (all of them without) to a bundle at `path` for another agent to
+    /// `import_tools`, signed when `auth` has the swarm secret
+    pub fn export_tools(&self, path: &Path, names: Option<&[String]>, auth: &IpcAuth) -> Result<BundleManifest> {
+        let mut names = match names {
+            Some(names) => names.to_vec(),
+            None => self.list_tools(),
+        };
+        names.sort();
+        names.dedup();
+        let tools = names.iter()
+            .map(|name| {
+                let path = find_tool_file(&self.tools_dir, name).ok_or_else(|| anyhow!("Tool '{}' not found", name))?;
+                let python = path.extension().is_some_and(|ext| ext == "py");
+                Ok((name.clone(), fs::read_to_string(&path)?, python))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let manifest = write_bundle(path, &tools, auth, &self.safety_policy())?;
+        let signed = if manifest.signature.is_some() { "signed" } else { "unsigned" };
+        self.audit.record_or_warn("export_tools", &format!("{} -> {}", names.join(", "), path.display()), signed);
+        Ok(manifest)
+    }
+
+    /// Install the tools of the bundle at `path`. The bundle must verify against `auth` and each
+    /// tool must match its hash; each then passes the safety policy and must compile. A tool the
+    /// confirm policy covers, or that replaces a different version, goes to the install approver,
+    /// or without one to the pending queue.
+    pub fn import_tools(&mut self, path: &Path, auth: &IpcAuth) -> Result<ImportReport> {
+        self.halt.check()?;
+        let bundle = read_bundle(path)?;
+        let (origin, source_agent, source_key) = match bundle.verify(auth).map_err(|e| anyhow!("Bundle {:?} rejected: {}", path, e))? {
+            Sender::Verified(agent) => {
+                let key = bundle.manifest.signature.as_ref().and_then(|s| s.key.clone());
+                let origin = match &key {
+                    Some(key) => format!("{} [key {}]", agent, key),
+                    None => agent.clone(),
+                };
+                (origin, agent, key)
+            }
+            Sender::Unsigned => {
+                let agent = format!("{} (unverified)", bundle.manifest.exported_by);
+                (format!("unsigned bundle from {}", bundle.manifest.exported_by), agent, None)
+            }
+        };
+        let approver = self.install_approver.read().unwrap().clone();
+        let mut imported = Vec::new();
+        for tool in &bundle.manifest.tools {
+            let code = &bundle.sources[&tool.name];
+            let python = tool.is_python();
+            let outcome = if self.tool_source(&tool.name).as_deref() == Some(code.as_str()) {
+                ImportOutcome::Unchanged
+            } else if let Some(reason) = self.safety_policy().refusal(code) {
+                ImportOutcome::Refused(reason)
+            } else if let Some(Err(e)) = self.backend(if python { "py" } else { "rhai" }).map(|b| b.compile(&tool.name, code)) {
+                ImportOutcome::Refused(format!("does not compile: {}", e))
+            } else {
+                match (self.install_review(&tool.name, code, python), &approver) {
+                    (Some(review), Some(approver)) if !approver(&review) => ImportOutcome::Declined,
+                    (Some(_), None) if python => ImportOutcome::Refused("Python tools need an operator to approve them".to_string()),
+                    (Some(review), None) => {
+                        self.pending_tools.lock().unwrap().push(PendingTool {
+                            name: tool.name.clone(),
+                            code: code.clone(),
+                            source_agent: source_agent.clone(),
+                            source_address: None,
+                            source_key: source_key.clone(),
+                            received_at: SystemTime::now(),
+                            description: tool.description.clone(),
+                            safety_level: review.safety_level,
+                            signature: None,
+                            security_review: None,
+                        });
+                        ImportOutcome::Queued
+                    }
+                    _ => {
+                        match self.save_tool(&tool.name, code, if python { "py" } else { "rhai" }, "import_tool", &origin) {
+                            Ok(_) => ImportOutcome::Installed,
+                            Err(e) => ImportOutcome::Refused(e.to_string()),
+                        }
+                    }
                 }
+            };
+            self.audit.record_or_warn("import_tool", &format!("{} from {}", tool.name, origin), &format!("{:?}", outcome));
+            imported.push(ImportedTool { name: tool.name.clone(), version: tool.version.clone(), outcome });
+        }
+        Ok(ImportReport { origin, tools: imported })
+    }
+
+    /// Ask the install approver, if there is one, whether the model's `block` may be installed.
+    /// Unchanged code and, short of a replacement, levels the confirm policy auto-approves go
+    /// unasked; with confirmations off, nothing is asked.
+    fn review_install(&self, block: &ToolBlock) -> Result<()> {
+        let approver = self.install_approver.read().unwrap().clone();
+        let Some(approver) = approver else { return Ok(()) };
+        let Some(review) = self.install_review(&block.name, &block.code, block.python) else { return Ok(()) };
+        if !approver(&review) {
+            self.audit.record_or_warn("create_tool", &block.name, "declined by operator");
+            return Err(anyhow!("The operator declined to install tool '{}'", block.name));
+        }
+        Ok(())
+    }
+
+    /// Ask the execution approver, if there is one, whether `call` may run. Without one, a
+    /// call that `must_ask` is refused.
+    fn confirm_run(&self, call: &str, level: &ToolSafetyLevel, must_ask: bool) -> Result<()> {
+        let approver = self.approver.read().unwrap().clone();
+        match approver {
+            Some(approver) if !approver(call, level) => {
+                self.audit.record_or_warn("execute_tool", call, "declined by operator");
+                Err(anyhow!("The operator declined to run {}", call))
+            }
+            None if must_ask => {
+                self.audit.record_or_warn("execute_tool", call, "refused: no operator to confirm");
+                Err(anyhow!("{} needs an operator to confirm it, and there is none", call))
             }
+            _ => Ok(()),
+        }
+    }
+
+    /// Run a tool of an external MCP server (see `admit_mcp`)
+    fn execute_mcp_tool(&self, name: &str, args: Vec<String>) -> Result<String> {
+        self.admit_mcp(name, &args)?;
+        self.mcp.call(name, &args).map(|output| self.secrets.redact(&output))
+    }
+
+    /// `admit` for a tool of an external MCP server. What it does can't be seen from here, so it
+    /// counts as MediumRisk for the tool policy and confirmations.
+    fn admit_mcp(&self, name: &str, args: &[String]) -> Result<(String, ToolSafetyLevel)> {
+        let level = ToolSafetyLevel::MediumRisk;
+        let max_risk = self.policy().max_risk;
+        if level > max_risk {
+            return Err(anyhow!("Tool '{}' is not allowed: {:?} exceeds the {:?} limit", name, level, max_risk));
+        }
+        let call = format!("{}({})", name, args.join(", "));
+        if self.confirm.requires(&level) {
+            self.confirm_run(&call, &level, false)?;
+        }
+        Ok((call, level))
+    }
+
+    fn run_tool(&self, name: &str, args: Vec<String>) -> Result<String> {
+        // Route tool files by extension; natives run in Rhai
+        let Some(path) = find_tool_file(&self.tools_dir, name) else {
+            return self.rhai.run(name, &args);
+        };
+        let extension = path.extension().and_then(|s| s.to_str()).unwrap_or_default();
+        match self.backend(extension) {
+            Some(backend) => backend.execute(name, &path, &args),
+            None if extension == "py" => Err(anyhow!("Tool '{}' is a Python tool but PYTHON_TOOLS is not enabled", name)),
+            None => Err(anyhow!("Tool '{}' is a .{} tool, but no backend for it is registered", name, extension)),
         }
     }
 
     pub fn queue_tool(&mut self, name: String, code: String, source_agent: String, description: Option<String>) -> Result<String> {
-        let safety_level = validate_tool_code(&code);
+        let safety_level = validate_tool_code(&code, &self.safety_policy());
         
         let pending = PendingTool {
             name: name.clone(),
             code,
             source_agent,
+            source_address: None,
+            source_key: None,
             received_at: SystemTime::now(),
             description,
             safety_level: safety_level.clone(),
+            signature: None,
+            security_review: None,
         };
         
         self.pending_tools.lock().unwrap().push(pending);
@@ -564,42 +2266,48 @@ impl ToolManager {
     }
 
     pub fn approve_tool(&mut self, name: &str) -> Result<String> {
+        self.halt.check()?;
         let mut tools = self.pending_tools.lock().unwrap();
         if let Some(index) = tools.iter().position(|t| t.name == name) {
+            check_provenance(&self.tools_dir, name, &tools[index].code, tools[index].signature.as_ref())
+                .map_err(|e| anyhow!("Tool '{}' cannot be approved: {}", name, e))?;
             let tool = tools.remove(index);
             // Drop lock before calling create_tool to avoid potential deadlocks (though create_tool doesn't lock pending_tools)
             drop(tools);
-            self.create_tool(&tool.name, &tool.code)?;
+            let created = self.save_tool(&tool.name, &tool.code, "rhai", "approve_tool", &tool.origin())
+                .and_then(|saved| store_signature(&self.tools_dir, &tool.name, tool.signature.as_ref()).map(|_| saved));
+            let outcome = match &created {
+                Ok(_) => "installed".to_string(),
+                Err(e) => format!("error: {}", e),
+            };
+            record_tool_decision(&self.audit, "approve_tool", &tool, &outcome);
+            created?;
             Ok(format!("Tool '{}' approved and installed successfully", name))
         } else {
             Err(anyhow!("Tool '{}' not found in pending queue", name))
         }
     }
 
+    /// Run a pending tool in the sandbox with `args`
+    pub fn test_pending_tool(&self, name: &str, args: &[String]) -> Result<SandboxReport> {
+        test_pending_tool(&self.pending_tools, &self.audit, name, args)
+    }
+
     pub fn reject_tool(&mut self, name: &str) -> Result<String> {
-        let mut tools = self.pending_tools.lock().unwrap();
-        if let Some(index) = tools.iter().position(|t| t.name == name) {
-            tools.remove(index);
-            Ok(format!("Tool '{}' rejected and removed from queue", name))
-        } else {
-            Err(anyhow!("Tool '{}' not found in pending queue", name))
-        }
+        reject_pending_tool(&self.pending_tools, &self.tools_dir, &self.audit, name)
+    }
+
+    /// Everything rejected or refused, as `list_quarantine` shows it
+    pub fn list_quarantine(&self) -> Result<String> {
+        Quarantine::new(&self.tools_dir).render()
+    }
+
+    /// Delete quarantined tool `id`, or all of them
+    pub fn purge_quarantine(&self, id: Option<&str>) -> Result<usize> {
+        Quarantine::new(&self.tools_dir).purge(id)
     }
 
     pub fn list_pending_tools(&self) -> String {
-        let tools = self.pending_tools.lock().unwrap();
-        if tools.is_empty() {
-            return "No tools pending approval.".to_string();
-        }
-        
-        let mut output = String::from("Pending Tools:\n");
-        for (i, tool) in tools.iter().enumerate() {
-            output.push_str(&format!("{}. {} (Safety: {:?}) - From: {}\n", 
-                i + 1, tool.name, tool.safety_level, tool.source_agent));
-            if let Some(desc) = &tool.description {
-                output.push_str(&format!("   Description: {}\n", desc));
-            }
-        }
-        output
+        render_pending_tools(&self.pending_tools.lock().unwrap())
     }
 }
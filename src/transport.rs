@@ -0,0 +1,253 @@
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use rumqttc::{AsyncClient, Event, MqttOptions, Packet, QoS};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::mpsc;
+use crate::ipc::{message_url, Message, MessageResponse};
+use crate::limits::Limits;
+use tracing::{error, warn};
+
+/// Addresses of agents reached through the MQTT broker: `mqtt://<agent id>`
+pub const MQTT_SCHEME: &str = "mqtt://";
+
+/// How signed messages travel to another agent. `send_ipc_message` and friends pick one by
+/// the address, so HTTP and MQTT peers can be mixed freely.
+#[async_trait]
+pub trait Transport: Send + Sync {
+    /// Deliver `payload` to the agent at `address`
+    async fn send(&self, address: &str, payload: &Message, timeout: Option<Duration>) -> Result<MessageResponse>;
+}
+
+/// Direct HTTP posts to the peer's `/message` endpoint; the answer comes back in the response
+pub struct HttpTransport;
+
+#[async_trait]
+impl Transport for HttpTransport {
+    async fn send(&self, address: &str, payload: &Message, timeout: Option<Duration>) -> Result<MessageResponse> {
+        let mut request = http_client().post(message_url(address)).json(payload);
+        if let Some(timeout) = timeout {
+            request = request.timeout(timeout);
+        }
+        Ok(request.send().await?.error_for_status()?.json().await?)
+    }
+}
+
+/// One client for every post: building one loads the TLS roots, which takes longer than a
+/// local request. Connections aren't pooled, since natives send from short-lived runtimes.
+/// Redirects aren't followed: the egress check only saw `address`, and names only resolve to
+/// addresses the policy allows.
+fn http_client() -> &'static reqwest::Client {
+    static CLIENT: std::sync::OnceLock<reqwest::Client> = std::sync::OnceLock::new();
+    CLIENT.get_or_init(|| {
+        reqwest::Client::builder()
+            .pool_max_idle_per_host(0)
+            .redirect(reqwest::redirect::Policy::none())
+            .dns_resolver(crate::egress::resolver(crate::safety::SafetyPolicy::from_env))
+            .build()
+            .unwrap_or_default()
+    })
+}
+
+/// Which transport `start_server` listens on: `SWARM_TRANSPORT=http` (default) or `mqtt`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TransportKind {
+    Http,
+    Mqtt,
+}
+
+impl TransportKind {
+    pub fn from_env() -> Self {
+        match std::env::var("SWARM_TRANSPORT").map(|v| v.trim().to_lowercase()).as_deref() {
+            Ok("mqtt") => TransportKind::Mqtt,
+            _ => TransportKind::Http,
+        }
+    }
+}
+
+/// Broker connection and topic layout. Each agent reads `<prefix>/agents/<id>/inbox`,
+/// and everyone reads `<prefix>/broadcast`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MqttConfig {
+    pub host: String,
+    pub port: u16,
+    pub agent_id: String,
+    pub prefix: String,
+}
+
+impl MqttConfig {
+    /// `broker` is `host`, `host:port` or `mqtt://host:port` (port 1883 by default)
+    pub fn new(broker: &str, agent_id: &str) -> Result<Self> {
+        let broker = broker.trim().trim_start_matches(MQTT_SCHEME).trim_start_matches("tcp://").trim_end_matches('/');
+        let (host, port) = match broker.rsplit_once(':') {
+            Some((host, port)) => (host, port.parse().map_err(|_| anyhow!("Invalid MQTT broker port '{}'", port))?),
+            None => (broker, 1883),
+        };
+        if host.is_empty() {
+            return Err(anyhow!("MQTT broker address is empty"));
+        }
+        if agent_id.is_empty() || agent_id.contains(['/', '+', '#']) {
+            return Err(anyhow!("Invalid MQTT agent id '{}': it must be non-empty without '/', '+' or '#'", agent_id));
+        }
+        Ok(Self { host: host.to_string(), port, agent_id: agent_id.to_string(), prefix: "swarm".to_string() })
+    }
+
+    pub fn with_prefix(mut self, prefix: &str) -> Self {
+        self.prefix = prefix.trim_matches('/').to_string();
+        self
+    }
+
+    /// `MQTT_BROKER`, `MQTT_AGENT_ID` (default `agent-<pid>`) and `MQTT_TOPIC_PREFIX` (default `swarm`);
+    /// None if no broker is configured
+    pub fn from_env() -> Option<Result<Self>> {
+        let broker = std::env::var("MQTT_BROKER").ok().filter(|b| !b.trim().is_empty())?;
+        let agent_id = std::env::var("MQTT_AGENT_ID").ok()
+            .filter(|id| !id.trim().is_empty())
+            .unwrap_or_else(|| format!("agent-{}", std::process::id()));
+        let config = Self::new(&broker, agent_id.trim());
+        Some(match std::env::var("MQTT_TOPIC_PREFIX").ok().filter(|p| !p.trim().is_empty()) {
+            Some(prefix) => config.map(|c| c.with_prefix(&prefix)),
+            None => config,
+        })
+    }
+
+    pub fn inbox_topic(&self, agent_id: &str) -> String {
+        format!("{}/agents/{}/inbox", self.prefix, agent_id)
+    }
+
+    pub fn broadcast_topic(&self) -> String {
+        format!("{}/broadcast", self.prefix)
+    }
+
+    /// The topic a message for `address` is published on: `mqtt://*` is the broadcast topic
+    pub fn topic_for(&self, address: &str) -> Result<String> {
+        match mqtt_agent(address) {
+            Some("*") => Ok(self.broadcast_topic()),
+            Some(agent) if !agent.is_empty() && !agent.contains(['/', '+', '#']) => Ok(self.inbox_topic(agent)),
+            _ => Err(anyhow!("'{}' is not an MQTT agent address", address)),
+        }
+    }
+}
+
+/// The agent id in an `mqtt://<id>` address
+pub fn mqtt_agent(address: &str) -> Option<&str> {
+    address.strip_prefix(MQTT_SCHEME).map(|id| id.trim_end_matches('/'))
+}
+
+/// Messages published through a broker. The connection is driven on a thread of its own,
+/// so it outlives the short-lived runtimes natives send from, and reconnects by itself.
+pub struct MqttTransport {
+    config: MqttConfig,
+    client: AsyncClient,
+    incoming: Mutex<Option<mpsc::UnboundedReceiver<Message>>>,
+}
+
+impl MqttTransport {
+    /// Connect to the broker and subscribe to this agent's inbox and the broadcast topic
+    pub fn connect(config: MqttConfig) -> Result<Self> {
+        // Client ids must be unique on the broker, so a restarted agent doesn't fight its old session
+        let client_id = format!("{}-{}", config.agent_id, crate::message::message_id());
+        let mut options = MqttOptions::new(client_id, config.host.clone(), config.port);
+        options.set_keep_alive(Duration::from_secs(30));
+        let max_bytes = Limits::from_env().max_body_bytes;
+        options.set_max_packet_size(max_bytes, max_bytes);
+        let (client, mut eventloop) = AsyncClient::new(options, 64);
+
+        let (sender, incoming) = mpsc::unbounded_channel();
+        let topics = [config.inbox_topic(&config.agent_id), config.broadcast_topic()];
+        let me = format!("{}{}", MQTT_SCHEME, config.agent_id);
+        let subscriber = client.clone();
+        std::thread::spawn(move || {
+            let rt = tokio::runtime::Runtime::new().unwrap();
+            rt.block_on(async move {
+                loop {
+                    match eventloop.poll().await {
+                        // Subscriptions don't survive a reconnect with a clean session
+                        Ok(Event::Incoming(Packet::ConnAck(_))) => {
+                            for topic in &topics {
+                                if let Err(e) = subscriber.try_subscribe(topic, QoS::AtLeastOnce) {
+                                    warn!("Failed to subscribe to {}: {}", topic, e);
+                                }
+                            }
+                        }
+                        Ok(Event::Incoming(Packet::Publish(publish))) => {
+                            match serde_json::from_slice::<Message>(&publish.payload) {
+                                // Our own broadcasts come back to us too
+                                Ok(message) if message.from.as_deref() == Some(me.as_str()) => {}
+                                Ok(message) => {
+                                    let _ = sender.send(message);
+                                }
+                                Err(e) => warn!("Ignoring malformed message on {}: {}", publish.topic, e),
+                            }
+                        }
+                        Ok(_) => {}
+                        Err(e) => {
+                            error!("MQTT connection error: {}", e);
+                            tokio::time::sleep(Duration::from_secs(1)).await;
+                        }
+                    }
+                }
+            });
+        });
+        Ok(Self { config, client, incoming: Mutex::new(Some(incoming)) })
+    }
+
+    pub fn config(&self) -> &MqttConfig {
+        &self.config
+    }
+
+    /// This agent's address, for peers and `from` fields
+    pub fn address(&self) -> String {
+        format!("{}{}", MQTT_SCHEME, self.config.agent_id)
+    }
+
+    /// Messages arriving for this agent; only one server can take them
+    pub fn take_incoming(&self) -> Option<mpsc::UnboundedReceiver<Message>> {
+        self.incoming.lock().unwrap().take()
+    }
+
+    /// Hand the inbox back once a server stops
+    pub fn restore_incoming(&self, incoming: mpsc::UnboundedReceiver<Message>) {
+        *self.incoming.lock().unwrap() = Some(incoming);
+    }
+}
+
+#[async_trait]
+impl Transport for MqttTransport {
+    /// Publish to the peer's inbox topic. The broker holds no reply channel, so answers
+    /// to calls come back later as messages of their own.
+    async fn send(&self, address: &str, payload: &Message, timeout: Option<Duration>) -> Result<MessageResponse> {
+        let topic = self.config.topic_for(address)?;
+        let publish = self.client.publish(topic.clone(), QoS::AtLeastOnce, false, serde_json::to_vec(payload)?);
+        match timeout {
+            Some(timeout) => tokio::time::timeout(timeout, publish).await
+                .map_err(|_| anyhow!("MQTT broker did not take the message within {:?}", timeout))??,
+            None => publish.await?,
+        }
+        Ok(MessageResponse::ok(format!("Published to {}", topic)))
+    }
+}
+
+// One broker connection per process, opened on first use
+static MQTT: Mutex<Option<Arc<MqttTransport>>> = Mutex::new(None);
+
+/// The process-wide MQTT connection, configured by `MQTT_BROKER` and friends
+pub fn mqtt() -> Result<Arc<MqttTransport>> {
+    let mut shared = MQTT.lock().unwrap();
+    if let Some(transport) = shared.as_ref() {
+        return Ok(transport.clone());
+    }
+    let config = MqttConfig::from_env().ok_or_else(|| anyhow!("MQTT_BROKER is not set"))??;
+    let transport = Arc::new(MqttTransport::connect(config)?);
+    *shared = Some(transport.clone());
+    Ok(transport)
+}
+
+/// The transport that reaches `address`: MQTT for `mqtt://` addresses, HTTP otherwise
+pub fn for_address(address: &str) -> Result<Arc<dyn Transport>> {
+    if mqtt_agent(address).is_some() {
+        Ok(mqtt()?)
+    } else {
+        Ok(Arc::new(HttpTransport))
+    }
+}
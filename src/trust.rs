@@ -0,0 +1,198 @@
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use crate::auth::Sender;
+use crate::registry::address_key;
+use crate::quarantine::Quarantine;
+use crate::state::StateStore;
+use rhai::Engine;
+use crate::tools::{record_tool_decision, NativeContext, PendingTool};
+
+const TRUST_KEY: &str = "peer_trust";
+/// Fingerprint of the agent key each trusted name was first seen signing with
+const KEYS_KEY: &str = "peer_keys";
+
+/// How far this agent trusts a peer, from most to least
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TrustLevel {
+    /// Safe tools it shares are installed without approval
+    Trusted,
+    Known,
+    /// Anyone not in the store
+    Unknown,
+    /// Messages are refused and its pending tools rejected
+    Blocked,
+}
+
+impl TrustLevel {
+    pub fn parse(level: &str) -> Result<Self> {
+        match level.trim().to_lowercase().as_str() {
+            "trusted" => Ok(TrustLevel::Trusted),
+            "known" => Ok(TrustLevel::Known),
+            "unknown" => Ok(TrustLevel::Unknown),
+            "blocked" => Ok(TrustLevel::Blocked),
+            other => Err(anyhow!("Unknown trust level '{}': expected trusted, known, unknown or blocked", other)),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TrustLevel::Trusted => "trusted",
+            TrustLevel::Known => "known",
+            TrustLevel::Unknown => "unknown",
+            TrustLevel::Blocked => "blocked",
+        }
+    }
+}
+
+/// Per-peer trust, persisted in the agent state store. Peers are agent names, which only
+/// verified signatures prove, or server addresses.
+///
+/// A verified name only proves the sender holds the swarm secret, which every member does, so
+/// trust in a name is pinned to the agent key (`Signature::agent_key`) it is first seen with
+/// after being trusted. Messages under that name signed with any other key, or with none, are
+/// only known. Setting the peer's level again forgets the pinned key.
+#[derive(Debug, Clone)]
+pub struct TrustStore {
+    store: StateStore,
+}
+
+/// Addresses are kept in their `address_key` form, so `http://host:port/message` is `host:port`
+fn peer_key(peer: &str) -> String {
+    address_key(peer.trim())
+}
+
+impl TrustStore {
+    pub fn new(store: StateStore) -> Self {
+        Self { store }
+    }
+
+    pub fn set(&self, peer: &str, level: TrustLevel) -> Result<()> {
+        let peer = peer_key(peer);
+        if peer.is_empty() {
+            return Err(anyhow!("Peer name is empty"));
+        }
+        self.store.update(KEYS_KEY, |keys: &mut BTreeMap<String, String>| {
+            keys.remove(&peer);
+        })?;
+        self.store.update(TRUST_KEY, |levels: &mut BTreeMap<String, TrustLevel>| {
+            if level == TrustLevel::Unknown {
+                levels.remove(&peer);
+            } else {
+                levels.insert(peer.clone(), level);
+            }
+        })
+    }
+
+    /// The agent key pinned for `peer`, if it has been seen signing since it was trusted
+    pub fn pinned_key(&self, peer: &str) -> Result<Option<String>> {
+        let keys = self.store.get::<BTreeMap<String, String>>(KEYS_KEY)?.unwrap_or_default();
+        Ok(keys.get(&peer_key(peer)).cloned())
+    }
+
+    /// Whether `key` is the one pinned for trusted `agent`, pinning it if none is yet
+    fn vouches(&self, agent: &str, key: Option<&str>) -> Result<bool> {
+        let Some(key) = key else { return Ok(false) };
+        let mut matches = false;
+        self.store.update(KEYS_KEY, |keys: &mut BTreeMap<String, String>| {
+            matches = keys.entry(peer_key(agent)).or_insert_with(|| key.to_string()) == key;
+        })?;
+        Ok(matches)
+    }
+
+    pub fn level(&self, peer: &str) -> Result<TrustLevel> {
+        let levels = self.store.get::<BTreeMap<String, TrustLevel>>(TRUST_KEY)?.unwrap_or_default();
+        Ok(levels.get(&peer_key(peer)).copied().unwrap_or(TrustLevel::Unknown))
+    }
+
+    /// The standing of a message's sender, who signed with agent key `key` (a fingerprint).
+    /// Blocking its verified name or its claimed address blocks it, but only a verified name
+    /// signed with the name's pinned key can make it trusted; a trusted address is just known.
+    pub fn standing(&self, sender: &Sender, key: Option<&str>, from: Option<&str>) -> Result<TrustLevel> {
+        let by_name = match sender {
+            Sender::Verified(agent) => match self.level(agent)? {
+                TrustLevel::Trusted if !self.vouches(agent, key)? => TrustLevel::Known,
+                level => level,
+            },
+            Sender::Unsigned => TrustLevel::Unknown,
+        };
+        let by_address = match from {
+            Some(address) => self.level(address)?,
+            None => TrustLevel::Unknown,
+        };
+        Ok(match (by_name, by_address) {
+            (TrustLevel::Blocked, _) | (_, TrustLevel::Blocked) => TrustLevel::Blocked,
+            (TrustLevel::Trusted, _) => TrustLevel::Trusted,
+            (TrustLevel::Known, _) | (_, TrustLevel::Trusted) | (_, TrustLevel::Known) => TrustLevel::Known,
+            _ => TrustLevel::Unknown,
+        })
+    }
+
+    /// Every peer with a level other than unknown
+    pub fn list(&self) -> Result<Vec<(String, TrustLevel)>> {
+        let levels = self.store.get::<BTreeMap<String, TrustLevel>>(TRUST_KEY)?.unwrap_or_default();
+        Ok(levels.into_iter().collect())
+    }
+
+    pub fn render(&self) -> Result<String> {
+        Ok(self.list()?.iter()
+            .map(|(peer, level)| format!("{}: {}", peer, level.as_str()))
+            .collect::<Vec<_>>()
+            .join("\n"))
+    }
+}
+
+/// `trust_agent`, `block_agent`, `set_trust` and `trust_levels`
+pub(crate) fn register_trust_natives(engine: &mut Engine, ctx: &NativeContext) {
+    let NativeContext { tools_dir, audit, pending_tools, trust, .. } = ctx;
+    // Peer trust: blocked peers are refused and lose their pending tools, Safe tools
+    // from trusted ones are installed without approval
+    let set_level = {
+        let trust = trust.clone();
+        let pending_clone = pending_tools.clone();
+        let quarantine = Quarantine::new(tools_dir);
+        let audit_clone = audit.clone();
+        move |peer: &str, level: TrustLevel| -> String {
+            if let Err(e) = trust.set(peer, level) {
+                return format!("Error: {}", e);
+            }
+            let mut message = format!("Peer '{}' is now {}", peer.trim(), level.as_str());
+            if level == TrustLevel::Blocked {
+                let key = address_key(peer.trim());
+                let mut tools = pending_clone.lock().unwrap();
+                let (blocked, kept): (Vec<PendingTool>, Vec<PendingTool>) = tools.drain(..).partition(|tool| {
+                    tool.source_agent == key || tool.source_address.as_deref().map(address_key) == Some(key.clone())
+                });
+                *tools = kept;
+                for tool in &blocked {
+                    record_tool_decision(&audit_clone, "reject_tool", tool, "rejected (sender blocked)");
+                    quarantine.add_or_warn(tool, "sender blocked");
+                }
+                if !blocked.is_empty() {
+                    message.push_str(&format!("; {} pending tool(s) from it rejected", blocked.len()));
+                }
+            }
+            message
+        }
+    };
+    let set_level_clone = set_level.clone();
+    engine.register_fn("trust_agent", move |peer: &str| -> String { set_level_clone(peer, TrustLevel::Trusted) });
+    let set_level_clone = set_level.clone();
+    engine.register_fn("block_agent", move |peer: &str| -> String { set_level_clone(peer, TrustLevel::Blocked) });
+    engine.register_fn("set_trust", move |peer: &str, level: &str| -> String {
+        match TrustLevel::parse(level) {
+            Ok(level) => set_level(peer, level),
+            Err(e) => format!("Error: {}", e),
+        }
+    });
+
+    let trust_clone = trust.clone();
+    engine.register_fn("trust_levels", move || -> String {
+        match trust_clone.render() {
+            Ok(levels) if levels.is_empty() => "Every peer is unknown".to_string(),
+            Ok(levels) => levels,
+            Err(e) => format!("Error reading trust levels: {}", e),
+        }
+    });
+}
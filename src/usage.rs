@@ -0,0 +1,162 @@
+use serde::Serialize;
+use std::collections::BTreeMap;
+use crate::llm::{Completion, Usage};
+use tracing::warn;
+
+/// USD per million tokens
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct Price {
+    pub input: f64,
+    pub output: f64,
+}
+
+impl Price {
+    pub fn cost(&self, usage: &Usage) -> f64 {
+        (usage.input_tokens as f64 * self.input + usage.output_tokens as f64 * self.output) / 1_000_000.0
+    }
+}
+
+/// Prices keyed by a model name fragment; the longest key contained in a
+/// `provider:model` label wins, and unknown models cost nothing
+#[derive(Debug, Clone)]
+pub struct PriceTable {
+    prices: BTreeMap<String, Price>,
+}
+
+impl Default for PriceTable {
+    fn default() -> Self {
+        let defaults = [
+            ("claude-3-haiku", 0.25, 1.25),
+            ("claude-3-sonnet", 3.0, 15.0),
+            ("claude-3-5-sonnet", 3.0, 15.0),
+            ("claude-3-opus", 15.0, 75.0),
+            ("gpt-4o", 2.5, 10.0),
+            ("gpt-4o-mini", 0.15, 0.6),
+            ("ollama:", 0.0, 0.0),
+        ];
+        Self {
+            prices: defaults.iter()
+                .map(|(model, input, output)| (model.to_string(), Price { input: *input, output: *output }))
+                .collect(),
+        }
+    }
+}
+
+impl PriceTable {
+    pub fn empty() -> Self {
+        Self { prices: BTreeMap::new() }
+    }
+
+    /// Built-in prices, extended or overridden by `LLM_PRICES`
+    /// (`model=input/output` per million tokens, comma separated)
+    pub fn from_env() -> Self {
+        let mut table = Self::default();
+        if let Ok(spec) = std::env::var("LLM_PRICES") {
+            for entry in spec.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+                let parsed = entry.split_once('=').and_then(|(model, price)| {
+                    let (input, output) = price.split_once('/')?;
+                    Some((model.trim(), input.trim().parse().ok()?, output.trim().parse().ok()?))
+                });
+                match parsed {
+                    Some((model, input, output)) => table.set(model, Price { input, output }),
+                    None => warn!("Ignoring invalid LLM_PRICES entry '{}'", entry),
+                }
+            }
+        }
+        table
+    }
+
+    pub fn set(&mut self, model: &str, price: Price) {
+        self.prices.insert(model.to_string(), price);
+    }
+
+    pub fn price_for(&self, model: &str) -> Option<Price> {
+        self.prices.iter()
+            .filter(|(key, _)| model.contains(key.as_str()))
+            .max_by_key(|(key, _)| key.len())
+            .map(|(_, price)| *price)
+    }
+
+    pub fn cost(&self, model: &str, usage: &Usage) -> f64 {
+        self.price_for(model).map(|p| p.cost(usage)).unwrap_or(0.0)
+    }
+}
+
+/// Totals for one model
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ModelUsage {
+    pub usage: Usage,
+    pub requests: u64,
+    pub cost: f64,
+}
+
+/// Token and cost totals for the session and for the latest turn
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct UsageReport {
+    pub session: Usage,
+    pub session_cost: f64,
+    pub requests: u64,
+    pub cached_requests: u64,
+    /// Everything spent on the latest user turn (including summarization calls)
+    pub turn: Usage,
+    pub turn_cost: f64,
+    pub by_model: BTreeMap<String, ModelUsage>,
+}
+
+impl UsageReport {
+    pub fn render(&self) -> String {
+        let mut out = format!(
+            "Session: {} in / {} out tokens, {} requests ({} cached), ${:.4}\nLast turn: {} in / {} out tokens, ${:.4}",
+            self.session.input_tokens, self.session.output_tokens, self.requests, self.cached_requests, self.session_cost,
+            self.turn.input_tokens, self.turn.output_tokens, self.turn_cost,
+        );
+        for (model, totals) in &self.by_model {
+            out.push_str(&format!(
+                "\n  {}: {} in / {} out tokens, {} requests, ${:.4}",
+                model, totals.usage.input_tokens, totals.usage.output_tokens, totals.requests, totals.cost
+            ));
+        }
+        out
+    }
+}
+
+/// Accumulates usage from completions and prices it
+#[derive(Debug, Clone)]
+pub struct UsageTracker {
+    prices: PriceTable,
+    report: UsageReport,
+}
+
+impl UsageTracker {
+    pub fn new(prices: PriceTable) -> Self {
+        Self { prices, report: UsageReport::default() }
+    }
+
+    /// Reset the per-turn totals
+    pub fn start_turn(&mut self) {
+        self.report.turn = Usage::default();
+        self.report.turn_cost = 0.0;
+    }
+
+    pub fn record(&mut self, completion: &Completion) {
+        let cost = self.prices.cost(&completion.model, &completion.usage);
+        let report = &mut self.report;
+        report.requests += 1;
+        if completion.cached {
+            report.cached_requests += 1;
+        }
+        report.session += completion.usage;
+        report.session_cost += cost;
+        report.turn += completion.usage;
+        report.turn_cost += cost;
+
+        let model = report.by_model.entry(completion.model.clone()).or_default();
+        model.usage += completion.usage;
+        model.requests += 1;
+        model.cost += cost;
+    }
+
+    pub fn report(&self) -> &UsageReport {
+        &self.report
+    }
+}
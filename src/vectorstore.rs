@@ -0,0 +1,263 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+use crate::embeddings::cosine_similarity;
+use tracing::warn;
+
+/// A stored vector with arbitrary JSON metadata
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Record {
+    pub id: String,
+    pub vector: Vec<f32>,
+    #[serde(default)]
+    pub metadata: Value,
+}
+
+#[derive(Debug, Clone)]
+pub struct SearchHit {
+    pub id: String,
+    pub score: f32,
+    pub metadata: Value,
+}
+
+/// One line of the on-disk log
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum LogEntry {
+    Add(Record),
+    Delete(String),
+}
+
+#[derive(Default)]
+struct Inner {
+    records: HashMap<String, Record>,
+    /// Log lines that no longer describe a live record (overwritten adds, deletes)
+    dead_lines: usize,
+    /// Size and mtime of the file when we last read or wrote it
+    seen: Option<(u64, Option<SystemTime>)>,
+}
+
+/// Small persistent vector index: an append-only JSON-lines log replayed into memory,
+/// searched by brute-force cosine similarity. `compact` rewrites the log with only live
+/// records; it also runs automatically once dead lines outnumber live ones.
+#[derive(Clone)]
+pub struct VectorStore {
+    path: Option<PathBuf>,
+    inner: Arc<Mutex<Inner>>,
+}
+
+fn file_state(path: &Path) -> Option<(u64, Option<SystemTime>)> {
+    fs::metadata(path).ok().map(|m| (m.len(), m.modified().ok()))
+}
+
+impl VectorStore {
+    /// Open (or create) the store at `path`
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        if let Some(parent) = path.parent() {
+            if !parent.as_os_str().is_empty() {
+                fs::create_dir_all(parent)?;
+            }
+        }
+        let store = Self {
+            path: Some(path),
+            inner: Arc::new(Mutex::new(Inner::default())),
+        };
+        store.sync(&mut store.inner.lock().unwrap())?;
+        Ok(store)
+    }
+
+    /// A store that lives only in memory
+    pub fn in_memory() -> Self {
+        Self {
+            path: None,
+            inner: Arc::new(Mutex::new(Inner::default())),
+        }
+    }
+
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    /// Reload from disk if another handle changed the file since we last saw it
+    fn sync(&self, inner: &mut Inner) -> Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let current = file_state(path);
+        if current == inner.seen && inner.seen.is_some() {
+            return Ok(());
+        }
+
+        let mut records = HashMap::new();
+        let mut lines = 0;
+        if path.exists() {
+            for line in fs::read_to_string(path)?.lines().filter(|l| !l.trim().is_empty()) {
+                lines += 1;
+                match serde_json::from_str::<LogEntry>(line) {
+                    Ok(LogEntry::Add(record)) => {
+                        records.insert(record.id.clone(), record);
+                    }
+                    Ok(LogEntry::Delete(id)) => {
+                        records.remove(&id);
+                    }
+                    // A crash mid-append can leave a truncated last line
+                    Err(e) => warn!("Skipping unreadable line in {:?}: {}", path, e),
+                }
+            }
+        }
+        inner.dead_lines = lines - records.len();
+        inner.records = records;
+        inner.seen = current;
+        Ok(())
+    }
+
+    fn append(&self, inner: &mut Inner, entries: &[LogEntry]) -> Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let mut content = String::new();
+        for entry in entries {
+            content.push_str(&serde_json::to_string(entry)?);
+            content.push('\n');
+        }
+        let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+        file.write_all(content.as_bytes())?;
+        inner.seen = file_state(path);
+        Ok(())
+    }
+
+    /// Insert or replace records
+    pub fn add(&self, records: Vec<Record>) -> Result<()> {
+        let mut inner = self.inner.lock().unwrap();
+        self.sync(&mut inner)?;
+        for record in &records {
+            if inner.records.contains_key(&record.id) {
+                inner.dead_lines += 1;
+            }
+        }
+        let entries: Vec<LogEntry> = records.iter().cloned().map(LogEntry::Add).collect();
+        self.append(&mut inner, &entries)?;
+        for record in records {
+            inner.records.insert(record.id.clone(), record);
+        }
+        self.maybe_compact(&mut inner)
+    }
+
+    /// Delete records by id, returning how many existed
+    pub fn delete(&self, ids: &[String]) -> Result<usize> {
+        let mut inner = self.inner.lock().unwrap();
+        self.sync(&mut inner)?;
+        let existing: Vec<String> = ids.iter().filter(|id| inner.records.contains_key(*id)).cloned().collect();
+        if existing.is_empty() {
+            return Ok(0);
+        }
+        let entries: Vec<LogEntry> = existing.iter().cloned().map(LogEntry::Delete).collect();
+        self.append(&mut inner, &entries)?;
+        for id in &existing {
+            inner.records.remove(id);
+        }
+        // Both the add and the delete line are now dead
+        inner.dead_lines += existing.len() * 2;
+        self.maybe_compact(&mut inner)?;
+        Ok(existing.len())
+    }
+
+    /// Delete every record whose metadata matches `predicate`
+    pub fn delete_where(&self, predicate: impl Fn(&Value) -> bool) -> Result<usize> {
+        let ids: Vec<String> = {
+            let mut inner = self.inner.lock().unwrap();
+            self.sync(&mut inner)?;
+            inner.records.values().filter(|r| predicate(&r.metadata)).map(|r| r.id.clone()).collect()
+        };
+        self.delete(&ids)
+    }
+
+    pub fn get(&self, id: &str) -> Result<Option<Record>> {
+        let mut inner = self.inner.lock().unwrap();
+        self.sync(&mut inner)?;
+        Ok(inner.records.get(id).cloned())
+    }
+
+    pub fn len(&self) -> Result<usize> {
+        let mut inner = self.inner.lock().unwrap();
+        self.sync(&mut inner)?;
+        Ok(inner.records.len())
+    }
+
+    pub fn is_empty(&self) -> Result<bool> {
+        Ok(self.len()? == 0)
+    }
+
+    /// All records, sorted by id
+    pub fn records(&self) -> Result<Vec<Record>> {
+        let mut inner = self.inner.lock().unwrap();
+        self.sync(&mut inner)?;
+        let mut records: Vec<Record> = inner.records.values().cloned().collect();
+        records.sort_by(|a, b| a.id.cmp(&b.id));
+        Ok(records)
+    }
+
+    /// Top `k` records by cosine similarity to `query`
+    pub fn search(&self, query: &[f32], k: usize) -> Result<Vec<SearchHit>> {
+        self.search_where(query, k, |_| true)
+    }
+
+    /// Top `k` records by cosine similarity among those whose metadata matches `filter`
+    pub fn search_where(&self, query: &[f32], k: usize, filter: impl Fn(&Value) -> bool) -> Result<Vec<SearchHit>> {
+        let mut inner = self.inner.lock().unwrap();
+        self.sync(&mut inner)?;
+        let mut hits: Vec<SearchHit> = inner.records.values()
+            .filter(|r| filter(&r.metadata))
+            .map(|r| SearchHit {
+                id: r.id.clone(),
+                score: cosine_similarity(query, &r.vector),
+                metadata: r.metadata.clone(),
+            })
+            .collect();
+        hits.sort_by(|a, b| b.score.total_cmp(&a.score).then_with(|| a.id.cmp(&b.id)));
+        hits.truncate(k);
+        Ok(hits)
+    }
+
+    /// Rewrite the log with only the live records
+    pub fn compact(&self) -> Result<()> {
+        let mut inner = self.inner.lock().unwrap();
+        self.sync(&mut inner)?;
+        self.rewrite(&mut inner)
+    }
+
+    fn maybe_compact(&self, inner: &mut Inner) -> Result<()> {
+        if inner.dead_lines > 64 && inner.dead_lines > inner.records.len() {
+            self.rewrite(inner)?;
+        }
+        Ok(())
+    }
+
+    fn rewrite(&self, inner: &mut Inner) -> Result<()> {
+        let Some(path) = &self.path else {
+            inner.dead_lines = 0;
+            return Ok(());
+        };
+        let mut ids: Vec<&String> = inner.records.keys().collect();
+        ids.sort();
+        let mut content = String::new();
+        for id in ids {
+            content.push_str(&serde_json::to_string(&LogEntry::Add(inner.records[id].clone()))?);
+            content.push('\n');
+        }
+        // Write to a temp file first so a crash never loses the log
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, content)?;
+        fs::rename(&tmp, path)?;
+        inner.dead_lines = 0;
+        inner.seen = file_state(path);
+        Ok(())
+    }
+}
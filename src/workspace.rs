@@ -0,0 +1,216 @@
+use anyhow::{anyhow, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::{Duration, SystemTime};
+use tracing::{info, warn};
+use crate::changes::BACKUP_DIR;
+use crate::config::WorkspaceConfig;
+use crate::jail::FsJail;
+
+/// How long a workspace left behind (say, by a session killed with Ctrl-C) must go unchanged before
+/// it is cleaned up, so the workspace of an agent still running in the same directory is spared
+const LEFTOVER_AFTER: Duration = Duration::from_secs(24 * 3600);
+
+/// The file `SessionWorkspace::start` leaves in each workspace, holding the session id. Only
+/// directories with one are pruned, so anything else kept in the workspace dir is safe.
+pub const SESSION_MARKER: &str = ".swarm-session";
+
+/// What becomes of a session's workspace when the session ends
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Cleanup {
+    /// Leave it for later sessions' pruning
+    Keep,
+    /// Remove it if the session left no files in it
+    #[default]
+    Empty,
+    /// Remove it with everything in it
+    Always,
+}
+
+impl FromStr for Cleanup {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "keep" | "never" => Ok(Cleanup::Keep),
+            "empty" => Ok(Cleanup::Empty),
+            "always" | "delete" => Ok(Cleanup::Always),
+            other => Err(anyhow!("expected keep, empty or always, not '{}'", other)),
+        }
+    }
+}
+
+/// A file the session left in its workspace
+#[derive(Debug, Clone, PartialEq)]
+pub struct Artifact {
+    /// Relative to the workspace, with `/` separators
+    pub path: String,
+    pub size: u64,
+}
+
+fn size_label(bytes: u64) -> String {
+    match bytes {
+        b if b < 1024 => format!("{} B", b),
+        b if b < 1024 * 1024 => format!("{:.1} KB", b as f64 / 1024.0),
+        b => format!("{:.1} MB", b as f64 / (1024.0 * 1024.0)),
+    }
+}
+
+fn collect(root: &Path, dir: &Path, artifacts: &mut Vec<Artifact>) -> Result<()> {
+    for entry in fs::read_dir(dir)?.flatten() {
+        let path = entry.path();
+        let kind = entry.file_type()?;
+        if dir == root && (entry.file_name() == BACKUP_DIR || entry.file_name() == SESSION_MARKER) {
+            continue;
+        }
+        if kind.is_dir() {
+            collect(root, &path, artifacts)?;
+        } else if kind.is_file() {
+            let relative = path.strip_prefix(root).unwrap_or(&path);
+            let path = relative.components().map(|c| c.as_os_str().to_string_lossy()).collect::<Vec<_>>().join("/");
+            artifacts.push(Artifact { path, size: entry.metadata()?.len() });
+        }
+    }
+    Ok(())
+}
+
+/// When anything under `dir` last changed
+fn last_change(dir: &Path) -> SystemTime {
+    let mut newest = fs::metadata(dir).and_then(|m| m.modified()).unwrap_or(SystemTime::UNIX_EPOCH);
+    for entry in fs::read_dir(dir).into_iter().flatten().flatten() {
+        let modified = match entry.file_type() {
+            Ok(kind) if kind.is_dir() => last_change(&entry.path()),
+            _ => entry.metadata().and_then(|m| m.modified()).unwrap_or(SystemTime::UNIX_EPOCH),
+        };
+        newest = newest.max(modified);
+    }
+    newest
+}
+
+/// Every file under `root` but the undo backups and the session marker, sorted by path
+pub fn artifacts(root: &Path) -> Result<Vec<Artifact>> {
+    let mut artifacts = Vec::new();
+    collect(root, root, &mut artifacts)?;
+    artifacts.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(artifacts)
+}
+
+/// Whether `path` is a session workspace: a directory `SessionWorkspace::start` marked
+fn is_session(path: &Path) -> bool {
+    fs::symlink_metadata(path.join(SESSION_MARKER)).is_ok_and(|m| m.is_file())
+}
+
+/// Workspaces of earlier sessions in `dir` that `config` says to remove; `current` is spared,
+/// as is any directory without a session marker
+pub fn prune(dir: &Path, config: &WorkspaceConfig, current: &str) -> Result<Vec<String>> {
+    let cleanup: Cleanup = config.cleanup.parse()?;
+    let mut sessions: Vec<(String, PathBuf, SystemTime)> = Vec::new();
+    for entry in fs::read_dir(dir)?.flatten() {
+        let name = entry.file_name().to_string_lossy().to_string();
+        if name != current && entry.file_type()?.is_dir() && is_session(&entry.path()) {
+            sessions.push((name, entry.path(), last_change(&entry.path())));
+        }
+    }
+    // Newest first, so whatever is past keep_last is the oldest
+    sessions.sort_by_key(|s| std::cmp::Reverse(s.2));
+    let max_age = Duration::from_secs(config.max_age_days * 86_400);
+    let (mut removed, mut kept) = (Vec::new(), 0);
+    for (name, path, modified) in sessions {
+        // The current session counts towards keep_last
+        let surplus = config.keep_last > 0 && kept + 1 >= config.keep_last;
+        let age = modified.elapsed().unwrap_or_default();
+        let expired = config.max_age_days > 0 && age > max_age;
+        let leftover = age > LEFTOVER_AFTER && match cleanup {
+            Cleanup::Keep => false,
+            Cleanup::Empty => artifacts(&path).map(|a| a.is_empty()).unwrap_or(false),
+            Cleanup::Always => true,
+        };
+        if surplus || expired || leftover {
+            match fs::remove_dir_all(&path) {
+                Ok(()) => removed.push(name),
+                Err(e) => warn!("Cannot remove workspace {:?}: {}", path, e),
+            }
+        } else {
+            kept += 1;
+        }
+    }
+    Ok(removed)
+}
+
+/// A directory of its own for one session, where the file tools work
+#[derive(Debug, Clone)]
+pub struct SessionWorkspace {
+    root: PathBuf,
+    session: String,
+    cleanup: Cleanup,
+}
+
+impl SessionWorkspace {
+    /// Create `<config.dir>/<session>` with its `SESSION_MARKER`, first pruning the workspaces
+    /// of earlier sessions
+    pub fn start(config: &WorkspaceConfig, session: &str) -> Result<Self> {
+        fs::create_dir_all(&config.dir)?;
+        let removed = prune(&config.dir, config, session)?;
+        if !removed.is_empty() {
+            info!("Removed {} old session workspace(s): {}", removed.len(), removed.join(", "));
+        }
+        let root = FsJail::new(config.dir.join(session))?.root().to_path_buf();
+        fs::write(root.join(SESSION_MARKER), session)?;
+        Ok(Self { root, session: session.to_string(), cleanup: config.cleanup.parse()? })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.root
+    }
+
+    pub fn session(&self) -> &str {
+        &self.session
+    }
+
+    pub fn artifacts(&self) -> Result<Vec<Artifact>> {
+        artifacts(&self.root)
+    }
+
+    /// The files so far, one per line with its size, for `/workspace`
+    pub fn render(&self) -> Result<String> {
+        let artifacts = self.artifacts()?;
+        let mut lines = vec![format!("Workspace of {}: {}", self.session, self.root.display())];
+        if artifacts.is_empty() {
+            lines.push("No files yet".to_string());
+        }
+        lines.extend(artifacts.iter().map(|a| format!("  {} ({})", a.path, size_label(a.size))));
+        Ok(lines.join("\n"))
+    }
+
+    /// Open the workspace, or the file `path` in it, with the desktop's default application
+    pub fn open(&self, path: &str) -> Result<PathBuf> {
+        let target = FsJail::new(&self.root)?.resolve(path.trim())?;
+        if !target.exists() {
+            return Err(anyhow!("No file '{}' in the workspace", path.trim()));
+        }
+        let (program, args): (&str, &[&str]) = if cfg!(target_os = "macos") {
+            ("open", &[])
+        } else if cfg!(windows) {
+            ("cmd", &["/C", "start", ""])
+        } else {
+            ("xdg-open", &[])
+        };
+        std::process::Command::new(program).args(args).arg(&target).spawn()
+            .map_err(|e| anyhow!("Cannot run {}: {}", program, e))?;
+        Ok(target)
+    }
+
+    /// End the session as the cleanup policy says; true if the workspace was removed
+    pub fn finish(&self) -> Result<bool> {
+        let remove = match self.cleanup {
+            Cleanup::Keep => false,
+            Cleanup::Empty => self.artifacts()?.is_empty(),
+            Cleanup::Always => true,
+        };
+        if remove {
+            fs::remove_dir_all(&self.root)?;
+        }
+        Ok(remove)
+    }
+}
@@ -0,0 +1,81 @@
+use anyhow::Result;
+use std::net::{IpAddr, SocketAddr};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use swarm_thing::auth::{AccessControl, AccessRule, IpcAuth, Sender};
+use swarm_thing::ipc::{router, IpcState, Message, TaskQueue};
+use swarm_thing::registry::PeerRegistry;
+use swarm_thing::state::StateStore;
+use swarm_thing::threads::PeerThreads;
+
+#[test]
+fn test_access_rules() {
+    let ip = |s: &str| Some(s.parse::<IpAddr>().unwrap());
+    let alpha = Sender::Verified("alpha".to_string());
+
+    assert_eq!(AccessRule::parse("10.0.0.5"), AccessRule::Ip("10.0.0.5".parse().unwrap()));
+    assert!(AccessRule::parse("10.0.0.0/8").matches(ip("10.20.30.40"), &Sender::Unsigned));
+    assert!(!AccessRule::parse("10.0.0.0/8").matches(ip("11.0.0.1"), &Sender::Unsigned));
+    assert!(AccessRule::parse("0.0.0.0/0").matches(ip("192.168.1.1"), &Sender::Unsigned));
+    // IPv4 peers on a dual-stack socket
+    assert!(AccessRule::parse("127.0.0.1").matches(ip("::ffff:127.0.0.1"), &Sender::Unsigned));
+    // Agent names only match verified signatures
+    assert!(AccessRule::parse("alpha").matches(None, &alpha));
+    assert!(!AccessRule::parse("alpha").matches(None, &Sender::Unsigned));
+
+    let access = AccessControl {
+        allow: vec![AccessRule::parse("192.168.0.0/16"), AccessRule::parse("alpha")],
+        deny: vec![AccessRule::parse("192.168.1.66")],
+    };
+    assert!(access.check(ip("192.168.1.2"), &Sender::Unsigned).is_ok());
+    assert!(access.check(ip("10.1.1.1"), &alpha).is_ok());
+    assert!(access.check(ip("10.1.1.1"), &Sender::Unsigned).is_err());
+    // Deny wins over allow
+    assert!(access.check(ip("192.168.1.66"), &alpha).is_err());
+    assert!(AccessControl::default().check(ip("8.8.8.8"), &Sender::Unsigned).is_ok());
+}
+
+async fn serve(state: IpcState) -> Result<String> {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+    let url = format!("http://{}/message", listener.local_addr()?);
+    tokio::spawn(async move {
+        axum::serve(listener, router(state).into_make_service_with_connect_info::<SocketAddr>()).await.unwrap()
+    });
+    Ok(url)
+}
+
+fn state(name: &str) -> Result<IpcState> {
+    let path = std::env::temp_dir().join(format!("swarm_access_{}_{}.json", name, std::process::id()));
+    let threads = PeerThreads::new(StateStore::open(path)?);
+    Ok(IpcState::new(Arc::new(Mutex::new(Vec::new())), threads, TaskQueue::new(), PeerRegistry::new(), PathBuf::from("tools")))
+}
+
+#[tokio::test]
+async fn test_server_enforces_access_lists() -> Result<()> {
+    let client = reqwest::Client::new();
+    let hello = |auth: Option<&IpcAuth>| {
+        let mut msg = Message { content: "hello".to_string(), from: None, context: None, id: None, reply_to: None, signature: None, traceparent: None };
+        if let Some(auth) = auth {
+            auth.sign(&mut msg);
+        }
+        msg
+    };
+
+    let denied = serve(state("deny")?.with_auth(IpcAuth::new(None, "receiver")).with_access(AccessControl {
+        allow: Vec::new(),
+        deny: vec![AccessRule::parse("127.0.0.0/8")],
+    })).await?;
+    assert_eq!(client.post(&denied).json(&hello(None)).send().await?.status(), 403);
+
+    let auth = IpcAuth::new(Some("swarm-secret"), "receiver").with_allow_unsigned(true);
+    let by_identity = serve(state("allow")?.with_auth(auth).with_access(AccessControl {
+        allow: vec![AccessRule::parse("alpha")],
+        deny: Vec::new(),
+    })).await?;
+    let alpha = IpcAuth::new(Some("swarm-secret"), "alpha");
+    let beta = IpcAuth::new(Some("swarm-secret"), "beta");
+    assert_eq!(client.post(&by_identity).json(&hello(Some(&alpha))).send().await?.status(), 200);
+    assert_eq!(client.post(&by_identity).json(&hello(Some(&beta))).send().await?.status(), 403);
+    assert_eq!(client.post(&by_identity).json(&hello(None)).send().await?.status(), 403);
+    Ok(())
+}
@@ -0,0 +1,78 @@
+use anyhow::Result;
+use swarm_thing::agent::{Agent, JSON_RETRIES};
+use swarm_thing::config::LlmConfig;
+use swarm_thing::llm::{Completion, LlmClient, Usage};
+use swarm_thing::parser::{parse_json_actions, parse_response, parse_tool_call, render_markers, ActionProtocol, ParsedAction, ToolBlock};
+use swarm_thing::replay::RecordedResponses;
+use swarm_thing::tools::{ResponseAction, ToolManager};
+
+fn said(text: &str) -> Result<Completion, String> {
+    Ok(Completion { text: text.to_string(), usage: Usage { input_tokens: 10, output_tokens: 2 }, model: "mock".to_string(), cached: false })
+}
+
+#[test]
+fn test_json_actions_parse_and_validate() -> Result<()> {
+    let actions = parse_json_actions(r#"[
+        {"action": "create_tool", "name": "triple", "code": "fn triple(x) { \"```\" + x * 3 }"},
+        {"action": "call_tool", "name": "triple", "args": [4, "a, b"]},
+        {"action": "call_tool", "name": "mcp::files::read", "args": {"path": "a.txt"}}
+    ]"#)?;
+    assert_eq!(actions[0], ParsedAction::CreateTool(ToolBlock { name: "triple".to_string(), code: "fn triple(x) { \"```\" + x * 3 }".to_string(), python: false }));
+    assert_eq!(actions[1], ParsedAction::ToolCall { name: "triple".to_string(), args: vec!["4, a, b".to_string()] });
+    assert_eq!(actions[2], ParsedAction::ToolCall { name: "mcp::files::read".to_string(), args: vec![r#"{"path":"a.txt"}"#.to_string()] });
+
+    // Written out as markers, the actions read back the same, the code gaining a filename comment
+    let reread = parse_response(&render_markers(&actions));
+    assert!(matches!(&reread[0], ParsedAction::CreateTool(block) if block.name == "triple" && block.code.contains("\"```\" + x * 3")), "{:?}", reread);
+    assert_eq!(reread[1..], actions[1..]);
+    let fenced = "Here you go:\n```json\n{\"action\": \"final\", \"answer\": \"done\"}\n```";
+    assert_eq!(parse_json_actions(fenced)?, vec![ParsedAction::FinalAnswer("done".to_string())]);
+
+    let error = |text: &str| parse_json_actions(text).unwrap_err().to_string();
+    assert!(error("sure, I'll call it").contains("not valid JSON"));
+    assert!(error(r#"{"action": "dance"}"#).contains("unknown variant"));
+    assert!(error(r#"{"action": "call_tool", "args": []}"#).contains("missing field `name`"));
+    assert!(error(r#"{"action": "final", "answer": "x", "mood": "happy"}"#).contains("unknown field"));
+    assert!(error(r#"{"action": "create_tool", "name": "../evil", "code": "1"}"#).contains("not a valid tool name"));
+    assert!(error(r#"[{"action": "call_tool", "name": "a"}, {"action": "final", "answer": "b"}]"#).contains("can't be combined"));
+
+    // Apostrophes in marker arguments are not quotes
+    assert_eq!(parse_tool_call("[TOOL: search(Rust's borrow checker)]"), Some(("search".to_string(), vec!["Rust's borrow checker".to_string()])));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_agent_reprompts_malformed_json_actions() -> Result<()> {
+    let llm = LlmClient::replaying(RecordedResponses::new(vec![
+        said("I will square it now."),
+        said(r#"{"action": "call_tool", "name": "square", "args": ["6"]}"#),
+        said(r#"{"action": "final", "answer": "36"}"#),
+    ])).await?;
+    let mut agent = Agent::with_client(llm, "You use tools.");
+    agent.set_protocol(ActionProtocol::Json);
+    let mut tools = ToolManager::new()?;
+
+    let response = agent.chat("square 6").await?;
+    assert_eq!(response, "[TOOL: square(6)]");
+    assert!(matches!(&tools.act_on(&response, None)[..], [ResponseAction::Ran { result: Ok(output), .. }] if output == "36"));
+    // The bad reply and the correction stay in the history
+    assert_eq!(agent.history().len(), 4);
+    assert!(agent.history()[2].content.contains("not a valid JSON action"), "{}", agent.history()[2].content);
+    assert_eq!(agent.chat("Tool Output: 36").await?, "36");
+
+    // A model that never gets it right fails the turn
+    let replies = (0..=JSON_RETRIES).map(|_| said("no JSON here")).collect();
+    let mut agent = Agent::with_client(LlmClient::replaying(RecordedResponses::new(replies)).await?, "");
+    agent.set_protocol(ActionProtocol::Json);
+    let err = agent.chat("hi").await.unwrap_err();
+    assert!(err.to_string().contains(&format!("No valid JSON action after {} retries", JSON_RETRIES)), "{}", err);
+
+    // Per-model protocol selection
+    let mut config = LlmConfig::default();
+    config.protocols.insert("ollama".to_string(), "json".to_string());
+    config.protocols.insert("ollama:llama3.1".to_string(), "markers".to_string());
+    assert_eq!(config.protocol_for("ollama:qwen2.5"), ActionProtocol::Json);
+    assert_eq!(config.protocol_for("ollama:llama3.1"), ActionProtocol::Markers);
+    assert_eq!(config.protocol_for("openai:gpt-4o"), ActionProtocol::Markers);
+    Ok(())
+}
@@ -0,0 +1,72 @@
+use anyhow::Result;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use swarm_thing::audit::AuditLog;
+use swarm_thing::ipc::{router, IpcState, Message, TaskQueue};
+use swarm_thing::message::IpcMessage;
+use swarm_thing::registry::PeerRegistry;
+use swarm_thing::state::StateStore;
+use swarm_thing::threads::PeerThreads;
+use swarm_thing::tools::ToolManager;
+
+#[test]
+fn test_audit_search() -> Result<()> {
+    let path = std::env::temp_dir().join(format!("swarm_audit_search_{}.jsonl", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let audit = AuditLog::open(&path)?;
+    assert_eq!(audit.render_search("", 10)?, "The audit log is empty");
+    audit.record("create_tool", "square (Safe)", "created")?;
+    audit.record("ipc_send", "Text to 127.0.0.1:9000", "ok")?;
+    audit.record("clone_agent", "/tmp/copy", "Error creating directory")?;
+
+    assert_eq!(audit.search("", 2)?.iter().map(|e| e.action.as_str()).collect::<Vec<_>>(), vec!["ipc_send", "clone_agent"]);
+    // Filters match action, detail or outcome, ignoring case
+    assert_eq!(audit.search("SQUARE", 10)?.len(), 1);
+    assert_eq!(audit.search("error", 10)?[0].action, "clone_agent");
+    let rendered = audit.render_search("ipc", 10)?;
+    assert!(rendered.ends_with("ipc_send: Text to 127.0.0.1:9000 -> ok"), "{}", rendered);
+    assert_eq!(audit.render_search("nothing", 10)?, "No audit entries match 'nothing'");
+    let _ = std::fs::remove_file(&path);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_server_audits_received_messages() -> Result<()> {
+    let audit_path = std::env::temp_dir().join(format!("swarm_audit_ipc_{}.jsonl", std::process::id()));
+    let _ = std::fs::remove_file(&audit_path);
+    let state_path = std::env::temp_dir().join(format!("swarm_audit_state_{}.json", std::process::id()));
+    let threads = PeerThreads::new(StateStore::open(&state_path)?);
+    let state = IpcState::new(Arc::new(Mutex::new(Vec::new())), threads, TaskQueue::new(), PeerRegistry::new(), PathBuf::from("tools"))
+        .with_audit(AuditLog::open(&audit_path)?);
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+    let url = format!("http://{}/message", listener.local_addr()?);
+    tokio::spawn(async move { axum::serve(listener, router(state)).await.unwrap() });
+
+    let message = Message::new(&IpcMessage::text("hello"), Some("127.0.0.1:9000".to_string()))?;
+    reqwest::Client::new().post(&url).json(&message).send().await?.error_for_status()?;
+    let entries = AuditLog::open(&audit_path)?.entries()?;
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0].action, "ipc_receive");
+    assert_eq!(entries[0].detail, "Text from 127.0.0.1:9000 (unverified)");
+
+    let _ = std::fs::remove_file(&audit_path);
+    let _ = std::fs::remove_file(&state_path);
+    Ok(())
+}
+
+#[test]
+fn test_privileged_actions_are_audited() -> Result<()> {
+    let mut manager = ToolManager::new()?;
+    manager.create_tool("test_audit_wipe", r#"fn test_audit_wipe(path) { delete_file(path) }"#)?;
+    manager.execute_tool("test_audit_wipe", vec!["no_such_file_for_audit.txt".to_string()])?;
+    manager.execute_tool("remove_tool", vec!["test_audit_wipe".to_string()])?;
+
+    let entries = manager.audit_log().search("test_audit_wipe", 10)?;
+    let created = entries.iter().find(|e| e.action == "create_tool").expect("creation is audited");
+    assert_eq!(created.detail, "test_audit_wipe (HighRisk)");
+    let run = entries.iter().find(|e| e.action == "execute_tool").expect("HighRisk runs are audited");
+    assert_eq!(run.detail, "test_audit_wipe(no_such_file_for_audit.txt)");
+    assert!(run.outcome.starts_with("ok: Error deleting file"), "{}", run.outcome);
+    assert!(manager.execute_tool("show_audit", vec!["test_audit_wipe".to_string()])?.contains("create_tool: test_audit_wipe (HighRisk) -> created"));
+    Ok(())
+}
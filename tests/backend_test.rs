@@ -0,0 +1,93 @@
+use anyhow::{anyhow, Result};
+use std::path::Path;
+use std::sync::Arc;
+use swarm_thing::backend::{function_params, ToolBackend, ToolSignature};
+use swarm_thing::message::ToolSafetyLevel;
+use swarm_thing::safety::SafetyPolicy;
+use swarm_thing::tools::ToolManager;
+
+/// Text templates: `{0}`, `{1}`... are replaced with the arguments
+struct Templates;
+
+impl ToolBackend for Templates {
+    fn name(&self) -> &str {
+        "template"
+    }
+
+    fn extension(&self) -> &str {
+        "tpl"
+    }
+
+    fn compile(&self, _name: &str, code: &str) -> Result<()> {
+        if code.matches('{').count() != code.matches('}').count() {
+            return Err(anyhow!("unbalanced braces"));
+        }
+        Ok(())
+    }
+
+    fn execute(&self, _name: &str, path: &Path, args: &[String]) -> Result<String> {
+        let mut text = std::fs::read_to_string(path)?.lines().filter(|l| !l.starts_with("//")).collect::<Vec<_>>().join("\n");
+        for (i, arg) in args.iter().enumerate() {
+            text = text.replace(&format!("{{{}}}", i), arg);
+        }
+        Ok(text)
+    }
+
+    fn inspect(&self, _name: &str, code: &str) -> ToolSignature {
+        let count = (0..).take_while(|i| code.contains(&format!("{{{}}}", i))).count();
+        ToolSignature { params: (0..count).map(|i| format!("arg{}", i)).collect(), ..Default::default() }
+    }
+
+    fn validate(&self, _code: &str, _safety: &SafetyPolicy) -> ToolSafetyLevel {
+        ToolSafetyLevel::Safe
+    }
+}
+
+#[test]
+fn test_registered_backend_runs_its_tools() -> Result<()> {
+    let mut manager = ToolManager::new()?;
+    assert_eq!(manager.backend_names()[0], "rhai");
+    assert!(manager.create_tool_for("tpl", "test_backend_greet", "Hello").is_err(), "no backend for .tpl yet");
+
+    manager.register_backend(Arc::new(Templates))?;
+    assert!(manager.backend_names().contains(&"template".to_string()));
+    manager.create_tool_for("tpl", "test_backend_greet", "// Greets two people\nHello, {0} and {1}!")?;
+    assert!(manager.list_tools().contains(&"test_backend_greet".to_string()));
+    assert_eq!(manager.tool_params("test_backend_greet"), ["arg0", "arg1"]);
+    let greeting = manager.execute_tool("test_backend_greet", vec!["Ada".to_string(), "Alan".to_string()])?;
+    assert_eq!(greeting, "Hello, Ada and Alan!");
+
+    // Saved either way, but the author hears what's wrong
+    let err = manager.create_tool_for("tpl", "test_backend_broken", "Hello, {0").unwrap_err();
+    assert_eq!(err.to_string(), "unbalanced braces");
+    assert!(manager.check_tools().iter().any(|(name, e)| name == "test_backend_broken" && e == "unbalanced braces"));
+
+    // Rhai stays built in
+    struct Impostor;
+    impl ToolBackend for Impostor {
+        fn name(&self) -> &str { "impostor" }
+        fn extension(&self) -> &str { "rhai" }
+        fn compile(&self, _name: &str, _code: &str) -> Result<()> { Ok(()) }
+        fn execute(&self, _name: &str, _path: &Path, _args: &[String]) -> Result<String> { Ok(String::new()) }
+        fn inspect(&self, _name: &str, _code: &str) -> ToolSignature { ToolSignature::default() }
+    }
+    assert!(manager.register_backend(Arc::new(Impostor)).is_err());
+
+    manager.remove_tool("test_backend_greet")?;
+    manager.remove_tool("test_backend_broken")?;
+    Ok(())
+}
+
+#[test]
+fn test_rhai_backend_inspects_and_validates() -> Result<()> {
+    let manager = ToolManager::new()?;
+    let rhai = manager.backend("rhai").expect("built in");
+    let code = "// Reads a note aloud\nfn read_note(path, voice) { read_file(path) }";
+    let signature = rhai.inspect("read_note", code);
+    assert_eq!(signature.params, ["path", "voice"]);
+    assert_eq!(signature.description.as_deref(), Some("Reads a note aloud"));
+    assert_eq!(rhai.validate(code, &SafetyPolicy::default()), ToolSafetyLevel::MediumRisk);
+    assert!(rhai.compile("read_note", "fn read_note( {").is_err());
+    assert_eq!(function_params("def shout(self, text: str = 'hi'):", "def", "shout"), ["text"]);
+    Ok(())
+}
@@ -0,0 +1,113 @@
+use anyhow::Result;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use swarm_thing::blackboard::Blackboard;
+use swarm_thing::inbox::Inbox;
+use swarm_thing::ipc::{router, send_ipc_message, IpcState, TaskQueue};
+use swarm_thing::message::IpcMessage;
+use swarm_thing::registry::PeerRegistry;
+use swarm_thing::state::StateStore;
+use swarm_thing::threads::PeerThreads;
+use swarm_thing::tools::ToolManager;
+
+fn store(name: &str) -> Result<StateStore> {
+    let path = std::env::temp_dir().join(format!("swarm_blackboard_{}_{}.json", name, std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    StateStore::open(path)
+}
+
+/// Serve an agent whose blackboard is kept in `store`, returning its address
+async fn serve(store: StateStore, inbox: Inbox) -> Result<String> {
+    let state = IpcState::new(Arc::new(Mutex::new(Vec::new())), PeerThreads::new(store), TaskQueue::new(), PeerRegistry::new(), PathBuf::from("tools"))
+        .with_inbox(inbox);
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+    let address = listener.local_addr()?.to_string();
+    tokio::spawn(async move {
+        axum::serve(listener, router(state).into_make_service_with_connect_info::<SocketAddr>()).await.unwrap()
+    });
+    Ok(address)
+}
+
+#[test]
+fn test_copies_converge() -> Result<()> {
+    let a = Blackboard::new(store("a")?);
+    let b = Blackboard::new(store("b")?);
+
+    // b hears of a's write, then writes over it: its copy supersedes a's
+    let first = a.set("findings", "three papers", "a")?;
+    assert!(b.merge(first.clone())?);
+    let second = b.set("findings", "four papers", "b")?;
+    assert_eq!(second.clock.get("a"), Some(&1));
+    assert!(a.merge(second)?);
+    assert!(!a.merge(first)?, "an older write never wins");
+    assert_eq!(a.get("findings")?.unwrap().value, "four papers");
+
+    // Concurrent writes: the later one wins on both sides, whichever arrives first
+    let from_a = a.set("status", "drafting", "a")?;
+    std::thread::sleep(Duration::from_millis(5));
+    let from_b = b.set("status", "reviewing", "b")?;
+    assert!(a.merge(from_b.clone())?);
+    assert!(!b.merge(from_a)?);
+    assert_eq!(a.get("status")?.unwrap().value, "reviewing");
+    assert_eq!(b.get("status")?.unwrap().value, "reviewing");
+    // The winner now carries both histories, so a's next write supersedes it everywhere
+    let next = a.set("status", "done", "a")?;
+    assert!(b.merge(next)?);
+    assert_eq!(b.get("status")?.unwrap().value, "done");
+
+    assert_eq!(a.render()?, "findings = four papers (b)\nstatus = done (a)");
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_updates_and_sync_over_ipc() -> Result<()> {
+    let peer_store = store("peer")?;
+    let inbox = Inbox::new();
+    let peer = serve(peer_store.clone(), inbox.clone()).await?;
+    let theirs = Blackboard::new(peer_store);
+    assert!(theirs.watch("results/*")?);
+    assert!(!theirs.watch("results/*")?);
+    theirs.set("plan", "split the survey", "peer")?;
+
+    let mine = Blackboard::new(store("mine")?);
+    let update = mine.set("results/survey", "42 responses", "me")?;
+    let reply = send_ipc_message(&peer, &IpcMessage::BlackboardUpdate(update.clone()), Some("127.0.0.1:9000".to_string())).await?;
+    assert_eq!(reply, "Blackboard 'results/survey' updated");
+    assert_eq!(theirs.get("results/survey")?.unwrap().value, "42 responses");
+    assert!(inbox.render_unread().contains("[blackboard results/survey] 42 responses"), "{}", inbox.render_unread());
+    let reply = send_ipc_message(&peer, &IpcMessage::BlackboardUpdate(update), None).await?;
+    assert_eq!(reply, "Blackboard 'results/survey' already up to date");
+
+    // Unwatched keys change quietly
+    let before = inbox.unread_count();
+    let note = mine.set("notes", "ask about sampling", "me")?;
+    send_ipc_message(&peer, &IpcMessage::BlackboardUpdate(note), None).await?;
+    assert_eq!(inbox.unread_count(), before);
+
+    // A sync is answered with the receiver's entries, including those the sender missed
+    let reply = send_ipc_message(&peer, &IpcMessage::BlackboardSync { entries: mine.entries()? }, None).await?;
+    let IpcMessage::BlackboardSync { entries } = IpcMessage::from_json_or_text(&reply) else {
+        panic!("expected a sync reply: {}", reply);
+    };
+    for entry in entries {
+        mine.merge(entry)?;
+    }
+    assert_eq!(mine.get("plan")?.unwrap().value, "split the survey");
+    assert_eq!(mine.entries()?, theirs.entries()?);
+    Ok(())
+}
+
+#[test]
+fn test_blackboard_natives() -> Result<()> {
+    let manager = ToolManager::new()?;
+    let key = format!("test_blackboard_{}", std::process::id());
+    assert!(manager.execute_tool("blackboard_get", vec![key.clone()])?.starts_with("Error: nothing on the blackboard"));
+    assert!(manager.execute_tool("blackboard_set", vec![key.clone(), "halfway".to_string()])?.starts_with(&format!("Set '{}'", key)));
+    assert_eq!(manager.execute_tool("blackboard_get", vec![key.clone()])?, "halfway");
+    assert!(manager.execute_tool("blackboard_get", vec![])?.contains(&format!("{} = halfway", key)));
+    assert!(manager.execute_tool("blackboard_watch", vec![key.clone()])?.starts_with("Watching"));
+    assert!(manager.execute_tool("blackboard_unwatch", vec![key.clone()])?.starts_with("No longer watching"));
+    Ok(())
+}
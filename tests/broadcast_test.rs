@@ -0,0 +1,92 @@
+use anyhow::Result;
+use axum::{routing::post, Json, Router};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use swarm_thing::ipc::{Message, MessageResponse};
+use swarm_thing::message::IpcMessage;
+use swarm_thing::registry::{broadcast, PeerInfo, PeerRegistry};
+use swarm_thing::tools::ToolManager;
+
+fn peer(name: &str, address: &str, capabilities: &[&str]) -> PeerInfo {
+    PeerInfo {
+        name: name.to_string(),
+        address: address.to_string(),
+        capabilities: capabilities.iter().map(|c| c.to_string()).collect(),
+        tools: vec!["square".to_string()],
+        last_seen: 0,
+        missed_heartbeats: 0,
+        public_key: None,
+    }
+}
+
+/// A peer that records what it receives and answers `ack from <name>`
+async fn listening_peer(name: &'static str, received: Arc<Mutex<Vec<String>>>) -> Result<String> {
+    let app = Router::new().route("/message", post(move |Json(msg): Json<Message>| {
+        let received = received.clone();
+        async move {
+            received.lock().unwrap().push(msg.content);
+            Json(MessageResponse::ok(format!("ack from {}", name)))
+        }
+    }));
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+    let address = listener.local_addr()?.to_string();
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+    Ok(address)
+}
+
+#[test]
+fn test_resolve_peer_groups() {
+    let registry = PeerRegistry::new();
+    registry.upsert(peer("alpha", "127.0.0.1:1", &["web"]));
+    registry.upsert(peer("beta", "127.0.0.1:2", &["files"]));
+    registry.set_group("reviewers", vec!["http://127.0.0.1:2/message".to_string(), " ".to_string()]);
+
+    assert_eq!(registry.resolve_group("all"), vec!["127.0.0.1:1", "127.0.0.1:2"]);
+    assert_eq!(registry.resolve_group("reviewers"), vec!["127.0.0.1:2"]);
+    // Anything else matches advertised capabilities and tools
+    assert_eq!(registry.resolve_group("web"), vec!["127.0.0.1:1"]);
+    assert_eq!(registry.resolve_group("square").len(), 2);
+    assert!(registry.resolve_group("nobody").is_empty());
+
+    registry.set_group("reviewers", Vec::new());
+    assert!(registry.groups().is_empty());
+}
+
+#[tokio::test]
+async fn test_broadcast_reports_each_peer() -> Result<()> {
+    let received = Arc::new(Mutex::new(Vec::new()));
+    let first = listening_peer("first", received.clone()).await?;
+    let second = listening_peer("second", received.clone()).await?;
+    let peers = vec![first.clone(), second.clone(), "127.0.0.1:9889".to_string()];
+
+    let report = broadcast(&peers, &IpcMessage::text("new tool: square"), None, Duration::from_secs(5)).await;
+    assert_eq!(report.delivered(), 2);
+    assert_eq!(report.failed().len(), 1);
+    assert_eq!(report.failed()[0].peer, "127.0.0.1:9889");
+    assert_eq!(received.lock().unwrap().len(), 2);
+
+    let rendered = report.render();
+    assert!(rendered.starts_with("Delivered to 2/3 peer(s)"), "{}", rendered);
+    assert!(rendered.contains(&format!("✅ {}: ack from first", first)));
+    assert!(rendered.contains("❌ 127.0.0.1:9889:"));
+    Ok(())
+}
+
+// The native blocks its caller, so the peers need a worker thread of their own
+#[tokio::test(flavor = "multi_thread")]
+async fn test_broadcast_message_native() -> Result<()> {
+    let received = Arc::new(Mutex::new(Vec::new()));
+    let worker = listening_peer("worker", received.clone()).await?;
+    let mut tools = ToolManager::new()?;
+    tools.peers().upsert(peer("worker", &worker, &["bidding"]));
+    tools.peers().upsert(peer("other", "127.0.0.1:9890", &[]));
+    tools.create_tool("test_broadcast_bid", r#"fn test_broadcast_bid(group) { return broadcast_message(group, "Bids wanted: summarize report"); }"#)?;
+
+    let report = tools.execute_tool("test_broadcast_bid", vec!["bidding".to_string()])?;
+    assert!(report.starts_with("Delivered to 1/1 peer(s)"), "{}", report);
+    assert!(matches!(IpcMessage::from_json_or_text(&received.lock().unwrap()[0]), IpcMessage::Text { content, .. } if content.starts_with("Bids wanted")));
+    assert!(tools.execute_tool("test_broadcast_bid", vec!["nobody".to_string()])?.contains("no peers in group"));
+
+    std::fs::remove_file("tools/test_broadcast_bid.rhai")?;
+    Ok(())
+}
@@ -0,0 +1,118 @@
+use anyhow::Result;
+use std::sync::Arc;
+use swarm_thing::auth::IpcAuth;
+use swarm_thing::bundle::{read_bundle, tool_version, ImportOutcome, MANIFEST};
+use swarm_thing::message::ToolSafetyLevel;
+use swarm_thing::review::ToolReview;
+use swarm_thing::safety::SafetyPolicy;
+use swarm_thing::tools::{ConfirmPolicy, ToolManager};
+
+fn bundle_path(name: &str) -> std::path::PathBuf {
+    std::env::temp_dir().join(format!("swarm_bundle_{}_{}.tar", name, std::process::id()))
+}
+
+#[test]
+fn test_bundle_round_trip_is_signed_and_hashed() -> Result<()> {
+    let mut tools = ToolManager::new()?;
+    tools.set_confirm_policy(ConfirmPolicy::default());
+    tools.create_tool("test_bundle_double", "// Doubles a number\n// version: 1.2\nfn test_bundle_double(x) { parse_int(x) * 2 }")?;
+    tools.create_tool("test_bundle_greet", "fn test_bundle_greet(name) { \"hi \" + name }")?;
+    let alpha = IpcAuth::new(Some("swarm secret"), "alpha");
+    let path = bundle_path("round_trip");
+
+    let names = vec!["test_bundle_greet".to_string(), "test_bundle_double".to_string()];
+    let manifest = tools.export_tools(&path, Some(&names), &alpha)?;
+    assert_eq!(manifest.tools.iter().map(|t| t.name.as_str()).collect::<Vec<_>>(), ["test_bundle_double", "test_bundle_greet"]);
+    assert_eq!((manifest.tools[0].version.as_deref(), manifest.tools[0].description.as_deref()), (Some("1.2"), Some("Doubles a number")));
+    assert!(manifest.signature.is_some());
+    assert_eq!(tool_version("fn f() { 1 }"), None);
+
+    // Imported elsewhere: the same secret verifies it and both tools install
+    tools.remove_tool("test_bundle_double")?;
+    tools.remove_tool("test_bundle_greet")?;
+    let report = tools.import_tools(&path, &IpcAuth::new(Some("swarm secret"), "beta"))?;
+    assert!(report.origin.starts_with("alpha [key "), "{}", report.origin);
+    assert!(report.tools.iter().all(|t| t.outcome == ImportOutcome::Installed), "{}", report.render());
+    assert_eq!(tools.execute_tool("test_bundle_double", vec!["21".to_string()])?, "42");
+    let again = tools.import_tools(&path, &alpha)?;
+    assert!(again.render().contains("test_bundle_double 1.2: unchanged"), "{}", again.render());
+
+    // Another swarm's key, or no signature where one is required, is rejected
+    let err = tools.import_tools(&path, &IpcAuth::new(Some("other secret"), "gamma")).unwrap_err();
+    assert!(err.to_string().contains("not this swarm's key"), "{}", err);
+    let unsigned = bundle_path("unsigned");
+    tools.export_tools(&unsigned, Some(&names), &IpcAuth::new(None, "alpha"))?;
+    assert!(tools.import_tools(&unsigned, &alpha).unwrap_err().to_string().contains("unsigned"));
+    assert!(tools.import_tools(&unsigned, &IpcAuth::new(None, "beta"))?.origin.starts_with("unsigned bundle from alpha"));
+
+    // A tool changed after export no longer matches its hash
+    let bundle = read_bundle(&path)?;
+    let tampered = bundle_path("tampered");
+    let mut archive = tar::Builder::new(std::fs::File::create(&tampered)?);
+    let manifest = serde_json::to_vec(&bundle.manifest)?;
+    let mut header = tar::Header::new_gnu();
+    header.set_size(manifest.len() as u64);
+    archive.append_data(&mut header, MANIFEST, &manifest[..])?;
+    for tool in &bundle.manifest.tools {
+        let code = bundle.sources[&tool.name].replace("* 2", "* 3");
+        let mut header = tar::Header::new_gnu();
+        header.set_size(code.len() as u64);
+        archive.append_data(&mut header, tool.file(), code.as_bytes())?;
+    }
+    archive.finish()?;
+    drop(archive);
+    let err = tools.import_tools(&tampered, &alpha).unwrap_err();
+    assert_eq!(err.to_string(), "Tool 'test_bundle_double' does not match its hash in the manifest");
+
+    tools.remove_tool("test_bundle_double")?;
+    tools.remove_tool("test_bundle_greet")?;
+    for path in [path, unsigned, tampered] {
+        std::fs::remove_file(path)?;
+    }
+    Ok(())
+}
+
+#[test]
+fn test_imports_pass_validation_and_approval() -> Result<()> {
+    let auth = IpcAuth::new(None, "alpha");
+    let mut tools = ToolManager::new()?;
+    tools.set_confirm_policy(ConfirmPolicy::default());
+    tools.create_tool("test_import_save", "fn test_import_save(x) { write_file(\"import_test.txt\", x) }")?;
+    tools.create_tool("test_import_plain", "fn test_import_plain(x) { x }")?;
+    // Saved, though it doesn't compile
+    assert!(tools.create_tool("test_import_broken", "fn test_import_broken(x) { x + }").is_err());
+    tools.create_tool("test_import_banned", "fn test_import_banned() { run_command(\"ls\") }")?;
+    let path = bundle_path("approval");
+    let names: Vec<String> = ["test_import_save", "test_import_plain", "test_import_broken", "test_import_banned"].iter().map(|s| s.to_string()).collect();
+    tools.export_tools(&path, Some(&names), &auth)?;
+    for name in &names {
+        tools.remove_tool(name)?;
+    }
+    let bundle = read_bundle(&path)?;
+    assert_eq!(bundle.manifest.tools.iter().find(|t| t.name == "test_import_save").unwrap().safety_level, ToolSafetyLevel::HighRisk);
+
+    // Without an install approver, a HighRisk tool waits in the pending queue
+    tools.set_safety_policy(SafetyPolicy { banned_functions: vec!["run_command".to_string()], ..SafetyPolicy::default() });
+    let report = tools.import_tools(&path, &auth)?;
+    let outcome = |name: &str| report.tools.iter().find(|t| t.name == name).unwrap().outcome.clone();
+    assert_eq!(outcome("test_import_save"), ImportOutcome::Queued);
+    assert_eq!(outcome("test_import_plain"), ImportOutcome::Installed);
+    assert!(matches!(outcome("test_import_broken"), ImportOutcome::Refused(reason) if reason.starts_with("does not compile")), "{}", report.render());
+    assert!(matches!(outcome("test_import_banned"), ImportOutcome::Refused(reason) if reason.contains("run_command")), "{}", report.render());
+    assert!(tools.pending_tools.lock().unwrap().iter().any(|t| t.name == "test_import_save" && t.source_agent == "alpha (unverified)"));
+    assert!(tools.tool_source("test_import_save").is_none());
+
+    // With one, the operator reviews it and a replacement of a different version
+    tools.set_install_approver(Arc::new(|review: &ToolReview| review.name == "test_import_save"));
+    tools.create_tool("test_import_plain", "fn test_import_plain(x) { x + \"!\" }")?;
+    let report = tools.import_tools(&path, &auth)?;
+    let outcome = |name: &str| report.tools.iter().find(|t| t.name == name).unwrap().outcome.clone();
+    assert_eq!((outcome("test_import_save"), outcome("test_import_plain")), (ImportOutcome::Installed, ImportOutcome::Declined));
+    assert!(tools.tool_source("test_import_plain").unwrap().contains("\"!\""));
+
+    tools.pending_tools.lock().unwrap().retain(|t| t.name != "test_import_save");
+    tools.remove_tool("test_import_save")?;
+    tools.remove_tool("test_import_plain")?;
+    std::fs::remove_file(path)?;
+    Ok(())
+}
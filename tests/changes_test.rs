@@ -0,0 +1,82 @@
+use anyhow::Result;
+use std::fs;
+use std::path::PathBuf;
+use swarm_thing::changes::{ChangeKind, ChangeLog, BACKUP_DIR};
+use swarm_thing::config::Config;
+use swarm_thing::tools::ToolManager;
+use swarm_thing::workspace::artifacts;
+
+fn temp_root(name: &str) -> Result<PathBuf> {
+    let dir = std::env::temp_dir().join(format!("swarm_changes_{}_{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir)?;
+    Ok(dir.canonicalize()?)
+}
+
+#[test]
+fn test_changes_are_undone_newest_first() -> Result<()> {
+    let root = temp_root("log")?;
+    fs::write(root.join("keep.txt"), "original")?;
+    let log = ChangeLog::new(&root);
+    assert_eq!(log.render(), "No file changes in this session");
+
+    let (keep, new) = (root.join("keep.txt"), root.join("new.txt"));
+    log.track(&keep, ChangeKind::Write, || fs::write(&keep, "clobbered"))?;
+    log.track(&new, ChangeKind::Write, || fs::write(&new, "fresh"))?;
+    log.track(&keep, ChangeKind::Delete, || fs::remove_file(&keep))?;
+    // Failed changes aren't logged
+    assert!(log.track(&root.join("missing.txt"), ChangeKind::Delete, || fs::remove_file(root.join("missing.txt"))).is_err());
+
+    let listing = log.render();
+    assert!(listing.starts_with("1. write keep.txt at "), "{}", listing);
+    assert!(listing.contains("\n2. write new.txt (new file) at "), "{}", listing);
+    assert!(listing.contains("\n3. delete keep.txt at ") && listing.ends_with("undo_changes(n) reverts the last n"), "{}", listing);
+    // Backups don't count as the session's files
+    assert_eq!(artifacts(&root)?.iter().map(|a| a.path.as_str()).collect::<Vec<_>>(), ["new.txt"]);
+
+    assert_eq!(log.undo(2)?, ["Restored keep.txt (undid delete)", "Removed new.txt (undid write)"]);
+    assert_eq!(fs::read_to_string(&keep)?, "clobbered");
+    assert!(!new.exists());
+    assert_eq!(log.undo(5)?, ["Restored keep.txt (undid write)"]);
+    assert_eq!(fs::read_to_string(&keep)?, "original");
+    assert!(log.undo(1)?.is_empty());
+
+    // The backups are gone with the log
+    assert!(log.is_reserved(&root.join(BACKUP_DIR).join("x")));
+    drop(log);
+    assert!(!root.join(BACKUP_DIR).exists());
+    fs::remove_dir_all(&root)?;
+    Ok(())
+}
+
+fn call(tools: &ToolManager, name: &str, args: &[&str]) -> Result<String> {
+    tools.execute_tool(name, args.iter().map(|a| a.to_string()).collect())
+}
+
+#[test]
+fn test_undo_natives() -> Result<()> {
+    let root = temp_root("natives")?;
+    let mut config = Config::default();
+    config.paths.workspace = root.clone();
+    let mut tools = ToolManager::with_config(&config)?;
+
+    assert_eq!(call(&tools, "write_file", &["notes.txt", "v1"])?, "File written successfully");
+    assert_eq!(call(&tools, "append_file", &["notes.txt", " v2"])?, "File appended successfully");
+    assert_eq!(call(&tools, "write_file", &["notes.txt", "oops"])?, "File written successfully");
+    assert!(call(&tools, "list_changes", &[])?.contains("3. write notes.txt at "));
+    assert!(call(&tools, "write_file", &[&format!("{}/sneaky", BACKUP_DIR), "x"])?.contains("kept for undo_changes"));
+    assert_eq!(tools.changes().list().len(), 3);
+
+    // As [TOOL: undo_changes(1)] passes it
+    assert_eq!(call(&tools, "undo_changes", &["1"])?, "Restored notes.txt (undid write)");
+    assert_eq!(fs::read_to_string(root.join("notes.txt"))?, "v1 v2");
+    assert!(call(&tools, "undo_changes", &["many"])?.starts_with("Error"));
+    tools.create_tool("test_undo_all", "fn test_undo_all() { undo_changes(10) }")?;
+    assert_eq!(call(&tools, "test_undo_all", &[])?, "Restored notes.txt (undid append)\nRemoved notes.txt (undid write)");
+    assert!(!root.join("notes.txt").exists());
+
+    fs::remove_file("tools/test_undo_all.rhai")?;
+    drop(tools);
+    fs::remove_dir_all(&root)?;
+    Ok(())
+}
@@ -0,0 +1,94 @@
+use anyhow::Result;
+use axum::routing::get;
+use axum::Router;
+use swarm_thing::citations::{format_time, Citations};
+use swarm_thing::report::{Report, ReportFormat, ReportStore};
+use swarm_thing::tools::ToolManager;
+
+const PAGE: &str = "Grid batteries shipped in record numbers last year. Prices fell 14% in 2023 as cell factories expanded.";
+
+#[test]
+fn test_sources_are_recorded_once_in_first_read_order() {
+    let citations = Citations::new();
+    assert_eq!(citations.render(), "No sources recorded in this session");
+    citations.record("https://example.com/a", "scrape_url", "First   version");
+    citations.record("search: batteries", "search", "Mock results");
+    citations.record("https://example.com/a", "scrape_url", "Second version");
+    citations.record("https://example.com/empty", "scrape_url", "  ");
+
+    let list = citations.list();
+    assert_eq!(list.iter().map(|c| c.source.as_str()).collect::<Vec<_>>(), ["https://example.com/a", "search: batteries"]);
+    assert_eq!(list[0].excerpt, "Second version");
+    assert!(citations.render().starts_with("1. https://example.com/a (scrape_url, "), "{}", citations.render());
+
+    // Long text is cut at a word for the excerpt
+    citations.record("https://example.com/long", "scrape_url", &"word ".repeat(100));
+    let excerpt = citations.get("https://example.com/long").unwrap().excerpt;
+    assert!(excerpt.ends_with("word…") && excerpt.chars().count() <= 241, "{}", excerpt);
+
+    assert_eq!(format_time(0), "1970-01-01 00:00 UTC");
+    assert_eq!(format_time(1_792_229_400), "2026-10-17 09:30 UTC");
+}
+
+#[test]
+fn test_findings_are_attributed_to_the_source_they_quote() {
+    let citations = Citations::new();
+    citations.record("https://example.com/prices", "scrape_url", PAGE);
+    assert_eq!(citations.attribute("prices fell 14%   in 2023.").as_deref(), Some("https://example.com/prices"));
+    assert_eq!(citations.attribute("As https://example.com/prices reports, costs dropped").as_deref(), Some("https://example.com/prices"));
+    // Too short to tell, or not in any source
+    assert_eq!(citations.attribute("in 2023"), None);
+    assert_eq!(citations.attribute("Sodium-ion cells are entering production"), None);
+
+    let mut report = Report::default();
+    report.add("Findings", "Prices fell 14% in 2023.", None);
+    report.add("Findings", "Demand may double.", Some("https://example.com/forecast"));
+    report.cite(&citations);
+    let markdown = report.to_markdown();
+    assert!(markdown.contains("Prices fell 14% in 2023. [1]"), "{}", markdown);
+    assert!(markdown.contains("1. https://example.com/prices, accessed "), "{}", markdown);
+    assert!(markdown.contains(" via scrape_url: \"Grid batteries shipped in record numbers last year."), "{}", markdown);
+    // Sources the session never read are listed as given
+    assert!(markdown.ends_with("2. https://example.com/forecast\n"), "{}", markdown);
+    assert!(report.to_html().contains("</a>, accessed "));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_scraped_pages_are_footnoted_in_exports() -> Result<()> {
+    let app = Router::new()
+        .route("/robots.txt", get(|| async { "" }))
+        .route("/prices", get(|| async { format!("<html><body><p>{}</p></body></html>", PAGE) }));
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+    let url = format!("http://{}/prices", listener.local_addr()?);
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+    let dir = std::env::temp_dir().join(format!("swarm_citations_{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    let store = ReportStore::open(dir.join("report.json"))?;
+    std::env::set_var("AGENT_REPORT", store.path());
+    std::env::set_var("HTTP_CACHE_DIR", dir.join("cache"));
+    let (url_clone, path) = (url.clone(), "citations_test_report.md");
+    let manager = tokio::task::spawn_blocking(move || -> Result<(ToolManager, String)> {
+        let manager = ToolManager::new()?;
+        assert_eq!(manager.execute_tool("list_sources", vec![])?, "No sources recorded in this session");
+        assert!(manager.execute_tool("scrape_url", vec![url_clone.clone()])?.contains("Prices fell 14%"));
+        manager.execute_tool("report_add", vec!["Findings, Prices fell 14% in 2023 as cell factories expanded.".to_string()])?;
+        let exported = manager.execute_tool("report_export", vec![format!("markdown, {}", path)])?;
+        Ok((manager, exported))
+    });
+    let (manager, exported) = manager.await??;
+    assert!(exported.contains("(1 sections, 1 sources)"), "{}", exported);
+    let markdown = std::fs::read_to_string(path)?;
+    assert!(markdown.contains("Prices fell 14% in 2023 as cell factories expanded. [1]"), "{}", markdown);
+    assert!(markdown.contains(&format!("1. {}, accessed ", url)), "{}", markdown);
+    let sources = tokio::task::spawn_blocking(move || manager.execute_tool("list_sources", vec![])).await??;
+    assert!(sources.starts_with(&format!("1. {} (scrape_url, ", url)), "{}", sources);
+
+    // The stored report keeps the finding as it was added
+    assert_eq!(store.load()?.sections[0].findings[0].source, None);
+    assert!(store.export(ReportFormat::Markdown, &dir.join("plain.md")).is_ok());
+    assert!(!std::fs::read_to_string(dir.join("plain.md"))?.contains("accessed"));
+    std::fs::remove_file(path)?;
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}
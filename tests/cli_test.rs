@@ -0,0 +1,47 @@
+use anyhow::Result;
+use clap::Parser;
+use std::path::PathBuf;
+use swarm_thing::cli::{Cli, Command, ConfigCommand, ToolCommand};
+use swarm_thing::tools::ToolManager;
+
+fn parse(args: &[&str]) -> Result<Cli, clap::Error> {
+    Cli::try_parse_from(std::iter::once("swarm-thing").chain(args.iter().copied()))
+}
+
+#[test]
+fn test_subcommands_parse() -> Result<()> {
+    assert_eq!(parse(&[])?.command, None);
+    let cli = parse(&["--persona", "coder", "serve", "--port", "9100"])?;
+    assert_eq!((cli.persona.as_deref(), cli.command), (Some("coder"), Some(Command::Serve { port: Some(9100) })));
+    assert_eq!(parse(&["tool", "run", "square", "4", "--auto-approve"])?.command,
+        Some(Command::Tool(ToolCommand::Run { name: "square".to_string(), args: vec!["4".to_string()] })));
+    assert_eq!(parse(&["config", "validate"])?.command, Some(Command::Config(ConfigCommand::Validate)));
+    assert!(!Command::Serve { port: None }.is_scripted() && Command::Tool(ToolCommand::List).is_interactive());
+
+    let clone = parse(&["clone", "../worker", "--tools", "square,web", "--config", "worker.env", "--as", "researcher"])?.command.unwrap();
+    let options = clone.clone_options().unwrap();
+    assert_eq!(options.tools, Some(vec!["square".to_string(), "web".to_string()]));
+    assert_eq!((options.env, options.config, options.persona.as_deref()), (false, Some(PathBuf::from("worker.env")), Some("researcher")));
+    // The .env stays behind unless asked for
+    assert!(!parse(&["clone", "../worker"])?.command.unwrap().clone_options().unwrap().env);
+    assert!(parse(&["clone", "../worker", "--env"])?.command.unwrap().clone_options().unwrap().env);
+
+    assert!(parse(&["tool", "frobnicate"]).is_err());
+    assert!(parse(&["serve", "--port", "http"]).is_err());
+    Ok(())
+}
+
+#[test]
+fn test_tool_management() -> Result<()> {
+    let tools = ToolManager::new()?;
+    let mut writer = ToolManager::new()?;
+    writer.create_tool("cli_test_double", "fn cli_test_double(x) { parse_int(x) * 2 }")?;
+    assert!(tools.list_tools().contains(&"cli_test_double".to_string()));
+    assert!(tools.tool_source("cli_test_double").unwrap().contains("* 2"));
+    assert_eq!(tools.execute_tool("cli_test_double", vec!["21".to_string()])?, "42");
+
+    assert_eq!(tools.remove_tool("cli_test_double")?, "Tool 'cli_test_double' removed successfully");
+    assert!(tools.tool_source("cli_test_double").is_none());
+    assert!(tools.remove_tool("cli_test_double").unwrap_err().to_string().contains("not found"));
+    Ok(())
+}
@@ -1,6 +1,7 @@
 use anyhow::Result;
-use rust_research_agent::tools::ToolManager;
+use swarm_thing::tools::ToolManager;
 use std::path::Path;
+use swarm_thing::lineage::{CloneOptions, Lineage};
 
 #[test]
 fn test_agent_cloning() -> Result<()> {
@@ -22,6 +23,9 @@ fn test_agent_cloning() -> Result<()> {
     
     // Verify the clone was successful
     assert!(result.contains("successfully") || result.contains("✅"));
+    // Credentials stay with the parent unless asked for
+    assert!(result.contains(", no .env"), "{}", result);
+    assert!(!Path::new(clone_dir).join(".env").exists());
     
     // Verify clone directory exists
     let clone_path = Path::new(clone_dir);
@@ -50,3 +54,36 @@ fn test_agent_cloning() -> Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn test_differential_clone() -> Result<()> {
+    let mut manager = ToolManager::new()?;
+    manager.create_tool("test_clone_worker", r#"
+    fn test_clone_worker(dir) {
+        return clone_agent(dir, #{ tools: ["tool_b"], persona: "researcher" });
+    }
+    "#)?;
+
+    let clone_dir = format!("/tmp/rust_agent_worker_test_{}", std::process::id());
+    let result = manager.execute_tool("test_clone_worker", vec![clone_dir.clone()])?;
+    assert!(result.contains("with 2 tool(s) [tool_a, tool_b], no .env"), "{}", result);
+
+    // Only the tool asked for and the one it calls; no credentials, but the persona
+    let mut tools: Vec<String> = std::fs::read_dir(Path::new(&clone_dir).join("tools"))?
+        .map(|e| e.map(|e| e.file_name().to_string_lossy().to_string()))
+        .collect::<std::io::Result<_>>()?;
+    tools.sort();
+    assert_eq!(tools, vec!["tool_a.rhai", "tool_b.rhai"]);
+    assert_eq!(std::fs::read_to_string(Path::new(&clone_dir).join(".env"))?, "AGENT_PERSONA=researcher\n");
+    assert_eq!(Lineage::load(Path::new(&clone_dir))?.persona.as_deref(), Some("researcher"));
+
+    let mut options = rhai::Map::new();
+    options.insert("tools".into(), "web, nonexistent_tool".into());
+    assert!(CloneOptions::from_map(&options).is_ok());
+    options.insert("colour".into(), "blue".into());
+    assert!(CloneOptions::from_map(&options).unwrap_err().to_string().contains("Unknown clone option 'colour'"));
+
+    std::fs::remove_dir_all(&clone_dir)?;
+    std::fs::remove_file("tools/test_clone_worker.rhai")?;
+    Ok(())
+}
@@ -0,0 +1,98 @@
+use anyhow::Result;
+use std::sync::Arc;
+use std::time::Duration;
+use swarm_thing::audit::AuditLog;
+use swarm_thing::command::CommandRunner;
+use swarm_thing::tools::ToolManager;
+
+#[test]
+fn test_command_allowlist_and_approval() -> Result<()> {
+    let audit_path = "/tmp/swarm_thing_command_test/audit.jsonl";
+    let _ = std::fs::remove_file(audit_path);
+    let audit = AuditLog::open(audit_path)?;
+    let runner = CommandRunner::new(
+        vec!["echo".to_string(), "sh".to_string()],
+        Duration::from_millis(500),
+        "/tmp",
+        audit.clone(),
+    );
+    
+    // Denied by default until an approver is installed
+    let outcome = runner.run("echo", &["hello".to_string()]);
+    assert_eq!(outcome.status, "denied");
+    
+    runner.set_approver(Arc::new(|_| true));
+    let outcome = runner.run("echo", &["hello".to_string()]);
+    assert_eq!(outcome.status, "ok");
+    assert_eq!(outcome.exit_code, 0);
+    assert_eq!(outcome.stdout.trim(), "hello");
+    
+    // stderr is captured separately
+    let outcome = runner.run("sh", &["-c".to_string(), "echo oops >&2; exit 3".to_string()]);
+    assert_eq!(outcome.exit_code, 3);
+    assert!(outcome.stdout.is_empty());
+    assert_eq!(outcome.stderr.trim(), "oops");
+    
+    // Commands outside the allowlist never run
+    let outcome = runner.run("rm", &["-rf".to_string(), "/tmp/nothing".to_string()]);
+    assert_eq!(outcome.status, "denied");
+    
+    // Long-running commands are killed
+    let outcome = runner.run("sh", &["-c".to_string(), "sleep 5".to_string()]);
+    assert_eq!(outcome.status, "timeout");
+
+    // So is what a command leaves running with the pipes open
+    let started = std::time::Instant::now();
+    let outcome = runner.run("sh", &["-c".to_string(), "echo started; sleep 999 &".to_string()]);
+    assert_eq!((outcome.status.as_str(), outcome.stdout.trim()), ("ok", "started"));
+    assert!(started.elapsed() < Duration::from_secs(3), "took {:?}", started.elapsed());
+    
+    // Every invocation is audited
+    let entries = audit.entries()?;
+    assert_eq!(entries.len(), 6);
+    assert!(entries.iter().all(|e| e.action == "run_command"));
+    assert!(entries[3].detail.starts_with("rm"));
+    
+    std::fs::remove_dir_all("/tmp/swarm_thing_command_test")?;
+    Ok(())
+}
+
+#[test]
+fn test_command_output_is_capped() -> Result<()> {
+    let audit = AuditLog::open("/tmp/swarm_thing_command_cap_test/audit.jsonl")?;
+    let runner = CommandRunner::new(vec!["sh".to_string()], Duration::from_secs(5), "/tmp", audit)
+        .with_max_output_bytes(16);
+    runner.set_approver(Arc::new(|_| true));
+
+    let outcome = runner.run("sh", &["-c".to_string(), "echo short".to_string()]);
+    assert_eq!((outcome.stdout.trim(), outcome.truncated), ("short", false));
+
+    // A flood is cut at the limit, and the command still finishes
+    let outcome = runner.run("sh", &["-c".to_string(), "yes | head -c 1000000; yes | head -c 1000000 >&2".to_string()]);
+    assert_eq!(outcome.status, "ok");
+    assert_eq!((outcome.stdout.len(), outcome.stderr.len()), (16, 16));
+    assert!(outcome.truncated);
+    assert!(outcome.to_map()["truncated"].as_bool().unwrap());
+
+    std::fs::remove_dir_all("/tmp/swarm_thing_command_cap_test")?;
+    Ok(())
+}
+
+#[test]
+fn test_run_command_tool_denied_by_default() -> Result<()> {
+    let mut manager = ToolManager::new()?;
+    manager.set_command_approver(Arc::new(|_| true));
+    
+    // Nothing is allowlisted unless COMMAND_ALLOWLIST is set
+    manager.create_tool("test_run_command", r#"
+    fn test_run_command(cmd) {
+        let out = run_command(cmd, "");
+        return out.status;
+    }
+    "#)?;
+    let result = manager.execute_tool("test_run_command", vec!["whoami".to_string()])?;
+    assert_eq!(result, "denied");
+    
+    std::fs::remove_file("tools/test_run_command.rhai")?;
+    Ok(())
+}
@@ -0,0 +1,52 @@
+use anyhow::Result;
+use axum::{routing::post, Json, Router};
+use swarm_thing::compare::Comparer;
+use swarm_thing::llm::LlmClient;
+
+// Minimal Ollama-compatible endpoint that answers with the requested model name
+async fn mock_chat(Json(body): Json<serde_json::Value>) -> Json<serde_json::Value> {
+    let model = body["model"].as_str().unwrap_or_default().to_string();
+    let last = body["messages"].as_array()
+        .and_then(|m| m.last())
+        .and_then(|m| m["content"].as_str())
+        .unwrap_or_default()
+        .to_string();
+    let content = if last.contains("Reconcile") {
+        "Final: 4\nDisagreements: none".to_string()
+    } else {
+        format!("{} says 4", model)
+    };
+    Json(serde_json::json!({ "message": { "role": "assistant", "content": content } }))
+}
+
+#[tokio::test]
+async fn test_compare_models() -> Result<()> {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+    let url = format!("http://{}/api/chat", listener.local_addr()?);
+    tokio::spawn(async move {
+        let app = Router::new().route("/api/chat", post(mock_chat));
+        axum::serve(listener, app).await.unwrap();
+    });
+    
+    let model_a = LlmClient::with_model("ollama", Some("alpha".to_string())).await?.with_ollama_url(&url);
+    let model_b = LlmClient::with_model("ollama", Some("beta".to_string())).await?.with_ollama_url(&url);
+    let judge = LlmClient::with_model("ollama", Some("judge".to_string())).await?.with_ollama_url(&url);
+    
+    let comparer = Comparer::new(vec![model_a, model_b], Some(judge));
+    assert_eq!(comparer.models(), vec!["ollama:alpha", "ollama:beta"]);
+    
+    let comparison = comparer.compare("What is 2 + 2?", None).await?;
+    
+    // Answers keep the configured order
+    assert_eq!(comparison.answers[0].model, "ollama:alpha");
+    assert_eq!(comparison.answers[0].answer.as_ref().unwrap(), "alpha says 4");
+    assert_eq!(comparison.answers[1].answer.as_ref().unwrap(), "beta says 4");
+    assert!(comparison.verdict.as_ref().unwrap().contains("Disagreements"));
+    
+    let rendered = comparison.render();
+    println!("{}", rendered);
+    assert!(rendered.contains("=== ollama:beta ==="));
+    assert!(rendered.contains("Judge verdict"));
+
+    Ok(())
+}
@@ -0,0 +1,86 @@
+use anyhow::Result;
+use swarm_thing::config::Config;
+use swarm_thing::tools::ToolManager;
+
+const CONFIG: &str = r#"
+[agent]
+name = "file-agent"
+
+[llm]
+provider = "ollama"
+model = "llama3.1"
+
+[ipc]
+port = 9101
+discovery_port = 9902
+
+[policies]
+confirm_risk = "high_risk"
+prompt = ["Be brief", "Cite tools"]
+
+[peers.groups]
+workers = ["127.0.0.1:9001", "127.0.0.1:9002"]
+"#;
+
+#[test]
+fn test_config_file_and_validation() -> Result<()> {
+    let dir = std::env::temp_dir().join(format!("swarm_config_{}", std::process::id()));
+    std::fs::create_dir_all(&dir)?;
+    let path = dir.join("swarm.toml");
+    std::fs::write(&path, CONFIG)?;
+
+    let config = Config::load(&path)?;
+    assert_eq!(config.agent.name, "file-agent");
+    assert_eq!((config.llm.provider.as_str(), config.llm.model.as_deref()), ("ollama", Some("llama3.1")));
+    assert_eq!((config.ipc.port, config.ipc.bind.as_str()), (Some(9101), "127.0.0.1"));
+    assert_eq!(config.paths.tools_dir, std::path::PathBuf::from("tools"));
+    assert_eq!(config.peers.groups["workers"].len(), 2);
+    assert!(config.validate().is_empty(), "{:?}", config.validate());
+    let rendered = config.render()?;
+    assert!(rendered.contains("[llm]") && rendered.contains(&path.display().to_string()), "{}", rendered);
+
+    let mut broken = config.clone();
+    broken.llm.provider = "nowhere".to_string();
+    broken.ipc.grpc_port = Some(9101);
+    broken.policies.confirm_risk = "sometimes".to_string();
+    let problems = broken.validate();
+    assert_eq!(problems.len(), 3, "{:?}", problems);
+    assert!(problems[1].contains("port 9101 is also ipc.port"), "{:?}", problems);
+
+    std::fs::write(&path, "[agent]\nnmae = \"typo\"\n")?;
+    assert!(Config::load(&path).unwrap_err().to_string().contains("unknown field"));
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+
+#[test]
+fn test_env_overrides_config() -> Result<()> {
+    let dir = std::env::temp_dir().join(format!("swarm_config_env_{}", std::process::id()));
+    std::fs::create_dir_all(&dir)?;
+    let path = dir.join("swarm.toml");
+    std::fs::write(&path, format!("{}\n[paths]\ntools_dir = {:?}\n", CONFIG, dir.join("tools")))?;
+
+    std::env::set_var("SWARM_CONFIG", &path);
+    std::env::set_var("AGENT_NAME", "env-agent");
+    std::env::remove_var("DISCOVERY_PORT");
+    let config = Config::from_env()?;
+    assert_eq!(config.agent.name, "env-agent");
+    assert_eq!(config.ipc.discovery_port, 9902);
+    assert!(config.overridden.contains(&"AGENT_NAME"));
+    assert!(config.render()?.contains("Overridden by the environment: AGENT_NAME"));
+
+    // File values reach modules that read the environment; the environment keeps its own
+    config.export_env();
+    assert_eq!(std::env::var("DISCOVERY_PORT")?, "9902");
+    assert_eq!(std::env::var("AGENT_POLICIES")?, "Be brief;Cite tools");
+    assert_eq!(std::env::var("AGENT_NAME")?, "env-agent");
+    assert!(std::env::var("IPC_BIND").is_err());
+
+    let tools = ToolManager::with_config(&config)?;
+    assert!(dir.join("tools").is_dir());
+    assert!(tools.list_tools().is_empty());
+    assert!(tools.confirm_policy().min_risk.is_some());
+
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}
@@ -0,0 +1,46 @@
+use anyhow::Result;
+use std::sync::{Arc, Mutex};
+use swarm_thing::message::ToolSafetyLevel;
+use swarm_thing::tools::{ConfirmPolicy, ToolManager};
+
+#[test]
+fn test_confirm_policy_levels() {
+    let default = ConfirmPolicy::default();
+    assert!(!default.requires(&ToolSafetyLevel::LowRisk));
+    assert!(default.requires(&ToolSafetyLevel::MediumRisk));
+    assert!(default.requires(&ToolSafetyLevel::HighRisk));
+    let high_only = ConfirmPolicy { min_risk: Some(ToolSafetyLevel::HighRisk) };
+    assert!(!high_only.requires(&ToolSafetyLevel::MediumRisk));
+    assert!(!ConfirmPolicy::auto_approve().requires(&ToolSafetyLevel::HighRisk));
+}
+
+#[test]
+fn test_risky_runs_are_confirmed_with_exact_arguments() -> Result<()> {
+    let mut manager = ToolManager::new()?;
+    manager.set_confirm_policy(ConfirmPolicy::default());
+    let asked = Arc::new(Mutex::new(Vec::new()));
+    let answer = Arc::new(Mutex::new(false));
+    let (asked_clone, answer_clone) = (asked.clone(), answer.clone());
+    manager.set_execution_approver(Arc::new(move |call: &str, level: &ToolSafetyLevel| {
+        asked_clone.lock().unwrap().push(format!("{:?} {}", level, call));
+        *answer_clone.lock().unwrap()
+    }));
+
+    let declined = manager.execute_tool("delete_file", vec!["confirm_test_missing.txt".to_string()]).unwrap_err();
+    assert_eq!(declined.to_string(), "The operator declined to run delete_file(confirm_test_missing.txt)");
+    *answer.lock().unwrap() = true;
+    let ran = manager.execute_tool("delete_file", vec!["confirm_test_missing.txt".to_string()])?;
+    assert!(ran.starts_with("Error deleting file"), "{}", ran);
+    // Safe and LowRisk tools run unasked
+    manager.execute_tool("list_tools", vec![])?;
+    assert_eq!(*asked.lock().unwrap(), vec![
+        "HighRisk delete_file(confirm_test_missing.txt)".to_string(),
+        "HighRisk delete_file(confirm_test_missing.txt)".to_string(),
+    ]);
+
+    asked.lock().unwrap().clear();
+    manager.set_confirm_policy(ConfirmPolicy::auto_approve());
+    manager.execute_tool("delete_file", vec!["confirm_test_missing.txt".to_string()])?;
+    assert!(asked.lock().unwrap().is_empty());
+    Ok(())
+}
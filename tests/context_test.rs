@@ -0,0 +1,121 @@
+use anyhow::Result;
+use axum::{routing::post, Json, Router};
+use swarm_thing::agent::Agent;
+use swarm_thing::context::{estimate_tokens, ContextBudget, SummaryPolicy, TrimStrategy};
+use swarm_thing::llm::{LlmClient, Message, OpenAiConfig, Role};
+use swarm_thing::provider::{OllamaChat, OpenAiChat};
+
+// Ollama-compatible endpoint: summary requests get a fixed summary, everything else
+// is answered with a short reply describing the request it received
+async fn mock_chat(Json(body): Json<serde_json::Value>) -> Json<serde_json::Value> {
+    let messages = body["messages"].as_array().cloned().unwrap_or_default();
+    let first = messages.first().map(|m| m["content"].as_str().unwrap_or("").to_string()).unwrap_or_default();
+    let content = if first.starts_with("Summarize this conversation") {
+        "SUMMARY: user likes rust".to_string()
+    } else {
+        let has_summary = first.contains("SUMMARY: user likes rust");
+        format!("ok {} {}", messages.len(), has_summary)
+    };
+    Json(serde_json::json!({ "message": { "role": "assistant", "content": content } }))
+}
+
+async fn mock_client() -> Result<LlmClient> {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+    let url = format!("http://{}/api/chat", listener.local_addr()?);
+    tokio::spawn(async move {
+        let app = Router::new().route("/api/chat", post(mock_chat));
+        axum::serve(listener, app).await.unwrap();
+    });
+    Ok(LlmClient::with_model("ollama", None).await?.with_ollama_url(&url))
+}
+
+fn msg(role: Role, content: &str) -> Message {
+    Message { role, content: content.to_string(), images: Vec::new() }
+}
+
+#[test]
+fn test_estimate_tokens() {
+    let openai = OpenAiChat::new("gpt-4o-mini", OpenAiConfig::from_env());
+    assert_eq!(estimate_tokens(&openai, ""), 0);
+    assert_eq!(estimate_tokens(&openai, "abcdefgh"), 2);
+    // Llama's tokenizer packs fewer characters per token
+    let text = "x".repeat(700);
+    assert!(estimate_tokens(&OllamaChat::from_env(None), &text) > estimate_tokens(&openai, &text));
+}
+
+#[test]
+fn test_trim_point_keeps_newest_and_starts_with_user() {
+    let openai = OpenAiChat::new("gpt-4o-mini", OpenAiConfig::from_env());
+    let budget = ContextBudget { max_tokens: 40, reserve_tokens: 0, strategy: TrimStrategy::DropOldest };
+    let history = vec![
+        msg(Role::User, &"a".repeat(40)),
+        msg(Role::Assistant, &"b".repeat(40)),
+        msg(Role::User, &"c".repeat(40)),
+        msg(Role::Assistant, &"d".repeat(20)),
+        msg(Role::User, &"e".repeat(20)),
+    ];
+    // Each 40-char message costs 14 tokens, 20-char ones 9: the last three fit (32),
+    // adding the assistant turn before them (46) would not
+    assert_eq!(budget.trim_point(&openai, &history, ""), 2);
+
+    // If the cut lands on an assistant turn, it moves forward to the next user turn
+    let tight = ContextBudget { max_tokens: 20, ..budget.clone() };
+    assert_eq!(tight.trim_point(&openai, &history, ""), 4);
+
+    // The latest message is always kept even if it alone exceeds the budget
+    let tiny = ContextBudget { max_tokens: 1, ..budget };
+    assert_eq!(tiny.trim_point(&openai, &history, ""), 4);
+}
+
+#[tokio::test]
+async fn test_agent_drops_oldest_turns() -> Result<()> {
+    let mut agent = Agent::with_client(mock_client().await?, "sys");
+    agent.set_context_budget(ContextBudget { max_tokens: 60, reserve_tokens: 0, strategy: TrimStrategy::DropOldest });
+
+    for i in 0..5 {
+        agent.chat(&format!("{} {}", i, "question ".repeat(8))).await?;
+    }
+    assert!(agent.context_tokens() <= 60 + 20);
+    assert!(agent.history().len() < 10);
+    assert!(agent.history()[0].content.starts_with(|c: char| c.is_ascii_digit()));
+    assert!(agent.summary().is_none());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_agent_summarizes_trimmed_turns() -> Result<()> {
+    let mut agent = Agent::with_client(mock_client().await?, "sys");
+    agent.set_context_budget(ContextBudget { max_tokens: 80, reserve_tokens: 0, strategy: TrimStrategy::Summarize });
+
+    let mut last = String::new();
+    for i in 0..5 {
+        last = agent.chat(&format!("{} {}", i, "question ".repeat(8))).await?;
+    }
+    assert_eq!(agent.summary(), Some("SUMMARY: user likes rust"));
+    // The summary is carried in the system prompt of later requests
+    assert!(last.ends_with("true"), "reply was {}", last);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_rolling_summary_past_threshold() -> Result<()> {
+    let mut agent = Agent::with_client(mock_client().await?, "sys");
+    agent.set_context_budget(ContextBudget::default_for(&OllamaChat::from_env(None)));
+    agent.set_summary_policy(Some(SummaryPolicy { threshold_tokens: 100, keep_recent_tokens: 30 }));
+
+    // Each turn is ~23 tokens of question plus a short reply: under the threshold nothing happens
+    agent.chat(&format!("0 {}", "question ".repeat(8))).await?;
+    agent.chat(&format!("1 {}", "question ".repeat(8))).await?;
+    assert!(agent.summary().is_none());
+    assert_eq!(agent.history().len(), 4);
+
+    for i in 2..5 {
+        agent.chat(&format!("{} {}", i, "question ".repeat(8))).await?;
+    }
+    assert_eq!(agent.summary(), Some("SUMMARY: user likes rust"));
+    // Only the recent tail is kept verbatim and it still starts with a user turn
+    assert!(agent.history().len() <= 4);
+    assert!(matches!(agent.history()[0].role, Role::User));
+    assert!(agent.history().last().unwrap().content.ends_with("true"));
+    Ok(())
+}
@@ -0,0 +1,99 @@
+use anyhow::Result;
+use swarm_thing::agent::Agent;
+use swarm_thing::dashboard::{self, DashboardState, Decision, PendingView, ToolView, Transcript};
+use swarm_thing::llm::{Completion, LlmClient, Message, Usage};
+use swarm_thing::message::ToolSafetyLevel;
+use swarm_thing::registry::PeerInfo;
+use swarm_thing::replay::RecordedResponses;
+use swarm_thing::tools::ToolManager;
+
+fn said(text: &str) -> Result<Completion, String> {
+    Ok(Completion { text: text.to_string(), usage: Usage { input_tokens: 10, output_tokens: 2 }, model: "mock".to_string(), cached: false })
+}
+
+/// A client sending the run's token, as the page does
+fn client(token: &str) -> Result<reqwest::Client> {
+    let mut headers = reqwest::header::HeaderMap::new();
+    headers.insert(dashboard::TOKEN_HEADER, token.parse()?);
+    Ok(reqwest::Client::builder().default_headers(headers).build()?)
+}
+
+#[tokio::test]
+async fn test_dashboard_shows_agent_state() -> Result<()> {
+    let mut tools = ToolManager::new()?;
+    tools.peers().upsert(PeerInfo {
+        name: "dash-peer".to_string(),
+        address: "127.0.0.1:9555".to_string(),
+        capabilities: Vec::new(),
+        tools: Vec::new(),
+        last_seen: 0,
+        missed_heartbeats: 0,
+        public_key: None,
+    });
+    let transcript = Transcript::new();
+    let llm = LlmClient::replaying(RecordedResponses::new(vec![said("Hi there.")])).await?;
+    let mut agent = Agent::with_client(llm, "You use tools.");
+    agent.set_transcript(Some(transcript.clone()));
+    agent.chat("hello").await?;
+    tools.queue_tool("test_dash_pending".to_string(), "fn test_dash_pending() { 1 }".to_string(), "alpha".to_string(), None)?;
+
+    let page_url = dashboard::start(DashboardState::new(&tools, transcript), 0).await?;
+    let (url, token) = page_url.split_once("/?token=").unwrap();
+    let client = client(token)?;
+    let page = client.get(&page_url).send().await?.text().await?;
+    assert!(page.contains("/api/pending") && page.contains(&format!("const token = \"{}\"", token)));
+
+    let history: Vec<Message> = client.get(format!("{}/api/history", url)).send().await?.json().await?;
+    assert_eq!(history.iter().map(|m| m.content.as_str()).collect::<Vec<_>>(), vec!["hello", "Hi there."]);
+    let listed: Vec<ToolView> = client.get(format!("{}/api/tools", url)).send().await?.json().await?;
+    assert!(listed.contains(&ToolView { name: "square".to_string(), safety_level: ToolSafetyLevel::Safe }), "{:?}", listed);
+    // Tools are rated by the policy in force, not the one the dashboard started with
+    let mut safety = tools.safety_policy();
+    safety.risk_keywords.high.push("fn square".to_string());
+    tools.set_safety_policy(safety);
+    let listed: Vec<ToolView> = client.get(format!("{}/api/tools", url)).send().await?.json().await?;
+    assert!(listed.contains(&ToolView { name: "square".to_string(), safety_level: ToolSafetyLevel::HighRisk }), "{:?}", listed);
+    let pending: Vec<PendingView> = client.get(format!("{}/api/pending", url)).send().await?.json().await?;
+    assert_eq!(pending.iter().map(|p| (p.name.as_str(), p.origin.as_str())).collect::<Vec<_>>(), vec![("test_dash_pending", "alpha")]);
+    let peers: Vec<PeerInfo> = client.get(format!("{}/api/peers", url)).send().await?.json().await?;
+    assert!(peers.iter().any(|p| p.name == "dash-peer"));
+    let audit: serde_json::Value = client.get(format!("{}/api/audit?limit=5", url)).send().await?.json().await?;
+    assert!(audit.as_array().map(|a| a.len() <= 5).unwrap_or(false));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_dashboard_approves_and_rejects() -> Result<()> {
+    let mut tools = ToolManager::new()?;
+    tools.queue_tool("test_dash_approve".to_string(), "fn test_dash_approve() { 42 }".to_string(), "alpha".to_string(), None)?;
+    tools.queue_tool("test_dash_reject".to_string(), "fn test_dash_reject() { 0 }".to_string(), "alpha".to_string(), None)?;
+    let page_url = dashboard::start(DashboardState::new(&tools, Transcript::new()), 0).await?;
+    let (url, token) = page_url.split_once("/?token=").unwrap();
+    let client = client(token)?;
+    let decide = |name: &str, action: &str| client.post(format!("{}/api/pending/{}/{}", url, name, action)).send();
+
+    // Without the token, from another origin, or under another host name, nothing happens
+    let plain = reqwest::Client::new();
+    let approve = format!("{}/api/pending/test_dash_approve/approve", url);
+    assert_eq!(plain.post(&approve).send().await?.status(), reqwest::StatusCode::UNAUTHORIZED);
+    assert_eq!(plain.get(url).send().await?.status(), reqwest::StatusCode::UNAUTHORIZED);
+    assert_eq!(client.post(&approve).header("Origin", "http://evil.example").send().await?.status(), reqwest::StatusCode::FORBIDDEN);
+    assert_eq!(client.post(&approve).header("Host", "rebound.example").send().await?.status(), reqwest::StatusCode::FORBIDDEN);
+    assert!(tools.list_pending_tools().contains("test_dash_approve"));
+
+    let approved: Decision = decide("test_dash_approve", "approve").await?.json().await?;
+    assert!(approved.ok, "{}", approved.message);
+    assert_eq!(tools.execute_tool("test_dash_approve", vec![])?, "42");
+    let rejected: Decision = decide("test_dash_reject", "reject").await?.json().await?;
+    assert!(rejected.ok, "{}", rejected.message);
+    assert!(tools.list_pending_tools().contains("No tools pending"));
+
+    // Unknown tools are refused with a conflict
+    let missing = decide("test_dash_reject", "approve").await?;
+    assert_eq!(missing.status(), reqwest::StatusCode::CONFLICT);
+    let missing: Decision = missing.json().await?;
+    assert!(missing.message.contains("not found in pending queue"), "{}", missing.message);
+
+    std::fs::remove_file("tools/test_dash_approve.rhai")?;
+    Ok(())
+}
@@ -0,0 +1,110 @@
+use anyhow::Result;
+use rusqlite::types::Value;
+use swarm_thing::data::{table_name, DataTables};
+use swarm_thing::tools::ToolManager;
+
+const SALES: &str = "region,product,amount,units
+north,widget,12.5,3
+south,widget,7,1
+north,gadget,,2
+east,widget,20.25,5
+";
+
+#[test]
+fn test_csv_columns_are_typed() -> Result<()> {
+    let data = DataTables::new()?;
+    let info = data.load_csv("sales", SALES)?;
+    assert_eq!(info.rows, 4);
+    assert_eq!(info.render(), "Loaded 4 rows into table 'sales' (region TEXT, product TEXT, amount REAL, units INTEGER)");
+
+    let totals = data.query("SELECT region, SUM(units) AS units FROM sales GROUP BY region ORDER BY units DESC, region")?;
+    assert_eq!(totals.columns, ["region", "units"]);
+    assert_eq!(totals.rows[0], [Value::Text("east".to_string()), Value::Integer(5)]);
+    assert_eq!(totals.render(2), "region | units\neast | 5\nnorth | 5\n(2 of 3 rows shown; aggregate or add LIMIT to see the rest)");
+
+    // Semicolons, a blank header and a repeated one
+    let info = data.load_csv("odd", "a;;A\n1;x;2\n")?;
+    assert_eq!(info.columns.iter().map(|(c, _)| c.as_str()).collect::<Vec<_>>(), ["a", "column_2", "A_2"]);
+
+    assert_eq!(table_name("downloads/Sales 2024.csv"), "sales_2024");
+    assert_eq!(table_name("2024.csv"), "t_2024");
+    Ok(())
+}
+
+#[test]
+fn test_only_read_only_queries_run() -> Result<()> {
+    let data = DataTables::new()?;
+    data.load_csv("sales", SALES)?;
+    let error = data.query("DELETE FROM sales").unwrap_err();
+    assert!(error.to_string().contains("Only read-only queries"), "{}", error);
+    assert!(data.query("DROP TABLE sales").is_err());
+    assert_eq!(data.query("SELECT COUNT(*) AS n FROM sales")?.rows[0], [Value::Integer(4)]);
+    Ok(())
+}
+
+#[test]
+fn test_json_records_become_rows() -> Result<()> {
+    let data = DataTables::new()?;
+    let info = data.load_json("people", r#"{"data": [{"name": "Ada", "age": 36, "tags": ["math"]}, {"name": "Alan", "city": "London"}]}"#)?;
+    assert_eq!(info.columns, [
+        ("age".to_string(), "INTEGER".to_string()),
+        ("name".to_string(), "TEXT".to_string()),
+        ("tags".to_string(), "TEXT".to_string()),
+        ("city".to_string(), "TEXT".to_string()),
+    ]);
+    assert_eq!(data.query("SELECT tags FROM people WHERE name = 'Ada'")?.rows[0], [Value::Text("[\"math\"]".to_string())]);
+
+    let info = data.load_json("events", "{\"kind\": \"click\", \"ms\": 1.5}\n{\"kind\": \"view\", \"ms\": 3}\n")?;
+    assert_eq!(info.rows, 2);
+    assert!(data.load_json("bad", "[1, 2]").is_err());
+    assert_eq!(data.tables()?, ["events", "people"]);
+    Ok(())
+}
+
+#[test]
+fn test_describe_summarises_each_column() -> Result<()> {
+    let data = DataTables::new()?;
+    data.load_csv("sales", SALES)?;
+    let summary = data.describe("sales")?;
+    assert!(summary.starts_with("Table 'sales': 4 rows, 4 columns\n"), "{}", summary);
+    assert!(summary.contains("- region TEXT: 4 values, 0 missing, 3 distinct; top: north (2), east (1), south (1)"), "{}", summary);
+    assert!(summary.contains("- amount REAL: 3 values, 1 missing, 3 distinct; min 7, max 20.25, mean 13.25"), "{}", summary);
+    assert!(summary.contains("First rows:\nregion | product | amount | units\nnorth | widget | 12.5 | 3"), "{}", summary);
+    assert_eq!(data.describe("missing").unwrap_err().to_string(), "No table 'missing'; loaded: sales");
+    Ok(())
+}
+
+#[test]
+fn test_data_natives() -> Result<()> {
+    let mut manager = ToolManager::new()?;
+    std::fs::write("data_test_sales.csv", SALES)?;
+    manager.create_tool("test_data", r#"
+    fn test_data(path) {
+        let loaded = load_csv(path, "sales");
+        return loaded + "\n" + query_data("SELECT product, COUNT(*) AS n FROM sales GROUP BY product ORDER BY n DESC") + "\n" + describe_data("sales");
+    }
+    "#)?;
+
+    let result = manager.execute_tool("test_data", vec!["data_test_sales.csv".to_string()])?;
+    assert!(result.starts_with("Loaded 4 rows into table 'sales'"), "got {}", result);
+    assert!(result.contains("product | n\nwidget | 3\ngadget | 1\n(2 rows)"), "got {}", result);
+    assert!(result.contains("Table 'sales': 4 rows, 4 columns"), "got {}", result);
+    let missing = manager.execute_tool("load_csv", vec!["data_test_missing.csv".to_string()])?;
+    assert!(missing.starts_with("Error"), "got {}", missing);
+
+    std::fs::remove_file("data_test_sales.csv")?;
+    std::fs::remove_file("tools/test_data.rhai")?;
+    Ok(())
+}
+
+#[test]
+fn test_query_data_cannot_attach_databases() -> Result<()> {
+    let manager = ToolManager::new()?;
+    let outside = std::env::temp_dir().join(format!("swarm_data_attach_{}.db", std::process::id()));
+    let attach = format!("ATTACH DATABASE '{}' AS host", outside.display());
+    let result = manager.execute_tool("query_data", vec![attach.clone()])?;
+    assert!(result.starts_with("Error running query: too many attached databases"), "got {}", result);
+    assert!(DataTables::new()?.query(&attach).is_err());
+    assert!(!outside.exists());
+    Ok(())
+}
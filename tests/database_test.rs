@@ -0,0 +1,75 @@
+use anyhow::Result;
+use rhai::Dynamic;
+use swarm_thing::database::AgentDb;
+use swarm_thing::tools::ToolManager;
+
+#[test]
+fn test_agent_db() -> Result<()> {
+    let path = "/tmp/swarm_thing_db_test/agent.db";
+    let _ = std::fs::remove_file(path);
+    let db = AgentDb::open(path)?;
+    
+    db.execute("CREATE TABLE facts (topic TEXT, value REAL); CREATE INDEX idx_topic ON facts(topic);", &[])?;
+    let inserted = db.execute(
+        "INSERT INTO facts (topic, value) VALUES (?1, ?2)",
+        &[Dynamic::from("ratio".to_string()), Dynamic::from(2.5_f64)],
+    )?;
+    assert_eq!(inserted, 1);
+    
+    // Data persists across connections
+    let db = AgentDb::open(path)?;
+    let rows = db.query("SELECT topic, value FROM facts WHERE topic = ?1", &[Dynamic::from("ratio".to_string())])?;
+    assert_eq!(rows.len(), 1);
+    let row = rows[0].clone().cast::<rhai::Map>();
+    assert_eq!(row["topic"].to_string(), "ratio");
+    assert_eq!(row["value"].as_float().unwrap(), 2.5);
+    
+    assert!(db.query("SELECT * FROM missing_table", &[]).is_err());
+    
+    std::fs::remove_dir_all("/tmp/swarm_thing_db_test")?;
+    Ok(())
+}
+
+#[test]
+fn test_db_tools() -> Result<()> {
+    let mut manager = ToolManager::new()?;
+    
+    manager.create_tool("test_db_roundtrip", r#"
+    fn test_db_roundtrip(name) {
+        db_execute("CREATE TABLE IF NOT EXISTS db_tool_test (name TEXT, hits INTEGER)");
+        db_execute("DELETE FROM db_tool_test WHERE name = ?1", [name]);
+        db_execute("INSERT INTO db_tool_test (name, hits) VALUES (?1, ?2)", [name, 42]);
+        let rows = db_query("SELECT hits FROM db_tool_test WHERE name = ?1", [name]);
+        return rows[0].hits;
+    }
+    "#)?;
+    let result = manager.execute_tool("test_db_roundtrip", vec!["alice".to_string()])?;
+    assert_eq!(result, "42");
+    
+    // SQL errors come back as strings rather than aborting the script
+    let result = manager.execute_tool("db_query", vec!["SELECT * FROM nope".to_string()])?;
+    assert!(result.contains("Error running query"));
+    
+    std::fs::remove_file("tools/test_db_roundtrip.rhai")?;
+    Ok(())
+}
+
+#[test]
+fn test_agent_db_stays_in_its_file() -> Result<()> {
+    let dir = std::env::temp_dir().join(format!("swarm_thing_db_confined_{}", std::process::id()));
+    let db = AgentDb::open(dir.join("agent.db"))?;
+    let outside = dir.join("outside.db");
+    db.execute("CREATE TABLE facts (value INTEGER)", &[])?;
+
+    // db_query only reads, and neither call can attach or write another database
+    let err = db.query("INSERT INTO facts VALUES (1)", &[]).unwrap_err();
+    assert!(err.to_string().contains("db_query only reads"), "{}", err);
+    let attach = format!("ATTACH DATABASE '{}' AS x", outside.display());
+    assert!(db.query(&attach, &[]).is_err());
+    assert!(db.execute(&attach, &[]).is_err());
+    assert!(db.execute(&format!("VACUUM INTO '{}'", outside.display()), &[]).is_err());
+    assert!(!outside.exists());
+
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}
@@ -1,5 +1,5 @@
 use anyhow::Result;
-use rust_research_agent::tools::ToolManager;
+use swarm_thing::tools::ToolManager;
 
 #[test]
 fn test_tool_discovery() -> Result<()> {
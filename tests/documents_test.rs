@@ -0,0 +1,187 @@
+use anyhow::Result;
+use swarm_thing::documents::{truncate_for_reading, DocumentKind, READ_MAX_CHARS};
+use swarm_thing::embeddings::Embedder;
+use swarm_thing::inflate::{inflate, zlib_decompress, MAX_INFLATED};
+use swarm_thing::jail::FsJail;
+use swarm_thing::knowledge::KnowledgeBase;
+use swarm_thing::pdf::pdf_pages;
+use swarm_thing::tools::ToolManager;
+
+/// `data` as a zlib stream of one stored (uncompressed) DEFLATE block
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    let length = data.len() as u16;
+    let mut out = vec![0x78, 0x01, 0x01];
+    out.extend_from_slice(&length.to_le_bytes());
+    out.extend_from_slice(&(!length).to_le_bytes());
+    out.extend_from_slice(data);
+    // Adler-32, which the reader doesn't check
+    out.extend_from_slice(&[0, 0, 0, 0]);
+    out
+}
+
+/// A two-page PDF: the first page's content is plain, the second's is FlateDecode
+fn two_page_pdf() -> Vec<u8> {
+    let first = b"BT /F1 12 Tf 72 720 Td (Swarms of agents) Tj 0 -14 Td [(share ) -250 (tools.)] TJ ET".to_vec();
+    let second = zlib_stored(b"BT /F1 12 Tf 72 720 Td (Second page \\(flate\\)) Tj ET");
+    let mut pdf = b"%PDF-1.4\n".to_vec();
+    let objects: Vec<Vec<u8>> = vec![
+        b"<< /Type /Catalog /Pages 2 0 R >>".to_vec(),
+        b"<< /Type /Pages /Kids [3 0 R 4 0 R] /Count 2 /Resources << /Font << /F1 5 0 R >> >> >>".to_vec(),
+        b"<< /Type /Page /Parent 2 0 R /Contents 6 0 R >>".to_vec(),
+        b"<< /Type /Page /Parent 2 0 R /Contents 7 0 R >>".to_vec(),
+        b"<< /Type /Font /Subtype /Type1 /BaseFont /Helvetica >>".to_vec(),
+        [format!("<< /Length {} >>\nstream\n", first.len()).into_bytes(), first, b"\nendstream".to_vec()].concat(),
+        [format!("<< /Length {} /Filter /FlateDecode >>\nstream\n", second.len()).into_bytes(), second, b"\nendstream".to_vec()].concat(),
+    ];
+    for (i, body) in objects.iter().enumerate() {
+        pdf.extend_from_slice(format!("{} 0 obj\n", i + 1).as_bytes());
+        pdf.extend_from_slice(body);
+        pdf.extend_from_slice(b"\nendobj\n");
+    }
+    pdf.extend_from_slice(b"trailer\n<< /Size 8 /Root 1 0 R >>\n%%EOF\n");
+    pdf
+}
+
+#[test]
+fn test_pdf_text_comes_out_page_by_page() -> Result<()> {
+    assert_eq!(zlib_decompress(&zlib_stored(b"hello"))?, b"hello");
+
+    let pdf = two_page_pdf();
+    let pages = pdf_pages(&pdf)?;
+    assert_eq!(pages.len(), 2);
+    assert_eq!(pages[0], "Swarms of agents\nshare tools.");
+    assert_eq!(pages[1], "Second page (flate)");
+
+    let text = DocumentKind::Pdf.extract(&pdf)?;
+    assert_eq!(text, "--- Page 1 ---\nSwarms of agents\nshare tools.\n\n--- Page 2 ---\nSecond page (flate)");
+    assert!(pdf_pages(b"not a pdf").is_err());
+    Ok(())
+}
+
+#[test]
+fn test_inflating_stops_at_the_limit() -> Result<()> {
+    use flate2::{write::DeflateEncoder, Compression};
+    use std::io::Write;
+    // A few dozen kilobytes that expand past the limit
+    let mut encoder = DeflateEncoder::new(Vec::new(), Compression::best());
+    encoder.write_all(&vec![0u8; MAX_INFLATED + 1])?;
+    let bomb = encoder.finish()?;
+    assert!(bomb.len() < 1024 * 1024);
+    let err = inflate(&bomb).unwrap_err();
+    assert!(err.to_string().contains("exceeds"), "{}", err);
+    Ok(())
+}
+
+#[test]
+fn test_deeply_nested_pdf_objects_do_not_overflow() {
+    let mut pdf = b"%PDF-1.4\n1 0 obj\n".to_vec();
+    pdf.extend(std::iter::repeat_n(b'[', 100_000));
+    pdf.extend(std::iter::repeat_n(b"<<".as_slice(), 100_000).flatten());
+    pdf.extend_from_slice(b"\nendobj\ntrailer\n<< /Root 1 0 R >>\n%%EOF\n");
+    // No pages, but no stack overflow either
+    assert!(pdf_pages(&pdf).map_or(true, |pages| pages.is_empty()));
+}
+
+#[test]
+fn test_document_kinds_are_detected() {
+    assert_eq!(DocumentKind::detect("paper.PDF", None, b""), Some(DocumentKind::Pdf));
+    assert_eq!(DocumentKind::detect("https://example.com/download?id=3", Some("application/pdf"), b""), Some(DocumentKind::Pdf));
+    assert_eq!(DocumentKind::detect("blob", None, b"%PDF-1.7\n"), Some(DocumentKind::Pdf));
+    assert_eq!(DocumentKind::detect("notes.txt", Some("text/plain"), b"hello"), None);
+
+    let long = "x".repeat(READ_MAX_CHARS + 10);
+    assert!(truncate_for_reading(&long).ends_with("ingest_document the source to search all of it]"));
+    assert_eq!(truncate_for_reading("short"), "short");
+}
+
+#[tokio::test]
+async fn test_pdfs_are_ingested_with_page_markers() -> Result<()> {
+    let dir = std::env::temp_dir().join(format!("swarm_documents_{}", std::process::id()));
+    std::fs::create_dir_all(&dir)?;
+    std::fs::write(dir.join("paper.pdf"), two_page_pdf())?;
+    let kb = KnowledgeBase::open(dir.join("kb.jsonl"), Some(Embedder::hashing()))?;
+    let jail = FsJail::new(&dir)?;
+
+    assert_eq!(kb.ingest("paper.pdf", &jail).await?, 1);
+    let passages = kb.retrieve("flate", 1).await?;
+    assert_eq!(passages[0].source, "paper.pdf");
+    assert!(passages[0].text.contains("--- Page 2 --- Second page (flate)"), "{}", passages[0].text);
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+
+#[test]
+fn test_read_pdf_native() -> Result<()> {
+    let mut manager = ToolManager::new()?;
+    std::fs::write("documents_test_paper.pdf", two_page_pdf())?;
+    manager.create_tool("test_read_pdf", r#"
+    fn test_read_pdf(path) {
+        return read_pdf(path);
+    }
+    "#)?;
+
+    let result = manager.execute_tool("test_read_pdf", vec!["documents_test_paper.pdf".to_string()])?;
+    assert!(result.starts_with("--- Page 1 ---\nSwarms of agents"), "got {}", result);
+    let missing = manager.execute_tool("test_read_pdf", vec!["documents_test_missing.pdf".to_string()])?;
+    assert!(missing.starts_with("Error"), "got {}", missing);
+
+    std::fs::remove_file("documents_test_paper.pdf")?;
+    std::fs::remove_file("tools/test_read_pdf.rhai")?;
+    Ok(())
+}
+
+/// A zip archive of `files`, stored without compression (CRCs left zero; the reader skips them)
+#[cfg(any(feature = "docx", feature = "epub"))]
+fn stored_zip(files: &[(&str, &str)]) -> Vec<u8> {
+    let (mut zip, mut directory) = (Vec::new(), Vec::new());
+    for (name, content) in files {
+        let offset = zip.len() as u32;
+        let sizes = [(content.len() as u32).to_le_bytes(), (content.len() as u32).to_le_bytes()].concat();
+        zip.extend_from_slice(b"PK\x03\x04\x14\0\0\0\0\0\0\0\0\0\0\0\0\0");
+        zip.extend_from_slice(&sizes);
+        zip.extend_from_slice(&(name.len() as u16).to_le_bytes());
+        zip.extend_from_slice(&[0, 0]);
+        zip.extend_from_slice(name.as_bytes());
+        zip.extend_from_slice(content.as_bytes());
+        directory.extend_from_slice(b"PK\x01\x02\x14\0\x14\0\0\0\0\0\0\0\0\0\0\0\0\0");
+        directory.extend_from_slice(&sizes);
+        directory.extend_from_slice(&(name.len() as u16).to_le_bytes());
+        directory.extend_from_slice(&[0; 12]);
+        directory.extend_from_slice(&offset.to_le_bytes());
+        directory.extend_from_slice(name.as_bytes());
+    }
+    let start = zip.len() as u32;
+    zip.extend_from_slice(&directory);
+    zip.extend_from_slice(b"PK\x05\x06\0\0\0\0");
+    zip.extend_from_slice(&(files.len() as u16).to_le_bytes());
+    zip.extend_from_slice(&(files.len() as u16).to_le_bytes());
+    zip.extend_from_slice(&(directory.len() as u32).to_le_bytes());
+    zip.extend_from_slice(&start.to_le_bytes());
+    zip.extend_from_slice(&[0, 0]);
+    zip
+}
+
+#[cfg(feature = "docx")]
+#[test]
+fn test_docx_paragraphs_are_read() -> Result<()> {
+    let document = r#"<w:document><w:body><w:p><w:r><w:t>Findings &amp; notes</w:t></w:r></w:p><w:p><w:r><w:t xml:space="preserve">Revenue </w:t></w:r><w:r><w:t>grew.</w:t></w:r></w:p></w:body></w:document>"#;
+    let docx = stored_zip(&[("[Content_Types].xml", "<Types/>"), ("word/document.xml", document)]);
+    assert_eq!(DocumentKind::detect("report.docx", None, &docx), Some(DocumentKind::Docx));
+    assert_eq!(DocumentKind::Docx.extract(&docx)?, "Findings & notes\nRevenue grew.");
+    Ok(())
+}
+
+#[cfg(feature = "epub")]
+#[test]
+fn test_epub_sections_follow_the_spine() -> Result<()> {
+    let container = r#"<container><rootfiles><rootfile full-path="OEBPS/content.opf" media-type="application/oebps-package+xml"/></rootfiles></container>"#;
+    let package = r#"<package><manifest><item id="b" href="two.xhtml"/><item id="a" href="one.xhtml"/></manifest><spine><itemref idref="a"/><itemref idref="b"/></spine></package>"#;
+    let epub = stored_zip(&[
+        ("META-INF/container.xml", container),
+        ("OEBPS/content.opf", package),
+        ("OEBPS/one.xhtml", "<html><body><p>Chapter one.</p></body></html>"),
+        ("OEBPS/two.xhtml", "<html><body><p>Chapter two.</p></body></html>"),
+    ]);
+    assert_eq!(DocumentKind::Epub.extract(&epub)?, "--- Section 1 ---\nChapter one.\n\n--- Section 2 ---\nChapter two.");
+    Ok(())
+}
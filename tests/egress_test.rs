@@ -0,0 +1,113 @@
+use anyhow::Result;
+use axum::response::Redirect;
+use axum::routing::get;
+use axum::Router;
+use std::sync::{Arc, Mutex};
+use swarm_thing::audit::AuditLog;
+use swarm_thing::egress::{self, is_egress_denied, EgressDenied};
+use swarm_thing::http_cache::fetch_page;
+use swarm_thing::politeness::Politeness;
+use swarm_thing::safety::SafetyPolicy;
+
+fn policy(allowed: &[&str], denied: &[&str]) -> SafetyPolicy {
+    SafetyPolicy {
+        allowed_hosts: allowed.iter().map(|h| h.to_string()).collect(),
+        denied_hosts: denied.iter().map(|h| h.to_string()).collect(),
+        ..SafetyPolicy::default()
+    }
+}
+
+#[test]
+fn test_allowlist_and_denylist() {
+    assert!(egress::check(&SafetyPolicy::default(), "http://10.1.2.3/").is_ok());
+
+    let denylist = policy(&[], &["10.0.0.0/8", "169.254.169.254", "*.internal.example"]);
+    assert!(denylist.allows_host("https://example.com/page"));
+    assert!(!denylist.allows_host("http://10.20.30.40:8080/message"));
+    assert!(!denylist.allows_host("http://169.254.169.254/latest/meta-data"));
+    assert!(!denylist.allows_host("db.internal.example:5432"));
+    assert!(denylist.allows_host("http://11.0.0.1/"));
+
+    // The denylist wins, and names are resolved for CIDR rules
+    let both = policy(&["*.example.com", "localhost", "192.168.0.0/16"], &["127.0.0.0/8"]);
+    assert!(both.allows_host("https://api.example.com/v1"));
+    assert!(both.allows_host("http://192.168.1.10/"));
+    assert!(!both.allows_host("http://evil.com/?next=example.com"));
+    let denied = egress::check(&both, "http://localhost:9000/message").unwrap_err();
+    assert_eq!(denied, EgressDenied { host: "localhost".to_string(), reason: "matches denied_hosts entry '127.0.0.0/8'".to_string() });
+
+    // A fully qualified name is the same host
+    let named = policy(&["example.com."], &["blocked.example", "*.internal.example."]);
+    assert!(!named.allows_host("http://blocked.example./"));
+    assert!(!named.allows_host("http://BLOCKED.EXAMPLE.:8080/x"));
+    assert!(!named.allows_host("http://db.internal.example./"));
+    assert!(named.allows_host("https://example.com./"));
+    assert_eq!(egress::host_of("http://Example.COM./a"), Some("example.com".to_string()));
+
+    let v6 = policy(&[], &["fd00::/8"]);
+    assert!(!v6.allows_host("http://[fd12::1]/"));
+    assert!(v6.allows_host("http://[2001:db8::1]/"));
+}
+
+#[test]
+fn test_violations_are_audited_and_typed() -> Result<()> {
+    let path = std::env::temp_dir().join(format!("swarm_egress_audit_{}.jsonl", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let audit = AuditLog::open(&path)?;
+    let denylist = policy(&["example.com"], &[]);
+
+    egress::guard(&denylist, "https://example.com/", "scrape_url", Some(&audit))?;
+    let error: anyhow::Error = egress::guard(&denylist, "https://other.org/x", "scrape_url", Some(&audit)).unwrap_err().into();
+    assert!(is_egress_denied(&error));
+    assert_eq!(error.to_string(), "Egress to other.org denied: not in allowed_hosts");
+    assert!(!is_egress_denied(&anyhow::anyhow!("connection refused")));
+
+    let entries = audit.search("egress_denied", 10)?;
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0].detail, "scrape_url to other.org");
+    let _ = std::fs::remove_file(&path);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_redirects_are_checked_hop_by_hop() -> Result<()> {
+    // localhost is denied; `/away` sends a request to 127.0.0.1 on to the same server by that name
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+    let port = listener.local_addr()?.port();
+    let app = Router::new()
+        .route("/here", get(|| async { "here" }))
+        .route("/near", get(|| async { Redirect::temporary("/here") }))
+        .route("/away", get(move || async move { Redirect::temporary(&format!("http://localhost:{}/here", port)) }));
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+    let safety = Arc::new(Mutex::new(policy(&[], &["localhost"])));
+    let politeness = Politeness::new().with_robots(false).with_egress(safety);
+    let base = format!("http://127.0.0.1:{}", port);
+    assert_eq!(fetch_page(None, &politeness, &format!("{}/near", base), false).await?, "here");
+    let error = fetch_page(None, &politeness, &format!("{}/away", base), false).await.unwrap_err();
+    assert!(is_egress_denied(&error), "{:#}", error);
+    assert!(format!("{:#}", error).contains("Egress to localhost denied: matches denied_hosts entry 'localhost'"), "{:#}", error);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_connections_only_reach_checked_addresses() -> Result<()> {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+    let port = listener.local_addr()?.port();
+    tokio::spawn(async move { axum::serve(listener, Router::new().route("/here", get(|| async { "here" }))).await.unwrap() });
+
+    // The client resolves names through the policy itself, so a name that passed a check and
+    // then resolves into a denied range is still refused when the client connects
+    let safety = Arc::new(Mutex::new(SafetyPolicy::default()));
+    let politeness = Politeness::new().with_robots(false).with_egress(safety.clone());
+    let url = format!("http://localhost:{}/here", port);
+    assert_eq!(fetch_page(None, &politeness, &url, false).await?, "here");
+    *safety.lock().unwrap() = policy(&[], &["127.0.0.0/8", "::1"]);
+    let error = fetch_page(None, &politeness, &url, false).await.unwrap_err();
+    assert!(is_egress_denied(&error), "{:#}", error);
+    assert!(format!("{:#}", error).contains("Egress to localhost denied"), "{:#}", error);
+    // Addresses are checked one by one, and only allowed ones are connected to
+    *safety.lock().unwrap() = policy(&[], &["::1"]);
+    assert_eq!(fetch_page(None, &politeness, &url, false).await?, "here");
+    Ok(())
+}
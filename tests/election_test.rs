@@ -0,0 +1,100 @@
+use anyhow::Result;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use swarm_thing::election::{leader_loop, Election};
+use swarm_thing::ipc::{router, IpcState, TaskQueue};
+use swarm_thing::registry::{HeartbeatConfig, PeerInfo, PeerRegistry};
+use swarm_thing::state::StateStore;
+use swarm_thing::threads::PeerThreads;
+use swarm_thing::tools::ToolManager;
+use tokio::task::JoinHandle;
+
+fn peer(address: &str, last_seen: u64) -> PeerInfo {
+    PeerInfo {
+        name: address.to_string(),
+        address: address.to_string(),
+        capabilities: Vec::new(),
+        tools: Vec::new(),
+        last_seen,
+        missed_heartbeats: 0,
+        public_key: None,
+    }
+}
+
+struct Node {
+    address: String,
+    election: Election,
+    peers: PeerRegistry,
+    server: JoinHandle<()>,
+}
+
+/// An agent serving on a random port, with its own election and peer registry
+async fn node(index: usize) -> Result<Node> {
+    let state_path = std::env::temp_dir().join(format!("swarm_election_{}_{}.json", index, std::process::id()));
+    let _ = std::fs::remove_file(&state_path);
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+    let address = listener.local_addr()?.to_string();
+    let election = Election::new(Duration::from_millis(500));
+    let peers = PeerRegistry::new();
+    let state = IpcState::new(Arc::new(Mutex::new(Vec::new())), PeerThreads::new(StateStore::open(&state_path)?), TaskQueue::new(), peers.clone(), PathBuf::from("tools"))
+        .with_identity(peer(&address, 0))
+        .with_election(election.clone());
+    let server = tokio::spawn(async move {
+        axum::serve(listener, router(state).into_make_service_with_connect_info::<SocketAddr>()).await.unwrap()
+    });
+    Ok(Node { address, election, peers, server })
+}
+
+async fn wait_for_leader(nodes: &[&Node], leader: &str) -> bool {
+    for _ in 0..50 {
+        if nodes.iter().all(|n| n.election.leader().as_deref() == Some(leader)) {
+            return true;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    false
+}
+
+#[tokio::test]
+async fn test_highest_agent_leads_and_is_replaced() -> Result<()> {
+    let mut nodes = Vec::new();
+    for i in 0..3 {
+        nodes.push(node(i).await?);
+    }
+    nodes.sort_by(|a, b| a.address.cmp(&b.address));
+    for node in &nodes {
+        for other in nodes.iter().filter(|o| o.address != node.address) {
+            node.peers.upsert(peer(&other.address, 0));
+        }
+    }
+    let (low, middle, high) = (&nodes[0], &nodes[1], &nodes[2]);
+
+    // The lowest agent calls the election; the others take it over and the highest wins
+    let leader = low.election.run(&low.address, &low.peers).await;
+    assert!(wait_for_leader(&[low, middle, high], &high.address).await, "leaders: {:?}", nodes.iter().map(|n| n.election.leader()).collect::<Vec<_>>());
+    assert_eq!(leader.as_deref(), Some(high.address.as_str()));
+
+    // The leader goes silent: once heartbeats count it dead, the next in line takes over
+    high.server.abort();
+    for node in [low, middle] {
+        node.peers.remove(&high.address);
+        node.peers.merge(peer(&high.address, 1));
+    }
+    let config = HeartbeatConfig { interval: Duration::from_millis(100), timeout: Duration::from_secs(2) };
+    let watcher = tokio::spawn(leader_loop(low.election.clone(), low.address.clone(), low.peers.clone(), config));
+    assert!(wait_for_leader(&[low, middle], &middle.address).await, "leaders: {:?}", [low.election.leader(), middle.election.leader()]);
+    watcher.abort();
+    Ok(())
+}
+
+#[test]
+fn test_current_leader_native() -> Result<()> {
+    let manager = ToolManager::new()?;
+    assert_eq!(manager.execute_tool("current_leader", vec![])?, "No leader elected yet");
+    assert!(manager.execute_tool("elect_leader", vec![])?.starts_with("Error: start_server first"));
+    manager.election().set_leader("http://127.0.0.1:9001/message");
+    assert_eq!(manager.execute_tool("current_leader", vec![])?, "127.0.0.1:9001");
+    Ok(())
+}
@@ -0,0 +1,122 @@
+use anyhow::Result;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use swarm_thing::encryption::{is_sealed, seal, seal_to, unseal, AgentKeys};
+use swarm_thing::ipc::{router, send_ipc_message, IpcState, TaskQueue};
+use swarm_thing::message::{IpcMessage, ToolSafetyLevel};
+use swarm_thing::registry::{PeerInfo, PeerRegistry};
+use swarm_thing::state::StateStore;
+use swarm_thing::threads::PeerThreads;
+use swarm_thing::tools::PendingTool;
+
+fn temp(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("swarm_encryption_{}_{}", name, std::process::id()))
+}
+
+#[test]
+fn test_only_the_recipient_can_open() -> Result<()> {
+    let alice = AgentKeys::generate();
+    let eve = AgentKeys::generate();
+    let code = "fn square(x) { x * x }";
+
+    let sealed = seal(code, &alice.public_key())?;
+    assert!(is_sealed(&sealed) && !sealed.contains("square"), "{}", sealed);
+    assert_ne!(sealed, seal(code, &alice.public_key())?);
+    assert_eq!(alice.open(&sealed)?, code);
+    assert!(eve.open(&sealed).is_err());
+
+    // A flipped byte fails authentication instead of decrypting to garbage
+    let mut tampered = sealed.clone().into_bytes();
+    let last = tampered.len() - 3;
+    tampered[last] = if tampered[last] == b'A' { b'B' } else { b'A' };
+    assert!(alice.open(&String::from_utf8(tampered)?).is_err());
+
+    // Peers without a key get plaintext, and plaintext passes through unsealing
+    assert_eq!(seal_to(None, code)?, code);
+    assert_eq!(unseal(Some(&alice), &seal_to(Some(&alice.public_key_base64()), code)?)?, code);
+    assert_eq!(unseal(None, code)?, code);
+    assert!(unseal(None, &sealed).is_err());
+    Ok(())
+}
+
+#[test]
+fn test_keys_persist() -> Result<()> {
+    let path = temp("key");
+    let _ = std::fs::remove_file(&path);
+    let created = AgentKeys::load_or_create(&path)?;
+    let loaded = AgentKeys::load_or_create(&path)?;
+    assert_eq!(created.public_key_base64(), loaded.public_key_base64());
+    std::fs::remove_file(&path)?;
+    Ok(())
+}
+
+/// Serve tools from `tools_dir` on a random port, returning its address and approval queue
+async fn serve(name: &str, tools_dir: PathBuf, peers: PeerRegistry, keys: Option<AgentKeys>) -> Result<(String, Arc<Mutex<Vec<PendingTool>>>)> {
+    let state_path = temp(&format!("{}.json", name));
+    let _ = std::fs::remove_file(&state_path);
+    let pending = Arc::new(Mutex::new(Vec::new()));
+    let mut state = IpcState::new(pending.clone(), PeerThreads::new(StateStore::open(&state_path)?), TaskQueue::new(), peers, tools_dir);
+    if let Some(keys) = keys {
+        state = state.with_keys(keys);
+    }
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+    let address = listener.local_addr()?.to_string();
+    tokio::spawn(async move {
+        axum::serve(listener, router(state).into_make_service_with_connect_info::<SocketAddr>()).await.unwrap()
+    });
+    Ok((address, pending))
+}
+
+#[tokio::test]
+async fn test_shared_code_is_decrypted_before_queueing() -> Result<()> {
+    let keys = AgentKeys::generate();
+    let (address, pending) = serve("receiver", PathBuf::from("tools"), PeerRegistry::new(), Some(keys.clone())).await?;
+    let code = "fn test_sealed_square(x) { x * x }";
+    let share = IpcMessage::tool_share("test_sealed_square", seal(code, &keys.public_key())?, None, ToolSafetyLevel::Safe);
+    let reply = send_ipc_message(&address, &share, None).await?;
+    assert!(reply.contains("queued for approval"), "{}", reply);
+    assert_eq!(pending.lock().unwrap()[0].code, code);
+
+    // Code sealed to someone else is refused rather than queued
+    let share = IpcMessage::tool_share("test_sealed_cube", seal(code, &AgentKeys::generate().public_key())?, None, ToolSafetyLevel::Safe);
+    let reply = send_ipc_message(&address, &share, None).await?;
+    assert!(reply.contains("cannot decrypt tool 'test_sealed_cube'"), "{}", reply);
+    assert_eq!(pending.lock().unwrap().len(), 1);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_requested_tools_are_sealed_to_the_requester() -> Result<()> {
+    let tools_dir = temp("tools");
+    std::fs::create_dir_all(&tools_dir)?;
+    let code = "// Doubles a number\nfn double(x) { x * 2 }";
+    std::fs::write(tools_dir.join("double.rhai"), code)?;
+
+    let requester = AgentKeys::generate();
+    let peers = PeerRegistry::new();
+    peers.upsert(PeerInfo {
+        name: "requester".to_string(),
+        address: "127.0.0.1:9".to_string(),
+        capabilities: Vec::new(),
+        tools: Vec::new(),
+        last_seen: 0,
+        missed_heartbeats: 0,
+        public_key: Some(requester.public_key_base64()),
+    });
+    let (address, _) = serve("owner", tools_dir.clone(), peers, None).await?;
+
+    let reply = send_ipc_message(&address, &IpcMessage::tool_request("double"), Some("127.0.0.1:9".to_string())).await?;
+    let IpcMessage::ToolShare { code: sealed, .. } = IpcMessage::from_json_or_text(&reply) else {
+        panic!("expected a ToolShare, got {}", reply);
+    };
+    assert!(is_sealed(&sealed));
+    assert_eq!(requester.open(&sealed)?, code);
+
+    // Requesters that advertised no key get plaintext
+    let reply = send_ipc_message(&address, &IpcMessage::tool_request("double"), Some("127.0.0.1:10".to_string())).await?;
+    assert!(matches!(IpcMessage::from_json_or_text(&reply), IpcMessage::ToolShare { code: plain, .. } if plain == code));
+
+    std::fs::remove_dir_all(&tools_dir)?;
+    Ok(())
+}
@@ -0,0 +1,103 @@
+use anyhow::Result;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use swarm_thing::ipc::{router, send_ipc_message, AgentInfo, IpcState, TaskQueue};
+use swarm_thing::message::{IpcMessage, ToolSafetyLevel};
+use swarm_thing::registry::{PeerInfo, PeerRegistry};
+use swarm_thing::safety::SafetyPolicy;
+use swarm_thing::state::StateStore;
+use swarm_thing::threads::PeerThreads;
+use swarm_thing::tools::{tool_catalog, CatalogEntry, SharePolicy, ToolManager};
+
+async fn serve(name: &str, tools_dir: &Path, identity: Option<PeerInfo>) -> Result<String> {
+    let state_path = std::env::temp_dir().join(format!("swarm_endpoints_{}_{}.json", name, std::process::id()));
+    let threads = PeerThreads::new(StateStore::open(&state_path)?);
+    let mut state = IpcState::new(Arc::new(Mutex::new(Vec::new())), threads, TaskQueue::new(), PeerRegistry::new(), tools_dir.to_path_buf());
+    if let Some(me) = identity {
+        state = state.with_identity(me);
+    }
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+    let address = listener.local_addr()?.to_string();
+    tokio::spawn(async move {
+        axum::serve(listener, router(state).into_make_service_with_connect_info::<SocketAddr>()).await.unwrap()
+    });
+    Ok(format!("http://{}", address))
+}
+
+fn tools_dir(name: &str) -> Result<std::path::PathBuf> {
+    let dir = std::env::temp_dir().join(format!("swarm_endpoints_tools_{}_{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir)?;
+    std::fs::write(dir.join("greet.rhai"), "// Say hello\nfn greet(name) { \"Hello \" + name }")?;
+    std::fs::write(dir.join("wipe.rhai"), "// Delete things\nfn wipe(path) { delete_file(path) }")?;
+    Ok(dir)
+}
+
+#[tokio::test]
+async fn test_health_and_info() -> Result<()> {
+    let me = PeerInfo {
+        name: "scout".to_string(),
+        address: "127.0.0.1:9000".to_string(),
+        capabilities: vec!["messaging".to_string()],
+        tools: Vec::new(),
+        last_seen: 0,
+        missed_heartbeats: 0,
+        public_key: None,
+    };
+    let base = serve("info", &tools_dir("info")?, Some(me)).await?;
+    let health: serde_json::Value = reqwest::get(format!("{}/health", base)).await?.json().await?;
+    assert_eq!(health["status"], "ok");
+
+    let info: AgentInfo = reqwest::get(format!("{}/info", base)).await?.json().await?;
+    assert_eq!(info.name, "scout");
+    assert_eq!(info.version, env!("CARGO_PKG_VERSION"));
+    assert_eq!(info.address.as_deref(), Some("127.0.0.1:9000"));
+    assert_eq!(info.capabilities, vec!["messaging".to_string()]);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_tool_catalog() -> Result<()> {
+    let dir = tools_dir("catalog")?;
+    let base = serve("catalog", &dir, None).await?;
+    let catalog: Vec<CatalogEntry> = reqwest::get(format!("{}/tools", base)).await?.json().await?;
+    let greet = catalog.iter().find(|entry| entry.name == "greet").expect("greet is listed");
+    assert_eq!(greet.description.as_deref(), Some("Say hello"));
+    assert_eq!(greet.safety_level, ToolSafetyLevel::Safe);
+    assert!(greet.shareable);
+    let wipe = catalog.iter().find(|entry| entry.name == "wipe").expect("wipe is listed");
+    assert_eq!(wipe.safety_level, ToolSafetyLevel::HighRisk);
+    assert!(!wipe.shareable);
+
+    // Denied tools are left out altogether
+    let mut policy = SharePolicy::default();
+    policy.deny.push("wipe".to_string());
+    assert!(tool_catalog(&dir, &policy, &SafetyPolicy::default()).iter().all(|entry| entry.name != "wipe"));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_metrics() -> Result<()> {
+    let base = serve("metrics", &tools_dir("metrics")?, None).await?;
+    send_ipc_message(&base, &IpcMessage::text("hello"), Some("scout".to_string())).await?;
+    let tools = ToolManager::new()?;
+    assert_eq!(tools.execute_tool("square", vec!["4".to_string()])?, "16");
+    assert!(tools.execute_tool("square", vec!["four".to_string()]).is_err());
+
+    let response = reqwest::get(format!("{}/metrics", base)).await?;
+    assert!(response.headers()["content-type"].to_str()?.starts_with("text/plain"));
+    let body = response.text().await?;
+    let value = |series: &str| -> f64 {
+        body.lines().find_map(|line| line.strip_prefix(series)?.trim().parse().ok()).unwrap_or(0.0)
+    };
+    // Tests in this file share the process's metrics, so only lower bounds hold
+    assert!(value(r#"swarm_ipc_messages_total{direction="in",kind="Text"}"#) >= 1.0, "{}", body);
+    assert!(value(r#"swarm_ipc_messages_total{direction="out",kind="Text"}"#) >= 1.0, "{}", body);
+    assert!(value(r#"swarm_tool_executions_total{level="Safe",outcome="ok"}"#) >= 1.0, "{}", body);
+    assert!(value(r#"swarm_tool_executions_total{level="Safe",outcome="error"}"#) >= 1.0, "{}", body);
+    assert!(value(r#"swarm_tool_execution_duration_seconds_count{level="Safe"}"#) >= 2.0, "{}", body);
+    assert!(body.contains("swarm_pending_tools 0"), "{}", body);
+    assert!(body.contains("swarm_pending_tasks 0"), "{}", body);
+    Ok(())
+}
@@ -0,0 +1,106 @@
+use anyhow::Result;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use swarm_thing::agent::Agent;
+use swarm_thing::ensemble::{normalize_vote, Aggregator, Ensemble, Voter};
+use swarm_thing::inbox::Inbox;
+use swarm_thing::ipc::{router, serve_inbox, IpcState, PendingCalls, TaskQueue};
+use swarm_thing::llm::{Completion, LlmClient, Usage};
+use swarm_thing::orchestrator::Member;
+use swarm_thing::registry::PeerRegistry;
+use swarm_thing::replay::RecordedResponses;
+use swarm_thing::state::StateStore;
+use swarm_thing::threads::PeerThreads;
+use swarm_thing::tools::ToolManager;
+
+fn said(text: &str) -> Result<Completion, String> {
+    Ok(Completion { text: text.to_string(), usage: Usage { input_tokens: 10, output_tokens: 2 }, model: "mock".to_string(), cached: false })
+}
+
+async fn model(responses: Vec<Result<Completion, String>>) -> Result<Voter> {
+    Ok(Voter::Model(LlmClient::replaying(RecordedResponses::new(responses)).await?))
+}
+
+/// Serve an agent on a random port, returning its address and thread history
+async fn serve(name: &str, inbox: Inbox, calls: PendingCalls) -> Result<(String, PeerThreads)> {
+    let state_path = std::env::temp_dir().join(format!("swarm_ensemble_{}_{}.json", name, std::process::id()));
+    let _ = std::fs::remove_file(&state_path);
+    let threads = PeerThreads::new(StateStore::open(&state_path)?);
+    let state = IpcState::new(Arc::new(Mutex::new(Vec::new())), threads.clone(), TaskQueue::new(), PeerRegistry::new(), PathBuf::from("tools"))
+        .with_inbox(inbox)
+        .with_calls(calls);
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+    let address = listener.local_addr()?.to_string();
+    tokio::spawn(async move {
+        axum::serve(listener, router(state).into_make_service_with_connect_info::<SocketAddr>()).await.unwrap()
+    });
+    Ok((address, threads))
+}
+
+/// A peer whose agent answers with `answer`
+async fn peer(name: &str, answer: &str) -> Result<String> {
+    let inbox = Inbox::new();
+    let (address, threads) = serve(name, inbox.clone(), PendingCalls::new()).await?;
+    let llm = LlmClient::replaying(RecordedResponses::new(vec![said(answer)])).await?;
+    let member = Member::new(name, "Answers peers", Agent::with_client(llm, "You answer."), ToolManager::new()?, "You answer.");
+    tokio::spawn(serve_inbox(inbox, threads, member, Some(address.clone())));
+    Ok(address)
+}
+
+#[test]
+fn test_votes_are_compared_loosely() {
+    assert_eq!(normalize_vote("  Paris. "), "paris");
+    assert_eq!(normalize_vote("\"The  answer\nis 42!\""), "the answer is 42");
+    assert_ne!(normalize_vote("Paris"), normalize_vote("Lyon"));
+}
+
+#[tokio::test]
+async fn test_majority_vote_across_models() -> Result<()> {
+    let voters = vec![
+        model(vec![said("Lyon")]).await?,
+        model(vec![said("Paris.")]).await?,
+        model(vec![Err("model down".to_string())]).await?,
+        model(vec![said("paris")]).await?,
+    ];
+    let consensus = Ensemble::new(voters, Aggregator::Majority).ask("What is the capital of France?").await?;
+    assert_eq!(consensus.answer, "Paris.");
+    assert_eq!(consensus.agreeing, Some(2));
+    let rendered = consensus.render();
+    assert!(rendered.starts_with("Paris.\n\n2 of 3 answer(s) agree (majority vote)"), "{}", rendered);
+    assert!(rendered.contains("Error: model down"), "{}", rendered);
+
+    // Nobody answering is an error, not an empty answer
+    let silent = Ensemble::new(vec![model(vec![Err("model down".to_string())]).await?], Aggregator::Majority);
+    assert_eq!(silent.ask("anything").await.unwrap_err().to_string(), "none of the 1 voter(s) answered");
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_synthesis_and_peer_voters() -> Result<()> {
+    let calls = PendingCalls::new();
+    let (me, _) = serve("asker", Inbox::new(), calls.clone()).await?;
+    let voters = vec![
+        Voter::Peer(peer("first", "Mercury is closest to the Sun.").await?),
+        Voter::Peer(peer("second", "Mercury.").await?),
+    ];
+    let judge = LlmClient::replaying(RecordedResponses::new(vec![said("Mercury, the innermost planet.")])).await?;
+    let ensemble = Ensemble::new(voters, Aggregator::Synthesis(judge))
+        .with_calls(calls, Some(me))
+        .with_timeout(Duration::from_secs(5));
+    let consensus = ensemble.ask("Which planet is closest to the Sun?").await?;
+
+    assert_eq!(consensus.answer, "Mercury, the innermost planet.");
+    assert_eq!(consensus.agreeing, None);
+    assert_eq!(consensus.votes.iter().filter(|v| v.answer.is_ok()).count(), 2, "{:?}", consensus.votes);
+    assert!(consensus.render().contains("From 2 of 2 answer(s) (synthesis)"));
+    Ok(())
+}
+
+#[test]
+fn test_ensemble_ask_needs_voters() -> Result<()> {
+    let manager = ToolManager::new()?;
+    assert_eq!(manager.execute_tool("ensemble_ask", vec!["Is it raining?".to_string(), "0".to_string()])?, "Error: an ensemble needs at least one voter");
+    Ok(())
+}
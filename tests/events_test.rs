@@ -0,0 +1,102 @@
+use anyhow::Result;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use swarm_thing::config::Config;
+use swarm_thing::events::{ask_for_approvals, AgentEvents, ApprovalRequest, NoEvents};
+use swarm_thing::inbox::InboxMessage;
+use swarm_thing::llm::{Completion, LlmClient, Usage};
+use swarm_thing::replay::RecordedResponses;
+use swarm_thing::runtime::AgentRuntime;
+use swarm_thing::tools::{ConfirmPolicy, ResponseAction, ToolManager};
+
+fn said(text: &str) -> Result<Completion, String> {
+    Ok(Completion { text: text.to_string(), usage: Usage { input_tokens: 10, output_tokens: 2 }, model: "mock".to_string(), cached: false })
+}
+
+/// Writes down every hook call and declines every approval
+#[derive(Default)]
+struct Recorder {
+    calls: Mutex<Vec<String>>,
+}
+
+impl Recorder {
+    fn push(&self, call: String) {
+        self.calls.lock().unwrap().push(call);
+    }
+}
+
+impl AgentEvents for Recorder {
+    fn on_llm_call(&self, input: &str, response: Result<&str, &str>) {
+        self.push(format!("llm {} -> {:?}", input, response));
+    }
+
+    fn on_tool_executed(&self, name: &str, args: &[String], result: Result<&str, &str>, _duration_ms: u64) {
+        self.push(format!("ran {}({}) -> {:?}", name, args.join(", "), result));
+    }
+
+    fn on_message_received(&self, message: &InboxMessage) {
+        self.push(format!("message from {}: {}", message.sender(), message.content));
+    }
+
+    fn on_approval_needed(&self, request: &ApprovalRequest) -> Option<bool> {
+        if let ApprovalRequest::Execution { call, level } = request {
+            self.push(format!("approve {:?} {}", level, call));
+        }
+        Some(false)
+    }
+}
+
+#[tokio::test]
+async fn test_hooks_hear_each_step() -> Result<()> {
+    let recorder = Arc::new(Recorder::default());
+    let mut tools = ToolManager::new()?;
+    tools.set_confirm_policy(ConfirmPolicy::default());
+    let llm = LlmClient::replaying(RecordedResponses::new(vec![
+        said("[TOOL: square(3)]"),
+        said("[TOOL: read_file(notes.txt)]"),
+        said("Declined."),
+    ])).await?;
+    let mut runtime = AgentRuntime::builder()
+        .config(Config::default())
+        .tools(tools)
+        .llm(llm)
+        .journal(None)
+        .events(recorder.clone())
+        .build()
+        .await?;
+
+    let turn = runtime.handle_input("square 3, then read my notes").await;
+    assert_eq!(turn.final_answer(), Some("Declined."));
+    let calls = recorder.calls.lock().unwrap().clone();
+    assert_eq!(calls, vec![
+        r#"llm square 3, then read my notes -> Ok("[TOOL: square(3)]")"#.to_string(),
+        r#"ran square(3) -> Ok("9")"#.to_string(),
+        "llm Tool Output: 9 -> Ok(\"[TOOL: read_file(notes.txt)]\")".to_string(),
+        "approve MediumRisk read_file(notes.txt)".to_string(),
+        r#"ran read_file(notes.txt) -> Err("The operator declined to run read_file(notes.txt)")"#.to_string(),
+        calls[5].clone(),
+    ]);
+    assert!(calls[5].ends_with(r#"-> Ok("Declined.")"#), "{}", calls[5]);
+
+    // Messages from peers are passed on as they arrive
+    recorder.calls.lock().unwrap().clear();
+    runtime.tools().inbox().push(Some("http://127.0.0.1:9".to_string()), Some("scout".to_string()), "found it");
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert_eq!(*recorder.calls.lock().unwrap(), ["message from scout: found it"]);
+    Ok(())
+}
+
+#[test]
+fn test_unanswered_approvals_are_declined() -> Result<()> {
+    let mut tools = ToolManager::new()?;
+    tools.set_confirm_policy(ConfirmPolicy::default());
+    ask_for_approvals(&tools, Arc::new(NoEvents));
+
+    let declined = tools.execute_tool("read_file", vec!["events_test_missing.txt".to_string()]).unwrap_err();
+    assert!(declined.to_string().contains("declined"), "{}", declined);
+    let written = "```rhai\n// filename: events_test_wipe\nfn events_test_wipe(p) { delete_file(p) }\n```";
+    let actions = tools.act_on(written, None);
+    assert!(matches!(&actions[..], [ResponseAction::Created { outcome: Err(e), .. }] if e.contains("declined")), "{:?}", actions);
+    assert!(!tools.has_tool("events_test_wipe"));
+    Ok(())
+}
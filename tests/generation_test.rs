@@ -0,0 +1,68 @@
+use anyhow::Result;
+use axum::{routing::post, Json, Router};
+use swarm_thing::llm::{GenerationConfig, LlmClient, Message};
+
+// Ollama-compatible endpoint that answers with the options it received
+async fn mock_chat(Json(body): Json<serde_json::Value>) -> Json<serde_json::Value> {
+    Json(serde_json::json!({
+        "message": { "role": "assistant", "content": body["options"].to_string() }
+    }))
+}
+
+#[test]
+fn test_generation_config_merge() {
+    let defaults = GenerationConfig {
+        temperature: Some(0.7),
+        max_tokens: Some(1024),
+        top_p: None,
+        stop: vec!["END".to_string()],
+    };
+    let overrides = GenerationConfig {
+        temperature: Some(0.0),
+        ..Default::default()
+    };
+    let merged = defaults.merge(&overrides);
+    assert_eq!(merged.temperature, Some(0.0));
+    assert_eq!(merged.max_tokens, Some(1024));
+    assert_eq!(merged.stop, vec!["END".to_string()]);
+}
+
+#[tokio::test]
+async fn test_generation_options_sent_to_ollama() -> Result<()> {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+    let url = format!("http://{}/api/chat", listener.local_addr()?);
+    tokio::spawn(async move {
+        let app = Router::new().route("/api/chat", post(mock_chat));
+        axum::serve(listener, app).await.unwrap();
+    });
+    
+    let client = LlmClient::with_model("ollama", None).await?
+        .with_ollama_url(&url)
+        .with_generation(GenerationConfig {
+            temperature: Some(0.5),
+            max_tokens: Some(256),
+            top_p: Some(0.9),
+            stop: vec![],
+        });
+    let messages = vec![Message::user("hi")];
+    
+    let reply = client.chat(messages.clone(), None).await?;
+    let options: serde_json::Value = serde_json::from_str(&reply)?;
+    assert_eq!(options["temperature"], 0.5);
+    assert_eq!(options["num_predict"], 256);
+    assert!(options.get("stop").is_none());
+    
+    // Per-call overrides win over the client defaults
+    let overrides = GenerationConfig {
+        max_tokens: Some(16),
+        stop: vec!["\n\n".to_string()],
+        ..Default::default()
+    };
+    let reply = client.chat_with(messages, None, &overrides).await?;
+    let options: serde_json::Value = serde_json::from_str(&reply)?;
+    assert_eq!(options["num_predict"], 16);
+    assert_eq!(options["temperature"], 0.5);
+    assert_eq!(options["stop"][0], "\n\n");
+
+    Ok(())
+}
@@ -0,0 +1,87 @@
+use anyhow::Result;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use swarm_thing::auth::IpcAuth;
+use swarm_thing::grpc::proto::request_tool_reply::Outcome;
+use swarm_thing::grpc::proto::swarm_client::SwarmClient;
+use swarm_thing::grpc::proto::{self, Envelope};
+use swarm_thing::grpc::{envelope, serve_grpc};
+use swarm_thing::inbox::Inbox;
+use swarm_thing::ipc::{IpcState, Message, TaskQueue};
+use swarm_thing::message::IpcMessage;
+use swarm_thing::registry::PeerRegistry;
+use swarm_thing::state::StateStore;
+use swarm_thing::threads::PeerThreads;
+use swarm_thing::tools::{InvokePolicy, ToolManager};
+use tonic::Code;
+
+fn agent_state(name: &str, inbox: Inbox) -> Result<IpcState> {
+    let state_path = std::env::temp_dir().join(format!("swarm_grpc_{}_{}.json", name, std::process::id()));
+    let threads = PeerThreads::new(StateStore::open(&state_path)?);
+    Ok(IpcState::new(Arc::new(Mutex::new(Vec::new())), threads, TaskQueue::new(), PeerRegistry::new(), PathBuf::from("tools"))
+        .with_inbox(inbox))
+}
+
+async fn connect(port: u16) -> Result<SwarmClient<tonic::transport::Channel>> {
+    for _ in 0..50 {
+        if let Ok(client) = SwarmClient::connect(format!("http://127.0.0.1:{}", port)).await {
+            return Ok(client);
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    Err(anyhow::anyhow!("gRPC server on port {} never came up", port))
+}
+
+#[tokio::test]
+async fn test_grpc_rpcs() -> Result<()> {
+    let mut host = ToolManager::new()?;
+    host.create_tool("test_grpc_double", "fn test_grpc_double(x) { let n = parse_int(x); return n * 2; }")?;
+    let inbox = Inbox::new();
+    let state = agent_state("rpcs", inbox.clone())?.with_tool_host(Arc::new(host), InvokePolicy::default());
+    tokio::spawn(serve_grpc(9893, state, std::future::pending()));
+    let mut client = connect(9893).await?;
+    let from = Some("127.0.0.1:7001".to_string());
+
+    let reply = client.send_message(proto::SendMessageRequest {
+        envelope: Some(envelope(&IpcMessage::text("hello over grpc"), from.clone())?),
+        content: "hello over grpc".to_string(),
+    }).await?.into_inner();
+    assert_eq!(reply.received, "hello over grpc");
+    assert!(inbox.render_unread().contains("hello over grpc"));
+
+    let reply = client.request_tool(proto::RequestToolRequest { envelope: None, name: "no_such_tool".to_string() }).await?.into_inner();
+    assert_eq!(reply.outcome, Some(Outcome::Refused("tool not found".to_string())));
+
+    let reply = client.invoke_tool(proto::InvokeToolRequest {
+        envelope: None,
+        name: "test_grpc_double".to_string(),
+        args: vec!["21".to_string()],
+    }).await?.into_inner();
+    assert_eq!((reply.result.as_deref(), reply.error), (Some("42"), None));
+
+    std::fs::remove_file("tools/test_grpc_double.rhai")?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_grpc_checks_signatures() -> Result<()> {
+    let auth = IpcAuth::new(Some("swarm-secret"), "coordinator");
+    tokio::spawn(serve_grpc(9894, agent_state("signed", Inbox::new())?.with_auth(auth.clone()), std::future::pending()));
+    let mut client = connect(9894).await?;
+
+    let unsigned = client.send_message(proto::SendMessageRequest { envelope: None, content: "hi".to_string() }).await.unwrap_err();
+    assert_eq!(unsigned.code(), Code::Unauthenticated);
+
+    // Signed like an HTTP post of the same message
+    let mut payload = Message::new(&IpcMessage::text("hi"), None)?;
+    auth.sign(&mut payload);
+    let signature = payload.signature.map(|s| proto::Signature { agent: s.agent, timestamp: s.timestamp, hmac: s.hmac, key: s.key, agent_key: s.agent_key, agent_signature: s.agent_signature });
+    let signed = proto::SendMessageRequest { envelope: Some(Envelope { from: None, signature: signature.clone(), traceparent: None }), content: "hi".to_string() };
+    assert_eq!(client.send_message(signed).await?.into_inner().received, "hi");
+
+    // A signature doesn't carry over to other content
+    let tampered = proto::SendMessageRequest { envelope: Some(Envelope { from: None, signature, traceparent: None }), content: "bye".to_string() };
+    assert_eq!(client.send_message(tampered).await.unwrap_err().code(), Code::Unauthenticated);
+    Ok(())
+}
@@ -0,0 +1,70 @@
+use anyhow::Result;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use swarm_thing::auth::IpcAuth;
+use swarm_thing::halt::HaltSwitch;
+use swarm_thing::ipc::{router, IpcState, Message, MessageResponse, TaskQueue};
+use swarm_thing::lineage::{CloneRegistry, Lineage};
+use swarm_thing::message::IpcMessage;
+use swarm_thing::registry::PeerRegistry;
+use swarm_thing::state::StateStore;
+use swarm_thing::threads::PeerThreads;
+use swarm_thing::tools::ToolManager;
+
+#[test]
+fn test_halt_stops_tools_until_resumed() -> Result<()> {
+    let mut manager = ToolManager::new()?;
+    manager.create_tool("halt_test_spin", "fn halt_test_spin() { let n = 0; loop { n += 1; } }")?;
+
+    // A running script is aborted when the switch trips
+    let halt = manager.halt_switch().clone();
+    let tripper = std::thread::spawn(move || {
+        std::thread::sleep(Duration::from_millis(200));
+        halt.halt("runaway loop");
+    });
+    assert!(manager.execute_tool("halt_test_spin", vec![]).is_err());
+    tripper.join().unwrap();
+
+    let refused = manager.execute_tool("list_tools", vec![]).unwrap_err();
+    assert_eq!(refused.to_string(), "The agent is halted (runaway loop); /resume to continue");
+    assert!(manager.create_tool("halt_test_other", "fn halt_test_other() { 1 }").is_err());
+    assert_eq!(manager.halt("again", false), "Already halted");
+
+    assert!(manager.resume());
+    assert!(!manager.resume());
+    manager.execute_tool("list_tools", vec![])?;
+    std::fs::remove_file("tools/halt_test_spin.rhai")?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_only_signed_halts_are_obeyed() -> Result<()> {
+    let state_path = std::env::temp_dir().join(format!("swarm_halt_{}.json", std::process::id()));
+    let store = StateStore::open(&state_path)?;
+    CloneRegistry::new(store.clone()).record("/tmp/halt_test_clone", &Lineage::root().child("/tmp"))?;
+    let halt = HaltSwitch::new();
+    let state = IpcState::new(Arc::new(Mutex::new(Vec::new())), PeerThreads::new(store), TaskQueue::new(), PeerRegistry::new(), PathBuf::from("tools"))
+        .with_auth(IpcAuth::new(Some("swarm-secret"), "receiver").with_allow_unsigned(true))
+        .with_halt(halt.clone());
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+    let url = format!("http://{}/message", listener.local_addr()?);
+    tokio::spawn(async move { axum::serve(listener, router(state)).await.unwrap() });
+
+    let client = reqwest::Client::new();
+    let halt_message = || -> Result<Message> {
+        Message::new(&IpcMessage::Halt { reason: "drill".to_string(), clones: true }, Some("127.0.0.1:9000".to_string()))
+    };
+    let unsigned: MessageResponse = client.post(&url).json(&halt_message()?).send().await?.json().await?;
+    assert_eq!(unsigned.received, "Halt refused: halts must be signed with the swarm key");
+    assert!(!halt.is_halted());
+
+    let mut signed = halt_message()?;
+    IpcAuth::new(Some("swarm-secret"), "operator").sign(&mut signed);
+    let reply: MessageResponse = client.post(&url).json(&signed).send().await?.json().await?;
+    assert_eq!(reply.received, "Halted: drill\n/tmp/halt_test_clone: no known address, not halted");
+    assert_eq!(halt.reason().as_deref(), Some("drill (halted by operator)"));
+
+    let _ = std::fs::remove_file(&state_path);
+    Ok(())
+}
@@ -0,0 +1,77 @@
+use anyhow::Result;
+use swarm_thing::agent::Agent;
+use swarm_thing::headless::{exit_code, read_tasks, Runner, EXIT_FAILED, EXIT_OK};
+use swarm_thing::llm::{Completion, LlmClient, Usage};
+use swarm_thing::replay::RecordedResponses;
+use swarm_thing::tools::ToolManager;
+
+fn said(text: &str) -> Result<Completion, String> {
+    Ok(Completion { text: text.to_string(), usage: Usage { input_tokens: 10, output_tokens: 2 }, model: "mock".to_string(), cached: false })
+}
+
+async fn scripted(responses: Vec<Result<Completion, String>>) -> Result<Runner> {
+    let llm = LlmClient::replaying(RecordedResponses::new(responses)).await?;
+    Ok(Runner::new(Agent::with_client(llm, "You use tools."), ToolManager::new()?))
+}
+
+#[tokio::test]
+async fn test_run_feeds_tool_output_back() -> Result<()> {
+    let mut runner = scripted(vec![said("[TOOL: square(4)]"), said("4 squared is 16.")]).await?;
+    let outcome = runner.run("what is 4 squared?").await;
+    assert!(outcome.ok, "{}", outcome.render());
+    assert_eq!(outcome.answer, "4 squared is 16.");
+    assert_eq!(outcome.tools.len(), 1);
+    assert_eq!((outcome.tools[0].action.as_str(), outcome.tools[0].output.as_str()), ("ran", "16"));
+    assert_eq!(runner.agent().history()[2].content, "Tool Output: 16");
+
+    // A model that never stops calling tools, and one that fails
+    let mut runner = scripted(vec![said("[TOOL: square(2)]"), said("[TOOL: square(4)]")]).await?.with_max_steps(1);
+    let looping = runner.run("loop").await;
+    assert!(!looping.ok);
+    assert_eq!(looping.error.as_deref(), Some("Still calling tools after 1 steps"));
+    let failed = runner.run("again").await;
+    assert!(failed.render().contains("ran out of recorded responses"), "{}", failed.render());
+    assert_eq!(exit_code(&[outcome, looping]), EXIT_FAILED);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_batch_runs_each_task_fresh() -> Result<()> {
+    let path = std::env::temp_dir().join(format!("swarm_batch_{}.txt", std::process::id()));
+    std::fs::write(&path, "# squares\nsquare 2\n\n  square 3  \n")?;
+    let tasks = read_tasks(&path)?;
+    assert_eq!(tasks, vec!["square 2", "square 3"]);
+    assert!(read_tasks("missing_tasks.txt").unwrap_err().to_string().contains("Cannot read tasks file"));
+
+    let mut runner = scripted(vec![said("4"), said("9")]).await?;
+    let outcomes = runner.run_batch(&tasks).await;
+    assert_eq!(outcomes.iter().map(|o| o.answer.as_str()).collect::<Vec<_>>(), vec!["4", "9"]);
+    assert_eq!(runner.agent().history().len(), 2);
+    assert_eq!(exit_code(&outcomes), EXIT_OK);
+    let json = serde_json::to_value(&outcomes[1])?;
+    assert_eq!((json["prompt"].as_str(), json["ok"].as_bool()), (Some("square 3"), Some(true)));
+
+    std::fs::remove_file(&path)?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_run_executes_every_tool_call() -> Result<()> {
+    let mut runner = scripted(vec![
+        said("First [TOOL: square(3)], then [TOOL: no_such_tool(1)] and [TOOL: square(5)]"),
+        said("9 and 25."),
+    ]).await?;
+    let outcome = runner.run("squares of 3 and 5").await;
+    assert!(outcome.ok, "{}", outcome.render());
+    let ran: Vec<(&str, bool)> = outcome.tools.iter().map(|t| (t.name.as_str(), t.ok)).collect();
+    assert_eq!(ran, vec![("square", true), ("no_such_tool", false), ("square", true)]);
+
+    // Each result goes back to the model, the failure in its place
+    let feedback = &runner.agent().history()[2].content;
+    let lines: Vec<&str> = feedback.lines().collect();
+    assert_eq!(lines.len(), 3, "{}", feedback);
+    assert_eq!(lines[0], "Tool Output (square): 9");
+    assert!(lines[1].starts_with("Tool Error (no_such_tool): "), "{}", feedback);
+    assert_eq!(lines[2], "Tool Output (square): 25");
+    Ok(())
+}
@@ -0,0 +1,85 @@
+use anyhow::Result;
+use std::time::Duration;
+use swarm_thing::registry::{heartbeat_loop, HeartbeatConfig, PeerInfo, PeerRegistry, PeerStatus};
+use swarm_thing::tools::ToolManager;
+
+fn peer(name: &str, address: &str, last_seen: u64) -> PeerInfo {
+    PeerInfo {
+        name: name.to_string(),
+        address: address.to_string(),
+        capabilities: Vec::new(),
+        tools: Vec::new(),
+        last_seen,
+        missed_heartbeats: 0,
+        public_key: None,
+    }
+}
+
+#[test]
+fn test_peer_liveness() {
+    let registry = PeerRegistry::new();
+    registry.upsert(peer("fresh", "127.0.0.1:1", 0));
+    registry.merge(peer("stale", "127.0.0.1:2", 1));
+    let timeout = Duration::from_secs(30);
+
+    assert_eq!(registry.status("127.0.0.1:1", timeout), Some(PeerStatus::Alive));
+    assert_eq!(registry.status("http://127.0.0.1:2/message", timeout), Some(PeerStatus::Dead));
+    assert_eq!(registry.status("127.0.0.1:3", timeout), None);
+    assert_eq!(registry.alive(timeout).len(), 1);
+
+    registry.record_missed_heartbeat("127.0.0.1:2");
+    assert!(registry.render_status(timeout).contains("stale @ 127.0.0.1:2: dead"));
+    assert!(registry.render_status(timeout).contains("1 missed heartbeats"));
+
+    assert!(registry.touch("127.0.0.1:2"));
+    assert!(!registry.touch("127.0.0.1:3"));
+    assert_eq!(registry.get("127.0.0.1:2").unwrap().missed_heartbeats, 0);
+    assert_eq!(registry.status("127.0.0.1:2", timeout), Some(PeerStatus::Alive));
+}
+
+#[tokio::test]
+async fn test_heartbeats_track_liveness() -> Result<()> {
+    let worker = ToolManager::new()?;
+    worker.execute_tool("start_server", vec!["9861".to_string()])?;
+    tokio::time::sleep(Duration::from_millis(400)).await;
+    // The worker knows us from an old announcement; our heartbeats refresh it
+    worker.peers().merge(peer("boss", "127.0.0.1:9860", 1));
+
+    let registry = PeerRegistry::new();
+    registry.merge(peer("worker", "127.0.0.1:9861", 1));
+    registry.merge(peer("gone", "127.0.0.1:9869", 1));
+    let config = HeartbeatConfig { interval: Duration::from_millis(100), timeout: Duration::from_secs(2) };
+    let handle = tokio::spawn(heartbeat_loop("127.0.0.1:9860".to_string(), registry.clone(), config));
+    tokio::time::sleep(Duration::from_millis(350)).await;
+    handle.abort();
+
+    assert_eq!(registry.status("127.0.0.1:9861", config.timeout), Some(PeerStatus::Alive));
+    assert_eq!(registry.status("127.0.0.1:9869", config.timeout), Some(PeerStatus::Dead));
+    assert!(registry.get("127.0.0.1:9869").unwrap().missed_heartbeats >= 2);
+    assert_eq!(worker.peers().status("127.0.0.1:9860", config.timeout), Some(PeerStatus::Alive));
+    Ok(())
+}
+
+#[test]
+fn test_peer_status_tool_and_dead_peer_delegation() -> Result<()> {
+    let mut manager = ToolManager::new()?;
+    assert_eq!(manager.execute_tool("peer_status", vec![])?, "No peers known yet");
+
+    manager.peers().upsert(peer("alive", "127.0.0.1:9871", 0));
+    manager.peers().merge(peer("dead", "127.0.0.1:9872", 1));
+    let status = manager.execute_tool("peer_status", vec![])?;
+    assert!(status.contains("alive @ 127.0.0.1:9871: alive"), "{}", status);
+    assert!(status.contains("dead @ 127.0.0.1:9872: dead"), "{}", status);
+    assert_eq!(manager.execute_tool("peer_status", vec!["127.0.0.1:9872".to_string()])?, "127.0.0.1:9872 is dead");
+
+    manager.create_tool("heartbeat_test_delegate", r#"
+    fn heartbeat_test_delegate(url) {
+        return delegate_task(url, "anything");
+    }
+    "#)?;
+    let refused = manager.execute_tool("heartbeat_test_delegate", vec!["http://127.0.0.1:9872/message".to_string()])?;
+    assert!(refused.contains("not responding to heartbeats"), "{}", refused);
+
+    std::fs::remove_file("tools/heartbeat_test_delegate.rhai")?;
+    Ok(())
+}
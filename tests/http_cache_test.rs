@@ -0,0 +1,83 @@
+use anyhow::Result;
+use axum::http::{HeaderMap, StatusCode};
+use axum::routing::get;
+use axum::Router;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use swarm_thing::http_cache::HttpCache;
+use swarm_thing::politeness::Politeness;
+
+/// The `If-None-Match` header of each request the site got
+type Requests = Arc<Mutex<Vec<Option<String>>>>;
+
+/// A site whose `/page` has ETag `"v1"` and whose `/missing` is a 404
+async fn serve() -> Result<(String, Requests)> {
+    let requests: Requests = Arc::new(Mutex::new(Vec::new()));
+    let seen = requests.clone();
+    let app = Router::new()
+        .route("/page", get(move |headers: HeaderMap| async move {
+            let etag = headers.get("if-none-match").and_then(|v| v.to_str().ok()).map(String::from);
+            seen.lock().unwrap().push(etag.clone());
+            if etag.as_deref() == Some("\"v1\"") {
+                (StatusCode::NOT_MODIFIED, [("etag", "\"v1\"")], String::new())
+            } else {
+                (StatusCode::OK, [("etag", "\"v1\"")], "<html><body>Hello</body></html>".to_string())
+            }
+        }))
+        .route("/missing", get(|| async { (StatusCode::NOT_FOUND, "gone") }));
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+    let address = listener.local_addr()?.to_string();
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+    Ok((format!("http://{}", address), requests))
+}
+
+fn cache_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("swarm_http_cache_{}_{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    dir
+}
+
+#[tokio::test]
+async fn test_fresh_pages_are_served_from_disk() -> Result<()> {
+    let (site, requests) = serve().await?;
+    let dir = cache_dir("fresh");
+    let cache = HttpCache::open(&dir, Some(Duration::from_secs(3600)))?;
+    let politeness = Politeness::new();
+    let url = format!("{}/page", site);
+
+    assert!(cache.fetch(&politeness, &url, false).await?.contains("Hello"));
+    assert!(cache.fetch(&politeness, &url, false).await?.contains("Hello"));
+    assert_eq!(requests.lock().unwrap().len(), 1);
+    assert_eq!(cache.get(&url).unwrap().etag.as_deref(), Some("\"v1\""));
+
+    // A forced refresh downloads the page in full
+    assert!(cache.fetch(&politeness, &url, true).await?.contains("Hello"));
+    assert_eq!(*requests.lock().unwrap(), vec![None, None]);
+
+    // Error pages are returned but not kept
+    assert_eq!(cache.fetch(&politeness, &format!("{}/missing", site), false).await?, "gone");
+    assert!(cache.get(&format!("{}/missing", site)).is_none());
+    assert_eq!(cache.clear()?, 1);
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_stale_pages_are_revalidated() -> Result<()> {
+    let (site, requests) = serve().await?;
+    let dir = cache_dir("stale");
+    // Nothing stays fresh: every fetch asks the site
+    let cache = HttpCache::open(&dir, Some(Duration::ZERO))?;
+    let politeness = Politeness::new();
+    let url = format!("{}/page", site);
+
+    cache.fetch(&politeness, &url, false).await?;
+    let fetched_at = cache.get(&url).unwrap().fetched_at;
+    // The 304 has no body; the cached one is returned
+    assert!(cache.fetch(&politeness, &url, false).await?.contains("Hello"));
+    assert_eq!(*requests.lock().unwrap(), vec![None, Some("\"v1\"".to_string())]);
+    assert!(cache.get(&url).unwrap().fetched_at >= fetched_at);
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}
@@ -0,0 +1,56 @@
+use anyhow::Result;
+use std::time::Duration;
+use swarm_thing::inbox::Inbox;
+use swarm_thing::tools::ToolManager;
+
+#[test]
+fn test_inbox_read_state() {
+    let inbox = Inbox::new();
+    let first = inbox.push(Some("127.0.0.1:9000".to_string()), None, "hello");
+    let second = inbox.push(Some("127.0.0.1:9001".to_string()), Some("coder".to_string()), "x".repeat(100));
+    assert_eq!(inbox.unread_count(), 2);
+    assert_eq!(inbox.get(second).unwrap().sender(), "coder");
+
+    let rendered = inbox.render_unread();
+    assert!(rendered.contains("#1 from 127.0.0.1:9000: hello"));
+    assert!(rendered.contains("#2 from coder:") && rendered.ends_with("..."));
+
+    assert!(inbox.mark_read(first));
+    assert!(!inbox.mark_read(99));
+    assert_eq!(inbox.unread().iter().map(|m| m.id).collect::<Vec<_>>(), vec![second]);
+    assert_eq!(inbox.list().len(), 2);
+}
+
+#[tokio::test]
+async fn test_inbox_natives() -> Result<()> {
+    let mut receiver = ToolManager::new()?;
+    receiver.execute_tool("start_server", vec!["9883".to_string()])?;
+    tokio::time::sleep(Duration::from_millis(500)).await;
+
+    let mut sender = ToolManager::new()?;
+    sender.create_tool("test_inbox_send", r#"
+    fn test_inbox_send(text) {
+        return send_message("http://127.0.0.1:9883/message", text);
+    }
+    "#)?;
+    sender.execute_tool("test_inbox_send", vec!["Can you review PR 7?".to_string()])?;
+
+    receiver.create_tool("test_inbox_check", "fn test_inbox_check(x) { return check_inbox(); }")?;
+    receiver.create_tool("test_inbox_read", "fn test_inbox_read(id) { return read_message(id); }")?;
+    receiver.create_tool("test_inbox_mark", "fn test_inbox_mark(id) { return mark_read(id); }")?;
+
+    let unread = receiver.execute_tool("test_inbox_check", vec![String::new()])?;
+    assert!(unread.starts_with("1 unread message(s)"), "{}", unread);
+    let id = receiver.inbox().unread()[0].id.to_string();
+    assert!(receiver.execute_tool("test_inbox_read", vec![id.clone()])?.contains("Can you review PR 7?"));
+    // Reading doesn't mark the message; that's explicit
+    assert_eq!(receiver.inbox().unread_count(), 1);
+    assert!(receiver.execute_tool("test_inbox_mark", vec![id])?.contains("marked as read"));
+    assert_eq!(receiver.execute_tool("test_inbox_check", vec![String::new()])?, "No unread messages");
+    assert!(receiver.execute_tool("test_inbox_read", vec!["abc".to_string()])?.starts_with("Error"));
+
+    for tool in ["test_inbox_send", "test_inbox_check", "test_inbox_read", "test_inbox_mark"] {
+        std::fs::remove_file(format!("tools/{}.rhai", tool))?;
+    }
+    Ok(())
+}
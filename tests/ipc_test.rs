@@ -1,5 +1,5 @@
 use anyhow::Result;
-use rust_research_agent::tools::ToolManager;
+use swarm_thing::tools::ToolManager;
 use std::time::Duration;
 
 #[tokio::test]
@@ -0,0 +1,59 @@
+use anyhow::Result;
+use swarm_thing::jail::FsJail;
+use swarm_thing::tools::ToolManager;
+
+#[test]
+fn test_jail_path_resolution() -> Result<()> {
+    let root = "/tmp/swarm_thing_jail_test";
+    let jail = FsJail::new(root)?;
+    
+    // Paths inside the jail resolve, even if they don't exist yet
+    let inside = jail.resolve("notes/today.txt")?;
+    assert!(inside.starts_with(jail.root()));
+    assert!(jail.resolve("./a/../b.txt")?.ends_with("b.txt"));
+    
+    // Paths escaping the jail are rejected
+    assert!(jail.resolve("../outside.txt").is_err());
+    assert!(jail.resolve("notes/../../outside.txt").is_err());
+    assert!(jail.resolve("/etc/passwd").is_err());
+    
+    // Symlinks pointing outside the jail are rejected
+    #[cfg(unix)]
+    {
+        let link = jail.root().join("escape");
+        let _ = std::fs::remove_file(&link);
+        std::os::unix::fs::symlink("/etc", &link)?;
+        assert!(jail.resolve("escape/passwd").is_err());
+
+        // So are dangling ones, which a write would follow to create their target
+        let dangling = jail.root().join("dangling");
+        let _ = std::fs::remove_file(&dangling);
+        std::os::unix::fs::symlink("/tmp/swarm_thing_jail_test_outside.txt", &dangling)?;
+        assert!(jail.resolve("dangling").is_err());
+        assert!(jail.resolve("dangling/child.txt").is_err());
+    }
+    
+    std::fs::remove_dir_all(root)?;
+    Ok(())
+}
+
+#[test]
+fn test_file_tools_honor_jail() -> Result<()> {
+    let manager = ToolManager::new()?;
+    
+    let result = manager.execute_tool("read_file", vec!["/etc/passwd".to_string()])?;
+    println!("read_file result: {}", result);
+    assert!(result.contains("outside the workspace"));
+    
+    let result = manager.execute_tool("list_dir", vec!["../".to_string()])?;
+    assert!(result.contains("outside the workspace"));
+    
+    let result = manager.execute_tool("delete_file", vec!["/tmp/../etc/hosts".to_string()])?;
+    assert!(result.contains("outside the workspace"));
+    
+    // Listing inside the workspace works
+    let result = manager.execute_tool("list_dir", vec!["tools".to_string()])?;
+    assert!(result.contains("magic_math.rhai"));
+
+    Ok(())
+}
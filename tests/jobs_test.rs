@@ -0,0 +1,102 @@
+use anyhow::Result;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use swarm_thing::jobs::{serve_jobs, JobStatus};
+use swarm_thing::message::ToolSafetyLevel;
+use swarm_thing::safety::SafetyPolicy;
+use swarm_thing::tools::{ConfirmPolicy, ToolManager};
+
+fn background(tools: &ToolManager, args: &[&str]) -> Result<String> {
+    tools.execute_tool("execute_tool_background", args.iter().map(|s| s.to_string()).collect())
+}
+
+#[tokio::test]
+async fn test_background_jobs_run_on_workers() -> Result<()> {
+    let mut tools = ToolManager::new()?;
+    tools.create_tool("test_jobs_slow", "fn test_jobs_slow(x) { parse_int(x) * 3 }")?;
+    assert_eq!(background(&tools, &["test_jobs_slow", "5"])?, "Started bg-1 in the background");
+    assert_eq!(background(&tools, &["test_jobs_missing"])?, "Started bg-2 in the background");
+
+    // Nothing runs without a worker
+    assert_eq!(tools.execute_tool("job_status", vec!["bg-1".to_string()])?, "bg-1 test_jobs_slow(5): queued");
+    assert_eq!(tools.execute_tool("job_result", vec!["bg-1".to_string()])?, "Error: Job bg-1 is still queued");
+
+    for _ in 0..2 {
+        let mut worker = ToolManager::new()?;
+        worker.set_jobs(tools.jobs().clone());
+        tokio::spawn(serve_jobs(tools.jobs().clone(), worker));
+    }
+    let mut finished = Vec::new();
+    while finished.len() < 2 {
+        finished.extend(tokio::time::timeout(Duration::from_secs(10), tools.jobs().finished()).await?);
+    }
+    finished.sort_by(|a, b| a.id.cmp(&b.id));
+    assert_eq!(finished.iter().map(|j| (j.id.as_str(), j.status)).collect::<Vec<_>>(), [("bg-1", JobStatus::Done), ("bg-2", JobStatus::Failed)]);
+    assert_eq!(tools.execute_tool("job_result", vec!["bg-1".to_string()])?, "15");
+    assert!(tools.execute_tool("job_result", vec!["bg-2".to_string()])?.starts_with("Error: Error executing tool 'test_jobs_missing'"));
+    assert!(tools.execute_tool("job_status", vec![])?.contains("bg-1 test_jobs_slow(5): done in "));
+    assert_eq!(tools.execute_tool("job_status", vec!["bg-9".to_string()])?, "Error: No background job 'bg-9'");
+    assert_eq!(tools.jobs().active(), 0);
+
+    tools.remove_tool("test_jobs_slow")?;
+    Ok(())
+}
+
+#[test]
+fn test_background_runs_are_confirmed_when_queued() -> Result<()> {
+    let mut tools = ToolManager::new()?;
+    tools.set_confirm_policy(ConfirmPolicy::default());
+    let asked = Arc::new(Mutex::new(Vec::new()));
+    let asked_clone = asked.clone();
+    tools.set_execution_approver(Arc::new(move |call: &str, level: &ToolSafetyLevel| {
+        asked_clone.lock().unwrap().push((call.to_string(), level.clone()));
+        false
+    }));
+
+    // The tool is confirmed, not execute_tool_background itself, and a refusal queues nothing
+    let err = background(&tools, &["read_file", "notes.txt"]).unwrap_err();
+    assert_eq!(err.to_string(), "The operator declined to run read_file(notes.txt)");
+    assert_eq!(*asked.lock().unwrap(), [("read_file(notes.txt)".to_string(), ToolSafetyLevel::MediumRisk)]);
+    assert!(tools.jobs().all().is_empty());
+
+    // Safe tools go straight to the queue
+    assert_eq!(background(&tools, &["square", "3"])?, "Started bg-1 in the background");
+    assert_eq!(asked.lock().unwrap().len(), 1);
+    assert!(background(&tools, &[]).unwrap_err().to_string().contains("needs the name of a tool"));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_workers_run_jobs_as_they_were_admitted() -> Result<()> {
+    let mut tools = ToolManager::new()?;
+    tools.set_confirm_policy(ConfirmPolicy::default());
+    let asked = Arc::new(Mutex::new(0));
+    let asked_clone = asked.clone();
+    tools.set_execution_approver(Arc::new(move |_: &str, _: &ToolSafetyLevel| {
+        *asked_clone.lock().unwrap() += 1;
+        true
+    }));
+    let mut policy = SafetyPolicy::default();
+    policy.rate_limits.insert("square".to_string(), 1);
+    tools.set_safety_policy(policy.clone());
+
+    // Queuing spends the one call a minute and asks the operator; the worker does neither again
+    assert_eq!(background(&tools, &["square", "6"])?, "Started bg-1 in the background");
+    assert_eq!(background(&tools, &["delete_file", "jobs_test_missing.txt"])?, "Started bg-2 in the background");
+    assert!(background(&tools, &["square", "7"]).unwrap_err().to_string().contains("rate limited"));
+    assert_eq!(*asked.lock().unwrap(), 1);
+
+    // A worker with its own minute already spent, and no approver, still runs them
+    let worker = ToolManager::new()?;
+    worker.set_safety_policy(policy);
+    worker.execute_tool("square", vec!["1".to_string()])?;
+    tokio::spawn(serve_jobs(tools.jobs().clone(), worker));
+    let mut finished = Vec::new();
+    while finished.len() < 2 {
+        finished.extend(tokio::time::timeout(Duration::from_secs(10), tools.jobs().finished()).await?);
+    }
+    assert_eq!(tools.execute_tool("job_result", vec!["bg-1".to_string()])?, "36");
+    let deleted = tools.execute_tool("job_result", vec!["bg-2".to_string()])?;
+    assert!(deleted.starts_with("Error deleting file"), "{}", deleted);
+    Ok(())
+}
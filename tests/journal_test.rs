@@ -0,0 +1,70 @@
+use anyhow::Result;
+use axum::{routing::post, Json, Router};
+use swarm_thing::agent::Agent;
+use swarm_thing::journal::{sessions, Journal, JournalEvent, Replay};
+use swarm_thing::llm::LlmClient;
+
+// Ollama-compatible endpoint that asks for a tool
+async fn mock_chat(Json(_body): Json<serde_json::Value>) -> Json<serde_json::Value> {
+    Json(serde_json::json!({
+        "message": { "role": "assistant", "content": "[TOOL: square(4)]" },
+        "prompt_eval_count": 40,
+        "eval_count": 8
+    }))
+}
+
+#[test]
+fn test_journal_records_and_replays() -> Result<()> {
+    let dir = std::env::temp_dir().join(format!("swarm_journal_{}", std::process::id()));
+    let journal = Journal::open(&dir)?;
+    journal.start_turn();
+    journal.record(JournalEvent::Input { text: "square 4".to_string() })?;
+    journal.record(JournalEvent::ToolCall { name: "square".to_string(), args: vec!["4".to_string()] })?;
+    journal.record(JournalEvent::ToolResult { name: "square".to_string(), output: "16".to_string(), ok: true, duration_ms: 3 })?;
+    journal.start_turn();
+    journal.record(JournalEvent::Input { text: "thanks".to_string() })?;
+
+    assert_eq!(sessions(&dir)?, vec![journal.session().to_string()]);
+    let replay = Replay::find(&dir, "latest")?;
+    assert_eq!(replay.session, journal.session());
+    assert_eq!((replay.entries.len(), replay.turns()), (4, 2));
+    assert_eq!(replay.entries.iter().map(|e| e.seq).collect::<Vec<_>>(), vec![1, 2, 3, 4]);
+    assert_eq!(replay.turn(1).len(), 3);
+    let runs = replay.tool_runs();
+    assert_eq!(runs.len(), 1);
+    assert!(matches!(&runs[0].1.unwrap().event, JournalEvent::ToolResult { output, .. } if output == "16"));
+    assert_eq!(replay.render(Some(2)).lines().count(), 1);
+    assert!(replay.render(None).contains("tool square ok in 3ms: 16"), "{}", replay.render(None));
+
+    std::fs::write(journal.path(), "not json\n")?;
+    assert!(Replay::load(journal.path()).unwrap_err().to_string().contains("Invalid journal line 1"));
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_agent_journals_llm_requests() -> Result<()> {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+    let url = format!("http://{}/api/chat", listener.local_addr()?);
+    tokio::spawn(async move {
+        let app = Router::new().route("/api/chat", post(mock_chat));
+        axum::serve(listener, app).await.unwrap();
+    });
+
+    let dir = std::env::temp_dir().join(format!("swarm_journal_agent_{}", std::process::id()));
+    let client = LlmClient::with_model("ollama", None).await?.with_ollama_url(&url);
+    let mut agent = Agent::with_client(client, "sys");
+    agent.set_journal(Some(Journal::open(&dir)?));
+    agent.chat("what is 4 squared?").await?;
+
+    let replay = Replay::load(agent.journal().unwrap().path())?;
+    let kinds: Vec<&JournalEvent> = replay.entries.iter().map(|e| &e.event).collect();
+    assert!(matches!(kinds[0], JournalEvent::Input { text } if text == "what is 4 squared?"));
+    let JournalEvent::LlmRequest { hash, purpose, messages, .. } = kinds[1] else { panic!("{:?}", kinds[1]) };
+    assert_eq!((purpose.as_str(), *messages), ("chat", 1));
+    assert!(matches!(kinds[2], JournalEvent::LlmResponse { hash: h, text, input_tokens: 40, .. } if h == hash && text == "[TOOL: square(4)]"));
+    assert!(replay.entries.iter().all(|e| e.turn == 1));
+
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}
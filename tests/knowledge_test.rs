@@ -0,0 +1,90 @@
+use anyhow::Result;
+use axum::{routing::get, Router};
+use swarm_thing::embeddings::Embedder;
+use swarm_thing::jail::FsJail;
+use swarm_thing::knowledge::{chunk_text, html_to_text, KnowledgeBase};
+use swarm_thing::tools::ToolManager;
+
+fn temp_kb(name: &str) -> Result<KnowledgeBase> {
+    let path = std::env::temp_dir().join(format!("{}_{}.jsonl", name, std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    Ok(KnowledgeBase::open(path, Some(Embedder::hashing()))?.with_chunking(8, 2))
+}
+
+#[test]
+fn test_chunk_text_overlap() {
+    let text = (1..=10).map(|i| i.to_string()).collect::<Vec<_>>().join(" ");
+    assert_eq!(chunk_text(&text, 4, 1), vec!["1 2 3 4", "4 5 6 7", "7 8 9 10"]);
+    assert_eq!(chunk_text(&text, 20, 5), vec![text.clone()]);
+    assert!(chunk_text("   ", 4, 1).is_empty());
+    assert_eq!(html_to_text("<html><body><h1>Title</h1>\n<p>Some   text</p></body></html>"), "Title Some text");
+}
+
+#[tokio::test]
+async fn test_ingest_and_retrieve() -> Result<()> {
+    let kb = temp_kb("kb_test")?;
+    let rust = "Rust is a systems programming language focused on memory safety and ownership. The borrow checker enforces ownership rules.";
+    let coffee = "Espresso is brewed by forcing hot water through finely ground coffee beans under pressure.";
+    assert!(kb.ingest_text("rust.txt", rust).await? >= 2);
+    assert_eq!(kb.ingest_text("coffee.txt", coffee).await?, 2);
+
+    let passages = kb.retrieve("how does the borrow checker enforce ownership", 2).await?;
+    assert_eq!(passages.len(), 2);
+    assert_eq!(passages[0].source, "rust.txt");
+    assert!(passages[0].score >= passages[1].score);
+
+    // Re-ingesting a source replaces its chunks; the file persists across handles
+    kb.ingest_text("coffee.txt", "Coffee beans are roasted.").await?;
+    let reopened = KnowledgeBase::open(kb.path(), Some(Embedder::hashing()))?;
+    assert_eq!(reopened.sources()?, vec![("coffee.txt".to_string(), 1), ("rust.txt".to_string(), 3)]);
+    assert_eq!(reopened.remove_source("rust.txt")?, 3);
+    assert_eq!(reopened.retrieve("ownership", 5).await?[0].source, "coffee.txt");
+
+    std::fs::remove_file(kb.path())?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_ingest_url_and_jailed_file() -> Result<()> {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+    let url = format!("http://{}/page", listener.local_addr()?);
+    tokio::spawn(async move {
+        let app = Router::new().route("/page", get(|| async {
+            axum::response::Html("<html><body><p>Tokio is an asynchronous runtime for Rust.</p></body></html>")
+        }));
+        axum::serve(listener, app).await.unwrap();
+    });
+
+    let kb = temp_kb("kb_url_test")?;
+    let jail = FsJail::new(std::env::temp_dir())?;
+    assert_eq!(kb.ingest(&url, &jail).await?, 1);
+    let passages = kb.retrieve("asynchronous runtime", 1).await?;
+    assert_eq!(passages[0].text, "Tokio is an asynchronous runtime for Rust.");
+
+    assert!(kb.ingest("/etc/passwd", &jail).await.is_err());
+
+    std::fs::remove_file(kb.path())?;
+    Ok(())
+}
+
+#[test]
+fn test_knowledge_natives() -> Result<()> {
+    let mut manager = ToolManager::new()?;
+    std::fs::write("knowledge_test_notes.txt", "The quarterly report says revenue grew twelve percent in the northern region.")?;
+    manager.create_tool("test_knowledge", r#"
+    fn test_knowledge(query) {
+        let status = ingest_document("knowledge_test_notes.txt");
+        return status + "\n" + retrieve(query, 1);
+    }
+    "#)?;
+
+    let result = manager.execute_tool("test_knowledge", vec!["revenue growth in the north".to_string()])?;
+    assert!(result.starts_with("Ingested 1 chunks from knowledge_test_notes.txt"), "got {}", result);
+    assert!(result.contains("[1] knowledge_test_notes.txt #0"));
+    assert!(result.contains("revenue grew twelve percent"));
+
+    KnowledgeBase::from_env()?.remove_source("knowledge_test_notes.txt")?;
+    std::fs::remove_file("knowledge_test_notes.txt")?;
+    std::fs::remove_file("tools/test_knowledge.rhai")?;
+    Ok(())
+}
@@ -0,0 +1,97 @@
+use anyhow::Result;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use swarm_thing::auth::IpcAuth;
+use swarm_thing::ipc::{router, IpcState, Message, MessageResponse, TaskQueue};
+use swarm_thing::limits::{Limits, RateLimiter, ToolRateLimits};
+use swarm_thing::message::IpcMessage;
+use swarm_thing::registry::PeerRegistry;
+use swarm_thing::state::StateStore;
+use swarm_thing::threads::PeerThreads;
+
+async fn serve(name: &str, limits: Limits, auth: IpcAuth) -> Result<String> {
+    let state_path = std::env::temp_dir().join(format!("swarm_limits_{}_{}.json", name, std::process::id()));
+    let threads = PeerThreads::new(StateStore::open(&state_path)?);
+    let state = IpcState::new(Arc::new(Mutex::new(Vec::new())), threads, TaskQueue::new(), PeerRegistry::new(), PathBuf::from("tools"))
+        .with_limits(limits)
+        .with_auth(auth);
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+    let address = listener.local_addr()?.to_string();
+    tokio::spawn(async move {
+        axum::serve(listener, router(state).into_make_service_with_connect_info::<SocketAddr>()).await.unwrap()
+    });
+    Ok(format!("http://{}/message", address))
+}
+
+async fn post(url: &str, message: &Message) -> Result<(u16, MessageResponse)> {
+    let response = reqwest::Client::new().post(url).json(message).send().await?;
+    Ok((response.status().as_u16(), response.json().await?))
+}
+
+#[test]
+fn test_rate_limiter_buckets() {
+    let limiter = RateLimiter::per_minute(2);
+    assert!(limiter.check("a").is_ok());
+    assert!(limiter.check("a").is_ok());
+    let wait = limiter.check("a").unwrap_err();
+    assert!(wait.as_secs() > 20 && wait.as_secs() <= 30, "{:?}", wait);
+    // Each peer has its own budget
+    assert!(limiter.check("b").is_ok());
+    assert!(Limits::new(1024, 0).rate.is_none());
+}
+
+#[tokio::test]
+async fn test_server_rejects_floods_and_large_bodies() -> Result<()> {
+    let url = serve("flood", Limits::new(4096, 3), IpcAuth::new(None, "receiver")).await?;
+    let hello = Message::new(&IpcMessage::text("hello"), None)?;
+    for _ in 0..3 {
+        assert_eq!(post(&url, &hello).await?.0, 200);
+    }
+    let (status, body) = post(&url, &hello).await?;
+    assert_eq!(status, 429);
+    assert_eq!(body.status, "too many requests");
+    assert!(body.received.starts_with("rate limit exceeded for 127.0.0.1: retry in"), "{}", body.received);
+
+    let url = serve("large", Limits::new(4096, 0), IpcAuth::new(None, "receiver")).await?;
+    let (status, body) = post(&url, &Message::new(&IpcMessage::text("x".repeat(10_000)), None)?).await?;
+    assert_eq!(status, 413);
+    assert_eq!(body.received, "message larger than 4096 bytes");
+    assert_eq!(post(&url, &hello).await?.0, 200);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_signed_agents_are_limited_by_name() -> Result<()> {
+    let url = serve("agents", Limits::new(4096, 1), IpcAuth::new(Some("secret"), "receiver")).await?;
+    let signed = |agent: &str| -> Result<Message> {
+        let mut message = Message::new(&IpcMessage::text("hi"), None)?;
+        IpcAuth::new(Some("secret"), agent).sign(&mut message);
+        Ok(message)
+    };
+    // Two agents on the same host each get their own budget
+    assert_eq!(post(&url, &signed("alpha")?).await?.0, 200);
+    assert_eq!(post(&url, &signed("beta")?).await?.0, 200);
+    let (status, body) = post(&url, &signed("alpha")?).await?;
+    assert_eq!(status, 429);
+    assert!(body.received.contains("agent alpha"), "{}", body.received);
+    Ok(())
+}
+
+#[test]
+fn test_tool_rate_limits_follow_the_policy() {
+    let limits = ToolRateLimits::new();
+    assert!(limits.check("scrape_url", 1).is_ok());
+    let wait = limits.check("scrape_url", 1).unwrap_err();
+    assert!(wait.as_secs() >= 50 && wait.as_secs() <= 60, "{:?}", wait);
+    assert!(limits.check("send_message", 1).is_ok());
+    assert!(limits.check("scrape_url", 2).is_ok());
+
+    // A spent budget fails the lot without spending from the others
+    let limits = ToolRateLimits::new();
+    assert!(limits.check("get_secret", 1).is_ok());
+    let (tool, per_minute, _) = limits.check_all(&[("weather", 1), ("get_secret", 1)]).unwrap_err();
+    assert_eq!((tool, per_minute), ("get_secret", 1));
+    assert!(limits.check_all(&[("weather", 1)]).is_ok());
+    assert!(limits.check("weather", 1).is_err());
+}
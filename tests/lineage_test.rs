@@ -0,0 +1,59 @@
+use anyhow::Result;
+use std::path::Path;
+use swarm_thing::lineage::{CloneRegistry, Lineage};
+use swarm_thing::safety::{ClonePolicy, SafetyPolicy};
+use swarm_thing::state::StateStore;
+use swarm_thing::tools::ToolManager;
+
+#[test]
+fn test_lineage_and_registry() -> Result<()> {
+    let dir = std::env::temp_dir().join(format!("swarm_lineage_{}", std::process::id()));
+    std::fs::create_dir_all(&dir)?;
+    let child = Lineage::root().child("/home/agent");
+    let grandchild = child.child(&dir.display().to_string());
+    assert_eq!((child.generation, grandchild.generation), (1, 2));
+    assert_eq!(grandchild.ancestors, vec!["root".to_string(), child.id.clone()]);
+    grandchild.write(&dir)?;
+    assert_eq!(Lineage::load(&dir)?, grandchild);
+    assert_eq!(Lineage::load(Path::new("/nonexistent"))?, Lineage::root());
+
+    let state_path = std::env::temp_dir().join(format!("swarm_lineage_{}.json", std::process::id()));
+    let clones = CloneRegistry::new(StateStore::open(&state_path)?);
+    assert_eq!(clones.render(|_| "ok".to_string())?, "No clones");
+    clones.record(&dir.display().to_string(), &grandchild)?;
+    clones.set_address(&dir.display().to_string(), "127.0.0.1:9100")?;
+    clones.record("/tmp/swarm_lineage_gone", &child)?;
+    assert_eq!(clones.live_count()?, 1);
+    let rendered = clones.render(|_| "ok".to_string())?;
+    assert!(rendered.contains(&format!("({}, generation 2) at 127.0.0.1:9100: ok", grandchild.id)), "{}", rendered);
+    assert!(rendered.ends_with(&format!("/tmp/swarm_lineage_gone ({}, generation 1) [deleted]", child.id)), "{}", rendered);
+
+    let policy = SafetyPolicy { clone: ClonePolicy { max_depth: 2, max_count: 1, ..ClonePolicy::default() }, ..SafetyPolicy::default() };
+    assert!(policy.check_replication(2, 0).is_ok());
+    assert!(policy.check_replication(3, 0).unwrap_err().to_string().contains("max_depth of 2"));
+    assert!(policy.check_replication(1, 1).unwrap_err().to_string().contains("max_count"));
+
+    std::fs::remove_dir_all(&dir)?;
+    let _ = std::fs::remove_file(&state_path);
+    Ok(())
+}
+
+#[test]
+fn test_clone_agent_writes_lineage_and_respects_limits() -> Result<()> {
+    let manager = ToolManager::new()?;
+    let target = format!("/tmp/swarm_lineage_clone_{}", std::process::id());
+    let result = manager.execute_tool("clone_agent", vec![target.clone()])?;
+    assert!(result.contains("(generation 1)"), "{}", result);
+    let lineage = Lineage::load(Path::new(&target))?;
+    assert_eq!(lineage.parent_id.as_deref(), Some("root"));
+    assert!(manager.clones().list()?.iter().any(|c| c.dir == target && c.id == lineage.id));
+    assert!(manager.execute_tool("list_clones", vec![])?.contains(&lineage.id));
+
+    manager.set_safety_policy(SafetyPolicy { clone: ClonePolicy { max_depth: 0, ..ClonePolicy::default() }, ..SafetyPolicy::default() });
+    let refused = manager.execute_tool("clone_agent", vec![format!("{}_2", target)])?;
+    assert!(refused.starts_with("Error: a clone would be generation 1"), "{}", refused);
+    assert!(!Path::new(&format!("{}_2", target)).exists());
+
+    std::fs::remove_dir_all(&target)?;
+    Ok(())
+}
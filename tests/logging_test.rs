@@ -0,0 +1,73 @@
+use anyhow::Result;
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+use swarm_thing::config::{Config, LogConfig};
+use swarm_thing::logging::{subscriber, LogFormat};
+use swarm_thing::tools::ToolManager;
+
+// Collects what the subscriber writes
+#[derive(Clone, Default)]
+struct Captured(Arc<Mutex<Vec<u8>>>);
+
+impl Write for Captured {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Captured {
+    fn lines(&self) -> Vec<serde_json::Value> {
+        String::from_utf8(self.0.lock().unwrap().clone()).unwrap()
+            .lines().map(|line| serde_json::from_str(line).unwrap()).collect()
+    }
+}
+
+#[test]
+fn test_json_logs_carry_the_tool_span() -> Result<()> {
+    let captured = Captured::default();
+    let config = LogConfig { level: "debug".to_string(), format: "json".to_string() };
+    let writer = captured.clone();
+    let logger = subscriber(&config, move || writer.clone())?;
+
+    let tools = ToolManager::new()?;
+    let output = tracing::subscriber::with_default(logger, || tools.execute_tool("square", vec!["5".to_string()]))?;
+    assert_eq!(output, "25");
+
+    let lines = captured.lines();
+    let ran = lines.iter().find(|line| line["fields"]["message"] == "Tool ran").expect("no tool event");
+    assert_eq!(ran["level"], "DEBUG");
+    assert_eq!(ran["span"]["name"], "tool_call");
+    assert_eq!(ran["span"]["tool"], "square");
+    assert_eq!(ran["span"]["level"], "Safe");
+    assert_eq!(ran["spans"][0], ran["span"]);
+    assert!(ran["fields"]["elapsed_ms"].is_u64());
+
+    // At info the per-tool events are filtered out
+    let quiet = Captured::default();
+    let writer = quiet.clone();
+    let logger = subscriber(&LogConfig { level: "info".to_string(), format: "json".to_string() }, move || writer.clone())?;
+    tracing::subscriber::with_default(logger, || tools.execute_tool("square", vec!["6".to_string()]))?;
+    assert!(quiet.lines().is_empty());
+    Ok(())
+}
+
+#[test]
+fn test_log_settings_are_checked() -> Result<()> {
+    assert_eq!("JSON".parse::<LogFormat>()?, LogFormat::Json);
+    assert_eq!(LogConfig::default().format.parse::<LogFormat>()?, LogFormat::Text);
+    assert!("xml".parse::<LogFormat>().is_err());
+
+    let mut config = Config::default();
+    config.log.format = "xml".to_string();
+    config.log.level = "swarm_thing=loud".to_string();
+    let problems = config.validate();
+    assert!(problems.iter().any(|p| p.starts_with("log.format: expected text or json")), "{:?}", problems);
+    assert!(problems.iter().any(|p| p.starts_with("log.level:")), "{:?}", problems);
+    assert!(subscriber(&config.log, std::io::sink).is_err());
+    Ok(())
+}
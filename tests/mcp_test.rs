@@ -0,0 +1,144 @@
+use anyhow::Result;
+use serde_json::{json, Value};
+use swarm_thing::config::{Config, McpServerConfig};
+use swarm_thing::mcp::{McpClient, McpServer};
+use swarm_thing::message::ToolSafetyLevel;
+use swarm_thing::persona::ToolPolicy;
+use swarm_thing::tools::{InvokePolicy, ResponseAction, ToolManager};
+
+fn request(id: u64, method: &str, params: Value) -> String {
+    json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params }).to_string()
+}
+
+fn tool_names(list: &Value) -> Vec<String> {
+    list["result"]["tools"].as_array().unwrap().iter().map(|t| t["name"].as_str().unwrap().to_string()).collect()
+}
+
+#[tokio::test]
+async fn test_mcp_lists_and_calls_tools() -> Result<()> {
+    let mut tools = ToolManager::new()?;
+    tools.create_tool("test_mcp_greet", "// Greet someone by name\nfn test_mcp_greet(name) { \"Hello, \" + name }")?;
+    tools.create_tool("test_mcp_wipe", "fn test_mcp_wipe(path) { delete_file(path) }")?;
+    let server = McpServer::new(tools).with_natives(vec!["read_file".to_string(), "write_file".to_string()]);
+
+    let init = server.handle(&request(1, "initialize", json!({ "protocolVersion": "2025-03-26" }))).await.unwrap();
+    assert_eq!(init["result"]["protocolVersion"], "2025-03-26");
+    assert!(server.handle(r#"{"jsonrpc":"2.0","method":"notifications/initialized"}"#).await.is_none());
+
+    // Safe tools only by default: HighRisk tools and risky natives stay hidden
+    let list = server.handle(&request(2, "tools/list", json!({}))).await.unwrap();
+    let names = tool_names(&list);
+    assert!(names.contains(&"test_mcp_greet".to_string()) && names.contains(&"square".to_string()), "{:?}", names);
+    assert!(!names.contains(&"test_mcp_wipe".to_string()) && !names.contains(&"write_file".to_string()), "{:?}", names);
+    let greet = list["result"]["tools"].as_array().unwrap().iter().find(|t| t["name"] == "test_mcp_greet").unwrap();
+    assert_eq!(greet["description"], "Greet someone by name");
+    assert_eq!(greet["inputSchema"]["required"], json!(["name"]));
+
+    let called = server.handle(&request(3, "tools/call", json!({ "name": "test_mcp_greet", "arguments": { "name": "Ada" } }))).await.unwrap();
+    assert_eq!(called["result"], json!({ "content": [{ "type": "text", "text": "Hello, Ada" }], "isError": false }));
+    let hidden = server.handle(&request(4, "tools/call", json!({ "name": "test_mcp_wipe", "arguments": { "path": "x" } }))).await.unwrap();
+    assert_eq!(hidden["error"]["code"], -32602);
+    let unknown = server.handle(&request(5, "resources/list", json!({}))).await.unwrap();
+    assert_eq!(unknown["error"]["code"], -32601);
+    assert_eq!(server.handle("not json").await.unwrap()["error"]["code"], -32700);
+
+    // A wider policy exposes more, and failures come back as tool errors
+    let mut tools = ToolManager::new()?;
+    tools.create_tool("test_mcp_fail", "fn test_mcp_fail(x) { throw \"broken: \" + x }")?;
+    let server = McpServer::new(tools)
+        .with_policy(InvokePolicy { max_risk: ToolSafetyLevel::MediumRisk, deny: vec!["square".to_string()] })
+        .with_natives(vec!["read_file".to_string()]);
+    let names = tool_names(&server.handle(&request(6, "tools/list", json!({}))).await.unwrap());
+    assert!(names.contains(&"read_file".to_string()) && !names.contains(&"square".to_string()), "{:?}", names);
+    let failed = server.handle(&request(7, "tools/call", json!({ "name": "test_mcp_fail", "arguments": { "x": 1 } }))).await.unwrap();
+    assert_eq!(failed["result"]["isError"], true);
+    assert!(failed["result"]["content"][0]["text"].as_str().unwrap().contains("broken: 1"), "{}", failed);
+
+    for name in ["test_mcp_greet", "test_mcp_wipe", "test_mcp_fail"] {
+        std::fs::remove_file(format!("tools/{}.rhai", name))?;
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_mcp_over_sse() -> Result<()> {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+    let base = format!("http://{}", listener.local_addr()?);
+    let router = McpServer::new(ToolManager::new()?).router();
+    tokio::spawn(async move { axum::serve(listener, router).await.unwrap() });
+
+    let client = reqwest::Client::new();
+    let mut events = client.get(format!("{}/sse", base)).send().await?;
+    let mut received = String::new();
+    let mut next_event = async || -> Result<String> {
+        while !received.contains("\n\n") {
+            let chunk = events.chunk().await?.expect("stream ended");
+            received.push_str(&String::from_utf8_lossy(&chunk));
+        }
+        let (event, rest) = received.split_once("\n\n").unwrap();
+        let event = event.to_string();
+        received = rest.to_string();
+        Ok(event)
+    };
+
+    let endpoint = next_event().await?;
+    assert!(endpoint.starts_with("event: endpoint"), "{}", endpoint);
+    let path = endpoint.lines().find_map(|l| l.strip_prefix("data: ")).unwrap().to_string();
+    let posted = client.post(format!("{}{}", base, path))
+        .body(request(1, "tools/call", json!({ "name": "square", "arguments": { "x": "9" } })))
+        .send().await?;
+    assert_eq!(posted.status(), reqwest::StatusCode::ACCEPTED);
+    let answer = next_event().await?;
+    let data: Value = serde_json::from_str(answer.lines().find_map(|l| l.strip_prefix("data: ")).unwrap())?;
+    assert_eq!(data["id"], 1);
+    assert_eq!(data["result"]["content"][0]["text"], "81");
+
+    let stale = client.post(format!("{}/messages?sessionId=nope", base)).body(request(2, "ping", json!({}))).send().await?;
+    assert_eq!(stale.status(), reqwest::StatusCode::NOT_FOUND);
+    Ok(())
+}
+
+/// This binary's own `mcp` mode, as an external server
+fn own_server() -> McpServerConfig {
+    McpServerConfig { command: env!("CARGO_BIN_EXE_swarm-thing").to_string(), args: vec!["mcp".to_string()], ..Default::default() }
+}
+
+#[test]
+fn test_mcp_client_lists_and_calls() -> Result<()> {
+    let client = McpClient::connect("self", &own_server())?;
+    let square = client.tools().iter().find(|t| t.name == "square").expect("square is listed").clone();
+    assert_eq!(square.qualified_name(), "mcp::self::square");
+    assert_eq!(square.signature(), "mcp::self::square(x)");
+    assert_eq!(square.arguments(&["7".to_string()]), json!({ "x": "7" }));
+    assert_eq!(square.arguments(&[r#"{"x": "8"}"#.to_string()]), json!({ "x": "8" }));
+    assert_eq!(client.call("square", json!({ "x": "7" }))?, "49");
+    let err = client.call("no_such_tool", json!({})).unwrap_err();
+    assert!(err.to_string().contains("Unknown tool"), "{}", err);
+
+    let missing = McpServerConfig { command: "/nonexistent/mcp-server".to_string(), ..Default::default() };
+    let err = McpClient::connect("gone", &missing).err().expect("the command does not exist");
+    assert!(err.to_string().contains("Cannot start MCP server 'gone'"), "{}", err);
+    Ok(())
+}
+
+#[test]
+fn test_mcp_tools_route_through_tool_manager() -> Result<()> {
+    let mut config = Config::default();
+    config.mcp.servers.insert("self".to_string(), own_server());
+    let mut tools = ToolManager::with_config(&config)?;
+    assert!(tools.mcp_tools().iter().any(|t| t.qualified_name() == "mcp::self::square"));
+    assert!(tools.has_tool("mcp::self::square"));
+    assert!(!tools.has_tool("mcp::self::nope"));
+
+    assert_eq!(tools.execute_tool("mcp::self::square", vec!["6".to_string()])?, "36");
+    let actions = tools.act_on("[TOOL: mcp::self::square(5)]", None);
+    assert!(matches!(&actions[..], [ResponseAction::Ran { result: Ok(output), .. }] if output == "25"), "{:?}", actions);
+    let err = tools.execute_tool("mcp::other::square", vec![]).unwrap_err();
+    assert!(err.to_string().contains("No MCP server 'other'"), "{}", err);
+
+    // External tools count as MediumRisk for personas
+    tools.set_policy(ToolPolicy { capabilities: None, max_risk: ToolSafetyLevel::LowRisk });
+    let err = tools.execute_tool("mcp::self::square", vec!["2".to_string()]).unwrap_err();
+    assert!(err.to_string().contains("exceeds the LowRisk limit"), "{}", err);
+    Ok(())
+}
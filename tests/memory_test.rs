@@ -0,0 +1,49 @@
+use anyhow::Result;
+use swarm_thing::memory::MemoryStore;
+use swarm_thing::state::StateStore;
+use swarm_thing::tools::ToolManager;
+
+#[test]
+fn test_memory_store() -> Result<()> {
+    let path = "/tmp/swarm_thing_memory_test/state.json";
+    let _ = std::fs::remove_file(path);
+    let memory = MemoryStore::new(StateStore::open(path)?);
+    
+    memory.set("paper:rust", "Rust ownership paper")?;
+    memory.set("paper:rhai", "Rhai embedding notes")?;
+    memory.set("person:alice", "Reviewer")?;
+    memory.set("paper:rust", "Rust ownership paper (2nd ed.)")?;
+    
+    // Survives reopening
+    let memory = MemoryStore::new(StateStore::open(path)?);
+    assert_eq!(memory.get("paper:rust")?.as_deref(), Some("Rust ownership paper (2nd ed.)"));
+    assert_eq!(memory.get("missing")?, None);
+    
+    let papers = memory.search("paper:")?;
+    assert_eq!(papers.len(), 2);
+    assert_eq!(papers[0].0, "paper:rhai");
+    
+    std::fs::remove_dir_all("/tmp/swarm_thing_memory_test")?;
+    Ok(())
+}
+
+#[test]
+fn test_memory_tools() -> Result<()> {
+    let mut manager = ToolManager::new()?;
+    
+    manager.create_tool("test_memory_roundtrip", r#"
+    fn test_memory_roundtrip(value) {
+        memory_set("memory_test:value", value);
+        let found = memory_search("memory_test:");
+        return memory_get("memory_test:value") + "/" + found.len();
+    }
+    "#)?;
+    let result = manager.execute_tool("test_memory_roundtrip", vec!["remember me".to_string()])?;
+    assert_eq!(result, "remember me/1");
+    
+    let missing = manager.execute_tool("memory_get", vec!["memory_test:missing".to_string()])?;
+    assert_eq!(missing, "");
+    
+    std::fs::remove_file("tools/test_memory_roundtrip.rhai")?;
+    Ok(())
+}
@@ -0,0 +1,83 @@
+use anyhow::Result;
+use axum::extract::Query;
+use axum::http::HeaderMap;
+use axum::{routing::post, Json, Router};
+use std::collections::HashMap;
+use swarm_thing::llm::{LlmClient, Message, OpenAiConfig};
+use swarm_thing::provider::providers;
+
+// Echoes back how the request was authenticated so the test can check it
+async fn mock_completions(
+    headers: HeaderMap,
+    Query(query): Query<HashMap<String, String>>,
+    Json(body): Json<serde_json::Value>,
+) -> Json<serde_json::Value> {
+    let header = |name: &str| headers.get(name).and_then(|v| v.to_str().ok()).unwrap_or("-").to_string();
+    let content = format!(
+        "model={} auth={} api-key={} org={} version={} system={}",
+        body["model"].as_str().unwrap_or("-"),
+        header("authorization"),
+        header("api-key"),
+        header("openai-organization"),
+        query.get("api-version").map(String::as_str).unwrap_or("-"),
+        body["messages"][0]["role"].as_str().unwrap_or("-"),
+    );
+    Json(serde_json::json!({
+        "choices": [ { "message": { "role": "assistant", "content": content } } ]
+    }))
+}
+
+async fn start_mock() -> Result<String> {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+    let base = format!("http://{}", listener.local_addr()?);
+    tokio::spawn(async move {
+        let app = Router::new()
+            .route("/v1/chat/completions", post(mock_completions))
+            .route("/openai/deployments/gpt4/chat/completions", post(mock_completions));
+        axum::serve(listener, app).await.unwrap();
+    });
+    Ok(base)
+}
+
+fn hello() -> Vec<Message> {
+    vec![Message::user("hello")]
+}
+
+#[tokio::test]
+async fn test_openai_provider() -> Result<()> {
+    let base = start_mock().await?;
+    
+    assert!(providers().contains("Azure"));
+    
+    let client = LlmClient::with_model("openai", Some("gpt-test".to_string())).await?
+        .with_openai_config(OpenAiConfig {
+            base_url: format!("{}/v1", base),
+            api_key: Some("sk-test".to_string()),
+            organization: Some("org-1".to_string()),
+            api_version: None,
+        });
+    assert_eq!(client.label(), "openai:gpt-test");
+    
+    let reply = client.chat(hello(), Some("be brief".to_string())).await?;
+    assert_eq!(reply, "model=gpt-test auth=Bearer sk-test api-key=- org=org-1 version=- system=system");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_azure_openai_provider() -> Result<()> {
+    let base = start_mock().await?;
+    
+    let client = LlmClient::with_model("openai", Some("gpt4".to_string())).await?
+        .with_openai_config(OpenAiConfig {
+            base_url: format!("{}/openai/deployments/gpt4/", base),
+            api_key: Some("azure-key".to_string()),
+            organization: None,
+            api_version: Some("2024-02-01".to_string()),
+        });
+    
+    let reply = client.chat(hello(), None).await?;
+    assert_eq!(reply, "model=gpt4 auth=- api-key=azure-key org=- version=2024-02-01 system=user");
+
+    Ok(())
+}
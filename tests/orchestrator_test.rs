@@ -0,0 +1,91 @@
+use anyhow::Result;
+use axum::{routing::post, Json, Router};
+use swarm_thing::agent::Agent;
+use swarm_thing::llm::LlmClient;
+use swarm_thing::orchestrator::{parse_delegations, MessageBus, Member, Orchestrator};
+use swarm_thing::tools::ToolManager;
+
+// Ollama-compatible endpoint scripting a coordinator that delegates and a worker that uses a tool
+async fn mock_chat(Json(body): Json<serde_json::Value>) -> Json<serde_json::Value> {
+    let messages = body["messages"].as_array().cloned().unwrap_or_default();
+    let system = messages.first().and_then(|m| m["content"].as_str()).unwrap_or("").to_string();
+    let last = messages.last().and_then(|m| m["content"].as_str()).unwrap_or("").to_string();
+
+    let content = if let Some(results) = last.strip_prefix("Results from workers:\n") {
+        format!("Final: {}", results.trim())
+    } else if let Some(output) = last.strip_prefix("Tool Output: ") {
+        format!("The answer is {}", output)
+    } else if system.contains("[DELEGATE:") {
+        "Let me split this.\n[DELEGATE: calc] square of 4\n[DELEGATE: nobody] anything".to_string()
+    } else {
+        "[TOOL: orchestrator_test_square(4)]".to_string()
+    };
+    Json(serde_json::json!({ "message": { "role": "assistant", "content": content } }))
+}
+
+async fn member(name: &str, url: &str) -> Result<Member> {
+    let llm = LlmClient::with_model("ollama", Some("mock".to_string())).await?
+        .with_ollama_url(url)
+        .with_cache(None);
+    let prompt = format!("You are {}.", name);
+    Ok(Member::new(name, format!("{} role", name), Agent::with_client(llm, &prompt), ToolManager::new()?, &prompt))
+}
+
+#[test]
+fn test_parse_delegations() {
+    let response = "Plan:\n[DELEGATE: researcher] find sources\n  [DELEGATE:coder]write it \n[DELEGATE: reviewer]\n";
+    assert_eq!(parse_delegations(response), vec![
+        ("researcher".to_string(), "find sources".to_string()),
+        ("coder".to_string(), "write it".to_string()),
+    ]);
+}
+
+#[tokio::test]
+async fn test_message_bus() -> Result<()> {
+    let bus = MessageBus::new();
+    let mut inbox = bus.register("worker");
+    assert_eq!(bus.members(), vec!["worker"]);
+
+    bus.send("boss", "worker", "hello")?;
+    let reply = bus.request("boss", "worker", "ping")?;
+    assert_eq!(inbox.recv().await.unwrap().content, "hello");
+    let envelope = inbox.recv().await.unwrap();
+    assert_eq!((envelope.from.as_str(), envelope.content.as_str()), ("boss", "ping"));
+    envelope.respond("pong".to_string());
+    assert_eq!(reply.await?, "pong");
+
+    assert!(bus.send("boss", "missing", "hi").is_err());
+    bus.unregister("worker");
+    assert!(bus.members().is_empty());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_coordinator_delegates_to_worker() -> Result<()> {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+    let url = format!("http://{}/api/chat", listener.local_addr()?);
+    tokio::spawn(async move {
+        let app = Router::new().route("/api/chat", post(mock_chat));
+        axum::serve(listener, app).await.unwrap();
+    });
+
+    let mut tools = ToolManager::new()?;
+    tools.create_tool("orchestrator_test_square", r#"
+    fn orchestrator_test_square(x) {
+        let n = parse_int(x);
+        return n * n;
+    }
+    "#)?;
+
+    let mut orchestrator = Orchestrator::new(member("coordinator", &url).await?);
+    orchestrator.add_worker(member("calc", &url).await?);
+    assert_eq!(orchestrator.workers(), vec!["calc"]);
+
+    let answer = orchestrator.run("what is 4 squared?").await?;
+    assert!(answer.starts_with("Final: [calc] The answer is 16"), "{}", answer);
+    // Unknown workers are reported back to the coordinator instead of failing the task
+    assert!(answer.contains("[nobody] Error: No agent named 'nobody'"), "{}", answer);
+
+    std::fs::remove_file("tools/orchestrator_test_square.rhai")?;
+    Ok(())
+}
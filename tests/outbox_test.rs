@@ -0,0 +1,106 @@
+use anyhow::Result;
+use axum::{http::StatusCode, routing::post, Json, Router};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use swarm_thing::ipc::{Message, MessageResponse};
+use swarm_thing::message::IpcMessage;
+use swarm_thing::outbox::{DeliveryStatus, Outbox};
+use swarm_thing::retry::RetryPolicy;
+use swarm_thing::state::StateStore;
+use swarm_thing::tools::ToolManager;
+
+fn outbox(name: &str) -> Result<(Outbox, std::path::PathBuf)> {
+    let path = std::env::temp_dir().join(format!("swarm_outbox_{}_{}.json", name, std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    Ok((Outbox::new(StateStore::open(&path)?), path))
+}
+
+#[tokio::test]
+async fn test_outbox_retries_until_peer_is_up() -> Result<()> {
+    let (outbox, path) = outbox("retry")?;
+    let id = outbox.enqueue("127.0.0.1:9884", IpcMessage::text("are you up?"), Some("127.0.0.1:9000".to_string()), None)?;
+
+    // Nobody listening yet: the entry stays pending with a later retry time
+    assert_eq!(outbox.deliver_due().await?, 0);
+    let entry = outbox.get(id)?.unwrap();
+    assert_eq!(entry.status, DeliveryStatus::Pending);
+    assert_eq!(entry.attempts, 1);
+    assert!(entry.last_error.is_some());
+    assert!(outbox.due()?.is_empty());
+    assert!(outbox.render_status()?.contains(&format!("#{} to 127.0.0.1:9884: Pending after 1 attempt(s)", id)));
+
+    let received = Arc::new(Mutex::new(Vec::new()));
+    let log = received.clone();
+    let app = Router::new().route("/message", post(move |Json(msg): Json<Message>| {
+        let log = log.clone();
+        async move {
+            log.lock().unwrap().push(msg);
+            Json(MessageResponse::ok("thanks"))
+        }
+    }));
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:9884").await?;
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+    let worker = tokio::spawn(outbox.clone().run(Duration::from_millis(100)));
+    for _ in 0..50 {
+        if outbox.get(id)?.unwrap().status == DeliveryStatus::Delivered {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    worker.abort();
+    assert_eq!(outbox.get(id)?.unwrap().status, DeliveryStatus::Delivered);
+    let received = received.lock().unwrap().clone();
+    assert_eq!(received.len(), 1);
+    assert_eq!(received[0].from.as_deref(), Some("127.0.0.1:9000"));
+    assert!(matches!(IpcMessage::from_json_or_text(&received[0].content), IpcMessage::Text { content, .. } if content == "are you up?"));
+
+    assert_eq!(outbox.clear_delivered()?, 1);
+    let _ = std::fs::remove_file(&path);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_outbox_gives_up() -> Result<()> {
+    let app = Router::new().route("/message", post(|| async { (StatusCode::FORBIDDEN, "denied") }));
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+    let refusing = listener.local_addr()?.to_string();
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+    let (outbox, path) = outbox("give_up")?;
+    let outbox = outbox.with_retry(RetryPolicy { max_retries: 0, ..RetryPolicy::default() });
+    // Refusals aren't retried, and unreachable peers fail once retries run out
+    let refused = outbox.enqueue(&refusing, IpcMessage::text("hi"), None, None)?;
+    let unreachable = outbox.enqueue("127.0.0.1:9886", IpcMessage::text("hi"), None, None)?;
+    outbox.deliver_due().await?;
+    assert_eq!(outbox.get(refused)?.unwrap().status, DeliveryStatus::Failed);
+    assert!(outbox.get(refused)?.unwrap().last_error.unwrap().contains("403"));
+    assert_eq!(outbox.get(unreachable)?.unwrap().status, DeliveryStatus::Failed);
+
+    assert!(outbox.cancel(refused)?);
+    assert!(!outbox.cancel(refused)?);
+    let _ = std::fs::remove_file(&path);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_send_message_queues_when_peer_is_down() -> Result<()> {
+    let mut tools = ToolManager::new()?;
+    tools.create_tool("test_outbox_send", r#"
+    fn test_outbox_send(text) {
+        return send_message("http://127.0.0.1:9885/message", text);
+    }
+    "#)?;
+    tools.create_tool("test_outbox_status", "fn test_outbox_status(id) { return outbox_status(id); }")?;
+
+    let result = tools.execute_tool("test_outbox_send", vec!["ping".to_string()])?;
+    assert!(result.contains("queued in outbox as #"), "{}", result);
+    let id = result.rsplit('#').next().unwrap().trim().to_string();
+    let status = tools.execute_tool("test_outbox_status", vec![id.clone()])?;
+    assert!(status.contains("127.0.0.1:9885") && status.contains("Pending"), "{}", status);
+
+    tools.outbox().cancel(id.parse()?)?;
+    std::fs::remove_file("tools/test_outbox_send.rhai")?;
+    std::fs::remove_file("tools/test_outbox_status.rhai")?;
+    Ok(())
+}
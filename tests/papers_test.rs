@@ -0,0 +1,165 @@
+use anyhow::Result;
+use axum::extract::{Path, Query};
+use axum::http::{HeaderMap, StatusCode};
+use axum::routing::get;
+use axum::Router;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use swarm_thing::knowledge::KnowledgeBase;
+use swarm_thing::papers::{arxiv_id, parse_arxiv_feed, PaperClient};
+use swarm_thing::politeness::Politeness;
+use swarm_thing::tools::ToolManager;
+
+/// An arXiv API feed with one paper, its links pointing at `base`
+fn feed(base: &str) -> String {
+    format!(r#"<?xml version="1.0" encoding="UTF-8"?>
+<feed xmlns="http://www.w3.org/2005/Atom">
+  <link href="http://arxiv.org/api/query" rel="self" type="application/atom+xml"/>
+  <title type="html">ArXiv Query: search_query=all:swarm</title>
+  <entry>
+    <id>http://arxiv.org/abs/2101.00001v2</id>
+    <published>2021-01-01T12:00:00Z</published>
+    <title>Swarms of Tool-Making
+      Agents</title>
+    <summary>  We study agents that write &amp; share their own tools.
+    </summary>
+    <author><name>Ada Lovelace</name></author>
+    <author><name>Alan Turing</name><arxiv:affiliation xmlns:arxiv="http://arxiv.org/schemas/atom">Bletchley Park</arxiv:affiliation></author>
+    <arxiv:doi xmlns:arxiv="http://arxiv.org/schemas/atom">10.1000/swarm.1</arxiv:doi>
+    <link href="http://arxiv.org/abs/2101.00001v2" rel="alternate" type="text/html"/>
+    <link title="pdf" href="{base}/pdf/2101.00001v2" rel="related" type="application/pdf"/>
+    <category term="cs.MA" scheme="http://arxiv.org/schemas/atom"/>
+    <category term="cs.AI" scheme="http://arxiv.org/schemas/atom"/>
+  </entry>
+</feed>"#)
+}
+
+/// A mock arXiv and Semantic Scholar, recording the arXiv queries and Semantic Scholar API keys
+async fn serve() -> Result<(String, Arc<Mutex<Vec<HashMap<String, String>>>>, Arc<Mutex<Vec<Option<String>>>>)> {
+    let queries = Arc::new(Mutex::new(Vec::new()));
+    let keys = Arc::new(Mutex::new(Vec::new()));
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+    let base = format!("http://{}", listener.local_addr()?);
+    let (seen_queries, seen_keys, feed_base) = (queries.clone(), keys.clone(), base.clone());
+    let app = Router::new()
+        .route("/api/query", get(move |Query(params): Query<HashMap<String, String>>| async move {
+            seen_queries.lock().unwrap().push(params);
+            feed(&feed_base)
+        }))
+        .route("/pdf/:id", get(|| async { ([("content-type", "text/plain")], "Tool-making swarms converge on shared libraries of utilities.") }))
+        .route("/graph/v1/paper/*id", get(move |Path(id): Path<String>, headers: HeaderMap| async move {
+            seen_keys.lock().unwrap().push(headers.get("x-api-key").and_then(|v| v.to_str().ok()).map(String::from));
+            if id != "DOI:10.1000/graph.7" {
+                return (StatusCode::NOT_FOUND, "{}".to_string());
+            }
+            (StatusCode::OK, serde_json::json!({
+                "paperId": "abc123",
+                "title": "Graph Agents",
+                "authors": [{"authorId": "1", "name": "Grace Hopper"}],
+                "year": 2020,
+                "publicationDate": null,
+                "abstract": "Agents on graphs.",
+                "externalIds": {"DOI": "10.1000/graph.7", "ArXiv": "2002.00002", "CorpusId": 42},
+                "url": "https://www.semanticscholar.org/paper/abc123",
+                "openAccessPdf": null,
+                "venue": "AgentConf",
+                "citationCount": 7
+            }).to_string())
+        }));
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+    Ok((base, queries, keys))
+}
+
+fn client(base: &str) -> PaperClient {
+    PaperClient::new()
+        .with_arxiv_url(&format!("{}/api/query", base))
+        .with_scholar_url(&format!("{}/graph/v1", base))
+        .with_arxiv_delay(Duration::ZERO)
+        .with_politeness(Politeness::new())
+}
+
+#[test]
+fn test_arxiv_ids_are_recognised() {
+    assert_eq!(arxiv_id("2101.00001").as_deref(), Some("2101.00001"));
+    assert_eq!(arxiv_id("arXiv:2101.00001v2").as_deref(), Some("2101.00001v2"));
+    assert_eq!(arxiv_id("https://arxiv.org/pdf/2101.12345.pdf").as_deref(), Some("2101.12345"));
+    assert_eq!(arxiv_id("hep-th/9901001").as_deref(), Some("hep-th/9901001"));
+    assert_eq!(arxiv_id("math.GT/0309136v1").as_deref(), Some("math.GT/0309136v1"));
+    assert_eq!(arxiv_id("10.1000/graph.7"), None);
+    assert_eq!(arxiv_id("649def34f8be52c8b66281af98ae884c09aef38b"), None);
+}
+
+#[test]
+fn test_arxiv_feeds_are_parsed() -> Result<()> {
+    let papers = parse_arxiv_feed(&feed("http://arxiv.org"))?;
+    assert_eq!(papers.len(), 1);
+    let paper = &papers[0];
+    assert_eq!(paper.id, "arXiv:2101.00001v2");
+    assert_eq!(paper.title, "Swarms of Tool-Making Agents");
+    assert_eq!(paper.summary, "We study agents that write & share their own tools.");
+    assert_eq!(paper.authors, ["Ada Lovelace", "Alan Turing"]);
+    assert_eq!(paper.published.as_deref(), Some("2021-01-01"));
+    assert_eq!(paper.doi.as_deref(), Some("10.1000/swarm.1"));
+    assert_eq!(paper.categories, ["cs.MA", "cs.AI"]);
+    assert_eq!(paper.pdf_url.as_deref(), Some("https://arxiv.org/pdf/2101.00001v2"));
+    assert!(paper.render().starts_with("Title: Swarms of Tool-Making Agents\nAuthors: Ada Lovelace, Alan Turing\nPublished: 2021-01-01\nID: arXiv:2101.00001v2"));
+
+    let error = r#"<feed><entry><id>http://arxiv.org/api/errors#incorrect_id_format_for_x</id><title>Error</title>
+        <summary>incorrect id format for x</summary><author><name>arXiv api core</name></author></entry></feed>"#;
+    assert_eq!(parse_arxiv_feed(error).unwrap_err().to_string(), "arXiv: incorrect id format for x");
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_papers_are_looked_up_on_arxiv_and_semantic_scholar() -> Result<()> {
+    let (base, queries, keys) = serve().await?;
+    let papers = client(&base).with_scholar_key(Some("s2-test-key".to_string()));
+
+    let found = papers.search_arxiv("tool making swarms", 3).await?;
+    assert_eq!(found[0].title, "Swarms of Tool-Making Agents");
+    let arxiv = papers.get_paper("arXiv:2101.00001v2").await?;
+    assert_eq!(arxiv.pdf_url, Some(format!("{}/pdf/2101.00001v2", base)));
+    {
+        let queries = queries.lock().unwrap();
+        assert_eq!(queries[0]["search_query"], "all:tool AND all:making AND all:swarms");
+        assert_eq!(queries[0]["max_results"], "3");
+        assert_eq!(queries[1]["id_list"], "2101.00001v2");
+    }
+
+    // A bare DOI goes to Semantic Scholar; its arXiv id stands in for the missing PDF
+    let paper = papers.get_paper("10.1000/graph.7").await?;
+    assert_eq!(paper.id, "abc123");
+    assert_eq!(paper.authors, ["Grace Hopper"]);
+    assert_eq!(paper.published.as_deref(), Some("2020"));
+    assert_eq!(paper.categories, ["AgentConf"]);
+    assert_eq!(paper.citations, Some(7));
+    assert_eq!(paper.pdf_url.as_deref(), Some("https://arxiv.org/pdf/2002.00002"));
+    assert!(papers.get_paper("CorpusId:1").await.unwrap_err().to_string().contains("No paper CorpusId:1"));
+    assert_eq!(keys.lock().unwrap()[0].as_deref(), Some("s2-test-key"));
+    Ok(())
+}
+
+#[test]
+fn test_paper_natives() -> Result<()> {
+    let rt = tokio::runtime::Runtime::new()?;
+    let (base, _, _) = rt.block_on(serve())?;
+    std::env::set_var("ARXIV_API_URL", format!("{}/api/query", base));
+    std::env::set_var("ARXIV_DELAY_MS", "0");
+    let mut manager = ToolManager::new()?;
+    manager.create_tool("test_papers", r#"
+    fn test_papers(query) {
+        return search_arxiv(query, 2) + "\n---\n" + get_paper("2101.00001v2", true);
+    }
+    "#)?;
+
+    let result = manager.execute_tool("test_papers", vec!["swarms".to_string()])?;
+    assert!(result.starts_with("[1] Title: Swarms of Tool-Making Agents"), "got {}", result);
+    assert!(result.contains("Abstract: We study agents that write & share their own tools."));
+    let pdf = format!("{}/pdf/2101.00001v2", base);
+    assert!(result.ends_with(&format!("Ingested 1 chunks from {}", pdf)), "got {}", result);
+
+    KnowledgeBase::from_env()?.remove_source(&pdf)?;
+    std::fs::remove_file("tools/test_papers.rhai")?;
+    Ok(())
+}
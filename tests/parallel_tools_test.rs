@@ -0,0 +1,106 @@
+use anyhow::Result;
+use axum::{extract::Path, routing::get, Router};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use swarm_thing::message::ToolSafetyLevel;
+use swarm_thing::tools::{ConfirmPolicy, ResponseAction, ToolManager};
+
+// A page that takes 300ms to load
+async fn slow_page(Path(word): Path<String>) -> String {
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    format!("<html><body><p>{}</p></body></html>", word)
+}
+
+fn calls(base: &str, words: &[&str]) -> Vec<(String, Vec<String>)> {
+    words.iter().map(|w| ("scrape_url".to_string(), vec![format!("{}/{}", base, w)])).collect()
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_independent_calls_run_at_the_same_time() -> Result<()> {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+    let base = format!("http://{}", listener.local_addr()?);
+    tokio::spawn(async move {
+        // robots.txt answers at once, so only the pages are slow
+        let app = Router::new().route("/robots.txt", get(|| async { "" })).route("/:word", get(slow_page));
+        axum::serve(listener, app).await.unwrap();
+    });
+
+    let mut tools = ToolManager::new()?;
+    let started = Instant::now();
+    let results = tools.execute_tools(&calls(&base, &["one", "two", "three", "four"]));
+    assert!(started.elapsed() < Duration::from_millis(1000), "took {:?}", started.elapsed());
+    let results: Vec<String> = results.into_iter().collect::<Result<_>>()?;
+    assert_eq!(results, ["one", "two", "three", "four"]);
+
+    // One at a time they take the sum of their times (new pages, as the others are cached now)
+    tools.set_parallel_tools(1);
+    let started = Instant::now();
+    tools.execute_tools(&calls(&base, &["five", "six", "seven"]));
+    assert!(started.elapsed() >= Duration::from_millis(900));
+
+    // A response's calls come back in order, after the tools it defines
+    tools.set_parallel_tools(2);
+    let response = format!(
+        "[TOOL: scrape_url({base}/first)]\n[TOOL: scrape_url({base}/second)]\n```rhai\nfn test_parallel_shout(x) {{ x + \"!\" }}\n```\n[TOOL: test_parallel_shout(third)]"
+    );
+    let actions = tools.act_on(&response, None);
+    let ran: Vec<(String, String)> = actions.iter().filter_map(|action| match action {
+        ResponseAction::Ran { name, result, .. } => Some((name.clone(), result.clone().unwrap_or_else(|e| e))),
+        ResponseAction::Created { .. } => None,
+    }).collect();
+    assert_eq!(ran, [
+        ("scrape_url".to_string(), "first".to_string()),
+        ("scrape_url".to_string(), "second".to_string()),
+        ("test_parallel_shout".to_string(), "third!".to_string()),
+    ]);
+    assert!(matches!(&actions[0], ResponseAction::Created { outcome: Ok(_), .. }));
+
+    tools.remove_tool("test_parallel_shout")?;
+    Ok(())
+}
+
+#[test]
+fn test_parallel_calls_are_confirmed_in_turn() -> Result<()> {
+    let mut tools = ToolManager::new()?;
+    tools.set_confirm_policy(ConfirmPolicy::default());
+    let asked = Arc::new(Mutex::new(Vec::new()));
+    let asked_clone = asked.clone();
+    tools.set_execution_approver(Arc::new(move |call: &str, _: &ToolSafetyLevel| {
+        asked_clone.lock().unwrap().push(call.to_string());
+        !call.contains("secret")
+    }));
+
+    let results = tools.execute_tools(&[
+        ("list_dir".to_string(), vec![".".to_string()]),
+        ("square".to_string(), vec!["6".to_string()]),
+        ("read_file".to_string(), vec!["secret.txt".to_string()]),
+        ("square".to_string(), vec!["7".to_string()]),
+    ]);
+    // Only the risky calls are asked about, in order, and a declined one fails alone
+    assert_eq!(*asked.lock().unwrap(), ["list_dir(.)", "read_file(secret.txt)"]);
+    assert!(results[0].is_ok());
+    assert_eq!(results[1].as_ref().unwrap(), "36");
+    assert_eq!(results[2].as_ref().unwrap_err().to_string(), "The operator declined to run read_file(secret.txt)");
+    assert_eq!(results[3].as_ref().unwrap(), "49");
+    Ok(())
+}
+
+#[test]
+fn test_dependent_calls_run_in_order() -> Result<()> {
+    let tools = ToolManager::new()?;
+    let path = "parallel_tools_test_order.txt".to_string();
+    // A write then a read of the same file, twice: each read sees the write before it
+    let call = |name: &str, args: &[&str]| (name.to_string(), args.iter().map(|a| a.to_string()).collect::<Vec<_>>());
+    let results = tools.execute_tools(&[
+        call("write_file", &[&path, "first"]),
+        call("read_file", &[&path]),
+        call("write_file", &[&path, "second"]),
+        call("read_file", &[&path]),
+        call("memory_set", &["parallel_tools_test:order", "set"]),
+        call("memory_get", &["parallel_tools_test:order"]),
+    ]);
+    let results: Vec<String> = results.into_iter().collect::<Result<_>>()?;
+    assert_eq!((results[1].as_str(), results[3].as_str(), results[5].as_str()), ("first", "second", "set"));
+    std::fs::remove_file(&path)?;
+    Ok(())
+}
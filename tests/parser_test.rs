@@ -0,0 +1,53 @@
+use swarm_thing::parser::{code_blocks, parse_response, parse_tool_call, tool_blocks, ParsedAction, ToolBlock};
+
+#[test]
+fn test_parser_finds_every_tool_block() {
+    let response = "Two tools.\n\
+        ```rhai\n// filename: tools/double.rhai\nfn double(x) { x * 2 }\n```\n\
+        And `triple.rhai`:\n\
+        ```Rhai\nfn helper(x) { x }\nfn triple(x) { x * 3 }\n```\n\
+        ```python\ndef quad(x):\n    return x * 4\n```\n\
+        A shell example, not a tool:\n```bash\nls -la\n```\n\
+        ```rhai\nlet x = 1;\n```\n";
+    let names: Vec<(String, bool)> = tool_blocks(response).into_iter().map(|b| (b.name, b.python)).collect();
+    assert_eq!(names, vec![
+        ("double".to_string(), false),
+        ("triple".to_string(), false),
+        ("quad".to_string(), true),
+    ]);
+
+    // A longer fence keeps ``` lines inside the block
+    let nested = "````rhai\n// filename: docs\nfn docs() { \"use ```rhai blocks\" }\n```\nfn more() {}\n````\nafter";
+    let blocks = code_blocks(nested);
+    assert_eq!(blocks.len(), 1);
+    assert_eq!(blocks[0].code, "// filename: docs\nfn docs() { \"use ```rhai blocks\" }\n```\nfn more() {}\n");
+    assert_eq!(tool_blocks(nested)[0].name, "docs");
+
+    // An unclosed block runs to the end
+    let cut = "```rhai\n// filename: cut\nfn cut() { 1 }";
+    assert_eq!(tool_blocks(cut), vec![ToolBlock { name: "cut".to_string(), code: "// filename: cut\nfn cut() { 1 }\n".to_string(), python: false }]);
+}
+
+#[test]
+fn test_parser_reads_calls_and_answers() {
+    assert_eq!(parse_tool_call("[TOOL: square(4)]"), Some(("square".to_string(), vec!["4".to_string()])));
+    assert_eq!(
+        parse_tool_call("Let me check. [TOOL: mcp::files::read(\"a (1).txt\", [1, 2])] then more"),
+        Some(("mcp::files::read".to_string(), vec!["\"a (1).txt\", [1, 2]".to_string()]))
+    );
+    assert_eq!(parse_tool_call("[TOOL: list_tools()]"), Some(("list_tools".to_string(), vec![String::new()])));
+    assert_eq!(parse_tool_call("[TOOL: broken(1]"), None);
+
+    // Calls shown inside code blocks are examples, not requests
+    let response = "To square:\n```\n[TOOL: square(2)]\n```\nNow: [TOOL: double(3)] and [TOOL: square(5)]";
+    assert_eq!(parse_response(response), vec![
+        ParsedAction::ToolCall { name: "double".to_string(), args: vec!["3".to_string()] },
+        ParsedAction::ToolCall { name: "square".to_string(), args: vec!["5".to_string()] },
+    ]);
+
+    let response = "Saved it.\n```rhai\nfn inc(x) { x + 1 }\n```\n";
+    let actions = parse_response(response);
+    assert!(matches!(&actions[0], ParsedAction::CreateTool(block) if block.name == "inc"), "{:?}", actions);
+    assert_eq!(actions[1], ParsedAction::FinalAnswer(response.trim().to_string()));
+    assert_eq!(parse_response("  42  "), vec![ParsedAction::FinalAnswer("42".to_string())]);
+}
@@ -0,0 +1,82 @@
+use anyhow::Result;
+use swarm_thing::state::StateStore;
+use swarm_thing::threads::{peer_key, thread_key, Direction, PeerThreads};
+use swarm_thing::tools::ToolManager;
+use std::time::Duration;
+
+#[test]
+fn test_peer_thread_storage() -> Result<()> {
+    let path = "/tmp/swarm_thing_threads_test/state.json";
+    let _ = std::fs::remove_file(path);
+    let threads = PeerThreads::new(StateStore::open(path)?);
+    
+    assert_eq!(peer_key("http://127.0.0.1:9000/message"), "127.0.0.1:9000");
+    
+    threads.record("http://127.0.0.1:9000/message", Direction::Outgoing, "hello")?;
+    threads.record("127.0.0.1:9000", Direction::Incoming, "hi back")?;
+    threads.record("127.0.0.1:9001", Direction::Incoming, "unrelated")?;
+    
+    // Threads survive reopening the store
+    let reopened = PeerThreads::new(StateStore::open(path)?);
+    let history = reopened.history("127.0.0.1:9000")?;
+    assert_eq!(history.len(), 2);
+    assert_eq!(history[1].direction, Direction::Incoming);
+    assert_eq!(reopened.peers()?, vec!["127.0.0.1:9000", "127.0.0.1:9001"]);
+    
+    assert_eq!(reopened.render("127.0.0.1:9000", 1)?, "[them] hi back");
+    
+    std::fs::remove_dir_all("/tmp/swarm_thing_threads_test")?;
+    Ok(())
+}
+
+#[test]
+fn test_named_threads_are_kept_apart() -> Result<()> {
+    let path = std::env::temp_dir().join(format!("swarm_named_threads_{}.json", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let threads = PeerThreads::new(StateStore::open(&path)?);
+
+    assert_eq!(thread_key("http://127.0.0.1:9000/message", Some("deal-1")), "127.0.0.1:9000#deal-1");
+    threads.record("127.0.0.1:9000", Direction::Incoming, "hello")?;
+    threads.record_in("127.0.0.1:9000", Some("deal-1"), Direction::Outgoing, "offer: 40")?;
+    threads.record_in("127.0.0.1:9000", Some("deal-2"), Direction::Outgoing, "offer: 55")?;
+    threads.record_in("127.0.0.1:9000", Some("deal-1"), Direction::Incoming, "counter: 45")?;
+
+    assert_eq!(threads.render("127.0.0.1:9000", 10)?, "[them] hello");
+    assert_eq!(threads.render_in("127.0.0.1:9000", Some("deal-1"), 10)?, "[me] offer: 40\n[them] counter: 45");
+    assert_eq!(threads.thread_ids("http://127.0.0.1:9000/message")?, ["deal-1", "deal-2"]);
+    assert_eq!(threads.peers()?, ["127.0.0.1:9000"]);
+
+    std::fs::remove_file(&path)?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_peer_threads_over_ipc() -> Result<()> {
+    let mut agent_a = ToolManager::new()?;
+    let agent_b = ToolManager::new()?;
+    
+    agent_a.execute_tool("start_server", vec!["9994".to_string()])?;
+    agent_b.execute_tool("start_server", vec!["9995".to_string()])?;
+    tokio::time::sleep(Duration::from_millis(500)).await;
+    
+    let marker = format!("thread test {:?}", std::time::SystemTime::now());
+    agent_a.create_tool("test_thread_send", &format!(r#"
+    fn test_thread_send(dummy) {{
+        return send_message("http://127.0.0.1:9995/message", "{}");
+    }}
+    "#, marker))?;
+    let result = agent_a.execute_tool("test_thread_send", vec!["x".to_string()])?;
+    assert!(result.contains("ok"));
+    
+    // Sender recorded it under the peer's address, receiver under the sender's
+    let sent = agent_a.threads().history("127.0.0.1:9995")?;
+    assert!(sent.iter().any(|m| m.content == marker && m.direction == Direction::Outgoing));
+    let received = agent_b.threads().history("127.0.0.1:9994")?;
+    assert!(received.iter().any(|m| m.content == marker && m.direction == Direction::Incoming));
+    
+    let rendered = agent_a.execute_tool("peer_history", vec!["http://127.0.0.1:9995/message".to_string()])?;
+    assert!(rendered.contains(&marker));
+    
+    std::fs::remove_file("tools/test_thread_send.rhai")?;
+    Ok(())
+}
@@ -0,0 +1,82 @@
+use anyhow::Result;
+use swarm_thing::message::ToolSafetyLevel;
+use swarm_thing::persona::{selected_persona, Capability, Persona, ToolPolicy};
+use swarm_thing::prompts::PromptLibrary;
+use swarm_thing::tools::ToolManager;
+
+#[test]
+fn test_shipped_personas_load() -> Result<()> {
+    assert_eq!(Persona::available("personas"), vec!["coder", "coordinator", "researcher", "reviewer"]);
+    for name in Persona::available("personas") {
+        let persona = Persona::load("personas", &name)?;
+        assert_eq!(persona.name, name);
+        assert_eq!(persona.prompt, "system.md");
+        assert!(!persona.role.is_empty());
+    }
+
+    let reviewer = Persona::load("personas", "reviewer")?;
+    assert_eq!(reviewer.tools.max_risk, ToolSafetyLevel::MediumRisk);
+    assert!(reviewer.tools.allows(Capability::Files));
+    assert!(!reviewer.tools.allows(Capability::Commands));
+
+    assert!(Persona::load("personas", "missing").is_err());
+    assert!(Persona::load("personas", "../Cargo").is_err());
+    Ok(())
+}
+
+#[test]
+fn test_persona_prompt_and_selection() -> Result<()> {
+    let persona = Persona::load("personas", "researcher")?;
+    let prompt = PromptLibrary::new("prompts").render(&persona.prompt, &persona.prompt_context(vec!["square".to_string()]))?;
+    assert!(prompt.contains(&format!("Your role: {}", persona.role)));
+    assert!(prompt.contains("Additional Policies:\n- Cite the source"));
+
+    let args = |a: &[&str]| a.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+    assert_eq!(selected_persona(&args(&["--persona", "coder"])), Some("coder".to_string()));
+    assert_eq!(selected_persona(&args(&["--persona=reviewer"])), Some("reviewer".to_string()));
+    Ok(())
+}
+
+#[test]
+fn test_tool_policy_enforced() -> Result<()> {
+    let mut manager = ToolManager::new()?;
+    manager.create_tool("persona_test_reader", r#"
+    fn persona_test_reader() {
+        return read_file("Cargo.toml").len() > 0;
+    }
+    "#)?;
+    manager.create_tool("persona_test_writer", r#"
+    fn persona_test_writer() {
+        return write_file("persona_test_out.txt", "x");
+    }
+    "#)?;
+    manager.create_tool("persona_test_wrapper", r#"
+    fn persona_test_wrapper() {
+        return persona_test_writer();
+    }
+    "#)?;
+    manager.create_tool("persona_test_pure", r#"
+    fn persona_test_pure() {
+        return 6 * 7;
+    }
+    "#)?;
+
+    // Files allowed, but nothing above MediumRisk
+    manager.set_policy(ToolPolicy { capabilities: Some(vec![Capability::Files]), max_risk: ToolSafetyLevel::MediumRisk });
+    assert_eq!(manager.execute_tool("persona_test_reader", vec![])?, "true");
+    assert!(manager.execute_tool("persona_test_writer", vec![]).unwrap_err().to_string().contains("HighRisk"));
+    // Calling a forbidden tool through another tool is caught too
+    assert!(manager.execute_tool("persona_test_wrapper", vec![]).is_err());
+    assert_eq!(manager.execute_tool("persona_test_pure", vec![])?, "42");
+
+    // Capability allowlist applies to direct native calls
+    manager.set_policy(ToolPolicy { capabilities: Some(vec![]), max_risk: ToolSafetyLevel::HighRisk });
+    assert!(manager.execute_tool("read_file", vec!["Cargo.toml".to_string()]).unwrap_err().to_string().contains("Files"));
+    assert!(manager.execute_tool("persona_test_reader", vec![]).is_err());
+    assert!(manager.execute_tool("list_tools", vec![]).is_ok());
+
+    for tool in ["persona_test_reader", "persona_test_writer", "persona_test_wrapper", "persona_test_pure"] {
+        std::fs::remove_file(format!("tools/{}.rhai", tool))?;
+    }
+    Ok(())
+}
@@ -0,0 +1,87 @@
+use anyhow::Result;
+use axum::{routing::post, Json, Router};
+use swarm_thing::agent::Agent;
+use swarm_thing::llm::LlmClient;
+use swarm_thing::message::ToolSafetyLevel;
+use swarm_thing::persona::ToolPolicy;
+use swarm_thing::planner::{make_plan, Plan, StepStatus};
+use swarm_thing::tools::ToolManager;
+
+#[test]
+fn test_parse_plan() -> Result<()> {
+    let response = "Here is my plan:\n```json\n{\"steps\": [{\"description\": \"Square it\", \"tool\": \"square\", \"args\": [\"4\"]}, {\"description\": \"Explain\", \"tool\": null}]}\n```";
+    let plan = Plan::parse("square 4", response)?;
+    assert_eq!(plan.goal, "square 4");
+    assert_eq!(plan.steps.len(), 2);
+    assert_eq!(plan.steps[0].tool.as_deref(), Some("square"));
+    assert_eq!(plan.steps[1].tool, None);
+    assert!(plan.render().contains("1. Square it [TOOL: square(4)]"));
+
+    let plan = Plan::parse("g", r#"[{"description": "Think", "tool": ""}]"#)?;
+    assert_eq!(plan.steps[0].tool, None);
+
+    assert!(Plan::parse("g", "I can't plan this").is_err());
+    Ok(())
+}
+
+#[test]
+fn test_validate_and_execute_plan() -> Result<()> {
+    let mut manager = ToolManager::new()?;
+    manager.create_tool("planner_test_double", r#"
+    fn planner_test_double(x) {
+        return parse_int(x) * 2;
+    }
+    "#)?;
+
+    let plan = Plan::parse("g", r#"{"steps": [
+        {"description": "Double", "tool": "planner_test_double", "args": ["3"]},
+        {"description": "Double again", "tool": "planner_test_double", "args": ["{{step1}}"]},
+        {"description": "Read outside the jail", "tool": "read_file", "args": ["../outside.txt"]},
+        {"description": "Never runs", "tool": "planner_test_double", "args": ["{{step2}}"]}
+    ]}"#)?;
+    assert!(plan.validate(&manager).is_empty(), "{:?}", plan.validate(&manager));
+
+    let results = plan.execute(&manager);
+    assert_eq!((results[0].status.clone(), results[0].output.as_str()), (StepStatus::Done, "6"));
+    assert_eq!((results[1].status.clone(), results[1].output.as_str()), (StepStatus::Done, "12"));
+    assert_eq!(results[2].status, StepStatus::Failed);
+    assert_eq!(results[3].status, StepStatus::Skipped);
+
+    let bad = Plan::parse("g", r#"{"steps": [
+        {"description": "Unknown", "tool": "planner_test_missing"},
+        {"description": "Two args", "tool": "planner_test_double", "args": ["1", "2"]},
+        {"description": "Forward reference", "tool": "planner_test_double", "args": ["{{step4}}"]},
+        {"description": "Write", "tool": "write_file", "args": ["x"]}
+    ]}"#)?;
+    manager.set_policy(ToolPolicy { capabilities: None, max_risk: ToolSafetyLevel::MediumRisk });
+    let problems = bad.validate(&manager);
+    assert_eq!(problems.len(), 4, "{:?}", problems);
+    assert!(problems[0].contains("not found"));
+    assert!(problems[3].starts_with("Step 4") && problems[3].contains("HighRisk"));
+
+    std::fs::remove_file("tools/planner_test_double.rhai")?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_make_plan_from_agent() -> Result<()> {
+    async fn mock_chat(Json(body): Json<serde_json::Value>) -> Json<serde_json::Value> {
+        let last = body["messages"].as_array().and_then(|m| m.last()).cloned().unwrap_or_default();
+        let goal = last["content"].as_str().unwrap_or("").rsplit("Goal: ").next().unwrap_or("").to_string();
+        let content = format!(r#"{{"steps": [{{"description": "{}", "tool": "list_tools", "args": []}}]}}"#, goal);
+        Json(serde_json::json!({ "message": { "role": "assistant", "content": content } }))
+    }
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+    let url = format!("http://{}/api/chat", listener.local_addr()?);
+    tokio::spawn(async move {
+        axum::serve(listener, Router::new().route("/api/chat", post(mock_chat))).await.unwrap();
+    });
+
+    let llm = LlmClient::with_model("ollama", Some("mock".to_string())).await?.with_ollama_url(&url).with_cache(None);
+    let mut agent = Agent::with_client(llm, "You plan.");
+    let manager = ToolManager::new()?;
+    let plan = make_plan(&mut agent, &manager, "show the tools").await?;
+    assert_eq!(plan.steps[0].description, "show the tools");
+    assert!(plan.validate(&manager).is_empty());
+    Ok(())
+}
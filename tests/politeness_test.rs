@@ -0,0 +1,104 @@
+use anyhow::Result;
+use axum::http::HeaderMap;
+use axum::routing::get;
+use axum::Router;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use swarm_thing::http_cache::fetch_page;
+use swarm_thing::politeness::{Politeness, Robots};
+
+const ROBOTS: &str = "
+# Everyone
+User-agent: *
+Disallow: /private
+Allow: /private/public
+Disallow: /*.pdf$
+
+User-agent: swarm-thing
+User-agent: other-bot
+Disallow: /no-agents
+Crawl-delay: 0.3
+";
+
+#[test]
+fn test_robots_rules_pick_the_agents_group() {
+    let generic = Robots::parse(ROBOTS, "some-crawler");
+    assert!(!generic.allows("/private/notes"));
+    // The longer rule wins
+    assert!(generic.allows("/private/public/index.html"));
+    assert!(!generic.allows("/papers/a.pdf"));
+    assert!(generic.allows("/papers/a.pdf?download=1"));
+    assert_eq!(generic.crawl_delay, None);
+
+    let ours = Robots::parse(ROBOTS, "swarm-thing");
+    assert!(ours.allows("/private/notes"));
+    assert!(!ours.allows("/no-agents/x"));
+    assert_eq!(ours.crawl_delay, Some(Duration::from_millis(300)));
+
+    assert!(Robots::parse("User-agent: *\nDisallow:\n", "swarm-thing").allows("/anything"));
+    assert!(!Robots::disallow_all().allows("/"));
+}
+
+#[test]
+fn test_domain_delays_cover_subdomains() {
+    let politeness = Politeness::new()
+        .with_delay(Duration::from_millis(100))
+        .with_domain_delay("example.com", Duration::from_secs(2))
+        .with_domain_delay("*.slow.example.com", Duration::from_secs(5));
+    assert_eq!(politeness.delay_for("example.com"), Duration::from_secs(2));
+    assert_eq!(politeness.delay_for("www.Example.com"), Duration::from_secs(2));
+    assert_eq!(politeness.delay_for("a.slow.example.com"), Duration::from_secs(5));
+    assert_eq!(politeness.delay_for("notexample.com"), Duration::from_millis(100));
+}
+
+/// A site with `ROBOTS` that records the User-Agent of each page request
+async fn serve() -> Result<(String, Arc<Mutex<Vec<String>>>)> {
+    let agents = Arc::new(Mutex::new(Vec::new()));
+    let seen = agents.clone();
+    let app = Router::new()
+        .route("/robots.txt", get(|| async { ROBOTS }))
+        .route("/*path", get(move |headers: HeaderMap| async move {
+            let agent = headers.get("user-agent").and_then(|v| v.to_str().ok()).unwrap_or_default().to_string();
+            seen.lock().unwrap().push(agent);
+            "<html><body>page</body></html>"
+        }));
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+    let address = listener.local_addr()?.to_string();
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+    Ok((format!("http://{}", address), agents))
+}
+
+#[tokio::test]
+async fn test_requests_follow_robots_and_crawl_delay() -> Result<()> {
+    let (site, agents) = serve().await?;
+    let politeness = Politeness::new().with_user_agent("swarm-thing/test (research agent)");
+
+    let refused = fetch_page(None, &politeness, &format!("{}/no-agents/page", site), false).await.unwrap_err();
+    assert!(refused.to_string().contains("robots.txt of http://") && refused.to_string().contains("disallows /no-agents/page for swarm-thing"), "{}", refused);
+    assert!(agents.lock().unwrap().is_empty());
+
+    // Crawl-delay spaces out requests to the host
+    let started = Instant::now();
+    fetch_page(None, &politeness, &format!("{}/a", site), false).await?;
+    fetch_page(None, &politeness, &format!("{}/b", site), false).await?;
+    assert!(started.elapsed() >= Duration::from_millis(300), "took {:?}", started.elapsed());
+    assert_eq!(*agents.lock().unwrap(), vec!["swarm-thing/test (research agent)"; 2]);
+
+    // Ignoring robots.txt is up to the operator
+    let rude = Politeness::new().with_robots(false);
+    assert!(fetch_page(None, &rude, &format!("{}/no-agents/page", site), false).await?.contains("page"));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_connections_per_host_are_capped() -> Result<()> {
+    let (site, _) = serve().await?;
+    let politeness = Politeness::new().with_robots(false).with_max_per_host(1);
+    let client = politeness.client();
+    let first = politeness.admit(&client, &format!("{}/a", site)).await?;
+    // The host's only slot is taken until the first request is done
+    assert!(tokio::time::timeout(Duration::from_millis(200), politeness.admit(&client, &format!("{}/b", site))).await.is_err());
+    drop(first);
+    tokio::time::timeout(Duration::from_millis(200), politeness.admit(&client, &format!("{}/b", site))).await??;
+    Ok(())
+}
@@ -0,0 +1,58 @@
+use anyhow::Result;
+use swarm_thing::prompts::{PromptContext, PromptLibrary};
+
+fn temp_dir(name: &str) -> std::path::PathBuf {
+    let dir = std::env::temp_dir().join(format!("prompts_test_{}_{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[test]
+fn test_system_prompt_variables_and_policies() -> Result<()> {
+    let library = PromptLibrary::new("prompts");
+    let mut context = PromptContext::new("Scout", vec!["square".to_string(), "web_search".to_string()]);
+
+    let prompt = library.system_prompt(&context)?;
+    assert!(prompt.starts_with("You are Scout, a Research Agent"));
+    assert!(prompt.contains("Available Tools: [square, web_search]"));
+    assert!(prompt.contains("maps `#{a: 1, b: 2}`"));
+    assert!(!prompt.contains("Additional Policies"));
+
+    context.policies = vec!["Cite sources".to_string(), "Never delete files".to_string()];
+    let prompt = library.system_prompt(&context)?;
+    assert!(prompt.contains("Additional Policies:\n- Cite sources\n- Never delete files\n"));
+    Ok(())
+}
+
+#[test]
+fn test_templates_hot_reload() -> Result<()> {
+    let dir = temp_dir("reload");
+    let library = PromptLibrary::new(&dir);
+    let mut context = PromptContext::new("Scout", vec!["square".to_string()]);
+    context.vars.insert("tone".to_string(), "terse".to_string());
+
+    std::fs::write(dir.join("system.md"), "{{ agent_name }} has {{ tools | length }} tool(s)")?;
+    assert_eq!(library.system_prompt(&context)?, "Scout has 1 tool(s)");
+
+    // Edits are picked up on the next render without rebuilding the library
+    std::fs::write(dir.join("system.md"), "{{ agent_name }} is {{ vars.tone }}")?;
+    assert_eq!(library.system_prompt(&context)?, "Scout is terse");
+
+    std::fs::write(dir.join("broken.md"), "{% if %}")?;
+    assert!(library.render("broken.md", &context).is_err());
+    assert!(library.render("missing.md", &context).is_err());
+
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+
+#[test]
+fn test_builtin_system_prompt_fallback() -> Result<()> {
+    let dir = temp_dir("fallback");
+    let library = PromptLibrary::new(&dir);
+    let prompt = library.system_prompt(&PromptContext::new("Scout", vec!["square".to_string()]))?;
+    assert!(prompt.contains("Available Tools: [square]"));
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}
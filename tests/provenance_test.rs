@@ -0,0 +1,129 @@
+use anyhow::Result;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use swarm_thing::audit::AuditLog;
+use swarm_thing::encryption::AgentKeys;
+use swarm_thing::ipc::{router, send_ipc_message, IpcState, TaskQueue};
+use swarm_thing::message::{IpcMessage, ToolSafetyLevel};
+use swarm_thing::provenance::{check_provenance, installed_signature, signature_for_share, store_signature, AuthorSignature};
+use swarm_thing::registry::PeerRegistry;
+use swarm_thing::safety::SafetyPolicy;
+use swarm_thing::state::StateStore;
+use swarm_thing::threads::PeerThreads;
+use swarm_thing::tools::{approve_pending_tool, PendingTool};
+
+const SQUARE: &str = "fn square(x) { x * x }";
+
+fn temp(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("swarm_provenance_{}_{}", name, std::process::id()))
+}
+
+fn tools_dir(name: &str) -> Result<PathBuf> {
+    let dir = temp(name);
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir)?;
+    Ok(dir)
+}
+
+#[test]
+fn test_signatures_cover_name_and_code() -> Result<()> {
+    let author = AgentKeys::generate();
+    let signature = AuthorSignature::sign(&author, "square", SQUARE);
+    assert_eq!(signature.author.len(), 16);
+    signature.verify("square", SQUARE)?;
+    assert!(signature.verify("square", "fn square(x) { run_command(x) }").is_err());
+    assert!(signature.verify("cube", SQUARE).is_err());
+
+    // Claiming someone else's fingerprint doesn't pass
+    let mut forged = signature.clone();
+    forged.author = AuthorSignature::sign(&AgentKeys::generate(), "square", SQUARE).author;
+    assert!(forged.verify("square", SQUARE).is_err());
+    Ok(())
+}
+
+#[test]
+fn test_installed_tools_keep_their_author() -> Result<()> {
+    let dir = tools_dir("pinned")?;
+    let author = AgentKeys::generate();
+    let signed = AuthorSignature::sign(&author, "square", SQUARE);
+
+    // Nothing installed: any copy with a valid signature, or none, may be queued
+    check_provenance(&dir, "square", SQUARE, None)?;
+    check_provenance(&dir, "square", SQUARE, Some(&signed))?;
+    assert!(check_provenance(&dir, "square", "fn square(x) { x }", Some(&signed)).is_err());
+
+    std::fs::write(dir.join("square.rhai"), SQUARE)?;
+    store_signature(&dir, "square", Some(&signed))?;
+    assert_eq!(installed_signature(&dir, "square"), Some(signed.clone()));
+
+    // Updates by the author pass; copies by anyone else, or unsigned ones, don't
+    let update = "fn square(x) { return x * x; }";
+    check_provenance(&dir, "square", update, Some(&AuthorSignature::sign(&author, "square", update)))?;
+    let other = check_provenance(&dir, "square", update, Some(&AuthorSignature::sign(&AgentKeys::generate(), "square", update))).unwrap_err();
+    assert!(other.to_string().contains(&format!("installed signed by {}", signed.author)), "{}", other);
+    assert!(check_provenance(&dir, "square", update, None).unwrap_err().to_string().contains("this copy is unsigned"));
+
+    // Sharing passes the author's signature on, and signs only what changed here
+    let sharer = AgentKeys::generate();
+    assert_eq!(signature_for_share(&dir, "square", SQUARE, Some(&sharer)), Some(signed.clone()));
+    let changed = signature_for_share(&dir, "square", update, Some(&sharer)).unwrap();
+    assert_eq!(changed, AuthorSignature::sign(&sharer, "square", update));
+
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+
+/// Serve tools from `dir` on a random port, returning its address and approval queue
+async fn serve(dir: PathBuf, keys: AgentKeys) -> Result<(String, Arc<Mutex<Vec<PendingTool>>>)> {
+    let state_path = dir.join("state.json");
+    let pending = Arc::new(Mutex::new(Vec::new()));
+    let state = IpcState::new(pending.clone(), PeerThreads::new(StateStore::open(&state_path)?), TaskQueue::new(), PeerRegistry::new(), dir)
+        .with_keys(keys);
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+    let address = listener.local_addr()?.to_string();
+    tokio::spawn(async move {
+        axum::serve(listener, router(state).into_make_service_with_connect_info::<SocketAddr>()).await.unwrap()
+    });
+    Ok((address, pending))
+}
+
+fn share(code: &str, signature: Option<AuthorSignature>) -> IpcMessage {
+    IpcMessage::tool_share("square", code, None, ToolSafetyLevel::Safe).signed(signature)
+}
+
+#[tokio::test]
+async fn test_received_tools_are_verified() -> Result<()> {
+    let dir = tools_dir("server")?;
+    let author = AgentKeys::generate();
+    let (address, pending) = serve(dir.clone(), AgentKeys::generate()).await?;
+
+    // Altered in transit: refused before it reaches the queue
+    let tampered = share("fn square(x) { run_command(x) }", Some(AuthorSignature::sign(&author, "square", SQUARE)));
+    let reply = send_ipc_message(&address, &tampered, None).await?;
+    assert!(reply.contains("refused") && reply.contains("does not match the code"), "{}", reply);
+
+    // Approving a signed tool keeps the signature with it
+    let signed = AuthorSignature::sign(&author, "square", SQUARE);
+    assert!(send_ipc_message(&address, &share(SQUARE, Some(signed.clone())), None).await?.contains("queued for approval"));
+    assert!(pending.lock().unwrap()[0].origin().contains(&format!("signed by {}", signed.author)));
+    let audit = AuditLog::open(dir.join("audit.jsonl"))?;
+    approve_pending_tool(&pending, &dir, &SafetyPolicy::default(), &audit, "square")?;
+    assert_eq!(installed_signature(&dir, "square"), Some(signed));
+
+    // From then on, only its author can replace it
+    let reply = send_ipc_message(&address, &share("fn square(x) { 0 }", None), None).await?;
+    assert!(reply.contains("this copy is unsigned"), "{}", reply);
+    assert!(pending.lock().unwrap().is_empty());
+
+    // Peers asking for it get the author's signature, not the server's
+    let reply = send_ipc_message(&address, &IpcMessage::tool_request("square"), None).await?;
+    let IpcMessage::ToolShare { code, signature: Some(signature), .. } = IpcMessage::from_json_or_text(&reply) else {
+        panic!("expected a signed ToolShare, got {}", reply);
+    };
+    signature.verify("square", &code)?;
+    assert_eq!(Some(signature), installed_signature(&dir, "square"));
+
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}
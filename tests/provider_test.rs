@@ -0,0 +1,104 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use axum::{routing::post, Json, Router};
+use std::sync::{Arc, Mutex};
+use swarm_thing::config::Config;
+use swarm_thing::llm::{GenerationConfig, LlmClient, Message, OpenAiConfig, Usage};
+use swarm_thing::provider::{providers, ChatProvider, OllamaChat, OpenAiChat};
+
+/// A gateway of our own: answers with the last message, shouted
+struct Shouting {
+    model: String,
+}
+
+#[async_trait]
+impl ChatProvider for Shouting {
+    fn name(&self) -> &str {
+        "shouting"
+    }
+
+    fn model(&self) -> &str {
+        &self.model
+    }
+
+    async fn chat(&self, messages: Vec<Message>, _system_prompt: Option<String>, _generation: &GenerationConfig) -> Result<(String, Usage)> {
+        let last = messages.last().map(|m| m.content.to_uppercase()).unwrap_or_default();
+        Ok((last, Usage { input_tokens: 3, output_tokens: 1 }))
+    }
+
+    fn context_window(&self) -> usize {
+        1_000
+    }
+}
+
+#[tokio::test]
+async fn test_registered_provider_is_used_by_name() -> Result<()> {
+    providers().register("shouting", |model| async move {
+        Ok(Arc::new(Shouting { model: model.unwrap_or_else(|| "loud".to_string()) }) as Arc<dyn ChatProvider>)
+    });
+    let client = LlmClient::from_spec("Shouting:v2").await?.with_cache(None);
+    assert_eq!(client.label(), "shouting:v2");
+    assert_eq!(client.provider().context_window(), 1_000);
+    let completion = client.complete(vec![Message::user("hello")], None, &GenerationConfig::default()).await?;
+    assert_eq!((completion.text.as_str(), completion.usage.input_tokens), ("HELLO", 3));
+
+    // Without streaming of its own, the whole answer comes as one chunk
+    let chunks = Mutex::new(Vec::new());
+    let streamed = client.complete_stream(vec![Message::user("hi")], None, &GenerationConfig::default(), &|chunk: &str| chunks.lock().unwrap().push(chunk.to_string())).await?;
+    assert_eq!((streamed.text.as_str(), chunks.into_inner().unwrap()), ("HI", vec!["HI".to_string()]));
+
+    let mut config = Config::default();
+    config.llm.provider = "shouting".to_string();
+    assert!(config.validate().iter().all(|p| !p.starts_with("llm.provider")), "{:?}", config.validate());
+    config.llm.provider = "whispering".to_string();
+    assert!(config.validate().iter().any(|p| p.starts_with("llm.provider")));
+    let err = LlmClient::from_spec("whispering:v1").await.err().unwrap();
+    assert!(err.to_string().contains("Unknown LLM provider 'whispering'"), "{}", err);
+    Ok(())
+}
+
+// Streams the answer in pieces, as Ollama (NDJSON) and OpenAI (server-sent events) do
+async fn ollama_stream(Json(body): Json<serde_json::Value>) -> String {
+    assert_eq!(body["stream"], true);
+    [
+        r#"{"message":{"role":"assistant","content":"Hel"},"done":false}"#,
+        r#"{"message":{"role":"assistant","content":"lo"},"done":false}"#,
+        r#"{"message":{"role":"assistant","content":""},"done":true,"prompt_eval_count":7,"eval_count":2}"#,
+    ].join("\n")
+}
+
+async fn openai_stream(Json(body): Json<serde_json::Value>) -> String {
+    assert_eq!(body["stream"], true);
+    [
+        r#"data: {"choices":[{"delta":{"role":"assistant"}}]}"#,
+        r#"data: {"choices":[{"delta":{"content":"Hel"}}]}"#,
+        r#"data: {"choices":[{"delta":{"content":"lo"}}],"usage":{"prompt_tokens":5,"completion_tokens":2}}"#,
+        "data: [DONE]",
+        "",
+    ].join("\n\n")
+}
+
+#[tokio::test]
+async fn test_builtin_providers_stream() -> Result<()> {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+    let base = format!("http://{}", listener.local_addr()?);
+    tokio::spawn(async move {
+        let app = Router::new()
+            .route("/api/chat", post(ollama_stream))
+            .route("/v1/chat/completions", post(openai_stream));
+        axum::serve(listener, app).await.unwrap();
+    });
+
+    let openai = OpenAiChat::new("gpt-test", OpenAiConfig { base_url: format!("{}/v1", base), api_key: None, organization: None, api_version: None });
+    let providers: Vec<Arc<dyn ChatProvider>> = vec![Arc::new(OllamaChat::new("llama3.1", format!("{}/api/chat", base))), Arc::new(openai)];
+    for (provider, usage) in providers.into_iter().zip([(7, 2), (5, 2)]) {
+        let chunks = Mutex::new(Vec::new());
+        let on_chunk = |chunk: &str| chunks.lock().unwrap().push(chunk.to_string());
+        let client = LlmClient::with_provider(provider);
+        let completion = client.complete_stream(vec![Message::user("hi")], None, &GenerationConfig::default(), &on_chunk).await?;
+        assert_eq!(completion.text, "Hello", "{}", client.label());
+        assert_eq!((completion.usage.input_tokens, completion.usage.output_tokens), usage, "{}", client.label());
+        assert_eq!(chunks.into_inner().unwrap(), ["Hel", "lo"], "{}", client.label());
+    }
+    Ok(())
+}
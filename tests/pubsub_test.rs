@@ -0,0 +1,59 @@
+use anyhow::Result;
+use std::time::Duration;
+use swarm_thing::registry::{topic_matches, Subscriptions};
+use swarm_thing::state::StateStore;
+use swarm_thing::tools::ToolManager;
+
+#[test]
+fn test_subscriptions_persist() -> Result<()> {
+    let path = std::env::temp_dir().join(format!("swarm_pubsub_{}.json", std::process::id()));
+    let subscriptions = Subscriptions::new(StateStore::open(&path)?);
+    assert!(subscriptions.add_subscriber("builds/*", "http://127.0.0.1:1/message")?);
+    assert!(!subscriptions.add_subscriber("builds/*", "127.0.0.1:1")?);
+    assert!(subscriptions.add_subscriber("builds/done", "127.0.0.1:2")?);
+    subscriptions.record_subscribed("127.0.0.1:3", "alerts", true)?;
+
+    // A fresh handle on the same file sees everything
+    let reopened = Subscriptions::new(StateStore::open(&path)?);
+    assert_eq!(reopened.subscribers("builds/done")?, vec!["127.0.0.1:1", "127.0.0.1:2"]);
+    assert_eq!(reopened.subscribers("builds/failed")?, vec!["127.0.0.1:1"]);
+    assert_eq!(reopened.subscribed()?, vec![("127.0.0.1:3".to_string(), vec!["alerts".to_string()])]);
+    assert!(topic_matches("*", "anything"));
+    assert!(!topic_matches("builds", "builds/done"));
+
+    assert!(reopened.remove_subscriber("builds/*", "127.0.0.1:1")?);
+    assert!(!reopened.remove_subscriber("builds/*", "127.0.0.1:1")?);
+    assert!(reopened.subscribers("builds/failed")?.is_empty());
+
+    std::fs::remove_file(&path)?;
+    Ok(())
+}
+
+#[test]
+fn test_subscribe_and_publish_natives() -> Result<()> {
+    let mut publisher = ToolManager::new()?;
+    publisher.execute_tool("start_server", vec!["9891".to_string()])?;
+    let mut subscriber = ToolManager::new()?;
+    subscriber.execute_tool("start_server", vec!["9892".to_string()])?;
+    std::thread::sleep(Duration::from_millis(500));
+
+    subscriber.create_tool("test_pubsub_follow", r#"
+    fn test_pubsub_follow(on) {
+        if on == "yes" { return subscribe("127.0.0.1:9891", "test_pubsub/*"); }
+        return unsubscribe("127.0.0.1:9891", "test_pubsub/*");
+    }
+    "#)?;
+    publisher.create_tool("test_pubsub_announce", r#"fn test_pubsub_announce(text) { return publish("test_pubsub/releases", text); }"#)?;
+
+    assert!(subscriber.execute_tool("test_pubsub_follow", vec!["yes".to_string()])?.contains("Subscribed 127.0.0.1:9892"));
+    let report = publisher.execute_tool("test_pubsub_announce", vec!["v1.2 is out".to_string()])?;
+    assert!(report.starts_with("Delivered to 1/1 peer(s)"), "{}", report);
+    assert!(subscriber.inbox().render_unread().contains("[event test_pubsub/releases] v1.2 is out"));
+
+    subscriber.execute_tool("test_pubsub_follow", vec!["no".to_string()])?;
+    assert!(publisher.execute_tool("test_pubsub_announce", vec!["v1.3".to_string()])?.contains("No subscribers"));
+
+    std::fs::remove_file("tools/test_pubsub_follow.rhai")?;
+    std::fs::remove_file("tools/test_pubsub_announce.rhai")?;
+    Ok(())
+}
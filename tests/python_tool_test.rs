@@ -0,0 +1,87 @@
+use anyhow::Result;
+use std::sync::Arc;
+use std::time::Duration;
+use swarm_thing::backend::ToolBackend;
+use swarm_thing::message::ToolSafetyLevel;
+use swarm_thing::python::PythonBackend;
+use swarm_thing::tools::{ConfirmPolicy, ToolManager};
+
+#[test]
+fn test_python_tool_backend() -> Result<()> {
+    let mut manager = ToolManager::new()?;
+    
+    manager.create_python_tool("py_word_count", r#"
+def py_word_count(text):
+    print("counting words")  # must not break the protocol
+    return len(text.split())
+"#)?;
+    manager.create_python_tool("py_broken", r#"
+def py_broken(x):
+    raise ValueError("bad input: " + x)
+"#)?;
+    
+    // Disabled unless PYTHON_TOOLS is set
+    manager.set_python_backend(None);
+    assert!(manager.execute_tool("py_word_count", vec!["a b".to_string()]).is_err());
+    
+    manager.set_python_backend(Some(PythonBackend::new("python3", Duration::from_secs(10), ".")));
+
+    // Every run is confirmed, even with confirmations off, and nothing runs with nobody to ask
+    manager.set_confirm_policy(ConfirmPolicy::auto_approve());
+    let err = manager.execute_tool("py_word_count", vec!["a b".to_string()]).unwrap_err();
+    assert_eq!(err.to_string(), "py_word_count(a b) needs an operator to confirm it, and there is none");
+    manager.set_execution_approver(Arc::new(|call: &str, _: &ToolSafetyLevel| call.starts_with("py_")));
+    
+    // Routed by extension and listed alongside Rhai tools
+    assert!(manager.list_tools().contains(&"py_word_count".to_string()));
+    let result = manager.execute_tool("py_word_count", vec!["one two three".to_string()])?;
+    assert_eq!(result, "3");
+    
+    // Python exceptions surface as tool errors
+    let err = manager.execute_tool("py_broken", vec!["x".to_string()]).unwrap_err();
+    assert!(err.to_string().contains("ValueError: bad input: x"));
+    
+    // Rhai tools are unaffected
+    let summary = manager.execute_tool("inspect_tool", vec!["py_word_count".to_string()])?;
+    assert!(summary.starts_with("py_word_count(text)"), "{}", summary);
+    let source = manager.execute_tool("inspect_tool", vec!["py_word_count, source".to_string()])?;
+    assert!(source.contains("def py_word_count"));
+    
+    std::fs::remove_file("tools/py_word_count.py")?;
+    std::fs::remove_file("tools/py_broken.py")?;
+    Ok(())
+}
+
+#[test]
+fn test_python_tools_are_guarded() -> Result<()> {
+    let dir = std::env::temp_dir().join(format!("swarm_python_sandbox_{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(dir.join("work"))?;
+    let python = PythonBackend::new("python3", Duration::from_secs(10), dir.join("work"));
+    let run = |name: &str, body: &str| -> Result<String> {
+        let script = dir.join(format!("{}.py", name));
+        std::fs::write(&script, format!("import os, socket, subprocess\ndef {}():\n    {}\n", name, body))?;
+        python.execute(name, &script, &[])
+    };
+
+    // The workspace is theirs; the rest of the machine isn't
+    assert_eq!(run("note", "open('note.txt', 'w').write('hi'); return open('note.txt').read()")?, "hi");
+    let outside = dir.join("outside.txt");
+    let err = run("escape", &format!("open({:?}, 'w').write('x')", outside)).unwrap_err().to_string();
+    assert!(err.contains("outside the workspace"), "{}", err);
+    assert!(!outside.exists());
+    for (name, body) in [("shell", "return os.system('true')"), ("spawn", "return subprocess.run(['true']).returncode"), ("net", "socket.create_connection(('127.0.0.1', 9))"), ("native", "import ctypes")] {
+        let err = run(name, body).unwrap_err().to_string();
+        assert!(err.contains("PermissionError"), "{}: {}", name, err);
+    }
+    // Answers past the output limit are refused rather than kept whole
+    let capped = python.clone().with_max_output_bytes(64);
+    std::fs::write(dir.join("flood.py"), "def flood():\n    return 'x' * 1000\n")?;
+    let err = capped.execute("flood", &dir.join("flood.py"), &[]).unwrap_err().to_string();
+    assert!(err.contains("more than 64 bytes"), "{}", err);
+    // No bytecode is left behind, and Python tools are always rated HighRisk
+    assert!(!dir.join("__pycache__").exists());
+    assert_eq!(python.validate("def tidy(): return 1", &swarm_thing::safety::SafetyPolicy::default()), swarm_thing::message::ToolSafetyLevel::HighRisk);
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}
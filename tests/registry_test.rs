@@ -0,0 +1,88 @@
+use anyhow::Result;
+use std::time::Duration;
+use swarm_thing::registry::{listen_for_peers, PeerInfo, PeerRegistry};
+use swarm_thing::tools::ToolManager;
+
+fn peer(name: &str, address: &str) -> PeerInfo {
+    PeerInfo {
+        name: name.to_string(),
+        address: address.to_string(),
+        capabilities: vec!["web".to_string()],
+        tools: vec!["square".to_string()],
+        last_seen: 0,
+        missed_heartbeats: 0,
+        public_key: None,
+    }
+}
+
+#[test]
+fn test_peer_registry() {
+    let registry = PeerRegistry::new();
+    assert!(registry.upsert(peer("beta", "127.0.0.1:2")));
+    assert!(!registry.upsert(peer("beta", "127.0.0.1:2")));
+    assert!(registry.get("127.0.0.1:2").unwrap().last_seen > 0);
+
+    // Second-hand information never overwrites fresher first-hand information
+    registry.merge(peer("stale", "127.0.0.1:2"));
+    assert_eq!(registry.get("127.0.0.1:2").unwrap().name, "beta");
+    let mut old = peer("alpha", "127.0.0.1:1");
+    old.last_seen = 1;
+    registry.merge(old);
+
+    let names: Vec<String> = registry.list().into_iter().map(|p| p.name).collect();
+    assert_eq!(names, vec!["alpha", "beta"]);
+    assert!(registry.render().contains("beta @ 127.0.0.1:2 (seen 0s ago) capabilities: web tools: square"));
+
+    assert_eq!(registry.prune(60), 1);
+    assert!(registry.get("127.0.0.1:1").is_none());
+}
+
+#[tokio::test]
+async fn test_announce_to_registry_agent() -> Result<()> {
+    let registry = ToolManager::new()?;
+    let alice = ToolManager::new()?;
+    let bob = ToolManager::new()?;
+    assert!(alice.execute_tool("announce", vec!["127.0.0.1:9851".to_string()])?.contains("start_server first"));
+
+    registry.execute_tool("start_server", vec!["9851".to_string()])?;
+    alice.execute_tool("start_server", vec!["9852".to_string()])?;
+    bob.execute_tool("start_server", vec!["9853".to_string()])?;
+    tokio::time::sleep(Duration::from_millis(500)).await;
+
+    let reply = alice.execute_tool("announce", vec!["127.0.0.1:9851".to_string()])?;
+    assert!(reply.contains("knows 0 other peer(s)"), "{}", reply);
+    let reply = bob.execute_tool("announce", vec!["127.0.0.1:9851".to_string()])?;
+    assert!(reply.contains("knows 1 other peer(s)"), "{}", reply);
+
+    // The registry knows both; bob learned about alice from it
+    let listed = registry.execute_tool("list_peers", vec![])?;
+    assert!(listed.contains("127.0.0.1:9852") && listed.contains("127.0.0.1:9853"), "{}", listed);
+    assert!(bob.execute_tool("list_peers", vec![])?.contains("127.0.0.1:9852"));
+    assert_eq!(alice.execute_tool("list_peers", vec![])?, "No peers known yet");
+    assert_eq!(registry.peers().get("127.0.0.1:9852").unwrap().capabilities, vec!["all"]);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_udp_discovery_listener() -> Result<()> {
+    let registry = PeerRegistry::new();
+    let me = peer("me", "127.0.0.1:9854");
+    tokio::spawn(listen_for_peers(9899, me.clone(), registry.clone()));
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let socket = tokio::net::UdpSocket::bind("127.0.0.1:0").await?;
+    socket.send_to(&serde_json::to_vec(&me)?, "127.0.0.1:9899").await?;
+    socket.send_to(b"not json", "127.0.0.1:9899").await?;
+    socket.send_to(&serde_json::to_vec(&peer("other", "127.0.0.1:1"))?, "127.0.0.1:9899").await?;
+
+    for _ in 0..20 {
+        if registry.get("127.0.0.1:1").is_some() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    // Our own announcement and garbage are ignored
+    let addresses: Vec<String> = registry.list().into_iter().map(|p| p.address).collect();
+    assert_eq!(addresses, vec!["127.0.0.1:1"]);
+    Ok(())
+}
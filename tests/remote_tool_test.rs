@@ -0,0 +1,92 @@
+use anyhow::Result;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use swarm_thing::ipc::{call_peer, router, IpcState, PendingCalls, TaskQueue};
+use swarm_thing::message::{IpcMessage, ToolSafetyLevel};
+use swarm_thing::persona::{Capability, ToolPolicy};
+use swarm_thing::registry::PeerRegistry;
+use swarm_thing::state::StateStore;
+use swarm_thing::threads::PeerThreads;
+use swarm_thing::tools::{InvokePolicy, ToolManager};
+
+async fn serve(name: &str, host: Option<(Arc<ToolManager>, InvokePolicy)>) -> Result<String> {
+    let state_path = std::env::temp_dir().join(format!("swarm_remote_{}_{}.json", name, std::process::id()));
+    let threads = PeerThreads::new(StateStore::open(&state_path)?);
+    let mut state = IpcState::new(Arc::new(Mutex::new(Vec::new())), threads, TaskQueue::new(), PeerRegistry::new(), PathBuf::from("tools"));
+    if let Some((tools, policy)) = host {
+        state = state.with_tool_host(tools, policy);
+    }
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+    let address = listener.local_addr()?.to_string();
+    tokio::spawn(async move {
+        axum::serve(listener, router(state).into_make_service_with_connect_info::<SocketAddr>()).await.unwrap()
+    });
+    Ok(address)
+}
+
+/// Send a `ToolInvoke` and unpack the `ToolOutput`
+async fn invoke(calls: &PendingCalls, address: &str, name: &str, args: &[&str]) -> std::result::Result<String, String> {
+    let message = IpcMessage::tool_invoke(name, args.iter().map(|a| a.to_string()).collect());
+    match call_peer(calls, address, &message, None, Duration::from_secs(5)).await.unwrap() {
+        IpcMessage::ToolOutput { result: Some(result), .. } => Ok(result),
+        IpcMessage::ToolOutput { error, .. } => Err(error.unwrap_or_default()),
+        other => panic!("expected ToolOutput, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn test_tool_invoke_runs_allowed_tools() -> Result<()> {
+    let mut host = ToolManager::new()?;
+    host.create_tool("test_remote_square", "fn test_remote_square(x) { let n = parse_int(x); return n * n; }")?;
+    host.create_tool("test_remote_notes", "fn test_remote_notes(path) { return read_file(path); }")?;
+    host.create_tool("test_remote_search", r#"fn test_remote_search(q) { return search(q); }"#)?;
+    host.set_policy(ToolPolicy { capabilities: Some(vec![Capability::Files]), max_risk: ToolSafetyLevel::HighRisk });
+    let host = Arc::new(host);
+
+    let calls = PendingCalls::new();
+
+    let strict = serve("strict", Some((host.clone(), InvokePolicy::default()))).await?;
+    assert_eq!(invoke(&calls, &strict, "test_remote_square", &["7"]).await, Ok("49".to_string()));
+    assert!(invoke(&calls, &strict, "test_remote_notes", &["Cargo.toml"]).await.unwrap_err().contains("above the remote limit"));
+    assert!(invoke(&calls, &strict, "test_remote_missing", &[]).await.unwrap_err().contains("not found"));
+    assert!(invoke(&calls, &strict, "../etc", &[]).await.unwrap_err().contains("invalid"));
+
+    // The host's persona policy still applies: search needs the Web capability
+    let lax = InvokePolicy { max_risk: ToolSafetyLevel::MediumRisk, deny: vec!["test_remote_square".to_string()] };
+    let lax = serve("lax", Some((host.clone(), lax))).await?;
+    assert!(invoke(&calls, &lax, "test_remote_notes", &["Cargo.toml"]).await.unwrap().contains("[package]"));
+    assert!(invoke(&calls, &lax, "test_remote_search", &["rust"]).await.unwrap_err().contains("Web capability"));
+    assert!(invoke(&calls, &lax, "test_remote_square", &["2"]).await.unwrap_err().contains("not available"));
+    // HighRisk never runs for peers
+    assert!(invoke(&calls, &lax, "write_file", &["x"]).await.unwrap_err().contains("HighRisk"));
+
+    let closed = serve("closed", None).await?;
+    assert!(invoke(&calls, &closed, "test_remote_square", &["2"]).await.unwrap_err().contains("does not run tools"));
+
+    for tool in ["test_remote_square", "test_remote_notes", "test_remote_search"] {
+        std::fs::remove_file(format!("tools/{}.rhai", tool))?;
+    }
+    Ok(())
+}
+
+// The native blocks its caller, so the server needs a worker thread of its own
+#[tokio::test(flavor = "multi_thread")]
+async fn test_invoke_tool_native() -> Result<()> {
+    let mut host = ToolManager::new()?;
+    host.create_tool("test_invoke_greet", r#"fn test_invoke_greet(name) { return "hello " + name; }"#)?;
+    let address = serve("native", Some((Arc::new(host), InvokePolicy::default()))).await?;
+
+    let mut caller = ToolManager::new()?;
+    caller.create_tool("test_invoke_call", &format!(r#"
+    fn test_invoke_call(name) {{
+        return invoke_tool("{}", "test_invoke_greet", name);
+    }}
+    "#, address))?;
+    assert_eq!(caller.execute_tool("test_invoke_call", vec!["swarm".to_string()])?, "hello swarm");
+
+    std::fs::remove_file("tools/test_invoke_greet.rhai")?;
+    std::fs::remove_file("tools/test_invoke_call.rhai")?;
+    Ok(())
+}
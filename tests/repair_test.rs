@@ -0,0 +1,88 @@
+use anyhow::Result;
+use swarm_thing::agent::Agent;
+use swarm_thing::headless::Runner;
+use swarm_thing::journal::{Journal, JournalEvent, Replay};
+use swarm_thing::llm::{Completion, LlmClient, Usage};
+use swarm_thing::repair::repair_tools;
+use swarm_thing::replay::RecordedResponses;
+use swarm_thing::tools::{ResponseAction, ToolManager};
+
+fn said(text: &str) -> Result<Completion, String> {
+    Ok(Completion { text: text.to_string(), usage: Usage { input_tokens: 10, output_tokens: 2 }, model: "mock".to_string(), cached: false })
+}
+
+async fn scripted(responses: Vec<Result<Completion, String>>) -> Result<Agent> {
+    Ok(Agent::with_client(LlmClient::replaying(RecordedResponses::new(responses)).await?, "You use tools."))
+}
+
+#[tokio::test]
+async fn test_repair_fixes_tool_that_does_not_compile() -> Result<()> {
+    let dir = std::env::temp_dir().join(format!("swarm_repair_journal_{}", std::process::id()));
+    let mut agent = scripted(vec![
+        said("Sorry:\n```rhai\nfn test_fix_compile(x) { x + }\n```"),
+        said("Fixed:\n```rhai\n// filename: test_fix_compile\nfn test_fix_compile(x) { parse_int(x) + 1 }\n```"),
+    ]).await?;
+    agent.set_journal(Some(Journal::open(&dir)?));
+    let mut tools = ToolManager::new()?;
+
+    let actions = tools.act_on("```rhai\n// filename: test_fix_compile\nfn test_fix_compile(x) { x * }\n```", None);
+    let broken = tools.broken_tool(&actions[0]).expect("the tool does not compile");
+    assert_eq!((broken.name.as_str(), broken.args.as_ref()), ("test_fix_compile", None));
+
+    let repaired = repair_tools(&mut agent, &mut tools, actions, 2).await;
+    assert!(matches!(&repaired[..], [ResponseAction::Created { outcome: Ok(_), .. }]), "{:?}", repaired);
+    assert_eq!(tools.execute_tool("test_fix_compile", vec!["1".to_string()])?, "2");
+    // The first reply still didn't compile; it was shown to the model again with its error
+    assert!(agent.history()[2].content.contains("does not compile"), "{}", agent.history()[2].content);
+    assert!(agent.history()[2].content.contains("fn test_fix_compile(x) { x + }"), "{}", agent.history()[2].content);
+
+    let replay = Replay::load(std::fs::read_dir(&dir)?.next().unwrap()?.path())?;
+    let fixes: Vec<(usize, String)> = replay.entries.iter()
+        .filter_map(|e| match &e.event {
+            JournalEvent::ToolFix { attempt, outcome, .. } => Some((*attempt, outcome.clone())),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(fixes.len(), 2);
+    assert!(fixes[0].1.starts_with("fix failed"), "{:?}", fixes);
+    assert_eq!(fixes[1], (2, "fixed".to_string()));
+
+    std::fs::remove_file("tools/test_fix_compile.rhai")?;
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_runner_repairs_runtime_errors() -> Result<()> {
+    let mut tools = ToolManager::new()?;
+    tools.create_tool("test_fix_runtime", "fn test_fix_runtime(x) { throw \"cannot handle \" + x }")?;
+    let agent = scripted(vec![
+        said("[TOOL: test_fix_runtime(5)] [TOOL: test_fix_missing(1)]"),
+        said("```rhai\nfn test_fix_runtime(x) { parse_int(x) * 10 }\n```"),
+        said("It is 50."),
+    ]).await?;
+    let mut runner = Runner::new(agent, tools);
+    let outcome = runner.run("ten times 5").await;
+    assert!(outcome.ok, "{}", outcome.render());
+    assert_eq!(outcome.answer, "It is 50.");
+    // The fixed tool ran again; a missing tool has no code to fix and is left alone
+    let steps: Vec<(&str, bool, &str)> = outcome.tools.iter().map(|t| (t.name.as_str(), t.ok, t.output.as_str())).collect();
+    assert_eq!(steps[0], ("test_fix_runtime", true, "50"));
+    assert_eq!((steps[1].0, steps[1].1), ("test_fix_missing", false));
+    assert!(runner.agent().history()[2].content.contains("failed when called as test_fix_runtime(5)"));
+
+    // Out of attempts, the last failure stands
+    let mut tools = ToolManager::new()?;
+    tools.create_tool("test_fix_runtime", "fn test_fix_runtime(x) { throw \"cannot handle \" + x }")?;
+    let mut runner = Runner::new(scripted(vec![
+        said("[TOOL: test_fix_runtime(1)]"),
+        said("```rhai\nfn test_fix_runtime(x) { throw \"still \" + x }\n```"),
+        said("I could not fix it."),
+    ]).await?, tools).with_fix_attempts(1);
+    let outcome = runner.run("try").await;
+    assert_eq!((outcome.tools[0].ok, outcome.answer.as_str()), (false, "I could not fix it."));
+    assert!(outcome.tools[0].output.contains("still 1"), "{}", outcome.tools[0].output);
+
+    std::fs::remove_file("tools/test_fix_runtime.rhai")?;
+    Ok(())
+}
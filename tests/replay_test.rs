@@ -0,0 +1,73 @@
+use anyhow::Result;
+use swarm_thing::journal::{Journal, JournalEvent, Replay};
+use swarm_thing::replay::{replay_session, RecordedResponses};
+use swarm_thing::tools::ToolManager;
+
+fn response(text: &str) -> JournalEvent {
+    JournalEvent::LlmResponse {
+        hash: "h".to_string(),
+        model: "ollama:llama3.1".to_string(),
+        text: text.to_string(),
+        input_tokens: 10,
+        output_tokens: 2,
+        cached: false,
+        duration_ms: 900,
+    }
+}
+
+/// Turn 1 squares 4 with the `square` tool, turn 2 hits a provider error
+fn record(dir: &std::path::Path, result: &str) -> Result<Replay> {
+    let journal = Journal::open(dir)?;
+    journal.start_turn();
+    journal.record(JournalEvent::Input { text: "square 4".to_string() })?;
+    journal.record(response("Sure. [TOOL: square(4)]"))?;
+    journal.record(JournalEvent::ToolCall { name: "square".to_string(), args: vec!["4".to_string()] })?;
+    journal.record(JournalEvent::ToolResult { name: "square".to_string(), output: result.to_string(), ok: true, duration_ms: 1 })?;
+    journal.start_turn();
+    journal.record(JournalEvent::Input { text: "again".to_string() })?;
+    journal.record(JournalEvent::LlmError { hash: "h2".to_string(), error: "Ollama API error: 500".to_string(), duration_ms: 5 })?;
+    Replay::load(journal.path())
+}
+
+#[tokio::test]
+async fn test_replay_reproduces_session() -> Result<()> {
+    let dir = std::env::temp_dir().join(format!("swarm_replay_{}", std::process::id()));
+    let replay = record(&dir, "16")?;
+    let mut tools = ToolManager::new()?;
+
+    let report = replay_session(&replay, &mut tools, true).await?;
+    assert!(report.is_faithful(), "{}", report.render());
+    assert_eq!((report.turns, report.steps), (2, 3));
+    let dry = replay_session(&replay, &mut tools, false).await?;
+    assert!(dry.is_faithful(), "{}", dry.render());
+
+    let responses = RecordedResponses::from_replay(&replay);
+    assert_eq!(responses.next()?.text, "Sure. [TOOL: square(4)]");
+    assert_eq!(responses.next().unwrap_err().to_string(), "Ollama API error: 500");
+    assert!(responses.next().unwrap_err().to_string().contains("ran out of recorded responses"));
+
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_replay_reports_divergences() -> Result<()> {
+    let dir = std::env::temp_dir().join(format!("swarm_replay_diverge_{}", std::process::id()));
+    // As if `square` used to return 17
+    let replay = record(&dir, "17")?;
+    let mut tools = ToolManager::new()?;
+
+    let report = replay_session(&replay, &mut tools, true).await?;
+    assert!(!report.is_faithful());
+    assert_eq!(report.divergences.len(), 1);
+    assert_eq!(report.divergences[0].turn, 1);
+    assert_eq!(report.divergences[0].expected.as_deref(), Some("result square ok: 17"));
+    assert_eq!(report.divergences[0].actual.as_deref(), Some("result square ok: 16"));
+    assert!(report.render().contains("turn 1: expected `result square ok: 17`, got `result square ok: 16`"), "{}", report.render());
+
+    // Parsing alone doesn't see results
+    assert!(replay_session(&replay, &mut tools, false).await?.is_faithful());
+
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}
@@ -0,0 +1,131 @@
+use anyhow::Result;
+use std::path::PathBuf;
+use swarm_thing::pdf::{pdf_pages, write_pdf, PdfLine};
+use swarm_thing::report::{Report, ReportFormat, ReportStore};
+use swarm_thing::tools::ToolManager;
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("swarm_report_{}_{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    dir
+}
+
+fn sample() -> Report {
+    let mut report = Report { title: Some("Battery Market".to_string()), ..Default::default() };
+    report.add("Findings", "Prices fell 14% in 2023.", Some("https://example.com/prices"));
+    report.add("Risks", "Lithium supply is <concentrated> & volatile.", None);
+    report.add("findings", "Sodium-ion cells are entering production.", Some("https://example.com/sodium"));
+    report.add("Findings", "Storage demand doubled.", Some("https://example.com/prices"));
+    report
+}
+
+#[test]
+fn test_markdown_numbers_sources_by_first_citation() {
+    let report = sample();
+    assert_eq!(report.sources(), ["https://example.com/prices", "https://example.com/sodium"]);
+    assert_eq!(report.to_markdown(), "# Battery Market
+
+## Findings
+
+Prices fell 14% in 2023. [1]
+
+Sodium-ion cells are entering production. [2]
+
+Storage demand doubled. [1]
+
+## Risks
+
+Lithium supply is <concentrated> & volatile.
+
+## Sources
+
+1. https://example.com/prices
+2. https://example.com/sodium
+");
+}
+
+#[test]
+fn test_html_is_escaped_and_linked() {
+    let html = sample().to_html();
+    assert!(html.starts_with("<!DOCTYPE html>"));
+    assert!(html.contains("<title>Battery Market</title>"));
+    assert!(html.contains("<p>Prices fell 14% in 2023. <sup><a href=\"#source-1\">[1]</a></sup></p>"));
+    assert!(html.contains("<p>Lithium supply is &lt;concentrated&gt; &amp; volatile.</p>"));
+    assert!(html.contains("<li id=\"source-2\"><a href=\"https://example.com/sodium\">https://example.com/sodium</a></li>"));
+}
+
+#[test]
+fn test_pdf_reports_read_back() -> Result<()> {
+    let pages = pdf_pages(&sample().to_pdf())?;
+    assert_eq!(pages.len(), 1);
+    assert!(pages[0].starts_with("Battery Market\nFindings\nPrices fell 14% in 2023. [1]"), "{}", pages[0]);
+    assert!(pages[0].contains("Sources\n1. https://example.com/prices"), "{}", pages[0]);
+
+    // Long text wraps and spills onto more pages; punctuation outside ASCII survives
+    let long = vec![PdfLine::Text("lorem ipsum \u{201c}dolor\u{201d} \u{2014} sit amet ".repeat(40)); 20];
+    let pages = pdf_pages(&write_pdf(&long))?;
+    assert!(pages.len() > 1, "{} page(s)", pages.len());
+    assert!(pages[0].lines().all(|line| line.chars().count() < 100), "{}", pages[0]);
+    assert!(pages[0].starts_with("lorem ipsum \u{201c}dolor\u{201d} \u{2014} sit amet"), "{}", pages[0]);
+    Ok(())
+}
+
+#[test]
+fn test_store_keeps_findings_and_exports() -> Result<()> {
+    let dir = temp_dir("store");
+    let store = ReportStore::open(dir.join("report.json"))?;
+    assert!(store.export(ReportFormat::Markdown, &dir.join("empty.md")).unwrap_err().to_string().contains("The report is empty"));
+
+    store.set_title("Battery Market")?;
+    assert_eq!(store.add("Findings", "Prices fell.", Some("https://example.com/prices"))?,
+               "Added to 'Findings' (1 finding(s) there, 1 section(s) in the report)");
+    assert!(store.add(" ", "content", None).is_err());
+    // Another handle sees the same report
+    let reopened = ReportStore::open(store.path())?;
+    assert_eq!(reopened.load()?.sections[0].findings[0].content, "Prices fell.");
+
+    let exported = reopened.export(ReportFormat::Html, &dir.join("out/report.html"))?;
+    assert_eq!(exported, format!("Exported 'Battery Market' (1 sections, 1 sources) to {}", dir.join("out/report.html").display()));
+    assert!(std::fs::read_to_string(dir.join("out/report.html"))?.contains("<h2>Findings</h2>"));
+    // The extension has to match the format, so a report can't be written over a tool
+    assert_eq!(store.export(ReportFormat::Pdf, &dir.join("tools/x.rhai")).unwrap_err().to_string(), "A pdf report must be saved as .pdf");
+    assert!("docx".parse::<ReportFormat>().is_err());
+
+    store.clear()?;
+    assert!(store.load()?.is_empty());
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+
+#[test]
+fn test_report_natives() -> Result<()> {
+    let dir = temp_dir("natives");
+    std::env::set_var("AGENT_REPORT", dir.join("report.json"));
+    let mut manager = ToolManager::new()?;
+    manager.create_tool("test_report", r#"
+    fn test_report(path) {
+        report_clear();
+        report_title("Coffee");
+        report_add("Findings", "Espresso uses pressure.", "https://example.com/espresso");
+        return report_export("pdf", path);
+    }
+    "#)?;
+
+    let path = "report_test_coffee.pdf";
+    let result = manager.execute_tool("test_report", vec![path.to_string()])?;
+    assert!(result.starts_with("Exported 'Coffee' (1 sections, 1 sources)"), "got {}", result);
+    assert!(pdf_pages(&std::fs::read(path)?)?[0].contains("Espresso uses pressure. [1]"));
+
+    // Tool-call markers pass one string
+    let added = manager.execute_tool("report_add", vec!["Risks, Beans are perishable, and prices swing.".to_string()])?;
+    assert!(added.starts_with("Added to 'Risks'"), "got {}", added);
+    let shown = manager.execute_tool("report_show", vec![])?;
+    assert!(shown.contains("## Risks\n\nBeans are perishable, and prices swing."), "got {}", shown);
+    let refused = manager.execute_tool("report_export", vec!["markdown, ../outside.md".to_string()])?;
+    assert!(refused.starts_with("Error"), "got {}", refused);
+
+    std::fs::remove_file(path)?;
+    std::fs::remove_file("tools/test_report.rhai")?;
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}
@@ -0,0 +1,85 @@
+use anyhow::Result;
+use axum::{extract::State, routing::post, Json, Router};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use swarm_thing::llm::{GenerationConfig, LlmClient, Message};
+use swarm_thing::response_cache::ResponseCache;
+
+// Ollama-compatible endpoint that numbers its replies so cache hits are visible
+async fn mock_chat(State(calls): State<Arc<AtomicUsize>>, Json(_body): Json<serde_json::Value>) -> Json<serde_json::Value> {
+    let n = calls.fetch_add(1, Ordering::SeqCst) + 1;
+    Json(serde_json::json!({ "message": { "role": "assistant", "content": format!("reply {}", n) } }))
+}
+
+async fn mock_server(calls: Arc<AtomicUsize>) -> Result<String> {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+    let url = format!("http://{}/api/chat", listener.local_addr()?);
+    tokio::spawn(async move {
+        let app = Router::new().route("/api/chat", post(mock_chat)).with_state(calls);
+        axum::serve(listener, app).await.unwrap();
+    });
+    Ok(url)
+}
+
+fn user(content: &str) -> Vec<Message> {
+    vec![Message::user(content)]
+}
+
+#[test]
+fn test_cache_key_covers_request() {
+    let generation = GenerationConfig::default();
+    let key = ResponseCache::key("ollama:llama3.1", &user("hi"), Some("sys"), &generation);
+    assert_eq!(key.len(), 64);
+    assert_eq!(key, ResponseCache::key("ollama:llama3.1", &user("hi"), Some("sys"), &generation));
+    assert_ne!(key, ResponseCache::key("ollama:other", &user("hi"), Some("sys"), &generation));
+    assert_ne!(key, ResponseCache::key("ollama:llama3.1", &user("hi"), None, &generation));
+    let hot = GenerationConfig { temperature: Some(1.0), ..Default::default() };
+    assert_ne!(key, ResponseCache::key("ollama:llama3.1", &user("hi"), Some("sys"), &hot));
+}
+
+#[tokio::test]
+async fn test_identical_requests_served_from_cache() -> Result<()> {
+    let dir = std::env::temp_dir().join(format!("llm_cache_test_{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    let calls = Arc::new(AtomicUsize::new(0));
+    let url = mock_server(calls.clone()).await?;
+
+    let cache = ResponseCache::open(&dir, None)?;
+    let client = LlmClient::with_model("ollama", None).await?
+        .with_ollama_url(&url)
+        .with_cache(Some(cache.clone()));
+
+    assert_eq!(client.chat(user("hi"), None).await?, "reply 1");
+    assert_eq!(client.chat(user("hi"), None).await?, "reply 1");
+    assert_eq!(client.chat(user("other"), None).await?, "reply 2");
+    assert_eq!(calls.load(Ordering::SeqCst), 2);
+
+    // Entries persist on disk and are shared by a fresh client
+    let fresh = LlmClient::with_model("ollama", None).await?
+        .with_ollama_url(&url)
+        .with_cache(Some(ResponseCache::open(&dir, None)?));
+    assert_eq!(fresh.chat(user("hi"), None).await?, "reply 1");
+    assert_eq!(calls.load(Ordering::SeqCst), 2);
+
+    assert_eq!(cache.clear()?, 2);
+    assert_eq!(client.chat(user("hi"), None).await?, "reply 3");
+
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+
+#[test]
+fn test_expired_entries_are_ignored() -> Result<()> {
+    let dir = std::env::temp_dir().join(format!("llm_cache_ttl_test_{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+
+    let cache = ResponseCache::open(&dir, Some(Duration::from_secs(1)))?;
+    cache.put("k", "cached")?;
+    assert_eq!(cache.get("k").as_deref(), Some("cached"));
+    std::thread::sleep(Duration::from_millis(2100));
+    assert_eq!(cache.get("k"), None);
+
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}
@@ -0,0 +1,105 @@
+use anyhow::Result;
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::{routing::post, Json, Router};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use swarm_thing::llm::{LlmClient, Message};
+use swarm_thing::retry::RetryPolicy;
+
+/// Ollama-compatible endpoint that fails the first `fail_first` requests with `status`
+async fn flaky_chat(
+    State((calls, fail_first, status)): State<(Arc<AtomicUsize>, usize, StatusCode)>,
+    Json(body): Json<serde_json::Value>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    let call = calls.fetch_add(1, Ordering::SeqCst);
+    if call < fail_first {
+        return (status, Json(serde_json::json!({ "error": "unavailable" })));
+    }
+    let content = format!("{} answered", body["model"].as_str().unwrap_or_default());
+    (StatusCode::OK, Json(serde_json::json!({ "message": { "role": "assistant", "content": content } })))
+}
+
+async fn start_mock(fail_first: usize, status: StatusCode) -> Result<(String, Arc<AtomicUsize>)> {
+    let calls = Arc::new(AtomicUsize::new(0));
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+    let url = format!("http://{}/api/chat", listener.local_addr()?);
+    let state = (calls.clone(), fail_first, status);
+    tokio::spawn(async move {
+        let app = Router::new().route("/api/chat", post(flaky_chat)).with_state(state);
+        axum::serve(listener, app).await.unwrap();
+    });
+    Ok((url, calls))
+}
+
+fn fast_retry(max_retries: u32) -> RetryPolicy {
+    RetryPolicy {
+        max_retries,
+        base_delay: Duration::from_millis(5),
+        max_delay: Duration::from_millis(50),
+    }
+}
+
+fn hello() -> Vec<Message> {
+    vec![Message::user("hello")]
+}
+
+#[tokio::test]
+async fn test_retry_transient_errors() -> Result<()> {
+    let (url, calls) = start_mock(2, StatusCode::SERVICE_UNAVAILABLE).await?;
+    let client = LlmClient::with_model("ollama", Some("primary".to_string())).await?
+        .with_ollama_url(&url)
+        .with_retry(fast_retry(3));
+    
+    assert_eq!(client.chat(hello(), None).await?, "primary answered");
+    assert_eq!(calls.load(Ordering::SeqCst), 3);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_client_errors_are_not_retried() -> Result<()> {
+    let (url, calls) = start_mock(usize::MAX, StatusCode::BAD_REQUEST).await?;
+    let client = LlmClient::with_model("ollama", None).await?
+        .with_ollama_url(&url)
+        .with_retry(fast_retry(3));
+    
+    assert!(client.chat(hello(), None).await.is_err());
+    assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_failover_to_secondary() -> Result<()> {
+    let (primary_url, primary_calls) = start_mock(usize::MAX, StatusCode::TOO_MANY_REQUESTS).await?;
+    let (secondary_url, _) = start_mock(0, StatusCode::OK).await?;
+    
+    let secondary = LlmClient::with_model("ollama", Some("secondary".to_string())).await?
+        .with_ollama_url(&secondary_url)
+        .with_retry(fast_retry(0));
+    let client = LlmClient::with_model("ollama", Some("primary".to_string())).await?
+        .with_ollama_url(&primary_url)
+        .with_retry(fast_retry(2))
+        .with_fallback(secondary);
+    
+    assert_eq!(client.chat(hello(), None).await?, "secondary answered");
+    assert_eq!(primary_calls.load(Ordering::SeqCst), 3);
+
+    Ok(())
+}
+
+#[test]
+fn test_backoff_grows_and_is_capped() {
+    let policy = RetryPolicy {
+        max_retries: 10,
+        base_delay: Duration::from_millis(100),
+        max_delay: Duration::from_secs(1),
+    };
+    let first = policy.delay(0);
+    assert!(first >= Duration::from_millis(100) && first <= Duration::from_millis(150));
+    let third = policy.delay(2);
+    assert!(third >= Duration::from_millis(400) && third <= Duration::from_millis(600));
+    assert!(policy.delay(20) <= Duration::from_millis(1500));
+}
@@ -0,0 +1,72 @@
+use anyhow::Result;
+use std::sync::{Arc, Mutex};
+use swarm_thing::message::ToolSafetyLevel;
+use swarm_thing::persona::Capability;
+use swarm_thing::review::{diff_lines, DiffLine, ToolReview};
+use swarm_thing::safety::SafetyPolicy;
+use swarm_thing::tools::{ConfirmPolicy, ResponseAction, ToolManager};
+
+#[test]
+fn test_review_diff_and_safety_report() {
+    let diff = diff_lines("fn a(x) {\n  x + 1\n}\n", "fn a(x) {\n  write_file(x, \"hi\");\n  x + 1\n}\n");
+    assert_eq!(diff, vec![
+        DiffLine::Same("fn a(x) {".to_string()),
+        DiffLine::Added("  write_file(x, \"hi\");".to_string()),
+        DiffLine::Same("  x + 1".to_string()),
+        DiffLine::Same("}".to_string()),
+    ]);
+    assert_eq!(diff_lines("a\nb\n", "a\nc\n")[1..], [DiffLine::Removed("b".to_string()), DiffLine::Added("c".to_string())]);
+
+    let review = ToolReview::new("a", "fn a(x) { write_file(x, \"hi\") }", false, Some("fn a(x) { x }".to_string()), &SafetyPolicy::default());
+    assert_eq!(review.safety_level, ToolSafetyLevel::HighRisk);
+    assert_eq!(review.capabilities, vec![Capability::Files]);
+    let report = review.render(false);
+    assert!(report.starts_with("Replace tool 'a'\n  Safety level: HighRisk\n  Capabilities: Files\n"), "{}", report);
+    assert!(report.ends_with("- fn a(x) { x }\n+ fn a(x) { write_file(x, \"hi\") }\n"), "{}", report);
+    // A new tool is all additions
+    let new = ToolReview::new("b", "fn b() { 1 }", false, None, &SafetyPolicy::default());
+    assert!(new.render(false).starts_with("Install tool 'b'") && new.diff() == vec![DiffLine::Added("fn b() { 1 }".to_string())]);
+}
+
+#[test]
+fn test_agent_written_tools_are_reviewed_before_install() -> Result<()> {
+    let mut tools = ToolManager::new()?;
+    tools.set_confirm_policy(ConfirmPolicy::default());
+    let reviewed = Arc::new(Mutex::new(Vec::new()));
+    let answer = Arc::new(Mutex::new(true));
+    let (reviewed_clone, answer_clone) = (reviewed.clone(), answer.clone());
+    tools.set_install_approver(Arc::new(move |review: &ToolReview| {
+        reviewed_clone.lock().unwrap().push((review.name.clone(), review.previous.is_some(), review.safety_level.clone()));
+        *answer_clone.lock().unwrap()
+    }));
+    let block = |code: &str| format!("```rhai\n// filename: test_review_tool\n{}\n```", code);
+
+    // A new Safe tool is auto-approved by the confirm policy
+    let created = tools.act_on(&block("fn test_review_tool(x) { x }"), None);
+    assert!(matches!(&created[..], [ResponseAction::Created { outcome: Ok(_), .. }]), "{:?}", created);
+    assert!(reviewed.lock().unwrap().is_empty());
+
+    // Replacing it is reviewed; declined, the installed version stays
+    *answer.lock().unwrap() = false;
+    let declined = tools.act_on(&block("fn test_review_tool(x) { parse_int(x) + 1 }"), None);
+    assert!(matches!(&declined[..], [ResponseAction::Created { outcome: Err(e), .. }] if e.contains("declined to install tool 'test_review_tool'")), "{:?}", declined);
+    assert!(tools.tool_source("test_review_tool").unwrap().contains("{ x }"));
+    assert_eq!(reviewed.lock().unwrap()[0], ("test_review_tool".to_string(), true, ToolSafetyLevel::Safe));
+
+    // The same code again needs no review; approved, the replacement installs
+    tools.act_on(&block("fn test_review_tool(x) { x }"), None);
+    assert_eq!(reviewed.lock().unwrap().len(), 1);
+    *answer.lock().unwrap() = true;
+    tools.act_on(&block("fn test_review_tool(x) { parse_int(x) + 1 }"), None);
+    assert_eq!(tools.execute_tool("test_review_tool", vec!["1".to_string()])?, "2");
+
+    // With confirmations off nothing is asked
+    tools.set_confirm_policy(ConfirmPolicy::auto_approve());
+    *answer.lock().unwrap() = false;
+    tools.act_on(&block("fn test_review_tool(x) { parse_int(x) + 2 }"), None);
+    assert_eq!(reviewed.lock().unwrap().len(), 2);
+    assert_eq!(tools.execute_tool("test_review_tool", vec!["1".to_string()])?, "3");
+
+    std::fs::remove_file("tools/test_review_tool.rhai")?;
+    Ok(())
+}
@@ -0,0 +1,104 @@
+use anyhow::Result;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use swarm_thing::inbox::Inbox;
+use swarm_thing::ipc::{call_peer, router, send_reply, IpcState, PendingCalls, TaskQueue};
+use swarm_thing::message::IpcMessage;
+use swarm_thing::registry::PeerRegistry;
+use swarm_thing::state::StateStore;
+use swarm_thing::threads::PeerThreads;
+use swarm_thing::tools::ToolManager;
+
+/// Serve an agent on a random port, returning its address
+async fn serve(name: &str, inbox: Inbox, calls: PendingCalls) -> Result<String> {
+    let state_path = std::env::temp_dir().join(format!("swarm_rpc_{}_{}.json", name, std::process::id()));
+    let threads = PeerThreads::new(StateStore::open(&state_path)?);
+    let state = IpcState::new(Arc::new(Mutex::new(Vec::new())), threads, TaskQueue::new(), PeerRegistry::new(), PathBuf::from("tools"))
+        .with_inbox(inbox)
+        .with_calls(calls);
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+    let address = listener.local_addr()?.to_string();
+    tokio::spawn(async move {
+        axum::serve(listener, router(state).into_make_service_with_connect_info::<SocketAddr>()).await.unwrap()
+    });
+    Ok(address)
+}
+
+#[tokio::test]
+async fn test_call_peer_correlates_replies() -> Result<()> {
+    let calls = PendingCalls::new();
+    let caller = serve("caller", Inbox::new(), calls.clone()).await?;
+    let callee_inbox = Inbox::new();
+    let callee = serve("callee", callee_inbox.clone(), PendingCalls::new()).await?;
+
+    // Structured messages are answered in the HTTP response
+    let answer = call_peer(&calls, &callee, &IpcMessage::tool_request("no_such_tool"), Some(caller.clone()), Duration::from_secs(5)).await?;
+    assert!(matches!(answer, IpcMessage::ToolRefused { reason, .. } if reason == "tool not found"));
+
+    // Text waits for the peer's agent to reply to the message's id
+    let waiting = {
+        let calls = calls.clone();
+        let (callee, caller) = (callee.clone(), caller.clone());
+        tokio::spawn(async move {
+            call_peer(&calls, &callee, &IpcMessage::text("what is 6 * 7?"), Some(caller), Duration::from_secs(5)).await
+        })
+    };
+    let mut question = None;
+    for _ in 0..50 {
+        question = callee_inbox.unread().into_iter().next();
+        if question.is_some() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    let question = question.expect("question should reach the inbox");
+    assert!(callee_inbox.render_unread().contains("(awaiting reply)"));
+    let call_id = question.call_id.expect("calls carry their id");
+    send_reply(&caller, &call_id, &IpcMessage::text("42"), Some(callee.clone())).await?;
+    assert_eq!(waiting.await??, IpcMessage::text("42"));
+    assert_eq!(calls.pending(), 0);
+
+    // Unanswered calls time out and are forgotten; a late reply is then ordinary mail
+    let err = call_peer(&calls, &callee, &IpcMessage::text("anyone?"), Some(caller.clone()), Duration::from_millis(300)).await.unwrap_err();
+    assert!(err.to_string().contains("No reply"), "{}", err);
+    assert_eq!(calls.pending(), 0);
+
+    // Without a server of our own, a text call can't be answered
+    let err = call_peer(&calls, &callee, &IpcMessage::text("hello"), None, Duration::from_secs(1)).await.unwrap_err();
+    assert!(err.to_string().contains("no server"), "{}", err);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_call_peer_and_reply_natives() -> Result<()> {
+    let mut callee = ToolManager::new()?;
+    callee.execute_tool("start_server", vec!["9887".to_string()])?;
+    let mut caller = ToolManager::new()?;
+    caller.execute_tool("start_server", vec!["9888".to_string()])?;
+    tokio::time::sleep(Duration::from_millis(500)).await;
+
+    caller.create_tool("test_rpc_call", r#"
+    fn test_rpc_call(text) {
+        return call_peer("127.0.0.1:9887", text, 5);
+    }
+    "#)?;
+    callee.create_tool("test_rpc_reply", r#"fn test_rpc_reply(id) { return reply_message(id, "pong"); }"#)?;
+
+    let replier = std::thread::spawn(move || -> Result<String> {
+        for _ in 0..50 {
+            if let Some(message) = callee.inbox().unread().into_iter().next() {
+                return callee.execute_tool("test_rpc_reply", vec![message.id.to_string()]);
+            }
+            std::thread::sleep(Duration::from_millis(50));
+        }
+        Ok("no message arrived".to_string())
+    });
+    assert_eq!(caller.execute_tool("test_rpc_call", vec!["ping".to_string()])?, "pong");
+    assert!(replier.join().unwrap()?.starts_with("Replied to message #"));
+
+    std::fs::remove_file("tools/test_rpc_call.rhai")?;
+    std::fs::remove_file("tools/test_rpc_reply.rhai")?;
+    Ok(())
+}
@@ -0,0 +1,71 @@
+use anyhow::Result;
+use std::sync::{Arc, Mutex};
+use swarm_thing::config::Config;
+use swarm_thing::llm::{Completion, LlmClient, Usage};
+use swarm_thing::message::ToolSafetyLevel;
+use swarm_thing::replay::RecordedResponses;
+use swarm_thing::runtime::{AgentEvent, AgentRuntime};
+use swarm_thing::tools::ToolManager;
+
+fn said(text: &str) -> Result<Completion, String> {
+    Ok(Completion { text: text.to_string(), usage: Usage { input_tokens: 10, output_tokens: 2 }, model: "mock".to_string(), cached: false })
+}
+
+async fn runtime(responses: Vec<Result<Completion, String>>) -> Result<AgentRuntime> {
+    AgentRuntime::builder()
+        .config(Config::default())
+        .tools(ToolManager::new()?)
+        .llm(LlmClient::replaying(RecordedResponses::new(responses)).await?)
+        .journal(None)
+        .build()
+        .await
+}
+
+#[tokio::test]
+async fn test_handle_input_reports_events() -> Result<()> {
+    let mut runtime = runtime(vec![said("[TOOL: square(4)]"), said("4 squared is 16."), said("Hello")]).await?;
+    assert!(runtime.system_prompt().contains("square"), "{}", runtime.system_prompt());
+
+    let turn = runtime.handle_input("what is 4 squared?").await;
+    assert!(turn.is_ok(), "{:?}", turn.error);
+    assert_eq!(turn.events.len(), 4, "{:?}", turn.events);
+    assert!(matches!(&turn.events[1], AgentEvent::ToolExecuted { name, ok: true, output, .. } if name == "square" && output == "16"));
+    assert_eq!(turn.final_answer(), Some("4 squared is 16."));
+    assert_eq!(runtime.agent().history()[2].content, "Tool Output: 16");
+    let json = serde_json::to_value(&turn.events[1])?;
+    assert_eq!((json["event"].as_str(), json["args"][0].as_str()), (Some("tool_executed"), Some("4")));
+
+    // The conversation carries on; a failing model ends the turn with an error and no answer
+    assert_eq!(runtime.handle_input("hi").await.final_answer(), Some("Hello"));
+    let failed = runtime.handle_input("again").await;
+    assert!(failed.error.as_deref().is_some_and(|e| e.contains("ran out of recorded responses")), "{:?}", failed.error);
+    assert_eq!(failed.final_answer(), None);
+
+    // A helper shares the model and prompt but starts its own conversation
+    assert!(runtime.helper_agent().history().is_empty());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_helper_tools_ask_the_runtime_approvers() -> Result<()> {
+    let runtime = runtime(Vec::new()).await?;
+    let helper = runtime.helper_tools()?;
+    assert!(helper.is_unattended());
+    let write = || helper.execute_tool("write_file", vec!["runtime_test_helper.txt".to_string(), "hi".to_string()]);
+
+    // Nobody to ask: HighRisk runs are refused, safe ones go ahead
+    let refused = write().unwrap_err();
+    assert_eq!(refused.to_string(), "write_file(runtime_test_helper.txt, hi) needs an operator to confirm it, and there is none");
+    assert_eq!(helper.execute_tool("square", vec!["3".to_string()])?, "9");
+
+    // An approver installed on the runtime's tools, even afterwards, is asked for the helper's runs
+    let asked = Arc::new(Mutex::new(Vec::new()));
+    let asked_clone = asked.clone();
+    runtime.tools().set_execution_approver(Arc::new(move |call: &str, level: &ToolSafetyLevel| {
+        asked_clone.lock().unwrap().push(format!("{:?} {}", level, call));
+        false
+    }));
+    assert_eq!(write().unwrap_err().to_string(), "The operator declined to run write_file(runtime_test_helper.txt, hi)");
+    assert_eq!(*asked.lock().unwrap(), ["HighRisk write_file(runtime_test_helper.txt, hi)"]);
+    Ok(())
+}
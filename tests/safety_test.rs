@@ -0,0 +1,176 @@
+use anyhow::Result;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use swarm_thing::persona::Capability;
+use swarm_thing::message::ToolSafetyLevel;
+use swarm_thing::safety::{Approval, RiskKeywords, SafetyPolicy};
+use swarm_thing::tools::ToolManager;
+
+#[test]
+fn test_policy_file_parses_and_checks() -> Result<()> {
+    let path = std::env::temp_dir().join(format!("swarm_policy_{}.toml", std::process::id()));
+    std::fs::write(&path, r#"
+banned_functions = ["run_command"]
+max_tool_bytes = 100
+allowed_hosts = ["*.example.com", "localhost"]
+
+[approval]
+files = "confirm"
+replication = "deny"
+
+[clone]
+dirs = ["/tmp/clones"]
+
+[rate_limits]
+scrape_url = 10
+send_message = 0
+"#)?;
+    let policy = SafetyPolicy::load(&path)?;
+    assert_eq!(policy.approval.get(&Capability::Files), Some(&Approval::Confirm));
+    assert_eq!(policy.approval_for("fn t() { read_file(\"a\"); clone_agent(\"b\") }"), Some((Capability::Replication, Approval::Deny)));
+    assert!(policy.refusal("fn t() { run_command(\"ls\") }").unwrap().contains("run_command"));
+    assert!(policy.refusal(&"x".repeat(101)).unwrap().contains("100 byte limit"));
+
+    assert!(policy.allows_host("https://api.example.com/v1"));
+    assert!(policy.allows_host("example.com"));
+    assert!(policy.allows_host("localhost:8080"));
+    assert!(!policy.allows_host("http://evil.com/example.com"));
+    assert!(!policy.allows_host("http://notexample.com"));
+
+    assert!(policy.check_clone(Path::new("/tmp/clones/a")).is_ok());
+    assert!(policy.check_clone(Path::new("/tmp/clones/../elsewhere")).is_err());
+
+    // A limit of 0 is no limit
+    assert_eq!(policy.rate_limits_for("fn t(u) { send_message(u, scrape_url(u)) }"), vec![("scrape_url", 10)]);
+    assert!(policy.rate_limits_for("fn t(u) { u }").is_empty());
+    std::fs::remove_file(&path)?;
+    assert_eq!(SafetyPolicy::load(&path)?, SafetyPolicy::default());
+    Ok(())
+}
+
+#[test]
+fn test_policy_is_enforced_on_create_and_execute() -> Result<()> {
+    let mut manager = ToolManager::new()?;
+    manager.set_safety_policy(SafetyPolicy {
+        banned_functions: vec!["delete_file".to_string()],
+        approval: [(Capability::Replication, Approval::Deny)].into_iter().collect(),
+        ..SafetyPolicy::default()
+    });
+
+    let refused = manager.create_tool("safety_test_wipe", "fn safety_test_wipe(p) { delete_file(p) }").unwrap_err();
+    assert!(refused.to_string().contains("delete_file, which is banned"), "{}", refused);
+    assert!(!manager.has_tool("safety_test_wipe"));
+
+    let banned = manager.execute_tool("delete_file", vec!["safety_test_missing.txt".to_string()]).unwrap_err();
+    assert!(banned.to_string().contains("banned by the safety policy"), "{}", banned);
+    let denied = manager.check_tool("clone_agent").unwrap_err();
+    assert!(denied.to_string().contains("denies the Replication capability"), "{}", denied);
+    manager.check_tool("list_tools")?;
+    Ok(())
+}
+
+#[test]
+fn test_rate_limits_are_enforced_per_tool() -> Result<()> {
+    let manager = ToolManager::new()?;
+    manager.set_safety_policy(SafetyPolicy {
+        rate_limits: [("list_tools".to_string(), 2)].into_iter().collect(),
+        ..SafetyPolicy::default()
+    });
+
+    manager.execute_tool("list_tools", vec![])?;
+    manager.execute_tool("list_tools", vec![])?;
+    let limited = manager.execute_tool("list_tools", vec![]).unwrap_err();
+    assert!(limited.to_string().starts_with("Tool 'list_tools' is rate limited to 2 call(s) per minute by the safety policy; try again in "), "{}", limited);
+    // Other tools have budgets of their own
+    manager.execute_tool("list_pending_tools", vec![])?;
+
+    // Raising the limit starts the tool's budget afresh
+    manager.set_safety_policy(SafetyPolicy {
+        rate_limits: [("list_tools".to_string(), 3)].into_iter().collect(),
+        ..SafetyPolicy::default()
+    });
+    manager.execute_tool("list_tools", vec![])?;
+    Ok(())
+}
+
+#[test]
+fn test_natives_spend_their_budget_on_every_call() -> Result<()> {
+    let mut manager = ToolManager::new()?;
+    manager.set_safety_policy(SafetyPolicy {
+        rate_limits: [("search".to_string(), 2)].into_iter().collect(),
+        ..SafetyPolicy::default()
+    });
+    // One run of the tool makes three calls, and the third is over budget
+    manager.create_tool("safety_test_search_loop", r#"
+    fn safety_test_search_loop(q) {
+        let out = [];
+        for i in 0..3 { out.push(search(q + i)); }
+        out[2]
+    }
+    "#)?;
+    let third = manager.execute_tool("safety_test_search_loop", vec!["rust".to_string()])?;
+    assert!(third.starts_with("Error: search is rate limited to 2 call(s) per minute by the safety policy"), "{}", third);
+    let direct = manager.execute_tool("search", vec!["rust".to_string()])?;
+    assert!(direct.starts_with("Error: search is rate limited"), "{}", direct);
+    manager.remove_tool("safety_test_search_loop")?;
+    Ok(())
+}
+
+#[test]
+fn test_managers_can_share_one_budget() -> Result<()> {
+    let policy = SafetyPolicy {
+        rate_limits: [("square".to_string(), 1), ("search".to_string(), 1)].into_iter().collect(),
+        ..SafetyPolicy::default()
+    };
+    let (repl, worker) = (ToolManager::new()?, ToolManager::new()?);
+    repl.set_safety_policy(policy.clone());
+    worker.set_safety_policy(policy);
+    worker.set_rate_limits(repl.rate_limits());
+
+    // A call on either spends the budget of both, for tools and for metered natives
+    assert_eq!(repl.execute_tool("square", vec!["2".to_string()])?, "4");
+    let err = worker.execute_tool("square", vec!["2".to_string()]).unwrap_err();
+    assert!(err.to_string().starts_with("Tool 'square' is rate limited"), "{}", err);
+    worker.execute_tool("search", vec!["rust".to_string()])?;
+    let searched = repl.execute_tool("search", vec!["rust".to_string()])?;
+    assert!(searched.starts_with("Error: search is rate limited"), "{}", searched);
+    Ok(())
+}
+
+#[test]
+fn test_policy_sets_what_makes_a_tool_risky() -> Result<()> {
+    let path = std::env::temp_dir().join(format!("swarm_policy_risk_{}.toml", std::process::id()));
+    std::fs::write(&path, r#"
+max_tool_bytes = 200
+
+[risk_keywords]
+high = ["send_message"]
+medium = ["read_file"]
+"#)?;
+    let policy = SafetyPolicy::load(&path)?;
+    std::fs::remove_file(&path)?;
+    // Lists the file leaves out keep their defaults
+    assert_eq!(policy.risk_keywords.low, RiskKeywords::default().low);
+    assert_eq!(policy.classify("fn t(u) { send_message(u, \"hi\") }"), ToolSafetyLevel::HighRisk);
+    assert_eq!(policy.classify("fn t() { read_file(\"a\") }"), ToolSafetyLevel::MediumRisk);
+    assert_eq!(policy.classify("fn t(k) { memory_get(k) }"), ToolSafetyLevel::LowRisk);
+    assert_eq!(policy.classify("fn t() { write_file(\"a\", \"b\") }"), ToolSafetyLevel::Safe);
+    assert_eq!(policy.classify(&format!("fn t() {{ {} }}", "1 + ".repeat(60) + "1")), ToolSafetyLevel::HighRisk);
+    assert_eq!(SafetyPolicy::default().classify("fn t() { write_file(\"a\", \"b\") }"), ToolSafetyLevel::HighRisk);
+
+    // The manager rates tools, and so asks before running them, by the policy it has
+    let manager = ToolManager::new()?;
+    manager.set_safety_policy(SafetyPolicy {
+        risk_keywords: RiskKeywords { high: vec!["list_tools".to_string()], ..RiskKeywords::default() },
+        ..SafetyPolicy::default()
+    });
+    let asked = Arc::new(Mutex::new(Vec::new()));
+    let asked_clone = asked.clone();
+    manager.set_execution_approver(Arc::new(move |call: &str, level: &ToolSafetyLevel| {
+        asked_clone.lock().unwrap().push((call.to_string(), level.clone()));
+        false
+    }));
+    assert!(manager.execute_tool("list_tools", vec![]).is_err());
+    assert_eq!(*asked.lock().unwrap(), vec![("list_tools()".to_string(), ToolSafetyLevel::HighRisk)]);
+    Ok(())
+}
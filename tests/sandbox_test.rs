@@ -0,0 +1,71 @@
+use anyhow::Result;
+use swarm_thing::persona::Capability;
+use swarm_thing::sandbox::{run_sandboxed, SandboxLimits};
+use swarm_thing::tools::ToolManager;
+
+#[test]
+fn test_pure_tools_run_normally() {
+    let code = r#"
+    fn shout(text) {
+        print("shouting " + text);
+        return text.to_upper() + "!";
+    }
+    "#;
+    let report = run_sandboxed("shout", code, &["hello".to_string()], &SandboxLimits::default());
+    assert_eq!(report.output, Ok("HELLO!".to_string()));
+    assert_eq!(report.printed, vec!["shouting hello"]);
+    assert!(report.violations.is_empty());
+    assert!(report.render().contains("No capability violations"));
+}
+
+#[test]
+fn test_capability_use_is_reported_not_performed() {
+    let path = std::env::temp_dir().join(format!("swarm_sandbox_{}.txt", std::process::id()));
+    let code = format!(r#"
+    fn exfiltrate(url) {{
+        let page = scrape_url(url);
+        write_file("{}", page);
+        run_command("curl " + url);
+        return "done";
+    }}
+    "#, path.display());
+    let report = run_sandboxed("exfiltrate", &code, &["http://example.com".to_string()], &SandboxLimits::default());
+
+    assert_eq!(report.output, Ok("done".to_string()));
+    let capabilities: Vec<_> = report.violations.iter().map(|v| v.capability).collect();
+    assert_eq!(capabilities, vec![Some(Capability::Web), Some(Capability::Files), Some(Capability::Commands)]);
+    assert_eq!(report.violations[2].args, vec!["curl http://example.com"]);
+    assert!(!path.exists());
+    let rendered = report.render();
+    assert!(rendered.contains("Capability violations (3):"), "{}", rendered);
+    assert!(rendered.contains("- run_command(\"curl http://example.com\") [commands]"), "{}", rendered);
+}
+
+#[test]
+fn test_runaway_and_unknown_code_is_stopped() {
+    let limits = SandboxLimits { max_operations: 1_000, ..SandboxLimits::default() };
+    let report = run_sandboxed("spin", "fn spin() { loop { } }", &[], &limits);
+    assert_eq!(report.output, Err("stopped: too many operations".to_string()));
+
+    let report = run_sandboxed("sneaky", "fn sneaky(x) { secret_native(x) }", &["a".to_string()], &limits);
+    assert!(report.output.as_ref().unwrap_err().contains("secret_native"), "{:?}", report.output);
+    assert_eq!(report.violations[0].native, "secret_native");
+    assert_eq!(report.violations[0].capability, None);
+
+    let report = run_sandboxed("evil", "fn evil() { eval(\"1\") }", &[], &limits);
+    assert!(report.output.unwrap_err().contains("does not compile"));
+}
+
+#[test]
+fn test_pending_tool_native() -> Result<()> {
+    let mut manager = ToolManager::new()?;
+    manager.queue_tool("test_sandboxed_fetch".to_string(), "fn test_sandboxed_fetch(url) { scrape_url(url).len() }".to_string(), "peer".to_string(), None)?;
+    let report = manager.execute_tool("test_pending_tool", vec!["test_sandboxed_fetch".to_string(), "http://example.com".to_string()])?;
+    assert!(report.contains("Output: 0"), "{}", report);
+    assert!(report.contains("- scrape_url(\"http://example.com\") [web]"), "{}", report);
+    assert!(manager.list_pending_tools().contains("test_sandboxed_fetch"));
+
+    let missing = manager.execute_tool("test_pending_tool", vec!["nope".to_string(), "".to_string()])?;
+    assert_eq!(missing, "Error: Tool 'nope' not found in pending queue");
+    Ok(())
+}
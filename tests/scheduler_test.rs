@@ -0,0 +1,95 @@
+use anyhow::Result;
+use swarm_thing::agent::Agent;
+use swarm_thing::llm::{Completion, LlmClient, Usage};
+use swarm_thing::orchestrator::Member;
+use swarm_thing::replay::RecordedResponses;
+use swarm_thing::scheduler::{run_due, CronSchedule, JobAction, Scheduler};
+use swarm_thing::state::StateStore;
+use swarm_thing::tools::ToolManager;
+
+fn said(text: &str) -> Result<Completion, String> {
+    Ok(Completion { text: text.to_string(), usage: Usage { input_tokens: 10, output_tokens: 2 }, model: "mock".to_string(), cached: false })
+}
+
+// Saturday 2026-10-17 10:07:30 UTC
+const SATURDAY: u64 = 1792231650;
+
+fn next(cron: &str, after: u64) -> Option<u64> {
+    cron.parse::<CronSchedule>().unwrap().next_after(after)
+}
+
+#[test]
+fn test_cron_expressions() {
+    assert_eq!(next("*/15 * * * *", SATURDAY), Some(1792232100)); // 10:15
+    assert_eq!(next("30 9 * * 1-5", SATURDAY), Some(1792402200)); // Monday 09:30
+    assert_eq!(next("@monthly", SATURDAY), Some(1793491200)); // 2026-11-01
+    assert_eq!(next("0 0 29 2 *", SATURDAY), Some(1835395200)); // 2028-02-29
+    // With both day fields restricted, either day matches: the 1st or a Sunday
+    assert_eq!(next("0 12 1 * 7", SATURDAY), Some(1792324800));
+    // Exactly on a matching minute, the next one is next
+    assert_eq!(next("*/15 * * * *", 1792232100), Some(1792233000));
+    assert_eq!(next("0 0 31 2 *", SATURDAY), None);
+
+    for bad in ["61 * * * *", "* * *", "*/0 * * * *", "5-1 * * * *", "@often"] {
+        assert!(bad.parse::<CronSchedule>().is_err(), "{}", bad);
+    }
+}
+
+#[tokio::test]
+async fn test_scheduled_jobs_persist_and_run_when_due() -> Result<()> {
+    let state_path = std::env::temp_dir().join(format!("swarm_scheduler_{}.json", std::process::id()));
+    let _ = std::fs::remove_file(&state_path);
+    let scheduler = Scheduler::new(StateStore::open(&state_path)?);
+    let tool = scheduler.schedule("*/15 * * * *", "[TOOL: square(4)]", SATURDAY)?;
+    let prompt = scheduler.schedule("@hourly", "Summarize the inbox", SATURDAY)?;
+    assert_eq!((tool.id.as_str(), prompt.id.as_str()), ("job-1", "job-2"));
+    assert_eq!(tool.action, JobAction::Tool { name: "square".to_string(), args: vec!["4".to_string()] });
+    assert!(scheduler.schedule("every day", "hi", SATURDAY).is_err());
+
+    // Another handle on the same file sees the jobs, as after a restart
+    let reopened = Scheduler::new(StateStore::open(&state_path)?);
+    assert_eq!(reopened.list()?.len(), 2);
+    let llm = LlmClient::replaying(RecordedResponses::new(vec![said("Nothing new.")])).await?;
+    let mut member = Member::new("scheduler", "", Agent::with_client(llm, "You run jobs."), ToolManager::new()?, "You run jobs.");
+    assert!(run_due(&reopened, &mut member, SATURDAY).await.is_empty());
+
+    // 10:15 runs the tool; 11:00 runs both, the tool's 10:30 and 10:45 runs folded into one
+    let ran = run_due(&reopened, &mut member, 1792232100).await;
+    assert_eq!(ran, vec![("job-1".to_string(), Ok("16".to_string()))]);
+    let ran = run_due(&reopened, &mut member, 1792234800).await;
+    assert_eq!(ran, vec![("job-1".to_string(), Ok("16".to_string())), ("job-2".to_string(), Ok("Nothing new.".to_string()))]);
+    let jobs = scheduler.list()?;
+    assert_eq!((jobs[0].next_run, jobs[1].next_run), (1792235700, 1792238400));
+    assert_eq!((jobs[1].last_run, jobs[1].last_outcome.as_deref()), (Some(1792234800), Some("Nothing new.")));
+    assert!(scheduler.render()?.contains("job-2 [@hourly] next at 1792238400: Summarize the inbox"));
+
+    assert!(scheduler.cancel("job-1")?);
+    assert!(!scheduler.cancel("job-1")?);
+    assert_eq!(scheduler.list()?.len(), 1);
+    // Ids aren't reused while later jobs remain
+    assert_eq!(scheduler.schedule("@daily", "[TOOL: square(2)]", SATURDAY)?.id, "job-3");
+
+    std::fs::remove_file(&state_path)?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_scheduled_high_risk_runs_need_an_approver() -> Result<()> {
+    let state_path = std::env::temp_dir().join(format!("swarm_scheduler_risk_{}.json", std::process::id()));
+    let _ = std::fs::remove_file(&state_path);
+    let scheduler = Scheduler::new(StateStore::open(&state_path)?);
+    scheduler.schedule("@hourly", "[TOOL: delete_file(scheduler_test_kept.txt)]", SATURDAY)?;
+    let tools = ToolManager::new()?;
+    let kept = tools.jail().root().join("scheduler_test_kept.txt");
+    std::fs::write(&kept, "keep me")?;
+    let llm = LlmClient::replaying(RecordedResponses::new(Vec::new())).await?;
+    let mut member = Member::new("scheduler", "", Agent::with_client(llm, ""), tools, "");
+
+    let ran = run_due(&scheduler, &mut member, SATURDAY + 3600).await;
+    assert_eq!(ran, vec![("job-1".to_string(), Err("delete_file(scheduler_test_kept.txt) needs an operator to confirm it, and there is none".to_string()))]);
+    assert!(kept.exists());
+
+    std::fs::remove_file(&kept)?;
+    std::fs::remove_file(&state_path)?;
+    Ok(())
+}
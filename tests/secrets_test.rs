@@ -0,0 +1,50 @@
+use anyhow::Result;
+use swarm_thing::message::ToolSafetyLevel;
+use swarm_thing::safety::SafetyPolicy;
+use swarm_thing::secrets::{mask, Secrets};
+use swarm_thing::tools::ToolManager;
+
+#[test]
+fn test_secret_names_masking_and_env_files() -> Result<()> {
+    let secrets = Secrets::default().with_keyring(false).with_names(vec!["SWARM_TEST_DSN".to_string()]);
+    for name in ["OPENAI_API_KEY", "AWS_SESSION_TOKEN", "MQTT_PASSWORD", "SWARM_TEST_DSN"] {
+        assert!(secrets.is_secret(name), "{}", name);
+    }
+    for name in ["LLM_MAX_TOKENS", "SECRET_NAMES", "IPC_ADDRESS"] {
+        assert!(!secrets.is_secret(name), "{}", name);
+    }
+    assert_eq!(mask("sk-abcdefghijklmnop"), "****mnop");
+    assert_eq!(mask("short"), "****");
+
+    std::env::set_var("SWARM_TEST_DSN", "postgres://agent:hunter22@db");
+    assert_eq!(secrets.get("SWARM_TEST_DSN")?.as_deref(), Some("postgres://agent:hunter22@db"));
+    assert_eq!(secrets.get("SWARM_TEST_MISSING_KEY")?, None);
+    assert_eq!(secrets.redact("connect to postgres://agent:hunter22@db now"), "connect to [redacted:SWARM_TEST_DSN] now");
+    assert!(secrets.redacted().contains(&("SWARM_TEST_DSN".to_string(), "****2@db".to_string())));
+
+    let dir = std::env::temp_dir().join(format!("swarm_secrets_{}", std::process::id()));
+    std::fs::create_dir_all(&dir)?;
+    std::fs::write(dir.join("src.env"), "# LLM\nLLM_PROVIDER=openai\nOPENAI_API_KEY=sk-live\nexport IPC_SECRET=swarm\nLLM_MAX_TOKENS=512\n")?;
+    let dropped = secrets.copy_env_file(&dir.join("src.env"), &dir.join("dst.env"))?;
+    assert_eq!(dropped, vec!["OPENAI_API_KEY", "IPC_SECRET"]);
+    assert_eq!(std::fs::read_to_string(dir.join("dst.env"))?, "# LLM\nLLM_PROVIDER=openai\nLLM_MAX_TOKENS=512\n");
+
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+
+#[test]
+fn test_get_secret_is_high_risk_and_redacted() -> Result<()> {
+    std::env::set_var("SWARM_TEST_SERVICE_TOKEN", "tok-0123456789");
+    let mut manager = ToolManager::new()?;
+    assert_eq!(SafetyPolicy::default().classify("get_secret(\"X\")"), ToolSafetyLevel::HighRisk);
+
+    // Tools can use the value, but it doesn't leave them
+    assert_eq!(manager.execute_tool("get_secret", vec!["SWARM_TEST_SERVICE_TOKEN".to_string()])?, "[redacted:SWARM_TEST_SERVICE_TOKEN]");
+    manager.create_tool("test_secret_len", r#"fn test_secret_len() { let t = get_secret("SWARM_TEST_SERVICE_TOKEN"); return "len " + t.len(); }"#)?;
+    assert_eq!(manager.execute_tool("test_secret_len", vec![])?, "len 14");
+    assert!(manager.execute_tool("get_secret", vec!["SWARM_TEST_NO_SUCH_KEY".to_string()])?.starts_with("Error"));
+
+    std::fs::remove_file("tools/test_secret_len.rhai")?;
+    Ok(())
+}
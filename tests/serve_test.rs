@@ -0,0 +1,152 @@
+use anyhow::Result;
+use axum::{routing::post, Json, Router};
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use swarm_thing::agent::Agent;
+use swarm_thing::inbox::Inbox;
+use swarm_thing::ipc::{call_peer, router, send_ipc_message, serve_inbox, IpcState, PendingCalls, TaskQueue};
+use swarm_thing::llm::{Completion, LlmClient, Usage};
+use swarm_thing::message::IpcMessage;
+use swarm_thing::orchestrator::Member;
+use swarm_thing::registry::PeerRegistry;
+use swarm_thing::replay::RecordedResponses;
+use swarm_thing::state::StateStore;
+use swarm_thing::threads::{Direction, PeerThreads};
+use swarm_thing::tools::ToolManager;
+
+fn said(text: &str) -> Result<Completion, String> {
+    Ok(Completion { text: text.to_string(), usage: Usage { input_tokens: 10, output_tokens: 2 }, model: "mock".to_string(), cached: false })
+}
+
+fn threads(name: &str) -> Result<PeerThreads> {
+    let state_path = std::env::temp_dir().join(format!("swarm_serve_{}_{}.json", name, std::process::id()));
+    let _ = std::fs::remove_file(&state_path);
+    Ok(PeerThreads::new(StateStore::open(&state_path)?))
+}
+
+/// Serve an agent on a random port, returning its address
+async fn serve(threads: PeerThreads, inbox: Inbox, calls: PendingCalls) -> Result<String> {
+    let state = IpcState::new(Arc::new(Mutex::new(Vec::new())), threads, TaskQueue::new(), PeerRegistry::new(), PathBuf::from("tools"))
+        .with_inbox(inbox)
+        .with_calls(calls);
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+    let address = listener.local_addr()?.to_string();
+    tokio::spawn(async move {
+        axum::serve(listener, router(state).into_make_service_with_connect_info::<SocketAddr>()).await.unwrap()
+    });
+    Ok(address)
+}
+
+/// A worker whose inbox is answered by an agent on scripted model responses
+async fn worker(name: &str, responses: Vec<Result<Completion, String>>) -> Result<(String, PeerThreads)> {
+    let threads = threads(name)?;
+    let inbox = Inbox::new();
+    let address = serve(threads.clone(), inbox.clone(), PendingCalls::new()).await?;
+    let llm = LlmClient::replaying(RecordedResponses::new(responses)).await?;
+    let member = Member::new(name, "Answers peers", Agent::with_client(llm, "You use tools."), ToolManager::new()?, "You use tools.");
+    tokio::spawn(serve_inbox(inbox, threads.clone(), member, Some(address.clone())));
+    Ok((address, threads))
+}
+
+#[tokio::test]
+async fn test_serve_answers_calls_through_agent_loop() -> Result<()> {
+    let calls = PendingCalls::new();
+    let caller = serve(threads("caller")?, Inbox::new(), calls.clone()).await?;
+    let (worker, worker_threads) = worker("worker", vec![said("[TOOL: square(6)]"), said("6 squared is 36.")]).await?;
+
+    let answer = call_peer(&calls, &worker, &IpcMessage::text("what is 6 squared?"), Some(caller.clone()), Duration::from_secs(5)).await?;
+    assert_eq!(answer, IpcMessage::text("6 squared is 36."));
+    assert_eq!(calls.pending(), 0);
+    // The answer is recorded in the caller's thread once it's sent
+    let mut last = None;
+    for _ in 0..50 {
+        last = worker_threads.history(&caller)?.last().map(|m| m.content.clone());
+        if last.as_deref() == Some("6 squared is 36.") {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    assert_eq!(last.as_deref(), Some("6 squared is 36."));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_serve_replies_to_plain_messages() -> Result<()> {
+    let caller_inbox = Inbox::new();
+    let caller = serve(threads("plain_caller")?, caller_inbox.clone(), PendingCalls::new()).await?;
+    let (worker, _) = worker("plain_worker", vec![said("Hello back."), Err("model down".to_string())]).await?;
+
+    send_ipc_message(&worker, &IpcMessage::text("hello"), Some(caller.clone())).await?;
+    send_ipc_message(&worker, &IpcMessage::text("still there?"), Some(caller.clone())).await?;
+    let mut replies = Vec::new();
+    for _ in 0..100 {
+        replies = caller_inbox.unread();
+        if replies.len() == 2 {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    assert_eq!(replies.len(), 2, "{:?}", replies);
+    assert_eq!(replies[0].content, "Hello back.");
+    assert_eq!(replies[0].from.as_deref(), Some(worker.as_str()));
+    // A model failure is reported to the sender rather than left unanswered
+    assert!(replies[1].content.starts_with("Error:"), "{}", replies[1].content);
+    Ok(())
+}
+
+// Ollama-compatible model that says how many messages of the conversation it was sent
+async fn counting_chat(Json(body): Json<serde_json::Value>) -> Json<serde_json::Value> {
+    let asked = body["messages"].as_array().map(|m| m.iter().filter(|m| m["role"] == "user").count()).unwrap_or(0);
+    Json(serde_json::json!({ "message": { "role": "assistant", "content": format!("{} so far", asked) } }))
+}
+
+#[tokio::test]
+async fn test_serve_keeps_a_conversation_per_thread() -> Result<()> {
+    let model = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+    let model_url = format!("http://{}/api/chat", model.local_addr()?);
+    tokio::spawn(async move { axum::serve(model, Router::new().route("/api/chat", post(counting_chat))).await.unwrap() });
+
+    let caller_inbox = Inbox::new();
+    let caller = serve(threads("threaded_caller")?, caller_inbox.clone(), PendingCalls::new()).await?;
+    let worker_threads = threads("threaded_worker")?;
+    let worker_inbox = Inbox::new();
+    let worker = serve(worker_threads.clone(), worker_inbox.clone(), PendingCalls::new()).await?;
+    let llm = LlmClient::with_model("ollama", Some("mock".to_string())).await?.with_ollama_url(&model_url).with_cache(None);
+    let member = Member::new("seller", "Negotiates", Agent::with_client(llm, "You sell."), ToolManager::new()?, "You sell.");
+    tokio::spawn(serve_inbox(worker_inbox.clone(), worker_threads.clone(), member, Some(worker.clone())));
+
+    // Two negotiations at once: neither sees the other's messages
+    for (content, thread) in [("bid 40", "deal-a"), ("bid 55", "deal-b"), ("bid 45", "deal-a")] {
+        send_ipc_message(&worker, &IpcMessage::threaded(content, Some(thread.to_string())), Some(caller.clone())).await?;
+        let expected = caller_inbox.list().len() + 1;
+        for _ in 0..100 {
+            if caller_inbox.list().len() == expected {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+    }
+    let replies: Vec<(String, Option<String>)> = caller_inbox.list().into_iter().map(|m| (m.content, m.thread_id)).collect();
+    assert_eq!(replies, [
+        ("1 so far".to_string(), Some("deal-a".to_string())),
+        ("1 so far".to_string(), Some("deal-b".to_string())),
+        ("2 so far".to_string(), Some("deal-a".to_string())),
+    ]);
+    assert_eq!(worker_inbox.threads().len(), 2);
+    // The answer is recorded once it's sent
+    let mut deal_a = Vec::new();
+    for _ in 0..50 {
+        deal_a = worker_threads.history_in(&caller, Some("deal-a"))?;
+        if deal_a.len() == 4 {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    assert_eq!(deal_a.iter().map(|m| (m.direction, m.content.as_str())).collect::<Vec<_>>(), [
+        (Direction::Incoming, "bid 40"), (Direction::Outgoing, "1 so far"), (Direction::Incoming, "bid 45"), (Direction::Outgoing, "2 so far"),
+    ]);
+    assert!(worker_threads.history(&caller)?.is_empty());
+    Ok(())
+}
@@ -0,0 +1,45 @@
+use anyhow::Result;
+use std::time::Duration;
+use swarm_thing::server::ServerManager;
+use swarm_thing::tools::ToolManager;
+
+#[test]
+fn test_server_manager_lifecycle() {
+    let manager = ServerManager::new();
+    assert!(manager.status().is_none());
+    manager.start("test:1", |shutdown| shutdown.wait()).unwrap();
+    assert!(manager.is_running());
+    assert!(manager.start("test:2", |shutdown| shutdown.wait()).unwrap_err().to_string().contains("already running at test:1"));
+
+    assert_eq!(manager.stop(Duration::from_secs(1)).as_deref(), Some("test:1"));
+    assert!(manager.status().is_none());
+    assert!(manager.stop(Duration::from_secs(1)).is_none());
+
+    // A server that exits on its own is reported, and can be replaced
+    manager.start("test:3", |_| async {}).unwrap();
+    std::thread::sleep(Duration::from_millis(100));
+    assert_eq!(manager.status().unwrap().render(), "Server at test:3 has exited");
+    manager.start("test:4", |shutdown| shutdown.wait()).unwrap();
+    assert!(manager.is_running());
+    manager.stop(Duration::from_secs(1));
+}
+
+#[test]
+fn test_stop_and_restart_server_natives() -> Result<()> {
+    let agent = ToolManager::new()?;
+    assert_eq!(agent.execute_tool("server_status", vec![])?, "No server is running");
+    assert_eq!(agent.execute_tool("start_server", vec!["9895".to_string()])?, "IPC server starting on port 9895");
+    assert!(agent.execute_tool("server_status", vec![])?.starts_with("Server running at 127.0.0.1:9895"));
+    assert!(agent.execute_tool("start_server", vec!["9896".to_string()])?.contains("already running"));
+
+    // Another agent can't take the same port
+    let other = ToolManager::new()?;
+    assert!(other.execute_tool("start_server", vec!["9895".to_string()])?.starts_with("Error: cannot listen on 127.0.0.1:9895"));
+
+    assert_eq!(agent.execute_tool("stop_server", vec![])?, "Server at 127.0.0.1:9895 stopped");
+    assert_eq!(agent.execute_tool("stop_server", vec![])?, "No server is running");
+    // The port is free again once the server stopped
+    assert_eq!(other.execute_tool("start_server", vec!["9895".to_string()])?, "IPC server starting on port 9895");
+    other.execute_tool("stop_server", vec![])?;
+    Ok(())
+}
@@ -0,0 +1,60 @@
+use anyhow::Result;
+use rhai::Engine;
+use swarm_thing::backend::rhai_signature;
+use swarm_thing::prompts::{PromptContext, PromptLibrary};
+use swarm_thing::tools::ToolManager;
+
+const CLAMPED_SUM: &str = r#"
+// filename: clamped_sum
+// Adds two numbers and keeps the result in range
+fn clamped_sum(a, b) {
+    clamp(parse_int(a) + parse_int(b), 0, 100)
+}
+
+/// Keeps n between lo and hi
+private fn clamp(n, lo, hi) {
+    if n < lo { lo } else if n > hi { hi } else { n }
+}
+
+fn clamped_sum(a) { clamped_sum(a, 0) }
+"#;
+
+#[test]
+fn test_signatures_come_from_the_ast() {
+    let engine = Engine::new_raw();
+    let signature = rhai_signature(&engine, CLAMPED_SUM, "clamped_sum");
+    // The overload with the most parameters, and the leading comment without the filename
+    assert_eq!(signature.params, ["a", "b"]);
+    assert_eq!(signature.description.as_deref(), Some("Adds two numbers and keeps the result in range"));
+    assert_eq!(signature.helpers.len(), 1);
+    assert_eq!((signature.helpers[0].call(), signature.helpers[0].doc.as_deref()), ("clamp(n, lo, hi)".to_string(), Some("Keeps n between lo and hi")));
+    assert_eq!(signature.summary("clamped_sum"), "clamped_sum(a, b) - Adds two numbers and keeps the result in range");
+
+    // A doc comment on the tool's function wins over the leading comment
+    let documented = "// Old notes\n/** Greets\n * someone by name */\nfn greet(name) { \"hi \" + name }";
+    assert_eq!(rhai_signature(&engine, documented, "greet").description.as_deref(), Some("Greets someone by name"));
+
+    // Code that doesn't parse is still read line by line
+    let broken = rhai_signature(&engine, "// Half written\nfn draft(x, y) {", "draft");
+    assert_eq!((broken.params, broken.description.as_deref()), (vec!["x".to_string(), "y".to_string()], Some("Half written")));
+}
+
+#[test]
+fn test_signatures_in_inspect_tool_and_the_prompt() -> Result<()> {
+    let mut manager = ToolManager::new()?;
+    manager.create_tool("clamped_sum", CLAMPED_SUM)?;
+    assert_eq!(manager.tool_params("clamped_sum"), ["a", "b"]);
+
+    let summary = manager.execute_tool("inspect_tool", vec!["clamped_sum".to_string()])?;
+    assert_eq!(summary, "clamped_sum(a, b) - Adds two numbers and keeps the result in range\nHelpers:\n  clamp(n, lo, hi) - Keeps n between lo and hi\nSafety: Safe\ninspect_tool(clamped_sum, source) shows the code");
+
+    let listed = vec!["clamped_sum".to_string(), "mcp::files::read(path)".to_string()];
+    let mut context = PromptContext::new("Scout", listed.clone());
+    context.tool_signatures = manager.prompt_signatures(&listed);
+    let prompt = PromptLibrary::new("prompts").system_prompt(&context)?;
+    assert!(prompt.contains("Available Tools:\n- clamped_sum(a, b) - Adds two numbers and keeps the result in range\n- mcp::files::read(path)\n\n"), "{}", prompt);
+    assert!(!prompt.contains("fn clamped_sum"));
+
+    std::fs::remove_file("tools/clamped_sum.rhai")?;
+    Ok(())
+}
@@ -0,0 +1,35 @@
+use anyhow::Result;
+use std::path::Path;
+use swarm_thing::supervisor::{CloneProcess, CloneSupervisor, CLONE_LOG};
+use swarm_thing::tools::ToolManager;
+
+#[test]
+fn test_supervisor_spawns_and_stops() -> Result<()> {
+    let dir = std::env::temp_dir().join(format!("swarm_supervisor_{}", std::process::id()));
+    std::fs::create_dir_all(&dir)?;
+    let key = dir.display().to_string();
+    let supervisor = CloneSupervisor::new();
+    assert_eq!(supervisor.status(&key), CloneProcess::NotSupervised);
+
+    let pid = supervisor.spawn(Path::new("sleep"), &["30".to_string()], &dir, &[])?;
+    assert_eq!(supervisor.status(&key), CloneProcess::Running { pid });
+    assert!(supervisor.spawn(Path::new("sleep"), &["30".to_string()], &dir, &[]).unwrap_err().to_string().contains("already running"));
+    assert!(dir.join(CLONE_LOG).exists());
+
+    assert!(supervisor.stop(&key)?);
+    assert_eq!(supervisor.status(&key), CloneProcess::Stopped);
+    assert!(!supervisor.stop(&key)?);
+    assert!(supervisor.stop_all().is_empty());
+
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+
+#[test]
+fn test_spawn_clone_requires_registered_clone() -> Result<()> {
+    let manager = ToolManager::new()?;
+    let result = manager.execute_tool("spawn_clone", vec!["/tmp/swarm_not_a_clone".to_string(), "9100".to_string(), String::new()])?;
+    assert!(result.contains("is not a registered clone"), "{}", result);
+    assert_eq!(manager.execute_tool("stop_clone", vec!["nobody".to_string()])?, "Error: no clone 'nobody'");
+    Ok(())
+}
@@ -0,0 +1,90 @@
+use anyhow::Result;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use swarm_thing::ipc::{router, run_auction, send_ipc_message, IpcState, TaskQueue};
+use swarm_thing::message::{IpcMessage, TaskAnnounce, TaskBid, TaskRequest};
+use swarm_thing::registry::PeerRegistry;
+use swarm_thing::state::StateStore;
+use swarm_thing::threads::PeerThreads;
+
+/// A tools directory holding `tools`
+fn tools_dir(name: &str, tools: &[&str]) -> Result<PathBuf> {
+    let dir = std::env::temp_dir().join(format!("swarm_bidding_{}_{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir)?;
+    for tool in tools {
+        std::fs::write(dir.join(format!("{}.rhai", tool)), format!("fn {}(x) {{ x }}", tool))?;
+    }
+    Ok(dir)
+}
+
+/// Serve an agent with `tools_dir` and task queue `tasks` on a random port, returning its address
+async fn serve(name: &str, tools_dir: &Path, tasks: TaskQueue) -> Result<String> {
+    let state_path = std::env::temp_dir().join(format!("swarm_bidding_{}_{}.json", name, std::process::id()));
+    let _ = std::fs::remove_file(&state_path);
+    let threads = PeerThreads::new(StateStore::open(&state_path)?);
+    let state = IpcState::new(Arc::new(Mutex::new(Vec::new())), threads, tasks, PeerRegistry::new(), tools_dir.to_path_buf());
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+    let address = listener.local_addr()?.to_string();
+    tokio::spawn(async move {
+        axum::serve(listener, router(state).into_make_service_with_connect_info::<SocketAddr>()).await.unwrap()
+    });
+    Ok(address)
+}
+
+#[tokio::test]
+async fn test_task_goes_to_the_best_bid() -> Result<()> {
+    // Two workers that can summarize, one of them with a backlog, and one that can't
+    let busy_tasks = TaskQueue::new();
+    busy_tasks.push(TaskRequest::new("earlier work"));
+    busy_tasks.push(TaskRequest::new("more earlier work"));
+    let busy = serve("busy", &tools_dir("busy", &["summarize", "translate"])?, busy_tasks.clone()).await?;
+    let idle_tasks = TaskQueue::new();
+    let idle = serve("idle", &tools_dir("idle", &["summarize"])?, idle_tasks.clone()).await?;
+    let unskilled_tasks = TaskQueue::new();
+    let unskilled = serve("unskilled", &tools_dir("unskilled", &["translate"])?, unskilled_tasks.clone()).await?;
+
+    let coordinator = TaskQueue::new();
+    let task = TaskRequest::new("Summarize the meeting notes").with_deadline(Duration::from_secs(60));
+    let announce = TaskAnnounce::new(task.clone(), vec!["summarize".to_string()]);
+    let peers = vec![busy.clone(), idle.clone(), unskilled.clone()];
+    let auction = run_auction(&coordinator, &peers, &announce, None, Duration::from_secs(5)).await;
+
+    assert_eq!(auction.bids.len(), 2, "{:?}", auction.bids);
+    let busy_bid = auction.bids.iter().find(|b| b.bidder == busy).expect("busy worker bids");
+    assert_eq!(busy_bid.cost_estimate, 3.0);
+    assert_eq!(busy_bid.capability_match, 1.0);
+    let winner = auction.winner.as_ref().expect("a winner");
+    assert_eq!(winner.bidder, idle);
+    assert!(matches!(&auction.award, Some(Ok(reply)) if reply.contains("accepted")), "{:?}", auction.award);
+    assert!(auction.render().starts_with(&format!("Awarded task {} to {}", task.id, idle)), "{}", auction.render());
+
+    assert_eq!(idle_tasks.pending(), 1);
+    assert_eq!(idle_tasks.try_next().map(|t| t.id), Some(task.id.clone()));
+    assert_eq!(busy_tasks.pending(), 2);
+    assert_eq!(unskilled_tasks.pending(), 0);
+
+    // Nobody able: no winner, nothing awarded
+    let announce = TaskAnnounce::new(TaskRequest::new("Paint a fence"), vec!["paint".to_string()]);
+    let auction = run_auction(&coordinator, &peers, &announce, None, Duration::from_secs(5)).await;
+    assert!(auction.winner.is_none());
+    assert_eq!(auction.render(), format!("No bids for task {}", announce.task.id));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_bids_sent_to_the_coordinator_are_recorded() -> Result<()> {
+    let tasks = TaskQueue::new();
+    let coordinator = serve("coordinator", &tools_dir("coordinator", &[])?, tasks.clone()).await?;
+    let bid = TaskBid { task_id: "task-1".to_string(), bidder: String::new(), cost_estimate: 2.0, capability_match: 0.5 };
+    let reply = send_ipc_message(&coordinator, &IpcMessage::TaskBid(bid), Some("127.0.0.1:9000".to_string())).await?;
+    assert!(reply.contains("recorded"), "{}", reply);
+
+    let bids = tasks.bids("task-1");
+    assert_eq!(bids.len(), 1);
+    assert_eq!(bids[0].bidder, "127.0.0.1:9000");
+    assert_eq!(bids[0].score(), 0.5 / 3.0);
+    Ok(())
+}
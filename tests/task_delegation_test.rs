@@ -0,0 +1,106 @@
+use anyhow::Result;
+use axum::{routing::post, Json, Router};
+use std::time::Duration;
+use swarm_thing::agent::Agent;
+use swarm_thing::ipc::{send_ipc_message, serve_tasks};
+use swarm_thing::llm::{Completion, LlmClient, Usage};
+use swarm_thing::message::{IpcMessage, TaskRequest, TaskStatus};
+use swarm_thing::orchestrator::Member;
+use swarm_thing::replay::RecordedResponses;
+use swarm_thing::tools::ToolManager;
+
+// Ollama-compatible endpoint that "works" on a task by echoing it
+async fn mock_chat(Json(body): Json<serde_json::Value>) -> Json<serde_json::Value> {
+    let last = body["messages"].as_array().and_then(|m| m.last()).cloned().unwrap_or_default();
+    let content = format!("Done: {}", last["content"].as_str().unwrap_or(""));
+    Json(serde_json::json!({ "message": { "role": "assistant", "content": content } }))
+}
+
+#[tokio::test]
+async fn test_task_request_and_result_round_trip() -> Result<()> {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+    let llm_url = format!("http://{}/api/chat", listener.local_addr()?);
+    tokio::spawn(async move {
+        axum::serve(listener, Router::new().route("/api/chat", post(mock_chat))).await.unwrap();
+    });
+
+    // Worker agent: serves IPC on 9841 and works through its task queue
+    let worker = ToolManager::new()?;
+    worker.execute_tool("start_server", vec!["9841".to_string()])?;
+    let llm = LlmClient::with_model("ollama", Some("mock".to_string())).await?
+        .with_ollama_url(&llm_url)
+        .with_cache(None);
+    let member = Member::new("worker", "", Agent::with_client(llm, "You do tasks."), ToolManager::new()?, "You do tasks.");
+    tokio::spawn(serve_tasks(worker.tasks().clone(), member));
+
+    // Delegating agent: serves on 9842 so the result can come back
+    let mut delegator = ToolManager::new()?;
+    delegator.execute_tool("start_server", vec!["9842".to_string()])?;
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    delegator.create_tool("test_delegate", r#"
+    fn test_delegate(description) {
+        return delegate_task("127.0.0.1:9841", description);
+    }
+    "#)?;
+
+    let sent = delegator.execute_tool("test_delegate", vec!["count the tools".to_string()])?;
+    assert!(sent.contains("queued"), "{}", sent);
+    let id = sent.trim_start_matches("Delegated task ").split(':').next().unwrap().to_string();
+    assert!(delegator.execute_tool("task_status", vec![id.clone()])?.contains("pending"));
+
+    let mut result = None;
+    for _ in 0..50 {
+        result = delegator.tasks().result(&id);
+        if result.is_some() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    let result = result.expect("result should arrive");
+    assert_eq!(result.status, TaskStatus::Completed);
+    assert_eq!(result.output, "Done: count the tools");
+    assert_eq!(delegator.execute_tool("task_status", vec![id])?, "Completed: Done: count the tools");
+
+    // Tasks whose deadline already passed are turned away
+    let mut late = TaskRequest::new("too late");
+    late.deadline = Some(1);
+    let reply = send_ipc_message("127.0.0.1:9841", &IpcMessage::task_request(late), None).await?;
+    assert!(reply.contains("rejected"), "{}", reply);
+    assert_eq!(worker.tasks().pending(), 0);
+
+    std::fs::remove_file("tools/test_delegate.rhai")?;
+    Ok(())
+}
+
+fn said(text: &str) -> Result<Completion, String> {
+    Ok(Completion { text: text.to_string(), usage: Usage { input_tokens: 10, output_tokens: 2 }, model: "mock".to_string(), cached: false })
+}
+
+#[tokio::test]
+async fn test_delegated_tasks_run_no_unconfirmed_high_risk_tools() -> Result<()> {
+    let tools = ToolManager::new()?;
+    let tasks = tools.tasks().clone();
+    let kept = tools.jail().root().join("task_delegation_unattended.txt");
+    std::fs::write(&kept, "keep me")?;
+    let llm = LlmClient::replaying(RecordedResponses::new(vec![
+        said("[TOOL: delete_file(task_delegation_unattended.txt)]"),
+        said("Could not delete it."),
+    ])).await?;
+    tokio::spawn(serve_tasks(tasks.clone(), Member::new("worker", "", Agent::with_client(llm, ""), tools, "")));
+
+    // No approver on the worker's tools: the peer's task can't delete files
+    let task = TaskRequest::new("delete a file");
+    tasks.push(task.clone());
+    let mut result = None;
+    for _ in 0..50 {
+        result = tasks.result(&task.id);
+        if result.is_some() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    assert_eq!(result.expect("the task should finish").output, "Could not delete it.");
+    assert!(kept.exists());
+    std::fs::remove_file(&kept)?;
+    Ok(())
+}
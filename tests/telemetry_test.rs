@@ -0,0 +1,81 @@
+use anyhow::Result;
+use opentelemetry_sdk::testing::trace::InMemorySpanExporter;
+use opentelemetry_sdk::trace::TracerProvider;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use swarm_thing::config::{Config, LogConfig};
+use swarm_thing::ipc::{router, send_ipc_message, IpcState, TaskQueue};
+use swarm_thing::logging::traced_subscriber;
+use swarm_thing::message::{IpcMessage, TaskRequest};
+use swarm_thing::registry::PeerRegistry;
+use swarm_thing::state::StateStore;
+use swarm_thing::telemetry::{continue_trace, tracer, traceparent};
+use swarm_thing::threads::PeerThreads;
+use tracing::{info_span, Instrument};
+
+async fn serve(name: &str) -> Result<(String, TaskQueue)> {
+    let state_path = std::env::temp_dir().join(format!("swarm_telemetry_{}_{}.json", name, std::process::id()));
+    let threads = PeerThreads::new(StateStore::open(&state_path)?);
+    let tasks = TaskQueue::new();
+    let state = IpcState::new(Arc::new(Mutex::new(Vec::new())), threads, tasks.clone(), PeerRegistry::new(), std::env::temp_dir());
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+    let address = listener.local_addr()?.to_string();
+    tokio::spawn(async move {
+        axum::serve(listener, router(state).into_make_service_with_connect_info::<SocketAddr>()).await.unwrap()
+    });
+    Ok((format!("http://{}", address), tasks))
+}
+
+// Single-threaded, so the servers' tasks see the subscriber set for this thread
+#[tokio::test]
+async fn test_delegated_task_is_one_trace() -> Result<()> {
+    let exporter = InMemorySpanExporter::default();
+    let provider = TracerProvider::builder().with_simple_exporter(exporter.clone()).build();
+    let logger = traced_subscriber(&LogConfig::default(), std::io::sink, tracer(&provider))?;
+    let _guard = tracing::subscriber::set_default(logger);
+
+    let (second, second_tasks) = serve("second").await?;
+    let (third, third_tasks) = serve("third").await?;
+
+    // The first agent delegates to the second, which passes the work on to the third
+    let root = info_span!("delegate");
+    let sent = traceparent(&root).expect("exported spans have a trace context");
+    send_ipc_message(&second, &IpcMessage::TaskRequest(TaskRequest::new("summarize")), None).instrument(root.clone()).await?;
+    let task = second_tasks.next().await;
+    let working = info_span!("task", id = %task.id);
+    continue_trace(&working, task.traceparent.as_deref());
+    send_ipc_message(&third, &IpcMessage::TaskRequest(TaskRequest::new("summarize part 1")), None).instrument(working.clone()).await?;
+    let task = third_tasks.next().await;
+    drop((root, working));
+
+    let trace_id = sent.split('-').nth(1).unwrap().to_string();
+    assert!(task.traceparent.as_deref().is_some_and(|t| t.contains(&trace_id)), "{:?}", task.traceparent);
+    let spans = exporter.get_finished_spans()?;
+    let names: Vec<&str> = spans.iter().map(|span| span.name.as_ref()).collect();
+    assert_eq!(names.iter().filter(|name| **name == "ipc_request").count(), 2, "{:?}", names);
+    assert_eq!(names.iter().filter(|name| **name == "ipc_send").count(), 2, "{:?}", names);
+    for span in &spans {
+        assert_eq!(span.span_context.trace_id().to_string(), trace_id, "{} left the trace", span.name);
+    }
+    // Each receiving span hangs off the span that sent it
+    for request in spans.iter().filter(|span| span.name == "ipc_request") {
+        let parent = spans.iter().find(|span| span.span_context.span_id() == request.parent_span_id).expect("parent exported");
+        assert_eq!(parent.name, "ipc_send");
+    }
+    Ok(())
+}
+
+#[test]
+fn test_traceparent_without_exporter() -> Result<()> {
+    // Without an OpenTelemetry layer there is nothing to propagate, and bad headers are ignored
+    let span = info_span!("plain");
+    assert_eq!(traceparent(&span), None);
+    continue_trace(&span, Some("not-a-trace"));
+
+    let mut config = Config::default();
+    config.telemetry.otlp_endpoint = Some("localhost:4317".to_string());
+    let problems = config.validate();
+    assert!(problems.iter().any(|p| p.starts_with("telemetry.otlp_endpoint:")), "{:?}", problems);
+    assert!(swarm_thing::telemetry::tracer_provider(&Config::default())?.is_none());
+    Ok(())
+}
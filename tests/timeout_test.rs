@@ -0,0 +1,70 @@
+use anyhow::Result;
+use axum::{routing::post, Json, Router};
+use std::time::{Duration, Instant};
+use swarm_thing::llm::{LlmClient, Message};
+use swarm_thing::retry::RetryPolicy;
+use swarm_thing::tools::ToolManager;
+
+// Ollama-compatible endpoint that takes half a second to answer
+async fn slow_chat(Json(_): Json<serde_json::Value>) -> Json<serde_json::Value> {
+    tokio::time::sleep(Duration::from_millis(500)).await;
+    Json(serde_json::json!({ "message": { "role": "assistant", "content": "Finally." } }))
+}
+
+#[tokio::test]
+async fn test_llm_requests_time_out() -> Result<()> {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+    let url = format!("http://{}/api/chat", listener.local_addr()?);
+    tokio::spawn(async move {
+        axum::serve(listener, Router::new().route("/api/chat", post(slow_chat))).await.unwrap();
+    });
+    let llm = LlmClient::with_model("ollama", Some("mock".to_string())).await?
+        .with_ollama_url(&url)
+        .with_cache(None)
+        .with_retry(RetryPolicy { max_retries: 0, ..RetryPolicy::default() });
+
+    let impatient = llm.clone().with_timeout(Some(Duration::from_millis(100)));
+    let started = Instant::now();
+    let err = impatient.chat(vec![Message::user("hello")], None).await.unwrap_err();
+    assert!(err.to_string().contains("did not answer within 100ms"), "{}", err);
+    assert!(started.elapsed() < Duration::from_millis(450));
+
+    assert_eq!(llm.with_timeout(Some(Duration::from_secs(5))).chat(vec![Message::user("hello")], None).await?, "Finally.");
+    Ok(())
+}
+
+#[test]
+fn test_tool_runs_time_out_and_cancel() -> Result<()> {
+    let mut tools = ToolManager::new()?;
+    tools.create_tool("test_timeout_spin", "fn test_timeout_spin() { let n = 0; loop { n += 1; } }")?;
+    tools.set_tool_timeout(Some(Duration::from_millis(200)));
+    let started = Instant::now();
+    let err = tools.execute_tool("test_timeout_spin", vec![]).unwrap_err();
+    assert_eq!(err.to_string(), "Tool 'test_timeout_spin' timed out after 200ms");
+    assert!(started.elapsed() < Duration::from_secs(5));
+
+    // Ctrl-C stops a run with no timeout, and only while a turn is in flight
+    tools.set_tool_timeout(None);
+    let cancel = tools.cancel().clone();
+    assert!(!cancel.cancel());
+    cancel.begin();
+    let canceller = std::thread::spawn({
+        let cancel = cancel.clone();
+        move || {
+            std::thread::sleep(Duration::from_millis(200));
+            cancel.cancel()
+        }
+    });
+    let err = tools.execute_tool("test_timeout_spin", vec![]).unwrap_err();
+    assert_eq!(err.to_string(), "Tool 'test_timeout_spin' was cancelled");
+    assert!(canceller.join().unwrap());
+    cancel.end();
+
+    // The next turn runs normally
+    cancel.begin();
+    assert_eq!(tools.execute_tool("square", vec!["9".to_string()])?, "81");
+    cancel.end();
+
+    tools.remove_tool("test_timeout_spin")?;
+    Ok(())
+}
@@ -0,0 +1,36 @@
+use anyhow::Result;
+use swarm_thing::tools::ToolManager;
+
+#[test]
+fn test_broken_tool_is_isolated() -> Result<()> {
+    let mut manager = ToolManager::new()?;
+    
+    // A tool with a syntax error is rejected at creation but stays on disk
+    assert!(manager.create_tool("cache_broken", "fn cache_broken(x) { return x +; }").is_err());
+    manager.create_tool("cache_healthy", r#"fn cache_healthy(x) { return x + "!"; }"#)?;
+    
+    // Reloading must not fail because of the broken tool, and other tools keep working
+    manager.load_tools()?;
+    assert_eq!(manager.execute_tool("cache_healthy", vec!["ok".to_string()])?, "ok!");
+    
+    let err = manager.execute_tool("cache_broken", vec!["x".to_string()]).unwrap_err();
+    assert!(err.to_string().contains("compile error"));
+    
+    let broken = manager.check_tools();
+    assert!(broken.iter().any(|(name, _)| name == "cache_broken"));
+    assert!(!broken.iter().any(|(name, _)| name == "cache_healthy"));
+    
+    // Tools calling a broken tool report the dependency
+    manager.create_tool("cache_caller", r#"fn cache_caller(x) { return cache_broken(x); }"#)?;
+    let err = manager.execute_tool("cache_caller", vec!["x".to_string()]).unwrap_err();
+    assert!(err.to_string().contains("depends on 'cache_broken'"));
+    
+    // Fixing the file on disk is picked up without an explicit reload
+    std::fs::write("tools/cache_broken.rhai", r#"fn cache_broken(x) { return x + " fixed"; }"#)?;
+    assert_eq!(manager.execute_tool("cache_caller", vec!["now".to_string()])?, "now fixed");
+    
+    for tool in ["cache_broken", "cache_healthy", "cache_caller"] {
+        std::fs::remove_file(format!("tools/{}.rhai", tool))?;
+    }
+    Ok(())
+}
@@ -0,0 +1,87 @@
+use anyhow::Result;
+use std::path::PathBuf;
+use swarm_thing::config::Config;
+use swarm_thing::tool_history::{commit_tool, has_history, tool_at};
+use swarm_thing::tools::ToolManager;
+
+fn git_tools(name: &str) -> Result<(ToolManager, PathBuf)> {
+    let dir = std::env::temp_dir().join(format!("swarm_tool_history_{}_{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir)?;
+    let mut config = Config::default();
+    config.paths.tools_dir = dir.clone();
+    config.agent.name = "alpha".to_string();
+    config.policies.tools_git = true;
+    Ok((ToolManager::with_config(&config)?, dir))
+}
+
+#[test]
+fn test_tool_changes_are_committed() -> Result<()> {
+    let dir = std::env::temp_dir().join(format!("swarm_tool_history_existing_{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir)?;
+    std::fs::write(dir.join("hello.rhai"), "fn hello() { \"hi\" }")?;
+    let mut config = Config::default();
+    config.paths.tools_dir = dir.clone();
+    config.policies.tools_git = true;
+    // The tools already there start the history
+    let mut tools = ToolManager::with_config(&config)?;
+    assert!(has_history(&dir));
+    assert_eq!(tools.tool_log("hello")?.iter().map(|c| c.message.as_str()).collect::<Vec<_>>(), ["Start tool history"]);
+
+    tools.create_tool("hello", "fn hello() { \"hello\" }")?;
+    tools.create_tool("hello", "fn hello() { write_file(\"greeting.txt\", \"hello\") }")?;
+    tools.queue_tool("borrowed".to_string(), "fn borrowed(x) { x }".to_string(), "beta".to_string(), None)?;
+    tools.approve_tool("borrowed")?;
+    tools.remove_tool("hello")?;
+    // Nothing changed, nothing committed
+    assert_eq!(commit_tool(&dir, "borrowed", "again")?, None);
+
+    let log: Vec<String> = tools.tool_log("hello")?.into_iter().map(|c| c.message).collect();
+    assert_eq!(log, [
+        "remove_tool hello by Swarm Thing",
+        "create_tool hello (HighRisk) by Swarm Thing",
+        "create_tool hello (Safe) by Swarm Thing",
+        "Start tool history",
+    ]);
+    assert_eq!(tools.tool_log("borrowed")?[0].message, "approve_tool borrowed (Safe) by beta");
+    assert_eq!(tools.tool_log("nothing")?, vec![]);
+
+    // Without TOOLS_GIT nothing is kept
+    let mut plain = Config::default();
+    plain.paths.tools_dir = dir.join("plain");
+    let mut untracked = ToolManager::with_config(&plain)?;
+    untracked.create_tool("plain", "fn plain() { 1 }")?;
+    assert!(!has_history(&plain.paths.tools_dir));
+    assert!(untracked.tool_log("plain").unwrap_err().to_string().contains("no tool history"));
+
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+
+#[test]
+fn test_revert_tool_to_an_earlier_commit() -> Result<()> {
+    let (mut tools, dir) = git_tools("revert")?;
+    tools.create_tool("count", "// version: 1\nfn count(x) { parse_int(x) + 1 }")?;
+    tools.create_tool("count", "// version: 2\nfn count(x) { parse_int(x) + 2 }")?;
+    assert_eq!(tools.execute_tool("count", vec!["1".to_string()])?, "3");
+
+    let first = tools.tool_log("count")?.last().unwrap().clone();
+    assert_eq!(first.message, "create_tool count (Safe) by alpha");
+    assert_eq!(tools.revert_tool("count", first.short_id())?, format!("Tool 'count' reverted to {}", first.short_id()));
+    assert_eq!(tools.execute_tool("count", vec!["1".to_string()])?, "2");
+    let log = tools.tool_log("count")?;
+    assert_eq!(log.len(), 3);
+    assert_eq!(log[0].message, format!("revert_tool count to {} (Safe) by alpha", first.short_id()));
+    assert_eq!(tool_at(&dir, "count", &log[1].id)?, ("// version: 2\nfn count(x) { parse_int(x) + 2 }".to_string(), false));
+
+    // A removed tool comes back; unknown commits and tools are errors
+    tools.remove_tool("count")?;
+    tools.revert_tool("count", &log[1].id)?;
+    assert!(tools.tool_source("count").unwrap().contains("version: 2"));
+    assert!(tools.revert_tool("count", "0000000").unwrap_err().to_string().contains("No commit '0000000'"));
+    assert!(tools.revert_tool("missing", &log[1].id).unwrap_err().to_string().contains("Tool 'missing' is not in commit"));
+
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}
@@ -0,0 +1,91 @@
+use anyhow::Result;
+use axum::{routing::post, Json, Router};
+use swarm_thing::embeddings::{cosine_similarity, hash_embedding, Embedder, EmbeddingProvider};
+use swarm_thing::tool_index::{tool_description, ToolIndex};
+use swarm_thing::tools::ToolManager;
+
+// Ollama-compatible embeddings endpoint: one-hot vectors by input length parity
+async fn mock_embed(Json(body): Json<serde_json::Value>) -> Json<serde_json::Value> {
+    let embeddings: Vec<Vec<f32>> = body["input"].as_array().unwrap().iter()
+        .map(|t| if t.as_str().unwrap().len() % 2 == 0 { vec![1.0, 0.0] } else { vec![0.0, 1.0] })
+        .collect();
+    Json(serde_json::json!({ "embeddings": embeddings }))
+}
+
+#[test]
+fn test_hash_embedding_similarity() {
+    let weather = hash_embedding("fetch the weather forecast for a city");
+    let forecast = hash_embedding("weather forecast lookup");
+    let math = hash_embedding("multiply two numbers");
+    assert!(cosine_similarity(&weather, &forecast) > cosine_similarity(&weather, &math));
+    assert!((cosine_similarity(&weather, &weather) - 1.0).abs() < 1e-5);
+    assert_eq!(cosine_similarity(&weather, &[]), 0.0);
+}
+
+#[test]
+fn test_tool_description_from_leading_comments() {
+    let source = "\n// filename: celsius\n// Convert Fahrenheit to Celsius\n// Input: degrees\nfn celsius(f) { return 1; }";
+    assert_eq!(tool_description(source), "Convert Fahrenheit to Celsius Input: degrees");
+    assert_eq!(tool_description("# Word count for a text\ndef wc(t):\n    pass"), "Word count for a text");
+    assert_eq!(tool_description("fn nothing() {}"), "");
+}
+
+#[tokio::test]
+async fn test_index_ranks_and_refreshes() -> Result<()> {
+    let dir = std::env::temp_dir().join(format!("tool_index_test_{}", std::process::id()));
+    std::fs::create_dir_all(&dir)?;
+    std::fs::write(dir.join("weather_report.rhai"), "// Fetch the weather forecast for a city\nfn weather_report(city) { return scrape_url(city); }")?;
+    std::fs::write(dir.join("add_numbers.rhai"), "// Add two numbers together\nfn add_numbers(a) { return a + 1; }")?;
+    std::fs::write(dir.join("word_count.py"), "# Count the words in a text\ndef word_count(t):\n    return len(t.split())")?;
+    let names = vec!["weather_report".to_string(), "add_numbers".to_string(), "word_count".to_string()];
+
+    let index = ToolIndex::new(Embedder::hashing());
+    assert_eq!(index.refresh(&dir, &names).await?, 3);
+    // Unchanged tools are not re-embedded
+    assert_eq!(index.refresh(&dir, &names).await?, 0);
+
+    let matches = index.search("what's the weather forecast in Paris", 2).await?;
+    assert_eq!(matches.len(), 2);
+    assert_eq!(matches[0].name, "weather_report");
+    assert_eq!(matches[0].description, "Fetch the weather forecast for a city");
+    assert_eq!(index.search("count words", 1).await?[0].name, "word_count");
+
+    // Edited tools are re-embedded and removed ones dropped
+    std::fs::write(dir.join("add_numbers.rhai"), "// Sum numbers\nfn add_numbers(a) { return a + 2; }")?;
+    assert_eq!(index.refresh(&dir, &names[..2]).await?, 1);
+    assert!(index.search("count words", 5).await?.iter().all(|m| m.name != "word_count"));
+
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_ollama_embeddings() -> Result<()> {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+    let url = format!("http://{}/api/embed", listener.local_addr()?);
+    tokio::spawn(async move {
+        let app = Router::new().route("/api/embed", post(mock_embed));
+        axum::serve(listener, app).await.unwrap();
+    });
+
+    let embedder = Embedder::with_model(EmbeddingProvider::Ollama, None).await.with_ollama_url(&url);
+    assert_eq!(embedder.label(), "ollama:nomic-embed-text");
+    let vectors = embedder.embed_batch(&["ab".to_string(), "abc".to_string()]).await?;
+    assert_eq!(vectors, vec![vec![1.0, 0.0], vec![0.0, 1.0]]);
+    Ok(())
+}
+
+#[test]
+fn test_find_tool_native() -> Result<()> {
+    let mut manager = ToolManager::new()?;
+    manager.create_tool("test_find_celsius", "// Convert a temperature from Fahrenheit to Celsius\nfn test_find_celsius(f) { return (parse_int(f) - 32) * 5 / 9; }")?;
+    manager.create_tool("test_find_tool", "fn test_find_tool(q) { return find_tool(q); }")?;
+
+    let result = manager.execute_tool("test_find_tool", vec!["convert temperature to celsius".to_string()])?;
+    assert!(result.starts_with("test_find_celsius ("), "got {}", result);
+    assert!(result.contains("Fahrenheit to Celsius"));
+
+    std::fs::remove_file("tools/test_find_celsius.rhai")?;
+    std::fs::remove_file("tools/test_find_tool.rhai")?;
+    Ok(())
+}
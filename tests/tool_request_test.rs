@@ -0,0 +1,79 @@
+use anyhow::Result;
+use std::time::Duration;
+use swarm_thing::message::{IpcMessage, ToolSafetyLevel};
+use swarm_thing::safety::SafetyPolicy;
+use swarm_thing::tools::{answer_tool_request, SharePolicy, ToolManager};
+
+#[test]
+fn test_share_policy_refusals() -> Result<()> {
+    let dir = std::env::temp_dir().join(format!("swarm_tool_request_{}", std::process::id()));
+    std::fs::create_dir_all(&dir)?;
+    std::fs::write(dir.join("double.rhai"), "// Doubles a number\nfn double(x) { return parse_int(x) * 2; }")?;
+    std::fs::write(dir.join("notes.rhai"), "fn notes(path) { return read_file(path); }")?;
+    std::fs::write(dir.join("shell.rhai"), "fn shell(cmd) { return run_command(cmd); }")?;
+    std::fs::write(dir.join("stats.py"), "def stats(x):\n    return x\n")?;
+
+    let policy = SharePolicy::default();
+    match answer_tool_request(&dir, "double", &policy, &SafetyPolicy::default()) {
+        IpcMessage::ToolShare { name, code, description, safety_level, .. } => {
+            assert_eq!(name, "double");
+            assert!(code.contains("fn double"));
+            assert_eq!(description.as_deref(), Some("Doubles a number"));
+            assert_eq!(safety_level, ToolSafetyLevel::Safe);
+        }
+        other => panic!("expected ToolShare, got {:?}", other),
+    }
+
+    let refusal = |name: &str, policy: &SharePolicy| match answer_tool_request(&dir, name, policy, &SafetyPolicy::default()) {
+        IpcMessage::ToolRefused { reason, .. } => reason,
+        other => panic!("expected ToolRefused for {}, got {:?}", name, other),
+    };
+    assert!(refusal("shell", &policy).contains("HighRisk"));
+    assert!(refusal("missing", &policy).contains("not found"));
+    assert!(refusal("stats", &policy).contains("only Rhai"));
+    assert!(refusal("../secret", &policy).contains("invalid"));
+
+    let strict = SharePolicy { max_risk: ToolSafetyLevel::LowRisk, deny: vec!["double".to_string()] };
+    assert!(refusal("notes", &strict).contains("above the share limit"));
+    assert!(refusal("double", &strict).contains("not shared"));
+    // HighRisk stays unshared even if the limit says otherwise
+    let lax = SharePolicy { max_risk: ToolSafetyLevel::HighRisk, deny: Vec::new() };
+    assert!(refusal("shell", &lax).contains("HighRisk"));
+
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_request_tool_from_peer() -> Result<()> {
+    let mut owner = ToolManager::new()?;
+    owner.create_tool("test_requested_triple", "// Triples a number\nfn test_requested_triple(x) { return parse_int(x) * 3; }")?;
+    owner.create_tool("test_requested_shell", "fn test_requested_shell(cmd) { return run_command(cmd); }")?;
+    owner.execute_tool("start_server", vec!["9881".to_string()])?;
+    tokio::time::sleep(Duration::from_millis(500)).await;
+
+    let mut requester = ToolManager::new()?;
+    requester.create_tool("test_request_helper", r#"
+    fn test_request_helper(name) {
+        return request_tool("127.0.0.1:9881", name);
+    }
+    "#)?;
+
+    // Shared tools arrive in the approval queue, not installed
+    let shared = requester.execute_tool("test_request_helper", vec!["test_requested_triple".to_string()])?;
+    assert!(shared.contains("queued for approval"), "{}", shared);
+    let pending = requester.pending_tools.lock().unwrap().clone();
+    let tool = pending.iter().find(|t| t.name == "test_requested_triple").expect("tool should be pending");
+    assert_eq!(tool.source_agent, "127.0.0.1:9881");
+    assert_eq!(tool.safety_level, ToolSafetyLevel::Safe);
+    assert_eq!(tool.description.as_deref(), Some("Triples a number"));
+
+    let refused = requester.execute_tool("test_request_helper", vec!["test_requested_shell".to_string()])?;
+    assert!(refused.contains("refused") && refused.contains("HighRisk"), "{}", refused);
+    assert!(!requester.pending_tools.lock().unwrap().iter().any(|t| t.name == "test_requested_shell"));
+
+    std::fs::remove_file("tools/test_requested_triple.rhai")?;
+    std::fs::remove_file("tools/test_requested_shell.rhai")?;
+    std::fs::remove_file("tools/test_request_helper.rhai")?;
+    Ok(())
+}
@@ -1,5 +1,5 @@
 use anyhow::Result;
-use rust_research_agent::tools::ToolManager;
+use swarm_thing::tools::ToolManager;
 use std::time::Duration;
 
 #[tokio::test]
@@ -24,7 +24,7 @@ async fn test_tool_sharing_between_agents() -> Result<()> {
     // Agent A inspects the tool and shares it with Agent B
     let share_tool_code = r#"
     fn share_square(dummy) {
-        let code = inspect_tool("square");
+        let code = inspect_tool("square", "source");
         return send_message("http://127.0.0.1:9998/message", code);
     }
     "#;
@@ -0,0 +1,146 @@
+use anyhow::Result;
+use bytes::BytesMut;
+use rumqttc::{mqttbytes, ConnAck, ConnectReturnCode, Packet, PubAck, Publish, QoS, SubAck, SubscribeReasonCode};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use swarm_thing::inbox::Inbox;
+use swarm_thing::ipc::{call_peer, serve_mqtt, IpcState, Message, PendingCalls, TaskQueue};
+use swarm_thing::message::IpcMessage;
+use swarm_thing::registry::PeerRegistry;
+use swarm_thing::state::StateStore;
+use swarm_thing::threads::PeerThreads;
+use swarm_thing::transport::{mqtt, mqtt_agent, MqttConfig, MqttTransport, Transport};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::sync::mpsc;
+
+type Subscribers = Arc<Mutex<Vec<(String, mpsc::UnboundedSender<BytesMut>)>>>;
+
+/// A bare-bones MQTT 3.1.1 broker: exact topic subscriptions, forwarded at QoS 0
+async fn broker() -> Result<u16> {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+    let port = listener.local_addr()?.port();
+    let subscribers: Subscribers = Arc::new(Mutex::new(Vec::new()));
+    tokio::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            tokio::spawn(client(stream, subscribers.clone()));
+        }
+    });
+    Ok(port)
+}
+
+async fn client(stream: tokio::net::TcpStream, subscribers: Subscribers) {
+    let (mut reader, mut writer) = stream.into_split();
+    let (outgoing, mut queue) = mpsc::unbounded_channel::<BytesMut>();
+    tokio::spawn(async move {
+        while let Some(bytes) = queue.recv().await {
+            if writer.write_all(&bytes).await.is_err() {
+                break;
+            }
+        }
+    });
+    let mut buffer = BytesMut::new();
+    loop {
+        let packet = match mqttbytes::v4::read(&mut buffer, 4 * 1024 * 1024) {
+            Ok(packet) => packet,
+            Err(mqttbytes::Error::InsufficientBytes(_)) => {
+                let mut chunk = [0u8; 4096];
+                match reader.read(&mut chunk).await {
+                    Ok(0) | Err(_) => return,
+                    Ok(n) => buffer.extend_from_slice(&chunk[..n]),
+                }
+                continue;
+            }
+            Err(_) => return,
+        };
+        let mut reply = BytesMut::new();
+        match packet {
+            Packet::Connect(_) => { ConnAck::new(ConnectReturnCode::Success, false).write(&mut reply).unwrap(); }
+            Packet::Subscribe(subscribe) => {
+                let codes = subscribe.filters.iter().map(|_| SubscribeReasonCode::Success(QoS::AtMostOnce)).collect();
+                for filter in subscribe.filters {
+                    subscribers.lock().unwrap().push((filter.path, outgoing.clone()));
+                }
+                SubAck::new(subscribe.pkid, codes).write(&mut reply).unwrap();
+            }
+            Packet::Publish(publish) => {
+                if publish.qos == QoS::AtLeastOnce {
+                    PubAck::new(publish.pkid).write(&mut reply).unwrap();
+                }
+                for (topic, subscriber) in subscribers.lock().unwrap().iter().filter(|(t, _)| *t == publish.topic) {
+                    let mut forward = BytesMut::new();
+                    Publish::new(topic.clone(), QoS::AtMostOnce, publish.payload.to_vec()).write(&mut forward).unwrap();
+                    let _ = subscriber.send(forward);
+                }
+            }
+            Packet::PingReq => { reply.extend_from_slice(&[0xD0, 0x00]); }
+            Packet::Disconnect => return,
+            _ => {}
+        }
+        if !reply.is_empty() && outgoing.send(reply).is_err() {
+            return;
+        }
+    }
+}
+
+fn agent_state(name: &str, inbox: Inbox, calls: PendingCalls) -> Result<IpcState> {
+    let state_path = std::env::temp_dir().join(format!("swarm_mqtt_{}_{}.json", name, std::process::id()));
+    let threads = PeerThreads::new(StateStore::open(&state_path)?);
+    Ok(IpcState::new(Arc::new(Mutex::new(Vec::new())), threads, TaskQueue::new(), PeerRegistry::new(), PathBuf::from("tools"))
+        .with_inbox(inbox)
+        .with_calls(calls))
+}
+
+#[test]
+fn test_mqtt_config_and_topics() -> Result<()> {
+    let config = MqttConfig::new("mqtt://broker.local:8883", "alice")?.with_prefix("/lab/");
+    assert_eq!((config.host.as_str(), config.port), ("broker.local", 8883));
+    assert_eq!(config.topic_for("mqtt://bob")?, "lab/agents/bob/inbox");
+    assert_eq!(config.topic_for("mqtt://*")?, "lab/broadcast");
+    assert!(config.topic_for("127.0.0.1:9000").is_err());
+    assert!(config.topic_for("mqtt://a/b").is_err());
+
+    assert_eq!(MqttConfig::new("localhost", "bob")?.port, 1883);
+    assert!(MqttConfig::new("localhost:abc", "bob").is_err());
+    assert!(MqttConfig::new("localhost", "team/#").is_err());
+    assert_eq!(mqtt_agent("mqtt://bob"), Some("bob"));
+    assert_eq!(mqtt_agent("http://127.0.0.1:9000"), None);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_messages_and_calls_over_mqtt() -> Result<()> {
+    let port = broker().await?;
+    // alice is this process's shared connection, which every mqtt:// send goes through
+    std::env::set_var("MQTT_BROKER", format!("127.0.0.1:{}", port));
+    std::env::set_var("MQTT_AGENT_ID", "alice");
+    let alice = mqtt()?;
+    let alice_calls = PendingCalls::new();
+    tokio::spawn(serve_mqtt(agent_state("alice", Inbox::new(), alice_calls.clone())?, alice.clone(), std::future::pending()));
+
+    let bob = Arc::new(MqttTransport::connect(MqttConfig::new(&format!("127.0.0.1:{}", port), "bob")?)?);
+    let bob_inbox = Inbox::new();
+    tokio::spawn(serve_mqtt(agent_state("bob", bob_inbox.clone(), PendingCalls::new())?, bob.clone(), std::future::pending()));
+    tokio::time::sleep(Duration::from_millis(500)).await;
+    assert!(bob.take_incoming().is_none(), "only one server takes the inbox");
+
+    // Plain messages land in the inbox; the broker gives no answer of its own
+    let hello = Message::new(&IpcMessage::text("hello over mqtt"), Some(alice.address()))?;
+    let response = alice.send("mqtt://bob", &hello, Some(Duration::from_secs(5))).await?;
+    assert_eq!(response.received, "Published to swarm/agents/bob/inbox");
+    let mut delivered = false;
+    for _ in 0..50 {
+        delivered = bob_inbox.render_unread().contains("hello over mqtt");
+        if delivered {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    assert!(delivered, "message should reach bob's inbox");
+
+    // Inline answers come back as replies published to the caller
+    let answer = call_peer(&alice_calls, "mqtt://bob", &IpcMessage::tool_request("no_such_tool"), Some(alice.address()), Duration::from_secs(5)).await?;
+    assert!(matches!(answer, IpcMessage::ToolRefused { reason, .. } if reason == "tool not found"));
+    assert_eq!(alice_calls.pending(), 0);
+    Ok(())
+}
@@ -0,0 +1,99 @@
+use anyhow::Result;
+use std::sync::{Arc, Mutex};
+use swarm_thing::auth::{IpcAuth, Sender};
+use swarm_thing::encryption::AgentKeys;
+use swarm_thing::ipc::{router, IpcState, Message, MessageResponse, TaskQueue};
+use swarm_thing::message::{IpcMessage, ToolSafetyLevel};
+use swarm_thing::registry::PeerRegistry;
+use swarm_thing::state::StateStore;
+use swarm_thing::threads::PeerThreads;
+use swarm_thing::tools::PendingTool;
+use swarm_thing::trust::{TrustLevel, TrustStore};
+
+fn state_path(name: &str) -> std::path::PathBuf {
+    std::env::temp_dir().join(format!("swarm_trust_{}_{}.json", name, std::process::id()))
+}
+
+#[test]
+fn test_trust_levels_and_standing() -> Result<()> {
+    let path = state_path("levels");
+    let _ = std::fs::remove_file(&path);
+    let trust = TrustStore::new(StateStore::open(&path)?);
+    assert_eq!(trust.level("alpha")?, TrustLevel::Unknown);
+    trust.set("alpha", TrustLevel::Trusted)?;
+    trust.set("http://10.0.0.5:8080/message", TrustLevel::Blocked)?;
+    trust.set("10.0.0.6:8080", TrustLevel::Trusted)?;
+    assert_eq!(trust.level("10.0.0.5:8080")?, TrustLevel::Blocked);
+
+    let alpha = Sender::Verified("alpha".to_string());
+    // Trust in a name needs an agent key, and holds only for the first one seen
+    assert_eq!(trust.standing(&alpha, None, Some("10.0.0.7:8080"))?, TrustLevel::Known);
+    assert_eq!(trust.standing(&alpha, Some("a1a1"), Some("10.0.0.7:8080"))?, TrustLevel::Trusted);
+    assert_eq!(trust.pinned_key("alpha")?.as_deref(), Some("a1a1"));
+    assert_eq!(trust.standing(&alpha, Some("b2b2"), None)?, TrustLevel::Known);
+    // A blocked address blocks whoever claims it, and a trusted one only makes its sender known
+    assert_eq!(trust.standing(&alpha, Some("a1a1"), Some("10.0.0.5:8080"))?, TrustLevel::Blocked);
+    assert_eq!(trust.standing(&Sender::Unsigned, None, Some("10.0.0.6:8080"))?, TrustLevel::Known);
+    assert_eq!(trust.standing(&Sender::Unsigned, None, None)?, TrustLevel::Unknown);
+    // Trusting the name again forgets the pinned key
+    trust.set("alpha", TrustLevel::Trusted)?;
+    assert_eq!(trust.standing(&alpha, Some("b2b2"), None)?, TrustLevel::Trusted);
+
+    trust.set("alpha", TrustLevel::Unknown)?;
+    assert_eq!(trust.render()?, "10.0.0.5:8080: blocked\n10.0.0.6:8080: trusted");
+    assert!(TrustLevel::parse("friendly").is_err());
+    let _ = std::fs::remove_file(&path);
+    Ok(())
+}
+
+async fn share(url: &str, agent: &str, keys: &AgentKeys, name: &str, code: &str) -> Result<(u16, MessageResponse)> {
+    let content = IpcMessage::tool_share(name, code, None, ToolSafetyLevel::Safe).to_json()?;
+    let mut message = Message { content, from: Some("127.0.0.1:9000".to_string()), context: None, id: None, reply_to: None, signature: None, traceparent: None };
+    IpcAuth::new(Some("swarm-secret"), agent).with_keys(keys).sign(&mut message);
+    let response = reqwest::Client::new().post(url).json(&message).send().await?;
+    Ok((response.status().as_u16(), response.json().await?))
+}
+
+#[tokio::test]
+async fn test_server_applies_trust() -> Result<()> {
+    let path = state_path("server");
+    let _ = std::fs::remove_file(&path);
+    let tools_dir = std::env::temp_dir().join(format!("swarm_trust_tools_{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&tools_dir);
+    std::fs::create_dir_all(&tools_dir)?;
+    let pending: Arc<Mutex<Vec<PendingTool>>> = Arc::new(Mutex::new(Vec::new()));
+    let threads = PeerThreads::new(StateStore::open(&path)?);
+    let state = IpcState::new(pending.clone(), threads, TaskQueue::new(), PeerRegistry::new(), tools_dir.clone())
+        .with_auth(IpcAuth::new(Some("swarm-secret"), "receiver"));
+    state.trust.set("friend", TrustLevel::Trusted)?;
+    state.trust.set("mallory", TrustLevel::Blocked)?;
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+    let url = format!("http://{}/message", listener.local_addr()?);
+    tokio::spawn(async move { axum::serve(listener, router(state)).await.unwrap() });
+
+    let (friend, impostor) = (AgentKeys::generate(), AgentKeys::generate());
+    let (status, body) = share(&url, "mallory", &impostor, "square", "fn square(x) { x * x }").await?;
+    assert_eq!(status, 403);
+    assert_eq!(body.received, "mallory is blocked by this agent");
+
+    // Names that aren't plain identifiers never reach the disk or the queue
+    let (_, body) = share(&url, "friend", &friend, "../escape", "fn escape() { 1 }").await?;
+    assert_eq!(body.received, "Error: invalid tool name '../escape'");
+    assert!(!tools_dir.parent().unwrap().join("escape.rhai").exists());
+
+    // Safe tools from trusted agents skip approval; anything riskier still waits for it
+    let (status, body) = share(&url, "friend", &friend, "square", "fn square(x) { x * x }").await?;
+    assert_eq!(status, 200);
+    assert_eq!(body.received, "Tool 'square' installed (trusted sender)");
+    assert!(tools_dir.join("square.rhai").exists());
+    share(&url, "friend", &friend, "shout", "fn shout(url) { send_message(url, \"hi\") }").await?;
+    share(&url, "stranger", &friend, "cube", "fn cube(x) { x * x * x }").await?;
+    // Another member signing as friend, with its own key, isn't trusted
+    share(&url, "friend", &impostor, "halve", "fn halve(x) { x / 2 }").await?;
+    let queued: Vec<String> = pending.lock().unwrap().iter().map(|t| t.name.clone()).collect();
+    assert_eq!(queued, vec!["shout".to_string(), "cube".to_string(), "halve".to_string()]);
+
+    let _ = std::fs::remove_file(&path);
+    let _ = std::fs::remove_dir_all(&tools_dir);
+    Ok(())
+}
@@ -0,0 +1,76 @@
+use anyhow::Result;
+use axum::{routing::post, Json, Router};
+use swarm_thing::agent::Agent;
+use swarm_thing::llm::{Completion, LlmClient, Usage};
+use swarm_thing::usage::{Price, PriceTable, UsageTracker};
+
+// Ollama-compatible endpoint reporting fixed token counts
+async fn mock_chat(Json(_body): Json<serde_json::Value>) -> Json<serde_json::Value> {
+    Json(serde_json::json!({
+        "message": { "role": "assistant", "content": "hello" },
+        "prompt_eval_count": 120,
+        "eval_count": 30
+    }))
+}
+
+fn completion(model: &str, input: u64, output: u64) -> Completion {
+    Completion {
+        text: String::new(),
+        usage: Usage { input_tokens: input, output_tokens: output },
+        model: model.to_string(),
+        cached: false,
+    }
+}
+
+#[test]
+fn test_price_table_longest_match() {
+    let table = PriceTable::default();
+    let mini = table.price_for("openai:gpt-4o-mini").unwrap();
+    assert_eq!(mini, Price { input: 0.15, output: 0.6 });
+    assert_eq!(table.price_for("openai:gpt-4o").unwrap().input, 2.5);
+    assert_eq!(table.cost("ollama:llama3.1", &Usage { input_tokens: 1000, output_tokens: 1000 }), 0.0);
+    assert!(table.price_for("bedrock:unknown-model").is_none());
+}
+
+#[test]
+fn test_tracker_session_and_turn_totals() {
+    let mut prices = PriceTable::empty();
+    prices.set("model-a", Price { input: 1.0, output: 2.0 });
+    let mut tracker = UsageTracker::new(prices);
+
+    tracker.record(&completion("test:model-a", 1_000_000, 500_000));
+    tracker.start_turn();
+    tracker.record(&completion("test:model-a", 100, 10));
+    tracker.record(&completion("test:model-b", 50, 5));
+
+    let report = tracker.report();
+    assert_eq!(report.session, Usage { input_tokens: 1_000_150, output_tokens: 500_015 });
+    assert_eq!(report.turn, Usage { input_tokens: 150, output_tokens: 15 });
+    assert_eq!(report.requests, 3);
+    assert!((report.session_cost - 2.00012).abs() < 1e-9);
+    assert_eq!(report.by_model["test:model-b"].cost, 0.0);
+    assert_eq!(report.by_model["test:model-a"].requests, 2);
+    assert!(report.render().contains("test:model-b"));
+}
+
+#[tokio::test]
+async fn test_agent_accumulates_provider_usage() -> Result<()> {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+    let url = format!("http://{}/api/chat", listener.local_addr()?);
+    tokio::spawn(async move {
+        let app = Router::new().route("/api/chat", post(mock_chat));
+        axum::serve(listener, app).await.unwrap();
+    });
+
+    let client = LlmClient::with_model("ollama", None).await?.with_ollama_url(&url);
+    let mut agent = Agent::with_client(client, "sys");
+    agent.chat("one").await?;
+    agent.chat("two").await?;
+
+    let usage = agent.usage();
+    assert_eq!(usage.session, Usage { input_tokens: 240, output_tokens: 60 });
+    assert_eq!(usage.turn, Usage { input_tokens: 120, output_tokens: 30 });
+    assert_eq!(usage.by_model["ollama:llama3.1"].requests, 2);
+    assert_eq!(usage.session_cost, 0.0);
+    Ok(())
+}
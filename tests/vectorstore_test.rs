@@ -0,0 +1,83 @@
+use anyhow::Result;
+use swarm_thing::vectorstore::{Record, VectorStore};
+
+fn record(id: &str, vector: Vec<f32>, tag: &str) -> Record {
+    Record { id: id.to_string(), vector, metadata: serde_json::json!({ "tag": tag }) }
+}
+
+fn temp_path(name: &str) -> std::path::PathBuf {
+    let path = std::env::temp_dir().join(format!("{}_{}.jsonl", name, std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    path
+}
+
+fn line_count(path: &std::path::Path) -> usize {
+    std::fs::read_to_string(path).unwrap().lines().count()
+}
+
+#[test]
+fn test_add_search_delete() -> Result<()> {
+    let store = VectorStore::in_memory();
+    store.add(vec![
+        record("x", vec![1.0, 0.0], "a"),
+        record("y", vec![0.0, 1.0], "b"),
+        record("xy", vec![1.0, 1.0], "a"),
+    ])?;
+    assert_eq!(store.len()?, 3);
+
+    let hits = store.search(&[1.0, 0.1], 2)?;
+    assert_eq!(hits.iter().map(|h| h.id.as_str()).collect::<Vec<_>>(), vec!["x", "xy"]);
+    assert!(hits[0].score > 0.99);
+
+    let filtered = store.search_where(&[0.0, 1.0], 5, |m| m["tag"] == "a")?;
+    assert_eq!(filtered[0].id, "xy");
+    assert_eq!(filtered.len(), 2);
+
+    assert_eq!(store.delete(&["x".to_string(), "missing".to_string()])?, 1);
+    assert_eq!(store.delete_where(|m| m["tag"] == "b")?, 1);
+    assert_eq!(store.records()?.iter().map(|r| r.id.clone()).collect::<Vec<_>>(), vec!["xy"]);
+    Ok(())
+}
+
+#[test]
+fn test_persistence_and_compaction() -> Result<()> {
+    let path = temp_path("vectorstore_test");
+    let store = VectorStore::open(&path)?;
+    store.add(vec![record("a", vec![1.0, 0.0], "t"), record("b", vec![0.0, 1.0], "t")])?;
+    store.add(vec![record("a", vec![0.5, 0.5], "t")])?;
+    store.delete(&["b".to_string()])?;
+    assert_eq!(line_count(&path), 4);
+
+    // A second handle replays the log
+    let reopened = VectorStore::open(&path)?;
+    assert_eq!(reopened.records()?, vec![record("a", vec![0.5, 0.5], "t")]);
+
+    reopened.compact()?;
+    assert_eq!(line_count(&path), 1);
+    // The first handle notices the rewritten file
+    assert_eq!(store.len()?, 1);
+    assert_eq!(store.get("a")?.unwrap().vector, vec![0.5, 0.5]);
+
+    std::fs::remove_file(&path)?;
+    Ok(())
+}
+
+#[test]
+fn test_truncated_line_and_auto_compaction() -> Result<()> {
+    let path = temp_path("vectorstore_auto_test");
+    let store = VectorStore::open(&path)?;
+    for i in 0..100 {
+        store.add(vec![record("same", vec![i as f32, 1.0], "t")])?;
+    }
+    // Overwrites pile up dead lines until the log is rewritten
+    assert!(line_count(&path) < 100);
+    assert_eq!(store.get("same")?.unwrap().vector, vec![99.0, 1.0]);
+
+    // A crash mid-append leaves a partial line, which is skipped
+    std::fs::write(&path, format!("{}{}", std::fs::read_to_string(&path)?, "{\"add\":{\"id\":\"bro"))?;
+    let reopened = VectorStore::open(&path)?;
+    assert_eq!(reopened.len()?, 1);
+
+    std::fs::remove_file(&path)?;
+    Ok(())
+}
@@ -0,0 +1,86 @@
+use anyhow::Result;
+use axum::{routing::post, Json, Router};
+use swarm_thing::llm::{ImageContent, LlmClient, Message};
+use swarm_thing::tools::ToolManager;
+
+// Smallest valid PNG header; providers are mocked, so the pixels don't matter
+const PNG_BYTES: &[u8] = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR";
+
+// Ollama-compatible endpoint that reports the prompt and how many images it received
+async fn mock_chat(Json(body): Json<serde_json::Value>) -> Json<serde_json::Value> {
+    let last = body["messages"].as_array().and_then(|m| m.last()).cloned().unwrap_or_default();
+    let images = last["images"].as_array().map(|i| i.len()).unwrap_or(0);
+    let content = format!("{} with {} image(s): {}", last["content"].as_str().unwrap_or(""), images,
+        last["images"][0].as_str().unwrap_or(""));
+    Json(serde_json::json!({ "message": { "role": "assistant", "content": content } }))
+}
+
+async fn mock_server() -> Result<String> {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+    let url = format!("http://{}/api/chat", listener.local_addr()?);
+    tokio::spawn(async move {
+        let app = Router::new().route("/api/chat", post(mock_chat));
+        axum::serve(listener, app).await.unwrap();
+    });
+    Ok(url)
+}
+
+#[test]
+fn test_image_content_from_path_and_base64() -> Result<()> {
+    let path = std::env::temp_dir().join(format!("vision_test_{}.png", std::process::id()));
+    std::fs::write(&path, PNG_BYTES)?;
+    let image = ImageContent::from_path(&path)?;
+    assert_eq!(image.media_type, "image/png");
+    assert_eq!(image.format(), "png");
+    assert_eq!(image.bytes()?, PNG_BYTES);
+
+    std::fs::write(&path, b"not an image")?;
+    assert!(ImageContent::from_path(&path).is_err());
+    std::fs::remove_file(&path)?;
+
+    assert!(ImageContent::from_base64("image/png", image.data.clone()).is_ok());
+    assert!(ImageContent::from_base64("image/png", "not base64!").is_err());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_images_sent_to_ollama() -> Result<()> {
+    let url = mock_server().await?;
+    let client = LlmClient::with_model("ollama", Some("llava".to_string())).await?.with_ollama_url(&url);
+
+    let image = ImageContent::from_base64("image/png", "aGVsbG8=")?;
+    let reply = client.chat(vec![Message::user("what is this?").with_image(image)], None).await?;
+    assert_eq!(reply, "what is this? with 1 image(s): aGVsbG8=");
+
+    // Text-only messages carry no images field
+    let reply = client.chat(vec![Message::user("plain")], None).await?;
+    assert_eq!(reply, "plain with 0 image(s): ");
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_analyze_image_tool() -> Result<()> {
+    let url = mock_server().await?;
+    std::env::set_var("VISION_MODEL", "ollama:llava");
+    std::env::set_var("OLLAMA_URL", &url);
+
+    std::fs::write("vision_test_figure.png", PNG_BYTES)?;
+    let mut manager = ToolManager::new()?;
+    manager.create_tool("test_analyze_image", r#"
+    fn test_analyze_image(path) {
+        return analyze_image(path, "describe the figure");
+    }
+    "#)?;
+
+    let result = tokio::task::spawn_blocking(move || {
+        let ok = manager.execute_tool("test_analyze_image", vec!["vision_test_figure.png".to_string()]);
+        let missing = manager.execute_tool("test_analyze_image", vec!["../outside.png".to_string()]);
+        (ok, missing)
+    }).await?;
+    assert!(result.0?.starts_with("describe the figure with 1 image(s)"));
+    assert!(result.1?.starts_with("Error:"));
+
+    std::fs::remove_file("vision_test_figure.png")?;
+    std::fs::remove_file("tools/test_analyze_image.rhai")?;
+    Ok(())
+}
@@ -0,0 +1,51 @@
+use anyhow::Result;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use swarm_thing::auth::IpcAuth;
+use swarm_thing::ipc::{router, IpcState, Message, TaskQueue, WsClient};
+use swarm_thing::message::IpcMessage;
+use swarm_thing::registry::PeerRegistry;
+use swarm_thing::state::StateStore;
+use swarm_thing::threads::PeerThreads;
+
+#[tokio::test]
+async fn test_websocket_session_with_push_and_reconnect() -> Result<()> {
+    let state_path = std::env::temp_dir().join(format!("swarm_ws_{}.json", std::process::id()));
+    let threads = PeerThreads::new(StateStore::open(&state_path)?);
+    let state = IpcState::new(Arc::new(Mutex::new(Vec::new())), threads, TaskQueue::new(), PeerRegistry::new(), PathBuf::from("tools"))
+        .with_auth(IpcAuth::new(None, "server"));
+    let inbox = state.inbox.clone();
+    let sessions = state.sessions.clone();
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+    let address = listener.local_addr()?.to_string();
+    tokio::spawn(async move {
+        axum::serve(listener, router(state).into_make_service_with_connect_info::<SocketAddr>()).await.unwrap()
+    });
+
+    let client = WsClient::connect(&address, Some("127.0.0.1:9700".to_string())).await?
+        .with_auth(IpcAuth::new(None, "client"))
+        .with_timeout(Duration::from_secs(5));
+
+    // Requests travel over the socket and are handled like POSTed messages
+    assert_eq!(client.request(&IpcMessage::text("first")).await?, "first");
+    assert_eq!(client.request(&IpcMessage::Heartbeat { address: "127.0.0.1:9700".to_string() }).await?, "alive");
+    assert_eq!(inbox.list().iter().map(|m| m.content.clone()).collect::<Vec<_>>(), vec!["first".to_string()]);
+
+    // The server can push to the connected peer
+    assert_eq!(sessions.peers(), vec!["127.0.0.1:9700".to_string()]);
+    let push = Message { content: IpcMessage::text("pushed").to_json()?, from: None, context: None, id: None, reply_to: None, signature: None, traceparent: None };
+    assert!(sessions.push("http://127.0.0.1:9700/message", push));
+    let pushed = tokio::time::timeout(Duration::from_secs(5), client.next_incoming()).await?;
+    assert!(matches!(pushed, Some(IpcMessage::Text { content, .. }) if content == "pushed"));
+
+    // A dropped session is re-established and requests keep working
+    assert!(sessions.disconnect("127.0.0.1:9700"));
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert_eq!(client.request(&IpcMessage::text("second")).await?, "second");
+    assert_eq!(sessions.peers(), vec!["127.0.0.1:9700".to_string()]);
+
+    let _ = std::fs::remove_file(&state_path);
+    Ok(())
+}
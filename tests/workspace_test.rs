@@ -0,0 +1,121 @@
+use anyhow::Result;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use swarm_thing::config::{Config, WorkspaceConfig};
+use swarm_thing::tools::ToolManager;
+use swarm_thing::workspace::{prune, Cleanup, SessionWorkspace, SESSION_MARKER};
+
+fn workspace_config(name: &str, cleanup: &str) -> WorkspaceConfig {
+    let dir = std::env::temp_dir().join(format!("swarm_workspaces_{}_{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    WorkspaceConfig { dir, cleanup: cleanup.to_string(), ..Default::default() }
+}
+
+/// A workspace of an earlier session, last changed `days` ago
+fn old_session(config: &WorkspaceConfig, name: &str, days: u64, file: bool) -> Result<PathBuf> {
+    let path = old_dir(config, name, days, file)?;
+    let when = SystemTime::now() - Duration::from_secs(days * 86_400 + 60);
+    std::fs::write(path.join(SESSION_MARKER), name)?;
+    std::fs::File::options().write(true).open(path.join(SESSION_MARKER))?.set_modified(when)?;
+    std::fs::File::open(&path)?.set_modified(when)?;
+    Ok(path)
+}
+
+/// A directory in the workspace dir, last changed `days` ago
+fn old_dir(config: &WorkspaceConfig, name: &str, days: u64, file: bool) -> Result<PathBuf> {
+    let path = config.dir.join(name);
+    std::fs::create_dir_all(&path)?;
+    let when = SystemTime::now() - Duration::from_secs(days * 86_400 + 60);
+    if file {
+        std::fs::write(path.join("notes.txt"), "kept")?;
+        std::fs::File::options().write(true).open(path.join("notes.txt"))?.set_modified(when)?;
+    }
+    std::fs::File::open(&path)?.set_modified(when)?;
+    Ok(path)
+}
+
+fn names(dir: &Path) -> Result<Vec<String>> {
+    let mut names: Vec<String> = std::fs::read_dir(dir)?.flatten().map(|e| e.file_name().to_string_lossy().to_string()).collect();
+    names.sort();
+    Ok(names)
+}
+
+#[test]
+fn test_session_workspace_lists_and_cleans_up() -> Result<()> {
+    let config = workspace_config("finish", "empty");
+    let workspace = SessionWorkspace::start(&config, "session-a")?;
+    assert!(workspace.path().is_absolute() && workspace.path().ends_with("session-a"));
+    assert!(workspace.render()?.ends_with("No files yet"));
+    // Nothing was produced, so the workspace goes
+    assert!(workspace.finish()?);
+    assert!(!workspace.path().exists());
+
+    let workspace = SessionWorkspace::start(&config, "session-b")?;
+    std::fs::create_dir_all(workspace.path().join("reports"))?;
+    std::fs::write(workspace.path().join("reports/out.md"), "# Report\n")?;
+    std::fs::write(workspace.path().join("data.csv"), "a,b\n".repeat(600))?;
+    let listing = workspace.render()?;
+    assert!(listing.contains("  data.csv (2.3 KB)\n  reports/out.md (9 B)"), "{}", listing);
+    assert!(workspace.open("../session-a").is_err());
+    assert!(!workspace.finish()?);
+    assert!(workspace.path().join("reports/out.md").exists());
+
+    let always = SessionWorkspace::start(&WorkspaceConfig { cleanup: "always".to_string(), ..config.clone() }, "session-c")?;
+    std::fs::write(always.path().join("scratch.txt"), "x")?;
+    assert!(always.finish()?);
+    assert!(!always.path().exists());
+
+    assert_eq!("never".parse::<Cleanup>()?, Cleanup::Keep);
+    assert!("sometimes".parse::<Cleanup>().is_err());
+    std::fs::remove_dir_all(&config.dir)?;
+    Ok(())
+}
+
+#[test]
+fn test_old_workspaces_are_pruned() -> Result<()> {
+    let config = WorkspaceConfig { keep_last: 3, max_age_days: 10, ..workspace_config("prune", "empty") };
+    old_session(&config, "expired", 11, true)?;
+    old_session(&config, "oldest", 5, true)?;
+    old_session(&config, "older", 4, true)?;
+    old_session(&config, "newer", 3, true)?;
+    old_session(&config, "left-empty", 2, false)?;
+    // Empty, but another agent may still be starting to use it
+    old_session(&config, "fresh-empty", 0, false)?;
+    // Not a session's workspace, however old or empty
+    old_dir(&config, "datasets", 30, true)?;
+    old_dir(&config, "scratch", 30, false)?;
+
+    let mut removed = prune(&config.dir, &config, "current")?;
+    removed.sort();
+    // Past max_age_days, an empty leftover, and whatever is past keep_last (the current session included)
+    assert_eq!(removed, ["expired", "left-empty", "older", "oldest"]);
+    assert_eq!(names(&config.dir)?, ["datasets", "fresh-empty", "newer", "scratch"]);
+
+    // keep leaves empty workspaces alone, and 0 means no limits
+    let keep = WorkspaceConfig { cleanup: "keep".to_string(), keep_last: 0, max_age_days: 0, ..config.clone() };
+    old_session(&keep, "ancient", 400, false)?;
+    assert!(prune(&keep.dir, &keep, "current")?.is_empty());
+    std::fs::remove_dir_all(&config.dir)?;
+    Ok(())
+}
+
+#[test]
+fn test_workspace_config_and_file_tools() -> Result<()> {
+    let mut config: Config = toml::from_str("[workspace]\nper_session = false\ncleanup = \"keep\"\nkeep_last = 5\n")?;
+    assert_eq!((config.workspace.per_session, config.workspace.keep_last, config.workspace.max_age_days), (false, 5, 30));
+    config.workspace.cleanup = "tidy".to_string();
+    assert!(config.validate().iter().any(|p| p.starts_with("workspace.cleanup: expected keep, empty or always")), "{:?}", config.validate());
+
+    // The file tools work in the session's workspace, as the CLI sets it up
+    let workspace = SessionWorkspace::start(&workspace_config("tools", "empty"), "session-tools")?;
+    let mut config = Config::default();
+    config.paths.workspace = workspace.path().to_path_buf();
+    let tools = ToolManager::with_config(&config)?;
+    assert_eq!(tools.execute_tool("workspace_path", vec![])?, workspace.path().display().to_string());
+    assert_eq!(tools.execute_tool("write_file", vec!["answer.txt".to_string(), "42".to_string()])?, "File written successfully");
+    assert_eq!(std::fs::read_to_string(workspace.path().join("answer.txt"))?, "42");
+    assert!(tools.execute_tool("read_file", vec!["../../Cargo.toml".to_string()])?.starts_with("Error"));
+    assert_eq!(workspace.artifacts()?.len(), 1);
+    std::fs::remove_dir_all(workspace.path().parent().unwrap())?;
+    Ok(())
+}